@@ -1,2 +1,2 @@
-wasm-pack build -t nodejs ../taglogic/ --out-dir ../serv2/pkg
+wasm-pack build -t nodejs ../taglogic/wasm/ --out-dir ../../serv2/pkg --out-name taglogic
 nodemon index.js
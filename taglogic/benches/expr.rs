@@ -0,0 +1,64 @@
+//! Benchmarks for parsing expressions and matching them against pings and logs. Run with
+//! `cargo bench --features bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use taglogic::bool::Expr;
+use taglogic::testing::{generate_log, TagModel};
+
+const EXPRS: [&str; 3] = [
+    "work",
+    "(work | code) & !email & !(meeting | commute)",
+    "!(sleep | eat) and (read or exercise or social) | work & code & !(email, meeting)",
+];
+
+/// Logs are generated with this seed, so every run matches the same pings.
+const SEED: u64 = 1;
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (i, expr) in EXPRS.iter().enumerate() {
+        group.bench_with_input(BenchmarkId::from_parameter(i), expr, |b, expr| {
+            b.iter(|| Expr::parse(black_box(expr)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn evaluate(c: &mut Criterion) {
+    let tags = ["code", "work", "music"];
+    let mut group = c.benchmark_group("evaluate");
+    for (i, expr) in EXPRS.iter().enumerate() {
+        let expr = Expr::parse(expr).unwrap();
+        group.bench_with_input(BenchmarkId::new("ast", i), &expr, |b, expr| {
+            b.iter(|| expr.matches(black_box(&tags)))
+        });
+        let compiled = expr.compile();
+        group.bench_with_input(BenchmarkId::new("compiled", i), &compiled, |b, compiled| {
+            b.iter(|| compiled.matches(black_box(&tags)))
+        });
+    }
+    group.finish();
+}
+
+fn matching(c: &mut Criterion) {
+    let log = generate_log(SEED, 100_000, &TagModel::default());
+    let bitsets = log.tag_bitsets();
+    let mut group = c.benchmark_group("match_100k");
+    group.sample_size(20);
+    for (i, expr) in EXPRS.iter().enumerate() {
+        let expr = Expr::parse(expr).unwrap();
+        group.bench_with_input(BenchmarkId::new("ast", i), &expr, |b, expr| {
+            b.iter(|| log.pings().iter().filter(|ping| ping.matches(expr)).count())
+        });
+        group.bench_with_input(BenchmarkId::new("matches_many", i), &expr, |b, expr| {
+            b.iter(|| log.matches_many(expr))
+        });
+        group.bench_with_input(BenchmarkId::new("bitsets", i), &expr, |b, expr| {
+            b.iter(|| bitsets.matches(expr))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, evaluate, matching);
+criterion_main!(benches);
@@ -0,0 +1,70 @@
+//! Benchmarks for queries and stats over a synthetic log of about ten years of pings. Run with
+//! `cargo bench --features bench`.
+
+use chrono::{FixedOffset, Utc};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use taglogic::bool::Expr;
+use taglogic::cache::QueryCache;
+use taglogic::log::PingLog;
+use taglogic::stats::{self, Aggregates, Bucket, Metric, ReportSpec};
+use taglogic::testing::{generate_log, TagModel};
+
+const SEED: u64 = 1;
+const PINGS: usize = 100_000;
+const EXPR: &str = "(work | code) & !email & !(meeting | commute)";
+
+fn setup() -> (PingLog, Expr) {
+    let log = generate_log(SEED, PINGS, &TagModel::default());
+    (log, Expr::parse(EXPR).unwrap())
+}
+
+fn query(c: &mut Criterion) {
+    let (log, expr) = setup();
+    let mut group = c.benchmark_group("query_100k");
+    group.sample_size(20);
+    let month = log.times()[PINGS / 2]..log.times()[PINGS / 2] + 30 * 86400;
+    group.bench_function("month", |b| {
+        b.iter(|| {
+            let pings = log.range(month.start, month.end);
+            pings.iter().filter(|ping| ping.matches(&expr)).count()
+        })
+    });
+    group.bench_function("cached", |b| {
+        let mut cache = QueryCache::new(10);
+        cache.matches(&log, &expr);
+        b.iter(|| cache.matches(black_box(&log), &expr).len())
+    });
+    group.finish();
+}
+
+fn stats(c: &mut Criterion) {
+    let (log, expr) = setup();
+    let mut group = c.benchmark_group("stats_100k");
+    group.sample_size(20);
+    group.bench_function("estimate", |b| {
+        b.iter(|| stats::estimate(&log, &expr, 0..u64::MAX))
+    });
+    group.bench_function("daily_series", |b| {
+        b.iter(|| stats::time_series(&log, &expr, Bucket::Day, &Utc))
+    });
+    let spec = ReportSpec {
+        expr: expr.clone(),
+        range: 0..u64::MAX,
+        tz: Utc,
+        metrics: vec![
+            Metric::Total,
+            Metric::Series(Bucket::Week),
+            Metric::Sessions { max_gap: 5400 },
+            Metric::Diversity,
+        ],
+    };
+    group.bench_function("report", |b| b.iter(|| stats::report(&log, &spec)));
+    let utc = FixedOffset::east_opt(0).unwrap();
+    group.bench_function("aggregates", |b| {
+        b.iter(|| Aggregates::from_log(&log, utc, vec![expr.clone()]))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, query, stats);
+criterion_main!(benches);
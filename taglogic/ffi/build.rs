@@ -0,0 +1,11 @@
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).unwrap();
+    // only rewrites the header if it changed, so this doesn't cause rebuilds
+    cbindgen::generate_with_config(&crate_dir, config)
+        .expect("couldn't generate the C header")
+        .write_to_file(crate_dir.join("include/taglogic.h"));
+}
@@ -0,0 +1,112 @@
+#ifndef TAGLOGIC_H
+#define TAGLOGIC_H
+
+/* Generated by cbindgen from taglogic-ffi. Don't edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// A parsed expression.
+typedef struct TlExpr TlExpr;
+
+// Why an expression couldn't be parsed.
+typedef struct TlParseError TlParseError;
+
+// A log of answered pings.
+typedef struct TlPingLog TlPingLog;
+
+// A ping schedule.
+typedef struct TlSchedule TlSchedule;
+
+// An estimate of the hours spent on something, with a 95% confidence interval.
+typedef struct TlEstimate {
+  uint32_t pings;
+  double hours;
+  double low;
+  double high;
+} TlEstimate;
+
+// Frees a string returned by this library.
+void tl_string_free(char *s);
+
+// Parses an expression. Returns NULL if it's invalid, in which case `*error` (if `error` isn't
+// NULL) is set to the reason.
+struct TlExpr *tl_expr_parse(const char *expr, struct TlParseError **error);
+
+// Returns if an expression matches the space-separated `tags`. Returns false if `tags` isn't
+// valid UTF-8.
+bool tl_expr_matches(const struct TlExpr *expr, const char *tags);
+
+// Returns the tags used in an expression, sorted, without duplicates, and separated by spaces.
+char *tl_expr_tags(const struct TlExpr *expr);
+
+// Returns the expression written in canonical form.
+char *tl_expr_format(const struct TlExpr *expr);
+
+void tl_expr_free(struct TlExpr *expr);
+
+// The error message. It's freed along with the error.
+const char *tl_parse_error_message(const struct TlParseError *error);
+
+// Byte offset of the start of the part of the expression that caused the error.
+size_t tl_parse_error_start(const struct TlParseError *error);
+
+// Byte offset just past the end of the part of the expression that caused the error. This is
+// the same as the start if the expression ended too early.
+size_t tl_parse_error_end(const struct TlParseError *error);
+
+void tl_parse_error_free(struct TlParseError *error);
+
+// Creates a schedule. `tagtime` picks the original TagTime algorithm instead of the newer
+// hash-based one. Returns NULL if `avg_interval` is zero.
+struct TlSchedule *tl_schedule_new(uint32_t seed, uint32_t avg_interval, bool tagtime);
+
+// The universal TagTime schedule, which most TagTime clients use.
+struct TlSchedule *tl_schedule_universal(void);
+
+void tl_schedule_free(struct TlSchedule *schedule);
+
+// Returns if there's a ping at the Unix timestamp `time`.
+bool tl_should_ping_at(const struct TlSchedule *schedule, uint64_t time);
+
+// Finds the first ping after `time`, and writes it to `*out`. Returns false if there isn't one.
+bool tl_next_ping_after(const struct TlSchedule *schedule, uint64_t time, uint64_t *out);
+
+// Finds the last ping before `time`, and writes it to `*out`. Returns false if there isn't one.
+bool tl_last_ping_before(const struct TlSchedule *schedule, uint64_t time, uint64_t *out);
+
+// Returns an array of all pings from `start` up to `end` (inclusive), and writes its length to
+// `*len`. Free it with [`tl_pings_free`]. Returns NULL if there are no pings.
+uint64_t *tl_pings_between(const struct TlSchedule *schedule,
+                           uint64_t start,
+                           uint64_t end,
+                           size_t *len);
+
+// Frees an array from [`tl_pings_between`]. `len` must be the length it returned.
+void tl_pings_free(uint64_t *pings, size_t len);
+
+struct TlPingLog *tl_log_new(void);
+
+void tl_log_free(struct TlPingLog *log);
+
+// Adds an answered ping with space-separated `tags`, which repersents `interval` seconds.
+// Returns false if `tags` isn't valid UTF-8.
+bool tl_log_push(struct TlPingLog *log, uint64_t time, const char *tags, uint32_t interval);
+
+// Number of pings in the log.
+size_t tl_log_len(const struct TlPingLog *log);
+
+// Estimates the hours spent on `expr` from `start` up to (but not including) `end`.
+struct TlEstimate tl_log_estimate(const struct TlPingLog *log,
+                                  const struct TlExpr *expr,
+                                  uint64_t start,
+                                  uint64_t end);
+
+// Handles a JSON request for taglogic's `commands` module, returning a JSON response. Returns
+// NULL if the request isn't valid UTF-8.
+char *tl_dispatch(const char *request);
+
+#endif  /* TAGLOGIC_H */
@@ -0,0 +1,435 @@
+//! C bindings for taglogic, so native apps can use the exact same expression and schedule logic as
+//! the web frontend. The header is generated into `include/taglogic.h` when building.
+//!
+//! Conventions:
+//! - Strings are NUL-terminated UTF-8, both going in and coming out.
+//! - Anything returned as a pointer is owned by the caller, and must be freed with the matching
+//!   `_free` function. The free functions accept NULL.
+//! - Handles passed in must be valid pointers from this library, and not freed yet.
+//! - Failures are reported with a NULL or false return value.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+use taglogic::bool::Expr;
+use taglogic::log::{Ping, PingLog};
+use taglogic::{stats, tt, PingIntervalData};
+
+/// A parsed expression.
+pub struct TlExpr(Expr);
+
+/// A ping schedule.
+pub struct TlSchedule(PingIntervalData);
+
+/// A log of answered pings.
+pub struct TlPingLog(PingLog);
+
+/// Why an expression couldn't be parsed.
+pub struct TlParseError {
+    message: CString,
+    start: usize,
+    end: usize,
+}
+
+/// An estimate of the hours spent on something, with a 95% confidence interval.
+#[repr(C)]
+pub struct TlEstimate {
+    pub pings: u32,
+    pub hours: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str, std::str::Utf8Error> {
+    CStr::from_ptr(s).to_str()
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    // strings from this library never contain NUL, since they're made from C strings
+    CString::new(s).unwrap_or_default().into_raw()
+}
+
+fn into_handle<T>(value: T) -> *mut T {
+    Box::into_raw(Box::new(value))
+}
+
+unsafe fn free_handle<T>(handle: *mut T) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Frees a string returned by this library.
+#[no_mangle]
+pub unsafe extern "C" fn tl_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Parses an expression. Returns NULL if it's invalid, in which case `*error` (if `error` isn't
+/// NULL) is set to the reason.
+#[no_mangle]
+pub unsafe extern "C" fn tl_expr_parse(
+    expr: *const c_char,
+    error: *mut *mut TlParseError,
+) -> *mut TlExpr {
+    let result = match to_str(expr) {
+        Ok(expr) => Expr::parse(expr).map_err(|err| (err.kind.to_string(), err.span)),
+        Err(err) => Err((
+            "expression isn't valid UTF-8".to_string(),
+            err.valid_up_to()..(err.valid_up_to() + 1),
+        )),
+    };
+    match result {
+        Ok(expr) => {
+            if !error.is_null() {
+                *error = ptr::null_mut();
+            }
+            into_handle(TlExpr(expr))
+        }
+        Err((message, span)) => {
+            if !error.is_null() {
+                *error = into_handle(TlParseError {
+                    message: CString::new(message).unwrap_or_default(),
+                    start: span.start,
+                    end: span.end,
+                });
+            }
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Returns if an expression matches the space-separated `tags`. Returns false if `tags` isn't
+/// valid UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn tl_expr_matches(expr: *const TlExpr, tags: *const c_char) -> bool {
+    match to_str(tags) {
+        Ok(tags) => (*expr)
+            .0
+            .matches(&tags.split_whitespace().collect::<Vec<_>>()),
+        Err(_) => false,
+    }
+}
+
+/// Returns the tags used in an expression, sorted, without duplicates, and separated by spaces.
+#[no_mangle]
+pub unsafe extern "C" fn tl_expr_tags(expr: *const TlExpr) -> *mut c_char {
+    into_c_string((*expr).0.tags().join(" "))
+}
+
+/// Returns the expression written in canonical form.
+#[no_mangle]
+pub unsafe extern "C" fn tl_expr_format(expr: *const TlExpr) -> *mut c_char {
+    into_c_string((*expr).0.to_string())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tl_expr_free(expr: *mut TlExpr) {
+    free_handle(expr);
+}
+
+/// The error message. It's freed along with the error.
+#[no_mangle]
+pub unsafe extern "C" fn tl_parse_error_message(error: *const TlParseError) -> *const c_char {
+    (*error).message.as_ptr()
+}
+
+/// Byte offset of the start of the part of the expression that caused the error.
+#[no_mangle]
+pub unsafe extern "C" fn tl_parse_error_start(error: *const TlParseError) -> usize {
+    (*error).start
+}
+
+/// Byte offset just past the end of the part of the expression that caused the error. This is
+/// the same as the start if the expression ended too early.
+#[no_mangle]
+pub unsafe extern "C" fn tl_parse_error_end(error: *const TlParseError) -> usize {
+    (*error).end
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tl_parse_error_free(error: *mut TlParseError) {
+    free_handle(error);
+}
+
+/// Creates a schedule. `tagtime` picks the original TagTime algorithm instead of the newer
+/// hash-based one. Returns NULL if `avg_interval` is zero.
+#[no_mangle]
+pub extern "C" fn tl_schedule_new(seed: u32, avg_interval: u32, tagtime: bool) -> *mut TlSchedule {
+    if avg_interval == 0 {
+        return ptr::null_mut();
+    }
+    into_handle(TlSchedule(taglogic::new_ping_interval_data(
+        seed,
+        avg_interval,
+        tagtime,
+    )))
+}
+
+/// The universal TagTime schedule, which most TagTime clients use.
+#[no_mangle]
+pub extern "C" fn tl_schedule_universal() -> *mut TlSchedule {
+    into_handle(TlSchedule(tt::UNIV_SCHED))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tl_schedule_free(schedule: *mut TlSchedule) {
+    free_handle(schedule);
+}
+
+/// Returns if there's a ping at the Unix timestamp `time`.
+#[no_mangle]
+pub unsafe extern "C" fn tl_should_ping_at(schedule: *const TlSchedule, time: u64) -> bool {
+    taglogic::should_ping_at_time(time, &(*schedule).0)
+}
+
+/// Finds the first ping after `time`, and writes it to `*out`. Returns false if there isn't one.
+#[no_mangle]
+pub unsafe extern "C" fn tl_next_ping_after(
+    schedule: *const TlSchedule,
+    time: u64,
+    out: *mut u64,
+) -> bool {
+    match taglogic::next_ping_after(time, &(*schedule).0) {
+        Some(ping) => {
+            *out = ping;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Finds the last ping before `time`, and writes it to `*out`. Returns false if there isn't one.
+#[no_mangle]
+pub unsafe extern "C" fn tl_last_ping_before(
+    schedule: *const TlSchedule,
+    time: u64,
+    out: *mut u64,
+) -> bool {
+    match taglogic::last_ping(time, &(*schedule).0) {
+        Some(ping) => {
+            *out = ping;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns an array of all pings from `start` up to `end` (inclusive), and writes its length to
+/// `*len`. Free it with [`tl_pings_free`]. Returns NULL if there are no pings.
+#[no_mangle]
+pub unsafe extern "C" fn tl_pings_between(
+    schedule: *const TlSchedule,
+    start: u64,
+    end: u64,
+    len: *mut usize,
+) -> *mut u64 {
+    let pings = if start < end {
+        taglogic::pings_between(start, end, &(*schedule).0)
+    } else {
+        vec![]
+    };
+    *len = pings.len();
+    if pings.is_empty() {
+        return ptr::null_mut();
+    }
+    Box::into_raw(pings.into_boxed_slice()) as *mut u64
+}
+
+/// Frees an array from [`tl_pings_between`]. `len` must be the length it returned.
+#[no_mangle]
+pub unsafe extern "C" fn tl_pings_free(pings: *mut u64, len: usize) {
+    if !pings.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(pings, len)));
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn tl_log_new() -> *mut TlPingLog {
+    into_handle(TlPingLog(PingLog::new()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tl_log_free(log: *mut TlPingLog) {
+    free_handle(log);
+}
+
+/// Adds an answered ping with space-separated `tags`, which repersents `interval` seconds.
+/// Returns false if `tags` isn't valid UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn tl_log_push(
+    log: *mut TlPingLog,
+    time: u64,
+    tags: *const c_char,
+    interval: u32,
+) -> bool {
+    match to_str(tags) {
+        Ok(tags) => {
+            let tags = tags.split_whitespace().map(String::from).collect();
+            (*log).0.push(Ping::new(time, tags, interval));
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Number of pings in the log.
+#[no_mangle]
+pub unsafe extern "C" fn tl_log_len(log: *const TlPingLog) -> usize {
+    (*log).0.len()
+}
+
+/// Estimates the hours spent on `expr` from `start` up to (but not including) `end`.
+#[no_mangle]
+pub unsafe extern "C" fn tl_log_estimate(
+    log: *const TlPingLog,
+    expr: *const TlExpr,
+    start: u64,
+    end: u64,
+) -> TlEstimate {
+    let estimate = stats::estimate(&(*log).0, &(*expr).0, start..end);
+    TlEstimate {
+        pings: estimate.pings,
+        hours: estimate.hours,
+        low: estimate.low,
+        high: estimate.high,
+    }
+}
+
+/// Handles a JSON request for taglogic's `commands` module, returning a JSON response. Returns
+/// NULL if the request isn't valid UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn tl_dispatch(request: *const c_char) -> *mut c_char {
+    match to_str(request) {
+        Ok(request) => into_c_string(taglogic::commands::dispatch(request)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    unsafe fn take_string(s: *mut c_char) -> String {
+        let owned = CStr::from_ptr(s).to_str().unwrap().to_string();
+        tl_string_free(s);
+        owned
+    }
+
+    #[test]
+    fn expressions() {
+        unsafe {
+            let mut error = ptr::null_mut();
+            let expr = tl_expr_parse(c("(b or a) and !c").as_ptr(), &mut error);
+            assert!(!expr.is_null());
+            assert!(error.is_null());
+            assert!(tl_expr_matches(expr, c("a b").as_ptr()));
+            assert!(!tl_expr_matches(expr, c("a c").as_ptr()));
+            assert_eq!(take_string(tl_expr_tags(expr)), "a b c");
+            assert_eq!(take_string(tl_expr_format(expr)), "(b | a) & !c");
+            tl_expr_free(expr);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        unsafe {
+            let mut error = ptr::null_mut();
+            let expr = tl_expr_parse(c("a & )").as_ptr(), &mut error);
+            assert!(expr.is_null());
+            assert_eq!(
+                CStr::from_ptr(tl_parse_error_message(error)).to_str(),
+                Ok("unexpected closing bracket")
+            );
+            assert_eq!(
+                (tl_parse_error_start(error), tl_parse_error_end(error)),
+                (4, 5)
+            );
+            tl_parse_error_free(error);
+
+            let invalid = [b'a', 0xff, 0];
+            let expr = tl_expr_parse(invalid.as_ptr() as *const c_char, &mut error);
+            assert!(expr.is_null());
+            assert_eq!(tl_parse_error_start(error), 1);
+            tl_parse_error_free(error);
+
+            // errors can be ignored
+            assert!(tl_expr_parse(c(")").as_ptr(), ptr::null_mut()).is_null());
+        }
+    }
+
+    #[test]
+    fn schedule() {
+        unsafe {
+            let schedule = tl_schedule_new(1234, 28, false);
+            let mut len = 0;
+            let pings = tl_pings_between(schedule, 5, 100, &mut len);
+            assert_eq!(std::slice::from_raw_parts(pings, len), &[21, 50, 87]);
+            tl_pings_free(pings, len);
+            assert!(tl_pings_between(schedule, 100, 5, &mut len).is_null());
+            assert_eq!(len, 0);
+
+            let mut next = 0;
+            assert!(tl_next_ping_after(schedule, 21, &mut next));
+            assert_eq!(next, 50);
+            assert!(tl_last_ping_before(schedule, 50, &mut next));
+            assert_eq!(next, 21);
+            assert!(tl_should_ping_at(schedule, 87));
+            tl_schedule_free(schedule);
+
+            let universal = tl_schedule_universal();
+            assert!(tl_should_ping_at(universal, 1594907790));
+            tl_schedule_free(universal);
+
+            assert!(tl_schedule_new(1, 0, false).is_null());
+        }
+    }
+
+    #[test]
+    fn log_estimates() {
+        unsafe {
+            let log = tl_log_new();
+            assert!(tl_log_push(log, 10, c("work email").as_ptr(), 3600));
+            assert!(tl_log_push(log, 0, c("work").as_ptr(), 1800));
+            assert!(tl_log_push(log, 20, c("play").as_ptr(), 3600));
+            assert_eq!(tl_log_len(log), 3);
+            let expr = tl_expr_parse(c("work").as_ptr(), ptr::null_mut());
+            let estimate = tl_log_estimate(log, expr, 0, 100);
+            assert_eq!(estimate.pings, 2);
+            assert_eq!(estimate.hours, 1.5);
+            assert!(estimate.low < 1.5 && estimate.high > 1.5);
+            tl_expr_free(expr);
+            tl_log_free(log);
+        }
+    }
+
+    #[test]
+    fn free_accepts_null() {
+        unsafe {
+            tl_string_free(ptr::null_mut());
+            tl_expr_free(ptr::null_mut());
+            tl_parse_error_free(ptr::null_mut());
+            tl_schedule_free(ptr::null_mut());
+            tl_pings_free(ptr::null_mut(), 0);
+            tl_log_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn dispatches() {
+        unsafe {
+            let response =
+                tl_dispatch(c(r#"{"command": "parse/v1", "args": {"expr": "a"}}"#).as_ptr());
+            assert!(take_string(response).starts_with(r#"{"ok":true"#));
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! The `expr!` macro, for tag expressions that are checked when compiling, like goals defined in
+//! server code:
+//!
+//! ```
+//! use taglogic::bool::Expr;
+//! use taglogic_macros::expr;
+//!
+//! const FOCUS: Expr = expr!(work & !(email | meeting));
+//! assert!(FOCUS.matches(&["work"]));
+//! // tags that aren't Rust tokens, like `x:y` or emoji, have to be in a string
+//! let music = expr!("x:y | 🎵");
+//! assert!(music.matches(&["🎵"]));
+//! ```
+//!
+//! An invalid expression is a compile error, pointing at where the problem is:
+//!
+//! ```compile_fail
+//! let expr = taglogic_macros::expr!(work & !);
+//! ```
+//!
+//! The expression is parsed while compiling, and expands to a constant [`Expr`] that borrows its
+//! nodes from static data, so there's nothing to parse or allocate at runtime.
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+use std::fmt::Write as _;
+use std::ops::Range;
+
+use taglogic::bool::{AstNode, Expr, ParseErrorKind};
+
+/// Parses a tag expression while compiling, giving a constant [`Expr`]. The expression is either
+/// written directly, or as a string. See the [crate] docs.
+#[proc_macro]
+pub fn expr(input: TokenStream) -> TokenStream {
+    let source = match Source::new(input) {
+        Ok(source) => source,
+        Err((message, span)) => return compile_error(&message, span),
+    };
+    match Expr::parse(&source.text) {
+        Ok(expr) => expand(&expr),
+        Err(err) => {
+            let hint = match err.kind {
+                ParseErrorKind::InvalidAfterName | ParseErrorKind::ExtraTokens
+                    if !source.quoted =>
+                {
+                    " (tags with punctuation in them have to be in a string, like `expr!(\"x:y\")`)"
+                }
+                _ => "",
+            };
+            let message = format!("invalid expression: {}{}", err.kind, hint);
+            compile_error(&message, source.span_at(err.span.start))
+        }
+    }
+}
+
+/// The text of an expression given to the macro, and where each part of it came from.
+struct Source {
+    text: String,
+    /// Byte ranges of `text`, and the tokens they came from.
+    spans: Vec<(Range<usize>, Span)>,
+    /// Whether the expression was given as a string.
+    quoted: bool,
+}
+
+impl Source {
+    fn new(input: TokenStream) -> Result<Self, (String, Span)> {
+        let tokens: Vec<TokenTree> = input.into_iter().collect();
+        if let [TokenTree::Literal(literal)] = tokens.as_slice() {
+            let text = unquote(&literal.to_string()).ok_or_else(|| {
+                let message = "expected an expression, or a string without unusual escapes";
+                (message.to_string(), literal.span())
+            })?;
+            return Ok(Self {
+                spans: vec![(0..text.len(), literal.span())],
+                text,
+                quoted: true,
+            });
+        }
+        let mut source = Self {
+            text: String::new(),
+            spans: Vec::new(),
+            quoted: false,
+        };
+        for token in tokens {
+            source.push(token)?;
+        }
+        Ok(source)
+    }
+
+    fn push(&mut self, token: TokenTree) -> Result<(), (String, Span)> {
+        let (open, close) = match &token {
+            TokenTree::Group(group) => match group.delimiter() {
+                Delimiter::Parenthesis => ("(", ")"),
+                // from a `macro_rules!` fragment
+                Delimiter::None => ("", ""),
+                _ => return Err(("only `()` brackets can be used".to_string(), group.span())),
+            },
+            _ => {
+                // tokens are separated, since whether they were is lost
+                self.append(" ", token.span());
+                self.append(&token.to_string(), token.span());
+                return Ok(());
+            }
+        };
+        if let TokenTree::Group(group) = token {
+            self.append(open, group.span_open());
+            for inner in group.stream() {
+                self.push(inner)?;
+            }
+            self.append(close, group.span_close());
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, text: &str, span: Span) {
+        let start = self.text.len();
+        self.text.push_str(text);
+        self.spans.push((start..self.text.len(), span));
+    }
+
+    /// The span of the token at byte `offset` of the text, or the last one if it's at the end.
+    fn span_at(&self, offset: usize) -> Span {
+        self.spans
+            .iter()
+            .rev()
+            .find(|(range, _)| range.start <= offset && !range.is_empty())
+            .map_or_else(Span::call_site, |(_, span)| *span)
+    }
+}
+
+/// The contents of a string literal, or None if it isn't one (or has an escape that isn't
+/// handled).
+fn unquote(literal: &str) -> Option<String> {
+    if let Some(raw) = literal.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let inner = raw.get(hashes..raw.len() - hashes)?;
+        return Some(inner.strip_prefix('"')?.strip_suffix('"')?.to_string());
+    }
+    let inner = literal.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            c @ ('\\' | '"' | '\'') => c,
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                char::from(u8::from_str_radix(&hex, 16).ok().filter(u8::is_ascii)?)
+            }
+            'u' => {
+                let rest = chars.as_str().strip_prefix('{')?;
+                let end = rest.find('}')?;
+                let code = u32::from_str_radix(&rest[..end].replace('_', ""), 16).ok()?;
+                chars = rest[end + 1..].chars();
+                char::from_u32(code)?
+            }
+            _ => return None,
+        });
+    }
+    Some(out)
+}
+
+/// A block evaluating to the expression as a constant.
+fn expand(expr: &Expr) -> TokenStream {
+    let (nodes, names) = expr.__parts();
+    let mut code = String::from(
+        "{ const EXPR: ::taglogic::bool::Expr = ::taglogic::bool::Expr::__from_parts(&[",
+    );
+    for node in nodes {
+        let _ = match *node {
+            AstNode::Invert(a) => write!(code, "::taglogic::bool::AstNode::Invert({}),", a),
+            AstNode::Binary(op, a1, a2) => write!(
+                code,
+                "::taglogic::bool::AstNode::Binary(::taglogic::bool::BinaryOp::{:?}, {}, {}),",
+                op, a1, a2
+            ),
+            AstNode::Name(start, end) => {
+                write!(code, "::taglogic::bool::AstNode::Name({}, {}),", start, end)
+            }
+        };
+    }
+    // Debug formatting a string gives a valid Rust string literal
+    let _ = write!(code, "], {:?}); EXPR }}", names);
+    code.parse()
+        .unwrap_or_else(|_| compile_error("couldn't expand expression", Span::call_site()))
+}
+
+/// `compile_error!(message)`, with the error at `span`.
+fn compile_error(message: &str, span: Span) -> TokenStream {
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+    let mut message = Literal::string(message);
+    message.set_span(span);
+    let mut args = Group::new(
+        Delimiter::Parenthesis,
+        TokenStream::from(TokenTree::Literal(message)),
+    );
+    args.set_span(span);
+    vec![
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(bang),
+        TokenTree::Group(args),
+    ]
+    .into_iter()
+    .collect()
+}
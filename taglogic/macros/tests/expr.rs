@@ -0,0 +1,32 @@
+use taglogic::bool::Expr;
+use taglogic_macros::expr;
+
+const GOAL: Expr = expr!(work & !(email | meeting));
+
+#[test]
+fn same_as_parsing() {
+    assert_eq!(GOAL, Expr::parse("work & !(email | meeting)").unwrap());
+    assert!(GOAL.matches(&["work"]));
+    assert!(!GOAL.matches(&["work", "email"]));
+    assert_eq!(expr!(a | b & c), Expr::parse("a | b & c").unwrap());
+    assert_eq!(expr!(a and !b or c), Expr::parse("a & !b | c").unwrap());
+    assert_eq!(expr!(), Expr::parse("").unwrap());
+}
+
+#[test]
+fn strings() {
+    assert_eq!(expr!("x:y | 🎵"), Expr::parse("x:y | 🎵").unwrap());
+    assert_eq!(expr!(r#"a-b & "c""#), Expr::parse(r#"a-b & "c""#).unwrap());
+    assert_eq!(expr!("caf\u{e9} | \x41"), Expr::parse("café | A").unwrap());
+}
+
+macro_rules! wrapped {
+    ($e:expr) => {
+        expr!($e)
+    };
+}
+
+#[test]
+fn from_macro_rules() {
+    assert_eq!(wrapped!(a & b), Expr::parse("a & b").unwrap());
+}
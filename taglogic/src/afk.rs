@@ -0,0 +1,233 @@
+//! Proposing tags for long runs of unanswered pings, like while the user was asleep or away from
+//! their devices, so they don't have to tag each one by hand.
+//!
+//! Like classic TagTime's `RETROTHRESH`, which tagged pings that went unanswered for too long,
+//! except the tags are only proposed, and runs mostly at night get a different tag than the rest.
+
+use chrono::{TimeZone, Timelike};
+use std::convert::TryFrom;
+
+use crate::log::{Ping, PingLog};
+use crate::{pings_between, should_ping_at_time, PingIntervalData};
+
+/// What counts as a long run of unanswered pings, and how to tag it, for [`propose_afk`].
+#[derive(Debug, Clone, Copy)]
+pub struct AfkOptions<'a, Tz> {
+    /// The time zone nights are in.
+    pub tz: &'a Tz,
+    /// The shortest time (in seconds) between answers that a run is proposed for.
+    pub min_secs: u64,
+    /// Minutes after local midnight that the night starts and ends. The night can go past
+    /// midnight.
+    pub night_start_mins: u32,
+    pub night_end_mins: u32,
+    /// The tag for runs with at least half of their pings at night.
+    pub sleep_tag: &'a str,
+    /// The tag for other runs.
+    pub away_tag: &'a str,
+}
+
+impl<'a, Tz: TimeZone> AfkOptions<'a, Tz> {
+    /// Runs of at least 3 hours, tagged `slp` if they're mostly between 10 p.m. and 10 a.m. and
+    /// `off` otherwise.
+    pub fn new(tz: &'a Tz) -> Self {
+        Self {
+            tz,
+            min_secs: 3 * 3600,
+            night_start_mins: 22 * 60,
+            night_end_mins: 10 * 60,
+            sleep_tag: "slp",
+            away_tag: "off",
+        }
+    }
+}
+
+/// A run of unanswered pings and the tag proposed for them, from [`propose_afk`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AfkProposal {
+    /// Unix timestamps (in seconds) of the unanswered pings, oldest first.
+    pub times: Vec<u64>,
+    pub tag: String,
+}
+
+impl AfkProposal {
+    /// The pings to add to the log if the user accepts, each repersenting `interval` seconds.
+    pub fn to_pings(&self, interval: u32) -> Vec<Ping> {
+        let ping = |&time| Ping::new(time, vec![self.tag.clone()], interval);
+        self.times.iter().map(ping).collect()
+    }
+}
+
+/// Proposes tags for the runs of pings on `schedule` in `start..end` that weren't answered in
+/// `log`, when there was at least `min_secs` between the answers around the run. A run at the
+/// start or end of the range only counts from its first ping or to its last one.
+///
+/// ```
+/// use chrono::Utc;
+/// use taglogic::afk::{propose_afk, AfkOptions};
+/// use taglogic::log::PingLog;
+/// use taglogic::pings_between;
+/// use taglogic::tt::UNIV_SCHED;
+///
+/// // a day with nothing answered
+/// let (start, end) = (1600000000, 1600086400);
+/// let options = AfkOptions::new(&Utc);
+/// let proposals = propose_afk(&PingLog::new(), &UNIV_SCHED, start, end, &options);
+/// assert_eq!(proposals.len(), 1);
+/// assert_eq!(proposals[0].times, pings_between(start, end - 1, &UNIV_SCHED));
+/// ```
+pub fn propose_afk<Tz: TimeZone>(
+    log: &PingLog,
+    schedule: &PingIntervalData,
+    start: u64,
+    end: u64,
+    options: &AfkOptions<Tz>,
+) -> Vec<AfkProposal> {
+    if start >= end {
+        return vec![];
+    }
+    let logged = log.range(start, end).times();
+    // pings_between needs a range of at least two seconds
+    let scheduled = if end - start > 1 {
+        pings_between(start, end - 1, schedule)
+    } else if should_ping_at_time(start, schedule) {
+        vec![start]
+    } else {
+        vec![]
+    };
+
+    let mut proposals = Vec::new();
+    let mut run: Vec<u64> = Vec::new();
+    let mut last_answer = None;
+    for time in scheduled {
+        if logged.binary_search(&time).is_ok() {
+            if let Some(proposal) = propose(&run, last_answer, Some(time), options) {
+                proposals.push(proposal);
+            }
+            run.clear();
+            last_answer = Some(time);
+        } else {
+            run.push(time);
+        }
+    }
+    proposals.extend(propose(&run, last_answer, None, options));
+    proposals
+}
+
+/// The proposal for a run of unanswered pings between two answers, if it's long enough.
+fn propose<Tz: TimeZone>(
+    run: &[u64],
+    before: Option<u64>,
+    after: Option<u64>,
+    options: &AfkOptions<Tz>,
+) -> Option<AfkProposal> {
+    let from = before.or_else(|| run.first().copied())?;
+    let to = after.or_else(|| run.last().copied())?;
+    if run.is_empty() || to - from < options.min_secs {
+        return None;
+    }
+    let at_night = run.iter().filter(|&&time| at_night(time, options)).count();
+    let tag = if 2 * at_night >= run.len() {
+        options.sleep_tag
+    } else {
+        options.away_tag
+    };
+    Some(AfkProposal {
+        times: run.to_vec(),
+        tag: tag.to_string(),
+    })
+}
+
+fn at_night<Tz: TimeZone>(time: u64, options: &AfkOptions<Tz>) -> bool {
+    let local = i64::try_from(time)
+        .ok()
+        .and_then(|secs| options.tz.timestamp_opt(secs, 0).single());
+    let mins = match local {
+        Some(local) => local.hour() * 60 + local.minute(),
+        None => return false,
+    };
+    let (start, end) = (options.night_start_mins, options.night_end_mins);
+    if start <= end {
+        start <= mins && mins < end
+    } else {
+        mins >= start || mins < end
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tt::UNIV_SCHED;
+    use chrono::{FixedOffset, Utc};
+
+    // 2020-09-13 00:00:00 UTC
+    const MIDNIGHT: u64 = 1599955200;
+    const HOUR: u64 = 3600;
+
+    /// A log with every ping answered, except in `skipped`.
+    fn answered_except(skipped: &[std::ops::Range<u64>]) -> PingLog {
+        let pings = pings_between(MIDNIGHT, MIDNIGHT + 48 * HOUR, &UNIV_SCHED).into_iter();
+        let answered = pings.filter(|time| !skipped.iter().any(|range| range.contains(time)));
+        let ping = |time| Ping::new(time, vec!["work".to_string()], 2700);
+        PingLog::from_pings(answered.map(ping).collect())
+    }
+
+    fn propose(log: &PingLog, options: &AfkOptions<Utc>) -> Vec<(String, u64, u64)> {
+        let proposals = propose_afk(log, &UNIV_SCHED, MIDNIGHT, MIDNIGHT + 48 * HOUR, options);
+        let bounds = |proposal: &AfkProposal| {
+            let first = proposal.times[0];
+            let last = proposal.times[proposal.times.len() - 1];
+            (proposal.tag.clone(), first, last)
+        };
+        proposals.iter().map(bounds).collect()
+    }
+
+    #[test]
+    fn proposes_sleep_and_away() {
+        let night = MIDNIGHT + 23 * HOUR..MIDNIGHT + 31 * HOUR;
+        let afternoon = MIDNIGHT + 13 * HOUR..MIDNIGHT + 18 * HOUR;
+        let lunch = MIDNIGHT + 36 * HOUR..MIDNIGHT + 37 * HOUR;
+        let log = answered_except(&[night.clone(), afternoon.clone(), lunch]);
+        let proposals = propose(&log, &AfkOptions::new(&Utc));
+        assert_eq!(proposals.len(), 2);
+        let (tag, first, last) = &proposals[0];
+        assert_eq!(tag, "off");
+        assert!(afternoon.contains(first) && afternoon.contains(last));
+        let (tag, first, last) = &proposals[1];
+        assert_eq!(tag, "slp");
+        assert!(night.contains(first) && night.contains(last));
+
+        // the runs are all of the unanswered pings
+        let options = AfkOptions::new(&Utc);
+        let all = propose_afk(&log, &UNIV_SCHED, MIDNIGHT, MIDNIGHT + 48 * HOUR, &options);
+        let in_night = pings_between(night.start, night.end - 1, &UNIV_SCHED);
+        assert_eq!(all[1].times, in_night);
+        let pings = all[1].to_pings(2700);
+        assert_eq!(pings[0].tags, ["slp"]);
+        assert_eq!(pings.len(), in_night.len());
+
+        // nothing to propose with everything answered, or nothing scheduled
+        assert!(propose(&answered_except(&[]), &options).is_empty());
+        assert!(propose_afk(&log, &UNIV_SCHED, MIDNIGHT, MIDNIGHT, &options).is_empty());
+    }
+
+    #[test]
+    fn uses_options() {
+        let afternoon = MIDNIGHT + 13 * HOUR..MIDNIGHT + 18 * HOUR;
+        let log = answered_except(&[afternoon]);
+        let options = AfkOptions {
+            min_secs: 8 * HOUR,
+            ..AfkOptions::new(&Utc)
+        };
+        assert!(propose(&log, &options).is_empty());
+
+        // in UTC+12 the afternoon is the middle of the night
+        let tz = FixedOffset::east_opt(12 * 3600).unwrap();
+        let options = AfkOptions {
+            sleep_tag: "asleep",
+            ..AfkOptions::new(&tz)
+        };
+        let proposals = propose_afk(&log, &UNIV_SCHED, MIDNIGHT, MIDNIGHT + 48 * HOUR, &options);
+        assert_eq!(proposals[0].tag, "asleep");
+    }
+}
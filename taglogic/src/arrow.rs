@@ -0,0 +1,191 @@
+//! Converting logs and time series to Arrow record batches, which Polars, DuckDB and pandas can
+//! read without copying.
+
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use arrow_array::builder::{ListBuilder, StringBuilder};
+use arrow_array::{
+    ArrayRef, BooleanArray, RecordBatch, StringArray, TimestampSecondArray, UInt32Array,
+};
+use arrow_schema::ArrowError;
+
+use crate::bool::Expr;
+use crate::log::PingLog;
+
+impl PingLog {
+    /// The log as a record batch with one row per ping, and the columns `time` and `answered`
+    /// (UTC timestamps, with `answered` null if it wasn't recorded), `tags` (a list of strings),
+    /// `interval` and `comment`. Each of `exprs` adds a boolean column with its name, which is true
+    /// for pings matching it.
+    ///
+    /// Fails if a timestamp is too large for Arrow to repersent.
+    pub fn to_arrow(&self, exprs: &[(&str, &Expr)]) -> Result<RecordBatch, ArrowError> {
+        let pings = self.pings();
+        let times = pings
+            .iter()
+            .map(|ping| timestamp(ping.time))
+            .collect::<Result<Vec<_>, _>>()?;
+        let answered = pings
+            .iter()
+            .map(|ping| ping.answered.map(timestamp).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut tags = ListBuilder::new(StringBuilder::new());
+        for ping in &pings {
+            tags.append_value(ping.tags.iter().map(Some));
+        }
+
+        let mut columns: Vec<(&str, ArrayRef, bool)> = vec![
+            (
+                "time",
+                Arc::new(TimestampSecondArray::from(times).with_timezone_utc()),
+                false,
+            ),
+            ("tags", Arc::new(tags.finish()), false),
+            (
+                "interval",
+                Arc::new(UInt32Array::from_iter_values(
+                    pings.iter().map(|ping| ping.interval),
+                )),
+                false,
+            ),
+            (
+                "comment",
+                Arc::new(StringArray::from(
+                    pings.iter().map(|ping| ping.comment).collect::<Vec<_>>(),
+                )),
+                true,
+            ),
+            (
+                "answered",
+                Arc::new(TimestampSecondArray::from(answered).with_timezone_utc()),
+                true,
+            ),
+        ];
+        for (name, expr) in exprs {
+            let matches = BooleanArray::from(self.matches_many(expr));
+            columns.push((name, Arc::new(matches), false));
+        }
+        RecordBatch::try_from_iter_with_nullable(columns)
+    }
+}
+
+#[cfg(feature = "stats")]
+impl crate::stats::TimeSeries {
+    /// The series as a record batch with one row per bucket, and the columns `start` (a date),
+    /// `pings`, and `hours`, `low` and `high` from the bucket's estimate.
+    pub fn to_arrow(&self) -> Result<RecordBatch, ArrowError> {
+        use arrow_array::{Date32Array, Float64Array};
+        use chrono::DateTime;
+
+        let epoch = DateTime::UNIX_EPOCH.date_naive();
+        let points = &self.points;
+        let float_column = |value: fn(&crate::stats::TimeEstimate) -> f64| -> ArrayRef {
+            Arc::new(Float64Array::from_iter_values(
+                points.iter().map(|point| value(&point.estimate)),
+            ))
+        };
+        let columns: Vec<(&str, ArrayRef)> = vec![
+            (
+                "start",
+                Arc::new(Date32Array::from_iter_values(points.iter().map(|point| {
+                    // dates are at most 262,143 years from the epoch, so this always fits
+                    (point.start - epoch).num_days() as i32
+                }))),
+            ),
+            (
+                "pings",
+                Arc::new(UInt32Array::from_iter_values(
+                    points.iter().map(|point| point.estimate.pings),
+                )),
+            ),
+            ("hours", float_column(|estimate| estimate.hours)),
+            ("low", float_column(|estimate| estimate.low)),
+            ("high", float_column(|estimate| estimate.high)),
+        ];
+        RecordBatch::try_from_iter(columns)
+    }
+}
+
+fn timestamp(time: u64) -> Result<i64, ArrowError> {
+    i64::try_from(time).map_err(|_| {
+        ArrowError::InvalidArgumentError(format!("timestamp {} is out of range", time))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{TimestampSecondType, UInt32Type};
+    use arrow_array::Array;
+
+    #[test]
+    fn log_to_arrow() {
+        let mut ping = Ping::new(20, vec!["b".into()], 2700);
+        ping.comment = Some("hi".into());
+        ping.answered = Some(25);
+        let log = PingLog::from_pings(vec![
+            Ping::new(10, vec!["a".into(), "b".into()], 1800),
+            ping,
+        ]);
+        let a = Expr::from_string("a").unwrap();
+        let batch = log.to_arrow(&[("is_a", &a)]).unwrap();
+
+        assert_eq!(batch.num_rows(), 2);
+        let names: Vec<&str> = batch
+            .schema_ref()
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["time", "tags", "interval", "comment", "answered", "is_a"]
+        );
+
+        let times = batch.column(0).as_primitive::<TimestampSecondType>();
+        assert_eq!(times.values(), &[10, 20]);
+        let tags = batch.column(1).as_list::<i32>();
+        assert_eq!(tags.value(0).as_string::<i32>().value(1), "b");
+        assert_eq!(tags.value(1).len(), 1);
+        let intervals = batch.column(2).as_primitive::<UInt32Type>();
+        assert_eq!(intervals.values(), &[1800, 2700]);
+        let comments = batch.column(3).as_string::<i32>();
+        assert!(comments.is_null(0));
+        assert_eq!(comments.value(1), "hi");
+        let answered = batch.column(4).as_primitive::<TimestampSecondType>();
+        assert!(answered.is_null(0));
+        assert_eq!(answered.value(1), 25);
+        let is_a = batch.column(5).as_boolean();
+        assert!(is_a.value(0));
+        assert!(!is_a.value(1));
+
+        let huge = PingLog::from_pings(vec![Ping::new(u64::MAX, vec![], 2700)]);
+        assert!(huge.to_arrow(&[]).is_err());
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn time_series_to_arrow() {
+        use crate::stats::{time_series, Bucket};
+        use arrow_array::types::{Date32Type, Float64Type};
+        use chrono::Utc;
+
+        let log = PingLog::from_pings(vec![
+            Ping::new(0, vec!["a".into()], 3600),
+            Ping::new(2 * 86400, vec!["a".into()], 3600),
+        ]);
+        let series = time_series(&log, &Expr::from_string("a").unwrap(), Bucket::Day, &Utc);
+        let batch = series.to_arrow().unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        let starts = batch.column(0).as_primitive::<Date32Type>();
+        assert_eq!(starts.values(), &[0, 1, 2]);
+        let hours = batch.column_by_name("hours").unwrap();
+        assert_eq!(
+            hours.as_primitive::<Float64Type>().values(),
+            &[1.0, 0.0, 1.0]
+        );
+    }
+}
@@ -0,0 +1,387 @@
+//! Snapshots of logs saved to a directory, so a bad sync or a bug can't lose years of pings.
+//!
+//! Each snapshot is a gzipped file named for when it was taken, like
+//! `ttw-20240301T120000Z.json.gz`. It holds the log as JSON in a
+//! [versioned envelope](crate::format::wrap), so snapshots from older versions still
+//! [restore](Backups::restore) through the format's migrations, after a first line with the
+//! SHA-256 of the rest, so a snapshot that's been truncated or corrupted is caught by
+//! [`Backups::verify`] instead of being restored. Since it's plain gzip, `zcat` can read one
+//! without ttw.
+//!
+//! Old snapshots are removed by [`Backups::prune`], which keeps the newest snapshot of each of
+//! the last few days and weeks that have them, like `rsnapshot` or Time Machine.
+
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDateTime};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+
+use crate::format::{self, Format, FormatError};
+use crate::log::{Ping, PingLog};
+
+const PREFIX: &str = "ttw-";
+const SUFFIX: &str = ".json.gz";
+const TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const CHECKSUM_PREFIX: &str = "sha256 ";
+
+/// An error writing, reading or pruning snapshots.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum BackupError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// A time that can't be written as a date.
+    #[error("can't name a snapshot taken at {0}")]
+    InvalidTime(u64),
+    /// A snapshot that isn't gzipped text starting with a checksum, so it isn't one or it's been
+    /// cut short.
+    #[error("{} isn't a snapshot", .0.display())]
+    NotSnapshot(PathBuf),
+    /// A snapshot whose contents don't match its checksum, so it's been changed or damaged since
+    /// it was written.
+    #[error("{} doesn't match its checksum", .0.display())]
+    ChecksumMismatch(PathBuf),
+    #[error("invalid snapshot: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Format(#[from] FormatError),
+}
+
+/// A snapshot in a [`Backups`] directory.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Snapshot {
+    /// Unix timestamp (in seconds) of when the snapshot was taken.
+    pub time: u64,
+    pub path: PathBuf,
+}
+
+/// How many old snapshots [`Backups::prune`] keeps: the newest of each of the last `daily` days
+/// and the last `weekly` weeks (starting on Monday) that have any, in UTC. The newest snapshot is
+/// always kept.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub daily: usize,
+    pub weekly: usize,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            daily: 7,
+            weekly: 4,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// Which of the snapshots taken at `times` (in any order) to keep.
+    ///
+    /// ```
+    /// use taglogic::backup::RetentionPolicy;
+    ///
+    /// let day = 86400;
+    /// // two a day for three weeks
+    /// let times: Vec<u64> = (0..42).map(|i| 100 * day + i * day / 2).collect();
+    /// let kept: Vec<u64> = RetentionPolicy { daily: 2, weekly: 2 }.keep(&times).into_iter().collect();
+    /// assert_eq!(kept, [115 * day + day / 2, 119 * day + day / 2, 120 * day + day / 2]);
+    /// ```
+    pub fn keep(&self, times: &[u64]) -> BTreeSet<u64> {
+        let mut newest_first = times.to_vec();
+        newest_first.sort_unstable_by(|a, b| b.cmp(a));
+        let mut kept: BTreeSet<u64> = newest_first.first().copied().into_iter().collect();
+        let mut days = BTreeSet::new();
+        let mut weeks = BTreeSet::new();
+        for &time in &newest_first {
+            let day = time / 86400;
+            // 1970-01-01 was a Thursday
+            let week = (day + 3) / 7;
+            if days.len() < self.daily && days.insert(day) {
+                kept.insert(time);
+            }
+            if weeks.len() < self.weekly && weeks.insert(week) {
+                kept.insert(time);
+            }
+        }
+        kept
+    }
+}
+
+/// A directory of snapshots.
+///
+/// ```
+/// use taglogic::backup::{Backups, RetentionPolicy};
+/// use taglogic::log::{Ping, PingLog};
+///
+/// let dir = std::env::temp_dir().join(format!("taglogic-doc-backups-{}", std::process::id()));
+/// let backups = Backups::new(&dir);
+/// let log = PingLog::from_pings(vec![Ping::new(1_700_000_000, vec!["work".into()], 2700)]);
+/// let snapshot = backups.write(&log, 1_700_000_100).unwrap();
+/// assert_eq!(backups.restore(&snapshot).unwrap(), log);
+/// assert!(backups.prune(&RetentionPolicy::default()).unwrap().is_empty());
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Backups {
+    dir: PathBuf,
+}
+
+impl Backups {
+    /// Snapshots in `dir`, which is made when the first one is written.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Saves a snapshot of `log` taken at `time`, replacing any taken in the same second. It's
+    /// written to a temporary file first, so a crash never leaves half a snapshot.
+    pub fn write(&self, log: &PingLog, time: u64) -> Result<Snapshot, BackupError> {
+        let name = (i64::try_from(time).ok())
+            .and_then(|secs| DateTime::from_timestamp(secs, 0))
+            .ok_or(BackupError::InvalidTime(time))?
+            .format(TIME_FORMAT);
+        let path = self.dir.join(format!("{}{}{}", PREFIX, name, SUFFIX));
+        let pings = serde_json::to_value(log.pings())?;
+        let body = serde_json::to_string(&format::wrap(Format::Log, pings))?;
+
+        fs::create_dir_all(&self.dir)?;
+        let temp = path.with_extension("gz.tmp");
+        let contents = format!("{}{}\n{}", CHECKSUM_PREFIX, checksum(body.as_bytes()), body);
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(contents.as_bytes())?;
+        fs::write(&temp, gzip.finish()?)?;
+        fs::rename(&temp, &path)?;
+        Ok(Snapshot { time, path })
+    }
+
+    /// Every snapshot in the directory, oldest first. Other files are ignored, and a directory
+    /// that doesn't exist has no snapshots.
+    pub fn list(&self) -> Result<Vec<Snapshot>, BackupError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut snapshots = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let time = (path.file_name().and_then(|name| name.to_str()))
+                .and_then(|name| name.strip_prefix(PREFIX)?.strip_suffix(SUFFIX))
+                .and_then(|name| NaiveDateTime::parse_from_str(name, TIME_FORMAT).ok())
+                .and_then(|time| u64::try_from(time.and_utc().timestamp()).ok());
+            if let Some(time) = time {
+                snapshots.push(Snapshot { time, path });
+            }
+        }
+        snapshots.sort();
+        Ok(snapshots)
+    }
+
+    /// Checks that a snapshot is intact and can be restored, without restoring it.
+    pub fn verify(&self, snapshot: &Snapshot) -> Result<(), BackupError> {
+        self.restore(snapshot).map(drop)
+    }
+
+    /// Reads the log back from a snapshot, after checking its checksum. Its pings are restored,
+    /// but not the log's [audit trail](PingLog::audit).
+    pub fn restore(&self, snapshot: &Snapshot) -> Result<PingLog, BackupError> {
+        let compressed = fs::read(&snapshot.path)?;
+        let not_snapshot = || BackupError::NotSnapshot(snapshot.path.clone());
+        let mut contents = String::new();
+        (GzDecoder::new(compressed.as_slice()))
+            .read_to_string(&mut contents)
+            .map_err(|_| not_snapshot())?;
+        let (header, body) = contents.split_once('\n').ok_or_else(not_snapshot)?;
+        let expected = header
+            .strip_prefix(CHECKSUM_PREFIX)
+            .ok_or_else(not_snapshot)?;
+        if checksum(body.as_bytes()) != expected {
+            return Err(BackupError::ChecksumMismatch(snapshot.path.clone()));
+        }
+        let pings = format::load(Format::Log, serde_json::from_str(body)?)?;
+        let pings: Vec<Ping> = serde_json::from_value(pings)?;
+        Ok(PingLog::from_pings(pings))
+    }
+
+    /// The log from the newest snapshot that's intact, skipping any that aren't, or None if
+    /// there isn't one.
+    pub fn restore_latest(&self) -> Result<Option<PingLog>, BackupError> {
+        Ok(self
+            .list()?
+            .iter()
+            .rev()
+            .find_map(|snapshot| self.restore(snapshot).ok()))
+    }
+
+    /// Removes the snapshots `policy` doesn't keep, giving the ones it removed. Only intact
+    /// snapshots count towards the policy, so a damaged one never pushes out a good one, and
+    /// damaged ones are left for [`verify`](Self::verify) to find instead of being removed.
+    pub fn prune(&self, policy: &RetentionPolicy) -> Result<Vec<Snapshot>, BackupError> {
+        let intact: Vec<Snapshot> = (self.list()?.into_iter())
+            .filter(|snapshot| self.verify(snapshot).is_ok())
+            .collect();
+        let times: Vec<u64> = intact.iter().map(|snapshot| snapshot.time).collect();
+        let kept = policy.keep(&times);
+        let mut removed = Vec::new();
+        for snapshot in intact {
+            if !kept.contains(&snapshot.time) {
+                fs::remove_file(&snapshot.path)?;
+                removed.push(snapshot);
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// The hex SHA-256 of `bytes`.
+fn checksum(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Weight;
+    use std::collections::BTreeMap;
+
+    fn temp_backups(name: &str) -> Backups {
+        let dir =
+            std::env::temp_dir().join(format!("taglogic-backups-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Backups::new(dir)
+    }
+
+    fn gzip(text: &str) -> Vec<u8> {
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(text.as_bytes()).unwrap();
+        gzip.finish().unwrap()
+    }
+
+    fn gunzip(path: &Path) -> String {
+        let mut text = String::new();
+        (GzDecoder::new(fs::File::open(path).unwrap()))
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    fn log() -> PingLog {
+        let mut ping = Ping::new(1_700_000_000, vec!["work".into(), "email".into()], 2700);
+        ping.comment = Some("inbox zero".to_string());
+        ping.author = Some("phone".to_string());
+        let weight = |weight| Weight::new(weight).unwrap();
+        ping.weights = BTreeMap::from([
+            ("work".to_string(), weight(0.75)),
+            ("email".to_string(), weight(0.25)),
+        ]);
+        PingLog::from_pings(vec![
+            ping,
+            Ping::new(1_700_003_000, vec!["sleep".into()], 2700),
+        ])
+    }
+
+    #[test]
+    fn writes_and_restores() {
+        let backups = temp_backups("restore");
+        assert_eq!(backups.list().unwrap(), []);
+        assert!(backups.restore_latest().unwrap().is_none());
+
+        let old = backups.write(&PingLog::default(), 1_700_000_000).unwrap();
+        let snapshot = backups.write(&log(), 1_700_086_400).unwrap();
+        assert!(snapshot.path.ends_with("ttw-20231115T221320Z.json.gz"));
+        assert!(gunzip(&snapshot.path).starts_with(CHECKSUM_PREFIX));
+        fs::write(backups.dir().join("notes.txt"), "not a snapshot").unwrap();
+        assert_eq!(backups.list().unwrap(), [old.clone(), snapshot.clone()]);
+        // everything comes back, including authors and weights
+        assert_eq!(backups.restore(&snapshot).unwrap(), log());
+        assert_eq!(backups.restore_latest().unwrap(), Some(log()));
+
+        // a changed snapshot fails its checksum, so the one before it is restored instead
+        let contents = gunzip(&snapshot.path);
+        fs::write(&snapshot.path, gzip(&contents.replace("sleep", "sleeP"))).unwrap();
+        assert!(matches!(
+            backups.verify(&snapshot),
+            Err(BackupError::ChecksumMismatch(_))
+        ));
+        assert_eq!(backups.restore_latest().unwrap(), Some(PingLog::default()));
+        fs::write(&snapshot.path, gzip("{}")).unwrap();
+        assert!(matches!(
+            backups.verify(&snapshot),
+            Err(BackupError::NotSnapshot(_))
+        ));
+        // as does one that isn't gzipped, or was cut short
+        fs::write(&snapshot.path, contents).unwrap();
+        assert!(matches!(
+            backups.verify(&snapshot),
+            Err(BackupError::NotSnapshot(_))
+        ));
+        let compressed = gzip(&gunzip(&old.path));
+        fs::write(&snapshot.path, &compressed[..compressed.len() - 4]).unwrap();
+        assert!(matches!(
+            backups.verify(&snapshot),
+            Err(BackupError::NotSnapshot(_))
+        ));
+        assert!(matches!(
+            backups.write(&log(), u64::MAX),
+            Err(BackupError::InvalidTime(_))
+        ));
+        fs::remove_dir_all(backups.dir()).unwrap();
+    }
+
+    #[test]
+    fn keeps_daily_and_weekly() {
+        let day = 86400;
+        let policy = RetentionPolicy {
+            daily: 3,
+            weekly: 0,
+        };
+        let times = [day, day + 10, 2 * day, 5 * day, 9 * day];
+        let kept: Vec<u64> = policy.keep(&times).into_iter().collect();
+        assert_eq!(kept, [2 * day, 5 * day, 9 * day]);
+        let none = RetentionPolicy {
+            daily: 0,
+            weekly: 0,
+        };
+        assert_eq!(none.keep(&times).into_iter().collect::<Vec<_>>(), [9 * day]);
+        // day 4 is a Monday, so days 1 and 2 are in the week before 5 and 9
+        let weekly = RetentionPolicy {
+            daily: 1,
+            weekly: 2,
+        };
+        let kept: Vec<u64> = weekly.keep(&times).into_iter().collect();
+        assert_eq!(kept, [2 * day, 9 * day]);
+    }
+
+    #[test]
+    fn prunes_intact_snapshots() {
+        let backups = temp_backups("prune");
+        let day = 86400;
+        let times = [day, 2 * day, 3 * day, 3 * day + 60];
+        let snapshots: Vec<Snapshot> = (times.iter())
+            .map(|&time| backups.write(&log(), time).unwrap())
+            .collect();
+        // the newest is damaged, so the one before it is kept in its place
+        fs::write(&snapshots[3].path, gzip("sha256 0\n[]")).unwrap();
+        let policy = RetentionPolicy {
+            daily: 2,
+            weekly: 0,
+        };
+        let removed = backups.prune(&policy).unwrap();
+        assert_eq!(removed, [snapshots[0].clone()]);
+        assert_eq!(backups.list().unwrap(), snapshots[1..]);
+        assert!(backups.prune(&policy).unwrap().is_empty());
+        fs::remove_dir_all(backups.dir()).unwrap();
+    }
+}
@@ -0,0 +1,338 @@
+//! Pushing time spent to Beeminder goals, like the original TagTime's `beeminder.pl`.
+//!
+//! Each local day with matching pings gets one datapoint, with the estimated hours spent that day.
+//! Syncing makes a goal's datapoints on the synced days match the local ones exactly: missing days
+//! are created, days with a different value are updated, and any other datapoints on those days
+//! (including extra ones on days that already have one) are deleted.
+
+use std::ops::RangeInclusive;
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::bool::Expr;
+use crate::log::PingLog;
+use crate::stats::{daily_tallies, day_start, Calendar};
+
+const BASE_URL: &str = "https://www.beeminder.com";
+const USER_AGENT: &str = "TagTimeWeb/1.0 (ttw@smitop.com)";
+/// Values closer than this are treated as equal, since they're only sent with 5 decimal places.
+const TOLERANCE: f64 = 1e-5;
+
+/// A datapoint for one day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Datapoint {
+    pub daystamp: NaiveDate,
+    /// Unix timestamp of the datapoint. Beeminder uses this (and the goal's deadline) to decide
+    /// which day the datapoint is on, so local datapoints are at noon.
+    pub timestamp: u64,
+    pub value: f64,
+    pub comment: String,
+}
+
+/// A datapoint that's already on Beeminder.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteDatapoint {
+    pub id: String,
+    pub datapoint: Datapoint,
+}
+
+/// Changes needed to make a goal's datapoints match the local ones.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncPlan {
+    pub create: Vec<Datapoint>,
+    /// IDs of remote datapoints, and what they should be changed to.
+    pub update: Vec<(String, Datapoint)>,
+    /// IDs of remote datapoints to delete.
+    pub delete: Vec<String>,
+}
+
+impl SyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.create.is_empty() && self.update.is_empty() && self.delete.is_empty()
+    }
+}
+
+/// One datapoint for each day in `days` with pings matching `expr`, with days in the time zone
+/// `tz`.
+pub fn local_datapoints<Tz: Calendar>(
+    log: &PingLog,
+    expr: &Expr,
+    days: RangeInclusive<NaiveDate>,
+    tz: &Tz,
+) -> Vec<Datapoint> {
+    daily_tallies(log, expr, days, tz)
+        .into_iter()
+        .filter(|(_, tally)| tally.matching() > 0)
+        .filter_map(|(daystamp, tally)| {
+            let timestamp = day_start(daystamp, tz)? + 12 * 3600;
+            Some(Datapoint {
+                daystamp,
+                timestamp,
+                value: tally.estimate().hours,
+                comment: format!("{} pings (via TagTime Web)", tally.matching()),
+            })
+        })
+        .collect()
+}
+
+/// Works out how to make the remote datapoints on `days` match `local`. Datapoints on other days
+/// are left alone.
+pub fn plan_sync(
+    local: &[Datapoint],
+    remote: &[RemoteDatapoint],
+    days: RangeInclusive<NaiveDate>,
+) -> SyncPlan {
+    let mut plan = SyncPlan::default();
+    let mut remote: Vec<&RemoteDatapoint> = remote
+        .iter()
+        .filter(|point| days.contains(&point.datapoint.daystamp))
+        .collect();
+    for point in local.iter().filter(|point| days.contains(&point.daystamp)) {
+        match remote
+            .iter()
+            .position(|other| other.datapoint.daystamp == point.daystamp)
+        {
+            Some(index) => {
+                let existing = remote.remove(index);
+                if (existing.datapoint.value - point.value).abs() > TOLERANCE {
+                    plan.update.push((existing.id.clone(), point.clone()));
+                }
+            }
+            None => plan.create.push(point.clone()),
+        }
+    }
+    plan.delete = remote.into_iter().map(|point| point.id.clone()).collect();
+    plan
+}
+
+/// An error talking to Beeminder.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The request couldn't be sent, or the response couldn't be read.
+    #[error("couldn't reach Beeminder: {0}")]
+    Http(#[from] reqwest::Error),
+    /// Beeminder responded with an error.
+    #[error("Beeminder responded with {status}: {body}")]
+    Api { status: u16, body: String },
+}
+
+/// A datapoint as returned by the API.
+#[derive(Debug, Deserialize)]
+struct ApiDatapoint {
+    id: String,
+    timestamp: u64,
+    daystamp: String,
+    value: f64,
+    #[serde(default)]
+    comment: String,
+}
+
+impl ApiDatapoint {
+    fn into_remote(self) -> Option<RemoteDatapoint> {
+        Some(RemoteDatapoint {
+            id: self.id,
+            datapoint: Datapoint {
+                daystamp: NaiveDate::parse_from_str(&self.daystamp, "%Y%m%d").ok()?,
+                timestamp: self.timestamp,
+                value: self.value,
+                comment: self.comment,
+            },
+        })
+    }
+}
+
+/// A client for one Beeminder user, authenticated with their personal auth token.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    user: String,
+    auth_token: String,
+}
+
+impl Client {
+    pub fn new(user: &str, auth_token: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: BASE_URL.to_string(),
+            user: user.to_string(),
+            auth_token: auth_token.to_string(),
+        }
+    }
+
+    /// Sends requests to `base_url` instead of Beeminder, for testing against a fake server.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    fn url(&self, goal: &str, path: &str) -> String {
+        format!(
+            "{}/api/v1/users/{}/goals/{}/{}",
+            self.base_url, self.user, goal, path
+        )
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, Error> {
+        let response = request
+            .query(&[("auth_token", &self.auth_token)])
+            .header(reqwest::header::USER_AGENT, USER_AGENT)
+            .send()
+            .await?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            Err(Error::Api {
+                status: status.as_u16(),
+                body: response.text().await.unwrap_or_default(),
+            })
+        }
+    }
+
+    /// All of a goal's datapoints. Datapoints with invalid daystamps are skipped.
+    pub async fn datapoints(&self, goal: &str) -> Result<Vec<RemoteDatapoint>, Error> {
+        let request = self.http.get(self.url(goal, "datapoints.json"));
+        let points: Vec<ApiDatapoint> = self.send(request).await?.json().await?;
+        Ok(points
+            .into_iter()
+            .filter_map(ApiDatapoint::into_remote)
+            .collect())
+    }
+
+    pub async fn create(&self, goal: &str, point: &Datapoint) -> Result<(), Error> {
+        let request = self
+            .http
+            .post(self.url(goal, "datapoints.json"))
+            .form(&form(point));
+        self.send(request).await.map(drop)
+    }
+
+    pub async fn update(&self, goal: &str, id: &str, point: &Datapoint) -> Result<(), Error> {
+        let path = format!("datapoints/{}.json", id);
+        let request = self.http.put(self.url(goal, &path)).form(&form(point));
+        self.send(request).await.map(drop)
+    }
+
+    pub async fn delete(&self, goal: &str, id: &str) -> Result<(), Error> {
+        let path = format!("datapoints/{}.json", id);
+        self.send(self.http.delete(self.url(goal, &path)))
+            .await
+            .map(drop)
+    }
+
+    /// Makes the goal's datapoints on `days` match `local`, returning the changes that were made.
+    /// If a request fails partway through, the changes before it are kept, and syncing again will
+    /// finish the job.
+    pub async fn sync(
+        &self,
+        goal: &str,
+        local: &[Datapoint],
+        days: RangeInclusive<NaiveDate>,
+    ) -> Result<SyncPlan, Error> {
+        let remote = self.datapoints(goal).await?;
+        let plan = plan_sync(local, &remote, days);
+        for point in &plan.create {
+            self.create(goal, point).await?;
+        }
+        for (id, point) in &plan.update {
+            self.update(goal, id, point).await?;
+        }
+        for id in &plan.delete {
+            self.delete(goal, id).await?;
+        }
+        Ok(plan)
+    }
+}
+
+fn form(point: &Datapoint) -> [(&'static str, String); 4] {
+    [
+        ("value", format!("{:.5}", point.value)),
+        ("timestamp", point.timestamp.to_string()),
+        ("comment", point.comment.clone()),
+        // makes retrying a create that actually went through harmless
+        (
+            "requestid",
+            format!("ttw-{}", point.daystamp.format("%Y%m%d")),
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use chrono::Utc;
+
+    fn date(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2021, 1, d).unwrap()
+    }
+
+    fn point(d: u32, value: f64) -> Datapoint {
+        Datapoint {
+            daystamp: date(d),
+            timestamp: 0,
+            value,
+            comment: String::new(),
+        }
+    }
+
+    fn remote(id: &str, d: u32, value: f64) -> RemoteDatapoint {
+        RemoteDatapoint {
+            id: id.to_string(),
+            datapoint: point(d, value),
+        }
+    }
+
+    #[test]
+    fn one_datapoint_per_day() {
+        // 2021-01-04 00:00:00 UTC
+        let monday = 1609718400;
+        let log = PingLog::from_pings(vec![
+            Ping::new(monday + 100, vec!["a".into()], 3600),
+            Ping::new(monday + 200, vec!["a".into()], 1800),
+            Ping::new(monday + 86400, vec!["b".into()], 3600),
+            Ping::new(monday + 2 * 86400, vec!["a".into()], 3600),
+        ]);
+        let expr = Expr::from_string("a").unwrap();
+        let points = local_datapoints(&log, &expr, date(4)..=date(6), &Utc);
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].daystamp, date(4));
+        assert_eq!(points[0].timestamp, monday + 12 * 3600);
+        assert_eq!(points[0].value, 1.5);
+        assert_eq!(points[0].comment, "2 pings (via TagTime Web)");
+        assert_eq!(points[1].daystamp, date(6));
+    }
+
+    #[test]
+    fn plans_sync() {
+        let local = vec![point(1, 1.0), point(2, 2.0), point(3, 3.0)];
+        let remote = vec![
+            remote("same", 1, 1.000001),
+            remote("changed", 2, 1.0),
+            remote("extra", 2, 2.0),
+            remote("gone", 4, 1.0),
+            remote("outside", 9, 1.0),
+        ];
+        let plan = plan_sync(&local, &remote, date(1)..=date(5));
+        assert_eq!(plan.create, vec![point(3, 3.0)]);
+        assert_eq!(plan.update, vec![("changed".to_string(), point(2, 2.0))]);
+        assert_eq!(plan.delete, vec!["extra".to_string(), "gone".to_string()]);
+        assert!(plan_sync(&local, &[], date(9)..=date(9)).is_empty());
+        assert!(plan_sync(&[], &remote, date(6)..=date(8)).is_empty());
+    }
+
+    #[test]
+    fn reads_api_datapoints() {
+        let points: Vec<ApiDatapoint> = serde_json::from_str(
+            r#"[{"id": "abc", "timestamp": 1609761600, "daystamp": "20210104", "value": 1.5,
+                 "comment": "hi", "requestid": null, "updated_at": 1609761601}]"#,
+        )
+        .unwrap();
+        let point = points.into_iter().next().unwrap().into_remote().unwrap();
+        assert_eq!(point.id, "abc");
+        assert_eq!(point.datapoint.daystamp, date(4));
+        assert_eq!(point.datapoint.value, 1.5);
+    }
+}
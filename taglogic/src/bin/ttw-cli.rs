@@ -0,0 +1,827 @@
+//! Command line access to taglogic, for scripts and for poking at logs outside of the browser.
+//!
+//! Logs are read as JSON arrays of pings if their file name ends in `.json`, as binary logs if it
+//! ends in `.ttwlog`, and as TagTime `.log` files otherwise. Binary logs are memory-mapped, so
+//! `query` and `stats` only read the pings they use.
+//!
+//! `repl` reads a log once and then takes commands for it, with history and tab completion of
+//! commands and tags (see `HELP`).
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::FixedOffset;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use taglogic::binlog::{self, MappedLog};
+use taglogic::bool::{self, Expr};
+use taglogic::diagnostics::Diagnostic;
+use taglogic::log::{Ping, PingLog};
+use taglogic::{import, stats, tt};
+
+#[derive(Debug, Parser)]
+#[command(name = "ttw-cli", about = "Query TagTime logs and ping schedules")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Checks an expression, and prints it in canonical form
+    Parse { expr: String },
+    /// Prints the pings in a log that match an expression, as a TagTime log
+    Query {
+        expr: String,
+        log: PathBuf,
+        #[command(flatten)]
+        options: LogOptions,
+    },
+    /// Prints the estimated time spent on pings matching an expression
+    Stats {
+        expr: String,
+        log: PathBuf,
+        #[command(flatten)]
+        options: LogOptions,
+        /// Print the estimate for each bucket instead of the total
+        #[arg(long)]
+        bucket: Option<BucketArg>,
+        /// Minutes ahead of UTC that days start at, for bucketing
+        #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+        utc_offset: i32,
+    },
+    /// Converts a TagTime log to JSON
+    Import {
+        log: PathBuf,
+        /// Seconds each ping repersents, since TagTime logs don't record it
+        #[arg(long, default_value_t = 2700)]
+        interval: u32,
+    },
+    /// Converts a JSON log to a TagTime log
+    Export { log: PathBuf },
+    /// Converts a log to a binary log, which is quicker to query when it's big
+    Pack {
+        log: PathBuf,
+        /// Where to write the binary log
+        out: PathBuf,
+        /// Seconds each ping in a TagTime log repersents
+        #[arg(long, default_value_t = 2700)]
+        interval: u32,
+    },
+    /// Prints how much memory a log takes once it's read, in bytes
+    Memory {
+        log: PathBuf,
+        /// Seconds each ping in a TagTime log repersents
+        #[arg(long, default_value_t = 2700)]
+        interval: u32,
+    },
+    /// Reads a log, then runs commands on it typed in one at a time
+    Repl {
+        log: PathBuf,
+        /// Seconds each ping in a TagTime log repersents
+        #[arg(long, default_value_t = 2700)]
+        interval: u32,
+    },
+    /// Ping schedule commands
+    Schedule {
+        #[command(subcommand)]
+        command: ScheduleCommand,
+    },
+}
+
+#[derive(Debug, Args)]
+struct LogOptions {
+    /// Seconds each ping in a TagTime log repersents
+    #[arg(long, default_value_t = 2700)]
+    interval: u32,
+    /// Only use pings at or after this Unix timestamp
+    #[arg(long, default_value_t = 0)]
+    start: u64,
+    /// Only use pings before this Unix timestamp
+    #[arg(long, default_value_t = u64::MAX)]
+    end: u64,
+}
+
+#[derive(Debug, Copy, Clone, ValueEnum)]
+enum BucketArg {
+    Day,
+    Week,
+    Month,
+}
+
+#[derive(Debug, Subcommand)]
+enum ScheduleCommand {
+    /// Prints the times of the next pings
+    Next {
+        /// Unix timestamp to start after, instead of now
+        #[arg(long)]
+        after: Option<u64>,
+        /// Number of pings to print
+        #[arg(long, default_value_t = 1)]
+        count: usize,
+        /// Seed of the schedule, instead of the universal schedule
+        #[arg(long)]
+        seed: Option<u32>,
+        /// Average seconds between pings, if `--seed` is given
+        #[arg(long, default_value_t = 2700)]
+        avg_interval: u32,
+        /// Use the original TagTime algorithm, if `--seed` is given
+        #[arg(long)]
+        tagtime: bool,
+    },
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let stdout = io::stdout();
+    if let Err(failure) = run(cli.command, &mut stdout.lock()) {
+        report(&failure);
+        process::exit(1);
+    }
+}
+
+/// Prints why a command failed to stderr.
+fn report(failure: &Failure) {
+    let color = io::stderr().is_terminal();
+    match failure {
+        Failure::Message(message) => {
+            eprint!("{}", Diagnostic::error(message).render("", "", color))
+        }
+        Failure::Diagnostic {
+            diagnostic,
+            name,
+            source,
+        } => eprint!("{}", diagnostic.render(name, source, color)),
+    }
+}
+
+/// Why a command failed: a message, or a diagnostic pointing into some input.
+#[derive(Debug)]
+enum Failure {
+    Message(String),
+    Diagnostic {
+        diagnostic: Box<Diagnostic>,
+        /// Where `source` came from, like a file name.
+        name: String,
+        source: String,
+    },
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Failure::Message(message)
+    }
+}
+
+impl From<&str> for Failure {
+    fn from(message: &str) -> Self {
+        Failure::Message(message.to_string())
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Message(message) => f.write_str(message),
+            Failure::Diagnostic {
+                diagnostic,
+                name,
+                source,
+            } => f.write_str(&diagnostic.render(name, source, false)),
+        }
+    }
+}
+
+fn run(command: Command, out: &mut dyn Write) -> Result<(), Failure> {
+    match command {
+        Command::Parse { expr } => {
+            let parsed = parse(&expr)?;
+            writeln!(out, "{}", parsed).map_err(io_error)?;
+            writeln!(out, "tags: {}", parsed.tags().join(" ")).map_err(io_error)?;
+        }
+        Command::Query { expr, log, options } => {
+            let parsed = parse(&expr)?;
+            let log = open_log(&log, options.interval)?;
+            warn_confusables(&expr, &parsed, &log.tags());
+            write_matching(out, &log.range(options.start, options.end)?, &parsed)?;
+        }
+        Command::Stats {
+            expr,
+            log,
+            options,
+            bucket,
+            utc_offset,
+        } => {
+            let parsed = parse(&expr)?;
+            let log = open_log(&log, options.interval)?;
+            warn_confusables(&expr, &parsed, &log.tags());
+            match bucket {
+                None => {
+                    let range = options.start..options.end;
+                    let estimate = match &log {
+                        LogFile::Pings(log) => stats::estimate(log, &parsed, range),
+                        LogFile::Binary(log) => log
+                            .estimate(&parsed, range)
+                            .map_err(|err| err.to_string())?,
+                    };
+                    write_estimate(out, &estimate)?;
+                }
+                Some(bucket) => {
+                    let tz = utc_offset
+                        .checked_mul(60)
+                        .and_then(FixedOffset::east_opt)
+                        .ok_or("UTC offset must be less than a day")?;
+                    let bucket = match bucket {
+                        BucketArg::Day => stats::Bucket::Day,
+                        BucketArg::Week => stats::Bucket::Week,
+                        BucketArg::Month => stats::Bucket::Month,
+                    };
+                    let log = log.range(options.start, options.end)?;
+                    for point in stats::time_series(&log, &parsed, bucket, &tz).points {
+                        let estimate = point.estimate;
+                        writeln!(
+                            out,
+                            "{}\t{:.2}\t{:.2}\t{:.2}",
+                            point.start, estimate.hours, estimate.low, estimate.high
+                        )
+                        .map_err(io_error)?;
+                    }
+                }
+            }
+        }
+        Command::Import { log, interval } => {
+            let log = read_log(&log, interval)?;
+            let json = serde_json::to_string_pretty(&log.pings()).map_err(|err| err.to_string())?;
+            writeln!(out, "{}", json).map_err(io_error)?;
+        }
+        Command::Export { log } => {
+            let log = read_log(&log, 0)?;
+            write!(out, "{}", import::write_tagtime_log(&log)).map_err(io_error)?;
+        }
+        Command::Pack {
+            log,
+            out: path,
+            interval,
+        } => {
+            let log = read_log(&log, interval)?;
+            fs::write(&path, binlog::write_binary_log(&log))
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+        }
+        Command::Memory { log, interval } => {
+            let log = read_log(&log, interval)?;
+            let usage = log.memory_usage();
+            for (name, bytes) in [
+                ("times", usage.times),
+                ("intervals", usage.intervals),
+                ("answered", usage.answered),
+                ("comments", usage.comments),
+                ("tags", usage.tags),
+                ("interner", usage.interner),
+                ("total", usage.total()),
+            ] {
+                writeln!(out, "{:<10} {}", name, bytes).map_err(io_error)?;
+            }
+        }
+        Command::Repl { log, interval } => repl(&read_log(&log, interval)?, out)?,
+        Command::Schedule {
+            command:
+                ScheduleCommand::Next {
+                    after,
+                    count,
+                    seed,
+                    avg_interval,
+                    tagtime,
+                },
+        } => {
+            let schedule = match seed {
+                None => tt::UNIV_SCHED,
+                Some(_) if avg_interval == 0 => {
+                    return Err("--avg-interval must be positive".into())
+                }
+                Some(seed) => taglogic::new_ping_interval_data(seed, avg_interval, tagtime),
+            };
+            let mut time = match after {
+                Some(after) => after,
+                None => SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|err| err.to_string())?
+                    .as_secs(),
+            };
+            for _ in 0..count {
+                time = taglogic::next_ping_after(time, &schedule).ok_or("no more pings")?;
+                writeln!(out, "{}", time).map_err(io_error)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parses an expression, pointing out where the error is if it's invalid.
+fn parse(expr: &str) -> Result<Expr, Failure> {
+    Expr::parse(expr).map_err(|err| Failure::Diagnostic {
+        diagnostic: Box::new(Diagnostic::from(&err)),
+        name: "expr".to_string(),
+        source: expr.to_string(),
+    })
+}
+
+/// Writes the pings in `log` that match `expr`, as a TagTime log.
+fn write_matching(out: &mut dyn Write, log: &PingLog, expr: &Expr) -> Result<(), Failure> {
+    let matching: Vec<Ping> = log
+        .pings()
+        .iter()
+        .filter(|ping| ping.matches(expr))
+        .map(|ping| ping.to_ping())
+        .collect();
+    let text = import::write_tagtime_log(&PingLog::from_pings(matching));
+    write!(out, "{}", text).map_err(io_error)?;
+    Ok(())
+}
+
+fn write_estimate(out: &mut dyn Write, estimate: &stats::TimeEstimate) -> Result<(), Failure> {
+    writeln!(
+        out,
+        "{:.2} hours (95% interval {:.2} to {:.2}) from {} pings",
+        estimate.hours, estimate.low, estimate.high, estimate.pings
+    )
+    .map_err(io_error)?;
+    Ok(())
+}
+
+/// Warns about tags in the expression that look like one of `tags` (or another tag in it), but
+/// aren't the same, since they'd silently match nothing.
+fn warn_confusables(text: &str, expr: &Expr, tags: &[&str]) {
+    let color = io::stderr().is_terminal();
+    for confusable in expr.confusables(tags) {
+        let warning = Diagnostic::from_confusable(&confusable, text);
+        eprint!("{}", warning.render("expr", text, color));
+    }
+}
+
+/// A log file, which is only read as it's used if it's binary.
+enum LogFile {
+    Pings(Box<PingLog>),
+    Binary(MappedLog),
+}
+
+impl LogFile {
+    fn tags(&self) -> Vec<&str> {
+        match self {
+            LogFile::Pings(log) => log.interner().names().collect(),
+            LogFile::Binary(log) => log.tags().iter().map(String::as_str).collect(),
+        }
+    }
+
+    /// The pings from `start` up to (not including) `end`.
+    fn range(&self, start: u64, end: u64) -> Result<PingLog, String> {
+        match self {
+            LogFile::Pings(log) => Ok(PingLog::from_pings(log.range(start, end).to_vec())),
+            LogFile::Binary(log) => log
+                .to_log(log.range(start, end))
+                .map_err(|err| err.to_string()),
+        }
+    }
+}
+
+fn open_log(path: &Path, interval: u32) -> Result<LogFile, Failure> {
+    let with_path = |err: &dyn std::fmt::Display| format!("{}: {}", path.display(), err);
+    if path.extension() == Some(OsStr::new("ttwlog")) {
+        return Ok(LogFile::Binary(
+            MappedLog::open(path).map_err(|err| with_path(&err))?,
+        ));
+    }
+    let text = fs::read_to_string(path).map_err(|err| with_path(&err))?;
+    let log = if path.extension() == Some(OsStr::new("json")) {
+        let pings: Vec<Ping> = serde_json::from_str(&text).map_err(|err| with_path(&err))?;
+        PingLog::from_pings(pings)
+    } else {
+        import::tagtime_log(&text, interval).map_err(|err| Failure::Diagnostic {
+            diagnostic: Box::new(Diagnostic::from_import_error(&err, &text)),
+            name: path.display().to_string(),
+            source: text.clone(),
+        })?
+    };
+    Ok(LogFile::Pings(Box::new(log)))
+}
+
+/// Reads every ping in a log.
+fn read_log(path: &Path, interval: u32) -> Result<PingLog, Failure> {
+    match open_log(path, interval)? {
+        LogFile::Pings(log) => Ok(*log),
+        LogFile::Binary(log) => Ok(log.to_log(0..log.len()).map_err(|err| err.to_string())?),
+    }
+}
+
+fn io_error(err: io::Error) -> String {
+    err.to_string()
+}
+
+const HELP: &str = "\
+query EXPR        print the pings matching EXPR
+stats EXPR        print the estimated time spent on pings matching EXPR
+plan EXPR         print how EXPR is matched, with estimates from the log's tags
+ping TIME [EXPR]  print the first ping at or after a Unix timestamp, and why EXPR does or
+                  doesn't match it
+tags              print every tag, most common first, with the percentage of pings with it
+help              print this
+quit              stop (so does Ctrl-D)
+";
+
+const COMMANDS: [&str; 8] = [
+    "query", "stats", "plan", "ping", "tags", "help", "quit", "exit",
+];
+
+/// Runs commands on `log` as they're typed, until it's told to stop. History is kept in
+/// `~/.ttw_history`.
+fn repl(log: &PingLog, out: &mut dyn Write) -> Result<(), Failure> {
+    let mut editor: Editor<ReplHelper, FileHistory> =
+        Editor::new().map_err(|err| err.to_string())?;
+    editor.set_helper(Some(ReplHelper {
+        tags: tags_by_frequency(log)
+            .into_iter()
+            .map(|(tag, _)| tag)
+            .collect(),
+    }));
+    let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".ttw_history"));
+    if let Some(path) = &history {
+        // there's none the first time
+        let _ = editor.load_history(path);
+    }
+    loop {
+        match editor.readline("ttw> ") {
+            Ok(line) => {
+                let _ = editor.add_history_entry(line.as_str());
+                match run_line(&line, log, out) {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(failure) => report(&failure),
+                }
+            }
+            // Ctrl-C just clears the line
+            Err(ReadlineError::Interrupted) => {}
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.to_string().into()),
+        }
+    }
+    if let Some(path) = &history {
+        editor
+            .save_history(path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+    }
+    Ok(())
+}
+
+/// Runs a line typed into the REPL, returning whether to keep going.
+fn run_line(line: &str, log: &PingLog, out: &mut dyn Write) -> Result<bool, Failure> {
+    let (command, rest) = split_word(line);
+    let rest = rest.trim();
+    let parse_expr = |expr: &str| -> Result<Expr, Failure> {
+        let parsed = parse(expr)?;
+        warn_confusables(expr, &parsed, &log.interner().names().collect::<Vec<_>>());
+        Ok(parsed)
+    };
+    match command {
+        "" => {}
+        "query" => {
+            write_matching(out, log, &parse_expr(rest)?)?;
+        }
+        "stats" => {
+            let parsed = parse_expr(rest)?;
+            write_estimate(out, &stats::estimate(log, &parsed, 0..u64::MAX))?;
+        }
+        "plan" => {
+            let compiled = parse_expr(rest)?.reorder(&log.tag_frequency()).compile();
+            let plan = compiled.explain_plan_with_frequency(&log.tag_frequency());
+            write!(out, "{}", plan).map_err(io_error)?;
+        }
+        "ping" => {
+            let (time, expr) = split_word(rest);
+            let time: u64 = time
+                .parse()
+                .map_err(|_| "expected a Unix timestamp, like `ping 1533754341`")?;
+            let ping = log
+                .range(time, u64::MAX)
+                .iter()
+                .next()
+                .map(|ping| ping.to_ping())
+                .ok_or("no pings at or after then")?;
+            let text = import::write_tagtime_log(&PingLog::from_pings(vec![ping.clone()]));
+            write!(out, "{}", text).map_err(io_error)?;
+            let expr = expr.trim();
+            if !expr.is_empty() {
+                let parsed = parse_expr(expr)?;
+                let tags: Vec<&str> = ping.tags.iter().map(String::as_str).collect();
+                writeln!(out, "{}", parsed.explain(&tags)).map_err(io_error)?;
+            }
+        }
+        "tags" => {
+            for (tag, frequency) in tags_by_frequency(log) {
+                writeln!(out, "{:>5.1}% {}", frequency * 100.0, tag).map_err(io_error)?;
+            }
+        }
+        "help" => write!(out, "{}", HELP).map_err(io_error)?,
+        "quit" | "exit" => return Ok(false),
+        _ => {
+            return Err(format!("unknown command `{}`, try `help`", command).into());
+        }
+    }
+    Ok(true)
+}
+
+/// Splits off the first word of `text`, returning it and everything after it.
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    text.split_at(end)
+}
+
+/// Every tag in the log (and `@author`) with the fraction of pings that have it, most common
+/// first.
+fn tags_by_frequency(log: &PingLog) -> Vec<(String, f64)> {
+    let stats = log.stats();
+    let mut tags: Vec<(String, f64)> = (stats.tags().map(String::from))
+        .chain(stats.authors().map(|author| format!("@{}", author)))
+        .map(|tag| {
+            let frequency = stats.frequency(&tag);
+            (tag, frequency)
+        })
+        .collect();
+    tags.sort_by(|(tag, frequency), (other, other_frequency)| {
+        other_frequency.total_cmp(frequency).then(tag.cmp(other))
+    });
+    // an author can also be a tag
+    tags.dedup();
+    tags
+}
+
+/// Completes commands, and tags in expressions.
+struct ReplHelper {
+    /// Every tag in the log, most common first.
+    tags: Vec<String>,
+}
+
+impl ReplHelper {
+    /// Where the word being typed at byte `pos` of `line` starts, and what it could be.
+    fn complete_line(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let before = &line[..pos];
+        let (command, rest) = split_word(before);
+        if rest.is_empty() {
+            let start = pos - command.len();
+            let commands = COMMANDS.iter().filter(|name| name.starts_with(command));
+            return (start, commands.map(|name| name.to_string()).collect());
+        }
+        let mut expr_start = pos - rest.len();
+        if command == "ping" {
+            // skip the time
+            let (time, after) = split_word(rest);
+            if after.is_empty() || time.is_empty() {
+                return (pos, Vec::new());
+            }
+            expr_start = pos - after.len();
+        }
+        if !matches!(command, "query" | "stats" | "plan" | "ping") {
+            return (pos, Vec::new());
+        }
+        let completion = bool::complete(&line[expr_start..], pos - expr_start, &self.tags);
+        let tags = completion.tags.into_iter().map(String::from).collect();
+        (expr_start + completion.span.start, tags)
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.complete_line(line, pos))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run_args(args: &[&str]) -> Result<String, String> {
+        let cli = Cli::try_parse_from(std::iter::once("ttw-cli").chain(args.iter().copied()))
+            .map_err(|err| err.to_string())?;
+        let mut out = Vec::new();
+        run(cli.command, &mut out).map_err(|failure| failure.to_string())?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn temp_file(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("ttw-cli-{}-{}", process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn parses() {
+        assert_eq!(run_args(&["parse", "b&(a)"]).unwrap(), "b & a\ntags: a b\n");
+        assert_eq!(
+            run_args(&["parse", "a & )"]).unwrap_err(),
+            "error: unexpected closing bracket\n --> expr:1:5\n  |\n1 | a & )\n  |     ^\n"
+        );
+    }
+
+    #[test]
+    fn queries_and_stats() {
+        let log = temp_file("query.log", "0 a\n3600 b\n86400 a b (hi)\n");
+        assert_eq!(
+            run_args(&["query", "a", &log]).unwrap(),
+            "0 a\n86400 a b (hi)\n"
+        );
+        assert_eq!(
+            run_args(&["query", "a", &log, "--start", "1"]).unwrap(),
+            "86400 a b (hi)\n"
+        );
+        assert!(run_args(&["stats", "a", &log, "--interval", "3600"])
+            .unwrap()
+            .starts_with("2.00 hours"));
+        assert_eq!(
+            run_args(&["stats", "a", &log, "--interval", "3600", "--bucket", "day"])
+                .unwrap()
+                .lines()
+                .map(|line| line.split('\t').take(2).collect::<Vec<_>>().join(" "))
+                .collect::<Vec<_>>(),
+            vec!["1970-01-01 1.00", "1970-01-02 1.00"]
+        );
+    }
+
+    #[test]
+    fn imports_and_exports() {
+        let log = temp_file("import.log", "10 a (hi)\n");
+        let json = run_args(&["import", &log]).unwrap();
+        let pings: Vec<Ping> = serde_json::from_str(&json).unwrap();
+        assert_eq!(pings[0].interval, 2700);
+        let json = temp_file("import.json", &json);
+        assert_eq!(run_args(&["export", &json]).unwrap(), "10 a (hi)\n");
+        assert!(run_args(&["import", "/nonexistent/file.log"]).is_err());
+    }
+
+    #[test]
+    fn packs() {
+        let log = temp_file("pack.log", "0 a\n3600 b\n86400 a b (hi)\n");
+        let packed = temp_file("pack.ttwlog", "");
+        assert_eq!(run_args(&["pack", &log, &packed]).unwrap(), "");
+        assert_eq!(
+            run_args(&["query", "a", &packed, "--start", "1"]).unwrap(),
+            "86400 a b (hi)\n"
+        );
+        assert_eq!(
+            run_args(&["stats", "a", &packed]).unwrap(),
+            run_args(&["stats", "a", &log]).unwrap()
+        );
+        assert_eq!(
+            run_args(&["stats", "a", &packed, "--bucket", "day"]).unwrap(),
+            run_args(&["stats", "a", &log, "--bucket", "day"]).unwrap()
+        );
+        assert_eq!(
+            run_args(&["export", &packed]).unwrap(),
+            run_args(&["export", &log]).unwrap()
+        );
+        assert!(run_args(&["stats", "a", &temp_file("bad.ttwlog", "nope")])
+            .unwrap_err()
+            .contains("not a binary log"));
+    }
+
+    #[test]
+    fn memory() {
+        let log = temp_file("memory.log", "0 a\n3600 b\n86400 a b (hi)\n");
+        let out = run_args(&["memory", &log]).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert!(lines[0].starts_with("times      "), "{}", out);
+        let total: usize = lines[6].split_whitespace().nth(1).unwrap().parse().unwrap();
+        let parts: usize = lines[..6]
+            .iter()
+            .map(|line| {
+                line.split_whitespace()
+                    .nth(1)
+                    .unwrap()
+                    .parse::<usize>()
+                    .unwrap()
+            })
+            .sum();
+        assert_eq!(total, parts);
+    }
+
+    fn run_lines(log: &PingLog, lines: &[&str]) -> Result<String, String> {
+        let mut out = Vec::new();
+        for line in lines {
+            run_line(line, log, &mut out).map_err(|failure| failure.to_string())?;
+        }
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn repl_commands() {
+        let log = import::tagtime_log("0 a\n3600 b\n86400 a b (hi)\n", 3600).unwrap();
+        assert_eq!(
+            run_lines(&log, &["query a"]).unwrap(),
+            "0 a\n86400 a b (hi)\n"
+        );
+        assert!(run_lines(&log, &["  stats a | b "])
+            .unwrap()
+            .starts_with("3.00 hours"));
+        assert_eq!(
+            run_lines(&log, &["ping 1 a & !b"]).unwrap(),
+            "3600 b\ndidn't match because `a` absent\n"
+        );
+        assert_eq!(
+            run_lines(&log, &["ping 86400"]).unwrap(),
+            "86400 a b (hi)\n"
+        );
+        assert_eq!(
+            run_lines(&log, &["tags", ""]).unwrap(),
+            " 66.7% a\n 66.7% b\n"
+        );
+        assert!(run_lines(&log, &["plan b | a"])
+            .unwrap()
+            .contains("match 88.9% of pings, checking 1.33 tags each\n"));
+        assert!(run_lines(&log, &["help"]).unwrap().contains("ping TIME"));
+        assert!(!run_line("quit", &log, &mut Vec::new()).unwrap());
+
+        assert_eq!(
+            run_lines(&log, &["nope"]).unwrap_err(),
+            "unknown command `nope`, try `help`"
+        );
+        assert!(run_lines(&log, &["query a &"])
+            .unwrap_err()
+            .starts_with("error: unexpected end"));
+        assert!(run_lines(&log, &["ping soon"]).is_err());
+        assert!(run_lines(&log, &["ping 90000"]).is_err());
+    }
+
+    #[test]
+    fn repl_completes() {
+        let helper = ReplHelper {
+            tags: vec!["work".into(), "meeting".into(), "meal".into()],
+        };
+        let complete = |line: &str| {
+            let (start, candidates) = helper.complete_line(line, line.len());
+            (start, candidates.join(" "))
+        };
+        assert_eq!(complete(""), (0, COMMANDS.join(" ")));
+        assert_eq!(complete(" q"), (1, "query quit".to_string()));
+        assert_eq!(
+            complete("query work & me"),
+            (13, "meeting meal".to_string())
+        );
+        assert_eq!(complete("stats "), (6, "work meeting meal".to_string()));
+        assert_eq!(complete("ping 1533754341 (w"), (17, "work".to_string()));
+        // not while typing the time, or for commands without expressions
+        assert_eq!(complete("ping 15"), (7, String::new()));
+        assert_eq!(complete("tags w"), (6, String::new()));
+        // only what's before the cursor counts
+        assert_eq!(
+            helper.complete_line("query mework", 8),
+            (6, vec!["meeting".to_string(), "meal".to_string()])
+        );
+    }
+
+    #[test]
+    fn schedule_next() {
+        assert_eq!(
+            run_args(&["schedule", "next", "--after", "1533754341", "--count", "2"]).unwrap(),
+            "1533758980\n1533759940\n"
+        );
+        assert_eq!(
+            run_args(&["schedule", "next", "--after", "0", "--seed", "543431"]).unwrap(),
+            format!(
+                "{}\n",
+                taglogic::next_ping_after(
+                    0,
+                    &taglogic::new_ping_interval_data(543431, 2700, false)
+                )
+                .unwrap()
+            )
+        );
+    }
+}
@@ -0,0 +1,583 @@
+//! A binary log format that can be read without decoding the whole log first, for native tools
+//! working on logs too big to load quickly.
+//!
+//! A log is written with [`write_binary_log`] and read with [`BinaryLog`], which only decodes a
+//! ping when it's asked for. Pings are fixed-size records, so finding one (or the pings in a time
+//! range) doesn't need to look at the others, and tags are stored as ids so matching expressions
+//! never reads tag names. With the `mmap` feature, [`BinaryLog::open`] memory-maps a file, so only
+//! the parts of it that are used are read from disk.
+//!
+//! All numbers are little-endian. The file is laid out as:
+//!
+//! - a 32 byte header: `b"TTWL"`, the [version](BINARY_LOG_VERSION) as a `u32`, the number of
+//!   pings as a `u64`, the number of tag ids as a `u64`, the number of tags as a `u32`, and the
+//!   length of the tag names as a `u32`
+//! - a 48 byte record per ping, sorted by time: the time, the answer time (or `u64::MAX`), the
+//!   index of its first tag id and the offset of its comment (or `u64::MAX`) as `u64`s, then the
+//!   interval, the number of tag ids and the length of the comment as `u32`s, and the id of its
+//!   [author](crate::log::Ping::author)'s `@author` tag as a `u32` (or `u32::MAX`), which was
+//!   padding before version 3
+//! - every ping's tag ids as `u32`s
+//! - the [weight](crate::log::Ping::weights) of each of those tags as a `u16`, in thousandths, or
+//!   0 if it doesn't have one (since version 2)
+//! - the tag names, separated by newlines
+//! - the comments
+
+use std::convert::TryFrom;
+use std::ops::Range;
+use std::str::Utf8Error;
+
+#[cfg(feature = "expr")]
+use crate::bool::{CommentMatcher, Expr};
+use crate::log::{Ping, PingLog, Weight};
+#[cfg(feature = "stats")]
+use crate::stats::{Tally, TimeEstimate};
+
+/// Version of the format written by [`write_binary_log`]. Bump this when changing the layout.
+/// Versions 1, from before weights, and 2, from before authors, can still be read.
+pub const BINARY_LOG_VERSION: u32 = 3;
+
+const MAGIC: &[u8; 4] = b"TTWL";
+const HEADER_LEN: usize = 32;
+const RECORD_LEN: usize = 48;
+const NONE: u64 = u64::MAX;
+const NO_AUTHOR: u32 = u32::MAX;
+
+/// An error reading a binary log.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum BinaryLogError {
+    #[error("not a binary log")]
+    NotBinaryLog,
+    #[error("unsupported binary log version")]
+    UnsupportedVersion,
+    #[error("binary log is truncated")]
+    Truncated,
+    #[error("tag names aren't UTF-8")]
+    TagNamesNotUtf8(#[source] Utf8Error),
+    #[error("wrong number of tag names")]
+    WrongTagCount,
+    #[error("ping index out of bounds")]
+    PingOutOfBounds,
+    #[error("tag id out of bounds")]
+    TagIdOutOfBounds,
+    #[error("tag ids out of bounds")]
+    TagIdsOutOfBounds,
+    #[error("comment out of bounds")]
+    CommentOutOfBounds,
+    #[error("comment isn't UTF-8")]
+    CommentNotUtf8(#[source] Utf8Error),
+}
+
+/// Writes a log in the binary format read by [`BinaryLog`].
+pub fn write_binary_log(log: &PingLog) -> Vec<u8> {
+    let pings = log.pings();
+    let names = log.interner().names().collect::<Vec<_>>().join("\n");
+    let tag_ids: usize = pings.iter().map(|ping| ping.tags.len()).sum();
+
+    let mut out = Vec::with_capacity(HEADER_LEN + pings.len() * RECORD_LEN + tag_ids * 6);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&BINARY_LOG_VERSION.to_le_bytes());
+    out.extend_from_slice(&(pings.len() as u64).to_le_bytes());
+    out.extend_from_slice(&(tag_ids as u64).to_le_bytes());
+    out.extend_from_slice(&(log.interner().len() as u32).to_le_bytes());
+    out.extend_from_slice(&(names.len() as u32).to_le_bytes());
+
+    let mut tags_start = 0;
+    let mut comments_len = 0;
+    for (index, ping) in pings.iter().enumerate() {
+        let comment = ping.comment.unwrap_or("");
+        out.extend_from_slice(&ping.time.to_le_bytes());
+        out.extend_from_slice(&ping.answered.unwrap_or(NONE).to_le_bytes());
+        out.extend_from_slice(&(tags_start as u64).to_le_bytes());
+        let comment_start = ping.comment.map_or(NONE, |_| comments_len as u64);
+        out.extend_from_slice(&comment_start.to_le_bytes());
+        out.extend_from_slice(&ping.interval.to_le_bytes());
+        out.extend_from_slice(&(ping.tags.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        let author = log.author_id(index).unwrap_or(NO_AUTHOR);
+        out.extend_from_slice(&author.to_le_bytes());
+        tags_start += ping.tags.len();
+        comments_len += comment.len();
+    }
+    for ping in &pings {
+        for id in ping.tags.ids() {
+            out.extend_from_slice(&id.to_le_bytes());
+        }
+    }
+    for ping in &pings {
+        for tag in ping.tags.iter() {
+            let weight = ping.weights.get(tag).map_or(0.0, Weight::get);
+            out.extend_from_slice(&((weight * 1000.0).round() as u16).to_le_bytes());
+        }
+    }
+    out.extend_from_slice(names.as_bytes());
+    for ping in &pings {
+        out.extend_from_slice(ping.comment.unwrap_or("").as_bytes());
+    }
+    out
+}
+
+/// A log in the binary format, read from `bytes` (like a `Vec<u8>`, or a memory-mapped file from
+/// [`BinaryLog::open`]) as pings are needed. Only the header and tag names are read up front.
+#[derive(Debug)]
+pub struct BinaryLog<B> {
+    bytes: B,
+    len: usize,
+    tag_ids: Range<usize>,
+    /// The weights of the tag ids, or None in a version 1 log.
+    weights: Option<Range<usize>>,
+    /// Whether records have authors, which they don't before version 3.
+    authors: bool,
+    comments: Range<usize>,
+    tags: Vec<String>,
+}
+
+impl<B: AsRef<[u8]>> BinaryLog<B> {
+    /// Reads the header and tag names of a log written by [`write_binary_log`].
+    pub fn new(bytes: B) -> Result<Self, BinaryLogError> {
+        let data = bytes.as_ref();
+        if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+            return Err(BinaryLogError::NotBinaryLog);
+        }
+        let version = u32_at(data, 4);
+        if !(1..=BINARY_LOG_VERSION).contains(&version) {
+            return Err(BinaryLogError::UnsupportedVersion);
+        }
+        let size = |count: u64, each: usize| {
+            usize::try_from(count)
+                .ok()
+                .and_then(|count| count.checked_mul(each))
+        };
+        let len = u64_at(data, 8);
+        let records = size(len, RECORD_LEN);
+        let tag_ids = size(u64_at(data, 16), 4);
+        let weights_len = match version {
+            1 => Some(0),
+            _ => size(u64_at(data, 16), 2),
+        };
+        let tag_count = u32_at(data, 24) as usize;
+        let names = u32_at(data, 28) as usize;
+        let tag_ids_start = records.and_then(|records| records.checked_add(HEADER_LEN));
+        let tag_ids = match (tag_ids_start, tag_ids) {
+            (Some(start), Some(len)) if start.saturating_add(len) <= data.len() => {
+                start..(start + len)
+            }
+            _ => return Err(BinaryLogError::Truncated),
+        };
+        let weights = match weights_len.and_then(|len| tag_ids.end.checked_add(len)) {
+            Some(end) if end <= data.len() => tag_ids.end..end,
+            _ => return Err(BinaryLogError::Truncated),
+        };
+        let names = match weights.end.checked_add(names) {
+            Some(end) if end <= data.len() => weights.end..end,
+            _ => return Err(BinaryLogError::Truncated),
+        };
+        let text = match std::str::from_utf8(&data[names.clone()]) {
+            Ok(text) => text,
+            Err(err) => return Err(BinaryLogError::TagNamesNotUtf8(err)),
+        };
+        let tags: Vec<String> = match tag_count {
+            0 if text.is_empty() => Vec::new(),
+            _ => text.split('\n').map(String::from).collect(),
+        };
+        if tags.len() != tag_count {
+            return Err(BinaryLogError::WrongTagCount);
+        }
+        Ok(Self {
+            len: len as usize,
+            tag_ids,
+            weights: Some(weights).filter(|_| version > 1),
+            authors: version > 2,
+            comments: names.end..data.len(),
+            tags,
+            bytes,
+        })
+    }
+
+    /// Every tag, indexed by the ids pings are stored with.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Time of the `index`th ping.
+    ///
+    /// ## Panics
+    /// Panics if `index` is out of bounds.
+    pub fn time(&self, index: usize) -> u64 {
+        assert!(index < self.len, "ping index out of bounds");
+        u64_at(self.record(index), 0)
+    }
+
+    /// Indices of the pings from `start` up to (not including) `end`.
+    pub fn range(&self, start: u64, end: u64) -> Range<usize> {
+        let start = self.partition_point(|time| time < start);
+        let end = self.partition_point(|time| time < end).max(start);
+        start..end
+    }
+
+    fn partition_point(&self, pred: impl Fn(u64) -> bool) -> usize {
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if pred(self.time(mid)) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
+    /// Decodes the `index`th ping.
+    pub fn get(&self, index: usize) -> Result<Ping, BinaryLogError> {
+        if index >= self.len {
+            return Err(BinaryLogError::PingOutOfBounds);
+        }
+        let record = self.record(index);
+        let tags: Vec<String> = self
+            .tag_ids(record)?
+            .map(|id| match self.tags.get(id as usize) {
+                Some(tag) => Ok(tag.clone()),
+                None => Err(BinaryLogError::TagIdOutOfBounds),
+            })
+            .collect::<Result<_, _>>()?;
+        let mut ping = Ping::new(u64_at(record, 0), tags, u32_at(record, 32));
+        for (tag, weight) in ping.tags.iter().zip(self.tag_weights(record)?) {
+            if let Some(weight) = weight {
+                ping.weights.insert(tag.clone(), weight);
+            }
+        }
+        if let Some(id) = self.author_id(record) {
+            let tag = (self.tags.get(id as usize)).ok_or(BinaryLogError::TagIdOutOfBounds)?;
+            ping.author = Some(tag.strip_prefix('@').unwrap_or(tag).to_string());
+        }
+        ping.answered = Some(u64_at(record, 8)).filter(|&time| time != NONE);
+        let comment_start = u64_at(record, 24);
+        if comment_start != NONE {
+            let range = usize::try_from(comment_start)
+                .ok()
+                .and_then(|start| Some(start..start.checked_add(u32_at(record, 40) as usize)?))
+                .filter(|range| range.end <= self.comments.len())
+                .ok_or(BinaryLogError::CommentOutOfBounds)?;
+            let bytes = &self.bytes.as_ref()[self.comments.start..][range];
+            let comment = std::str::from_utf8(bytes).map_err(BinaryLogError::CommentNotUtf8)?;
+            ping.comment = Some(comment.to_string());
+        }
+        Ok(ping)
+    }
+
+    /// Decodes the pings at `indices` into a [`PingLog`].
+    pub fn to_log(&self, indices: Range<usize>) -> Result<PingLog, BinaryLogError> {
+        let pings = indices
+            .map(|index| self.get(index))
+            .collect::<Result<_, _>>()?;
+        Ok(PingLog::from_pings(pings))
+    }
+
+    /// Whether each ping at `indices` matches `expr`, without decoding the pings (unless the
+    /// expression [searches comments](Expr::matches_ping)).
+    #[cfg(feature = "expr")]
+    pub fn matches_each(
+        &self,
+        indices: Range<usize>,
+        expr: &Expr,
+    ) -> Result<Vec<bool>, BinaryLogError> {
+        let searcher = CommentMatcher::new(expr);
+        if searcher.searches_comments() {
+            return indices
+                .map(|index| Ok(self.get(index)?.matches(expr)))
+                .collect();
+        }
+        let compiled = expr.compile_with_table(&self.tags);
+        let mut ids = Vec::new();
+        indices
+            .map(|index| {
+                if index >= self.len {
+                    return Err(BinaryLogError::PingOutOfBounds);
+                }
+                ids.clear();
+                let record = self.record(index);
+                ids.extend(self.tag_ids(record)?);
+                ids.extend(self.author_id(record));
+                Ok(compiled.matches_ids(&ids))
+            })
+            .collect()
+    }
+
+    /// Estimated hours spent on `expr` during `range`, like [`stats::estimate`] but without
+    /// decoding the pings.
+    ///
+    /// [`stats::estimate`]: crate::stats::estimate
+    #[cfg(feature = "stats")]
+    pub fn estimate(&self, expr: &Expr, range: Range<u64>) -> Result<TimeEstimate, BinaryLogError> {
+        let indices = self.range(range.start, range.end);
+        let matches = self.matches_each(indices.clone(), expr)?;
+        let mut tally = Tally::default();
+        for (index, matched) in indices.zip(matches) {
+            let record = self.record(index);
+            // pings split by weighted tags are decoded to find how much of them matched
+            let share = match self.tag_weights(record)?.any(|weight| weight.is_some()) {
+                true => self.get(index)?.share(expr),
+                false if matched => 1.0,
+                false => 0.0,
+            };
+            tally.add_share(u32_at(record, 32), share);
+        }
+        Ok(tally.estimate())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn record(&self, index: usize) -> &[u8] {
+        let start = HEADER_LEN + index * RECORD_LEN;
+        &self.bytes.as_ref()[start..(start + RECORD_LEN)]
+    }
+
+    /// The id of the `@author` tag of the ping with `record`, if it has an author.
+    fn author_id(&self, record: &[u8]) -> Option<u32> {
+        Some(u32_at(record, 44)).filter(|&id| self.authors && id != NO_AUTHOR)
+    }
+
+    /// Where the ping with `record` has its tag ids, counting in tag ids.
+    fn tag_range(&self, record: &[u8]) -> Result<Range<usize>, BinaryLogError> {
+        let ids = self.tag_ids.len() / 4;
+        usize::try_from(u64_at(record, 16))
+            .ok()
+            .and_then(|start| Some(start..start.checked_add(u32_at(record, 36) as usize)?))
+            .filter(|range| range.end <= ids)
+            .ok_or(BinaryLogError::TagIdsOutOfBounds)
+    }
+
+    fn tag_ids<'a>(
+        &'a self,
+        record: &[u8],
+    ) -> Result<impl Iterator<Item = u32> + 'a, BinaryLogError> {
+        let range = self.tag_range(record)?;
+        let bytes = &self.bytes.as_ref()[self.tag_ids.clone()][(range.start * 4)..(range.end * 4)];
+        Ok(bytes.chunks_exact(4).map(|id| u32_at(id, 0)))
+    }
+
+    /// The weight of each of the tags of the ping with `record`, in the same order as its tag ids.
+    fn tag_weights<'a>(
+        &'a self,
+        record: &[u8],
+    ) -> Result<impl Iterator<Item = Option<Weight>> + 'a, BinaryLogError> {
+        let range = self.tag_range(record)?;
+        let bytes = match &self.weights {
+            Some(weights) => {
+                &self.bytes.as_ref()[weights.clone()][(range.start * 2)..(range.end * 2)]
+            }
+            None => &[],
+        };
+        Ok((bytes.chunks_exact(2)).map(|weight| {
+            Weight::new(f64::from(u16::from_le_bytes([weight[0], weight[1]])) / 1000.0)
+        }))
+    }
+}
+
+/// A binary log in a memory-mapped file.
+#[cfg(feature = "mmap")]
+pub type MappedLog = BinaryLog<memmap2::Mmap>;
+
+#[cfg(feature = "mmap")]
+impl MappedLog {
+    /// Memory-maps a binary log file. Pings are read from disk as they're used, so even logs far
+    /// bigger than memory open instantly.
+    ///
+    /// The file mustn't be changed while it's open. Malformed logs give an
+    /// [`InvalidData`](std::io::ErrorKind::InvalidData) error.
+    pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // safety: the file is only read, and callers are told not to change it while it's mapped
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Self::new(map).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+}
+
+// Lengths are checked before reading numbers, so these only return 0 past the end of `bytes` if
+// that check is wrong.
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    bytes
+        .get(offset..)
+        .and_then(<[u8]>::first_chunk)
+        .map_or(0, |word| u64::from_le_bytes(*word))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    bytes
+        .get(offset..)
+        .and_then(<[u8]>::first_chunk)
+        .map_or(0, |word| u32::from_le_bytes(*word))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn log() -> PingLog {
+        let mut pings = vec![
+            Ping::new(10, vec!["a".into(), "b".into()], 2700),
+            Ping::new(20, vec![], 1800),
+            Ping::new(30, vec!["b".into(), "c".into()], 2700),
+        ];
+        pings[0].comment = Some("hi ☃".into());
+        pings[1].answered = Some(25);
+        pings[2].comment = Some(String::new());
+        PingLog::from_pings(pings)
+    }
+
+    #[test]
+    fn round_trips() {
+        let log = log();
+        let binary = BinaryLog::new(write_binary_log(&log)).unwrap();
+        assert_eq!(binary.len(), 3);
+        assert_eq!(binary.tags(), &["a", "b", "c"]);
+        assert_eq!(binary.time(2), 30);
+        assert_eq!(binary.get(1).unwrap(), log.get(1).unwrap().to_ping());
+        assert_eq!(binary.to_log(0..3).unwrap(), log);
+        assert_eq!(binary.get(3).unwrap_err(), BinaryLogError::PingOutOfBounds);
+
+        let empty = BinaryLog::new(write_binary_log(&PingLog::new())).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.range(0, u64::MAX), 0..0);
+    }
+
+    #[test]
+    fn finds_ranges() {
+        let binary = BinaryLog::new(write_binary_log(&log())).unwrap();
+        assert_eq!(binary.range(0, u64::MAX), 0..3);
+        assert_eq!(binary.range(11, 30), 1..2);
+        assert_eq!(binary.range(20, 21), 1..2);
+        assert_eq!(binary.range(30, 10), 2..2);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn matches_and_estimates() {
+        let log = log();
+        let binary = BinaryLog::new(write_binary_log(&log)).unwrap();
+        let expr = Expr::parse("b & !c").unwrap();
+        assert_eq!(
+            binary.matches_each(0..3, &expr).unwrap(),
+            log.matches_many(&expr)
+        );
+        assert_eq!(
+            binary.estimate(&expr, 0..30).unwrap(),
+            crate::stats::estimate(&log, &expr, 0..30)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_logs() {
+        let bytes = write_binary_log(&log());
+        let error = |bytes: &[u8]| BinaryLog::new(bytes).unwrap_err();
+        assert_eq!(error(b"TTWL"), BinaryLogError::NotBinaryLog);
+        assert_eq!(error(&bytes[..40]), BinaryLogError::Truncated);
+        let mut newer = bytes.clone();
+        newer[4] += 1;
+        assert_eq!(error(&newer), BinaryLogError::UnsupportedVersion);
+
+        // a tag id past the end of the tags
+        let mut bad_tag = bytes.clone();
+        bad_tag[HEADER_LEN + 3 * RECORD_LEN] = 9;
+        let binary = BinaryLog::new(bad_tag).unwrap();
+        assert_eq!(binary.get(0).unwrap_err(), BinaryLogError::TagIdOutOfBounds);
+        assert!(binary.get(1).is_ok());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn opens_files() {
+        let path = std::env::temp_dir().join(format!("taglogic-{}.ttwlog", std::process::id()));
+        std::fs::write(&path, write_binary_log(&log())).unwrap();
+        let binary = BinaryLog::open(&path).unwrap();
+        assert_eq!(binary.to_log(0..3).unwrap(), log());
+        drop(binary);
+
+        std::fs::write(&path, b"nope").unwrap();
+        let err = BinaryLog::open(&path).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn weighted_tags_agree_with_estimate() {
+        let log = PingLog::from_pings(vec![
+            Ping::from_entry(10, "work:0.75 email:0.25", 3600),
+            Ping::from_entry(20, "work:0.5 home", 3600),
+            Ping::from_entry(30, "work", 3600),
+        ]);
+        let binary = BinaryLog::new(write_binary_log(&log)).unwrap();
+        assert_eq!(binary.to_log(0..3).unwrap(), log);
+        for expr in &["work", "email", "home", "!work"] {
+            let expr = Expr::parse(expr).unwrap();
+            assert_eq!(
+                binary.estimate(&expr, 0..100).unwrap(),
+                crate::stats::estimate(&log, &expr, 0..100)
+            );
+        }
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn keeps_authors() {
+        let ping = |time, tags: &str, author: Option<&str>| {
+            let mut ping = Ping::from_entry(time, tags, 3600);
+            ping.author = author.map(String::from);
+            ping.comment = Some(format!("at {}", time));
+            ping
+        };
+        let log = PingLog::from_pings(vec![
+            ping(10, "work", Some("alice")),
+            ping(20, "work", Some("bob")),
+            ping(30, "work:0.5 @home", Some("alice")),
+            ping(40, "work", None),
+        ]);
+        let binary = BinaryLog::new(write_binary_log(&log)).unwrap();
+        assert_eq!(binary.to_log(0..4).unwrap(), log);
+        for expr in &[
+            "@alice & work",
+            "@bob | @home",
+            r#"@alice & comment:"at 3""#,
+        ] {
+            let expr = Expr::parse(expr).unwrap();
+            assert_eq!(
+                binary.matches_each(0..4, &expr).unwrap(),
+                log.matches_many(&expr)
+            );
+            assert_eq!(
+                binary.estimate(&expr, 0..100).unwrap(),
+                crate::stats::estimate(&log, &expr, 0..100)
+            );
+        }
+        let alice = Expr::parse("@alice & work").unwrap();
+        assert_eq!(binary.estimate(&alice, 0..100).unwrap().hours, 1.5);
+    }
+
+    #[test]
+    fn reads_old_versions() {
+        // version 2 had padding instead of authors, and version 1 didn't have weights either
+        let mut bytes = write_binary_log(&log());
+        for index in 0..3 {
+            let padding = HEADER_LEN + index * RECORD_LEN + 44;
+            bytes[padding..(padding + 4)].copy_from_slice(&[0; 4]);
+        }
+        bytes[4] = 2;
+        let binary = BinaryLog::new(bytes.clone()).unwrap();
+        assert_eq!(binary.to_log(0..3).unwrap(), log());
+        let tag_ids = HEADER_LEN + 3 * RECORD_LEN + 4 * 4;
+        bytes.drain(tag_ids..(tag_ids + 4 * 2));
+        bytes[4] = 1;
+        let binary = BinaryLog::new(bytes).unwrap();
+        assert_eq!(binary.to_log(0..3).unwrap(), log());
+    }
+}
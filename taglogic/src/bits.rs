@@ -0,0 +1,126 @@
+//! Bitwise operations on whole bitsets, for matching many pings at once.
+//!
+//! `std::simd` is still nightly-only, so on WASM with `simd128` enabled these use its 128-bit
+//! instructions directly. Everywhere else they work on fixed chunks of words, which the compiler
+//! turns into SIMD instructions where the target has them (like SSE2 on x86_64) and plain loops
+//! where it doesn't (like WASM without `simd128`).
+
+/// Whether these operations were built with WASM SIMD instructions.
+#[cfg(feature = "wasm")]
+pub(crate) const WASM_SIMD: bool = cfg!(all(target_arch = "wasm32", target_feature = "simd128"));
+
+/// Words handled at once by the portable loops.
+#[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+const CHUNK: usize = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum BitOp {
+    And,
+    Or,
+}
+
+impl BitOp {
+    #[inline(always)]
+    fn apply(self, a: u32, b: u32) -> u32 {
+        match self {
+            BitOp::And => a & b,
+            BitOp::Or => a | b,
+        }
+    }
+}
+
+/// Sets `dst` to `dst & src` or `dst | src`.
+///
+/// ## Panics
+/// Panics if the bitsets have different lengths.
+pub(crate) fn combine(op: BitOp, dst: &mut [u32], src: &[u32]) {
+    assert_eq!(dst.len(), src.len(), "bitsets must be the same length");
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    let (dst, src) = {
+        use core::arch::wasm32::{v128, v128_and, v128_load, v128_or, v128_store};
+        let whole = dst.len() / 4 * 4;
+        for (a, b) in dst[..whole].chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+            // safety: each chunk is 16 bytes, and v128 loads and stores don't need alignment
+            unsafe {
+                let ptr = a.as_mut_ptr() as *mut v128;
+                let (x, y) = (v128_load(ptr), v128_load(b.as_ptr() as *const v128));
+                let result = match op {
+                    BitOp::And => v128_and(x, y),
+                    BitOp::Or => v128_or(x, y),
+                };
+                v128_store(ptr, result);
+            }
+        }
+        (&mut dst[whole..], &src[whole..])
+    };
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    let (dst, src) = {
+        let whole = dst.len() / CHUNK * CHUNK;
+        for (a, b) in dst[..whole]
+            .chunks_exact_mut(CHUNK)
+            .zip(src.chunks_exact(CHUNK))
+        {
+            for (a, b) in a.iter_mut().zip(b) {
+                *a = op.apply(*a, *b);
+            }
+        }
+        (&mut dst[whole..], &src[whole..])
+    };
+    for (a, b) in dst.iter_mut().zip(src) {
+        *a = op.apply(*a, *b);
+    }
+}
+
+/// Flips every bit of `dst`.
+pub(crate) fn invert(dst: &mut [u32]) {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    let dst = {
+        use core::arch::wasm32::{v128, v128_load, v128_not, v128_store};
+        let whole = dst.len() / 4 * 4;
+        for chunk in dst[..whole].chunks_exact_mut(4) {
+            // safety: as in `combine`
+            unsafe {
+                let ptr = chunk.as_mut_ptr() as *mut v128;
+                v128_store(ptr, v128_not(v128_load(ptr)));
+            }
+        }
+        &mut dst[whole..]
+    };
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    let dst = {
+        let whole = dst.len() / CHUNK * CHUNK;
+        for chunk in dst[..whole].chunks_exact_mut(CHUNK) {
+            for word in chunk {
+                *word = !*word;
+            }
+        }
+        &mut dst[whole..]
+    };
+    for word in dst {
+        *word = !*word;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn combines_every_word() {
+        // long enough for whole chunks and a remainder
+        let a: Vec<u32> = (0..21).map(|i| 0x0f0f_0f0f ^ i).collect();
+        let b: Vec<u32> = (0..21).map(|i| 0x00ff_00ff * i).collect();
+        let mut and = a.clone();
+        combine(BitOp::And, &mut and, &b);
+        let mut or = a.clone();
+        combine(BitOp::Or, &mut or, &b);
+        let mut not = a.clone();
+        invert(&mut not);
+        for i in 0..a.len() {
+            assert_eq!(and[i], a[i] & b[i]);
+            assert_eq!(or[i], a[i] | b[i]);
+            assert_eq!(not[i], !a[i]);
+        }
+    }
+}
@@ -1,11 +1,50 @@
-use std::collections::VecDeque;
+use crate::bits::{self, BitOp};
+use crate::limits::Limits;
+use crate::unicode::{self, Confusable};
+use alloc::borrow::Cow;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Write as _};
+use core::ops::Range;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-const MAX_RECURSION: u16 = 20;
-const MAX_LEN: usize = 200;
+mod builder;
+mod comment;
+mod complete;
+mod context;
+mod cost;
+mod dedupe;
+mod explain;
+mod function;
+mod lint;
+mod logic;
+mod plan;
+mod synth;
+mod taxonomy;
+mod template;
 
+pub use builder::{not, tag, BuildError, ExprBuilder};
+#[cfg(feature = "stats")]
+pub(crate) use comment::had_comment_tags;
+#[cfg(feature = "log")]
+pub(crate) use comment::CommentMatcher;
+pub use complete::{complete, Completion};
+pub use context::EvalContext;
+pub use cost::{LogStats, QueryCost};
+pub use dedupe::{dedupe_queries, DuplicateGroup};
+pub use explain::{Explained, Explanation, Highlight, Reason};
+pub use function::{ExprFunction, Functions};
+pub use lint::{lint, lint_with, LintOptions, LintRule, LintWarning};
+pub use synth::{synthesize, SynthError, SynthLimits};
+pub use taxonomy::{Conflict, Taxonomy};
+pub use template::{Template, TemplateError};
+
+#[doc(hidden)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum BinaryOp {
+pub enum BinaryOp {
     And,
     Or,
 }
@@ -24,25 +63,106 @@ impl BinaryOp {
             _ => None,
         }
     }
+    /// The operator a word stands for, ignoring case.
     pub fn from_text(text: &str) -> Option<Self> {
-        match text {
-            "and" => Some(Self::And),
-            "or" => Some(Self::Or),
-            _ => None,
+        if text.eq_ignore_ascii_case("and") {
+            Some(Self::And)
+        } else if text.eq_ignore_ascii_case("or") {
+            Some(Self::Or)
+        } else {
+            None
         }
     }
 }
 
+/// A token, with names borrowed from the expression so lexing doesn't allocate for them.
 #[derive(Debug, Clone, PartialEq, Eq)]
-enum Token {
+enum Token<'a> {
     OpenBracket,
     CloseBracket,
     Invert,
-    Name { text: String },
+    Name { text: &'a str },
     BinaryOp(BinaryOp),
 }
 
-fn lex(s: &str) -> Result<Vec<Token>, &'static str> {
+/// A token, and the byte range of the expression it came from.
+type Spanned<'a> = (Token<'a>, Range<usize>);
+
+/// What went wrong while parsing an expression.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, thiserror::Error)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    /// The expression is longer than [`Limits::expr_len`].
+    #[error("expression too long")]
+    TooLong,
+    /// Brackets or inversions are nested deeper than [`Limits::expr_depth`].
+    #[error("expression too deep")]
+    TooDeep,
+    #[error("unexpected closing bracket")]
+    UnexpectedCloseBracket,
+    #[error("invalid token after inverted name")]
+    InvalidAfterInvertedName,
+    #[error("can't double invert, that would be pointless")]
+    DoubleInvert,
+    #[error("expected expression")]
+    ExpectedExpression,
+    #[error("expected token to invert, got EOF")]
+    NothingToInvert,
+    #[error("expected closing bracket")]
+    ExpectedCloseBracket,
+    #[error("invald token after closing bracket")]
+    InvalidAfterCloseBracket,
+    #[error("unexpected binary operator")]
+    UnexpectedBinaryOp,
+    #[error("name followed by invalid token")]
+    InvalidAfterName,
+    #[error("unexpected end of expression")]
+    UnexpectedEnd,
+    #[error("expected EOF, found extra tokens")]
+    ExtraTokens,
+    /// An accent, joiner, or other mark that's shown as part of the character before it, but
+    /// that character isn't part of a tag, like the accent in `a & \u{301}b`.
+    #[error("combining character isn't part of a tag")]
+    DetachedMark,
+    /// A comment search like `comment:"text` without its closing quote or slash.
+    #[error("comment search isn't closed")]
+    UnterminatedComment,
+    /// A comment search like `comment:/(/` with a regex that isn't valid, or any regex without
+    /// the `regex` feature.
+    #[error("invalid regex in comment search")]
+    InvalidCommentPattern,
+    /// A function call like `weather(rainy` without its closing bracket.
+    #[error("function call isn't closed")]
+    UnterminatedCall,
+}
+
+/// An error from parsing an expression.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{kind} (at {}..{})", span.start, span.end)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// Byte range of the part of the expression that caused the error. This is empty and at the
+    /// end of the expression if it ended too early.
+    pub span: Range<usize>,
+}
+
+impl ParseError {
+    /// The span as UTF-16 code unit and character offsets as well as byte offsets, for hosts that
+    /// index strings by those.
+    pub fn offsets(&self, expr: &str) -> unicode::Offsets {
+        unicode::offsets(expr, self.span.clone())
+    }
+
+    /// An error at `token`, or at the end of the expression (`end`) if there's no token.
+    fn at(kind: ParseErrorKind, token: Option<&Spanned<'_>>, end: usize) -> Self {
+        Self {
+            kind,
+            span: token.map_or(end..end, |(_, span)| span.clone()),
+        }
+    }
+}
+
+fn lex(s: &str, max_len: usize) -> Result<Vec<Spanned<'_>>, ParseError> {
     #[derive(Debug, Copy, Clone, Eq, PartialEq)]
     enum ParseState {
         AnyExpected,
@@ -51,18 +171,61 @@ fn lex(s: &str) -> Result<Vec<Token>, &'static str> {
         InSymbolBinOp(BinaryOp),
     }
 
-    if s.len() >= MAX_LEN {
-        return Err("expression too long");
+    fn name_token(name: &str) -> Token<'_> {
+        match BinaryOp::from_text(name) {
+            Some(op) => Token::BinaryOp(op),
+            None => Token::Name { text: name },
+        }
+    }
+
+    if s.len() >= max_len {
+        return Err(ParseError {
+            kind: ParseErrorKind::TooLong,
+            span: 0..s.len(),
+        });
     }
 
     let mut state = ParseState::AnyExpected;
-    let mut tokens = Vec::new();
-    let mut cur_name = String::new();
-    for c in s.chars() {
+    let mut tokens: Vec<Spanned> = Vec::new();
+    let mut name_start = 0;
+    // the end of the comment search being skipped over
+    let mut skip_to = 0;
+    for (i, c) in s.char_indices() {
+        if i < skip_to {
+            continue;
+        }
+        let span = i..(i + c.len_utf8());
+        // a name can't end in the middle of something shown as one character, and nothing else
+        // can have marks on it
+        if unicode::extends_grapheme(c) && state != ParseState::InName {
+            return Err(ParseError {
+                kind: ParseErrorKind::DetachedMark,
+                span,
+            });
+        }
         if let ParseState::InSymbolBinOp(op) = state {
             state = ParseState::AnyExpected;
             if c == op.as_char() {
                 // continuning the last bin op (| and || are treated the same)
+                if let Some((_, last)) = tokens.last_mut() {
+                    last.end = span.end;
+                }
+                continue;
+            }
+        }
+
+        if state == ParseState::InName && c == '(' {
+            // a name right before a bracket can be a function call
+            if let Some(call) = function::lex_call(s, name_start, i) {
+                let call = call?;
+                skip_to = call.end;
+                tokens.push((
+                    Token::Name {
+                        text: &s[call.clone()],
+                    },
+                    call,
+                ));
+                state = ParseState::AnyExpected;
                 continue;
             }
         }
@@ -70,165 +233,450 @@ fn lex(s: &str) -> Result<Vec<Token>, &'static str> {
         if state == ParseState::InName {
             let end_cur_token = match c {
                 '(' | ')' | '!' => true,
-                _ if BinaryOp::from_char(c) != None => true,
+                _ if BinaryOp::from_char(c).is_some() => true,
                 _ if c.is_whitespace() => true,
                 _ => false,
             };
             if end_cur_token {
-                let lower = cur_name.to_ascii_lowercase();
-                if let Some(op) = BinaryOp::from_text(&lower) {
-                    tokens.push(Token::BinaryOp(op));
-                } else {
-                    tokens.push(Token::Name { text: cur_name });
-                }
-                cur_name = String::new();
+                tokens.push((name_token(&s[name_start..i]), name_start..i));
                 state = ParseState::AnyExpected;
-            } else {
-                cur_name.push(c);
             }
         }
 
         if state == ParseState::AnyExpected {
-            let op = BinaryOp::from_char(c);
-            match c {
-                _ if op != None => {
-                    tokens.push(Token::BinaryOp(op.unwrap()));
-                    state = ParseState::InSymbolBinOp(op.unwrap());
+            match (c, BinaryOp::from_char(c)) {
+                (_, Some(op)) => {
+                    tokens.push((Token::BinaryOp(op), span));
+                    state = ParseState::InSymbolBinOp(op);
                 }
-                '(' => tokens.push(Token::OpenBracket),
-                ')' => tokens.push(Token::CloseBracket),
-                '!' => tokens.push(Token::Invert),
+                ('(', _) => tokens.push((Token::OpenBracket, span)),
+                (')', _) => tokens.push((Token::CloseBracket, span)),
+                ('!', _) => tokens.push((Token::Invert, span)),
                 // ignore whitespace
                 _ if c.is_whitespace() => {}
-                _ => {
-                    state = ParseState::InName;
-                    cur_name = String::with_capacity(1);
-                    cur_name.push(c);
-                }
+                _ => match comment::lex_term(s, i) {
+                    Some(term) => {
+                        let term = term?;
+                        skip_to = term.end;
+                        tokens.push((
+                            Token::Name {
+                                text: &s[term.clone()],
+                            },
+                            term,
+                        ));
+                    }
+                    None => {
+                        state = ParseState::InName;
+                        name_start = i;
+                    }
+                },
             }
         }
     }
-    if !cur_name.is_empty() {
-        let lower = cur_name.to_ascii_lowercase();
-        if let Some(op) = BinaryOp::from_text(&lower) {
-            tokens.push(Token::BinaryOp(op));
-        } else {
-            tokens.push(Token::Name { text: cur_name });
-        }
+    if state == ParseState::InName {
+        tokens.push((name_token(&s[name_start..]), name_start..s.len()));
     }
     Ok(tokens)
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum AstNode {
-    Invert(Box<AstNode>),
-    Binary(BinaryOp, Box<AstNode>, Box<AstNode>),
-    Name(String),
+/// Index of a node in an [`Ast`].
+type NodeId = u16;
+
+#[doc(hidden)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AstNode {
+    Invert(NodeId),
+    Binary(BinaryOp, NodeId, NodeId),
+    /// A name, as the range `start..end` of [`Ast::names`].
+    Name(u16, u16),
 }
 
-impl AstNode {
-    fn munch_tokens(tokens: &mut VecDeque<Token>, depth: u16) -> Result<Self, &'static str> {
+/// The nodes of a parsed expression, kept together in one arena instead of each being boxed, so
+/// even big expressions only take two allocations. Children come before their parents, so the
+/// last node is the root. Expressions are shorter than 65535 bytes (see
+/// [`Limits::expr_len`]), so their nodes and names can be indexed with `u16`s. Expressions from
+/// the `expr!` macro borrow both from constants.
+#[derive(Debug, Clone, Default)]
+struct Ast {
+    nodes: Cow<'static, [AstNode]>,
+    /// The text of every name, one after another.
+    names: Cow<'static, str>,
+    /// The byte range of the text each node was parsed from, in the same order as the nodes, or
+    /// empty if the expression wasn't parsed (or was changed after).
+    spans: Vec<(u16, u16)>,
+}
+
+impl Ast {
+    /// An empty arena with room for `nodes` nodes and `names` bytes of names, which the tokens and
+    /// text of an expression are enough for.
+    fn with_capacity(nodes: usize, names: usize) -> Self {
+        Self {
+            nodes: Cow::Owned(Vec::with_capacity(nodes)),
+            names: Cow::Owned(String::with_capacity(names)),
+            spans: Vec::with_capacity(nodes),
+        }
+    }
+
+    fn push(&mut self, node: AstNode) -> NodeId {
+        self.nodes.to_mut().push(node);
+        (self.nodes.len() - 1) as NodeId
+    }
+
+    fn push_name(&mut self, name: &str) -> NodeId {
+        let start = self.names.len() as u16;
+        self.names.to_mut().push_str(name);
+        self.push(AstNode::Name(start, self.names.len() as u16))
+    }
+
+    /// Pushes a node parsed from the text at `span`.
+    fn push_parsed(&mut self, node: AstNode, span: Range<usize>) -> NodeId {
+        // expressions are shorter than u16::MAX bytes
+        self.spans.push((span.start as u16, span.end as u16));
+        self.push(node)
+    }
+
+    /// Pushes a name parsed from the text at `span`.
+    fn push_parsed_name(&mut self, name: &str, span: Range<usize>) -> NodeId {
+        self.spans.push((span.start as u16, span.end as u16));
+        self.push_name(name)
+    }
+
+    /// Where the node was parsed from, if it was.
+    fn span(&self, id: NodeId) -> Option<Range<usize>> {
+        let &(start, end) = self.spans.get(usize::from(id))?;
+        Some(usize::from(start)..usize::from(end))
+    }
+
+    fn root(&self) -> NodeId {
+        (self.nodes.len() - 1) as NodeId
+    }
+
+    fn node(&self, id: NodeId) -> AstNode {
+        self.nodes[usize::from(id)]
+    }
+
+    fn name(&self, start: u16, end: u16) -> &str {
+        &self.names[usize::from(start)..usize::from(end)]
+    }
+
+    /// Parses tokens from the front of `tokens`. `end` is the length of the expression, used for
+    /// errors about it ending early, and `consumed` is set to where the last token taken ends.
+    fn munch_tokens(
+        &mut self,
+        tokens: &mut VecDeque<Spanned<'_>>,
+        depth: u16,
+        end: usize,
+        consumed: &mut usize,
+    ) -> Result<NodeId, ParseError> {
+        let err = |kind, token: Option<&Spanned<'_>>| ParseError::at(kind, token, end);
         if depth == 0 {
-            return Err("expression too deep");
+            return Err(err(ParseErrorKind::TooDeep, tokens.front()));
         }
-        while let Some(next) = tokens.get(0) {
+        if let Some((next, span)) = tokens.front() {
+            let span = span.clone();
             match next {
-                Token::CloseBracket => return Err("unexpected closing bracket"),
+                Token::CloseBracket => {
+                    return Err(err(ParseErrorKind::UnexpectedCloseBracket, tokens.front()))
+                }
                 Token::Invert => {
-                    tokens.remove(0);
+                    tokens.pop_front();
+                    *consumed = span.end;
                     // invert exactly the next token
                     // !a & b -> (!a) & b
-                    match tokens.get(0) {
-                        Some(Token::OpenBracket) => {
-                            return Ok(AstNode::Invert(Box::new(Self::munch_tokens(
-                                tokens,
-                                depth - 1,
-                            )?)));
+                    match tokens.front() {
+                        Some((Token::OpenBracket, _)) => {
+                            let inverted = self.munch_tokens(tokens, depth - 1, end, consumed)?;
+                            let span = span.start..*consumed;
+                            return Ok(self.push_parsed(AstNode::Invert(inverted), span));
                         }
-                        Some(Token::Name { text }) => {
+                        Some((Token::Name { text }, name_span)) => {
                             // is it like "!abc" or "!abc & xyz"
-                            let inverted = AstNode::Invert(Box::new(AstNode::Name(text.clone())));
+                            let text = *text;
+                            let name_span = name_span.clone();
                             match tokens.get(1) {
-                                Some(Token::BinaryOp(_)) => {
+                                Some((Token::BinaryOp(_), _)) => {
                                     // "!abc & xyz"
                                     // convert to unambiguous form and try again
-                                    tokens.insert(0, Token::OpenBracket);
-                                    tokens.insert(1, Token::Invert);
-                                    tokens.insert(2, Token::OpenBracket);
-                                    tokens.insert(4, Token::CloseBracket);
-                                    tokens.insert(5, Token::CloseBracket);
-                                    return Self::munch_tokens(tokens, depth - 1);
+                                    tokens.insert(0, (Token::OpenBracket, span.clone()));
+                                    tokens.insert(1, (Token::Invert, span));
+                                    tokens.insert(2, (Token::OpenBracket, name_span.clone()));
+                                    tokens.insert(4, (Token::CloseBracket, name_span.clone()));
+                                    tokens.insert(5, (Token::CloseBracket, name_span));
+                                    return self.munch_tokens(tokens, depth - 1, end, consumed);
                                 }
-                                None | Some(Token::CloseBracket) => {
+                                None | Some((Token::CloseBracket, _)) => {
                                     // "!abc"
-                                    tokens.remove(0); // remove name
-                                    return Ok(inverted);
+                                    tokens.pop_front(); // remove name
+                                    *consumed = name_span.end;
+                                    let span = span.start..name_span.end;
+                                    let name = self.push_parsed_name(text, name_span);
+                                    return Ok(self.push_parsed(AstNode::Invert(name), span));
+                                }
+                                Some(_) => {
+                                    return Err(err(
+                                        ParseErrorKind::InvalidAfterInvertedName,
+                                        tokens.get(1),
+                                    ))
                                 }
-                                Some(_) => return Err("invalid token after inverted name"),
                             }
                         }
-                        Some(Token::Invert) => {
-                            return Err("can't double invert, that would be pointless")
+                        Some((Token::Invert, _)) => {
+                            return Err(err(ParseErrorKind::DoubleInvert, tokens.front()))
                         }
-                        Some(_) => return Err("expected expression"),
-                        None => return Err("expected token to invert, got EOF"),
+                        Some(_) => {
+                            return Err(err(ParseErrorKind::ExpectedExpression, tokens.front()))
+                        }
+                        None => return Err(err(ParseErrorKind::NothingToInvert, None)),
                     }
                 }
                 Token::OpenBracket => {
-                    tokens.remove(0); // open bracket
-                    let result = Self::munch_tokens(tokens, depth - 1)?;
-                    match tokens.remove(0) {
-                        // remove closing bracket
-                        Some(Token::CloseBracket) => {}
-                        _ => return Err("expected closing bracket"),
+                    tokens.pop_front(); // open bracket
+                    *consumed = span.end;
+                    let result = self.munch_tokens(tokens, depth - 1, end, consumed)?;
+                    match tokens.front() {
+                        Some((Token::CloseBracket, close_span)) => {
+                            // remove closing bracket
+                            *consumed = close_span.end;
+                            tokens.pop_front();
+                        }
+                        token => return Err(err(ParseErrorKind::ExpectedCloseBracket, token)),
                     };
                     // check for binary op afterwards
-                    return match tokens.get(0) {
-                        Some(Token::BinaryOp(op)) => {
-                            let op = op.clone();
-                            tokens.remove(0).unwrap(); // remove binary op
-                            let ret = Ok(AstNode::Binary(
-                                op,
-                                Box::new(result),
-                                Box::new(Self::munch_tokens(tokens, depth - 1)?),
-                            ));
-                            ret
+                    return match tokens.front() {
+                        Some((Token::BinaryOp(op), _)) => {
+                            let op = *op;
+                            tokens.pop_front(); // remove binary op
+                            let right = self.munch_tokens(tokens, depth - 1, end, consumed)?;
+                            let span = span.start..*consumed;
+                            Ok(self.push_parsed(AstNode::Binary(op, result, right), span))
                         }
-                        Some(Token::CloseBracket) | None => Ok(result),
-                        Some(_) => Err("invald token after closing bracket"),
+                        Some((Token::CloseBracket, _)) | None => Ok(result),
+                        token => Err(err(ParseErrorKind::InvalidAfterCloseBracket, token)),
                     };
                 }
-                Token::BinaryOp(_) => return Err("unexpected binary operator"),
+                Token::BinaryOp(_) => {
+                    return Err(err(ParseErrorKind::UnexpectedBinaryOp, tokens.front()))
+                }
                 Token::Name { text } => {
                     // could be the start of the binary op or just a lone name
                     match tokens.get(1) {
-                        Some(Token::BinaryOp(_)) => {
+                        Some((Token::BinaryOp(_), _)) => {
                             // convert to unambiguous form and try again
-                            tokens.insert(1, Token::CloseBracket);
-                            tokens.insert(0, Token::OpenBracket);
-                            return Self::munch_tokens(tokens, depth - 1);
+                            tokens.insert(1, (Token::CloseBracket, span.clone()));
+                            tokens.insert(0, (Token::OpenBracket, span));
+                            return self.munch_tokens(tokens, depth - 1, end, consumed);
                         }
-                        Some(Token::CloseBracket) | None => {
+                        Some((Token::CloseBracket, _)) | None => {
                             // lone token
-                            let text = text.clone();
-                            tokens.remove(0);
-                            return Ok(AstNode::Name(text));
+                            let text = *text;
+                            tokens.pop_front();
+                            *consumed = span.end;
+                            return Ok(self.push_parsed_name(text, span));
                         }
-                        Some(_) => return Err("name followed by invalid token"),
+                        token => return Err(err(ParseErrorKind::InvalidAfterName, token)),
                     }
                 }
             }
         }
-        Err("unexpected end of expression")
+        Err(err(ParseErrorKind::UnexpectedEnd, None))
     }
 
-    fn matches(&self, tags: &[&str]) -> bool {
+    fn matches(&self, id: NodeId, tags: &[&str]) -> bool {
         // invert, binary, name
-        match self {
-            Self::Invert(inverted) => !inverted.matches(tags),
-            Self::Name(name) => tags.contains(&&**name),
-            Self::Binary(BinaryOp::And, a1, a2) => a1.matches(tags) && a2.matches(tags),
-            Self::Binary(BinaryOp::Or, a1, a2) => a1.matches(tags) || a2.matches(tags),
+        match self.node(id) {
+            AstNode::Invert(inverted) => !self.matches(inverted, tags),
+            AstNode::Name(start, end) => {
+                let name = self.name(start, end);
+                is_tag(name) && tags.contains(&name)
+            }
+            AstNode::Binary(BinaryOp::And, a1, a2) => {
+                self.matches(a1, tags) && self.matches(a2, tags)
+            }
+            AstNode::Binary(BinaryOp::Or, a1, a2) => {
+                self.matches(a1, tags) || self.matches(a2, tags)
+            }
+        }
+    }
+
+    /// Every name in the expression, in the order they're written.
+    fn names(&self) -> impl Iterator<Item = &str> {
+        // names are pushed as they're parsed, so the arena has them in order
+        self.nodes.iter().filter_map(move |node| match *node {
+            AstNode::Name(start, end) => Some(self.name(start, end)),
+            _ => None,
+        })
+    }
+
+    /// Whether the subtrees at `id` and `other_id` of `other` are the same expression, wherever
+    /// their nodes are stored.
+    fn same(&self, id: NodeId, other: &Ast, other_id: NodeId) -> bool {
+        match (self.node(id), other.node(other_id)) {
+            (AstNode::Invert(a), AstNode::Invert(b)) => self.same(a, other, b),
+            (AstNode::Binary(op, a1, a2), AstNode::Binary(other_op, b1, b2)) => {
+                op == other_op && self.same(a1, other, b1) && self.same(a2, other, b2)
+            }
+            (AstNode::Name(start, end), AstNode::Name(other_start, other_end)) => {
+                self.name(start, end) == other.name(other_start, other_end)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq for Ast {
+    fn eq(&self, other: &Self) -> bool {
+        self.same(self.root(), other, other.root())
+    }
+}
+
+impl Eq for Ast {}
+
+/// An instruction for [`CompiledExpr`]'s program, which works on a single value. Operands of `&`
+/// and `|` are joined by jumps, so the right operand is skipped when the left decides the result.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Op {
+    /// Sets the value to whether a ping has the tag in this slot.
+    Tag(u16),
+    /// Sets the value to false, for tags that are never matched (like ones missing from a table).
+    False,
+    Not,
+    /// Skips this many instructions if the value is false.
+    JumpIfFalse(u16),
+    /// Skips this many instructions if the value is true.
+    JumpIfTrue(u16),
+}
+
+impl Ast {
+    fn compile(&self, id: NodeId, slot: &dyn Fn(&str) -> Option<u16>, program: &mut Vec<Op>) {
+        match self.node(id) {
+            AstNode::Invert(inverted) => {
+                self.compile(inverted, slot, program);
+                program.push(Op::Not);
+            }
+            AstNode::Binary(op, a1, a2) => {
+                self.compile(a1, slot, program);
+                let jump = program.len();
+                program.push(Op::False);
+                self.compile(a2, slot, program);
+                let skip = (program.len() - jump - 1) as u16;
+                program[jump] = match op {
+                    BinaryOp::And => Op::JumpIfFalse(skip),
+                    BinaryOp::Or => Op::JumpIfTrue(skip),
+                };
+            }
+            AstNode::Name(start, end) => {
+                program.push(slot(self.name(start, end)).map_or(Op::False, Op::Tag))
+            }
+        }
+    }
+
+    /// The chance of matching a ping, if tags appear independently with chances from
+    /// `frequency`, and the number of tags checked to find out.
+    fn chance_and_cost(&self, id: NodeId, frequency: &dyn Fn(&str) -> f64) -> (f64, f64) {
+        match self.node(id) {
+            AstNode::Name(start, end) => (frequency(self.name(start, end)).clamp(0.0, 1.0), 1.0),
+            AstNode::Invert(inverted) => {
+                let (chance, cost) = self.chance_and_cost(inverted, frequency);
+                (1.0 - chance, cost)
+            }
+            AstNode::Binary(op, a1, a2) => {
+                let (chance1, cost1) = self.chance_and_cost(a1, frequency);
+                let (chance2, cost2) = self.chance_and_cost(a2, frequency);
+                match op {
+                    BinaryOp::And => (chance1 * chance2, cost1 + chance1 * cost2),
+                    BinaryOp::Or => (
+                        1.0 - (1.0 - chance1) * (1.0 - chance2),
+                        cost1 + (1.0 - chance1) * cost2,
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Copies the subtree at `id` into `out` with the operands of each chain of `&` or `|`
+    /// reordered so the ones most likely to decide the result for the fewest tags come first.
+    /// Chains stay the same length, so the expression is no deeper than before.
+    fn reorder(&self, id: NodeId, frequency: &dyn Fn(&str) -> f64, out: &mut Ast) -> NodeId {
+        let op = match self.node(id) {
+            AstNode::Name(start, end) => return out.push_name(self.name(start, end)),
+            AstNode::Invert(inverted) => {
+                let inverted = self.reorder(inverted, frequency, out);
+                return out.push(AstNode::Invert(inverted));
+            }
+            AstNode::Binary(op, _, _) => op,
+        };
+        let mut operands = Vec::new();
+        let mut node = id;
+        while let AstNode::Binary(next, a1, a2) = self.node(node) {
+            if next != op {
+                break;
+            }
+            operands.push(a1);
+            node = a2;
+        }
+        operands.push(node);
+
+        // an operand decides the result if it's false for `&` or true for `|`, so putting them in
+        // order of cost per chance of deciding minimizes the expected cost
+        let mut ranked: Vec<(f64, NodeId)> = operands
+            .into_iter()
+            .map(|operand| {
+                let (chance, cost) = self.chance_and_cost(operand, frequency);
+                let decides = match op {
+                    BinaryOp::And => 1.0 - chance,
+                    BinaryOp::Or => chance,
+                };
+                (cost / decides, operand)
+            })
+            .collect();
+        ranked.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(core::cmp::Ordering::Equal));
+        let mut operands = ranked
+            .into_iter()
+            .map(|(_, operand)| self.reorder(operand, frequency, out))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev();
+        // chains have at least two operands
+        #[allow(clippy::unwrap_used)]
+        let last = operands.next().unwrap();
+        operands.fold(last, |chain, operand| {
+            out.push(AstNode::Binary(op, operand, chain))
+        })
+    }
+
+    fn display(&self, id: NodeId) -> Display<'_> {
+        Display { ast: self, id }
+    }
+}
+
+/// Writes a node of an [`Ast`] with as few brackets as possible. Binary operators group to the
+/// right, so only the left side of one can need brackets.
+struct Display<'a> {
+    ast: &'a Ast,
+    id: NodeId,
+}
+
+impl fmt::Display for Display<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ast = self.ast;
+        match ast.node(self.id) {
+            AstNode::Name(start, end) => write!(f, "{}", ast.name(start, end)),
+            AstNode::Invert(inverted) => match ast.node(inverted) {
+                AstNode::Name(start, end) => write!(f, "!{}", ast.name(start, end)),
+                _ => write!(f, "!({})", ast.display(inverted)),
+            },
+            AstNode::Binary(op, a1, a2) => {
+                match ast.node(a1) {
+                    AstNode::Name(..) => write!(f, "{}", ast.display(a1))?,
+                    AstNode::Invert(inverted)
+                        if matches!(ast.node(inverted), AstNode::Name(..)) =>
+                    {
+                        write!(f, "{}", ast.display(a1))?
+                    }
+                    _ => write!(f, "({})", ast.display(a1))?,
+                }
+                write!(f, " {} {}", op.as_char(), ast.display(a2))
+            }
         }
     }
 }
@@ -236,31 +684,521 @@ impl AstNode {
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum ExprData {
     Empty,
-    HasNodes(AstNode),
+    HasNodes(Ast),
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Expr(ExprData); // wrap internal implementation details
 
 impl Expr {
-    pub fn from_string(s: &str) -> Result<Self, &'static str> {
-        let mut tokens: VecDeque<Token> = lex(s)?.into_iter().collect();
+    /// The empty expression, which matches every ping.
+    pub const EMPTY: Self = Self(ExprData::Empty);
+
+    pub fn parse(s: &str) -> Result<Self, ParseError> {
+        Self::parse_with_limits(s, &Limits::DEFAULT)
+    }
+
+    /// Like [`Expr::parse`], but with the length and depth limits in `limits` instead of the
+    /// default ones.
+    pub fn parse_with_limits(s: &str, limits: &Limits) -> Result<Self, ParseError> {
+        // names and nodes are indexed with u16s
+        let max_len = limits.expr_len.min(usize::from(u16::MAX));
+        let mut tokens: VecDeque<Spanned> = lex(s, max_len)?.into_iter().collect();
         if tokens.is_empty() {
             return Ok(Self(ExprData::Empty));
         }
-        let ast = AstNode::munch_tokens(&mut tokens, MAX_RECURSION)?;
+        let mut ast = Ast::with_capacity(tokens.len(), s.len());
+        ast.munch_tokens(&mut tokens, limits.expr_depth, s.len(), &mut 0)?;
         if !tokens.is_empty() {
-            dbg!(tokens);
-            return Err("expected EOF, found extra tokens");
+            return Err(ParseError::at(
+                ParseErrorKind::ExtraTokens,
+                tokens.front(),
+                s.len(),
+            ));
         }
         Ok(Self(ExprData::HasNodes(ast)))
     }
 
+    /// An expression made of `nodes` and `names` from [`__parts`](Self::__parts) of another one,
+    /// for the `expr!` macro in `taglogic-macros`, which expands to a call to this. Not part of the
+    /// public API.
+    #[doc(hidden)]
+    pub const fn __from_parts(nodes: &'static [AstNode], names: &'static str) -> Self {
+        if nodes.is_empty() {
+            return Self(ExprData::Empty);
+        }
+        Self(ExprData::HasNodes(Ast {
+            nodes: Cow::Borrowed(nodes),
+            names: Cow::Borrowed(names),
+            spans: Vec::new(),
+        }))
+    }
+
+    /// The nodes and names of the expression, for the `expr!` macro to write out as constants.
+    #[doc(hidden)]
+    pub fn __parts(&self) -> (&[AstNode], &str) {
+        match &self.0 {
+            ExprData::Empty => (&[], ""),
+            ExprData::HasNodes(ast) => (&ast.nodes, &ast.names),
+        }
+    }
+
+    /// Like [`Expr::parse`], but only keeps the kind of error.
+    pub fn from_string(s: &str) -> Result<Self, ParseErrorKind> {
+        Self::parse(s).map_err(|err| err.kind)
+    }
+
     pub fn matches(&self, tags: &[&str]) -> bool {
         match &self.0 {
             ExprData::Empty => true,
-            ExprData::HasNodes(node) => node.matches(tags),
+            ExprData::HasNodes(ast) => ast.matches(ast.root(), tags),
+        }
+    }
+
+    /// Why the expression does or doesn't match `tags`, with whether each part of it matches and
+    /// the tags that decided it.
+    pub fn explain(&self, tags: &[&str]) -> Explanation<'_> {
+        match &self.0 {
+            ExprData::Empty => Explanation::EMPTY,
+            ExprData::HasNodes(ast) => Explanation::new(ast, ast.root(), tags),
+        }
+    }
+
+    /// Whether the expression matches `tags`, worked out as simply as possible: every node is
+    /// evaluated, children first, without short-circuiting, compiling or reordering. It's slow, but
+    /// obviously right, so the faster ways of matching are tested against it.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn matches_slow(&self, tags: &[&str]) -> bool {
+        self.matches_slow_by(&|name| is_tag(name) && tags.contains(&name))
+    }
+
+    /// [`Expr::matches_slow`], with whether each name matches decided by `has`.
+    #[cfg(any(test, feature = "testing"))]
+    fn matches_slow_by(&self, has: &dyn Fn(&str) -> bool) -> bool {
+        let ast = match &self.0 {
+            ExprData::Empty => return true,
+            ExprData::HasNodes(ast) => ast,
+        };
+        let mut values: Vec<bool> = Vec::with_capacity(ast.nodes.len());
+        for &node in ast.nodes.iter() {
+            let value = match node {
+                AstNode::Name(start, end) => has(ast.name(start, end)),
+                AstNode::Invert(a) => !values[usize::from(a)],
+                AstNode::Binary(BinaryOp::And, a1, a2) => {
+                    values[usize::from(a1)] & values[usize::from(a2)]
+                }
+                AstNode::Binary(BinaryOp::Or, a1, a2) => {
+                    values[usize::from(a1)] | values[usize::from(a2)]
+                }
+            };
+            values.push(value);
+        }
+        // the root is the last node
+        values.last().copied().unwrap_or(true)
+    }
+
+    /// The same expression with the operands of `&` and `|` reordered so matching checks as few
+    /// tags as it can, given `frequency`, the fraction of pings that have each tag. Terms that are
+    /// cheap and likely to decide the result (rare tags for `&`, common ones for `|`) go first.
+    /// Compile the result to match with fewer steps.
+    pub fn reorder(&self, frequency: &dyn Fn(&str) -> f64) -> Self {
+        match &self.0 {
+            ExprData::Empty => self.clone(),
+            ExprData::HasNodes(ast) => {
+                let mut reordered = Ast::with_capacity(ast.nodes.len(), ast.names.len());
+                ast.reorder(ast.root(), frequency, &mut reordered);
+                Self(ExprData::HasNodes(reordered))
+            }
+        }
+    }
+
+    /// Compiles the expression to a flat program, which matches much faster than walking the
+    /// expression for every ping. [Reorder](Expr::reorder) it first to check fewer tags. Compiled
+    /// expressions only see tags, so [comment searches](Expr::matches_ping) never match, like in
+    /// [`Expr::matches`].
+    pub fn compile(&self) -> CompiledExpr {
+        let names: Vec<String> = self.tags().into_iter().map(String::from).collect();
+        let program = self.program(&|name| {
+            names
+                .binary_search_by(|other| other.as_str().cmp(name))
+                .ok()
+                .map(|slot| slot as u16)
+        });
+        let mut lengths = 0;
+        for name in &names {
+            lengths |= 1 << name.len().min(63);
+        }
+        let truth_table = if names.len() <= TRUTH_TABLE_TAGS {
+            let mut truth_table = vec![0; (1usize << names.len()).div_ceil(64)];
+            for present in 0..1 << names.len() {
+                if run_program(&program, |slot| present >> slot & 1 == 1) {
+                    truth_table[present as usize / 64] |= 1 << (present % 64);
+                }
+            }
+            truth_table
+        } else {
+            Vec::new()
+        };
+        CompiledExpr {
+            program,
+            truth_table,
+            names,
+            lengths,
+            ids: Vec::new(),
+        }
+    }
+
+    /// Like [`Expr::compile`], but also lets the expression match tags stored as indices into
+    /// `table` with [`CompiledExpr::matches_ids`], which skips comparing strings entirely.
+    pub fn compile_with_table<T: AsRef<str>>(&self, table: &[T]) -> CompiledExpr {
+        let mut compiled = self.compile();
+        compiled.ids = table
+            .iter()
+            .map(|tag| {
+                compiled
+                    .names
+                    .binary_search_by(|name| name.as_str().cmp(tag.as_ref()))
+                    .map_or(NO_SLOT, |slot| slot as u16)
+            })
+            .collect();
+        compiled
+    }
+
+    fn program(&self, slot: &dyn Fn(&str) -> Option<u16>) -> Vec<Op> {
+        let mut program = Vec::new();
+        if let ExprData::HasNodes(ast) = &self.0 {
+            ast.compile(ast.root(), slot, &mut program);
+        }
+        program
+    }
+
+    /// All of the tags used in the expression, sorted and without duplicates. Comment searches
+    /// (see [`Expr::matches_ping`]) and function calls (see [`Expr::calls`]) aren't tags.
+    pub fn tags(&self) -> Vec<&str> {
+        let mut tags: Vec<&str> = match &self.0 {
+            ExprData::Empty => Vec::new(),
+            ExprData::HasNodes(ast) => ast.names().filter(|name| is_tag(name)).collect(),
+        };
+        tags.sort_unstable();
+        tags.dedup();
+        tags
+    }
+
+    /// Tags in the expression that look like another tag in it or in `known` (like the tags in a
+    /// log), but aren't the same, like `work` written with a Cyrillic `о`. See [`unicode::skeleton`].
+    pub fn confusables<'a, T: AsRef<str>>(&'a self, known: &'a [T]) -> Vec<Confusable<'a>> {
+        let known: Vec<&str> = known.iter().map(AsRef::as_ref).collect();
+        unicode::confusables(&self.tags(), &known)
+    }
+}
+
+/// Whether a name matches a tag, rather than being a call like `weather(rainy)`, which only
+/// [`Expr::matches_with`] can match, or a [comment search](Expr::matches_ping), which only
+/// matching with a comment can.
+fn is_tag(name: &str) -> bool {
+    !comment::is_comment_term(name) && function::split_call(name).is_none()
+}
+
+/// Where each tag is named in an expression, skipping any that can't be lexed.
+pub(crate) fn name_spans(s: &str) -> Vec<(&str, Range<usize>)> {
+    lex(s, usize::MAX)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(token, span)| match token {
+            Token::Name { text } => Some((text, span)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// An expression compiled by [`Expr::compile`] to a flat program, which checks tags in the order
+/// they're written and skips whatever can't change the result.
+///
+/// Matching first finds which of the expression's tags a ping has, and then runs the program on
+/// those bits, which fit in a `u128` for expressions with at most 128 tags. Longer ones (which
+/// [`Limits`] can allow) use a bitset instead. For expressions with only a few tags, the result
+/// for every set of tags is worked out when compiling, so running is a single lookup.
+/// [`explain`](Self::explain) shows the program.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledExpr {
+    program: Vec<Op>,
+    /// Bit `present` is the result of running the program on `present`, if there are at most
+    /// [`TRUTH_TABLE_TAGS`] tags. Empty otherwise.
+    truth_table: Vec<u64>,
+    /// The expression's tags, sorted. `Op::Tag(slot)` is `names[slot]`.
+    names: Vec<String>,
+    /// Bit `n` is set if a tag is `n` bytes long (or 63 for longer ones), so most tags that
+    /// aren't in the expression are skipped without comparing them.
+    lengths: u64,
+    /// The slot of each tag in the table given to [`Expr::compile_with_table`], by index, or
+    /// [`NO_SLOT`] for tags that aren't in the expression.
+    ids: Vec<u16>,
+}
+
+impl CompiledExpr {
+    /// Returns if the expression matches a set of tags.
+    pub fn matches<T: AsRef<str>>(&self, tags: &[T]) -> bool {
+        self.run_slots(tags.iter().filter_map(|tag| {
+            let tag = tag.as_ref();
+            if self.lengths & (1 << tag.len().min(63)) == 0 {
+                return None;
+            }
+            self.names.iter().position(|name| name == tag)
+        }))
+    }
+
+    /// Returns if the expression matches a set of tags, given as indices into the table the
+    /// expression was compiled with by [`Expr::compile_with_table`].
+    pub fn matches_ids(&self, tags: &[u32]) -> bool {
+        self.matches_id_iter(tags.iter().copied())
+    }
+
+    /// Like [`matches_ids`](Self::matches_ids), for ids that aren't in one slice.
+    pub fn matches_id_iter(&self, tags: impl IntoIterator<Item = u32>) -> bool {
+        self.run_slots(tags.into_iter().filter_map(|tag| {
+            let slot = *self.ids.get(tag as usize)?;
+            (slot != NO_SLOT).then_some(usize::from(slot))
+        }))
+    }
+
+    /// Matches `pings` pings at once, given a bitset for each tag of which pings have it, where
+    /// bit `i % 32` of word `i / 32` is for ping `i`. `column` returns a tag's bitset, or `None`
+    /// for tags no ping has. Returns a bitset of the pings that matched.
+    ///
+    /// Rather than running the program for each ping, this runs it once on whole bitsets, so
+    /// `&` and `|` combine 32 pings per word (and more with SIMD).
+    ///
+    /// ## Panics
+    /// Panics if a bitset isn't `pings.div_ceil(32)` words long.
+    pub fn matches_bitsets<'a>(
+        &self,
+        pings: usize,
+        column: &dyn Fn(&str) -> Option<&'a [u32]>,
+    ) -> Vec<u32> {
+        let slot = |slot: u16| column(&self.names[usize::from(slot)]);
+        self.run_bitsets(pings, &slot)
+    }
+
+    /// Like [`matches_bitsets`](Self::matches_bitsets), but row `t` of `matrix` is the bitset of
+    /// tag `t` in the table the expression was compiled with by [`Expr::compile_with_table`].
+    /// Tags past the end of `matrix` are treated as on no pings, like unknown tags in
+    /// [`matches_ids`](Self::matches_ids).
+    pub fn matches_bitset_rows(&self, pings: usize, matrix: &[u32]) -> Vec<u32> {
+        let words = pings.div_ceil(32);
+        let slot = |slot: u16| {
+            let row = self.ids.iter().position(|&id| id == slot)?;
+            matrix.get(row * words..(row + 1) * words)
+        };
+        self.run_bitsets(pings, &slot)
+    }
+
+    fn run_bitsets<'a>(&self, pings: usize, column: &dyn Fn(u16) -> Option<&'a [u32]>) -> Vec<u32> {
+        let words = pings.div_ceil(32);
+        let mut bits = run_program_bitsets(&self.program, words, column);
+        if !pings.is_multiple_of(32) {
+            // `!` sets the bits past the last ping
+            bits[words - 1] &= (1 << (pings % 32)) - 1;
+        }
+        bits
+    }
+
+    /// Whether the expression matches a ping with the tags in `slots`.
+    fn run_slots(&self, slots: impl Iterator<Item = usize>) -> bool {
+        if self.names.len() > 128 {
+            let mut present = vec![0u64; self.names.len().div_ceil(64)];
+            for slot in slots {
+                present[slot / 64] |= 1 << (slot % 64);
+            }
+            let has = |slot: u16| present[usize::from(slot) / 64] >> (slot % 64) & 1 == 1;
+            return run_program(&self.program, has);
+        }
+        let mut present = 0;
+        for slot in slots {
+            present |= 1 << slot;
+        }
+        self.run(present)
+    }
+
+    /// Whether the expression matches, where bit `slot` of `present` is set if the ping has that
+    /// tag. Only for expressions with at most 128 tags.
+    fn run(&self, present: u128) -> bool {
+        if self.truth_table.is_empty() {
+            run_program(&self.program, |slot| present >> slot & 1 == 1)
+        } else {
+            self.truth_table[present as usize / 64] >> (present % 64) & 1 == 1
+        }
+    }
+
+    /// The program, one numbered instruction per line, in the order tags are checked. Matching
+    /// uses a truth table instead when the expression has few enough tags, which the first line
+    /// says.
+    pub fn explain(&self) -> String {
+        let mut out = String::new();
+        if self.truth_table.is_empty() {
+            out += "runs the program for each ping\n";
+        } else {
+            out += "looks up each ping in a truth table of this program\n";
+        }
+        for (index, op) in self.program.iter().enumerate() {
+            let _ = match *op {
+                Op::Tag(slot) => writeln!(out, "{}: tag {}", index, self.names[slot as usize]),
+                Op::False => writeln!(out, "{}: false", index),
+                Op::Not => writeln!(out, "{}: not", index),
+                Op::JumpIfFalse(skip) => {
+                    writeln!(
+                        out,
+                        "{}: if false, go to {}",
+                        index,
+                        index + 1 + usize::from(skip)
+                    )
+                }
+                Op::JumpIfTrue(skip) => {
+                    writeln!(
+                        out,
+                        "{}: if true, go to {}",
+                        index,
+                        index + 1 + usize::from(skip)
+                    )
+                }
+            };
+        }
+        out
+    }
+}
+
+/// Marks tags in a table that aren't in the expression. Expressions never have this many tags,
+/// since their names are at most `u16::MAX` bytes long and separated from each other.
+const NO_SLOT: u16 = u16::MAX;
+
+/// Expressions with at most this many tags get a truth table, which takes 2^n bits.
+const TRUTH_TABLE_TAGS: usize = 10;
+
+/// Runs a program, where `has(slot)` is whether the ping has that tag.
+fn run_program(program: &[Op], has: impl Fn(u16) -> bool) -> bool {
+    let mut value = true;
+    let mut next = 0;
+    while let Some(op) = program.get(next) {
+        next += 1;
+        match *op {
+            Op::Tag(slot) => value = has(slot),
+            Op::False => value = false,
+            Op::Not => value = !value,
+            Op::JumpIfFalse(skip) if !value => next += usize::from(skip),
+            Op::JumpIfTrue(skip) if value => next += usize::from(skip),
+            Op::JumpIfFalse(_) | Op::JumpIfTrue(_) => {}
+        }
+    }
+    value
+}
+
+/// Runs a program on whole bitsets of `words` words, where `column(slot)` is the bitset of pings
+/// with that tag. The program is a sequence of parts joined by jumps, and the instructions a jump
+/// skips are the right operand, so they're run on their own and combined with the value.
+fn run_program_bitsets<'a>(
+    program: &[Op],
+    words: usize,
+    column: &dyn Fn(u16) -> Option<&'a [u32]>,
+) -> Vec<u32> {
+    let mut value = vec![u32::MAX; words];
+    let mut next = 0;
+    while let Some(op) = program.get(next) {
+        next += 1;
+        match *op {
+            Op::Tag(slot) => match column(slot) {
+                Some(bits) => {
+                    assert_eq!(bits.len(), words, "bitsets must be the same length");
+                    value.copy_from_slice(bits);
+                }
+                None => value.fill(0),
+            },
+            Op::False => value.fill(0),
+            Op::Not => bits::invert(&mut value),
+            Op::JumpIfFalse(skip) | Op::JumpIfTrue(skip) => {
+                let end = next + usize::from(skip);
+                let right = run_program_bitsets(&program[next..end], words, column);
+                let op = match *op {
+                    Op::JumpIfFalse(_) => BitOp::And,
+                    _ => BitOp::Or,
+                };
+                bits::combine(op, &mut value, &right);
+                next = end;
+            }
+        }
+    }
+    value
+}
+
+impl fmt::Display for Expr {
+    /// Writes the expression in a canonical form, which parses back to the same expression.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 {
+            ExprData::Empty => Ok(()),
+            ExprData::HasNodes(ast) => write!(f, "{}", ast.display(ast.root())),
+        }
+    }
+}
+
+/// Tags that [arbitrary](arbitrary::Arbitrary) expressions mostly use, so the same tags come up
+/// often enough for expressions to match. Tag sets for matching them against can be picked from
+/// these too.
+#[cfg(feature = "arbitrary")]
+pub const ARBITRARY_TAGS: [&str; 8] = ["a", "b", "c", "work", "sleep", "not", "café", "x:y"];
+
+/// Random expressions for fuzzing and property tests. They're small enough to parse with
+/// [`Limits::DEFAULT`] once printed, and their tags are mostly from [`ARBITRARY_TAGS`].
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Expr {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        if u.ratio(1, 16)? {
+            return Ok(Self(ExprData::Empty));
+        }
+        let mut ast = Ast::default();
+        ast.arbitrary_node(u, 4)?;
+        Ok(Self(ExprData::HasNodes(ast)))
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl Ast {
+    /// Pushes a random subtree at most `depth` nodes deep. Children are pushed before their
+    /// parents, left first, like parsing does, so printing and parsing it gives the same nodes.
+    fn arbitrary_node(
+        &mut self,
+        u: &mut arbitrary::Unstructured<'_>,
+        depth: u32,
+    ) -> arbitrary::Result<NodeId> {
+        // running out of input picks a name, so this always ends
+        match if depth == 0 { 0 } else { u.choose_index(4)? } {
+            0 => {
+                if u.ratio(1, 4)? {
+                    let len = u.int_in_range(1..=6)?.min(u.len());
+                    let name: String = String::from_utf8_lossy(u.bytes(len)?)
+                        .chars()
+                        .filter(|&c| !c.is_whitespace() && !"()!&|,".contains(c))
+                        .collect();
+                    let name = name.trim_start_matches(unicode::extends_grapheme);
+                    if !name.is_empty() && BinaryOp::from_text(name).is_none() {
+                        return Ok(self.push_name(name));
+                    }
+                }
+                Ok(self.push_name(u.choose(&ARBITRARY_TAGS)?))
+            }
+            1 => {
+                let inverted = self.arbitrary_node(u, depth - 1)?;
+                Ok(self.push(AstNode::Invert(inverted)))
+            }
+            _ => {
+                let op = if u.arbitrary()? {
+                    BinaryOp::And
+                } else {
+                    BinaryOp::Or
+                };
+                let a1 = self.arbitrary_node(u, depth - 1)?;
+                let a2 = self.arbitrary_node(u, depth - 1)?;
+                Ok(self.push(AstNode::Binary(op, a1, a2)))
+            }
         }
     }
 }
@@ -268,27 +1206,138 @@ impl Expr {
 #[cfg(test)]
 mod test {
     use super::*;
+    use alloc::boxed::Box;
+
+    /// A parsed expression as a tree, to compare against.
+    #[derive(Debug, PartialEq)]
+    enum Tree {
+        Invert(Box<Tree>),
+        Binary(BinaryOp, Box<Tree>, Box<Tree>),
+        Name(String),
+    }
+
+    impl Ast {
+        fn tree(&self, id: NodeId) -> Tree {
+            match self.node(id) {
+                AstNode::Invert(inverted) => Tree::Invert(Box::new(self.tree(inverted))),
+                AstNode::Binary(op, a1, a2) => {
+                    Tree::Binary(op, Box::new(self.tree(a1)), Box::new(self.tree(a2)))
+                }
+                AstNode::Name(start, end) => Tree::Name(self.name(start, end).into()),
+            }
+        }
+    }
+
+    fn tree(s: &str) -> Tree {
+        match Expr::from_string(s).unwrap().0 {
+            ExprData::HasNodes(ast) => ast.tree(ast.root()),
+            ExprData::Empty => panic!("empty expression"),
+        }
+    }
 
     #[test]
-    fn premature_eof() {
+    fn keeps_spans() {
+        let text = "(a|b) & !(c | d)  &  !e";
+        let expr = Expr::parse(text).unwrap();
+        let ExprData::HasNodes(ast) = &expr.0 else {
+            unreachable!()
+        };
+        let spans: Vec<&str> = (0..ast.nodes.len() as NodeId)
+            .map(|id| &text[ast.span(id).unwrap()])
+            .collect();
         assert_eq!(
-            Expr::from_string("a &"),
-            Err("unexpected end of expression")
+            spans,
+            [
+                "a",
+                "b",
+                "a|b",
+                "c",
+                "d",
+                "c | d",
+                "e",
+                "!e",
+                // `!` before brackets inverts everything after them
+                "(c | d)  &  !e",
+                "!(c | d)  &  !e",
+                "(a|b) & !(c | d)  &  !e",
+            ]
         );
+        // only parsed expressions have them
+        let reordered = expr.reorder(&|_| 0.5);
+        let ExprData::HasNodes(ast) = &reordered.0 else {
+            unreachable!()
+        };
+        assert_eq!(ast.span(ast.root()), None);
+    }
+
+    #[test]
+    fn nodes_share_an_arena() {
+        let expr = Expr::from_string("a & !(bc | d)").unwrap();
+        match &expr.0 {
+            ExprData::HasNodes(ast) => {
+                assert_eq!(ast.nodes.len(), 6);
+                assert_eq!(ast.names, "abcd");
+                assert_eq!(ast.node(ast.root()), AstNode::Binary(BinaryOp::And, 0, 4));
+            }
+            ExprData::Empty => unreachable!(),
+        }
+        // equal however the nodes are laid out
+        let reordered = expr.reorder(&|tag| if tag == "a" { 0.99 } else { 0.1 });
+        assert_eq!(reordered.to_string(), "(!(bc | d)) & a");
+        assert_eq!(reordered, Expr::from_string("(!(bc | d)) & a").unwrap());
+        assert_ne!(reordered, expr);
+    }
+
+    #[test]
+    fn premature_eof() {
+        assert_eq!(Expr::from_string("a &"), Err(ParseErrorKind::UnexpectedEnd));
         assert_eq!(
             Expr::from_string("a & b &"),
-            Err("unexpected end of expression")
+            Err(ParseErrorKind::UnexpectedEnd)
         );
         assert_eq!(
             Expr::from_string("(a & b) |"),
-            Err("unexpected end of expression")
+            Err(ParseErrorKind::UnexpectedEnd)
         );
     }
 
     #[test]
     fn max_len() {
-        assert!(matches!(Expr::from_string("01234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789"), Err(_)));
-        assert!(matches!(Expr::from_string("1234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789"), Ok(_)));
+        assert!(Expr::from_string("01234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789").is_err());
+        assert!(Expr::from_string("1234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789").is_ok());
+    }
+
+    #[test]
+    fn configurable_limits() {
+        let limits = Limits {
+            expr_depth: 200,
+            expr_len: 100_000,
+            ..Limits::DEFAULT
+        };
+        let deep = format!("{}a{}", "(".repeat(150), ")".repeat(150));
+        assert!(Expr::parse(&deep).is_err());
+        let expr = Expr::parse_with_limits(&deep, &limits).unwrap();
+        assert!(expr.matches(&["a"]));
+        assert!(expr.compile().matches(&["a"]));
+
+        // far too deep, even for these limits, so it's an error rather than a stack overflow
+        let deeper = format!("{}a", "!(".repeat(30_000));
+        assert_eq!(
+            Expr::parse_with_limits(&deeper, &limits).unwrap_err().kind,
+            ParseErrorKind::TooDeep
+        );
+        // too long to index with u16s, whatever the limit
+        let long = "a".repeat(70_000);
+        assert_eq!(
+            Expr::parse_with_limits(&long, &limits).unwrap_err().kind,
+            ParseErrorKind::TooLong
+        );
+        let strict = Limits {
+            expr_len: 4,
+            ..Limits::DEFAULT
+        };
+        assert!(Expr::parse_with_limits("a & b", &strict).is_err());
+        assert!(Expr::parse_with_limits("a&b", &strict).is_ok());
     }
 
     #[test]
@@ -302,56 +1351,56 @@ mod test {
     #[test]
     fn simple_add() {
         assert_eq!(
-            Expr::from_string("a & b").unwrap().0,
-            ExprData::HasNodes(AstNode::Binary(
+            tree("a & b"),
+            Tree::Binary(
                 BinaryOp::And,
-                Box::new(AstNode::Name("a".to_string())),
-                Box::new(AstNode::Name("b".to_string())),
-            ))
+                Box::new(Tree::Name("a".to_string())),
+                Box::new(Tree::Name("b".to_string())),
+            )
         )
     }
 
     #[test]
     fn simple_inversion() {
         assert_eq!(
-            Expr::from_string("!a & b").unwrap().0,
-            ExprData::HasNodes(AstNode::Binary(
+            tree("!a & b"),
+            Tree::Binary(
                 BinaryOp::And,
-                Box::new(AstNode::Invert(Box::new(AstNode::Name("a".to_string())))),
-                Box::new(AstNode::Name("b".to_string())),
-            ))
+                Box::new(Tree::Invert(Box::new(Tree::Name("a".to_string())))),
+                Box::new(Tree::Name("b".to_string())),
+            )
         )
     }
 
     #[test]
     fn unbracketed_multiple_bin_ops() {
         assert_eq!(
-            Expr::from_string("a & b & c").unwrap().0,
-            ExprData::HasNodes(AstNode::Binary(
+            tree("a & b & c"),
+            Tree::Binary(
                 BinaryOp::And,
-                Box::new(AstNode::Name("a".to_string())),
-                Box::new(AstNode::Binary(
+                Box::new(Tree::Name("a".to_string())),
+                Box::new(Tree::Binary(
                     BinaryOp::And,
-                    Box::new(AstNode::Name("b".to_string())),
-                    Box::new(AstNode::Name("c".to_string())),
+                    Box::new(Tree::Name("b".to_string())),
+                    Box::new(Tree::Name("c".to_string())),
                 )),
-            ))
+            )
         )
     }
 
     #[test]
     fn multiple_with_inversion_in_middle() {
         assert_eq!(
-            Expr::from_string("a & !b & c").unwrap().0,
-            ExprData::HasNodes(AstNode::Binary(
+            tree("a & !b & c"),
+            Tree::Binary(
                 BinaryOp::And,
-                Box::new(AstNode::Name("a".to_string())),
-                Box::new(AstNode::Binary(
+                Box::new(Tree::Name("a".to_string())),
+                Box::new(Tree::Binary(
                     BinaryOp::And,
-                    Box::new(AstNode::Invert(Box::new(AstNode::Name("b".to_string())))),
-                    Box::new(AstNode::Name("c".to_string())),
+                    Box::new(Tree::Invert(Box::new(Tree::Name("b".to_string())))),
+                    Box::new(Tree::Name("c".to_string())),
                 )),
-            ))
+            )
         )
     }
 
@@ -375,26 +1424,21 @@ mod test {
         ];
         for test in tests {
             println!("trying: {}", test);
-            assert!(matches!(Expr::from_string(test), Err(_)));
+            assert!(Expr::from_string(test).is_err());
         }
     }
 
     #[test]
     fn lots_of_nesting() {
         assert_eq!(
-            Expr::from_string("(((((((a & (((((b))))))))))))")
-                .unwrap()
-                .0,
-            ExprData::HasNodes(AstNode::Binary(
+            tree("(((((((a & (((((b))))))))))))"),
+            Tree::Binary(
                 BinaryOp::And,
-                Box::new(AstNode::Name("a".to_string())),
-                Box::new(AstNode::Name("b".to_string())),
-            ))
-        );
-        assert_eq!(
-            Expr::from_string("(((((((a)))))))").unwrap().0,
-            ExprData::HasNodes(AstNode::Name("a".to_string()))
+                Box::new(Tree::Name("a".to_string())),
+                Box::new(Tree::Name("b".to_string())),
+            )
         );
+        assert_eq!(tree("(((((((a)))))))"), Tree::Name("a".to_string()));
     }
 
     #[test]
@@ -476,6 +1520,234 @@ mod test {
         );
     }
 
+    #[test]
+    fn lists_tags() {
+        assert_eq!(
+            Expr::from_string("b & !(a | c) & b").unwrap().tags(),
+            vec!["a", "b", "c"]
+        );
+        assert!(Expr::from_string("").unwrap().tags().is_empty());
+    }
+
+    #[test]
+    fn keeps_graphemes_together() {
+        let expr = Expr::parse("👩\u{200D}💻&!(❤\u{FE0F}|cafe\u{301})").unwrap();
+        assert_eq!(expr.tags(), ["cafe\u{301}", "❤\u{FE0F}", "👩\u{200D}💻"]);
+        assert!(expr.matches(&["👩\u{200D}💻"]));
+        assert!(!expr.matches(&["👩\u{200D}💻", "❤\u{FE0F}"]));
+        for (expr, at) in [("a & \u{301}b", 4), ("\u{FE0F}", 0), ("(\u{200D}a)", 1)] {
+            let err = Expr::parse(expr).unwrap_err();
+            assert_eq!(err.kind, ParseErrorKind::DetachedMark);
+            assert_eq!(err.span.start, at);
+        }
+    }
+
+    #[test]
+    fn spans_multibyte_input() {
+        let expr = "café & 😀 )";
+        let err = Expr::parse(expr).unwrap_err();
+        assert_eq!(err.span, 13..14);
+        assert_eq!(&expr[err.span.clone()], ")");
+        let offsets = err.offsets(expr);
+        assert_eq!((offsets.utf16, offsets.chars), (10..11, 9..10));
+        // the end of the expression
+        let err = Expr::parse("é &").unwrap_err();
+        assert_eq!(err.offsets("é &").bytes, 4..4);
+        assert_eq!(err.offsets("é &").utf16, 3..3);
+    }
+
+    #[test]
+    fn finds_confusable_tags() {
+        let expr = Expr::parse("wоrk | work | sleep").unwrap();
+        let found = expr.confusables(&["ѕleep"]);
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].tag, found[0].looks_like), ("sleep", "ѕleep"));
+        assert_eq!((found[1].tag, found[1].looks_like), ("work", "wоrk"));
+        assert_eq!(name_spans("a & (wоrk)"), [("a", 0..1), ("wоrk", 5..10)]);
+    }
+
+    #[test]
+    fn compiled_matches_like_expr() {
+        let table = ["a", "b", "c", "d"];
+        let sets: [&[u32]; 5] = [&[], &[0], &[0, 1], &[2, 3], &[0, 2, 3]];
+        for expr in &[
+            "",
+            "a",
+            "!a",
+            "a & !(b | c)",
+            "(a | b) & d",
+            "a | nope",
+            "!nope",
+            "!(!a & b) | !(c | d & !a)",
+        ] {
+            let expr = Expr::from_string(expr).unwrap();
+            let compiled = expr.compile_with_table(&table);
+            for set in &sets {
+                let tags: Vec<&str> = set.iter().map(|&i| table[i as usize]).collect();
+                assert_eq!(
+                    compiled.matches_ids(set),
+                    expr.matches(&tags),
+                    "{} {:?}",
+                    expr,
+                    tags
+                );
+                assert_eq!(compiled.matches(&tags), expr.matches(&tags));
+                assert_eq!(expr.matches_slow(&tags), expr.matches(&tags));
+            }
+            assert_eq!(expr.compile(), expr.compile_with_table::<&str>(&[]));
+        }
+    }
+
+    #[test]
+    fn compiled_matches_bitsets() {
+        let table = ["a", "b", "c", "d"];
+        let sets: [&[u32]; 5] = [&[], &[0], &[0, 1], &[2, 3], &[0, 2, 3]];
+        // ping `i` has the tags of `sets[i % 5]`, in a row of two words for each tag
+        let pings = 45;
+        let mut matrix = vec![0u32; table.len() * 2];
+        for i in 0..pings {
+            for &tag in sets[i % 5] {
+                matrix[tag as usize * 2 + i / 32] |= 1 << (i % 32);
+            }
+        }
+        for expr in &[
+            "",
+            "!a",
+            "a & !(b | c)",
+            "(a | b) & d",
+            "!nope",
+            "!(!a & b) | !(c | d)",
+        ] {
+            let compiled = Expr::from_string(expr).unwrap().compile_with_table(&table);
+            let bits = compiled.matches_bitset_rows(pings, &matrix);
+            let by_name = compiled.matches_bitsets(pings, &|tag| {
+                let row = table.iter().position(|&name| name == tag)?;
+                Some(&matrix[row * 2..row * 2 + 2])
+            });
+            assert_eq!(bits, by_name);
+            for i in 0..64 {
+                let expected = i < pings && compiled.matches_ids(sets[i % 5]);
+                assert_eq!(
+                    bits[i / 32] >> (i % 32) & 1 == 1,
+                    expected,
+                    "{} {}",
+                    expr,
+                    i
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn compiled_handles_deep_expressions() {
+        // the longest chain that isn't too deep, where every tag is pushed before any of the ands run
+        let tags: Vec<String> = (0..10).map(|i| format!("{:02}", i)).collect();
+        let expr = Expr::from_string(&tags.join("&")).unwrap();
+        let compiled = expr.compile();
+        let mut present: Vec<&str> = tags.iter().map(String::as_str).collect();
+        assert!(compiled.matches(&present));
+        present.remove(2);
+        assert!(!compiled.matches(&present));
+    }
+
+    #[test]
+    fn compiled_with_many_tags() {
+        // more tags than fit in a u128, which long enough limits allow
+        let tags: Vec<String> = (0..300).map(|i| format!("t{}", i)).collect();
+        let limits = Limits {
+            expr_depth: 1000,
+            expr_len: usize::MAX,
+            ..Limits::DEFAULT
+        };
+        let s = format!("({}) & !t7", tags.join(" | "));
+        let expr = Expr::parse_with_limits(&s, &limits).unwrap();
+        let compiled = expr.compile_with_table(&tags);
+        for present in [&["t299"][..], &["t7", "t200"], &["t0"], &["x"], &[]] {
+            assert_eq!(compiled.matches(present), expr.matches(present));
+            let ids: Vec<u32> = (present.iter())
+                .filter_map(|tag| tags.iter().position(|other| other == tag))
+                .map(|id| id as u32)
+                .collect();
+            assert_eq!(compiled.matches_ids(&ids), expr.matches(present));
+        }
+
+        let columns: Vec<Vec<u32>> = (0..300).map(|i| vec![1 << (i % 3)]).collect();
+        let column = |tag: &str| {
+            let i = tags.iter().position(|other| other == tag)?;
+            Some(columns[i].as_slice())
+        };
+        // every ping has some tag, and t7 is on ping 1
+        assert_eq!(compiled.matches_bitsets(3, &column), [0b101]);
+    }
+
+    #[test]
+    fn compiled_without_truth_table() {
+        // too many tags for a truth table, so the program is run for every ping
+        let expr =
+            Expr::from_string("(a | b | c | d | e | f) & !(g | h | i | j | k | long)").unwrap();
+        let compiled = expr.compile();
+        assert!(compiled.truth_table.is_empty());
+        for tags in [&["a"][..], &["f", "x"], &["a", "long"], &["g"], &[]] {
+            assert_eq!(compiled.matches(tags), expr.matches(tags), "{:?}", tags);
+        }
+        assert!(!Expr::from_string("a | b")
+            .unwrap()
+            .compile()
+            .truth_table
+            .is_empty());
+    }
+
+    #[test]
+    fn reorders_by_frequency() {
+        let frequency = |tag: &str| match tag {
+            "rare" => 0.01,
+            "common" => 0.9,
+            _ => 0.5,
+        };
+        let reorder = |s: &str| {
+            Expr::from_string(s)
+                .unwrap()
+                .reorder(&frequency)
+                .to_string()
+        };
+        assert_eq!(reorder("common & rare & x"), "rare & x & common");
+        assert_eq!(reorder("rare | x | common"), "common | x | rare");
+        // a negated common tag is rarely true
+        assert_eq!(reorder("x & !common"), "!common & x");
+        // bracketed chains move as one operand, and are reordered inside
+        assert_eq!(reorder("(common | rare) & rare"), "rare & common | rare");
+        assert_eq!(reorder("x | (common & rare)"), "x | rare & common");
+        assert_eq!(reorder(""), "");
+
+        let expr = Expr::from_string("(a | b) & !(c | d & !a) | e").unwrap();
+        let reordered = expr.reorder(&|tag| if tag == "e" { 0.9 } else { 0.2 });
+        for tags in [&["a"][..], &["b", "d"], &["e"], &["c", "a"], &[]] {
+            assert_eq!(reordered.matches(tags), expr.matches(tags), "{:?}", tags);
+        }
+    }
+
+    #[test]
+    fn explains_programs() {
+        // `a & (!b | c)`, since operators group to the right
+        let compiled = Expr::from_string("a & !b | c").unwrap().compile();
+        assert_eq!(
+            compiled.explain(),
+            "looks up each ping in a truth table of this program\n\
+             0: tag a\n\
+             1: if false, go to 6\n\
+             2: tag b\n\
+             3: not\n\
+             4: if true, go to 6\n\
+             5: tag c\n"
+        );
+        let compiled = Expr::from_string("a & nope")
+            .unwrap()
+            .compile_with_table(&["a"]);
+        assert!(compiled
+            .explain()
+            .contains("1: if false, go to 3\n2: tag nope\n"));
+    }
+
     #[test]
     fn lone_name() {
         assert!(Expr::from_string("a").unwrap().matches(&["a"]));
@@ -499,81 +1771,223 @@ mod test {
 
     #[test]
     fn nested_expr() {
-        let tokens = lex("abc & !(( ! xyz || dwf) | (!abc or dwp) & (dwp and r   ) )  ").unwrap();
+        let spanned = lex(
+            "abc & !(( ! xyz || dwf) | (!abc or dwp) & (dwp and r   ) )  ",
+            200,
+        )
+        .unwrap();
+        let tokens: Vec<Token> = spanned.iter().map(|(token, _)| token.clone()).collect();
         assert_eq!(
             tokens,
             vec![
-                Token::Name {
-                    text: "abc".to_string()
-                },
+                Token::Name { text: "abc" },
                 Token::BinaryOp(BinaryOp::And),
                 Token::Invert,
                 Token::OpenBracket,
                 Token::OpenBracket,
                 Token::Invert,
-                Token::Name {
-                    text: "xyz".to_string()
-                },
+                Token::Name { text: "xyz" },
                 Token::BinaryOp(BinaryOp::Or),
-                Token::Name {
-                    text: "dwf".to_string()
-                },
+                Token::Name { text: "dwf" },
                 Token::CloseBracket,
                 Token::BinaryOp(BinaryOp::Or),
                 Token::OpenBracket,
                 Token::Invert,
-                Token::Name {
-                    text: "abc".to_string()
-                },
+                Token::Name { text: "abc" },
                 Token::BinaryOp(BinaryOp::Or),
-                Token::Name {
-                    text: "dwp".to_string()
-                },
+                Token::Name { text: "dwp" },
                 Token::CloseBracket,
                 Token::BinaryOp(BinaryOp::And),
                 Token::OpenBracket,
-                Token::Name {
-                    text: "dwp".to_string()
-                },
+                Token::Name { text: "dwp" },
                 Token::BinaryOp(BinaryOp::And),
-                Token::Name {
-                    text: "r".to_string()
-                },
+                Token::Name { text: "r" },
                 Token::CloseBracket,
                 Token::CloseBracket,
             ]
         );
-        let mut tokens = tokens.into_iter().collect();
-        let ast = AstNode::munch_tokens(&mut tokens, MAX_RECURSION).unwrap();
+        let mut tokens = spanned.into_iter().collect();
+        let mut ast = Ast::default();
+        let root = ast
+            .munch_tokens(&mut tokens, Limits::DEFAULT.expr_depth, 0, &mut 0)
+            .unwrap();
         assert!(tokens.is_empty());
         assert_eq!(
-            format!("{:?}", ast),
+            format!("{:?}", ast.tree(root)),
             "Binary(And, Name(\"abc\"), Invert(Binary(Or, Binary(Or, Invert(Name(\"xyz\")), Name(\"dwf\")), Binary(And, Binary(Or, Invert(Name(\"abc\")), Name(\"dwp\")), Binary(And, Name(\"dwp\"), Name(\"r\"))))))".to_string()
         );
     }
 
     #[test]
     fn simple_lex() {
-        let tokens = lex("foo and !(bar | !baz)").unwrap();
+        let tokens = lex("foo and !(bar | !baz)", 200).unwrap();
         assert_eq!(
-            tokens,
+            tokens
+                .into_iter()
+                .map(|(token, _)| token)
+                .collect::<Vec<_>>(),
             vec![
-                Token::Name {
-                    text: "foo".to_string()
-                },
+                Token::Name { text: "foo" },
                 Token::BinaryOp(BinaryOp::And),
                 Token::Invert,
                 Token::OpenBracket,
-                Token::Name {
-                    text: "bar".to_string()
-                },
+                Token::Name { text: "bar" },
                 Token::BinaryOp(BinaryOp::Or),
                 Token::Invert,
-                Token::Name {
-                    text: "baz".to_string()
-                },
+                Token::Name { text: "baz" },
                 Token::CloseBracket
             ]
         );
     }
+
+    #[test]
+    fn word_operators_ignore_case() {
+        let tokens: Vec<Token> = lex("a AND b Or android", 200)
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Name { text: "a" },
+                Token::BinaryOp(BinaryOp::And),
+                Token::Name { text: "b" },
+                Token::BinaryOp(BinaryOp::Or),
+                Token::Name { text: "android" },
+            ]
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let tokens = lex("ab || !c", 200).unwrap();
+        let spans: Vec<Range<usize>> = tokens.into_iter().map(|(_, span)| span).collect();
+        assert_eq!(spans, vec![0..2, 3..5, 6..7, 7..8]);
+    }
+
+    #[test]
+    fn error_spans() {
+        let err = |s| Expr::parse(s).unwrap_err();
+        assert_eq!(
+            err("a & )"),
+            ParseError {
+                kind: ParseErrorKind::UnexpectedCloseBracket,
+                span: 4..5,
+            }
+        );
+        assert_eq!(err("a &").span, 3..3);
+        assert_eq!(err("(a) b").span, 4..5);
+        assert_eq!(err("a b").kind, ParseErrorKind::InvalidAfterName);
+        assert_eq!(err("a b").span, 2..3);
+        assert_eq!(err("!!a").span, 1..2);
+    }
+
+    #[test]
+    fn formats() {
+        let cases = vec![
+            ("", ""),
+            ("a", "a"),
+            ("a and b or c", "a & b | c"),
+            ("(a || b), c", "(a | b) & c"),
+            ("!a & b", "!a & b"),
+            ("!(a | b) & c", "!((a | b) & c)"),
+            ("(!(a | b)) & c", "(!(a | b)) & c"),
+            ("((a))", "a"),
+        ];
+        for (input, expected) in cases {
+            let expr = Expr::parse(input).unwrap();
+            assert_eq!(expr.to_string(), expected);
+            // formatting is lossless
+            assert_eq!(Expr::parse(&expr.to_string()).unwrap(), expr);
+        }
+    }
+
+    #[cfg(feature = "arbitrary")]
+    mod properties {
+        use super::*;
+        use arbitrary::{Arbitrary, Unstructured};
+        use proptest::prelude::*;
+
+        fn expr(bytes: &[u8]) -> Expr {
+            Expr::arbitrary(&mut Unstructured::new(bytes)).unwrap()
+        }
+
+        proptest! {
+            #[test]
+            fn prints_and_parses_back(bytes in any::<Vec<u8>>()) {
+                let expr = expr(&bytes);
+                prop_assert_eq!(Expr::parse(&expr.to_string()), Ok(expr));
+            }
+
+            #[test]
+            fn matches_like_reference(bytes in any::<Vec<u8>>()) {
+                let expr = expr(&bytes);
+                // every set of up to 10 of the expression's tags, and one it doesn't have
+                let mut table: Vec<&str> = expr.tags().into_iter().take(10).collect();
+                if !table.contains(&"other") {
+                    table.push("other");
+                }
+                let reordered = expr.reorder(&|tag| tag.len() as f64 / 8.0);
+                let compiled = expr.compile_with_table(&table);
+                let reordered_compiled = reordered.compile();
+
+                let pings = 1usize << table.len();
+                let words = pings.div_ceil(32);
+                let mut matrix = vec![0u32; table.len() * words];
+                let mut expected = vec![0u32; words];
+                for present in 0..pings {
+                    let ids: Vec<u32> = (0..table.len() as u32)
+                        .filter(|id| present >> id & 1 == 1)
+                        .collect();
+                    let tags: Vec<&str> = ids.iter().map(|&id| table[id as usize]).collect();
+                    let matches = expr.matches_slow(&tags);
+                    prop_assert_eq!(expr.matches(&tags), matches, "{} on {:?}", expr, tags);
+                    prop_assert_eq!(compiled.matches(&tags), matches);
+                    prop_assert_eq!(compiled.matches_ids(&ids), matches);
+                    prop_assert_eq!(reordered.matches(&tags), matches, "reordered to {}", reordered);
+                    prop_assert_eq!(reordered_compiled.matches(&tags), matches);
+                    for &id in &ids {
+                        matrix[id as usize * words + present / 32] |= 1 << (present % 32);
+                    }
+                    if matches {
+                        expected[present / 32] |= 1 << (present % 32);
+                    }
+                }
+                prop_assert_eq!(compiled.matches_bitset_rows(pings, &matrix), expected.clone());
+                let column = |tag: &str| {
+                    let id = table.iter().position(|&other| other == tag)?;
+                    Some(&matrix[id * words..(id + 1) * words])
+                };
+                prop_assert_eq!(compiled.matches_bitsets(pings, &column), expected);
+            }
+
+            #[test]
+            fn reasons_decide_matches(bytes in any::<Vec<u8>>(), present in any::<u16>()) {
+                let expr = expr(&bytes);
+                let all = expr.tags();
+                let tags: Vec<&str> = all
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| present >> (index % 16) & 1 == 1)
+                    .map(|(_, &tag)| tag)
+                    .collect();
+                let explanation = expr.explain(&tags);
+                prop_assert_eq!(explanation.matched, expr.matches_slow(&tags));
+                // whatever the other tags are, the reasons give the same result
+                let reasons = explanation.reasons();
+                for others in [false, true] {
+                    let tags: Vec<&str> = all
+                        .iter()
+                        .copied()
+                        .filter(|tag| match reasons.iter().find(|reason| reason.tag == *tag) {
+                            Some(reason) => reason.present,
+                            None => others,
+                        })
+                        .collect();
+                    prop_assert_eq!(expr.matches_slow(&tags), explanation.matched, "{} on {:?}", expr, tags);
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,240 @@
+use alloc::string::{String, ToString};
+use core::convert::TryFrom;
+use core::ops::{BitAnd, BitOr, Not};
+
+use super::{lex, Ast, AstNode, BinaryOp, Expr, ExprData, NodeId, Token};
+use crate::limits::Limits;
+
+/// Why an [`ExprBuilder`] couldn't build an expression.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// A tag that would parse as something else, like one with a space or `&` in it, or `and`.
+    #[error("`{0}` isn't a valid tag")]
+    InvalidTag(String),
+    /// The expression is nested deeper than [`Limits::expr_depth`].
+    #[error("expression too deep")]
+    TooDeep,
+    /// The expression has more than 65535 nodes, or bytes of tags.
+    #[error("expression too big")]
+    TooBig,
+}
+
+/// Builds an expression in code, for bots and tests that would otherwise format a string and
+/// parse it:
+///
+/// ```
+/// use taglogic::bool::{not, tag};
+///
+/// let expr = tag("work").and(not(tag("meeting"))).build().unwrap();
+/// assert_eq!(expr.to_string(), "work & !meeting");
+/// // or with operators, which group like they do in Rust
+/// let expr = (tag("a") | tag("b") & !tag("c")).build().unwrap();
+/// assert_eq!(expr.to_string(), "a | b & !c");
+/// ```
+///
+/// Every tree it builds is one that parsing could give, so printing the expression and parsing it
+/// gives the same expression back. Invalid tags and trees that are too deep are errors from
+/// [`build`](Self::build), so building can be chained without checking each step.
+#[derive(Debug, Clone)]
+pub struct ExprBuilder {
+    ast: Ast,
+    /// Nodes on the longest path from the root to a name.
+    depth: u16,
+    error: Option<BuildError>,
+}
+
+/// An expression that's just `name`.
+pub fn tag(name: &str) -> ExprBuilder {
+    ExprBuilder::tag(name)
+}
+
+/// An expression that matches whatever `expr` doesn't.
+pub fn not(expr: ExprBuilder) -> ExprBuilder {
+    !expr
+}
+
+impl ExprBuilder {
+    /// An expression that's just `name`.
+    pub fn tag(name: &str) -> Self {
+        let mut builder = Self {
+            ast: Ast::default(),
+            depth: 1,
+            error: None,
+        };
+        // the name has to lex as itself, so the expression prints as it was built
+        match lex(name, usize::MAX).as_deref() {
+            Ok([(Token::Name { text }, _)]) if *text == name => {
+                if u16::try_from(name.len()).is_ok() {
+                    builder.ast.push_name(name);
+                } else {
+                    builder.error = Some(BuildError::TooBig);
+                }
+            }
+            _ => builder.error = Some(BuildError::InvalidTag(name.to_string())),
+        }
+        builder
+    }
+
+    /// An expression matching both this and `other`.
+    pub fn and(self, other: Self) -> Self {
+        self.binary(BinaryOp::And, other)
+    }
+
+    /// An expression matching either this or `other`.
+    pub fn or(self, other: Self) -> Self {
+        self.binary(BinaryOp::Or, other)
+    }
+
+    fn binary(mut self, op: BinaryOp, other: Self) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        if other.error.is_some() {
+            return other;
+        }
+        let left = self.ast.root();
+        match self.append(&other.ast) {
+            Some(right) if self.ast.nodes.len() <= usize::from(NodeId::MAX) => {
+                self.ast.push(AstNode::Binary(op, left, right));
+                self.depth = self.depth.max(other.depth).saturating_add(1);
+            }
+            _ => self.error = Some(BuildError::TooBig),
+        }
+        self
+    }
+
+    /// Copies the nodes and names of `other` after this one's, returning the id of its root.
+    fn append(&mut self, other: &Ast) -> Option<NodeId> {
+        let nodes = NodeId::try_from(self.ast.nodes.len()).ok()?;
+        let names = u16::try_from(self.ast.names.len()).ok()?;
+        u16::try_from(self.ast.names.len() + other.names.len()).ok()?;
+        for &node in other.nodes.iter() {
+            let node = match node {
+                AstNode::Invert(a) => AstNode::Invert(a.checked_add(nodes)?),
+                AstNode::Binary(op, a1, a2) => {
+                    AstNode::Binary(op, a1.checked_add(nodes)?, a2.checked_add(nodes)?)
+                }
+                AstNode::Name(start, end) => {
+                    AstNode::Name(start.checked_add(names)?, end.checked_add(names)?)
+                }
+            };
+            self.ast.nodes.to_mut().push(node);
+        }
+        self.ast.names.to_mut().push_str(&other.names);
+        Some(self.ast.root())
+    }
+
+    /// The expression, as long as all of its tags are valid and it's no deeper than
+    /// [`Limits::DEFAULT`] allows.
+    pub fn build(self) -> Result<Expr, BuildError> {
+        self.build_with_limits(&Limits::DEFAULT)
+    }
+
+    /// Like [`build`](Self::build), but with the depth limit in `limits`.
+    pub fn build_with_limits(self, limits: &Limits) -> Result<Expr, BuildError> {
+        match self.error {
+            Some(err) => Err(err),
+            None if self.depth > limits.expr_depth => Err(BuildError::TooDeep),
+            None => Ok(Expr(ExprData::HasNodes(self.ast))),
+        }
+    }
+}
+
+impl Not for ExprBuilder {
+    type Output = Self;
+
+    fn not(mut self) -> Self {
+        if self.error.is_none() {
+            let inverted = self.ast.root();
+            if self.ast.nodes.len() <= usize::from(NodeId::MAX) {
+                self.ast.push(AstNode::Invert(inverted));
+                self.depth = self.depth.saturating_add(1);
+            } else {
+                self.error = Some(BuildError::TooBig);
+            }
+        }
+        self
+    }
+}
+
+impl BitAnd for ExprBuilder {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        self.and(other)
+    }
+}
+
+impl BitOr for ExprBuilder {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        self.or(other)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn builds() {
+        let expr = tag("work").and(not(tag("meeting"))).build().unwrap();
+        assert_eq!(expr, Expr::parse("work & !meeting").unwrap());
+        assert!(expr.matches(&["work"]));
+        assert!(!expr.matches(&["work", "meeting"]));
+
+        // grouped like Rust's operators, not like the expression syntax
+        let expr = (tag("a") & tag("b") | !(tag("c") | tag("d")))
+            .build()
+            .unwrap();
+        assert_eq!(expr.to_string(), "(a & b) | !(c | d)");
+        assert_eq!(expr, Expr::parse("(a & b) | !(c | d)").unwrap());
+        let expr = (!!tag("a")).build().unwrap();
+        assert_eq!(Expr::parse(&expr.to_string()), Ok(expr));
+    }
+
+    #[test]
+    fn rejects_invalid_tags() {
+        for name in ["", "a b", "a&b", "(a", "!a", "and", "OR", "\u{301}a"] {
+            assert_eq!(
+                tag("x").or(tag(name)).build(),
+                Err(BuildError::InvalidTag(name.to_string())),
+                "{:?}",
+                name
+            );
+        }
+        // the first invalid tag is the one reported
+        assert_eq!(
+            (tag("a b") & !tag("c d")).build(),
+            Err(BuildError::InvalidTag("a b".to_string()))
+        );
+        assert!(tag("café").and(tag("x:y")).build().is_ok());
+    }
+
+    #[test]
+    fn limits() {
+        let chain = |n: usize| (1..n).fold(tag("a"), |expr, _| expr | tag("a"));
+        assert!(chain(20).build().is_ok());
+        assert_eq!(chain(21).build(), Err(BuildError::TooDeep));
+        let limits = Limits {
+            expr_depth: u16::MAX,
+            ..Limits::DEFAULT
+        };
+        assert!(chain(1000).build_with_limits(&limits).is_ok());
+
+        // tags are copied into every expression they're combined with, so doubling quickly
+        // runs out of room
+        let mut expr = tag(&"a".repeat(1000));
+        let mut sizes = Vec::new();
+        while expr.error.is_none() {
+            sizes.push(expr.ast.names.len());
+            expr = expr.clone() & expr;
+        }
+        assert_eq!(expr.build_with_limits(&limits), Err(BuildError::TooBig));
+        assert!(sizes.iter().all(|&size| size <= usize::from(u16::MAX)));
+        assert_eq!(tag(&"a".repeat(70000)).build(), Err(BuildError::TooBig));
+    }
+}
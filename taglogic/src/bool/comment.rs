@@ -0,0 +1,285 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use super::{is_tag, Ast, AstNode, Expr, ExprData, NodeId, ParseError, ParseErrorKind};
+
+/// What names that search comments start with, before a `"` or `/`.
+const PREFIX: &str = "comment:";
+
+/// A `comment:"text"` or `comment:/regex/` name, which matches pings by their comment instead of
+/// a tag.
+#[derive(Debug, Clone)]
+enum CommentTerm {
+    /// Comments containing the text, ignoring case.
+    Contains(String),
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl CommentTerm {
+    /// The term a name is, or None if it's a tag.
+    fn parse(name: &str) -> Option<Result<Self, ParseErrorKind>> {
+        let (delimiter, body) = split(name)?;
+        let body = body.strip_suffix(delimiter)?;
+        Some(match delimiter {
+            '"' => Ok(Self::Contains(unescape(body, |_| true).to_lowercase())),
+            #[cfg(feature = "regex")]
+            _ => regex::RegexBuilder::new(&unescape(body, |c| c == '/'))
+                .case_insensitive(true)
+                .build()
+                .map(Self::Regex)
+                .map_err(|_| ParseErrorKind::InvalidCommentPattern),
+            // regexes need the regex feature
+            #[cfg(not(feature = "regex"))]
+            _ => Err(ParseErrorKind::InvalidCommentPattern),
+        })
+    }
+
+    fn matches(&self, comment: Option<&str>) -> bool {
+        let comment = match comment {
+            Some(comment) => comment,
+            None => return false,
+        };
+        match self {
+            Self::Contains(text) => comment.to_lowercase().contains(text.as_str()),
+            #[cfg(feature = "regex")]
+            Self::Regex(regex) => regex.is_match(comment),
+        }
+    }
+}
+
+/// The delimiter of a comment search and everything after it, if `name` is one.
+fn split(name: &str) -> Option<(char, &str)> {
+    let rest = name.strip_prefix(PREFIX)?;
+    let delimiter = rest.chars().next().filter(|&c| c == '"' || c == '/')?;
+    Some((delimiter, &rest[1..]))
+}
+
+/// Removes the backslashes before characters they escape.
+fn unescape(body: &str, escapes: impl Fn(char) -> bool) -> String {
+    let mut out = String::with_capacity(body.len());
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(&next) if c == '\\' && escapes(next) => {
+                out.push(next);
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Whether `text`, an expression from before comment searches (version 1 of
+/// [expressions](crate::format::EXPRESSION_VERSION)), has a tag that would be a comment search
+/// now. Names started and ended at the same characters then, but a `"` or `/` didn't end one.
+#[cfg(feature = "stats")]
+pub(crate) fn had_comment_tags(text: &str) -> bool {
+    let mut prev: Option<char> = None;
+    text.char_indices().any(|(i, c)| {
+        let starts_name = prev.is_none_or(|prev| {
+            prev.is_whitespace()
+                || matches!(prev, '(' | ')' | '!')
+                || super::BinaryOp::from_char(prev).is_some()
+        });
+        prev = Some(c);
+        starts_name && split(&text[i..]).is_some()
+    })
+}
+
+/// Whether a name searches comments instead of matching a tag.
+pub(super) fn is_comment_term(name: &str) -> bool {
+    split(name).is_some()
+}
+
+/// The span of the comment search starting at `start` of the expression `s`, up to and including
+/// its closing delimiter (which can be escaped with a backslash), or None if there isn't one
+/// there. Unlike tags, the text in a search can have spaces and brackets.
+pub(super) fn lex_term(s: &str, start: usize) -> Option<Result<Range<usize>, ParseError>> {
+    let (delimiter, body) = split(&s[start..])?;
+    let body_start = s.len() - body.len();
+    let mut escaped = false;
+    let close = body.char_indices().find(|&(_, c)| {
+        let close = c == delimiter && !escaped;
+        escaped = c == '\\' && !escaped;
+        close
+    });
+    let span = match close {
+        Some((i, c)) => start..body_start + i + c.len_utf8(),
+        None => {
+            return Some(Err(ParseError {
+                kind: ParseErrorKind::UnterminatedComment,
+                span: start..s.len(),
+            }))
+        }
+    };
+    Some(match CommentTerm::parse(&s[span.clone()]) {
+        Some(Err(kind)) => Err(ParseError { kind, span }),
+        _ => Ok(span),
+    })
+}
+
+/// An expression with its comment searches ready to match, so regexes are only built once for
+/// many pings.
+#[derive(Debug, Clone)]
+pub(crate) struct CommentMatcher<'a> {
+    expr: &'a Expr,
+    terms: Vec<(&'a str, CommentTerm)>,
+}
+
+impl<'a> CommentMatcher<'a> {
+    pub fn new(expr: &'a Expr) -> Self {
+        let terms = match &expr.0 {
+            ExprData::Empty => Vec::new(),
+            ExprData::HasNodes(ast) => (ast.names())
+                .filter_map(|name| Some((name, CommentTerm::parse(name)?.ok()?)))
+                .collect(),
+        };
+        Self { expr, terms }
+    }
+
+    /// Whether the expression has any comment searches, so matching needs comments.
+    #[cfg(feature = "log")]
+    pub fn searches_comments(&self) -> bool {
+        !self.terms.is_empty()
+    }
+
+    pub fn matches(&self, tags: &[&str], comment: Option<&str>) -> bool {
+        self.matches_by(tags, comment, &|_| None)
+    }
+
+    /// Like [`CommentMatcher::matches`], but with names that `other` gives a result for matching
+    /// that instead of a tag.
+    pub(super) fn matches_by(
+        &self,
+        tags: &[&str],
+        comment: Option<&str>,
+        other: &dyn Fn(&str) -> Option<bool>,
+    ) -> bool {
+        let ast = match &self.expr.0 {
+            ExprData::Empty => return true,
+            ExprData::HasNodes(ast) => ast,
+        };
+        let has = |name: &str| match self.terms.iter().find(|(term, _)| *term == name) {
+            Some((_, term)) => term.matches(comment),
+            None => other(name).unwrap_or_else(|| is_tag(name) && tags.contains(&name)),
+        };
+        ast.matches_by(ast.root(), &has)
+    }
+}
+
+impl Ast {
+    /// Like [`Ast::matches`], but with whether each name matches decided by `has`.
+    fn matches_by(&self, id: NodeId, has: &dyn Fn(&str) -> bool) -> bool {
+        match self.node(id) {
+            AstNode::Invert(inverted) => !self.matches_by(inverted, has),
+            AstNode::Name(start, end) => has(self.name(start, end)),
+            AstNode::Binary(op, a1, a2) => {
+                let left = self.matches_by(a1, has);
+                match op {
+                    super::BinaryOp::And => left && self.matches_by(a2, has),
+                    super::BinaryOp::Or => left || self.matches_by(a2, has),
+                }
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// Whether the expression matches a ping with `tags` and `comment`. Names like
+    /// `comment:"standup"` match comments containing the text and ones like `comment:/stand-?up/`
+    /// match comments the regex (with the `regex` feature) finds, both ignoring case. In
+    /// [`Expr::matches`], they never match.
+    ///
+    /// ```
+    /// use taglogic::bool::Expr;
+    ///
+    /// let expr = Expr::parse(r#"work & comment:"Code Review""#).unwrap();
+    /// assert!(expr.matches_ping(&["work"], Some("code review for alice")));
+    /// assert!(!expr.matches_ping(&["work"], Some("reviewing code")));
+    /// assert!(!expr.matches_ping(&["work"], None));
+    /// ```
+    pub fn matches_ping(&self, tags: &[&str], comment: Option<&str>) -> bool {
+        CommentMatcher::new(self).matches(tags, comment)
+    }
+
+    /// Like [`Expr::matches_slow`], but with comment searches searching `comment`, for testing the
+    /// ways of matching pings that search comments.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn matches_slow_ping(&self, tags: &[&str], comment: Option<&str>) -> bool {
+        self.matches_slow_by(&|name| match CommentTerm::parse(name) {
+            Some(Ok(term)) => term.matches(comment),
+            _ => is_tag(name) && tags.contains(&name),
+        })
+    }
+
+    /// Whether the expression has any [comment searches](Self::matches_ping).
+    pub fn searches_comments(&self) -> bool {
+        match &self.0 {
+            ExprData::Empty => false,
+            ExprData::HasNodes(ast) => ast.names().any(is_comment_term),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn searches_comments() {
+        let expr = Expr::parse(r#"!comment:"Ça (va" | comment:"say \"hi\"""#).unwrap();
+        assert!(expr.searches_comments());
+        assert_eq!(expr.tags(), Vec::<&str>::new());
+        assert_eq!(
+            expr.to_string(),
+            r#"!comment:"Ça (va" | comment:"say \"hi\"""#
+        );
+        assert!(expr.matches_ping(&[], Some("we SAY \"HI\"")));
+        assert!(!expr.matches_ping(&[], Some("ÇA (VA bien")));
+        assert!(expr.matches_ping(&[], None));
+
+        // matching without a comment never finds it, even in a tag with the same text
+        let tags = [r#"comment:"say \"hi\"""#];
+        let search = Expr::parse(tags[0]).unwrap();
+        assert!(!search.matches(&tags));
+        assert!(!search.compile().matches(&tags));
+        assert!(!search.matches_slow(&tags));
+        assert!(!search.matches_ping(&tags, None));
+        assert!(expr.matches(&tags));
+
+        // without a quote or slash, it's a tag
+        let tag = Expr::parse("comment:x").unwrap();
+        assert!(!tag.searches_comments());
+        assert!(tag.matches_ping(&["comment:x"], Some("comment:x")));
+    }
+
+    #[test]
+    fn rejects_bad_searches() {
+        let err = Expr::parse(r#"a & comment:"oops"#).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::UnterminatedComment);
+        assert_eq!(err.span, 4..17);
+        let err = Expr::parse(r#"comment:"a\" & b"#).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::UnterminatedComment);
+        #[cfg(not(feature = "regex"))]
+        assert_eq!(
+            Expr::from_string("comment:/a/"),
+            Err(ParseErrorKind::InvalidCommentPattern)
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn searches_with_regexes() {
+        let expr = Expr::parse(r"comment:/stand-?up|a\/b/ & work").unwrap();
+        assert!(expr.matches_ping(&["work"], Some("StandUp at 9")));
+        assert!(expr.matches_ping(&["work"], Some("A/B testing")));
+        assert!(!expr.matches_ping(&["play"], Some("standup")));
+        let err = Expr::parse("comment:/(/").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidCommentPattern);
+        assert_eq!(err.span, 0..11);
+    }
+}
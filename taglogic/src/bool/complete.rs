@@ -0,0 +1,72 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use super::name_spans;
+
+/// Tags that the tag being typed in an expression could be, from [`complete`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion<'a> {
+    /// Byte range of the tag being typed, which a chosen tag replaces. It's empty at the cursor if
+    /// a new tag is being started.
+    pub span: Range<usize>,
+    pub tags: Vec<&'a str>,
+}
+
+/// Completes the tag being typed at byte `cursor` of `expr` to each of `tags` that starts with
+/// what's typed before the cursor, in the order they're given (so put common ones first). At a
+/// space, bracket or operator every tag is given, since a new one could go there.
+///
+/// ```
+/// use taglogic::bool::complete;
+///
+/// let completion = complete("work & !me", 10, &["meeting", "email", "meal"]);
+/// assert_eq!(completion.span, 8..10);
+/// assert_eq!(completion.tags, ["meeting", "meal"]);
+/// ```
+///
+/// It works on expressions that don't parse yet, like ones with unclosed brackets.
+pub fn complete<'a, T: AsRef<str>>(expr: &str, cursor: usize, tags: &'a [T]) -> Completion<'a> {
+    let span = name_spans(expr)
+        .into_iter()
+        .map(|(_, span)| span)
+        .find(|span| span.start < cursor && cursor <= span.end)
+        .unwrap_or(cursor..cursor);
+    let typed = expr.get(span.start..cursor).unwrap_or_default();
+    let tags = tags
+        .iter()
+        .map(AsRef::as_ref)
+        .filter(|tag| tag.starts_with(typed))
+        .collect();
+    Completion { span, tags }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TAGS: [&str; 4] = ["work", "meeting", "email", "meal"];
+
+    #[test]
+    fn completes_tag_at_cursor() {
+        let completion = complete("work & me", 9, &TAGS);
+        assert_eq!(completion.span, 7..9);
+        assert_eq!(completion.tags, ["meeting", "meal"]);
+        // only what's before the cursor counts, but the whole tag is replaced
+        let completion = complete("(w | meal)", 6, &TAGS);
+        assert_eq!(completion.span, 5..9);
+        assert_eq!(completion.tags, ["meeting", "meal"]);
+        assert_eq!(complete("(wo", 3, &TAGS).tags, ["work"]);
+        assert!(complete("work & xyz", 10, &TAGS).tags.is_empty());
+    }
+
+    #[test]
+    fn offers_every_tag_between_tags() {
+        for (expr, cursor) in [("", 0), ("work & ", 7), ("work |", 6), ("!", 1), ("a ", 2)] {
+            let completion = complete(expr, cursor, &TAGS);
+            assert_eq!(completion.span, cursor..cursor, "{:?}", expr);
+            assert_eq!(completion.tags, TAGS);
+        }
+        // before a tag, a new one could go there too
+        assert_eq!(complete("work", 0, &TAGS).span, 0..0);
+    }
+}
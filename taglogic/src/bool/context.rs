@@ -0,0 +1,107 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use core::convert::TryFrom;
+
+/// No extra facts, for contexts that aren't given any.
+static NO_FACTS: BTreeMap<String, String> = BTreeMap::new();
+
+/// Everything an expression can be matched against: a ping's tags and comment, when it was sent
+/// and where, the ping before it, and facts from the host app (like the weather). Predicates that
+/// need more than tags, like [`ExprFunction`](super::ExprFunction)s, all get it from here, so new
+/// ones don't need new arguments. More may be added, so it's made with [`EvalContext::new`] and
+/// the `with_` methods.
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use taglogic::bool::EvalContext;
+///
+/// let facts = BTreeMap::from([("weather".to_string(), "rainy".to_string())]);
+/// let before = EvalContext::new(&["sleep"]).with_timestamp(3600);
+/// let ping = EvalContext::new(&["walk"])
+///     .with_timestamp(7200)
+///     .with_tz(-3600)
+///     .with_prev_ping(&before)
+///     .with_extra(&facts);
+/// assert_eq!(ping.local_time(), Some(3600));
+/// assert_eq!(ping.fact("weather"), Some("rainy"));
+/// assert_eq!(ping.prev_ping.map(|prev| prev.tags), Some(&["sleep"][..]));
+/// ```
+#[derive(Debug, Copy, Clone)]
+#[non_exhaustive]
+pub struct EvalContext<'a> {
+    pub tags: &'a [&'a str],
+    pub comment: Option<&'a str>,
+    /// When the ping was sent, if it's known.
+    pub timestamp: Option<u64>,
+    /// The time zone the ping was sent in, as seconds ahead of UTC.
+    pub tz: i32,
+    /// The ping before this one, if there is one.
+    pub prev_ping: Option<&'a EvalContext<'a>>,
+    /// Facts the host app knows that tags don't record, by name.
+    pub extra: &'a BTreeMap<String, String>,
+}
+
+impl<'a> EvalContext<'a> {
+    /// A ping with `tags` and nothing else known about it, in UTC.
+    pub fn new(tags: &'a [&'a str]) -> Self {
+        Self {
+            tags,
+            comment: None,
+            timestamp: None,
+            tz: 0,
+            prev_ping: None,
+            extra: &NO_FACTS,
+        }
+    }
+
+    pub fn with_comment(self, comment: Option<&'a str>) -> Self {
+        Self { comment, ..self }
+    }
+
+    pub fn with_timestamp(self, timestamp: u64) -> Self {
+        Self {
+            timestamp: Some(timestamp),
+            ..self
+        }
+    }
+
+    pub fn with_tz(self, tz: i32) -> Self {
+        Self { tz, ..self }
+    }
+
+    pub fn with_prev_ping(self, prev_ping: &'a EvalContext<'a>) -> Self {
+        Self {
+            prev_ping: Some(prev_ping),
+            ..self
+        }
+    }
+
+    pub fn with_extra(self, extra: &'a BTreeMap<String, String>) -> Self {
+        Self { extra, ..self }
+    }
+
+    /// A fact from [`extra`](Self::extra).
+    pub fn fact(&self, name: &str) -> Option<&'a str> {
+        self.extra.get(name).map(String::as_str)
+    }
+
+    /// Seconds since local midnight when the ping was sent, for time of day predicates.
+    pub fn local_time(&self) -> Option<u32> {
+        let local = i64::try_from(self.timestamp?).ok()? + i64::from(self.tz);
+        Some(local.rem_euclid(86400) as u32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn local_time_wraps() {
+        let ping = EvalContext::new(&[]).with_timestamp(1800).with_tz(-3600);
+        assert_eq!(ping.local_time(), Some(86400 - 1800));
+        assert_eq!(ping.with_tz(5 * 3600).local_time(), Some(5 * 3600 + 1800));
+        assert_eq!(EvalContext::new(&[]).local_time(), None);
+        assert_eq!(ping.fact("weather"), None);
+    }
+}
@@ -0,0 +1,289 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{CompiledExpr, Op};
+
+/// A summary of a log: how many pings it has and how many of them have each tag or author, the
+/// times of the first and last pings, and how much time they stand for. It's counted in one pass, and shared
+/// by [`CompiledExpr::estimate_cost`], [`Expr::reorder`](super::Expr::reorder), linting with
+/// known tags and the dashboard, so they don't each scan the log. Get one from a log with
+/// [`PingLog::stats`](crate::log::PingLog::stats), or keep one up to date with
+/// [`add_ping`](Self::add_ping) as pings are added.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogStats {
+    pub pings: u64,
+    /// The times of the first and last pings, if there are any.
+    pub span: Option<(u64, u64)>,
+    /// The sum of the pings' intervals.
+    pub interval_secs: u64,
+    tag_pings: BTreeMap<String, u64>,
+    /// Kept apart from `tag_pings`, so a ping tagged `@bob` isn't counted as one of Bob's.
+    author_pings: BTreeMap<String, u64>,
+}
+
+impl LogStats {
+    pub fn new(pings: u64) -> Self {
+        Self {
+            pings,
+            ..Self::default()
+        }
+    }
+
+    /// Counts a ping with `tags`, which can repeat, answered by `author`.
+    pub fn add_ping<'a>(
+        &mut self,
+        time: u64,
+        interval: u32,
+        tags: impl IntoIterator<Item = &'a str>,
+        author: Option<&str>,
+    ) {
+        self.pings += 1;
+        self.span = Some(match self.span {
+            Some((first, last)) => (first.min(time), last.max(time)),
+            None => (time, time),
+        });
+        self.interval_secs += u64::from(interval);
+        let mut seen: Vec<&str> = Vec::new();
+        for tag in tags {
+            if !seen.contains(&tag) {
+                seen.push(tag);
+                *self.tag_pings.entry(tag.into()).or_default() += 1;
+            }
+        }
+        if let Some(author) = author {
+            *self.author_pings.entry(author.into()).or_default() += 1;
+        }
+    }
+
+    /// Sets how many pings have `tag`.
+    pub fn set_tag_pings(&mut self, tag: &str, pings: u64) {
+        self.tag_pings.insert(tag.into(), pings);
+    }
+
+    /// How many pings have `tag`.
+    pub fn tag_pings(&self, tag: &str) -> u64 {
+        self.tag_pings.get(tag).copied().unwrap_or(0)
+    }
+
+    /// Sets how many pings `author` answered.
+    pub fn set_author_pings(&mut self, author: &str, pings: u64) {
+        self.author_pings.insert(author.into(), pings);
+    }
+
+    /// How many pings `author` answered.
+    pub fn author_pings(&self, author: &str) -> u64 {
+        self.author_pings.get(author).copied().unwrap_or(0)
+    }
+
+    /// The fraction of pings matching `tag`, which for a tag like `@bob` is the pings tagged that
+    /// way and Bob's pings, taken to be different pings.
+    pub fn frequency(&self, tag: &str) -> f64 {
+        if self.pings == 0 {
+            return 0.0;
+        }
+        let authored = tag
+            .strip_prefix('@')
+            .map_or(0, |author| self.author_pings(author));
+        ((self.tag_pings(tag) + authored) as f64 / self.pings as f64).clamp(0.0, 1.0)
+    }
+
+    /// Every tag on at least one ping, in order.
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        (self.tag_pings.iter())
+            .filter(|(_, &pings)| pings > 0)
+            .map(|(tag, _)| tag.as_str())
+    }
+
+    /// Every author of at least one ping, in order, without the `@`.
+    pub fn authors(&self) -> impl Iterator<Item = &str> {
+        (self.author_pings.iter())
+            .filter(|(_, &pings)| pings > 0)
+            .map(|(author, _)| author.as_str())
+    }
+
+    /// Pings per day between the first and last pings, or None if they're at the same time.
+    pub fn density(&self) -> Option<f64> {
+        let (first, last) = self.span?;
+        let days = (last - first) as f64 / 86400.0;
+        (last > first).then(|| self.pings as f64 / days)
+    }
+
+    /// Roughly the fraction of scheduled pings that were answered: the time the pings stand for
+    /// over the time between the first and last. None if they're at the same time.
+    pub fn answer_rate(&self) -> Option<f64> {
+        let (first, last) = self.span?;
+        (last > first).then(|| (self.interval_secs as f64 / (last - first) as f64).min(1.0))
+    }
+}
+
+/// What matching an expression against a whole log is expected to take, from
+/// [`CompiledExpr::estimate_cost`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct QueryCost {
+    pub pings: u64,
+    /// Pings ruled out by the tag index without evaluating the expression, because they have none
+    /// of its tags and the expression can't match a ping like that.
+    pub skipped: f64,
+    /// Pings the expression has to be evaluated for.
+    pub evaluated: f64,
+    /// Steps of evaluating the expression for all of the evaluated pings. A truth table lookup is
+    /// one step, and running the program is a step per instruction it gets to.
+    pub steps: f64,
+    /// Pings expected to match.
+    pub matching: f64,
+}
+
+impl CompiledExpr {
+    /// Predicts the cost of matching the expression against a log with `stats`, assuming tags
+    /// appear independently, so the app can warn before running an expensive query over years of
+    /// pings.
+    ///
+    /// ```
+    /// use taglogic::bool::{Expr, LogStats};
+    ///
+    /// let mut stats = LogStats::new(1000);
+    /// stats.set_tag_pings("work", 400);
+    /// stats.set_tag_pings("meeting", 100);
+    /// let cost = Expr::parse("work & meeting").unwrap().compile().estimate_cost(&stats);
+    /// // pings with neither tag are skipped
+    /// assert_eq!(cost.skipped, 540.0);
+    /// assert_eq!(cost.evaluated, 460.0);
+    /// assert_eq!(cost.matching.round(), 40.0);
+    /// ```
+    pub fn estimate_cost(&self, stats: &LogStats) -> QueryCost {
+        let pings = stats.pings as f64;
+        let frequency: Vec<f64> = (self.names.iter())
+            .map(|name| stats.frequency(name))
+            .collect();
+        let none = frequency.iter().map(|f| 1.0 - f).product::<f64>();
+        let skipped = if self.run(0) { 0.0 } else { pings * none };
+        let evaluated = pings - skipped;
+
+        // chance of the value being true and false before each instruction, and at the end
+        let mut true_chance = vec![0.0; self.program.len() + 1];
+        let mut false_chance = vec![0.0; self.program.len() + 1];
+        true_chance[0] = 1.0;
+        let mut steps_per_ping = 0.0;
+        for (index, op) in self.program.iter().enumerate() {
+            let (t, f) = (true_chance[index], false_chance[index]);
+            steps_per_ping += t + f;
+            let (t, f) = match *op {
+                Op::Tag(slot) => {
+                    let chance = frequency.get(usize::from(slot)).copied().unwrap_or(0.0);
+                    ((t + f) * chance, (t + f) * (1.0 - chance))
+                }
+                Op::False => (0.0, t + f),
+                Op::Not => (f, t),
+                Op::JumpIfFalse(skip) => {
+                    false_chance[index + 1 + usize::from(skip)] += f;
+                    (t, 0.0)
+                }
+                Op::JumpIfTrue(skip) => {
+                    true_chance[index + 1 + usize::from(skip)] += t;
+                    (0.0, f)
+                }
+            };
+            true_chance[index + 1] += t;
+            false_chance[index + 1] += f;
+        }
+        if !self.truth_table.is_empty() {
+            steps_per_ping = steps_per_ping.min(1.0);
+        }
+        QueryCost {
+            pings: stats.pings,
+            skipped,
+            evaluated,
+            steps: evaluated * steps_per_ping,
+            matching: pings * true_chance[self.program.len()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bool::Expr;
+    use crate::limits::Limits;
+    use alloc::format;
+
+    fn stats() -> LogStats {
+        let mut stats = LogStats::new(1000);
+        stats.set_tag_pings("a", 500);
+        stats.set_tag_pings("b", 100);
+        stats
+    }
+
+    fn cost(expr: &str) -> QueryCost {
+        Expr::parse(expr).unwrap().compile().estimate_cost(&stats())
+    }
+
+    #[test]
+    fn skips_pings_without_tags() {
+        let cost = cost("a | b");
+        assert_eq!(cost.skipped, 450.0);
+        assert_eq!(cost.evaluated, 550.0);
+        assert_eq!(cost.steps, 550.0);
+        assert_eq!(cost.matching, 550.0);
+
+        // pings without the tags match, so none can be skipped
+        let cost = self::cost("!b");
+        assert_eq!(cost.skipped, 0.0);
+        assert_eq!(cost.matching, 900.0);
+
+        // the empty expression matches everything without any steps
+        let cost = self::cost("");
+        assert_eq!(
+            (cost.skipped, cost.steps, cost.matching),
+            (0.0, 0.0, 1000.0)
+        );
+        let cost = self::cost("missing");
+        assert_eq!((cost.evaluated, cost.matching), (0.0, 0.0));
+    }
+
+    #[test]
+    fn counts_program_steps() {
+        // too many tags for a truth table, so the program runs for each ping
+        let mut stats = stats();
+        let tags: Vec<String> = (0..12).map(|i| format!("t{}", i)).collect();
+        for tag in &tags {
+            stats.set_tag_pings(tag, 500);
+        }
+        let limits = Limits {
+            expr_depth: 100,
+            ..Limits::DEFAULT
+        };
+        let expr = Expr::parse_with_limits(&tags.join(" & "), &limits).unwrap();
+        let expr = expr.compile();
+        let cost = expr.estimate_cost(&stats);
+        // each `&` after a false tag skips the rest
+        let steps_per_ping = (0..12).map(|i| 0.5f64.powi(i) * 2.0).sum::<f64>() - 0.5f64.powi(11);
+        assert!((cost.steps / cost.evaluated - steps_per_ping).abs() < 1e-9);
+        assert!((cost.matching - 1000.0 * 0.5f64.powi(12)).abs() < 1e-9);
+        assert_eq!(LogStats::default().frequency("a"), 0.0);
+    }
+
+    #[test]
+    fn adds_pings() {
+        let mut stats = LogStats::default();
+        assert_eq!((stats.density(), stats.answer_rate()), (None, None));
+        stats.add_ping(86400, 2700, ["a", "b", "a"], None);
+        assert_eq!(stats.density(), None);
+        stats.add_ping(0, 2700, ["b"], Some("a"));
+        stats.add_ping(43200, 2700, [], None);
+        assert_eq!(stats.pings, 3);
+        assert_eq!(stats.span, Some((0, 86400)));
+        assert_eq!((stats.tag_pings("a"), stats.tag_pings("b")), (1, 2));
+        assert_eq!(stats.tags().collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(stats.authors().collect::<Vec<_>>(), ["a"]);
+        assert_eq!(
+            (stats.tag_pings("@a"), stats.frequency("@a")),
+            (0, 1.0 / 3.0)
+        );
+        assert_eq!(stats.density(), Some(3.0));
+        assert_eq!(stats.answer_rate(), Some(0.09375));
+        stats.set_tag_pings("a", 0);
+        assert_eq!(stats.tags().collect::<Vec<_>>(), ["b"]);
+    }
+}
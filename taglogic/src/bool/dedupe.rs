@@ -0,0 +1,111 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::Expr;
+
+/// Saved queries that match the same pings, and ones that only match some of them, from
+/// [`dedupe_queries`]. Queries are given by their index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    /// Queries that match exactly the same pings, like `a & b` and `b & a`, in order.
+    pub equivalent: Vec<usize>,
+    /// Queries that only match pings the group matches, but not all of them, like `a & b` for
+    /// `a`, in order.
+    pub narrower: Vec<usize>,
+}
+
+/// Finds saved queries (or goals' expressions) that are the same as another one, or that only
+/// match some of the pings another one does, so near-identical ones can be cleaned up. Queries are
+/// compared by the pings they match, not how they're written.
+///
+/// There's a group for each set of equivalent queries that has more than one query or anything
+/// narrower than it. Queries that never match aren't counted as narrower than anything, and
+/// queries that always match (like the empty one) don't have anything narrower than them, since
+/// those would list every query.
+///
+/// ```
+/// use taglogic::bool::{dedupe_queries, DuplicateGroup, Expr};
+///
+/// let queries: Vec<Expr> = ["work", "work & meeting", "meeting & work", "play"]
+///     .iter()
+///     .map(|query| Expr::parse(query).unwrap())
+///     .collect();
+/// let groups = dedupe_queries(&queries);
+/// assert_eq!(groups, [
+///     DuplicateGroup { equivalent: vec![0], narrower: vec![1, 2] },
+///     DuplicateGroup { equivalent: vec![1, 2], narrower: vec![] },
+/// ]);
+/// ```
+pub fn dedupe_queries(queries: &[Expr]) -> Vec<DuplicateGroup> {
+    let mut classes: Vec<Vec<usize>> = Vec::new();
+    for (index, query) in queries.iter().enumerate() {
+        match (classes.iter_mut()).find(|class| queries[class[0]].is_equivalent(query)) {
+            Some(class) => class.push(index),
+            None => classes.push(vec![index]),
+        }
+    }
+    let matching: Vec<bool> = (classes.iter())
+        .map(|class| queries[class[0]].is_satisfiable())
+        .collect();
+    let mut groups = Vec::new();
+    for class in &classes {
+        let query = &queries[class[0]];
+        let mut narrower: Vec<usize> = Vec::new();
+        if !query.is_valid() {
+            for (other, &matches) in classes.iter().zip(&matching) {
+                if other != class && matches && queries[other[0]].implies(query) {
+                    narrower.extend(other);
+                }
+            }
+        }
+        narrower.sort_unstable();
+        if class.len() > 1 || !narrower.is_empty() {
+            groups.push(DuplicateGroup {
+                equivalent: class.clone(),
+                narrower,
+            });
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn groups_queries() {
+        let queries: Vec<Expr> = [
+            "a & b",
+            "a",
+            "b & a",
+            "a | a",
+            "c",
+            "!(!a | !b)",
+            "x & !x",
+            "y & !y",
+            "",
+        ]
+        .iter()
+        .map(|query| Expr::parse(query).unwrap())
+        .collect();
+        assert_eq!(
+            dedupe_queries(&queries),
+            [
+                DuplicateGroup {
+                    equivalent: vec![0, 2, 5],
+                    narrower: vec![],
+                },
+                DuplicateGroup {
+                    equivalent: vec![1, 3],
+                    narrower: vec![0, 2, 5],
+                },
+                DuplicateGroup {
+                    equivalent: vec![6, 7],
+                    narrower: vec![],
+                },
+            ]
+        );
+        assert!(dedupe_queries(&[]).is_empty());
+    }
+}
@@ -0,0 +1,292 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+use super::{is_tag, Ast, AstNode, BinaryOp, NodeId};
+
+/// Why an expression did or didn't match a set of tags, from [`Expr::explain`](super::Expr::explain):
+/// the expression's tree, with whether each part of it matched.
+///
+/// ```
+/// use taglogic::bool::Expr;
+///
+/// let expr = Expr::parse("work & !meeting").unwrap();
+/// let explanation = expr.explain(&["work", "email"]);
+/// assert!(explanation.matched);
+/// assert_eq!(
+///     explanation.to_string(),
+///     "matched because `work` present and `meeting` absent"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation<'a> {
+    /// Whether this part of the expression matched.
+    pub matched: bool,
+    pub node: Explained<'a>,
+    /// The byte range of this part in the text the expression was parsed from, or None if it was
+    /// built some other way.
+    pub span: Option<Range<usize>>,
+}
+
+/// A part of an [`Explanation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Explained<'a> {
+    /// The empty expression, which matches everything.
+    Empty,
+    /// A tag, which matches if it's one of the tags.
+    Tag(&'a str),
+    Not(Box<Explanation<'a>>),
+    Binary(BinaryOp, Box<Explanation<'a>>, Box<Explanation<'a>>),
+}
+
+/// A tag that decided whether an expression matched, and whether it was one of the tags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reason<'a> {
+    pub tag: &'a str,
+    pub present: bool,
+}
+
+/// A part of an expression's text that decided whether it matched, from
+/// [`Explanation::highlights`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Highlight {
+    /// Byte range of the part, which is a tag, or an inverted one with its `!`.
+    pub span: Range<usize>,
+    /// Whether the part matched, so it can be shown in green, or red if it didn't.
+    pub matched: bool,
+}
+
+impl<'a> Explanation<'a> {
+    pub(super) const EMPTY: Self = Self {
+        matched: true,
+        node: Explained::Empty,
+        span: None,
+    };
+
+    pub(super) fn new(ast: &'a Ast, id: NodeId, tags: &[&str]) -> Self {
+        let span = ast.span(id);
+        match ast.node(id) {
+            AstNode::Name(start, end) => {
+                let tag = ast.name(start, end);
+                Self {
+                    matched: is_tag(tag) && tags.contains(&tag),
+                    node: Explained::Tag(tag),
+                    span,
+                }
+            }
+            AstNode::Invert(inverted) => {
+                let inverted = Self::new(ast, inverted, tags);
+                Self {
+                    matched: !inverted.matched,
+                    node: Explained::Not(Box::new(inverted)),
+                    span,
+                }
+            }
+            AstNode::Binary(op, a1, a2) => {
+                let (a1, a2) = (Self::new(ast, a1, tags), Self::new(ast, a2, tags));
+                let matched = match op {
+                    BinaryOp::And => a1.matched && a2.matched,
+                    BinaryOp::Or => a1.matched || a2.matched,
+                };
+                Self {
+                    matched,
+                    node: Explained::Binary(op, Box::new(a1), Box::new(a2)),
+                    span,
+                }
+            }
+        }
+    }
+
+    /// The tags that decided the result: ones that made the expression match if it did, and ones
+    /// that stopped it if it didn't, in the order they're written and without duplicates. Where
+    /// either operand of `&` or `|` would decide it alone, only the first one that does is given,
+    /// so the reasons are always enough to decide the result on their own.
+    pub fn reasons(&self) -> Vec<Reason<'a>> {
+        let mut reasons = Vec::new();
+        self.for_each_term(&mut |_, reason| {
+            if !reasons.contains(&reason) {
+                reasons.push(reason);
+            }
+        });
+        reasons
+    }
+
+    /// Where the tags in [`reasons`](Self::reasons) are in the text the expression was parsed
+    /// from, with their `!` if they're inverted, for highlighting them. It's empty if the
+    /// expression wasn't parsed.
+    pub fn highlights(&self) -> Vec<Highlight> {
+        let mut highlights = Vec::new();
+        self.for_each_term(&mut |term, _| {
+            if let Some(span) = &term.span {
+                highlights.push(Highlight {
+                    span: span.clone(),
+                    matched: term.matched,
+                });
+            }
+        });
+        highlights
+    }
+
+    /// Calls `f` with each tag that decided the result, or the inversion of it if it's inverted.
+    fn for_each_term(&self, f: &mut dyn FnMut(&Self, Reason<'a>)) {
+        match &self.node {
+            Explained::Empty => {}
+            Explained::Tag(tag) => f(
+                self,
+                Reason {
+                    tag,
+                    present: self.matched,
+                },
+            ),
+            Explained::Not(inverted) => match inverted.node {
+                Explained::Tag(tag) => f(
+                    self,
+                    Reason {
+                        tag,
+                        present: inverted.matched,
+                    },
+                ),
+                _ => inverted.for_each_term(f),
+            },
+            Explained::Binary(op, a1, a2) => {
+                // the value that decides the result alone, false for `&` and true for `|`
+                let deciding = *op == BinaryOp::Or;
+                if self.matched == deciding {
+                    let first = if a1.matched == deciding { a1 } else { a2 };
+                    first.for_each_term(f);
+                } else {
+                    a1.for_each_term(f);
+                    a2.for_each_term(f);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Explanation<'_> {
+    /// A sentence for tooltips, like "matched because `work` present and `meeting` absent".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(if self.matched {
+            "matched"
+        } else {
+            "didn't match"
+        })?;
+        if let Explained::Empty = self.node {
+            return f.write_str(", since the expression is empty");
+        }
+        for (index, reason) in self.reasons().iter().enumerate() {
+            f.write_str(if index == 0 { " because " } else { " and " })?;
+            write!(f, "{}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Reason<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let present = if self.present { "present" } else { "absent" };
+        write!(f, "`{}` {}", self.tag, present)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bool::Expr;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+
+    fn explain(expr: &str, tags: &[&str]) -> String {
+        Expr::parse(expr).unwrap().explain(tags).to_string()
+    }
+
+    #[test]
+    fn explains_matches() {
+        let expr = Expr::parse("work & !meeting").unwrap();
+        let explanation = expr.explain(&["work", "meeting"]);
+        assert!(!explanation.matched);
+        match &explanation.node {
+            Explained::Binary(BinaryOp::And, work, not_meeting) => {
+                assert!(work.matched);
+                assert!(!not_meeting.matched);
+                assert_eq!(
+                    not_meeting.node,
+                    Explained::Not(Box::new(Explanation {
+                        matched: true,
+                        node: Explained::Tag("meeting"),
+                        span: Some(8..15),
+                    }))
+                );
+            }
+            node => panic!("{:?}", node),
+        }
+        assert_eq!(
+            explanation.reasons(),
+            [Reason {
+                tag: "meeting",
+                present: true
+            }]
+        );
+        assert_eq!(Expr::parse("").unwrap().explain(&[]), Explanation::EMPTY);
+    }
+
+    #[test]
+    fn gives_deciding_tags() {
+        assert_eq!(
+            explain("work & !meeting", &["work"]),
+            "matched because `work` present and `meeting` absent"
+        );
+        assert_eq!(
+            explain("work & !meeting", &[]),
+            "didn't match because `work` absent"
+        );
+        assert_eq!(
+            explain("a | b | c", &["b", "c"]),
+            "matched because `b` present"
+        );
+        assert_eq!(
+            explain("a | !(b & c)", &["b", "c"]),
+            "didn't match because `a` absent and `b` present and `c` present"
+        );
+        assert_eq!(explain("a | a", &[]), "didn't match because `a` absent");
+        assert_eq!(explain("", &[]), "matched, since the expression is empty");
+    }
+
+    #[test]
+    fn highlights_deciding_terms() {
+        fn highlights(expr: &str, tags: &[&str]) -> Vec<(&'static str, Range<usize>)> {
+            let expr = Expr::parse(expr).unwrap();
+            let highlights = expr.explain(tags).highlights();
+            let color = |matched| if matched { "green" } else { "red" };
+            highlights
+                .into_iter()
+                .map(|highlight| (color(highlight.matched), highlight.span))
+                .collect()
+        }
+
+        assert_eq!(
+            highlights("work & !meeting", &["work"]),
+            [("green", 0..4), ("green", 7..15)]
+        );
+        assert_eq!(
+            highlights("work & !meeting", &["work", "meeting"]),
+            [("red", 7..15)]
+        );
+        assert_eq!(
+            highlights("(a | b) & !(c | café)", &["b", "café"]),
+            [("green", 16..21)]
+        );
+        assert_eq!(
+            highlights("!a & b", &["b"]),
+            [("green", 0..2), ("green", 5..6)]
+        );
+        assert_eq!(highlights("a | a", &[]), [("red", 0..1), ("red", 4..5)]);
+        assert_eq!(highlights("", &[]), []);
+
+        // only parsed expressions have spans
+        let built = crate::bool::tag("a").build().unwrap();
+        assert_eq!(built.explain(&["a"]).highlights(), []);
+        assert_eq!(built.explain(&["a"]).reasons().len(), 1);
+    }
+}
@@ -0,0 +1,211 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+use super::comment::CommentMatcher;
+use super::{EvalContext, Expr, ExprData, ParseError, ParseErrorKind};
+
+/// A predicate that host apps add to expressions, like `weather(rainy)` or `location(office)`,
+/// for things tags don't record. It's called with the text between the brackets (without
+/// surrounding spaces) and the ping's [`EvalContext`] each time a ping is matched. Closures
+/// taking the same arguments are functions too.
+pub trait ExprFunction: Send + Sync {
+    fn call(&self, arg: &str, ping: &EvalContext<'_>) -> bool;
+}
+
+impl<F> ExprFunction for F
+where
+    F: Fn(&str, &EvalContext<'_>) -> bool + Send + Sync,
+{
+    fn call(&self, arg: &str, ping: &EvalContext<'_>) -> bool {
+        self(arg, ping)
+    }
+}
+
+/// The [`ExprFunction`]s expressions can call, by name.
+///
+/// ```
+/// use taglogic::bool::{EvalContext, Expr, Functions};
+///
+/// let mut functions = Functions::new();
+/// functions.register("weather", |arg: &str, ping: &EvalContext<'_>| {
+///     arg == "rainy" && ping.timestamp.is_some_and(|time| time < 1000)
+/// });
+/// let expr = Expr::parse("walk & !weather(rainy)").unwrap();
+/// assert!(!expr.matches_with(&EvalContext::new(&["walk"]).with_timestamp(0), &functions));
+/// assert!(expr.matches_with(&EvalContext::new(&["walk"]).with_timestamp(5000), &functions));
+/// assert_eq!(functions.unknown(&Expr::parse("mood(good)").unwrap()), ["mood"]);
+/// ```
+#[derive(Default)]
+pub struct Functions {
+    functions: BTreeMap<String, Box<dyn ExprFunction>>,
+}
+
+impl Functions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a function, replacing any with the same name.
+    pub fn register(&mut self, name: impl Into<String>, function: impl ExprFunction + 'static) {
+        self.functions.insert(name.into(), Box::new(function));
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    /// The names of every function.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().map(String::as_str)
+    }
+
+    /// Calls a function, or gives None if there isn't one called `name`.
+    pub fn call(&self, name: &str, arg: &str, ping: &EvalContext<'_>) -> Option<bool> {
+        Some(self.functions.get(name)?.call(arg, ping))
+    }
+
+    /// Functions `expr` calls that aren't registered, sorted and without duplicates, so typos can
+    /// be caught before matching (where they never match).
+    pub fn unknown<'e>(&self, expr: &'e Expr) -> Vec<&'e str> {
+        let mut unknown: Vec<&str> = (expr.calls().into_iter())
+            .map(|(name, _)| name)
+            .filter(|name| !self.contains(name))
+            .collect();
+        unknown.dedup();
+        unknown
+    }
+}
+
+impl fmt::Debug for Functions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.names()).finish()
+    }
+}
+
+/// The function and argument of a name like `weather(rainy)`, or None if it's not a call.
+pub(super) fn split_call(name: &str) -> Option<(&str, &str)> {
+    let (function, arg) = name.strip_suffix(')')?.split_once('(')?;
+    Some((function, arg.trim())).filter(|(function, _)| is_function_name(function))
+}
+
+/// Function names are a letter followed by letters, digits, `_` and `-`.
+fn is_function_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(char::is_alphabetic)
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// The span of a call whose name is `s[start..open]` and whose bracket is at `open`, up to and
+/// including the closing bracket, or None if the name can't be a function's. The argument can have
+/// anything but a closing bracket in it.
+pub(super) fn lex_call(
+    s: &str,
+    start: usize,
+    open: usize,
+) -> Option<Result<Range<usize>, ParseError>> {
+    if !is_function_name(&s[start..open]) {
+        return None;
+    }
+    Some(match s[open..].find(')') {
+        Some(close) => Ok(start..open + close + 1),
+        None => Err(ParseError {
+            kind: ParseErrorKind::UnterminatedCall,
+            span: start..s.len(),
+        }),
+    })
+}
+
+impl Expr {
+    /// Whether the expression matches a ping, with calls like `weather(rainy)` made to
+    /// `functions`, and [comment searches](Self::matches_ping) searching the ping's comment.
+    /// Calls to functions that aren't registered never match (see [`Functions::unknown`]).
+    pub fn matches_with(&self, ping: &EvalContext<'_>, functions: &Functions) -> bool {
+        let call = |name: &str| {
+            let (function, arg) = split_call(name)?;
+            Some(functions.call(function, arg, ping).unwrap_or(false))
+        };
+        CommentMatcher::new(self).matches_by(ping.tags, ping.comment, &call)
+    }
+
+    /// Every function call in the expression, as its function and argument, sorted and without
+    /// duplicates.
+    pub fn calls(&self) -> Vec<(&str, &str)> {
+        let mut calls: Vec<(&str, &str)> = match &self.0 {
+            ExprData::Empty => Vec::new(),
+            ExprData::HasNodes(ast) => ast.names().filter_map(split_call).collect(),
+        };
+        calls.sort_unstable();
+        calls.dedup();
+        calls
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn parses_calls() {
+        let expr = Expr::parse("!location( home office ) & (work | mood(😀)) & a-b(c").unwrap_err();
+        assert_eq!(expr.kind, ParseErrorKind::UnterminatedCall);
+        assert_eq!(expr.span, 49..54);
+
+        let expr = Expr::parse("(work | mood(😀)) & !location( home office )").unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "(work | mood(😀)) & !location( home office )"
+        );
+        assert_eq!(expr.calls(), [("location", "home office"), ("mood", "😀")]);
+        assert_eq!(expr.tags(), ["work"]);
+
+        // brackets after something that can't be a function are still brackets
+        assert_eq!(
+            Expr::from_string("1a(b)"),
+            Err(ParseErrorKind::InvalidAfterName)
+        );
+    }
+
+    #[test]
+    fn calls_functions() {
+        let mut functions = Functions::new();
+        functions.register("has", |arg: &str, ping: &EvalContext<'_>| {
+            ping.comment.is_some_and(|comment| comment.contains(arg))
+        });
+        let expr = Expr::parse(r#"has(x) | a & comment:"y" | missing()"#).unwrap();
+        let ping = |tags, comment| EvalContext::new(tags).with_comment(comment);
+        assert!(expr.matches_with(&ping(&[], Some("x")), &functions));
+        assert!(expr.matches_with(&ping(&["a"], Some("y")), &functions));
+        assert!(!expr.matches_with(&ping(&["a"], None), &functions));
+        assert_eq!(functions.unknown(&expr), ["missing"]);
+    }
+
+    #[test]
+    fn unknown_calls_never_match() {
+        let tags = ["weather(rainy)", "work"];
+        let context = EvalContext::new(&tags);
+        for (expr, expected) in [
+            ("weather(rainy)", false),
+            ("!weather(rainy)", true),
+            ("work & weather(rainy)", false),
+            ("work | weather(rainy)", true),
+        ] {
+            let expr = Expr::parse(expr).unwrap();
+            assert_eq!(expr.matches(&tags), expected, "{}", expr);
+            assert_eq!(expr.compile().matches(&tags), expected, "{}", expr);
+            assert_eq!(
+                expr.matches_with(&context, &Functions::new()),
+                expected,
+                "{}",
+                expr
+            );
+            assert_eq!(expr.matches_ping(&tags, None), expected, "{}", expr);
+            assert_eq!(expr.matches_slow(&tags), expected, "{}", expr);
+            assert_eq!(expr.explain(&tags).matched, expected, "{}", expr);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use super::{Ast, AstNode, BinaryOp, Expr, ExprData, NodeId};
+
+/// Parts with more tags than this aren't checked for always matching or being redundant, since
+/// every combination of their tags is tried.
+const LINT_TAGS: usize = 12;
+
+/// A kind of problem found by [`lint`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LintRule {
+    /// A part that matches every ping, like `a | !a`.
+    Tautology,
+    /// A part that matches no pings, like `a & !a`.
+    Contradiction,
+    /// A term that can be removed without changing what matches, like the second `a` in
+    /// `a & b & a`, or `a | b` in `a & (a | b)`.
+    Redundant,
+    /// A tag that isn't in [`LintOptions::known_tags`], which is likely a typo.
+    UnknownTag,
+    /// Operators nested deeper than [`LintOptions::max_depth`], which is hard to read.
+    DeepNesting,
+}
+
+impl LintRule {
+    pub const ALL: [Self; 5] = [
+        Self::Tautology,
+        Self::Contradiction,
+        Self::Redundant,
+        Self::UnknownTag,
+        Self::DeepNesting,
+    ];
+
+    /// The rule's id, which stays the same between versions, for picking quick-fixes.
+    pub fn id(self) -> &'static str {
+        match self {
+            Self::Tautology => "tautology",
+            Self::Contradiction => "contradiction",
+            Self::Redundant => "redundant-term",
+            Self::UnknownTag => "unknown-tag",
+            Self::DeepNesting => "deep-nesting",
+        }
+    }
+}
+
+/// A problem with part of an expression, from [`lint`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintWarning {
+    pub rule: LintRule,
+    /// The byte range of the part in the text the expression was parsed from, or None if it was
+    /// built some other way.
+    pub span: Option<Range<usize>>,
+    pub message: String,
+}
+
+/// Which rules [`lint_with`] checks, and how.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LintOptions<'a> {
+    pub rules: &'a [LintRule],
+    /// Every tag the user has, like the log's tags. [`LintRule::UnknownTag`] isn't checked
+    /// without them.
+    pub known_tags: Option<&'a [&'a str]>,
+    /// How many levels of `&` inside `|` (or the other way around) are fine.
+    pub max_depth: usize,
+}
+
+impl Default for LintOptions<'_> {
+    /// Every rule, without known tags, and up to 3 levels of nesting.
+    fn default() -> Self {
+        Self {
+            rules: &LintRule::ALL,
+            known_tags: None,
+            max_depth: 3,
+        }
+    }
+}
+
+/// Problems with `expr` that don't stop it parsing, but probably aren't what the user meant, with
+/// the [default options](LintOptions::default).
+///
+/// ```
+/// use taglogic::bool::{lint, Expr, LintRule};
+///
+/// let expr = Expr::parse("work & (meeting | !meeting)").unwrap();
+/// let warnings = lint(&expr);
+/// assert_eq!(warnings[0].rule, LintRule::Tautology);
+/// assert_eq!(warnings[0].span, Some(8..26));
+/// ```
+pub fn lint(expr: &Expr) -> Vec<LintWarning> {
+    lint_with(expr, &LintOptions::default())
+}
+
+/// Like [`lint`], checking the rules in `options`. Warnings are in the order their parts are
+/// written.
+pub fn lint_with(expr: &Expr, options: &LintOptions<'_>) -> Vec<LintWarning> {
+    let ast = match &expr.0 {
+        ExprData::Empty => return vec![],
+        ExprData::HasNodes(ast) => ast,
+    };
+    let mut linter = Linter {
+        ast,
+        options,
+        warnings: Vec::new(),
+    };
+    let root = ast.root();
+    linter.check(root);
+    if let Some(known) = options.known_tags {
+        linter.check_tags(known);
+    }
+    let depth = linter.depth(root);
+    if linter.enabled(LintRule::DeepNesting) && depth > options.max_depth {
+        let message = format!(
+            "nested {} levels deep, more than {}",
+            depth, options.max_depth
+        );
+        linter.warn(LintRule::DeepNesting, root, message);
+    }
+    let mut warnings = linter.warnings;
+    warnings.sort_by_key(|warning| warning.span.as_ref().map(|span| span.start));
+    warnings
+}
+
+struct Linter<'a> {
+    ast: &'a Ast,
+    options: &'a LintOptions<'a>,
+    warnings: Vec<LintWarning>,
+}
+
+impl Linter<'_> {
+    fn enabled(&self, rule: LintRule) -> bool {
+        self.options.rules.contains(&rule)
+    }
+
+    fn warn(&mut self, rule: LintRule, id: NodeId, message: String) {
+        self.warnings.push(LintWarning {
+            rule,
+            span: self.ast.span(id),
+            message,
+        });
+    }
+
+    /// Checks the part at `id` and the parts inside it. Parts inside one that always or never
+    /// matches aren't checked, since the whole part should go.
+    fn check(&mut self, id: NodeId) {
+        let ast = self.ast;
+        let operands = match ast.node(id) {
+            AstNode::Name(..) => return,
+            AstNode::Invert(inverted) => return self.check(inverted),
+            AstNode::Binary(op, ..) => {
+                let mut operands = Vec::new();
+                chain(ast, id, op, &mut operands);
+                operands
+            }
+        };
+        let tags = tags(ast, id);
+        let constant = if tags.len() <= LINT_TAGS {
+            self.constant(id, &tags)
+        } else {
+            None
+        };
+        match constant {
+            Some(true) => {
+                let message = format!("`{}` always matches", ast.display(id));
+                return self.warn(LintRule::Tautology, id, message);
+            }
+            Some(false) => {
+                let message = format!("`{}` never matches", ast.display(id));
+                return self.warn(LintRule::Contradiction, id, message);
+            }
+            None if tags.len() <= LINT_TAGS && self.enabled(LintRule::Redundant) => {
+                self.check_redundant(id, &operands, &tags)
+            }
+            None => {}
+        }
+        for operand in operands {
+            self.check(operand);
+        }
+    }
+
+    /// Whether the part at `id` with `tags` always or never matches, if that's being warned
+    /// about.
+    fn constant(&self, id: NodeId, tags: &[&str]) -> Option<bool> {
+        let ast = self.ast;
+        let results = assignments(tags).map(|present| ast.matches(id, &present));
+        let (always, never) = results.fold((true, true), |(always, never), matched| {
+            (always && matched, never && !matched)
+        });
+        if always && self.enabled(LintRule::Tautology) {
+            Some(true)
+        } else if never && self.enabled(LintRule::Contradiction) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Warns about each operand of the chain at `id` that doesn't change what it matches, once
+    /// the ones before it are removed (so only one of `a & a` is redundant). Operands that always
+    /// or never match are left to be warned about on their own.
+    fn check_redundant(&mut self, id: NodeId, operands: &[NodeId], tags: &[&str]) {
+        let ast = self.ast;
+        let op = match ast.node(id) {
+            AstNode::Binary(op, ..) => op,
+            _ => return,
+        };
+        let matches = |kept: &[NodeId], present: &[&str]| match op {
+            BinaryOp::And => kept.iter().all(|&operand| ast.matches(operand, present)),
+            BinaryOp::Or => kept.iter().any(|&operand| ast.matches(operand, present)),
+        };
+        let mut kept = operands.to_vec();
+        for &operand in operands.iter().rev() {
+            if self.constant(operand, &self::tags(ast, operand)).is_some() {
+                continue;
+            }
+            let without: Vec<NodeId> = kept.iter().copied().filter(|&o| o != operand).collect();
+            if assignments(tags)
+                .all(|present| matches(&kept, &present) == matches(&without, &present))
+            {
+                let message = format!("`{}` doesn't change what matches", ast.display(operand));
+                self.warn(LintRule::Redundant, operand, message);
+                kept = without;
+            }
+        }
+    }
+
+    fn check_tags(&mut self, known: &[&str]) {
+        if !self.enabled(LintRule::UnknownTag) {
+            return;
+        }
+        let ast = self.ast;
+        for (id, node) in ast.nodes.iter().enumerate() {
+            if let AstNode::Name(start, end) = *node {
+                let tag = ast.name(start, end);
+                let special = super::comment::is_comment_term(tag)
+                    || super::function::split_call(tag).is_some();
+                if !known.contains(&tag) && !special {
+                    let message = format!("no pings are tagged `{}`", tag);
+                    self.warn(LintRule::UnknownTag, id as NodeId, message);
+                }
+            }
+        }
+    }
+
+    /// Levels of operators at `id`, where a chain of the same operator is one level.
+    fn depth(&self, id: NodeId) -> usize {
+        let ast = self.ast;
+        match ast.node(id) {
+            AstNode::Name(..) => 0,
+            AstNode::Invert(inverted) => self.depth(inverted),
+            AstNode::Binary(op, ..) => {
+                let mut operands = Vec::new();
+                chain(ast, id, op, &mut operands);
+                let deepest = operands.into_iter().map(|operand| self.depth(operand));
+                1 + deepest.max().unwrap_or(0)
+            }
+        }
+    }
+}
+
+/// The operands of the chain of `op`s at `id`, like `a`, `b` and `c` in `a & (b & c)`.
+fn chain(ast: &Ast, id: NodeId, op: BinaryOp, operands: &mut Vec<NodeId>) {
+    match ast.node(id) {
+        AstNode::Binary(node_op, a1, a2) if node_op == op => {
+            chain(ast, a1, op, operands);
+            chain(ast, a2, op, operands);
+        }
+        _ => operands.push(id),
+    }
+}
+
+/// The tags in the part at `id`, without duplicates.
+fn tags(ast: &Ast, id: NodeId) -> Vec<&str> {
+    let mut tags = Vec::new();
+    let mut stack = vec![id];
+    while let Some(id) = stack.pop() {
+        match ast.node(id) {
+            AstNode::Name(start, end) => {
+                let tag = ast.name(start, end);
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+            AstNode::Invert(inverted) => stack.push(inverted),
+            AstNode::Binary(_, a1, a2) => stack.extend([a1, a2]),
+        }
+    }
+    tags
+}
+
+/// Every subset of `tags`.
+fn assignments<'a>(tags: &'a [&'a str]) -> impl Iterator<Item = Vec<&'a str>> + 'a {
+    (0..1u32 << tags.len()).map(move |bits| {
+        let present = tags.iter().enumerate();
+        present
+            .filter(|(i, _)| bits & 1 << i != 0)
+            .map(|(_, &tag)| tag)
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bool::{not, tag};
+
+    fn rules(expr: &str) -> Vec<(&'static str, String)> {
+        let expr = Expr::parse(expr).unwrap();
+        let known = ["a", "b", "c", "d"];
+        let options = LintOptions {
+            known_tags: Some(&known),
+            ..LintOptions::default()
+        };
+        let warnings = lint_with(&expr, &options);
+        let rule = |warning: LintWarning| (warning.rule.id(), warning.message);
+        warnings.into_iter().map(rule).collect()
+    }
+
+    #[test]
+    fn finds_tautologies_and_contradictions() {
+        assert_eq!(
+            rules("a | !a"),
+            [("tautology", "`a | !a` always matches".into())]
+        );
+        assert_eq!(
+            rules("b & (a & !a)"),
+            [("contradiction", "`b & a & !a` never matches".into())]
+        );
+        assert_eq!(
+            rules("b | (a & !a)"),
+            [("contradiction", "`a & !a` never matches".into())]
+        );
+        assert!(rules("a & !b").is_empty());
+        assert!(rules("").is_empty());
+    }
+
+    #[test]
+    fn finds_redundant_terms() {
+        assert_eq!(
+            rules("a & b & a"),
+            [("redundant-term", "`a` doesn't change what matches".into())]
+        );
+        let warnings = lint(&Expr::parse("a & (a | b)").unwrap());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].rule, LintRule::Redundant);
+        assert_eq!(warnings[0].span, Some(5..10));
+        assert!(rules("a & (b | c)").is_empty());
+    }
+
+    #[test]
+    fn finds_unknown_tags_and_nesting() {
+        assert_eq!(
+            rules("a & wrk"),
+            [("unknown-tag", "no pings are tagged `wrk`".into())]
+        );
+        let deep = "a & (b | (c & (d | !(a & b))))";
+        assert_eq!(
+            rules(deep),
+            [("deep-nesting", "nested 5 levels deep, more than 3".into())]
+        );
+        assert!(rules("a & (b | (c & d))").is_empty());
+    }
+
+    #[test]
+    fn uses_options() {
+        let expr = Expr::parse("x & x & !x").unwrap();
+        let options = LintOptions {
+            rules: &[LintRule::Redundant, LintRule::UnknownTag],
+            known_tags: None,
+            max_depth: 0,
+        };
+        // the contradiction isn't reported, so the rest is checked
+        let warnings = lint_with(&expr, &options);
+        let found: Vec<LintRule> = warnings.iter().map(|warning| warning.rule).collect();
+        assert_eq!(found, [LintRule::Redundant]);
+
+        // built expressions have no spans
+        let built = tag("a").and(not(tag("a"))).build().unwrap();
+        assert_eq!(lint(&built)[0].span, None);
+    }
+}
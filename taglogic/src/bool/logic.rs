@@ -0,0 +1,167 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{Ast, AstNode, BinaryOp, Expr, ExprData, NodeId};
+
+impl Expr {
+    /// Whether every ping this matches is matched by `other` too, like `a & b` implies `a`.
+    ///
+    /// ```
+    /// use taglogic::bool::Expr;
+    ///
+    /// let meetings = Expr::parse("work & meeting").unwrap();
+    /// assert!(meetings.implies(&Expr::parse("work").unwrap()));
+    /// assert!(!meetings.implies(&Expr::parse("work & !meeting").unwrap()));
+    /// ```
+    pub fn implies(&self, other: &Expr) -> bool {
+        !exists(&[(self, true), (other, false)])
+    }
+
+    /// Whether any ping could match, so filters like `a & !a` can be turned down when they're
+    /// saved.
+    ///
+    /// ```
+    /// use taglogic::bool::Expr;
+    ///
+    /// assert!(Expr::parse("work & !meeting").unwrap().is_satisfiable());
+    /// assert!(!Expr::parse("work & (meeting & !work)").unwrap().is_satisfiable());
+    /// ```
+    pub fn is_satisfiable(&self) -> bool {
+        exists(&[(self, true)])
+    }
+
+    /// Whether a ping could match both this and `other`, like two goals counting the same time.
+    pub fn overlaps(&self, other: &Expr) -> bool {
+        exists(&[(self, true), (other, true)])
+    }
+
+    /// Whether this matches exactly the same pings as `other`.
+    pub(crate) fn is_equivalent(&self, other: &Expr) -> bool {
+        self.implies(other) && other.implies(self)
+    }
+
+    /// Whether every ping matches.
+    pub(crate) fn is_valid(&self) -> bool {
+        !exists(&[(self, false)])
+    }
+}
+
+/// An expression with the number of the tag at each name node, into the tags of every
+/// expression being searched.
+struct Numbered<'a> {
+    ast: Option<&'a Ast>,
+    tags: Vec<usize>,
+    want: bool,
+}
+
+/// Whether some set of tags makes each expression match or not as wanted. Tags are tried present
+/// and then absent one at a time, backing out as soon as an expression is decided the wrong way,
+/// which is quick for expressions of the size people write.
+fn exists(wanted: &[(&Expr, bool)]) -> bool {
+    let mut names: Vec<&str> = Vec::new();
+    let numbered: Vec<Numbered> = (wanted.iter())
+        .map(|&(expr, want)| {
+            let ast = match &expr.0 {
+                ExprData::Empty => None,
+                ExprData::HasNodes(ast) => Some(ast),
+            };
+            let nodes = ast.map_or(&[][..], |ast| &ast.nodes);
+            let tags = (nodes.iter())
+                .map(|node| match (node, ast) {
+                    (&AstNode::Name(start, end), Some(ast)) => {
+                        let name = ast.name(start, end);
+                        names
+                            .iter()
+                            .position(|&other| other == name)
+                            .unwrap_or_else(|| {
+                                names.push(name);
+                                names.len() - 1
+                            })
+                    }
+                    _ => usize::MAX,
+                })
+                .collect();
+            Numbered { ast, tags, want }
+        })
+        .collect();
+    search(&numbered, &mut vec![None; names.len()], 0)
+}
+
+fn search(numbered: &[Numbered], values: &mut [Option<bool>], next: usize) -> bool {
+    let mut decided = true;
+    for expr in numbered {
+        let value = match expr.ast {
+            Some(ast) => eval(ast, ast.root(), &expr.tags, values),
+            None => Some(true),
+        };
+        match value {
+            Some(value) if value != expr.want => return false,
+            Some(_) => {}
+            None => decided = false,
+        }
+    }
+    if decided || next == values.len() {
+        return decided;
+    }
+    for value in [true, false] {
+        values[next] = Some(value);
+        if search(numbered, values, next + 1) {
+            return true;
+        }
+    }
+    values[next] = None;
+    false
+}
+
+/// Whether the part at `id` matches with the tags in `values`, or None if it depends on tags
+/// that aren't set yet.
+fn eval(ast: &Ast, id: NodeId, tags: &[usize], values: &[Option<bool>]) -> Option<bool> {
+    match ast.node(id) {
+        AstNode::Name(..) => values[tags[usize::from(id)]],
+        AstNode::Invert(inverted) => eval(ast, inverted, tags, values).map(|value| !value),
+        AstNode::Binary(op, a1, a2) => {
+            // the value that decides the operator on its own
+            let short = op == BinaryOp::Or;
+            let left = eval(ast, a1, tags, values);
+            if left == Some(short) {
+                return left;
+            }
+            let right = eval(ast, a2, tags, values);
+            match (left, right) {
+                (_, Some(right)) if right == short => Some(short),
+                (Some(_), right) => right,
+                (None, _) => None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expr(s: &str) -> Expr {
+        Expr::parse(s).unwrap()
+    }
+
+    #[test]
+    fn compares_expressions() {
+        assert!(expr("a & b").implies(&expr("a")));
+        assert!(!expr("a").implies(&expr("a & b")));
+        assert!(expr("a").implies(&expr("")));
+        assert!(!expr("").implies(&expr("a")));
+        assert!(expr("x & !x").implies(&expr("a")));
+        assert!(expr("!(a | b)").is_equivalent(&expr("!a & !b")));
+        assert!(expr("a & (b | c)").is_equivalent(&expr("a & b | a & c")));
+        assert!(!expr("a | b").is_equivalent(&expr("a & b")));
+        assert!(expr("a & !b").is_satisfiable());
+        assert!(!expr("a & (b & !a)").is_satisfiable());
+        assert!(expr("").is_satisfiable());
+        assert!(expr("a | !a").is_valid());
+        assert!(expr("").is_valid());
+        assert!(!expr("a | !b").is_valid());
+        assert!(expr("a | b").overlaps(&expr("b & c")));
+        assert!(!expr("a & !b").overlaps(&expr("b")));
+        assert!(!expr("a & !a").overlaps(&expr("")));
+    }
+}
@@ -0,0 +1,263 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+
+use super::{BinaryOp, CompiledExpr, Op, NO_SLOT, TRUTH_TABLE_TAGS};
+
+/// The tree a program was compiled from, rebuilt from its jumps.
+enum Step {
+    /// The empty program, which matches everything.
+    True,
+    Tag(u16),
+    False,
+    Not(Box<Step>),
+    /// The right operand is only run if the left one doesn't decide the result.
+    Binary(BinaryOp, Box<Step>, Box<Step>),
+}
+
+impl Step {
+    /// The tree of `program`, which is a sequence of parts joined by jumps over right operands.
+    fn parse(program: &[Op]) -> Step {
+        let mut step = Step::True;
+        let mut next = 0;
+        while let Some(&op) = program.get(next) {
+            next += 1;
+            step = match op {
+                Op::Tag(slot) => Step::Tag(slot),
+                Op::False => Step::False,
+                Op::Not => Step::Not(Box::new(step)),
+                Op::JumpIfFalse(skip) | Op::JumpIfTrue(skip) => {
+                    let end = (next + usize::from(skip)).min(program.len());
+                    let right = Step::parse(&program[next..end]);
+                    next = end;
+                    let op = match op {
+                        Op::JumpIfFalse(_) => BinaryOp::And,
+                        _ => BinaryOp::Or,
+                    };
+                    Step::Binary(op, Box::new(step), Box::new(right))
+                }
+            };
+        }
+        step
+    }
+
+    /// The operands of a chain of the same operator, like `a`, `b` and `c` of `a & (b & c)`.
+    fn chain(&self, op: BinaryOp) -> Vec<&Step> {
+        let mut operands = Vec::new();
+        let mut step = self;
+        while let Step::Binary(next, left, right) = step {
+            if *next != op {
+                break;
+            }
+            operands.extend(left.chain(op));
+            step = right;
+        }
+        operands.push(step);
+        operands
+    }
+
+    /// The chance of matching a ping and the expected number of tags checked, if tags appear
+    /// independently with chances from `frequency`.
+    fn chance_and_cost(&self, frequency: &dyn Fn(u16) -> f64) -> (f64, f64) {
+        match self {
+            Step::Tag(slot) => (frequency(*slot).clamp(0.0, 1.0), 1.0),
+            Step::True => (1.0, 0.0),
+            Step::False => (0.0, 0.0),
+            Step::Not(inverted) => {
+                let (chance, cost) = inverted.chance_and_cost(frequency);
+                (1.0 - chance, cost)
+            }
+            Step::Binary(op, left, right) => {
+                let (chance1, cost1) = left.chance_and_cost(frequency);
+                let (chance2, cost2) = right.chance_and_cost(frequency);
+                match op {
+                    BinaryOp::And => (chance1 * chance2, cost1 + chance1 * cost2),
+                    BinaryOp::Or => (
+                        1.0 - (1.0 - chance1) * (1.0 - chance2),
+                        cost1 + (1.0 - chance1) * cost2,
+                    ),
+                }
+            }
+        }
+    }
+}
+
+impl CompiledExpr {
+    /// Describes how the expression is matched, like `EXPLAIN` for a database query: whether it
+    /// uses a truth table or runs the program, how tags are found, what matching on bitsets does,
+    /// and the order tags are checked in, with where checking stops early.
+    pub fn explain_plan(&self) -> String {
+        self.plan(None)
+    }
+
+    /// Like [`explain_plan`](Self::explain_plan), but with the estimated fraction of pings
+    /// matching each part, from `frequency`, the fraction of pings with each tag (like
+    /// `PingLog::tag_frequency`), and the number of tags checked per ping.
+    pub fn explain_plan_with_frequency(&self, frequency: &dyn Fn(&str) -> f64) -> String {
+        self.plan(Some(&|slot| frequency(&self.names[usize::from(slot)])))
+    }
+
+    fn plan(&self, frequency: Option<&dyn Fn(u16) -> f64>) -> String {
+        let mut out = String::new();
+        let tags = self.names.len();
+        if self.truth_table.is_empty() {
+            let _ = writeln!(
+                out,
+                "runs the program of {} instructions for each ping, since it has more than {} tags",
+                self.program.len(),
+                TRUTH_TABLE_TAGS
+            );
+        } else {
+            let _ = writeln!(
+                out,
+                "looks up each ping in a truth table of {} entries, for its {} tags",
+                1usize << tags,
+                tags
+            );
+        }
+        if self.ids.is_empty() {
+            out += "finds tags by name, skipping ones of lengths no tag in it has\n";
+        } else {
+            let known = self.ids.iter().filter(|&&slot| slot != NO_SLOT).count();
+            let _ = writeln!(
+                out,
+                "finds tags by id, in a table of {} tags with {} of its {}",
+                self.ids.len(),
+                known,
+                tags
+            );
+        }
+        let combines = self
+            .program
+            .iter()
+            .filter(|op| matches!(op, Op::JumpIfFalse(_) | Op::JumpIfTrue(_)))
+            .count();
+        let simd = if cfg!(all(target_arch = "wasm32", target_feature = "simd128")) {
+            ", with SIMD"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            out,
+            "on bitsets, runs the program once, combining whole bitsets {} times{}",
+            combines, simd
+        );
+        let tree = Step::parse(&self.program);
+        if let Some(frequency) = frequency {
+            let (chance, cost) = tree.chance_and_cost(frequency);
+            let _ = writeln!(
+                out,
+                "estimated to match {:.1}% of pings, checking {:.2} tags each",
+                chance * 100.0,
+                cost
+            );
+        }
+        out += "checks, in order:\n";
+        self.write_step(&mut out, &tree, 1, frequency);
+        out
+    }
+
+    fn write_step(
+        &self,
+        out: &mut String,
+        step: &Step,
+        depth: usize,
+        frequency: Option<&dyn Fn(u16) -> f64>,
+    ) {
+        let _ = write!(out, "{:1$}", "", depth * 2);
+        let _ = match step {
+            Step::Tag(slot) => write!(out, "tag {}", self.names[usize::from(*slot)]),
+            Step::True => write!(out, "true"),
+            Step::False => write!(out, "false"),
+            Step::Not(inverted) => match **inverted {
+                Step::Tag(slot) => write!(out, "not tag {}", self.names[usize::from(slot)]),
+                _ => write!(out, "not"),
+            },
+            Step::Binary(BinaryOp::And, _, _) => write!(out, "all of, stopping at the first false"),
+            Step::Binary(BinaryOp::Or, _, _) => write!(out, "any of, stopping at the first true"),
+        };
+        if let Some(frequency) = frequency {
+            let (chance, _) = step.chance_and_cost(frequency);
+            let _ = write!(out, " (~{:.1}%)", chance * 100.0);
+        }
+        out.push('\n');
+        match step {
+            Step::Not(inverted) if !matches!(**inverted, Step::Tag(_)) => {
+                self.write_step(out, inverted, depth + 1, frequency)
+            }
+            Step::Binary(op, _, _) => {
+                for operand in step.chain(*op) {
+                    self.write_step(out, operand, depth + 1, frequency);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bool::Expr;
+    use crate::limits::Limits;
+    use alloc::format;
+    use alloc::string::String;
+
+    #[test]
+    fn explains_plans() {
+        let compiled = Expr::parse("work & (!(meeting | email)) & !lunch")
+            .unwrap()
+            .compile();
+        assert_eq!(
+            compiled.explain_plan(),
+            "looks up each ping in a truth table of 16 entries, for its 4 tags\n\
+             finds tags by name, skipping ones of lengths no tag in it has\n\
+             on bitsets, runs the program once, combining whole bitsets 3 times\n\
+             checks, in order:\n  \
+               all of, stopping at the first false\n    \
+                 tag work\n    \
+                 not\n      \
+                   any of, stopping at the first true\n        \
+                     tag meeting\n        \
+                     tag email\n    \
+                 not tag lunch\n"
+        );
+
+        let frequency = |tag: &str| if tag == "work" { 0.5 } else { 0.1 };
+        let plan = compiled.explain_plan_with_frequency(&frequency);
+        // 0.5 * 0.9 * 0.9 * 0.9, always checking work, then meeting half of the time, and email
+        // and lunch when the ones before them don't decide it
+        assert!(plan.contains("estimated to match 36.5% of pings, checking 2.35 tags each\n"));
+        assert!(plan.contains("  all of, stopping at the first false (~36.5%)\n"));
+        assert!(plan.contains("        tag email (~10.0%)\n"));
+    }
+
+    #[test]
+    fn explains_big_and_indexed_plans() {
+        let tags: Vec<String> = (0..11).map(|tag| format!("t{}", tag)).collect();
+        let limits = Limits {
+            expr_depth: 100,
+            ..Limits::DEFAULT
+        };
+        let expr = Expr::parse_with_limits(&tags.join(" | "), &limits).unwrap();
+        let plan = expr.compile().explain_plan();
+        assert!(plan.starts_with(
+            "runs the program of 21 instructions for each ping, since it has more than 10 tags\n"
+        ));
+        // a chain is one list, however it's grouped
+        assert!(plan.contains("  any of, stopping at the first true\n    tag t0\n"));
+        assert!(plan.ends_with("    tag t10\n"));
+
+        let compiled = Expr::parse("a & b")
+            .unwrap()
+            .compile_with_table(&["b", "x", "y"]);
+        assert!(compiled
+            .explain_plan()
+            .contains("finds tags by id, in a table of 3 tags with 1 of its 2\n"));
+        assert!(Expr::parse("")
+            .unwrap()
+            .compile()
+            .explain_plan()
+            .ends_with("checks, in order:\n  true\n"));
+    }
+}
@@ -0,0 +1,289 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use super::builder::{tag, BuildError, ExprBuilder};
+use super::Expr;
+
+/// Why [`synthesize`] couldn't find an expression.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum SynthError {
+    #[error("no pings to match")]
+    NoMatching,
+    /// A ping that should match has the same tags as one that shouldn't, so no expression can
+    /// tell them apart.
+    #[error("a ping to match has the same tags as one not to match")]
+    Inconsistent,
+    /// Separating the pings needs more terms or tags per term than allowed.
+    #[error("no expression within the limits separates the pings")]
+    TooComplex,
+    #[error(transparent)]
+    Build(#[from] BuildError),
+}
+
+/// How big an expression [`synthesize`] can make.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SynthLimits {
+    /// The most `|`ed terms.
+    pub terms: usize,
+    /// The most tags (or inverted tags) `&`ed together in each term.
+    pub tags_per_term: usize,
+}
+
+impl Default for SynthLimits {
+    /// Up to 4 terms of up to 4 tags each.
+    fn default() -> Self {
+        Self {
+            terms: 4,
+            tags_per_term: 4,
+        }
+    }
+}
+
+/// How many partly built terms [`synthesize`] keeps adding to.
+const BEAM: usize = 16;
+
+/// A tag, or an inverted one if `present` is false.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Literal {
+    tag: usize,
+    present: bool,
+}
+
+/// A ping's tags, as sorted indices into the tags of every example.
+type Example = Vec<usize>;
+
+impl Literal {
+    fn matches(self, example: &Example) -> bool {
+        example.binary_search(&self.tag).is_ok() == self.present
+    }
+}
+
+/// A small expression that matches every ping in `matching` and none in `not_matching`, given as
+/// each ping's tags, for users who'd rather point at pings than learn the syntax. It's an `|` of
+/// terms that each `&` a few tags or inverted tags, each picked to cover as many of the pings to
+/// match that are left as it can, and then pruned of any tags and terms that aren't needed.
+///
+/// ```
+/// use taglogic::bool::{synthesize, SynthLimits};
+///
+/// let matching = [vec!["work", "email"], vec!["work", "code"]];
+/// let not_matching = [vec!["work", "meeting"], vec!["email"]];
+/// let expr = synthesize(&matching, &not_matching, &SynthLimits::default()).unwrap();
+/// assert_eq!(expr.to_string(), "work & !meeting");
+/// ```
+///
+/// If there are no pings not to match, the result is the empty expression, which matches
+/// everything.
+pub fn synthesize<P, T>(
+    matching: &[P],
+    not_matching: &[P],
+    limits: &SynthLimits,
+) -> Result<Expr, SynthError>
+where
+    P: AsRef<[T]>,
+    T: AsRef<str>,
+{
+    if matching.is_empty() {
+        return Err(SynthError::NoMatching);
+    }
+    if not_matching.is_empty() {
+        return Ok(Expr::EMPTY);
+    }
+    let mut tags: Vec<&str> = (matching.iter().chain(not_matching))
+        .flat_map(|ping| ping.as_ref().iter().map(AsRef::as_ref))
+        .collect();
+    tags.sort_unstable();
+    tags.dedup();
+    let example = |ping: &P| -> Example {
+        let mut ids: Example = (ping.as_ref().iter())
+            .filter_map(|tag| tags.binary_search(&tag.as_ref()).ok())
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    };
+    let positive: Vec<Example> = matching.iter().map(example).collect();
+    let negative: Vec<Example> = not_matching.iter().map(example).collect();
+    if positive.iter().any(|example| negative.contains(example)) {
+        return Err(SynthError::Inconsistent);
+    }
+
+    let mut terms: Vec<Vec<Literal>> = Vec::new();
+    let mut uncovered: Vec<&Example> = positive.iter().collect();
+    while !uncovered.is_empty() {
+        if terms.len() == limits.terms {
+            return Err(SynthError::TooComplex);
+        }
+        let term = grow_term(tags.len(), &uncovered, &negative, limits.tags_per_term)
+            .ok_or(SynthError::TooComplex)?;
+        uncovered.retain(|example| !term.iter().all(|literal| literal.matches(example)));
+        terms.push(term);
+    }
+    prune(&mut terms, &positive, &negative);
+
+    let build = |term: &Vec<Literal>| {
+        let literal = |literal: &Literal| {
+            let name = tag(tags[literal.tag]);
+            if literal.present {
+                name
+            } else {
+                !name
+            }
+        };
+        right_fold(term.iter().map(literal), ExprBuilder::and)
+    };
+    let expr = right_fold(terms.iter().filter_map(build), ExprBuilder::or);
+    Ok(expr.map_or(Ok(Expr::EMPTY), ExprBuilder::build)?)
+}
+
+/// A term matching at least one of `positive` and none of `negative`. It's found with a beam
+/// search, keeping the [`BEAM`] terms of each length that match the most pings to match (and then
+/// the fewest not to) to add to, and picking the shortest of the terms that match the most.
+fn grow_term(
+    tags: usize,
+    positive: &[&Example],
+    negative: &[Example],
+    max_len: usize,
+) -> Option<Vec<Literal>> {
+    let matches = |term: &[Literal], example: &Example| term.iter().all(|l| l.matches(example));
+    let count = |term: &[Literal]| {
+        let pos = positive.iter().filter(|example| matches(term, example));
+        let neg = negative.iter().filter(|example| matches(term, example));
+        (pos.count(), neg.count())
+    };
+    let mut best: Option<(usize, Vec<Literal>)> = None;
+    let mut beam: Vec<Vec<Literal>> = vec![Vec::new()];
+    for _ in 0..max_len {
+        let mut next: Vec<(usize, usize, Vec<Literal>)> = Vec::new();
+        for term in &beam {
+            for tag in 0..tags {
+                if term.iter().any(|literal| literal.tag == tag) {
+                    continue;
+                }
+                for present in [true, false] {
+                    let mut longer = term.clone();
+                    longer.push(Literal { tag, present });
+                    // the same literals in any order are the same term
+                    longer.sort_unstable_by_key(|literal| (!literal.present, literal.tag));
+                    let (pos, neg) = count(&longer);
+                    if pos == 0 || next.iter().any(|(.., other)| *other == longer) {
+                        continue;
+                    }
+                    if neg > 0 {
+                        next.push((pos, neg, longer));
+                    } else if best.as_ref().is_none_or(|(best, _)| pos > *best) {
+                        best = Some((pos, longer));
+                    }
+                }
+            }
+        }
+        next.sort_by_key(|&(pos, neg, _)| (Reverse(pos), neg));
+        next.truncate(BEAM);
+        beam = next.into_iter().map(|(.., term)| term).collect();
+    }
+    best.map(|(_, term)| term)
+}
+
+/// Removes literals that don't stop any term matching a ping not to match, and then terms that
+/// only match pings other terms do.
+fn prune(terms: &mut Vec<Vec<Literal>>, positive: &[Example], negative: &[Example]) {
+    let matches = |term: &[Literal], example: &Example| term.iter().all(|l| l.matches(example));
+    for term in terms.iter_mut() {
+        let mut index = 0;
+        while index < term.len() {
+            let mut without = term.clone();
+            without.remove(index);
+            if negative.iter().any(|example| matches(&without, example)) {
+                index += 1;
+            } else {
+                *term = without;
+            }
+        }
+    }
+    let mut index = 0;
+    while index < terms.len() {
+        let others = |example: &Example| {
+            (terms.iter().enumerate()).any(|(i, term)| i != index && matches(term, example))
+        };
+        if terms.len() > 1 && positive.iter().all(others) {
+            terms.remove(index);
+        } else {
+            index += 1;
+        }
+    }
+}
+
+/// Joins `builders` with `op`, grouping to the right like parsing does.
+fn right_fold(
+    builders: impl DoubleEndedIterator<Item = ExprBuilder>,
+    op: fn(ExprBuilder, ExprBuilder) -> ExprBuilder,
+) -> Option<ExprBuilder> {
+    builders.rev().reduce(|right, left| op(left, right))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn synth(matching: &[&str], not_matching: &[&str]) -> Result<Expr, SynthError> {
+        fn split<'a>(pings: &[&'a str]) -> Vec<Vec<&'a str>> {
+            pings.iter().map(|tags| tags.split(' ').collect()).collect()
+        }
+        let limits = SynthLimits::default();
+        synthesize(&split(matching), &split(not_matching), &limits)
+    }
+
+    #[test]
+    fn separates_examples() {
+        let matching = ["work code", "work email", "call mom"];
+        let not_matching = ["work meeting", "email", "call boss", "sleep"];
+        let expr = synth(&matching, &not_matching).unwrap();
+        for tags in matching {
+            assert!(
+                expr.matches(&tags.split(' ').collect::<Vec<_>>()),
+                "{}",
+                expr
+            );
+        }
+        for tags in not_matching {
+            assert!(
+                !expr.matches(&tags.split(' ').collect::<Vec<_>>()),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(expr.to_string(), "(work & !meeting) | mom");
+
+        assert_eq!(synth(&["a"], &["b"]).unwrap().to_string(), "a");
+        assert_eq!(synth(&["a b", "a c"], &["a"]).unwrap().to_string(), "b | c");
+        assert_eq!(synth(&["a"], &[]).unwrap(), Expr::EMPTY);
+    }
+
+    #[test]
+    fn fails_when_it_cant() {
+        assert_eq!(synth(&[], &["a"]), Err(SynthError::NoMatching));
+        assert_eq!(synth(&["a b"], &["b a"]), Err(SynthError::Inconsistent));
+
+        // a xor b needs two terms
+        let limits = SynthLimits {
+            terms: 1,
+            tags_per_term: 4,
+        };
+        let result = synthesize(
+            &[vec!["a"], vec!["b"]],
+            &[vec!["a", "b"], vec!["c"]],
+            &limits,
+        );
+        assert_eq!(result, Err(SynthError::TooComplex));
+        let limits = SynthLimits { terms: 2, ..limits };
+        let result = synthesize(
+            &[vec!["a"], vec!["b"]],
+            &[vec!["a", "b"], vec!["c"]],
+            &limits,
+        );
+        assert_eq!(result.unwrap().to_string(), "(!a & !c) | a & !b");
+    }
+}
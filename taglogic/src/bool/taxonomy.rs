@@ -0,0 +1,312 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::{Ast, AstNode, BinaryOp, BuildError, Expr, ExprData, NodeId};
+
+/// How a user's tags relate: tags that imply others (like `code` implying `work`, making `work`
+/// its parent), groups of tags a ping can only have one of, and tags that can't go with any
+/// others, like `asleep`.
+///
+/// ```
+/// use taglogic::bool::{Expr, Taxonomy};
+///
+/// let mut taxonomy = Taxonomy::new();
+/// taxonomy.imply("code", "work").alone("asleep");
+/// let work = taxonomy.expand(&Expr::parse("work").unwrap()).unwrap();
+/// assert!(work.matches(&["code"]));
+/// assert_eq!(
+///     taxonomy.conflicts(&["code", "asleep"])[0].to_string(),
+///     "`code` and `asleep` can't go together"
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Taxonomy {
+    /// The tags each tag implies directly.
+    implies: BTreeMap<String, BTreeSet<String>>,
+    /// Groups of tags that a ping can have at most one of.
+    exclusive: Vec<BTreeSet<String>>,
+    /// Tags that a ping can't have any others with, except ones they imply.
+    alone: BTreeSet<String>,
+}
+
+/// Two tags of a ping that the [`Taxonomy`] says can't go together, from
+/// [`Taxonomy::conflicts`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conflict<'a> {
+    /// The tags of the ping.
+    pub tags: [&'a str; 2],
+    /// The tags that can't go together, which are the tags of the ping or tags they imply.
+    pub because: [&'a str; 2],
+}
+
+impl Taxonomy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares that pings with `tag` are also `implied`, so `implied` is a parent of `tag`.
+    /// Implications chain, so if `rust` implies `code` and `code` implies `work`, `rust` implies
+    /// `work`.
+    pub fn imply(&mut self, tag: &str, implied: &str) -> &mut Self {
+        let implied_tags = self.implies.entry(tag.to_string()).or_default();
+        implied_tags.insert(implied.to_string());
+        self
+    }
+
+    /// Declares that a ping can have at most one of `tags`, or of tags that imply them.
+    pub fn exclusive<T: AsRef<str>>(&mut self, tags: &[T]) -> &mut Self {
+        let group = tags.iter().map(|tag| tag.as_ref().to_string()).collect();
+        self.exclusive.push(group);
+        self
+    }
+
+    /// Declares that a ping with `tag` can't have any other tags, except ones `tag` implies and
+    /// ones that imply it.
+    pub fn alone(&mut self, tag: &str) -> &mut Self {
+        self.alone.insert(tag.to_string());
+        self
+    }
+
+    /// `tag` and every tag it implies, sorted.
+    pub fn implied<'a>(&'a self, tag: &'a str) -> Vec<&'a str> {
+        let mut found = BTreeSet::new();
+        let mut next = Vec::from([tag]);
+        while let Some(tag) = next.pop() {
+            if found.insert(tag) {
+                let implied = self.implies.get(tag).into_iter().flatten();
+                next.extend(implied.map(String::as_str));
+            }
+        }
+        found.into_iter().collect()
+    }
+
+    /// Every tag that implies `tag`, sorted and not including `tag` itself.
+    pub fn implying(&self, tag: &str) -> Vec<&str> {
+        let mut found: BTreeSet<&str> = BTreeSet::new();
+        let mut next = Vec::from([tag]);
+        while let Some(implied) = next.pop() {
+            for (tag, implies) in &self.implies {
+                if implies.contains(implied) && found.insert(tag) {
+                    next.push(tag);
+                }
+            }
+        }
+        found.remove(tag);
+        found.into_iter().collect()
+    }
+
+    /// `tags` with every tag they imply, sorted and without duplicates.
+    pub fn with_implied<'a>(&'a self, tags: &[&'a str]) -> Vec<&'a str> {
+        let mut all: Vec<&str> = tags.iter().flat_map(|tag| self.implied(tag)).collect();
+        all.sort_unstable();
+        all.dedup();
+        all
+    }
+
+    /// Returns if an expression matches a set of tags and the tags they imply.
+    pub fn matches(&self, expr: &Expr, tags: &[&str]) -> bool {
+        expr.matches(&self.with_implied(tags))
+    }
+
+    /// The expression with each tag replaced with it or any tag that implies it, so it matches
+    /// pings that only have the tags that imply it, like `work` matching a ping with just `code`.
+    /// Expand an expression before matching a log or passing it to stats, so they use the
+    /// taxonomy too. It's an error if the result would be too big.
+    pub fn expand(&self, expr: &Expr) -> Result<Expr, BuildError> {
+        match &expr.0 {
+            ExprData::Empty => Ok(Expr::EMPTY),
+            ExprData::HasNodes(ast) => {
+                let mut expanded = Ast::with_capacity(ast.nodes.len(), ast.names.len());
+                self.expand_node(ast, ast.root(), &mut expanded)?;
+                Ok(Expr(ExprData::HasNodes(expanded)))
+            }
+        }
+    }
+
+    /// Copies the subtree at `id` into `out`, with its tags expanded.
+    fn expand_node(&self, ast: &Ast, id: NodeId, out: &mut Ast) -> Result<NodeId, BuildError> {
+        let node = match ast.node(id) {
+            AstNode::Name(start, end) => {
+                let tag = ast.name(start, end);
+                let mut tags = Vec::from([tag]);
+                tags.extend(self.implying(tag));
+                let len: usize = tags.iter().map(|tag| tag.len()).sum();
+                if out.names.len() + len > usize::from(u16::MAX)
+                    || out.nodes.len() + 2 * tags.len() > usize::from(u16::MAX)
+                {
+                    return Err(BuildError::TooBig);
+                }
+                // a chain of `|`, grouped to the right like parsing groups it
+                let mut ids: Vec<NodeId> = tags.iter().map(|tag| out.push_name(tag)).collect();
+                let mut chain = ids.pop().unwrap_or_default();
+                while let Some(tag) = ids.pop() {
+                    chain = out.push(AstNode::Binary(BinaryOp::Or, tag, chain));
+                }
+                return Ok(chain);
+            }
+            AstNode::Invert(inverted) => AstNode::Invert(self.expand_node(ast, inverted, out)?),
+            AstNode::Binary(op, a1, a2) => {
+                let a1 = self.expand_node(ast, a1, out)?;
+                AstNode::Binary(op, a1, self.expand_node(ast, a2, out)?)
+            }
+        };
+        Ok(out.push(node))
+    }
+
+    /// Pairs of `tags` that can't go together on one ping, for flagging when a ping is answered.
+    pub fn conflicts<'a>(&'a self, tags: &[&'a str]) -> Vec<Conflict<'a>> {
+        let implied: Vec<Vec<&str>> = tags.iter().map(|tag| self.implied(tag)).collect();
+        let mut conflicts = Vec::new();
+        for (i, (&tag1, implied1)) in tags.iter().zip(&implied).enumerate() {
+            for (&tag2, implied2) in tags.iter().zip(&implied).skip(i + 1) {
+                if let Some(because) = self.conflict(tag1, implied1, tag2, implied2) {
+                    conflicts.push(Conflict {
+                        tags: [tag1, tag2],
+                        because,
+                    });
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// What makes two tags that imply `implied1` and `implied2` conflict, if anything does.
+    fn conflict<'a>(
+        &'a self,
+        tag1: &'a str,
+        implied1: &[&'a str],
+        tag2: &'a str,
+        implied2: &[&'a str],
+    ) -> Option<[&'a str; 2]> {
+        for group in &self.exclusive {
+            let in_group = |implied: &[&'a str]| {
+                let mut in_group = implied.iter().copied().filter(|tag| group.contains(*tag));
+                in_group.next()
+            };
+            if let (Some(a), Some(b)) = (in_group(implied1), in_group(implied2)) {
+                if a != b && !implied1.contains(&b) && !implied2.contains(&a) {
+                    return Some([a, b]);
+                }
+            }
+        }
+        // a tag that goes alone only goes with tags it implies, or that are a kind of it
+        let alone = |implied: &[&'a str], other: &'a str, other_implied: &[&'a str]| {
+            let tag = implied
+                .iter()
+                .copied()
+                .find(|tag| self.alone.contains(*tag))?;
+            let allowed = implied.contains(&other) || other_implied.contains(&tag);
+            (!allowed).then_some(tag)
+        };
+        if let Some(tag) = alone(implied1, tag2, implied2) {
+            return Some([tag, tag2]);
+        }
+        alone(implied2, tag1, implied1).map(|tag| [tag1, tag])
+    }
+}
+
+impl fmt::Display for Conflict<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a, b] = self.tags;
+        write!(f, "`{}` and `{}` can't go together", a, b)?;
+        if self.because != self.tags {
+            let [a, b] = self.because;
+            write!(f, ", since `{}` and `{}` can't", a, b)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn taxonomy() -> Taxonomy {
+        let mut taxonomy = Taxonomy::new();
+        taxonomy
+            .imply("rust", "code")
+            .imply("code", "work")
+            .imply("meeting", "work")
+            .imply("nap", "asleep")
+            .exclusive(&["home", "office", "commute"])
+            .alone("asleep");
+        taxonomy
+    }
+
+    fn expand(expr: &str) -> String {
+        let expr = Expr::parse(expr).unwrap();
+        taxonomy().expand(&expr).unwrap().to_string()
+    }
+
+    fn conflicts(tags: &[&str]) -> Vec<String> {
+        let taxonomy = taxonomy();
+        let conflicts = taxonomy.conflicts(tags);
+        conflicts.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn follows_implications() {
+        let taxonomy = taxonomy();
+        assert_eq!(taxonomy.implied("rust"), ["code", "rust", "work"]);
+        assert_eq!(taxonomy.implied("email"), ["email"]);
+        assert_eq!(taxonomy.implying("work"), ["code", "meeting", "rust"]);
+        assert!(taxonomy.implying("rust").is_empty());
+        assert_eq!(
+            taxonomy.with_implied(&["rust", "home"]),
+            ["code", "home", "rust", "work"]
+        );
+        let work = Expr::parse("work & !meeting").unwrap();
+        assert!(taxonomy.matches(&work, &["rust"]));
+        assert!(!taxonomy.matches(&work, &["meeting"]));
+
+        // cycles just make tags imply each other
+        let mut cycle = Taxonomy::new();
+        cycle.imply("a", "b").imply("b", "a");
+        assert_eq!(cycle.implied("a"), ["a", "b"]);
+        assert_eq!(cycle.implying("a"), ["b"]);
+    }
+
+    #[test]
+    fn expands_expressions() {
+        assert_eq!(expand("work"), "work | code | meeting | rust");
+        assert_eq!(expand("code & !meeting"), "(code | rust) & !meeting");
+        assert_eq!(expand("email"), "email");
+        assert_eq!(expand(""), "");
+
+        let expanded = taxonomy()
+            .expand(&Expr::parse("!work & home").unwrap())
+            .unwrap();
+        assert!(expanded.matches(&["home"]));
+        assert!(!expanded.matches(&["home", "rust"]));
+        // the expansion is the same as matching with implied tags
+        for tags in [&["rust"][..], &["meeting", "home"], &["home"], &[]] {
+            let expr = Expr::parse("(work | nap) & !commute").unwrap();
+            let taxonomy = taxonomy();
+            let expanded = taxonomy.expand(&expr).unwrap();
+            assert_eq!(expanded.matches(tags), taxonomy.matches(&expr, tags));
+        }
+    }
+
+    #[test]
+    fn finds_conflicts() {
+        assert_eq!(
+            conflicts(&["home", "office"]),
+            ["`home` and `office` can't go together"]
+        );
+        assert_eq!(
+            conflicts(&["code", "asleep"]),
+            ["`code` and `asleep` can't go together"]
+        );
+        assert_eq!(
+            conflicts(&["rust", "nap"]),
+            ["`rust` and `nap` can't go together, since `rust` and `asleep` can't"]
+        );
+        // a kind of asleep, and tags asleep implies, can go with it
+        assert!(conflicts(&["nap", "asleep"]).is_empty());
+        assert!(conflicts(&["rust", "work", "home"]).is_empty());
+        assert_eq!(conflicts(&["home", "office", "commute"]).len(), 3);
+    }
+}
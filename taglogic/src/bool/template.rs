@@ -0,0 +1,233 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+
+use super::{lex, Ast, AstNode, Expr, ExprData, NodeId, ParseError, Token};
+
+/// Why a [`Template`] couldn't be parsed or bound.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum TemplateError {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    /// A tag with a `{` that isn't closed, a `}` that wasn't opened, or `{}`.
+    #[error("bad placeholder in `{0}`")]
+    BadPlaceholder(String),
+    /// A placeholder with no value in the variables it was bound with.
+    #[error("no value for `{{{0}}}`")]
+    Unbound(String),
+    /// A value that doesn't make a tag, like one with a space or `&` in it.
+    #[error("`{0}` isn't a valid tag")]
+    InvalidTag(String),
+    /// The bound expression has more than 65535 bytes of tags.
+    #[error("expression too big")]
+    TooBig,
+}
+
+/// An expression with placeholders in its tags, like `work & project:{current}`, which are
+/// filled in when it's bound to variables. A saved query can use one to follow whatever the
+/// current project is.
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use taglogic::bool::Template;
+///
+/// let template = Template::parse("work & project:{current}").unwrap();
+/// assert_eq!(template.variables(), ["current"]);
+/// let mut vars = BTreeMap::new();
+/// vars.insert("current", "ttw");
+/// assert_eq!(template.bind(&vars).unwrap().to_string(), "work & project:ttw");
+/// assert!(template.matches(&["work", "project:ttw"], &vars).unwrap());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    /// The expression with the placeholders left in its tags.
+    expr: Expr,
+}
+
+impl Template {
+    /// Parses a template, which is an expression whose tags can have `{name}` in them.
+    pub fn parse(s: &str) -> Result<Self, TemplateError> {
+        let expr = Expr::parse(s)?;
+        for tag in expr.tags() {
+            placeholders(tag)?;
+        }
+        Ok(Self { expr })
+    }
+
+    /// The names of the placeholders, sorted and without duplicates.
+    pub fn variables(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = (self.expr.tags().into_iter())
+            .flat_map(|tag| placeholders(tag).unwrap_or_default())
+            .map(|(_, name)| name)
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// The expression with each placeholder replaced with its value in `vars`.
+    pub fn bind<K, V>(&self, vars: &BTreeMap<K, V>) -> Result<Expr, TemplateError>
+    where
+        K: Borrow<str> + Ord,
+        V: AsRef<str>,
+    {
+        match &self.expr.0 {
+            ExprData::Empty => Ok(Expr::EMPTY),
+            ExprData::HasNodes(ast) => {
+                let mut bound = Ast::with_capacity(ast.nodes.len(), ast.names.len());
+                let value = |name: &str| vars.get(name).map(AsRef::as_ref);
+                bind(ast, ast.root(), &value, &mut bound)?;
+                Ok(Expr(ExprData::HasNodes(bound)))
+            }
+        }
+    }
+
+    /// Returns if the expression bound to `vars` matches a set of tags.
+    pub fn matches<K, V>(&self, tags: &[&str], vars: &BTreeMap<K, V>) -> Result<bool, TemplateError>
+    where
+        K: Borrow<str> + Ord,
+        V: AsRef<str>,
+    {
+        Ok(self.bind(vars)?.matches(tags))
+    }
+}
+
+/// Copies the subtree at `id` into `out`, filling in the placeholders in its tags.
+fn bind<'a>(
+    ast: &Ast,
+    id: NodeId,
+    value: &dyn Fn(&str) -> Option<&'a str>,
+    out: &mut Ast,
+) -> Result<NodeId, TemplateError> {
+    let node = match ast.node(id) {
+        AstNode::Name(start, end) => {
+            let template = ast.name(start, end);
+            let mut tag = String::new();
+            let mut rest = 0;
+            for (span, name) in placeholders(template)? {
+                let value = value(name).ok_or_else(|| TemplateError::Unbound(name.to_string()))?;
+                tag.push_str(&template[rest..span.start]);
+                tag.push_str(value);
+                rest = span.end;
+            }
+            tag.push_str(&template[rest..]);
+            // the tag has to lex as itself, so values can't add operators to the expression
+            match lex(&tag, usize::MAX).as_deref() {
+                Ok([(Token::Name { text }, _)]) if *text == tag => {}
+                _ => return Err(TemplateError::InvalidTag(tag)),
+            }
+            if out.names.len() + tag.len() > usize::from(u16::MAX) {
+                return Err(TemplateError::TooBig);
+            }
+            return Ok(out.push_name(&tag));
+        }
+        AstNode::Invert(inverted) => AstNode::Invert(bind(ast, inverted, value, out)?),
+        AstNode::Binary(op, a1, a2) => {
+            let a1 = bind(ast, a1, value, out)?;
+            AstNode::Binary(op, a1, bind(ast, a2, value, out)?)
+        }
+    };
+    Ok(out.push(node))
+}
+
+/// The byte range and name of each `{name}` in `tag`.
+fn placeholders(tag: &str) -> Result<Vec<(core::ops::Range<usize>, &str)>, TemplateError> {
+    let bad = || TemplateError::BadPlaceholder(tag.to_string());
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(open) = tag[from..].find(['{', '}']).map(|i| from + i) {
+        if tag[open..].starts_with('}') {
+            return Err(bad());
+        }
+        let close = tag[open..].find('}').map(|i| open + i).ok_or_else(bad)?;
+        let name = &tag[open + 1..close];
+        if name.is_empty() || name.contains('{') {
+            return Err(bad());
+        }
+        found.push((open..close + 1, name));
+        from = close + 1;
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    fn vars(pairs: &[(&'static str, &'static str)]) -> BTreeMap<&'static str, &'static str> {
+        pairs.iter().copied().collect()
+    }
+
+    fn bind(template: &str, pairs: &[(&'static str, &'static str)]) -> String {
+        match Template::parse(template).and_then(|template| template.bind(&vars(pairs))) {
+            Ok(expr) => expr.to_string(),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn binds_placeholders() {
+        let current = [("current", "ttw")];
+        assert_eq!(
+            bind("work & project:{current}", &current),
+            "work & project:ttw"
+        );
+        assert_eq!(
+            bind("{current} | !({current}-review)", &current),
+            "ttw | !ttw-review"
+        );
+        assert_eq!(bind("{a}{b}:x", &[("a", "p"), ("b", "q")]), "pq:x");
+        assert_eq!(bind("work", &[]), "work");
+        assert_eq!(bind("", &[]), "");
+
+        // the saved query follows the current project
+        let template = Template::parse("work & project:{current}").unwrap();
+        let tags = ["work", "project:b"];
+        assert!(!template.matches(&tags, &vars(&[("current", "a")])).unwrap());
+        assert!(template.matches(&tags, &vars(&[("current", "b")])).unwrap());
+
+        let mut owned = BTreeMap::new();
+        owned.insert(String::from("current"), String::from("b"));
+        assert!(template.matches(&tags, &owned).unwrap());
+    }
+
+    #[test]
+    fn lists_variables() {
+        let template = Template::parse("{b}:{a} & !{a} | c").unwrap();
+        assert_eq!(template.variables(), vec!["a", "b"]);
+        assert!(Template::parse("a").unwrap().variables().is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_placeholders_and_values() {
+        for bad in ["a{", "a}", "{}", "{a{b}}", "x & {a}}"] {
+            assert!(
+                matches!(Template::parse(bad), Err(TemplateError::BadPlaceholder(_))),
+                "{}",
+                bad
+            );
+        }
+        assert!(matches!(
+            Template::parse("a &"),
+            Err(TemplateError::Parse(_))
+        ));
+        assert_eq!(bind("project:{current}", &[]), "no value for `{current}`");
+        // values can't change the expression around them
+        assert_eq!(
+            bind("project:{current}", &[("current", "a | b")]),
+            "`project:a | b` isn't a valid tag"
+        );
+        assert_eq!(
+            bind("{current}", &[("current", "and")]),
+            "`and` isn't a valid tag"
+        );
+        assert_eq!(
+            bind("{current}", &[("current", "")]),
+            "`` isn't a valid tag"
+        );
+    }
+}
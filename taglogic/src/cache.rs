@@ -0,0 +1,148 @@
+//! Remembering which pings match queries, so running the same queries again (like the dashboard's
+//! saved queries, every time it's shown) only matches the pings added since.
+//!
+//! Results are kept per expression and are only reused for the log they came from, while it has
+//! the same [generation](PingLog::generation). Since appending pings keeps the generation, only
+//! the new pings are matched after an append. Any other edit means matching the whole log again.
+
+use std::collections::HashMap;
+
+use crate::bool::Expr;
+use crate::log::{matches_each, PingLog};
+
+/// Cached matches for up to `capacity` expressions. Expressions are cached by their canonical
+/// form, so ones written differently but parsing the same share results.
+#[derive(Debug, Clone)]
+pub struct QueryCache {
+    capacity: usize,
+    entries: HashMap<String, Entry>,
+    /// Counts up with every lookup, to find the least recently used entry.
+    clock: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    generation: u64,
+    /// Whether each of the first `matches.len()` pings of the log matched.
+    matches: Vec<bool>,
+    last_used: u64,
+}
+
+impl QueryCache {
+    /// A cache for up to `capacity` expressions. When it's full, the least recently used
+    /// expression is forgotten.
+    ///
+    /// ## Panics
+    /// Panics if `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be at least 1");
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// Whether each ping in `log` matches `expr`, oldest to newest.
+    pub fn matches(&mut self, log: &PingLog, expr: &Expr) -> &[bool] {
+        self.clock += 1;
+        let key = expr.to_string();
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            self.evict();
+        }
+        let clock = self.clock;
+        let entry = self.entries.entry(key).or_insert_with(|| Entry {
+            generation: log.generation(),
+            matches: Vec::new(),
+            last_used: clock,
+        });
+        entry.last_used = clock;
+        if entry.generation != log.generation() || entry.matches.len() > log.len() {
+            entry.generation = log.generation();
+            entry.matches.clear();
+        }
+        let (_, new) = log.pings().split_at(entry.matches.len());
+        if !new.is_empty() {
+            entry.matches.extend(matches_each(&new, expr));
+        }
+        &entry.matches
+    }
+
+    fn evict(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = oldest {
+            self.entries.remove(&key);
+        }
+    }
+
+    /// Number of expressions with cached results.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+
+    fn ping(time: u64, tags: &str) -> Ping {
+        Ping::new(
+            time,
+            tags.split_whitespace().map(String::from).collect(),
+            2700,
+        )
+    }
+
+    fn expr(s: &str) -> Expr {
+        Expr::parse(s).unwrap()
+    }
+
+    #[test]
+    fn matches_new_pings() {
+        let mut cache = QueryCache::new(10);
+        let mut log = PingLog::from_pings(vec![ping(10, "a"), ping(20, "b")]);
+        assert_eq!(cache.matches(&log, &expr("a")), &[true, false]);
+
+        log.push(ping(30, "a b"));
+        assert_eq!(cache.matches(&log, &expr("a")), &[true, false, true]);
+        // an edit that isn't an append
+        log.push(ping(5, "a"));
+        assert_eq!(
+            cache.matches(&log, &expr("(a)")),
+            &[true, true, false, true]
+        );
+        assert_eq!(cache.len(), 1);
+
+        // a different log with the same number of pings
+        let other = PingLog::from_pings(vec![ping(1, "b"); 4]);
+        assert_eq!(cache.matches(&other, &expr("a")), &[false; 4]);
+    }
+
+    #[test]
+    fn forgets_least_recently_used() {
+        let mut cache = QueryCache::new(2);
+        let log = PingLog::from_pings(vec![ping(10, "a")]);
+        cache.matches(&log, &expr("a"));
+        cache.matches(&log, &expr("b"));
+        cache.matches(&log, &expr("a"));
+        cache.matches(&log, &expr("c"));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.entries.contains_key("a"));
+        assert!(!cache.entries.contains_key("b"));
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
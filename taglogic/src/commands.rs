@@ -0,0 +1,648 @@
+//! A single JSON entry point for every major operation, for hosts (like web workers) that would
+//! rather send messages than call bindings.
+//!
+//! A request looks like `{"command": "parse/v1", "args": {"expr": "a & b"}}`. The response is
+//! either `{"ok": true, "result": ...}` or `{"ok": false, "error": {"kind": ..., "message": ...}}`.
+//! Command names end with a version, which is bumped (keeping the old one working) whenever a
+//! command's arguments or result change in a way that isn't backwards compatible.
+
+use chrono::{FixedOffset, Weekday};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use crate::bool::Expr;
+use crate::diagnostics::Diagnostic;
+use crate::import::{self, ImportError, Normalizer};
+use crate::limits::{check_json_depth, Limits};
+use crate::log::{matches_each, Ping, PingLog};
+use crate::nudge::{NudgeEngine, Rule, Threshold};
+use crate::stats::{self, Bucket, Metric, ReportSpec};
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", content = "args")]
+enum Request {
+    /// Parses an expression, returning its canonical form and tags, and warnings about tags that
+    /// look like another one in it or in `tags` (like the tags in the user's log).
+    #[serde(rename = "parse/v1")]
+    Parse {
+        expr: String,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+    /// Times of the pings in a range that match an expression.
+    #[serde(rename = "query/v1")]
+    Query {
+        expr: String,
+        pings: Vec<Ping>,
+        #[serde(flatten)]
+        range: TimeRange,
+    },
+    /// A stats report, in the format of [`stats::report`].
+    #[serde(rename = "stats/v1", rename_all = "camelCase")]
+    Stats {
+        expr: String,
+        pings: Vec<Ping>,
+        #[serde(flatten)]
+        range: TimeRange,
+        #[serde(default)]
+        utc_offset_mins: i32,
+        metrics: Vec<MetricArg>,
+    },
+    /// Reads pings from another app's format, cleaning up their tags with `normalize` if it's
+    /// given.
+    #[serde(rename = "import/v1")]
+    Import {
+        format: Format,
+        text: String,
+        interval: u32,
+        #[serde(default)]
+        normalize: Option<NormalizeArg>,
+    },
+    /// Writes pings in another app's format.
+    #[serde(rename = "export/v1")]
+    Export { format: Format, pings: Vec<Ping> },
+    /// Checks nudge rules at the latest ping. Hosts keep `lastNudged` from each result and send
+    /// it back with the next request, so rules don't nudge again too soon.
+    #[serde(rename = "nudge/v1", rename_all = "camelCase")]
+    Nudge {
+        rules: Vec<RuleArg>,
+        pings: Vec<Ping>,
+        #[serde(default)]
+        utc_offset_mins: i32,
+        #[serde(default)]
+        last_nudged: BTreeMap<String, u64>,
+    },
+}
+
+/// A range of ping times, defaulting to all of them.
+#[derive(Debug, Deserialize)]
+pub(crate) struct TimeRange {
+    #[serde(default)]
+    pub start: u64,
+    #[serde(default = "end_of_time")]
+    pub end: u64,
+}
+
+fn end_of_time() -> u64 {
+    u64::MAX
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum MetricArg {
+    Total,
+    Series(BucketArg),
+    #[serde(rename_all = "camelCase")]
+    Sessions {
+        max_gap: u64,
+    },
+    AnswerDelays,
+    Diversity,
+    Summary,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum BucketArg {
+    Day,
+    Week,
+    Month,
+}
+
+impl From<&MetricArg> for Metric {
+    fn from(metric: &MetricArg) -> Self {
+        match metric {
+            MetricArg::Total => Metric::Total,
+            MetricArg::Series(BucketArg::Day) => Metric::Series(Bucket::Day),
+            MetricArg::Series(BucketArg::Week) => Metric::Series(Bucket::Week),
+            MetricArg::Series(BucketArg::Month) => Metric::Series(Bucket::Month),
+            MetricArg::Sessions { max_gap } => Metric::Sessions { max_gap: *max_gap },
+            MetricArg::AnswerDelays => Metric::AnswerDelays,
+            MetricArg::Diversity => Metric::Diversity,
+            MetricArg::Summary => Metric::Summary,
+        }
+    }
+}
+
+/// The rules of a [`Normalizer`]. Replacements are `[pattern, replacement]` pairs, and need the
+/// `regex` feature. With `dryRun`, the pings are left alone and only the changes are returned.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct NormalizeArg {
+    lowercase: bool,
+    strip_punctuation: bool,
+    aliases: BTreeMap<String, String>,
+    replacements: Vec<(String, String)>,
+    dry_run: bool,
+}
+
+impl NormalizeArg {
+    fn normalizer(&self) -> Result<Normalizer, Value> {
+        let mut normalizer = Normalizer::new();
+        if self.lowercase {
+            normalizer = normalizer.lowercase();
+        }
+        if self.strip_punctuation {
+            normalizer = normalizer.strip_punctuation();
+        }
+        for (from, to) in &self.aliases {
+            normalizer = normalizer.alias(from, to);
+        }
+        #[cfg(feature = "regex")]
+        for (pattern, replacement) in &self.replacements {
+            normalizer = normalizer
+                .replace(pattern, replacement)
+                .map_err(|err| json!({ "kind": "invalidRequest", "message": err.to_string() }))?;
+        }
+        #[cfg(not(feature = "regex"))]
+        if !self.replacements.is_empty() {
+            return Err(json!({
+                "kind": "invalidRequest",
+                "message": "replacements need the regex feature",
+            }));
+        }
+        Ok(normalizer)
+    }
+}
+
+/// A [`Rule`], with exactly one of `fewerThan` and `moreThan`. `hours` is `[start, end]`, and
+/// `days` are names like `"mon"`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RuleArg {
+    name: String,
+    expr: String,
+    window: usize,
+    fewer_than: Option<usize>,
+    more_than: Option<usize>,
+    hours: Option<(u32, u32)>,
+    #[serde(default)]
+    days: Vec<String>,
+    #[serde(default)]
+    cooldown_secs: u64,
+}
+
+impl RuleArg {
+    fn rule(&self, limits: &Limits) -> Result<Rule, Value> {
+        let invalid = |message: String| json!({ "kind": "invalidRequest", "message": message });
+        let threshold = match (self.fewer_than, self.more_than) {
+            (Some(count), None) => Threshold::FewerThan(count),
+            (None, Some(count)) => Threshold::MoreThan(count),
+            _ => {
+                return Err(invalid(format!(
+                    "rule `{}` needs one of fewerThan and moreThan",
+                    self.name
+                )))
+            }
+        };
+        let days = (self.days.iter())
+            .map(|day| {
+                day.parse::<Weekday>()
+                    .map_err(|_| invalid(format!("unknown day `{}`", day)))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Rule {
+            hours: self.hours.map(|(start, end)| start..end),
+            days,
+            cooldown: self.cooldown_secs,
+            ..Rule::new(
+                &self.name,
+                parse(&self.expr, limits)?,
+                self.window,
+                threshold,
+            )
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    /// The original TagTime's `.log` files.
+    TagTime,
+    /// CSV exports from the TagTime Android app. These can only be imported.
+    #[serde(rename = "tagtimeAndroid")]
+    TagTimeAndroid,
+}
+
+/// Handles a request, returning the response. Never panics on bad input; invalid requests get an
+/// error response with the kind `invalidRequest`.
+pub fn dispatch(request: &str) -> String {
+    dispatch_with_limits(request, &Limits::DEFAULT)
+}
+
+/// Like [`dispatch`], but with the limits in `limits` instead of the default ones. Requests that
+/// are too big or nested too deeply get an `invalidRequest` error before they're parsed.
+pub fn dispatch_with_limits(request: &str, limits: &Limits) -> String {
+    let checked = if request.len() > limits.input_len {
+        Err("request too big")
+    } else {
+        check_json_depth(request, limits.nesting_depth)
+    };
+    let response = match checked.map(|()| serde_json::from_str(request)) {
+        Ok(Ok(request)) => run(request, limits),
+        Ok(Err(err)) => Err(json!({
+            "kind": "invalidRequest",
+            "message": err.to_string(),
+        })),
+        Err(message) => Err(json!({
+            "kind": "invalidRequest",
+            "message": message,
+        })),
+    };
+    match response {
+        Ok(result) => json!({ "ok": true, "result": result }),
+        Err(error) => json!({ "ok": false, "error": error }),
+    }
+    .to_string()
+}
+
+fn run(request: Request, limits: &Limits) -> Result<Value, Value> {
+    Ok(match request {
+        Request::Parse { expr, tags } => {
+            let parsed = parse(&expr, limits)?;
+            let warnings: Vec<Value> = parsed
+                .confusables(&tags)
+                .iter()
+                .map(|confusable| Diagnostic::from_confusable(confusable, &expr).to_json(&expr))
+                .collect();
+            json!({ "formatted": parsed.to_string(), "tags": parsed.tags(), "warnings": warnings })
+        }
+        Request::Query { expr, pings, range } => {
+            query(&PingLog::from_pings(pings), &expr, &range, limits)?
+        }
+        Request::Stats {
+            expr,
+            pings,
+            range,
+            utc_offset_mins,
+            metrics,
+        } => report(
+            &PingLog::from_pings(pings),
+            &expr,
+            &range,
+            utc_offset_mins,
+            &metrics,
+            limits,
+        )?,
+        Request::Import {
+            format,
+            text,
+            interval,
+            normalize,
+        } => {
+            let mut log = match format {
+                Format::TagTime => import::tagtime_log_with_limits(&text, interval, limits),
+                Format::TagTimeAndroid => import::android_csv_with_limits(&text, interval, limits),
+            }
+            .map_err(import_error)?;
+            match normalize {
+                Some(normalize) => {
+                    let normalizer = normalize.normalizer()?;
+                    let changes: Vec<Value> = (normalizer.dry_run(&log).into_iter())
+                        .map(|change| {
+                            json!({ "from": change.from, "to": change.to, "pings": change.pings })
+                        })
+                        .collect();
+                    if !normalize.dry_run {
+                        log = normalizer.apply(&log);
+                    }
+                    json!({ "pings": log.pings(), "changes": changes })
+                }
+                None => json!({ "pings": log.pings() }),
+            }
+        }
+        Request::Export { format, pings } => match format {
+            Format::TagTime => {
+                json!({ "text": import::write_tagtime_log(&PingLog::from_pings(pings)) })
+            }
+            Format::TagTimeAndroid => {
+                return Err(json!({
+                    "kind": "invalidRequest",
+                    "message": "tagtimeAndroid logs can only be imported",
+                }))
+            }
+        },
+        Request::Nudge {
+            rules,
+            pings,
+            utc_offset_mins,
+            last_nudged,
+        } => {
+            let tz = utc_offset(utc_offset_mins)?;
+            let rules = (rules.iter())
+                .map(|rule| rule.rule(limits))
+                .collect::<Result<_, _>>()?;
+            let mut engine = NudgeEngine::new(rules);
+            for (name, &time) in &last_nudged {
+                engine.set_last_nudged(name, time);
+            }
+            let nudges: Vec<Value> = (engine.check(&PingLog::from_pings(pings), &tz).iter())
+                .map(|nudge| {
+                    json!({
+                        "rule": nudge.rule,
+                        "time": nudge.time,
+                        "matching": nudge.matching,
+                        "window": nudge.window,
+                    })
+                })
+                .collect();
+            let last_nudged: BTreeMap<&str, u64> = (engine.rules().iter())
+                .filter_map(|rule| Some((rule.name.as_str(), engine.last_nudged(&rule.name)?)))
+                .collect();
+            json!({ "nudges": nudges, "lastNudged": last_nudged })
+        }
+    })
+}
+
+/// The `query/v1` result for a log.
+pub(crate) fn query(
+    log: &PingLog,
+    expr: &str,
+    range: &TimeRange,
+    limits: &Limits,
+) -> Result<Value, Value> {
+    let expr = parse(expr, limits)?;
+    let pings = log.range(range.start, range.end);
+    let times: Vec<u64> = pings
+        .iter()
+        .zip(matches_each(&pings, &expr))
+        .filter(|(_, matched)| *matched)
+        .map(|(ping, _)| ping.time)
+        .collect();
+    Ok(json!({ "matches": times }))
+}
+
+/// The `stats/v1` result for a log.
+pub(crate) fn report(
+    log: &PingLog,
+    expr: &str,
+    range: &TimeRange,
+    utc_offset_mins: i32,
+    metrics: &[MetricArg],
+    limits: &Limits,
+) -> Result<Value, Value> {
+    let tz = utc_offset(utc_offset_mins)?;
+    let spec = ReportSpec {
+        expr: parse(expr, limits)?,
+        range: range.start..range.end,
+        tz,
+        metrics: metrics.iter().map(Metric::from).collect(),
+    };
+    Ok(stats::report(log, &spec))
+}
+
+/// A time zone `mins` minutes ahead of UTC.
+fn utc_offset(mins: i32) -> Result<FixedOffset, Value> {
+    mins.checked_mul(60)
+        .and_then(FixedOffset::east_opt)
+        .ok_or_else(|| {
+            json!({
+                "kind": "invalidRequest",
+                "message": "UTC offset must be less than a day",
+            })
+        })
+}
+
+/// Parses an expression, with errors spanning byte offsets into it (and UTF-16 offsets, for
+/// indexing JS strings), and a diagnostic with line and column numbers for editors.
+fn parse(expr: &str, limits: &Limits) -> Result<Expr, Value> {
+    Expr::parse_with_limits(expr, limits).map_err(|err| {
+        let utf16 = err.offsets(expr).utf16;
+        json!({
+            "kind": "parse",
+            "message": err.kind.to_string(),
+            "start": err.span.start,
+            "end": err.span.end,
+            "utf16Start": utf16.start,
+            "utf16End": utf16.end,
+            "diagnostic": Diagnostic::from(&err).to_json(expr),
+        })
+    })
+}
+
+fn import_error(err: ImportError) -> Value {
+    json!({ "kind": "import", "message": err.kind.to_string(), "line": err.line })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn call(request: Value) -> Value {
+        serde_json::from_str(&dispatch(&request.to_string())).unwrap()
+    }
+
+    #[test]
+    fn parses() {
+        assert_eq!(
+            call(json!({"command": "parse/v1", "args": {"expr": "b&(a)"}})),
+            json!({"ok": true, "result": {"formatted": "b & a", "tags": ["a", "b"], "warnings": []}})
+        );
+        let warnings =
+            &call(json!({"command": "parse/v1", "args": {"expr": "wоrk", "tags": ["work"]}}))
+                ["result"]["warnings"];
+        assert_eq!(
+            warnings[0]["message"],
+            "`wоrk` looks like `work`, but they're different tags"
+        );
+        assert_eq!(warnings[0]["labels"][0]["end"], 5);
+        assert_eq!(
+            call(json!({"command": "parse/v1", "args": {"expr": "a & )"}}))["error"],
+            json!({
+                "kind": "parse",
+                "message": "unexpected closing bracket",
+                "start": 4,
+                "end": 5,
+                "utf16Start": 4,
+                "utf16End": 5,
+                "diagnostic": {
+                    "severity": "error",
+                    "message": "unexpected closing bracket",
+                    "labels": [{"start": 4, "end": 5, "utf16Start": 4, "utf16End": 5, "line": 1,
+                                "column": 5, "endLine": 1, "endColumn": 6, "message": "",
+                                "primary": true}],
+                    "notes": [],
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn queries() {
+        let pings = json!([
+            {"time": 10, "tags": ["a"], "interval": 2700, "comment": null},
+            {"time": 20, "tags": ["b"], "interval": 2700},
+            {"time": 30, "tags": ["a", "b"], "interval": 2700},
+        ]);
+        let query = |args: Value| call(json!({"command": "query/v1", "args": args}));
+        assert_eq!(
+            query(json!({"expr": "a", "pings": pings}))["result"]["matches"],
+            json!([10, 30])
+        );
+        assert_eq!(
+            query(json!({"expr": "a", "pings": pings, "start": 11}))["result"]["matches"],
+            json!([30])
+        );
+    }
+
+    #[test]
+    fn stats_report() {
+        let response = call(json!({
+            "command": "stats/v1",
+            "args": {
+                "expr": "a",
+                "pings": [{"time": 0, "tags": ["a"], "interval": 3600}],
+                "metrics": ["total", {"series": "day"}, {"sessions": {"maxGap": 60}}],
+            },
+        }));
+        let result = &response["result"];
+        assert_eq!(result["version"], json!(stats::REPORT_VERSION));
+        assert_eq!(result["total"]["hours"], json!(1.0));
+        assert_eq!(result["series"]["day"][0]["start"], json!("1970-01-01"));
+        assert_eq!(result["sessions"][0]["pings"], json!(1));
+    }
+
+    #[test]
+    fn imports_and_exports() {
+        let imported = call(json!({
+            "command": "import/v1",
+            "args": {"format": "tagtime", "text": "20 b\n10 a (hi)\n", "interval": 2700},
+        }));
+        let pings = &imported["result"]["pings"];
+        assert_eq!(
+            pings[0],
+            json!({"time": 10, "tags": ["a"], "interval": 2700, "comment": "hi"})
+        );
+        let exported = call(json!({
+            "command": "export/v1",
+            "args": {"format": "tagtime", "pings": pings},
+        }));
+        assert_eq!(exported["result"]["text"], json!("10 a (hi)\n20 b\n"));
+
+        let error = call(json!({
+            "command": "import/v1",
+            "args": {"format": "tagtime", "text": "x", "interval": 2700},
+        }));
+        assert_eq!(error["error"]["kind"], json!("import"));
+        assert_eq!(error["error"]["line"], json!(1));
+
+        let android = call(json!({
+            "command": "import/v1",
+            "args": {"format": "tagtimeAndroid", "text": "ping,tags\n10,a b\n", "interval": 2700},
+        }));
+        assert_eq!(android["result"]["pings"][0]["tags"], json!(["a", "b"]));
+
+        let import = |dry_run| {
+            call(json!({
+                "command": "import/v1",
+                "args": {
+                    "format": "tagtime",
+                    "text": "10 Work, e-mail\n",
+                    "interval": 2700,
+                    "normalize": {
+                        "lowercase": true,
+                        "stripPunctuation": true,
+                        "aliases": {"e-mail": "email"},
+                        "dryRun": dry_run,
+                    },
+                },
+            }))["result"]
+                .clone()
+        };
+        let changes = json!([
+            {"from": "Work,", "to": "work", "pings": 1},
+            {"from": "e-mail", "to": "email", "pings": 1},
+        ]);
+        assert_eq!(import(true)["changes"], changes);
+        assert_eq!(import(true)["pings"][0]["tags"], json!(["Work,", "e-mail"]));
+        assert_eq!(import(false)["changes"], changes);
+        assert_eq!(import(false)["pings"][0]["tags"], json!(["work", "email"]));
+        let error = call(json!({
+            "command": "export/v1",
+            "args": {"format": "tagtimeAndroid", "pings": []},
+        }));
+        assert_eq!(error["error"]["kind"], json!("invalidRequest"));
+    }
+
+    #[test]
+    fn nudges() {
+        let request = |last_nudged: Value| {
+            call(json!({
+                "command": "nudge/v1",
+                "args": {
+                    "rules": [{"name": "focus", "expr": "work", "window": 2, "fewerThan": 1,
+                        "hours": [9, 17], "days": ["mon", "tue"]}],
+                    "pings": [{"time": 1609750800, "tags": ["games"], "interval": 2700},
+                        {"time": 1609754400, "tags": ["games"], "interval": 2700}],
+                    "lastNudged": last_nudged,
+                },
+            }))
+        };
+        let response = request(json!({}));
+        assert_eq!(
+            response["result"],
+            json!({
+                "nudges": [{"rule": "focus", "time": 1609754400, "matching": 0, "window": 2}],
+                "lastNudged": {"focus": 1609754400},
+            })
+        );
+        let response = request(response["result"]["lastNudged"].clone());
+        assert_eq!(response["result"]["nudges"], json!([]));
+
+        let error = call(json!({
+            "command": "nudge/v1",
+            "args": {"rules": [{"name": "x", "expr": "a", "window": 2}], "pings": []},
+        }));
+        assert_eq!(error["error"]["kind"], json!("invalidRequest"));
+    }
+
+    #[test]
+    fn invalid_requests() {
+        for request in &[
+            "not json",
+            r#"{"command": "parse/v0", "args": {"expr": "a"}}"#,
+            r#"{"command": "parse/v1", "args": {}}"#,
+        ] {
+            let response: Value = serde_json::from_str(&dispatch(request)).unwrap();
+            assert_eq!(response["ok"], json!(false));
+            assert_eq!(response["error"]["kind"], json!("invalidRequest"));
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let error = |request: &str, limits: &Limits| {
+            let response: Value =
+                serde_json::from_str(&dispatch_with_limits(request, limits)).unwrap();
+            response["error"].clone()
+        };
+        let deep = format!(
+            r#"{{"command": "parse/v1", "args": {{"expr": "a", "x": {}1{}}}}}"#,
+            "[".repeat(10_000),
+            "]".repeat(10_000)
+        );
+        assert_eq!(
+            error(&deep, &Limits::DEFAULT),
+            json!({ "kind": "invalidRequest", "message": "JSON nested too deeply" })
+        );
+        let request = r#"{"command": "parse/v1", "args": {"expr": "a & b"}}"#;
+        let small = Limits {
+            input_len: 20,
+            ..Limits::DEFAULT
+        };
+        assert_eq!(
+            error(request, &small),
+            json!({ "kind": "invalidRequest", "message": "request too big" })
+        );
+        let short = Limits {
+            expr_len: 3,
+            ..Limits::DEFAULT
+        };
+        assert_eq!(
+            error(request, &short)["message"],
+            json!("expression too long")
+        );
+    }
+}
@@ -0,0 +1,488 @@
+//! Errors pointed out in the text they came from, like a compiler does, for the CLI and editor
+//! tooling:
+//!
+//! ```text
+//! error: unexpected closing bracket
+//!  --> expr:1:5
+//!   |
+//! 1 | a & )
+//!   |     ^
+//! ```
+//!
+//! A [`Diagnostic`] has the same parts as one from `codespan-reporting` (a severity, a message,
+//! labelled spans, and notes), so tools already using that crate can convert them directly. With
+//! `serde_json`, [`Diagnostic::to_json`] gives the same thing with line and column numbers, for the
+//! web frontend's editor.
+
+#[cfg(feature = "expr")]
+use alloc::format;
+use alloc::string::String;
+#[cfg(any(feature = "expr", feature = "import"))]
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+use core::ops::Range;
+
+#[cfg(feature = "expr")]
+use crate::bool::{self, ParseError};
+#[cfg(feature = "import")]
+use crate::import::ImportError;
+use crate::unicode;
+#[cfg(feature = "expr")]
+use crate::unicode::Confusable;
+
+/// How bad a [`Diagnostic`] is.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Note => "note",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Self::Error => "\x1b[1;31m",
+            Self::Warning => "\x1b[1;33m",
+            Self::Note => "\x1b[1;32m",
+        }
+    }
+}
+
+/// A byte range of the source, with a message about it. Primary labels are where the problem is,
+/// and secondary ones give context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Range<usize>,
+    pub message: String,
+    pub primary: bool,
+}
+
+/// An error or warning about some source text, with the parts of it that caused the problem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    /// Adds a primary label. `message` can be empty, to just underline `span`.
+    pub fn with_label(mut self, span: Range<usize>, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    pub fn with_secondary_label(mut self, span: Range<usize>, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Renders the diagnostic for a terminal, quoting the lines of `source` that labels point at.
+    /// `name` is shown as where the source came from, like a file name. Labels spanning several
+    /// lines are underlined to the end of their first line. With `color`, ANSI escapes are used.
+    pub fn render(&self, name: &str, source: &str, color: bool) -> String {
+        let paint = |style: &'static str| if color { style } else { "" };
+        let reset = paint(RESET);
+        let mut out = String::new();
+        // writing to a string can't fail
+        let _ = writeln!(
+            out,
+            "{}{}{}{}: {}{}",
+            paint(self.severity.color()),
+            self.severity.name(),
+            reset,
+            paint(BOLD),
+            self.message,
+            reset
+        );
+
+        let mut labels: Vec<(Position, &Label)> = self
+            .labels
+            .iter()
+            .map(|label| (Position::of(source, label.span.start), label))
+            .collect();
+        labels.sort_by_key(|(start, label)| (start.line, !label.primary));
+        let width = labels.last().map_or(1, |(start, _)| digits(start.line));
+        let gutter = |out: &mut String, line: Option<usize>| {
+            let _ = match line {
+                Some(line) => write!(out, "{}{:>width$} |{}", paint(BLUE), line, reset),
+                None => write!(out, "{}{:width$} |{}", paint(BLUE), "", reset),
+            };
+        };
+
+        if let Some((start, _)) = labels
+            .iter()
+            .find(|(_, label)| label.primary)
+            .or(labels.first())
+        {
+            let _ = writeln!(
+                out,
+                "{:width$}{}-->{} {}:{}:{}",
+                "",
+                paint(BLUE),
+                reset,
+                name,
+                start.line,
+                start.column
+            );
+            gutter(&mut out, None);
+            out.push('\n');
+        }
+        let mut last_line = None;
+        for (start, label) in &labels {
+            let text = line_at(source, start.line_start);
+            if last_line != Some(start.line) {
+                gutter(&mut out, Some(start.line));
+                let _ = writeln!(out, " {}", text);
+                last_line = Some(start.line);
+            }
+            // underlined by what's shown as one character, so an emoji with a skin tone gets one
+            // mark rather than two
+            let end = Position::of(source, label.span.end.min(start.line_start + text.len()))
+                .offset
+                .max(start.offset);
+            let indent = unicode::graphemes(&source[start.line_start..start.offset]).count();
+            let len = unicode::graphemes(&source[start.offset..end]).count();
+            let (mark, style) = match label.primary {
+                true => ('^', self.severity.color()),
+                false => ('-', BLUE),
+            };
+            gutter(&mut out, None);
+            let _ = write!(out, " {:1$}{2}", "", indent, paint(style));
+            out.extend(core::iter::repeat_n(mark, len.max(1)));
+            if !label.message.is_empty() {
+                let _ = write!(out, " {}", label.message);
+            }
+            let _ = writeln!(out, "{}", reset);
+        }
+        for note in &self.notes {
+            let _ = writeln!(
+                out,
+                "{:width$} {}={} {}note{}: {}",
+                "",
+                paint(BLUE),
+                reset,
+                paint(BOLD),
+                reset,
+                note
+            );
+        }
+        out
+    }
+
+    /// The diagnostic as JSON, with each label's line and column (both from 1, with columns in
+    /// characters) as well as its byte and UTF-16 offsets into `source`:
+    ///
+    /// ```json
+    /// {"severity": "error", "message": "unexpected closing bracket", "notes": [],
+    ///  "labels": [{"start": 4, "end": 5, "utf16Start": 4, "utf16End": 5, "line": 1, "column": 5,
+    ///              "endLine": 1, "endColumn": 6, "message": "", "primary": true}]}
+    /// ```
+    #[cfg(feature = "serde_json")]
+    pub fn to_json(&self, source: &str) -> serde_json::Value {
+        let labels: Vec<_> = self
+            .labels
+            .iter()
+            .map(|label| {
+                let start = Position::of(source, label.span.start);
+                let end = Position::of(source, label.span.end.max(label.span.start));
+                let utf16 = unicode::offsets(source, label.span.clone()).utf16;
+                serde_json::json!({
+                    "start": label.span.start,
+                    "end": label.span.end,
+                    "utf16Start": utf16.start,
+                    "utf16End": utf16.end,
+                    "line": start.line,
+                    "column": start.column,
+                    "endLine": end.line,
+                    "endColumn": end.column,
+                    "message": label.message,
+                    "primary": label.primary,
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "severity": self.severity.name(),
+            "message": self.message,
+            "labels": labels,
+            "notes": self.notes,
+        })
+    }
+}
+
+#[cfg(feature = "expr")]
+impl From<&ParseError> for Diagnostic {
+    fn from(err: &ParseError) -> Self {
+        Self::error(err.kind.to_string()).with_label(err.span.clone(), "")
+    }
+}
+
+#[cfg(feature = "expr")]
+impl Diagnostic {
+    /// A warning that a tag in `expr` looks like another one, labelling everywhere it's used.
+    pub fn from_confusable(confusable: &Confusable, expr: &str) -> Self {
+        let Confusable { tag, looks_like } = *confusable;
+        let mut diagnostic = Self::warning(format!(
+            "`{}` looks like `{}`, but they're different tags",
+            tag, looks_like
+        ));
+        for (name, span) in bool::name_spans(expr) {
+            if name == tag {
+                diagnostic = diagnostic.with_label(span, "");
+            } else if name == looks_like {
+                diagnostic = diagnostic.with_secondary_label(span, "");
+            }
+        }
+        diagnostic.with_note(format!(
+            "`{}` is written `{}`, and `{}` is `{}`",
+            tag,
+            escape(tag),
+            looks_like,
+            escape(looks_like)
+        ))
+    }
+}
+
+#[cfg(feature = "import")]
+impl Diagnostic {
+    /// A diagnostic for an error importing `text`, labelling the whole line it's on.
+    pub fn from_import_error(err: &ImportError, text: &str) -> Self {
+        let diagnostic = Self::error(err.kind.to_string());
+        let start = match err.line {
+            0 => return diagnostic,
+            1 => 0,
+            line => match text.match_indices('\n').nth(line - 2) {
+                Some((newline, _)) => newline + 1,
+                None => text.len(),
+            },
+        };
+        let len = line_at(text, start).trim_end_matches('\r').len();
+        diagnostic.with_label(start..start + len, "")
+    }
+}
+
+/// Where a byte offset is, as a line and column from 1.
+struct Position {
+    line: usize,
+    column: usize,
+    /// Byte offset of the start of the line.
+    line_start: usize,
+    /// The byte offset, moved back to the start of a character.
+    offset: usize,
+}
+
+impl Position {
+    /// Offsets past the end of `source`, or inside a character, are moved back to the one before.
+    fn of(source: &str, offset: usize) -> Self {
+        let offset = unicode::floor_char_boundary(source, offset);
+        let before = &source[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            line_start,
+            offset,
+        }
+    }
+}
+
+/// The line starting at `start`, without the newline.
+fn line_at(source: &str, start: usize) -> &str {
+    let rest = source.get(start..).unwrap_or("");
+    rest.split('\n').next().unwrap_or(rest)
+}
+
+/// `text` with everything but ASCII written as `\u{...}` escapes.
+#[cfg(feature = "expr")]
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        let _ = match c.is_ascii() {
+            true => write!(out, "{}", c),
+            false => write!(out, "\\u{{{:x}}}", u32::from(c)),
+        };
+    }
+    out
+}
+
+fn digits(n: usize) -> usize {
+    n.checked_ilog10().map_or(1, |log| log as usize + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn renders() {
+        let diagnostic = Diagnostic::error("unexpected closing bracket")
+            .with_label(4..5, "")
+            .with_note("brackets have to match");
+        assert_eq!(
+            diagnostic.render("expr", "a & )", false),
+            "error: unexpected closing bracket\n \
+             --> expr:1:5\n  \
+               |\n\
+             1 | a & )\n  \
+               |     ^\n  \
+               = note: brackets have to match\n"
+        );
+        assert!(diagnostic
+            .render("expr", "a & )", true)
+            .starts_with("\x1b[1;31merror\x1b[0m"));
+    }
+
+    #[test]
+    fn renders_several_lines() {
+        let source = "1 a\n2 b\n3 café (x\n4 d\n5\n6\n7\n8\n9\n10 e\n";
+        let diagnostic = Diagnostic::warning("odd")
+            .with_secondary_label(0..1, "first")
+            .with_label(16..18, "unclosed")
+            .with_label(36..37, "");
+        assert_eq!(
+            diagnostic.render("log", source, false),
+            "warning: odd\n  \
+               --> log:3:8\n   \
+                |\n \
+              1 | 1 a\n   \
+                | - first\n \
+              3 | 3 café (x\n   \
+                |        ^^ unclosed\n\
+             10 | 10 e\n   \
+                |    ^\n"
+        );
+    }
+
+    #[test]
+    fn clamps_spans() {
+        // at the end, past the end, and inside a character
+        for span in [3..3, 9..12, 2..3] {
+            let rendered = Diagnostic::error("x")
+                .with_label(span, "")
+                .render("", "aé", false);
+            assert!(rendered.contains("1 | aé\n"), "{}", rendered);
+        }
+        // a span over several lines only underlines the first
+        let rendered = Diagnostic::error("x")
+            .with_label(1..6, "")
+            .render("", "abc\ndef", false);
+        assert!(rendered.ends_with("  |  ^^\n"), "{}", rendered);
+        assert!(Diagnostic::error("x").render("", "", false) == "error: x\n");
+    }
+
+    #[cfg(feature = "expr")]
+    #[test]
+    fn from_parse_errors() {
+        let err = crate::bool::Expr::parse("a &\n!(b").unwrap_err();
+        let rendered = Diagnostic::from(&err).render("expr", "a &\n!(b", false);
+        assert!(
+            rendered.starts_with("error: expected closing bracket\n --> expr:2:4\n"),
+            "{}",
+            rendered
+        );
+    }
+
+    #[test]
+    fn underlines_graphemes() {
+        let rendered = Diagnostic::error("x").with_label(11..20, "").render(
+            "",
+            "👍\u{1F3FD} & cafe\u{301}\u{FE0F} x",
+            false,
+        );
+        assert!(rendered.ends_with("  |     ^^^^\n"), "{}", rendered);
+    }
+
+    #[cfg(feature = "expr")]
+    #[test]
+    fn from_confusables() {
+        let expr = "wоrk | (work & wоrk)";
+        let parsed = crate::bool::Expr::parse(expr).unwrap();
+        let found = parsed.confusables::<&str>(&[]);
+        assert_eq!(
+            Diagnostic::from_confusable(&found[0], expr).render("expr", expr, false),
+            "warning: `work` looks like `wоrk`, but they're different tags\n \
+             --> expr:1:9\n  \
+               |\n\
+             1 | wоrk | (work & wоrk)\n  \
+               |         ^^^^\n  \
+               | ----\n  \
+               |                ----\n  \
+               = note: `work` is written `work`, and `wоrk` is `w\\u{43e}rk`\n"
+        );
+    }
+
+    #[cfg(feature = "import")]
+    #[test]
+    fn from_import_errors() {
+        let text = "1 a\r\nnope b\r\n";
+        let err = crate::import::tagtime_log(text, 2700).unwrap_err();
+        let diagnostic = Diagnostic::from_import_error(&err, text);
+        assert_eq!(diagnostic.labels[0].span, 5..11);
+        let first = crate::import::android_csv("_id,tags\n", 2700).unwrap_err();
+        let diagnostic = Diagnostic::from_import_error(&first, "_id,tags\n");
+        assert_eq!(diagnostic.labels[0].span, 0..8);
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn to_json() {
+        let json = Diagnostic::error("bad")
+            .with_label(4..8, "here")
+            .to_json("ab\ncdé");
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "severity": "error",
+                "message": "bad",
+                "labels": [{"start": 4, "end": 8, "utf16Start": 4, "utf16End": 6, "line": 2,
+                            "column": 2, "endLine": 2, "endColumn": 4, "message": "here",
+                            "primary": true}],
+                "notes": [],
+            })
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! JSON payloads for ping and goal events, for self-hosters sending them to webhooks (like Slack,
+//! ntfy or home automation).
+//!
+//! Every payload is an object with the same top-level keys:
+//!
+//! ```json
+//! {"version": 1, "type": "ping.answered", "time": 1609761600, "data": {...}}
+//! ```
+//!
+//! `version` is [`EVENT_VERSION`], and `time` is the Unix timestamp (in seconds) of when the event
+//! happened. `data` depends on `type`:
+//!
+//! - `ping.fired`: `{"ping": time}`, with the time the ping was sent.
+//! - `ping.answered`: the ping, with the same keys as in logs (`time`, `tags`, `interval`,
+//!   `comment`, and `answered` if it was recorded).
+//! - `goal.at_risk`: `{"goal", "expr", "direction", "target_hours", "period", "period_start",
+//!   "period_end", "hours_done", "hours_remaining", "required_daily_pace", "projected_hours"}`,
+//!   where `goal` is the goal's name, `expr` is in canonical form, `direction` is `"at_least"` or
+//!   `"at_most"`, and `period` is `"day"`, `"week"` or `"month"`.
+//! - `nudge`: `{"rule", "ping", "matching", "window"}`, with the name of the [nudge
+//!   rule](crate::nudge::Rule), the time of the ping that set it off, and how many of the rule's
+//!   window of pings matched.
+//!
+//! Payloads can be signed with a secret shared with the receiver, which is sent in the
+//! [`SIGNATURE_HEADER`] header as `sha256=` followed by the hex HMAC-SHA256 of the body.
+
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use crate::goal::{Direction, Goal, Progress};
+use crate::log::Ping;
+use crate::nudge::Nudge;
+use crate::stats::bucket_name;
+
+/// Version of the payloads. Bump this when changing their shape in a way that isn't backwards
+/// compatible.
+pub const EVENT_VERSION: u32 = 1;
+
+/// HTTP header that signatures are sent in.
+pub const SIGNATURE_HEADER: &str = "X-TTW-Signature";
+
+/// Something that happened that a webhook might want to know about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event<'a> {
+    /// A ping was sent, with the time it was sent.
+    PingFired(u64),
+    PingAnswered(&'a Ping),
+    /// A goal won't be met at the pace so far.
+    GoalAtRisk {
+        name: &'a str,
+        goal: &'a Goal,
+        progress: &'a Progress,
+    },
+    /// A nudge rule was set off.
+    Nudge(&'a Nudge),
+}
+
+impl<'a> Event<'a> {
+    /// A [`Event::GoalAtRisk`] event if the goal isn't on track, or None if it is.
+    pub fn goal_at_risk(name: &'a str, goal: &'a Goal, progress: &'a Progress) -> Option<Self> {
+        if progress.on_track {
+            None
+        } else {
+            Some(Self::GoalAtRisk {
+                name,
+                goal,
+                progress,
+            })
+        }
+    }
+
+    /// The payload's `type`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::PingFired(_) => "ping.fired",
+            Self::PingAnswered(_) => "ping.answered",
+            Self::GoalAtRisk { .. } => "goal.at_risk",
+            Self::Nudge(_) => "nudge",
+        }
+    }
+
+    /// The payload for this event, which happened at `time`.
+    pub fn to_json(&self, time: u64) -> Value {
+        let data = match self {
+            Self::PingFired(ping) => json!({ "ping": ping }),
+            Self::PingAnswered(ping) => {
+                let mut data = json!({
+                    "time": ping.time,
+                    "tags": ping.tags,
+                    "interval": ping.interval,
+                    "comment": ping.comment,
+                });
+                if let Some(answered) = ping.answered {
+                    data["answered"] = json!(answered);
+                }
+                data
+            }
+            Self::GoalAtRisk {
+                name,
+                goal,
+                progress,
+            } => json!({
+                "goal": name,
+                "expr": goal.expr.to_string(),
+                "direction": match goal.direction {
+                    Direction::AtLeast => "at_least",
+                    Direction::AtMost => "at_most",
+                },
+                "target_hours": goal.target_hours,
+                "period": bucket_name(goal.period),
+                "period_start": progress.period_start,
+                "period_end": progress.period_end,
+                "hours_done": progress.hours_done,
+                "hours_remaining": progress.hours_remaining,
+                "required_daily_pace": progress.required_daily_pace,
+                "projected_hours": progress.projected_hours,
+            }),
+            Self::Nudge(nudge) => json!({
+                "rule": nudge.rule,
+                "ping": nudge.time,
+                "matching": nudge.matching,
+                "window": nudge.window,
+            }),
+        };
+        json!({
+            "version": EVENT_VERSION,
+            "type": self.kind(),
+            "time": time,
+            "data": data,
+        })
+    }
+}
+
+/// The value of the [`SIGNATURE_HEADER`] header for a payload.
+pub fn sign(secret: &[u8], body: &str) -> String {
+    let mut out = String::from("sha256=");
+    for byte in mac(secret, body).finalize().into_bytes() {
+        out += &format!("{:02x}", byte);
+    }
+    out
+}
+
+/// If `signature` is the correct [`SIGNATURE_HEADER`] value for a payload, for receivers. The
+/// comparison takes the same time no matter how much of the signature is correct.
+pub fn verify(secret: &[u8], body: &str, signature: &str) -> bool {
+    let hex = match signature.strip_prefix("sha256=") {
+        Some(hex) if hex.len() % 2 == 0 && hex.is_ascii() => hex,
+        _ => return false,
+    };
+    let bytes: Option<Vec<u8>> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..(i + 2)], 16).ok())
+        .collect();
+    match bytes {
+        Some(bytes) => mac(secret, body).verify_slice(&bytes).is_ok(),
+        None => false,
+    }
+}
+
+fn mac(secret: &[u8], body: &str) -> Hmac<Sha256> {
+    #[allow(clippy::expect_used)]
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any length");
+    mac.update(body.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bool::Expr;
+    use crate::stats::Bucket;
+
+    #[test]
+    fn ping_payloads() {
+        assert_eq!(
+            Event::PingFired(100).to_json(101),
+            json!({"version": 1, "type": "ping.fired", "time": 101, "data": {"ping": 100}})
+        );
+        let mut ping = Ping::new(100, vec!["a".into()], 2700);
+        assert_eq!(
+            Event::PingAnswered(&ping).to_json(150)["data"],
+            json!({"time": 100, "tags": ["a"], "interval": 2700, "comment": null})
+        );
+        ping.answered = Some(150);
+        assert_eq!(
+            Event::PingAnswered(&ping).to_json(150)["data"]["answered"],
+            json!(150)
+        );
+    }
+
+    #[test]
+    fn goal_payloads() {
+        let goal = Goal {
+            expr: Expr::from_string("work&!email").unwrap(),
+            target_hours: 20.0,
+            period: Bucket::Week,
+            direction: Direction::AtLeast,
+        };
+        let mut progress = Progress {
+            period_start: 0,
+            period_end: 7 * 86400,
+            hours_done: 6.0,
+            hours_remaining: 14.0,
+            required_daily_pace: 3.5,
+            projected_hours: 14.0,
+            projected_completion: None,
+            on_track: false,
+        };
+        let event = Event::goal_at_risk("focus", &goal, &progress).unwrap();
+        let payload = event.to_json(3 * 86400);
+        assert_eq!(payload["type"], json!("goal.at_risk"));
+        assert_eq!(
+            payload["data"],
+            json!({
+                "goal": "focus",
+                "expr": "work & !email",
+                "direction": "at_least",
+                "target_hours": 20.0,
+                "period": "week",
+                "period_start": 0,
+                "period_end": 7 * 86400,
+                "hours_done": 6.0,
+                "hours_remaining": 14.0,
+                "required_daily_pace": 3.5,
+                "projected_hours": 14.0,
+            })
+        );
+        progress.on_track = true;
+        assert_eq!(Event::goal_at_risk("focus", &goal, &progress), None);
+    }
+
+    #[test]
+    fn nudge_payloads() {
+        let nudge = Nudge {
+            rule: "focus".into(),
+            time: 1000,
+            matching: 1,
+            window: 10,
+        };
+        let payload = Event::Nudge(&nudge).to_json(1005);
+        assert_eq!(payload["type"], json!("nudge"));
+        assert_eq!(
+            payload["data"],
+            json!({"rule": "focus", "ping": 1000, "matching": 1, "window": 10})
+        );
+    }
+
+    #[test]
+    fn signs_and_verifies() {
+        // RFC 4231 test case 2
+        let signature = sign(b"Jefe", "what do ya want for nothing?");
+        assert_eq!(
+            signature,
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert!(verify(b"Jefe", "what do ya want for nothing?", &signature));
+        assert!(!verify(b"Jefe", "what do ya want for nothing!", &signature));
+        assert!(!verify(b"jefe", "what do ya want for nothing?", &signature));
+        assert!(!verify(
+            b"Jefe",
+            "what do ya want for nothing?",
+            &signature[7..]
+        ));
+        assert!(!verify(
+            b"Jefe",
+            "what do ya want for nothing?",
+            "sha256=zz"
+        ));
+    }
+}
@@ -0,0 +1,235 @@
+//! Feeds of upcoming pings, so calendar apps (and feed readers) can subscribe to a ping schedule.
+//!
+//! Each ping's ID is made from its time and the schedule, so it stays the same between refreshes
+//! and apps update the feed instead of duplicating pings.
+
+use std::convert::TryFrom;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+
+use crate::{next_ping_after, PingAlg, PingIntervalData};
+
+/// What to put in a feed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedOptions {
+    pub title: String,
+    /// Number of upcoming pings to include.
+    pub count: usize,
+    /// How often apps should fetch the feed again, in seconds. This should be well under the time
+    /// `count` pings take, so the feed never runs out.
+    pub refresh_secs: u32,
+    /// Where the feed is served from, if it's known.
+    pub url: Option<String>,
+}
+
+impl Default for FeedOptions {
+    fn default() -> Self {
+        Self {
+            title: "TagTime pings".to_string(),
+            count: 50,
+            refresh_secs: 3600,
+            url: None,
+        }
+    }
+}
+
+/// The next `count` pings after `time`. Stops early if the schedule runs out, or pings get too
+/// far in the future to have a date.
+fn upcoming(schedule: &PingIntervalData, time: u64, count: usize) -> Vec<DateTime<Utc>> {
+    let mut pings = Vec::with_capacity(count);
+    let mut time = time;
+    while pings.len() < count {
+        time = match next_ping_after(time, schedule) {
+            Some(time) => time,
+            None => break,
+        };
+        match i64::try_from(time)
+            .ok()
+            .and_then(|time| DateTime::from_timestamp(time, 0))
+        {
+            Some(date) => pings.push(date),
+            None => break,
+        }
+    }
+    pings
+}
+
+fn uid(schedule: &PingIntervalData, time: &DateTime<Utc>) -> String {
+    let alg = match schedule.alg {
+        PingAlg::FnvTime => "fnv",
+        PingAlg::TagTime => "tagtime",
+    };
+    format!(
+        "ping-{}-{}-{}-{}@ttw.smitop.com",
+        time.timestamp(),
+        alg,
+        schedule.seed,
+        schedule.avg_interval
+    )
+}
+
+/// An iCalendar (RFC 5545) feed of the pings after `now`, with one short event per ping.
+pub fn ics_feed(schedule: &PingIntervalData, now: u64, options: &FeedOptions) -> String {
+    let stamp = i64::try_from(now)
+        .ok()
+        .and_then(|now| DateTime::from_timestamp(now, 0))
+        .unwrap_or_default();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//TagTime Web//taglogic//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        format!("NAME:{}", ics_text(&options.title)),
+        format!("X-WR-CALNAME:{}", ics_text(&options.title)),
+        format!(
+            "REFRESH-INTERVAL;VALUE=DURATION:PT{}S",
+            options.refresh_secs
+        ),
+        format!("X-PUBLISHED-TTL:PT{}S", options.refresh_secs),
+    ];
+    if let Some(url) = &options.url {
+        lines.push(format!("SOURCE;VALUE=URI:{}", url));
+    }
+    for time in upcoming(schedule, now, options.count) {
+        lines.extend(vec![
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}", uid(schedule, &time)),
+            format!("DTSTAMP:{}", ics_time(&stamp)),
+            format!("DTSTART:{}", ics_time(&time)),
+            "DURATION:PT1M".to_string(),
+            "SUMMARY:Ping".to_string(),
+            "TRANSP:TRANSPARENT".to_string(),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut out = String::new();
+    for line in lines {
+        fold(&line, &mut out);
+    }
+    out
+}
+
+/// A JSON Feed (version 1.1) of the pings after `now`. The refresh interval isn't part of the
+/// spec, so it's under the `_ttw` extension key as `refresh_secs`.
+pub fn json_feed(schedule: &PingIntervalData, now: u64, options: &FeedOptions) -> Value {
+    let items: Vec<Value> = upcoming(schedule, now, options.count)
+        .iter()
+        .map(|time| {
+            let date = time.to_rfc3339_opts(SecondsFormat::Secs, true);
+            json!({
+                "id": uid(schedule, time),
+                "title": "Ping",
+                "content_text": format!("Ping at {}", date),
+                "date_published": date,
+            })
+        })
+        .collect();
+    let mut feed = json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": options.title,
+        "items": items,
+        "_ttw": { "refresh_secs": options.refresh_secs },
+    });
+    if let Some(url) = &options.url {
+        feed["feed_url"] = json!(url);
+    }
+    feed
+}
+
+fn ics_time(time: &DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escapes text for an iCalendar property value.
+fn ics_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out += "\\n",
+            '\r' => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Writes a content line, folded so no line is longer than 75 bytes.
+fn fold(line: &str, out: &mut String) {
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tt::UNIV_SCHED;
+
+    // from the universal schedule tests
+    const NOW: u64 = 1533754341;
+    const NEXT: [u64; 2] = [1533758980, 1533759940];
+
+    fn options() -> FeedOptions {
+        FeedOptions {
+            title: "Pings, mine".to_string(),
+            count: 2,
+            refresh_secs: 600,
+            url: Some("https://example.com/pings".to_string()),
+        }
+    }
+
+    #[test]
+    fn ics() {
+        let feed = ics_feed(&UNIV_SCHED, NOW, &options());
+        assert!(feed.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(feed.ends_with("END:VCALENDAR\r\n"));
+        assert!(feed.contains("\r\nNAME:Pings\\, mine\r\n"));
+        assert!(feed.contains("\r\nREFRESH-INTERVAL;VALUE=DURATION:PT600S\r\n"));
+        assert!(feed.contains("\r\nDTSTAMP:20180808T185221Z\r\n"));
+        assert!(feed.contains("\r\nDTSTART:20180808T200940Z\r\n"));
+        assert!(feed.contains(&format!("\r\nUID:ping-{}-tagtime-11193462-2700@", NEXT[1])));
+        assert_eq!(feed.matches("BEGIN:VEVENT").count(), 2);
+        assert!(feed.split("\r\n").all(|line| line.len() <= 75));
+    }
+
+    #[test]
+    fn json() {
+        let feed = json_feed(&UNIV_SCHED, NOW, &options());
+        assert_eq!(feed["_ttw"]["refresh_secs"], json!(600));
+        assert_eq!(feed["feed_url"], json!("https://example.com/pings"));
+        let items = feed["items"].as_array().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0]["date_published"], json!("2018-08-08T20:09:40Z"));
+        assert_eq!(
+            items[0]["id"],
+            json!(uid(&UNIV_SCHED, &upcoming(&UNIV_SCHED, NOW, 1)[0]))
+        );
+        // the same ping keeps its ID in later feeds
+        let later = json_feed(&UNIV_SCHED, NEXT[0], &options());
+        assert_eq!(later["items"][0]["id"], items[1]["id"]);
+    }
+
+    #[test]
+    fn folds_and_escapes() {
+        let mut out = String::new();
+        fold(&"é".repeat(40), &mut out);
+        let lines: Vec<&str> = out.trim_end().split("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.len() <= 75));
+        assert_eq!(ics_text("a;b\\c\r\nd"), "a\\;b\\\\c\\nd");
+    }
+}
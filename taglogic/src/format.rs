@@ -0,0 +1,418 @@
+//! Versions of everything this crate saves, and migrations from old versions to the current ones,
+//! so old backups always load.
+//!
+//! JSON is loaded with [`load`], which finds the version of the value, then runs it through every
+//! migration from there to the current version. A value's version comes from, in order:
+//!
+//! - an envelope written by [`wrap`]: `{"format": "log", "version": 1, "data": [...]}`
+//! - a top-level `"version"` key, for formats that have always had one (aggregates, reports and
+//!   events)
+//! - otherwise, it's version 1, which is what every format was before it had a version
+//!
+//! Binary formats keep their version in their header, which [`detect`] reads.
+//!
+//! To change a format, bump its version and add a migration from the old version to
+//! `MIGRATIONS`, which turns a value of the old version into one of the new version.
+
+use serde_json::{json, Value};
+use std::convert::{TryFrom, TryInto};
+
+use crate::binlog::BINARY_LOG_VERSION;
+#[cfg(feature = "webhooks")]
+use crate::events::EVENT_VERSION;
+use crate::schedule::SCHEDULE_CACHE_VERSION;
+use crate::stats::{AGGREGATES_VERSION, REPORT_VERSION};
+
+/// Version of expressions as text, in the syntax [`Expr::parse`](crate::bool::Expr::parse) reads.
+/// Version 2 added comment searches, like `comment:"late"`.
+pub const EXPRESSION_VERSION: u32 = 2;
+
+/// Version of logs as JSON arrays of [pings](crate::log::Ping). Version 2 added pings'
+/// [`weights`](crate::log::Ping::weights) and [`author`](crate::log::Ping::author).
+pub const LOG_VERSION: u32 = 2;
+
+/// A step from one version of a JSON format to the next.
+type Migration = fn(Value) -> Result<Value, FormatError>;
+
+/// Migrations by the format and version they migrate from.
+const MIGRATIONS: &[(Format, u32, Migration)] = &[
+    (Format::Expression, 1, expression_without_comment_searches),
+    (Format::Log, 1, log_without_weights),
+];
+
+/// Names like `comment:"late"` and `comment:/late/` search comments from version 2, but were
+/// tags in version 1. There's no way to write those tags any more, so an expression with one
+/// fails to load instead of quietly searching comments. Everything else means the same.
+fn expression_without_comment_searches(expr: Value) -> Result<Value, FormatError> {
+    match expr.as_str() {
+        Some(text) if crate::bool::had_comment_tags(text) => Err(FormatError::Migration {
+            format: Format::Expression.name(),
+            version: 1,
+            message: "tags starting with `comment:\"` or `comment:/` search comments now"
+                .to_string(),
+        }),
+        _ => Ok(expr),
+    }
+}
+
+/// Pings in version 1 logs have no weights or authors, so they're already version 2 pings. The
+/// version changed so versions that don't know about weights and authors refuse new logs,
+/// instead of loading them without.
+fn log_without_weights(log: Value) -> Result<Value, FormatError> {
+    Ok(log)
+}
+
+/// Something this crate saves.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Format {
+    /// An expression's text.
+    Expression,
+    /// A log as a JSON array of pings.
+    Log,
+    /// A log written by [`write_binary_log`](crate::binlog::write_binary_log).
+    BinaryLog,
+    /// Checkpoints written by [`ScheduleCache::to_bytes`](crate::schedule::ScheduleCache::to_bytes).
+    ScheduleCache,
+    /// Daily counts written by [`Aggregates::to_json`](crate::stats::Aggregates::to_json).
+    Aggregates,
+    /// A report from [`stats::report`](crate::stats::report).
+    Report,
+    /// A webhook payload.
+    #[cfg(feature = "webhooks")]
+    Event,
+}
+
+impl Format {
+    /// Every format.
+    pub const ALL: &'static [Format] = &[
+        Format::Expression,
+        Format::Log,
+        Format::BinaryLog,
+        Format::ScheduleCache,
+        Format::Aggregates,
+        Format::Report,
+        #[cfg(feature = "webhooks")]
+        Format::Event,
+    ];
+
+    /// The name used in envelopes, like `"binary-log"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Expression => "expression",
+            Format::Log => "log",
+            Format::BinaryLog => "binary-log",
+            Format::ScheduleCache => "schedule-cache",
+            Format::Aggregates => "aggregates",
+            Format::Report => "report",
+            #[cfg(feature = "webhooks")]
+            Format::Event => "event",
+        }
+    }
+
+    /// The format named `name`, from an envelope.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.name() == name)
+    }
+
+    /// The version this crate writes.
+    pub fn version(self) -> u32 {
+        match self {
+            Format::Expression => EXPRESSION_VERSION,
+            Format::Log => LOG_VERSION,
+            Format::BinaryLog => BINARY_LOG_VERSION,
+            Format::ScheduleCache => u32::from(SCHEDULE_CACHE_VERSION),
+            Format::Aggregates => AGGREGATES_VERSION,
+            Format::Report => REPORT_VERSION,
+            #[cfg(feature = "webhooks")]
+            Format::Event => EVENT_VERSION,
+        }
+    }
+
+    /// Whether the format's JSON has always had a top-level `"version"` key.
+    fn has_version_key(self) -> bool {
+        match self {
+            Format::Aggregates | Format::Report => true,
+            #[cfg(feature = "webhooks")]
+            Format::Event => true,
+            _ => false,
+        }
+    }
+}
+
+/// Why a saved value couldn't be loaded.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum FormatError {
+    /// An envelope for another format, or one this crate doesn't know.
+    #[error("expected {expected}, found {found}")]
+    WrongFormat {
+        expected: &'static str,
+        found: String,
+    },
+    /// A version missing or not a number.
+    #[error("invalid {0} version")]
+    InvalidVersion(&'static str),
+    /// A version newer than this crate writes, from a newer version of the app.
+    #[error("{format} version {version} is newer than this version supports")]
+    TooNew { format: &'static str, version: u32 },
+    /// A version with no migration from it, like one from before the format was versioned.
+    #[error("no migration from {format} version {version}")]
+    NoMigration { format: &'static str, version: u32 },
+    /// A value that a migration couldn't turn into the next version.
+    #[error("couldn't migrate {format} version {version}: {message}")]
+    Migration {
+        format: &'static str,
+        version: u32,
+        message: String,
+    },
+}
+
+/// Wraps `data` of the current version of `format` in an envelope, for backups that might be
+/// loaded by a later version.
+pub fn wrap(format: Format, data: Value) -> Value {
+    json!({ "format": format.name(), "version": format.version(), "data": data })
+}
+
+/// Loads a value of any version of `format` (see the [module docs](self)) as the current version.
+/// An envelope is unwrapped, so the result is just the data.
+pub fn load(format: Format, value: Value) -> Result<Value, FormatError> {
+    load_with(format, value, format.version(), MIGRATIONS)
+}
+
+/// Loads a value of `format` as version `current`.
+fn load_with(
+    format: Format,
+    mut value: Value,
+    current: u32,
+    migrations: &[(Format, u32, Migration)],
+) -> Result<Value, FormatError> {
+    let version = |value: &Value| {
+        let version = value["version"]
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok());
+        version.ok_or(FormatError::InvalidVersion(format.name()))
+    };
+    let mut from = match value.get("format").and_then(Value::as_str) {
+        Some(name) if value.get("data").is_some() => {
+            if name != format.name() {
+                return Err(FormatError::WrongFormat {
+                    expected: format.name(),
+                    found: name.to_string(),
+                });
+            }
+            let from = version(&value)?;
+            value = value["data"].take();
+            from
+        }
+        _ if format.has_version_key() => version(&value)?,
+        _ => 1,
+    };
+    if from > current {
+        return Err(FormatError::TooNew {
+            format: format.name(),
+            version: from,
+        });
+    }
+    while from < current {
+        let migration = migrations
+            .iter()
+            .find(|(migrates, version, _)| *migrates == format && *version == from)
+            .map(|(_, _, migration)| migration)
+            .ok_or(FormatError::NoMigration {
+                format: format.name(),
+                version: from,
+            })?;
+        value = migration(value)?;
+        from += 1;
+    }
+    if format.has_version_key() {
+        value["version"] = json!(from);
+    }
+    Ok(value)
+}
+
+/// The binary format and version of `bytes`, from its header, or None if it isn't one.
+pub fn detect(bytes: &[u8]) -> Option<(Format, u32)> {
+    match bytes {
+        [b'T', b'T', b'W', b'L', version @ ..] => {
+            let version = version.get(..4)?.try_into().ok()?;
+            Some((Format::BinaryLog, u32::from_le_bytes(version)))
+        }
+        [b'T', b'T', b'W', b'S', version, ..] => Some((Format::ScheduleCache, u32::from(*version))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::binlog::write_binary_log;
+    use crate::log::{Ping, PingLog};
+    use crate::schedule::ScheduleCache;
+    use crate::tt;
+
+    /// Version 2 of logs for the tests, where each ping's tags are a string.
+    fn join_tags(mut log: Value) -> Result<Value, FormatError> {
+        for ping in log.as_array_mut().into_iter().flatten() {
+            let tags: Vec<&str> = ping["tags"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .collect();
+            ping["tags"] = json!(tags.join(" "));
+        }
+        Ok(log)
+    }
+
+    fn fail(_: Value) -> Result<Value, FormatError> {
+        Err(FormatError::Migration {
+            format: "report",
+            version: 1,
+            message: "nope".to_string(),
+        })
+    }
+
+    #[test]
+    fn names_every_format() {
+        for &format in Format::ALL {
+            assert_eq!(Format::from_name(format.name()), Some(format));
+            assert!(format.version() >= 1);
+        }
+        assert_eq!(Format::from_name("nope"), None);
+    }
+
+    #[test]
+    fn loads_current_and_unversioned_values() {
+        let log = json!([{"time": 1, "tags": ["a"], "interval": 2700}]);
+        assert_eq!(load(Format::Log, log.clone()), Ok(log.clone()));
+        assert_eq!(
+            load(Format::Log, wrap(Format::Log, log.clone())),
+            Ok(log.clone())
+        );
+        let pings: Vec<Ping> = serde_json::from_value(load(Format::Log, log).unwrap()).unwrap();
+        assert_eq!(pings[0].tags, ["a"]);
+        let weighted = json!([{"time": 1, "tags": ["a"], "interval": 2700, "weights": {"a": 0.5}}]);
+        let old = json!({"format": "log", "version": 1, "data": weighted.clone()});
+        assert_eq!(load(Format::Log, old), Ok(weighted));
+
+        let expr = wrap(Format::Expression, json!("a & !b"));
+        assert_eq!(load(Format::Expression, expr), Ok(json!("a & !b")));
+        let search = wrap(Format::Expression, json!(r#"a & comment:"late""#));
+        assert_eq!(
+            load(Format::Expression, search),
+            Ok(json!(r#"a & comment:"late""#))
+        );
+        let report = json!({"version": REPORT_VERSION, "pings": 0});
+        assert_eq!(load(Format::Report, report.clone()), Ok(report));
+    }
+
+    #[test]
+    fn rejects_values_it_cant_load() {
+        let log = wrap(Format::Log, json!([]));
+        assert_eq!(
+            load(Format::Report, log).unwrap_err().to_string(),
+            "expected report, found log"
+        );
+        let newer = json!({"format": "log", "version": LOG_VERSION + 1, "data": []});
+        assert_eq!(
+            load(Format::Log, newer),
+            Err(FormatError::TooNew {
+                format: "log",
+                version: LOG_VERSION + 1
+            })
+        );
+        let old = json!({"version": 0});
+        assert_eq!(
+            load(Format::Aggregates, old).unwrap_err().to_string(),
+            "no migration from aggregates version 0"
+        );
+        assert_eq!(
+            load(Format::Report, json!({"pings": 0})),
+            Err(FormatError::InvalidVersion("report"))
+        );
+    }
+
+    #[test]
+    fn runs_migrations_in_order() {
+        // pretend logs are at version 3, with tags joined into strings in version 2 and the pings
+        // put in an object in version 3
+        let migrations: [(Format, u32, Migration); 2] = [
+            (Format::Log, 2, |log| Ok(json!({ "pings": log }))),
+            (Format::Log, 1, join_tags),
+        ];
+        let log = json!([{"time": 1, "tags": ["a", "b"], "interval": 2700}]);
+        let migrated = json!({"pings": [{"time": 1, "tags": "a b", "interval": 2700}]});
+        assert_eq!(
+            load_with(Format::Log, log, 3, &migrations),
+            Ok(migrated.clone())
+        );
+        let wrapped = json!({"format": "log", "version": 2, "data": [{"tags": "a"}]});
+        assert_eq!(
+            load_with(Format::Log, wrapped, 3, &migrations),
+            Ok(json!({"pings": [{"tags": "a"}]}))
+        );
+        assert_eq!(
+            load_with(
+                Format::Log,
+                json!({"format": "log", "version": 3, "data": migrated.clone()}),
+                3,
+                &migrations
+            ),
+            Ok(migrated)
+        );
+
+        // formats with a version key get the new version
+        let migrations: [(Format, u32, Migration); 1] = [(Format::Report, 1, Ok)];
+        let report = json!({"version": 1, "pings": 0});
+        assert_eq!(
+            load_with(Format::Report, report.clone(), 2, &migrations),
+            Ok(json!({"version": 2, "pings": 0}))
+        );
+        let failing: [(Format, u32, Migration); 1] = [(Format::Report, 1, fail)];
+        assert_eq!(
+            load_with(Format::Report, report, 2, &failing)
+                .unwrap_err()
+                .to_string(),
+            "couldn't migrate report version 1: nope"
+        );
+    }
+
+    #[test]
+    fn detects_binary_formats() {
+        let log = PingLog::from_pings(vec![Ping::new(1, vec!["a".to_string()], 2700)]);
+        assert_eq!(
+            detect(&write_binary_log(&log)),
+            Some((Format::BinaryLog, BINARY_LOG_VERSION))
+        );
+        let cache = ScheduleCache::new(tt::UNIV_SCHED, 64, tt::UR_PING).to_bytes();
+        assert_eq!(
+            detect(&cache),
+            Some((Format::ScheduleCache, u32::from(SCHEDULE_CACHE_VERSION)))
+        );
+        assert_eq!(detect(b"TTWL"), None);
+        assert_eq!(detect(b"{}"), None);
+    }
+
+    #[test]
+    fn migrates_expressions_without_comment_searches() {
+        let old = |text: &str| load(Format::Expression, json!(text));
+        assert_eq!(old("a & !b"), Ok(json!("a & !b")));
+        // tags that only have the prefix partway through were never names of searches
+        assert_eq!(
+            old(r#"mycomment:"x" | comment:x"#),
+            Ok(json!(r#"mycomment:"x" | comment:x"#))
+        );
+        for text in &[r#"comment:"late""#, "a&!comment:/x/", r#"(b,comment:"c)"#] {
+            assert!(
+                matches!(old(text), Err(FormatError::Migration { version: 1, .. })),
+                "{}",
+                text
+            );
+        }
+    }
+}
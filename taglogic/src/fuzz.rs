@@ -0,0 +1,275 @@
+//! Checks that malformed input gets an error instead of a panic, by giving every parser thousands
+//! of broken copies of valid input. The copies come from a fixed seed, so failures reproduce.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Bytes that tend to mean something to one of the parsers.
+const INTERESTING: &[u8] = b"\0\x7f\x80\xff\n\r ()!&|,\"[]{}:-09aT";
+
+/// A small generator that's good enough for picking mutations.
+struct XorShift(u64);
+
+impl XorShift {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n.max(1) as u64) as usize
+    }
+}
+
+/// `count` copies of `valid`, each with a few bytes changed, copied, or removed, or cut short.
+pub(crate) fn mutations(valid: &[u8], count: usize) -> Vec<Vec<u8>> {
+    let mut rng = XorShift(0x9e37_79b9_7f4a_7c15 ^ valid.len() as u64);
+    (0..count)
+        .map(|_| {
+            let mut bytes = valid.to_vec();
+            for _ in 0..=rng.below(4) {
+                let at = rng.below(bytes.len() + 1);
+                match rng.below(6) {
+                    0 => bytes.truncate(at),
+                    1 => bytes.insert(at, INTERESTING[rng.below(INTERESTING.len())]),
+                    2 if at < bytes.len() => {
+                        bytes.remove(at);
+                    }
+                    3 if at < bytes.len() => bytes[at] ^= 1 << rng.below(8),
+                    4 if at < bytes.len() => bytes[at] = rng.below(256) as u8,
+                    _ => {
+                        // repeat a run, for deep nesting and long input
+                        let end = (at + rng.below(8)).min(bytes.len());
+                        let run = bytes[at..end].repeat(rng.below(64));
+                        bytes.splice(at..at, run);
+                    }
+                }
+            }
+            bytes
+        })
+        .collect()
+}
+
+/// [`mutations`] as strings, with invalid UTF-8 replaced.
+pub(crate) fn text_mutations(valid: &str, count: usize) -> Vec<String> {
+    mutations(valid.as_bytes(), count)
+        .into_iter()
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .collect()
+}
+
+#[cfg(feature = "expr")]
+#[test]
+fn exprs() {
+    use crate::bool::Expr;
+    use crate::limits::Limits;
+
+    let valid = [
+        "a & (b | !c)",
+        "!(!a), b || c && d or e and not f",
+        "work & !(email | phone) | sleep",
+    ];
+    for text in valid.iter().flat_map(|valid| text_mutations(valid, 3000)) {
+        let expr = match Expr::parse(&text) {
+            Ok(expr) => expr,
+            Err(err) => {
+                assert!(err.span.end <= text.len(), "{:?} for {:?}", err, text);
+                assert_eq!(err.offsets(&text).bytes, err.span, "for {:?}", text);
+                continue;
+            }
+        };
+        // printing can add brackets and spaces, so allow a bit more than the default limits
+        let roomier = Limits {
+            expr_depth: 64,
+            expr_len: 1000,
+            ..Limits::DEFAULT
+        };
+        let printed = expr.to_string();
+        assert_eq!(
+            Expr::parse_with_limits(&printed, &roomier).as_ref(),
+            Ok(&expr),
+            "{:?} printed as {:?}",
+            text,
+            printed
+        );
+        let tags = ["a", "b", "work", "sleep"];
+        let compiled = expr.compile_with_table(&tags);
+        assert_eq!(compiled.matches(&tags), expr.matches(&tags));
+        compiled.matches_bitset_rows(70, &[u32::MAX; 7]);
+        compiled.explain();
+        expr.reorder(&|tag| tag.len() as f64 / 10.0);
+    }
+}
+
+#[cfg(feature = "import")]
+#[test]
+fn imports() {
+    use crate::import::{android_csv, tagtime_log};
+
+    let log = "1184098080 work email (replying to bob) [2007.07.10 16:08:00 TUE]\n\
+               1184097393 afk  [2007.07.10 15:56:33 TUE]\n";
+    for text in text_mutations(log, 5000) {
+        let _ = tagtime_log(&text, 2700);
+    }
+    let csv = "_id,ping,notes,period,tags\r\n\
+               1,1300000000000,\"lunch, with \"\"bob\"\"\",30,\"eat social\"\r\n\
+               2,1300001800000,,45,work\r\n";
+    for text in text_mutations(csv, 5000) {
+        let _ = android_csv(&text, 2700);
+    }
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn binary_logs() {
+    use crate::binlog::{write_binary_log, BinaryLog};
+    use crate::log::{Ping, PingLog};
+
+    let mut commented = Ping::new(1_500_000_600, vec!["b".into(), "c".into()], 2700);
+    commented.comment = Some("hi".into());
+    commented.answered = Some(1_500_000_700);
+    let log = PingLog::from_pings(vec![
+        Ping::new(1_500_000_000, vec!["a".into()], 2700),
+        commented,
+    ]);
+    for bytes in mutations(&write_binary_log(&log), 5000) {
+        let log = match BinaryLog::new(bytes) {
+            Ok(log) => log,
+            Err(_) => continue,
+        };
+        for index in 0..=log.len() {
+            let _ = log.get(index);
+        }
+        let _ = log.to_log(0..log.len() + 1);
+        log.range(0, u64::MAX);
+        #[cfg(feature = "expr")]
+        {
+            let expr = crate::bool::Expr::parse("a | !c").unwrap();
+            let _ = log.matches_each(0..log.len(), &expr);
+        }
+    }
+}
+
+#[cfg(feature = "ping")]
+#[test]
+fn schedule_caches() {
+    use crate::schedule::ScheduleCache;
+    use crate::tt::UR_PING;
+
+    let schedule = crate::new_ping_interval_data(1234, 2700, true);
+    let valid = ScheduleCache::new(schedule, 8, UR_PING + 86400 * 30).to_bytes();
+    for bytes in mutations(&valid, 5000) {
+        let cache = match ScheduleCache::from_bytes(&bytes) {
+            Ok(cache) => cache,
+            Err(_) => continue,
+        };
+        // the schedule is walked from the last checkpoint, so only ask about times near one
+        let last = cache.last_checkpoint().unwrap_or(UR_PING);
+        for t in [0, UR_PING, last.saturating_add(1)] {
+            cache.next_ping_after(t);
+            cache.last_ping(t);
+            cache.checked_pings_between(t, t.saturating_add(3600));
+        }
+    }
+}
+
+#[cfg(feature = "ping")]
+#[test]
+fn schedules() {
+    use crate::schedule::ScheduleCache;
+    use crate::tt::{UNIV_SCHED, UR_PING};
+
+    // the schedule is walked from its start (or from 2030 for the universal schedule), so later
+    // times are slow rather than wrong, and `ping::test` checks overflow at the end of time
+    let times = [0, 1, UR_PING - 1, UR_PING, UR_PING + 1, UR_PING + 100_000];
+    for &seed in &[0, 1, 1234, 2_147_483_646, 2_147_483_647, u32::MAX] {
+        for &avg_interval in &[0, 1, 2700, u32::MAX] {
+            for &tagtime in &[false, true] {
+                let schedule = crate::new_ping_interval_data(seed, avg_interval, tagtime);
+                let cache = ScheduleCache::new(schedule, 16, UR_PING + 86400);
+                for &t in &times {
+                    crate::should_ping_at_time(t, &schedule);
+                    crate::pings_between_u32(t as u32, t as u32, &schedule);
+                    // FnvTime checks every second, which is slow with long intervals
+                    if tagtime || avg_interval < 3000 {
+                        crate::next_ping_after(t, &schedule);
+                        crate::last_ping(t, &schedule);
+                        cache.next_ping_after(t);
+                        cache.last_ping(t);
+                    }
+                }
+                if !tagtime {
+                    crate::next_ping_after(u64::MAX, &schedule);
+                }
+            }
+        }
+    }
+    assert_eq!(
+        crate::checked_pings_between(UR_PING, UR_PING, &UNIV_SCHED),
+        None
+    );
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn months() {
+    use crate::segments::Month;
+
+    for text in text_mutations("2024-03", 3000)
+        .into_iter()
+        .chain(["2147483647-12".into(), "-2147483648-01".into()])
+    {
+        if let Ok(month) = text.parse::<Month>() {
+            month.start();
+            month.next();
+        }
+    }
+    let months = Month::covering(u64::MAX - 86400 * 31, u64::MAX);
+    assert!(!months.is_empty() && months.len() <= 2);
+}
+
+#[cfg(feature = "commands")]
+#[test]
+fn commands() {
+    use crate::commands::dispatch;
+
+    let requests = [
+        r#"{"command": "parse/v1", "args": {"expr": "a & !b"}}"#,
+        r#"{"command": "query/v1", "args": {"expr": "a", "start": 5, "pings": [{"time": 10, "tags": ["a"], "interval": 2700}]}}"#,
+        r#"{"command": "stats/v1", "args": {"expr": "a", "utcOffsetMins": 60, "metrics": ["total", {"series": "month"}, {"sessions": {"maxGap": 3600}}, "answerDelays", "diversity"], "pings": [{"time": 1600000000, "tags": ["a"], "interval": 2700, "answered": 1600000100}]}}"#,
+        r#"{"command": "import/v1", "args": {"format": "tagtime", "text": "1184097393 afk\n", "interval": 2700}}"#,
+        r#"{"command": "export/v1", "args": {"format": "tagtime", "pings": [{"time": 10, "tags": ["a"], "interval": 2700}]}}"#,
+    ];
+    for request in requests
+        .iter()
+        .flat_map(|request| text_mutations(request, 2000))
+    {
+        let response: serde_json::Value = serde_json::from_str(&dispatch(&request)).unwrap();
+        assert!(response.is_object(), "{} for {}", response, request);
+    }
+}
+
+#[cfg(feature = "proto")]
+#[test]
+fn protos() {
+    use crate::log::{Ping, PingLog};
+    use crate::proto::{self, Message};
+
+    let old = PingLog::from_pings(vec![Ping::new(10, vec!["a".into()], 2700)]);
+    let new = PingLog::from_pings(vec![
+        Ping::new(10, vec!["b".into()], 2700),
+        Ping::new(20, vec!["c".into()], 2700),
+    ]);
+    let delta = proto::LogDelta::between(&old, &new).encode_to_vec();
+    for bytes in mutations(&delta, 5000) {
+        if let Ok(delta) = proto::LogDelta::decode(bytes.as_slice()) {
+            delta.apply(&old);
+        }
+    }
+    let state = proto::SchedulerState::new(&crate::tt::UNIV_SCHED, 1533758980).encode_to_vec();
+    for bytes in mutations(&state, 5000) {
+        if let Ok(state) = proto::SchedulerState::decode(bytes.as_slice()) {
+            if let Some(schedule) = state.schedule() {
+                crate::last_ping(crate::tt::UR_PING + 100_000, &schedule);
+            }
+        }
+    }
+}
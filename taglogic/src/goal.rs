@@ -0,0 +1,403 @@
+//! Goals for how much time to spend on things, and progress towards them.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::bool::{Expr, ParseError};
+use crate::log::{match_shares, PingLog};
+use crate::stats::{bucket_bounds, Bucket, Calendar};
+
+/// Whether a goal is to spend at least or at most the target amount of time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    AtLeast,
+    AtMost,
+}
+
+/// A target amount of time to spend on pings matching an expression, every period.
+///
+/// Goals can be written like `work >= 10h/week` or `games <= 1h30m/day`, for config files and
+/// goal forms: the expression, `>=` for at least or `<=` for at most, the amount in hours and
+/// minutes, and `day`, `week` or `month`. Formatting one writes it that way, and parsing that
+/// gives the same goal back.
+///
+/// ```
+/// use taglogic::goal::{Direction, Goal};
+///
+/// let goal: Goal = "work & !meeting >= 7.5h / week".parse().unwrap();
+/// assert_eq!(goal.target_hours, 7.5);
+/// assert_eq!(goal.direction, Direction::AtLeast);
+/// assert_eq!(goal.to_string(), "work & !meeting >= 7h30m/week");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Goal {
+    pub expr: Expr,
+    pub target_hours: f64,
+    pub period: Bucket,
+    pub direction: Direction,
+}
+
+/// An error parsing a [`Goal`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum GoalError {
+    #[error("goals are written like `work >= 10h/week`")]
+    NoComparison,
+    /// The expression is invalid. The error's span is in the whole goal, not just the expression.
+    #[error("invalid expression: {0}")]
+    Expr(#[source] ParseError),
+    #[error("amounts are written like `10h`, `45m` or `1h30m`")]
+    Amount,
+    #[error("periods are `day`, `week` or `month`")]
+    Period,
+    /// The expression can't match any ping, like `a & !a`.
+    #[error("the expression never matches")]
+    NeverMatches,
+}
+
+impl FromStr for Goal {
+    type Err = GoalError;
+
+    /// Parses a goal written like `work >= 10h/week`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // the last comparison, since tags can have `>=` or `<=` in them
+        let comparisons = [
+            (s.rfind(">="), Direction::AtLeast),
+            (s.rfind("<="), Direction::AtMost),
+        ];
+        let (split, direction) = (comparisons.iter())
+            .filter_map(|&(split, direction)| Some((split?, direction)))
+            .max_by_key(|&(split, _)| split)
+            .ok_or(GoalError::NoComparison)?;
+        let (expr, target) = (&s[..split], &s[split + 2..]);
+        let expr = Expr::parse(expr).map_err(|mut err| {
+            // the expression starts the goal, so its spans are already in the goal
+            err.span = err.span.start.min(split)..err.span.end.min(split);
+            GoalError::Expr(err)
+        })?;
+        if !expr.is_satisfiable() {
+            return Err(GoalError::NeverMatches);
+        }
+        let (amount, period) = target.split_once('/').ok_or(GoalError::Period)?;
+        let period = match period.trim() {
+            "day" => Bucket::Day,
+            "week" => Bucket::Week,
+            "month" => Bucket::Month,
+            _ => return Err(GoalError::Period),
+        };
+        Ok(Self {
+            expr,
+            target_hours: parse_amount(amount.trim()).ok_or(GoalError::Amount)?,
+            period,
+            direction,
+        })
+    }
+}
+
+/// Hours in an amount like `1h30m`, `1.5h` or `90m`. Whole minutes are added up as minutes and
+/// then turned into hours, so formatting and parsing round-trips.
+fn parse_amount(amount: &str) -> Option<f64> {
+    let (mut hours, mut minutes, mut has_minutes) = (0.0, 0.0, false);
+    let mut rest = amount;
+    while !rest.is_empty() {
+        let unit = rest.find(['h', 'm'])?;
+        let number: f64 = rest[..unit].parse().ok()?;
+        if !number.is_finite() || number < 0.0 || rest[..unit].starts_with('+') {
+            return None;
+        }
+        if rest[unit..].starts_with('h') {
+            hours += number;
+        } else {
+            minutes += number;
+            has_minutes = true;
+        }
+        rest = &rest[unit + 1..];
+    }
+    match (amount.is_empty(), has_minutes) {
+        (true, _) => None,
+        (false, true) => Some((hours * 60.0 + minutes) / 60.0),
+        (false, false) => Some(hours),
+    }
+}
+
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let comparison = match self.direction {
+            Direction::AtLeast => ">=",
+            Direction::AtMost => "<=",
+        };
+        write!(f, "{} {} ", self.expr, comparison)?;
+        let hours = self.target_hours;
+        let minutes = (hours * 60.0).round();
+        if minutes / 60.0 == hours {
+            match (minutes as u64 / 60, minutes as u64 % 60) {
+                (hours, 0) => write!(f, "{}h", hours)?,
+                (0, minutes) => write!(f, "{}m", minutes)?,
+                (hours, minutes) => write!(f, "{}h{}m", hours, minutes)?,
+            }
+        } else {
+            write!(f, "{}h", hours)?;
+        }
+        let period = match self.period {
+            Bucket::Day => "day",
+            Bucket::Week => "week",
+            Bucket::Month => "month",
+        };
+        write!(f, "/{}", period)
+    }
+}
+
+impl Goal {
+    /// Whether a ping could count towards both this goal and `other`, like `work` and `meeting`,
+    /// which double-counts its time if both goals are sent to Beeminder.
+    pub fn overlaps(&self, other: &Goal) -> bool {
+        self.expr.overlaps(&other.expr)
+    }
+}
+
+/// Progress towards a goal during the current period.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Progress {
+    /// Start of the current period.
+    pub period_start: u64,
+    /// End of the current period (the start of the next one).
+    pub period_end: u64,
+    /// Hours spent so far this period.
+    pub hours_done: f64,
+    /// Hours left until the target is reached. For [`Direction::AtMost`] goals, this is how much
+    /// time is left in the budget.
+    pub hours_remaining: f64,
+    /// Hours per day needed for the rest of the period to exactly reach the target. For
+    /// [`Direction::AtMost`] goals, this is the most that can be spent per day.
+    pub required_daily_pace: f64,
+    /// Hours expected to be done by the end of the period, at the pace so far.
+    pub projected_hours: f64,
+    /// When the target will be reached at the pace so far, or None if that won't happen this
+    /// period.
+    pub projected_completion: Option<u64>,
+    /// If the goal will be met at the pace so far.
+    pub on_track: bool,
+}
+
+/// Computes progress towards `goal` in the period containing `now`, with periods in the time zone
+/// `tz`. Returns None if the period's boundaries can't be repersented.
+pub fn progress<Tz: Calendar>(log: &PingLog, goal: &Goal, now: u64, tz: &Tz) -> Option<Progress> {
+    let (_, period_start, period_end) = bucket_bounds(goal.period, now, tz)?;
+
+    // weighted tags only count for their share, like in stats
+    let pings = log.range(period_start, now.saturating_add(1));
+    let hours_done = (pings.iter().zip(match_shares(&pings, &goal.expr)))
+        .map(|(ping, share)| f64::from(ping.interval) * share)
+        .sum::<f64>()
+        / 3600.0;
+    let hours_remaining = (goal.target_hours - hours_done).max(0.0);
+    let secs_left = period_end.saturating_sub(now) as f64;
+    let secs_elapsed = now.saturating_sub(period_start) as f64;
+    let required_daily_pace = if secs_left > 0.0 {
+        hours_remaining / (secs_left / 86400.0)
+    } else {
+        0.0
+    };
+    // hours per second so far
+    let rate = if secs_elapsed > 0.0 {
+        hours_done / secs_elapsed
+    } else {
+        0.0
+    };
+    let projected_hours = hours_done + rate * secs_left;
+    let projected_completion = if hours_remaining == 0.0 {
+        Some(now)
+    } else if rate > 0.0 {
+        // the cast saturates, so huge targets don't wrap around to a time in the period
+        let secs_needed = (hours_remaining * secs_elapsed / hours_done).ceil() as u64;
+        now.checked_add(secs_needed)
+            .filter(|time| *time <= period_end)
+    } else {
+        None
+    };
+    let on_track = match goal.direction {
+        Direction::AtLeast => projected_hours >= goal.target_hours,
+        Direction::AtMost => projected_hours <= goal.target_hours,
+    };
+    Some(Progress {
+        period_start,
+        period_end,
+        hours_done,
+        hours_remaining,
+        required_daily_pace,
+        projected_hours,
+        projected_completion,
+        on_track,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use chrono::Utc;
+
+    const DAY: u64 = 86400;
+    // 2021-01-04 00:00:00 UTC, a Monday
+    const MONDAY: u64 = 1609718400;
+
+    fn goal(expr: &str, target_hours: f64, direction: Direction) -> Goal {
+        Goal {
+            expr: Expr::from_string(expr).unwrap(),
+            target_hours,
+            period: Bucket::Week,
+            direction,
+        }
+    }
+
+    /// A log with 2 hours of "work" per day for the first `days` days of the week.
+    fn log(days: u64) -> PingLog {
+        PingLog::from_pings(
+            (0..days)
+                .flat_map(|day| {
+                    (0..2).map(move |i| {
+                        Ping::new(MONDAY + day * DAY + i * 3600, vec!["work".into()], 3600)
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn parses_goals() {
+        let parsed = |s: &str| s.parse::<Goal>();
+        assert_eq!(
+            parsed("work >= 20h/week"),
+            Ok(goal("work", 20.0, Direction::AtLeast))
+        );
+        let games = parsed("games|tv<=1h30m/day").unwrap();
+        assert_eq!(games.expr, Expr::parse("games | tv").unwrap());
+        assert_eq!(games.target_hours, 1.5);
+        assert_eq!(games.period, Bucket::Day);
+        assert_eq!(games.direction, Direction::AtMost);
+        assert_eq!(parsed("a >= 90m / month").unwrap().target_hours, 1.5);
+        assert_eq!(parsed("a >= 0.25h/day").unwrap().target_hours, 0.25);
+
+        for (s, err) in [
+            ("work 10h/week", GoalError::NoComparison),
+            ("work >= 10/week", GoalError::Amount),
+            ("work >= h/week", GoalError::Amount),
+            ("work >= -1h/week", GoalError::Amount),
+            ("work >= 1hm/week", GoalError::Amount),
+            ("work >= /week", GoalError::Amount),
+            ("work >= 10h", GoalError::Period),
+            ("work >= 10h/year", GoalError::Period),
+            ("work & !work >= 10h/week", GoalError::NeverMatches),
+        ] {
+            assert_eq!(parsed(s), Err(err), "{}", s);
+        }
+        match parsed("work & >= 1h/day") {
+            Err(GoalError::Expr(err)) => assert_eq!(err.span, 7..7),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn finds_overlapping_goals() {
+        let goal = |s: &str| s.parse::<Goal>().unwrap();
+        let work = goal("work >= 20h/week");
+        assert!(work.overlaps(&goal("meeting <= 5h/week")));
+        assert!(work.overlaps(&goal("work & email <= 5h/week")));
+        assert!(!work.overlaps(&goal("!work & email <= 5h/week")));
+    }
+
+    #[test]
+    fn formats_goals() {
+        for s in [
+            "work >= 20h/week",
+            "games | tv <= 1h30m/day",
+            "(a | b) & !c >= 45m/month",
+            "a <= 0h/day",
+            "a >= 1.0001h/week",
+        ] {
+            let goal: Goal = s.parse().unwrap();
+            assert_eq!(goal.to_string(), s);
+        }
+        // every amount round-trips, even ones that aren't whole minutes
+        for hours in [0.1, 1.0 / 3.0, 20.0 / 60.0, 80.0 / 60.0, 2.5e-7, 1234.5678] {
+            let goal = goal("a", hours, Direction::AtLeast);
+            assert_eq!(goal.to_string().parse(), Ok(goal));
+        }
+    }
+
+    #[test]
+    fn weekly_progress() {
+        let goal = goal("work", 20.0, Direction::AtLeast);
+        // end of Wednesday
+        let now = MONDAY + 3 * DAY;
+        let progress = progress(&log(3), &goal, now, &Utc).unwrap();
+        assert_eq!(progress.period_start, MONDAY);
+        assert_eq!(progress.period_end, MONDAY + 7 * DAY);
+        assert_eq!(progress.hours_done, 6.0);
+        assert_eq!(progress.hours_remaining, 14.0);
+        assert_eq!(progress.required_daily_pace, 3.5);
+        assert_eq!(progress.projected_hours, 14.0);
+        assert_eq!(progress.projected_completion, None);
+        assert!(!progress.on_track);
+    }
+
+    #[test]
+    fn projected_completion() {
+        let goal = goal("work", 10.0, Direction::AtLeast);
+        let now = MONDAY + 3 * DAY;
+        let progress = progress(&log(3), &goal, now, &Utc).unwrap();
+        assert_eq!(progress.projected_completion, Some(now + 2 * DAY));
+        assert!(progress.on_track);
+    }
+
+    #[test]
+    fn weighted_progress() {
+        let goal = goal("work", 10.0, Direction::AtLeast);
+        let mut pings = log(1).pings().to_vec();
+        pings.push(Ping::from_entry(
+            MONDAY + 7200,
+            "work:0.25 email:0.75",
+            3600,
+        ));
+        let log = PingLog::from_pings(pings);
+        let progress = progress(&log, &goal, MONDAY + DAY, &Utc).unwrap();
+        assert_eq!(progress.hours_done, 2.25);
+    }
+
+    #[test]
+    fn huge_targets_never_complete() {
+        let goal: Goal = "work >= 99999999999999h/week".parse().unwrap();
+        let now = MONDAY + 3 * DAY;
+        let progress = progress(&log(3), &goal, now, &Utc).unwrap();
+        assert_eq!(progress.projected_completion, None);
+        assert!(!progress.on_track);
+    }
+
+    #[test]
+    fn already_done() {
+        let goal = goal("work", 4.0, Direction::AtLeast);
+        let now = MONDAY + 3 * DAY;
+        let progress = progress(&log(3), &goal, now, &Utc).unwrap();
+        assert_eq!(progress.projected_completion, Some(now));
+        assert_eq!(progress.hours_remaining, 0.0);
+        assert_eq!(progress.required_daily_pace, 0.0);
+    }
+
+    #[test]
+    fn at_most_goals() {
+        let goal = goal("work", 10.0, Direction::AtMost);
+        let now = MONDAY + 3 * DAY;
+        let progress = progress(&log(3), &goal, now, &Utc).unwrap();
+        assert_eq!(progress.hours_remaining, 4.0);
+        assert_eq!(progress.required_daily_pace, 1.0);
+        assert!(!progress.on_track);
+    }
+
+    #[test]
+    fn ignores_previous_periods() {
+        let goal = goal("work", 10.0, Direction::AtLeast);
+        let progress = progress(&log(3), &goal, MONDAY + 7 * DAY, &Utc).unwrap();
+        assert_eq!(progress.period_start, MONDAY + 7 * DAY);
+        assert_eq!(progress.hours_done, 0.0);
+    }
+}
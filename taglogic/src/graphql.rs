@@ -0,0 +1,200 @@
+//! GraphQL types for logs and stats, so a thin server can expose them to the dashboard without
+//! mapping every type by hand.
+//!
+//! With this feature, [`Ping`] and [`TimeEstimate`] are GraphQL objects. [`LogQuery`] resolves
+//! queries against the log in the schema's data, which must be an `Arc<RwLock<PingLog>>` (the same
+//! as [`server`](crate::server) uses), and can be merged into a server's own query root.
+//!
+//! Invalid expressions are errors with the extensions `kind` (`"parse"`), and `start` and `end`
+//! (byte offsets into the expression).
+//!
+//! If the schema's data has a [`Limits`], expressions are parsed with its limits instead of the
+//! default ones, and [`with_limits`] applies its nesting limit to queries.
+
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+
+use async_graphql::{
+    Context, ErrorExtensions, InputObject, Object, ObjectType, Result, SchemaBuilder, SimpleObject,
+    SubscriptionType,
+};
+
+use crate::bool::Expr;
+use crate::limits::Limits;
+use crate::log::{Ping, PingLog};
+use crate::stats::{self, TimeEstimate};
+
+/// Pings to query: the ones matching `expr`, sent in the range `start..end`.
+#[derive(Debug, Clone, InputObject)]
+pub struct QueryInput {
+    pub expr: String,
+    /// Defaults to the start of the log.
+    pub start: Option<u64>,
+    /// Defaults to the end of the log.
+    pub end: Option<u64>,
+}
+
+/// An expression in canonical form.
+#[derive(Debug, Clone, SimpleObject)]
+pub struct ParsedExpr {
+    pub formatted: String,
+    /// Every tag in the expression, sorted and without duplicates.
+    pub tags: Vec<String>,
+}
+
+/// Queries on the log in the schema's data.
+#[derive(Debug, Default)]
+pub struct LogQuery;
+
+#[Object]
+impl LogQuery {
+    /// Checks an expression, and returns it in canonical form.
+    async fn parse(&self, ctx: &Context<'_>, expr: String) -> Result<ParsedExpr> {
+        let parsed = parse(ctx, &expr)?;
+        Ok(ParsedExpr {
+            formatted: parsed.to_string(),
+            tags: parsed.tags().into_iter().map(String::from).collect(),
+        })
+    }
+
+    /// The pings matching a query, oldest to newest.
+    async fn pings(&self, ctx: &Context<'_>, query: QueryInput) -> Result<Vec<Ping>> {
+        let expr = parse(ctx, &query.expr)?;
+        let log = read_log(ctx)?;
+        Ok(log
+            .range(query.start.unwrap_or(0), query.end.unwrap_or(u64::MAX))
+            .iter()
+            .filter(|ping| ping.matches(&expr))
+            .map(|ping| ping.to_ping())
+            .collect())
+    }
+
+    /// Estimated time spent on the pings matching a query.
+    async fn estimate(&self, ctx: &Context<'_>, query: QueryInput) -> Result<TimeEstimate> {
+        let expr = parse(ctx, &query.expr)?;
+        let log = read_log(ctx)?;
+        let range = query.start.unwrap_or(0)..query.end.unwrap_or(u64::MAX);
+        Ok(stats::estimate(&log, &expr, range))
+    }
+}
+
+fn read_log<'a>(ctx: &Context<'a>) -> Result<RwLockReadGuard<'a, PingLog>> {
+    let log = ctx.data::<Arc<RwLock<PingLog>>>()?;
+    Ok(log.read().unwrap_or_else(|err| err.into_inner()))
+}
+
+/// Rejects queries nested deeper than `limits.nesting_depth` before they're run, including
+/// fragments that spread themselves.
+pub fn with_limits<Query, Mutation, Subscription>(
+    builder: SchemaBuilder<Query, Mutation, Subscription>,
+    limits: &Limits,
+) -> SchemaBuilder<Query, Mutation, Subscription>
+where
+    Query: ObjectType + 'static,
+    Mutation: ObjectType + 'static,
+    Subscription: SubscriptionType + 'static,
+{
+    builder
+        .limit_depth(limits.nesting_depth)
+        .limit_recursive_depth(limits.nesting_depth)
+}
+
+fn parse(ctx: &Context<'_>, expr: &str) -> Result<Expr> {
+    let limits = ctx.data_opt::<Limits>().unwrap_or(&Limits::DEFAULT);
+    Expr::parse_with_limits(expr, limits).map_err(|err| {
+        let utf16 = err.offsets(expr).utf16;
+        async_graphql::Error::new(err.kind.to_string()).extend_with(|_, extensions| {
+            extensions.set("kind", "parse");
+            extensions.set("start", err.span.start as u64);
+            extensions.set("end", err.span.end as u64);
+            // for highlighting the error in a JS string
+            extensions.set("utf16Start", utf16.start as u64);
+            extensions.set("utf16End", utf16.end as u64);
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_graphql::{EmptyMutation, EmptySubscription, Schema, Value};
+    use serde_json::json;
+
+    async fn execute(query: &str) -> async_graphql::Response {
+        let log = PingLog::from_pings(vec![
+            Ping::new(10, vec!["a".into()], 3600),
+            Ping::new(20, vec!["b".into()], 3600),
+            Ping::new(30, vec!["a".into(), "b".into()], 3600),
+        ]);
+        Schema::build(LogQuery, EmptyMutation, EmptySubscription)
+            .data(Arc::new(RwLock::new(log)))
+            .finish()
+            .execute(query)
+            .await
+    }
+
+    fn to_json(value: Value) -> serde_json::Value {
+        value.into_json().unwrap()
+    }
+
+    #[tokio::test]
+    async fn queries() {
+        let response = execute(
+            r#"{
+                parse(expr: "b&(a)") { formatted tags }
+                pings(query: { expr: "a", start: 11 }) { time tags comment }
+                estimate(query: { expr: "a | b" }) { pings hours }
+            }"#,
+        )
+        .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            to_json(response.data),
+            json!({
+                "parse": {"formatted": "b & a", "tags": ["a", "b"]},
+                "pings": [{"time": 30, "tags": ["a", "b"], "comment": null}],
+                "estimate": {"pings": 3, "hours": 3.0},
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn parse_errors() {
+        let response = execute(r#"{ pings(query: { expr: "a & )" }) { time } }"#).await;
+        let error = &response.errors[0];
+        assert_eq!(error.message, "unexpected closing bracket");
+        let extensions = error.extensions.as_ref().unwrap();
+        assert_eq!(extensions.get("kind"), Some(&Value::from("parse")));
+        assert_eq!(extensions.get("start"), Some(&Value::from(4)));
+
+        let response = execute(r#"{ pings(query: { expr: "😀 & )" }) { time } }"#).await;
+        let extensions = response.errors[0].extensions.as_ref().unwrap();
+        assert_eq!(extensions.get("start"), Some(&Value::from(7)));
+        assert_eq!(extensions.get("utf16Start"), Some(&Value::from(5)));
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        let limits = Limits {
+            expr_len: 3,
+            nesting_depth: 2,
+            ..Limits::DEFAULT
+        };
+        let schema = with_limits(
+            Schema::build(LogQuery, EmptyMutation, EmptySubscription),
+            &limits,
+        )
+        .data(Arc::new(RwLock::new(PingLog::new())))
+        .data(limits)
+        .finish();
+        let response = schema.execute(r#"{ parse(expr: "a & b") { tags } }"#).await;
+        assert_eq!(response.errors[0].message, "expression too long");
+        let response = schema
+            .execute(r#"{ pings(query: { expr: "a" }) { time } parse(expr: "a") { tags } }"#)
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let response = schema
+            .execute(r#"{ ...q } fragment q on LogQuery { parse(expr: "a") { tags } ...q }"#)
+            .await;
+        assert!(!response.errors.is_empty());
+    }
+}
@@ -1,33 +1,98 @@
-use std::hash::Hasher;
-
-const U64_MAX_FLOAT: f64 = u64::MAX as f64;
+use core::hash::Hasher;
 
 /// Uses FNV hashing to map input times to randomly distributed numbers.
-pub fn time_hash(t: u64, seed: u64) -> f64 {
+pub fn time_hash(t: u64, seed: u64) -> u64 {
     let mut hasher = fnv::FnvHasher::with_key(seed);
     // write_u64 isn't used since that uses the native endianness.
     // Instead, we always use little-endian for hashing purposes, since most CPUs are
     // little-endian anyways, and most big-endian processors are old and slow anyways.
     hasher.write(&t.to_le_bytes());
-    let hash: u64 = hasher.finish();
-    (hash as f64) / U64_MAX_FLOAT
+    hasher.finish()
+}
+
+/// The lowest `1 / avg_interval` of all hashes, which are the seconds that get pings.
+///
+/// Whether a hash is in it used to be found with `hash as f64 / u64::MAX as f64 < 1.0 /
+/// avg_interval as f64`. This gives exactly the same answers, but with integers so that no
+/// platform's float quirks can move a ping.
+#[derive(Debug, Copy, Clone)]
+pub struct Rate {
+    /// The lowest hash that isn't in the rate, or None if they all are.
+    end: Option<u64>,
+}
+
+impl Rate {
+    pub fn new(avg_interval: u32) -> Self {
+        if avg_interval == 0 {
+            // 1.0 / 0.0 is infinity
+            return Self { end: None };
+        }
+        // 1 / avg_interval rounded to 53 bits like a float, as `q / 2^(52 + shift)`
+        let shift = 32 - (avg_interval - 1).leading_zeros();
+        let avg = u128::from(avg_interval);
+        let num = 1u128 << (52 + shift);
+        let (mut q, r) = (num / avg, num % avg);
+        if 2 * r > avg || (2 * r == avg && q % 2 == 1) {
+            q += 1;
+        }
+        // `u64::MAX as f64` is 2^64, so a hash is in if `hash / 2^64 < q / 2^(52 + shift)`, and
+        // rounding is monotonic, so the hashes that are in are the ones below some hash
+        let inside = |hash: u64| (round_to_f64(hash) << shift) < (q << 12);
+        if inside(u64::MAX) {
+            return Self { end: None };
+        }
+        let (mut low, mut high) = (0, u64::MAX);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if inside(mid) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Self { end: Some(low) }
+    }
+
+    pub fn contains(self, hash: u64) -> bool {
+        self.end.is_none_or(|end| hash < end)
+    }
+}
+
+/// `x` rounded to 53 significant bits, with ties to even, like `x as f64`.
+fn round_to_f64(x: u64) -> u128 {
+    let shift = (64 - x.leading_zeros()).saturating_sub(53);
+    if shift == 0 {
+        return u128::from(x);
+    }
+    let mut mantissa = x >> shift;
+    let rest = x & ((1 << shift) - 1);
+    let half = 1 << (shift - 1);
+    if rest > half || (rest == half && mantissa % 2 == 1) {
+        mantissa += 1;
+    }
+    u128::from(mantissa) << shift
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    /// The hash as a float from 0 to 1, which schedules used to be found with.
+    fn float_hash(t: u64, seed: u64) -> f64 {
+        time_hash(t, seed) as f64 / u64::MAX as f64
+    }
+
     #[test]
     fn time_hash_valid() {
-        assert_eq!(time_hash(1000000, 123), 0.7658504836526976);
+        assert_eq!(float_hash(1000000, 123), 0.7658504836526976);
     }
 
     #[test]
     fn all_time_hash_in_range() {
         #[inline]
         fn check_hash(t: u64, seed: u64) {
-            let hash = time_hash(t, seed);
-            if hash >= 1.0 || hash < 0.0 {
+            let hash = float_hash(t, seed);
+            if !(0.0..1.0).contains(&hash) {
                 panic!("time_hash({}, {}) = {}", t, seed, hash);
             };
         }
@@ -41,4 +106,56 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn rates_match_floats() {
+        let float = |hash: u64, avg: u32| (hash as f64 / u64::MAX as f64) < 1.0 / f64::from(avg);
+        let intervals = [
+            0,
+            1,
+            2,
+            3,
+            7,
+            60,
+            1024,
+            1025,
+            2700,
+            86_400,
+            1 << 31,
+            u32::MAX - 1,
+            u32::MAX,
+        ];
+        for &avg in &intervals {
+            let rate = Rate::new(avg);
+            // around where the answer changes, and everywhere else
+            let edge = if avg == 0 {
+                u64::MAX
+            } else {
+                u64::MAX / u64::from(avg)
+            };
+            let near = (0..20_000).map(|i| edge.saturating_sub(10_000).saturating_add(i));
+            let spread = (0..20_000u64).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            let ends = [0, 1, 1 << 53, (1 << 53) + 1, u64::MAX - 1024, u64::MAX];
+            for hash in near.chain(spread).chain(ends.iter().copied()) {
+                assert_eq!(rate.contains(hash), float(hash, avg), "{} {}", hash, avg);
+            }
+        }
+    }
+
+    #[test]
+    fn rounds_like_floats() {
+        for &x in &[
+            0,
+            1,
+            (1 << 53) - 1,
+            1 << 53,
+            (1 << 53) + 1,
+            (1 << 54) + 2,
+            (1 << 54) + 6,
+            u64::MAX,
+        ] {
+            assert_eq!(round_to_f64(x) as f64, x as f64);
+            assert_eq!(round_to_f64(x), x as f64 as u128);
+        }
+    }
 }
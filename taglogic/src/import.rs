@@ -0,0 +1,389 @@
+//! Reading and writing other apps' log formats, and cleaning up the tags they come with.
+
+use std::error::Error;
+use std::fmt;
+use std::num::ParseIntError;
+
+use crate::limits::Limits;
+use crate::log::{Ping, PingLog};
+
+mod normalize;
+
+pub use normalize::{NormalizeError, Normalizer, TagChange};
+
+/// An error reading a log, on a 1-based `line` of the input, or line 0 for errors about the whole
+/// input (like it being too big).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportError {
+    pub line: usize,
+    pub kind: ImportErrorKind,
+}
+
+/// What was wrong with a log.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum ImportErrorKind {
+    #[error("log too big")]
+    TooBig,
+    #[error("expected a timestamp")]
+    BadTimestamp(#[source] ParseIntError),
+    #[error("unclosed bracket")]
+    UnclosedBracket,
+    #[error("unclosed quote")]
+    UnclosedQuote,
+    #[error("no ping column")]
+    NoPingColumn,
+    #[error("no tags column")]
+    NoTagsColumn,
+    #[error("expected a period in minutes")]
+    BadPeriod,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl Error for ImportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.kind.source()
+    }
+}
+
+/// Reads a log in the format of the original TagTime's `.log` files, where each line is a
+/// timestamp followed by tags, like `1184097393 work email (replying to bob) [2007.07.10 ...]`.
+///
+/// Text in parentheses becomes the comment, and text in square brackets (which TagTime uses for
+/// human readable dates) is ignored. The format doesn't record ping gaps, so every ping is given
+/// `interval`. Blank lines are skipped.
+pub fn tagtime_log(text: &str, interval: u32) -> Result<PingLog, ImportError> {
+    tagtime_log_with_limits(text, interval, &Limits::DEFAULT)
+}
+
+/// Like [`tagtime_log`], but with the size limit in `limits` instead of the default one.
+pub fn tagtime_log_with_limits(
+    text: &str,
+    interval: u32,
+    limits: &Limits,
+) -> Result<PingLog, ImportError> {
+    check_len(text, limits)?;
+    let mut pings = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let err = |kind| ImportError {
+            line: index + 1,
+            kind,
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (time, rest) = line.split_at(line.find(char::is_whitespace).unwrap_or(line.len()));
+        let time = time
+            .parse()
+            .map_err(|source| err(ImportErrorKind::BadTimestamp(source)))?;
+
+        let mut tags = Vec::new();
+        let mut comments = Vec::new();
+        let mut rest = rest.trim_start();
+        while let Some(first) = rest.chars().next() {
+            let close = match first {
+                '(' => ')',
+                '[' => ']',
+                _ => {
+                    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                    tags.push(rest[..end].to_string());
+                    rest = rest[end..].trim_start();
+                    continue;
+                }
+            };
+            let end = rest
+                .find(close)
+                .ok_or_else(|| err(ImportErrorKind::UnclosedBracket))?;
+            if close == ')' {
+                comments.push(rest[1..end].trim());
+            }
+            rest = rest[(end + 1)..].trim_start();
+        }
+
+        let mut ping = Ping::new(time, tags, interval);
+        if !comments.is_empty() {
+            ping.comment = Some(comments.join(" "));
+        }
+        pings.push(ping);
+    }
+    Ok(PingLog::from_pings(pings))
+}
+
+/// Reads a CSV export of the TagTime Android app's pings table. The first row is a header, and
+/// columns are found by name: `ping` (or `time`) and `tags` are needed, and `notes` (the comment)
+/// and `period` (the ping gap in minutes) are used if they're there. Pings without a period are
+/// given `interval`.
+///
+/// Depending on the version of the app, times are in seconds or milliseconds. Times too large to be
+/// in seconds (after the year 5000) are taken to be milliseconds. Tags are separated by spaces or
+/// commas, and rows with a blank time are skipped.
+pub fn android_csv(text: &str, interval: u32) -> Result<PingLog, ImportError> {
+    android_csv_with_limits(text, interval, &Limits::DEFAULT)
+}
+
+/// Like [`android_csv`], but with the size limit in `limits` instead of the default one.
+pub fn android_csv_with_limits(
+    text: &str,
+    interval: u32,
+    limits: &Limits,
+) -> Result<PingLog, ImportError> {
+    check_len(text, limits)?;
+    let mut rows = csv_rows(text)?.into_iter();
+    let header = match rows.next() {
+        Some((_, header)) => header,
+        None => return Ok(PingLog::new()),
+    };
+    let column = |names: &[&str]| {
+        header
+            .iter()
+            .position(|name| names.contains(&name.trim().to_lowercase().as_str()))
+    };
+    let err = |line, kind| ImportError { line, kind };
+    let time_column =
+        column(&["ping", "time"]).ok_or_else(|| err(1, ImportErrorKind::NoPingColumn))?;
+    let tags_column = column(&["tags"]).ok_or_else(|| err(1, ImportErrorKind::NoTagsColumn))?;
+    let notes_column = column(&["notes"]);
+    let period_column = column(&["period"]);
+
+    let mut pings = Vec::new();
+    for (line, row) in rows {
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|index| row.get(index))
+                .map(|field| field.trim())
+                .filter(|field| !field.is_empty())
+        };
+        let time: u64 = match field(Some(time_column)) {
+            Some(time) => time
+                .parse()
+                .map_err(|source| err(line, ImportErrorKind::BadTimestamp(source)))?,
+            None => continue,
+        };
+        // 1e11 seconds is in the year 5138, and 1e11 milliseconds is in 1973
+        let time = if time >= 100_000_000_000 {
+            time / 1000
+        } else {
+            time
+        };
+        let interval = match field(period_column) {
+            Some(period) => period
+                .parse::<u32>()
+                .ok()
+                .and_then(|mins| mins.checked_mul(60))
+                .ok_or_else(|| err(line, ImportErrorKind::BadPeriod))?,
+            None => interval,
+        };
+        let tags = field(Some(tags_column))
+            .unwrap_or("")
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|tag| !tag.is_empty())
+            .map(String::from)
+            .collect();
+        let mut ping = Ping::new(time, tags, interval);
+        ping.comment = field(notes_column).map(String::from);
+        pings.push(ping);
+    }
+    Ok(PingLog::from_pings(pings))
+}
+
+/// Splits CSV into rows of fields, with the 1-based line each row starts on. Fields can be quoted,
+/// with `""` for a quote, and quoted fields can have commas and line breaks.
+fn csv_rows(text: &str) -> Result<Vec<(usize, Vec<String>)>, ImportError> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut row_line = 1;
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\n' | '\r' if !quoted => {
+                if c == '\r' && chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                row.push(std::mem::take(&mut field));
+                if row.iter().any(|field| !field.is_empty()) {
+                    rows.push((row_line, std::mem::take(&mut row)));
+                }
+                row.clear();
+                line += 1;
+                row_line = line;
+            }
+            _ => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+    if quoted {
+        return Err(ImportError {
+            line: row_line,
+            kind: ImportErrorKind::UnclosedQuote,
+        });
+    }
+    row.push(field);
+    if row.iter().any(|field| !field.is_empty()) {
+        rows.push((row_line, row));
+    }
+    Ok(rows)
+}
+
+fn check_len(text: &str, limits: &Limits) -> Result<(), ImportError> {
+    if text.len() > limits.input_len {
+        return Err(ImportError {
+            line: 0,
+            kind: ImportErrorKind::TooBig,
+        });
+    }
+    Ok(())
+}
+
+/// Writes a log in the format read by [`tagtime_log`]. Ping gaps and answer times are lost, and
+/// brackets in comments are dropped so the comment can be read back.
+pub fn write_tagtime_log(log: &PingLog) -> String {
+    let mut out = String::new();
+    for ping in log.pings() {
+        out += &ping.time.to_string();
+        for tag in &ping.tags {
+            out.push(' ');
+            out += tag;
+        }
+        if let Some(comment) = &ping.comment {
+            let comment: String = comment
+                .chars()
+                .filter(|c| !matches!(c, '(' | ')' | '[' | ']'))
+                .collect();
+            out += &format!(" ({})", comment);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_tagtime_log() {
+        let log = tagtime_log(
+            "1184098080 work email (replying to bob) [2007.07.10 16:08:00 TUE]\n\
+             \n\
+             1184097393 afk  [2007.07.10 15:56:33 TUE]\n",
+            2700,
+        )
+        .unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(
+            log.get(0).unwrap(),
+            Ping::new(1184097393, vec!["afk".into()], 2700)
+        );
+        let ping = log.get(1).unwrap();
+        assert_eq!(ping.tags.to_vec(), vec!["work", "email"]);
+        assert_eq!(ping.comment, Some("replying to bob"));
+    }
+
+    #[test]
+    fn import_errors_have_lines() {
+        let err = tagtime_log("1 a\nnope b\n", 2700).unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(matches!(err.kind, ImportErrorKind::BadTimestamp(_)));
+        assert_eq!(err.to_string(), "line 2: expected a timestamp");
+        assert_eq!(
+            err.source().map(ToString::to_string),
+            Some("invalid digit found in string".to_string())
+        );
+        assert_eq!(tagtime_log("1 a (oops", 2700).unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn reads_android_csv() {
+        let log = android_csv(
+            "_id,ping,notes,period,tags\r\n\
+             1,1300000000000,\"lunch, with \"\"bob\"\"\",30,\"eat social\"\r\n\
+             2,1299990000,,,\"work,email\"\r\n\
+             3,,,,\r\n",
+            2700,
+        )
+        .unwrap();
+        assert_eq!(log.len(), 2);
+        let mut first = Ping::new(1299990000, vec!["work".into(), "email".into()], 2700);
+        assert_eq!(log.get(0).unwrap(), first);
+        first = Ping::new(1300000000, vec!["eat".into(), "social".into()], 1800);
+        first.comment = Some("lunch, with \"bob\"".into());
+        assert_eq!(log.get(1).unwrap(), first);
+    }
+
+    #[test]
+    fn android_csv_errors() {
+        assert_eq!(
+            android_csv("_id,tags\n", 2700).unwrap_err().kind,
+            ImportErrorKind::NoPingColumn
+        );
+        let err = android_csv("ping,tags,notes\n1,a,\"multi\nline\"\nnope,b,\n", 2700).unwrap_err();
+        assert_eq!(
+            err,
+            ImportError {
+                line: 4,
+                kind: ImportErrorKind::BadTimestamp("nope".parse::<u64>().unwrap_err()),
+            }
+        );
+        assert_eq!(android_csv("ping,tags\n1,\"a", 2700).unwrap_err().line, 2);
+        assert!(android_csv("", 2700).unwrap().is_empty());
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut ping = Ping::new(20, vec!["a".into(), "b".into()], 2700);
+        ping.comment = Some("hi (there)".to_string());
+        let log = PingLog::from_pings(vec![Ping::new(10, vec![], 2700), ping]);
+        let text = write_tagtime_log(&log);
+        assert_eq!(text, "10\n20 a b (hi there)\n");
+        let read = tagtime_log(&text, 2700).unwrap();
+        assert_eq!(read.get(1).unwrap().comment, Some("hi there"));
+        assert_eq!(read.get(0).unwrap(), log.get(0).unwrap());
+    }
+
+    #[test]
+    fn limits_size() {
+        let limits = Limits {
+            input_len: 20,
+            ..Limits::DEFAULT
+        };
+        let text = "1184097393 a\n1184097400 b\n";
+        let too_big = Err(ImportError {
+            line: 0,
+            kind: ImportErrorKind::TooBig,
+        });
+        assert_eq!(tagtime_log_with_limits(text, 2700, &limits), too_big);
+        assert_eq!(
+            tagtime_log_with_limits(&text[..13], 2700, &limits)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            android_csv_with_limits("ping,tags\n1184097393,a b c d e\n", 2700, &limits),
+            too_big
+        );
+    }
+}
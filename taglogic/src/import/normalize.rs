@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::log::{Ping, PingLog};
+use crate::unicode::extends_grapheme;
+
+/// An error adding a rule to a [`Normalizer`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum NormalizeError {
+    #[error("invalid pattern: {0}")]
+    Pattern(String),
+}
+
+/// Rules for cleaning up tags from other tools, like `Work,` and `e-mail` into `work` and
+/// `email`. Rules are run in the order regex replacements, stripping punctuation, lowercasing,
+/// and then aliases, so aliases are written as tags look after the rest. Tags that end up empty
+/// are dropped, and so are repeats.
+///
+/// ```
+/// use taglogic::import::Normalizer;
+///
+/// let normalizer = Normalizer::new()
+///     .lowercase()
+///     .strip_punctuation()
+///     .alias("e-mail", "email");
+/// let tags = normalizer.normalize_tags(vec!["Work,".into(), "E-mail".into(), "work".into()]);
+/// assert_eq!(tags, ["work", "email"]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Normalizer {
+    lowercase: bool,
+    strip_punctuation: bool,
+    aliases: HashMap<String, String>,
+    #[cfg(feature = "regex")]
+    replacements: Vec<(regex::Regex, String)>,
+}
+
+/// How a tag would be changed by a [`Normalizer`], from [`Normalizer::dry_run`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagChange {
+    pub from: String,
+    /// The new tag, or None if it's dropped.
+    pub to: Option<String>,
+    /// How many pings have the tag.
+    pub pings: usize,
+}
+
+impl Normalizer {
+    /// A normalizer that doesn't change anything yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lowercases tags.
+    pub fn lowercase(mut self) -> Self {
+        self.lowercase = true;
+        self
+    }
+
+    /// Removes everything from tags except letters, digits, marks on them, `-` and `_`.
+    pub fn strip_punctuation(mut self) -> Self {
+        self.strip_punctuation = true;
+        self
+    }
+
+    /// Changes `from` to `to`, like `e-mail` to `email`. An empty `to` drops the tag.
+    pub fn alias(mut self, from: &str, to: &str) -> Self {
+        self.aliases.insert(from.to_string(), to.to_string());
+        self
+    }
+
+    /// Replaces every match of the regex `pattern` in tags with `replacement`, which can refer
+    /// to groups like `$1`.
+    #[cfg(feature = "regex")]
+    pub fn replace(mut self, pattern: &str, replacement: &str) -> Result<Self, NormalizeError> {
+        let regex =
+            regex::Regex::new(pattern).map_err(|err| NormalizeError::Pattern(err.to_string()))?;
+        self.replacements.push((regex, replacement.to_string()));
+        Ok(self)
+    }
+
+    /// The tag after the rules, or None if it's dropped.
+    pub fn normalize_tag(&self, tag: &str) -> Option<String> {
+        let mut tag = tag.to_string();
+        #[cfg(feature = "regex")]
+        for (regex, replacement) in &self.replacements {
+            tag = regex.replace_all(&tag, replacement.as_str()).into_owned();
+        }
+        if self.strip_punctuation {
+            tag.retain(|c| c.is_alphanumeric() || extends_grapheme(c) || c == '-' || c == '_');
+        }
+        if self.lowercase {
+            tag = tag.to_lowercase();
+        }
+        if let Some(alias) = self.aliases.get(&tag) {
+            tag = alias.clone();
+        }
+        // replacements can add spaces, which would split the tag
+        tag.retain(|c| !c.is_whitespace());
+        Some(tag).filter(|tag| !tag.is_empty())
+    }
+
+    /// Each of the tags after the rules, without ones that are dropped or repeated.
+    pub fn normalize_tags(&self, tags: Vec<String>) -> Vec<String> {
+        let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+        for tag in tags.iter().filter_map(|tag| self.normalize_tag(tag)) {
+            if !normalized.contains(&tag) {
+                normalized.push(tag);
+            }
+        }
+        normalized
+    }
+
+    /// The log with every ping's tags normalized, like after importing it.
+    pub fn apply(&self, log: &PingLog) -> PingLog {
+        let pings = log.pings().iter().map(|ping| Ping {
+            tags: self.normalize_tags(ping.tags.to_vec()),
+            ..ping.to_ping()
+        });
+        PingLog::from_pings(pings.collect())
+    }
+
+    /// What [`apply`](Self::apply) would change, without changing anything: each tag in the log
+    /// that would be changed or dropped, sorted by tag.
+    pub fn dry_run(&self, log: &PingLog) -> Vec<TagChange> {
+        let mut pings: BTreeMap<&str, usize> = BTreeMap::new();
+        for ping in log.pings() {
+            for tag in ping.tags {
+                *pings.entry(tag).or_default() += 1;
+            }
+        }
+        pings
+            .into_iter()
+            .filter_map(|(tag, pings)| {
+                let to = self.normalize_tag(tag);
+                (to.as_deref() != Some(tag)).then(|| TagChange {
+                    from: tag.to_string(),
+                    to,
+                    pings,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tags(tags: &str) -> Vec<String> {
+        tags.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn runs_rules_in_order() {
+        let normalizer = Normalizer::new().lowercase().alias("mtg", "meeting");
+        assert_eq!(normalizer.normalize_tag("MTG"), Some("meeting".into()));
+        assert_eq!(normalizer.normalize_tag("Work,"), Some("work,".into()));
+
+        let normalizer = normalizer.strip_punctuation().alias("junk", "");
+        assert_eq!(normalizer.normalize_tag("(Work),"), Some("work".into()));
+        assert_eq!(
+            normalizer.normalize_tag("deep_work"),
+            Some("deep_work".into())
+        );
+        assert_eq!(
+            normalizer.normalize_tag("Cafe\u{301}!"),
+            Some("cafe\u{301}".into())
+        );
+        assert_eq!(normalizer.normalize_tag("..."), None);
+        assert_eq!(normalizer.normalize_tag("junk"), None);
+        assert_eq!(normalizer.normalize_tags(tags("A a! junk b")), tags("a b"));
+        assert_eq!(
+            Normalizer::new().normalize_tag("Work,"),
+            Some("Work,".into())
+        );
+    }
+
+    #[test]
+    fn dry_runs() {
+        let log = PingLog::from_pings(vec![
+            Ping::new(10, tags("Work e-mail"), 2700),
+            Ping::new(20, tags("work E-mail ???"), 2700),
+        ]);
+        let normalizer = Normalizer::new()
+            .lowercase()
+            .strip_punctuation()
+            .alias("e-mail", "email");
+        let change = |from: &str, to: Option<&str>, pings| TagChange {
+            from: from.into(),
+            to: to.map(String::from),
+            pings,
+        };
+        assert_eq!(
+            normalizer.dry_run(&log),
+            [
+                change("???", None, 1),
+                change("E-mail", Some("email"), 1),
+                change("Work", Some("work"), 1),
+                change("e-mail", Some("email"), 1),
+            ]
+        );
+        let normalized = normalizer.apply(&log);
+        let all: Vec<Vec<String>> = (normalized.pings().iter())
+            .map(|ping| ping.tags.to_vec())
+            .collect();
+        assert_eq!(all, [tags("work email"), tags("work email")]);
+        assert!(normalizer.dry_run(&normalized).is_empty());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn replaces_with_regexes() {
+        let normalizer = Normalizer::new()
+            .replace(r"^proj:(\w+)$", "$1")
+            .unwrap()
+            .replace(r"\s+", "-")
+            .unwrap();
+        assert_eq!(normalizer.normalize_tag("proj:ttw"), Some("ttw".into()));
+        assert!(matches!(
+            Normalizer::new().replace("(", ""),
+            Err(NormalizeError::Pattern(_))
+        ));
+    }
+}
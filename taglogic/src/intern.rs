@@ -0,0 +1,191 @@
+//! Tags as dense ids instead of strings, so matching a log against an expression never compares
+//! strings.
+//!
+//! [`PingLog`](crate::log::PingLog) stores its tags as ids from its own [`TagInterner`]. Compile
+//! expressions with [`TagInterner::compile`] to match them against ids.
+
+use std::hash::Hasher;
+
+#[cfg(feature = "expr")]
+use crate::bool::{CompiledExpr, Expr};
+
+/// Marks an empty slot in [`TagInterner`]'s table.
+const EMPTY: u32 = u32::MAX;
+
+/// Gives each distinct tag a number, counting up from 0 in the order they're first seen.
+///
+/// Each tag's text is stored once, in one string with every tag. Ids are found with a hash table
+/// of ids into that string, rather than a map from owned strings, so there's no allocation per
+/// tag, and the table only takes 4 bytes per slot.
+#[derive(Debug, Clone, Default)]
+pub struct TagInterner {
+    /// The text of every tag, one after another.
+    text: String,
+    /// Where each tag ends in `text`, by id. Each tag starts where the last one ends.
+    ends: Vec<u32>,
+    /// Open addressing with linear probing, where each slot is an id or [`EMPTY`]. Its length is
+    /// a power of two, at least twice the number of tags.
+    table: Vec<u32>,
+}
+
+impl TagInterner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The tag's id, giving it a new one if it hasn't been seen before.
+    pub fn intern(&mut self, tag: &str) -> u32 {
+        if (self.ends.len() + 1) * 2 > self.table.len() {
+            self.grow();
+        }
+        let slot = match self.find(tag) {
+            Ok(id) => return id,
+            Err(slot) => slot,
+        };
+        let id = self.ends.len() as u32;
+        self.text.push_str(tag);
+        self.ends.push(self.text.len() as u32);
+        self.table[slot] = id;
+        id
+    }
+
+    /// The tag's id, if it's been seen.
+    pub fn get(&self, tag: &str) -> Option<u32> {
+        if self.table.is_empty() {
+            return None;
+        }
+        self.find(tag).ok()
+    }
+
+    /// The tag's id, or the empty slot it would go in.
+    fn find(&self, tag: &str) -> Result<u32, usize> {
+        let mask = self.table.len() - 1;
+        let mut slot = hash(tag) as usize & mask;
+        loop {
+            match self.table[slot] {
+                EMPTY => return Err(slot),
+                id if self.name_unchecked(id) == tag => return Ok(id),
+                _ => slot = (slot + 1) & mask,
+            }
+        }
+    }
+
+    /// Doubles the table, putting every id back in.
+    fn grow(&mut self) {
+        let len = (self.table.len() * 2).max(16);
+        self.table = vec![EMPTY; len];
+        for id in 0..self.ends.len() as u32 {
+            let mut slot = hash(self.name_unchecked(id)) as usize & (len - 1);
+            while self.table[slot] != EMPTY {
+                slot = (slot + 1) & (len - 1);
+            }
+            self.table[slot] = id;
+        }
+    }
+
+    /// The tag with an id.
+    pub fn name(&self, id: u32) -> Option<&str> {
+        if (id as usize) < self.ends.len() {
+            Some(self.name_unchecked(id))
+        } else {
+            None
+        }
+    }
+
+    /// The tag with an id, panicking if there isn't one.
+    pub(crate) fn name_unchecked(&self, id: u32) -> &str {
+        let start = match id {
+            0 => 0,
+            _ => self.ends[id as usize - 1] as usize,
+        };
+        &self.text[start..self.ends[id as usize] as usize]
+    }
+
+    /// Every tag, in order of id.
+    pub fn names(&self) -> impl ExactSizeIterator<Item = &str> + Clone + '_ {
+        (0..self.ends.len() as u32).map(move |id| self.name_unchecked(id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// Bytes allocated for the tags.
+    pub fn memory_usage(&self) -> usize {
+        self.text.capacity() + (self.ends.capacity() + self.table.capacity()) * 4
+    }
+
+    /// Compiles an expression to match ids from this interner. Tags interned afterwards never
+    /// match, so compile again after adding pings with new tags.
+    #[cfg(feature = "expr")]
+    pub fn compile(&self, expr: &Expr) -> CompiledExpr {
+        expr.compile_with_table(&self.names().collect::<Vec<_>>())
+    }
+}
+
+impl PartialEq for TagInterner {
+    /// Interners are equal if they have the same tags with the same ids.
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text && self.ends == other.ends
+    }
+}
+
+impl Eq for TagInterner {}
+
+fn hash(tag: &str) -> u64 {
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(tag.as_bytes());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interns() {
+        let mut interner = TagInterner::new();
+        assert_eq!(interner.intern("a"), 0);
+        assert_eq!(interner.intern("b"), 1);
+        assert_eq!(interner.intern("a"), 0);
+        assert_eq!(interner.get("b"), Some(1));
+        assert_eq!(interner.get("c"), None);
+        assert_eq!(interner.name(1), Some("b"));
+        assert_eq!(interner.name(2), None);
+        assert_eq!(interner.len(), 2);
+        assert_eq!(interner.names().collect::<Vec<_>>(), ["a", "b"]);
+    }
+
+    #[test]
+    fn interns_many() {
+        // enough to grow the table a few times
+        let mut interner = TagInterner::new();
+        for i in 0..1000 {
+            assert_eq!(interner.intern(&format!("tag{}", i)), i);
+        }
+        assert_eq!(interner.intern(""), 1000);
+        for i in 0..1000 {
+            let tag = format!("tag{}", i);
+            assert_eq!(interner.get(&tag), Some(i));
+            assert_eq!(interner.name(i), Some(tag.as_str()));
+        }
+        assert_eq!(interner.get(""), Some(1000));
+        assert_eq!(interner.get("tag1000"), None);
+        assert!(interner.memory_usage() < 1001 * 24);
+        assert_eq!(TagInterner::new().get("a"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "expr")]
+    fn compiles() {
+        let mut interner = TagInterner::new();
+        let (a, b) = (interner.intern("a"), interner.intern("b"));
+        let expr = interner.compile(&Expr::parse("a & !b").unwrap());
+        assert!(expr.matches_ids(&[a]));
+        assert!(!expr.matches_ids(&[a, b]));
+    }
+}
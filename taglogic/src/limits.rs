@@ -0,0 +1,86 @@
+//! Limits on the input that parsers accept, so broken or hostile input gets an error instead of
+//! overflowing the stack or running out of memory. In WASM, either of those aborts the module and
+//! takes down the whole page.
+//!
+//! Parsers take a [`Limits`] in their `_with_limits` variants, and use [`Limits::DEFAULT`]
+//! otherwise. Binary formats ([`binlog`](crate::binlog) and [`schedule`](crate::schedule)) aren't
+//! recursive and check every length against the input, so they don't need limits. Protobuf
+//! messages are decoded by `prost`, which has its own nesting limit of 100.
+
+/// How big and how deeply nested input can be.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// Deepest nesting of brackets, `!`, and operators in an expression. Matching and compiling
+    /// recurse as deep as parsing, so this bounds them too.
+    pub expr_depth: u16,
+    /// Expressions must be shorter than this many bytes. Anything over 65535 is treated as that.
+    pub expr_len: usize,
+    /// Deepest nesting of JSON requests and GraphQL queries.
+    pub nesting_depth: usize,
+    /// Biggest log to import, or JSON request, in bytes.
+    pub input_len: usize,
+}
+
+impl Limits {
+    pub const DEFAULT: Limits = Limits {
+        expr_depth: 20,
+        expr_len: 200,
+        nesting_depth: 32,
+        input_len: 64 << 20,
+    };
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Checks that brackets in JSON aren't nested more than `depth` deep, without parsing it, so
+/// it's safe to parse with a recursive parser. Brackets in strings are skipped.
+#[cfg(feature = "commands")]
+pub(crate) fn check_json_depth(json: &str, depth: usize) -> Result<(), &'static str> {
+    let mut nesting = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for byte in json.bytes() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                nesting += 1;
+                if nesting > depth {
+                    return Err("JSON nested too deeply");
+                }
+            }
+            b']' | b'}' => nesting = nesting.saturating_sub(1),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "commands"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn json_depth() {
+        assert_eq!(check_json_depth(r#"{"a": [1, {"b": []}]}"#, 4), Ok(()));
+        assert_eq!(
+            check_json_depth(r#"{"a": [1, {"b": []}]}"#, 3),
+            Err("JSON nested too deeply")
+        );
+        // brackets in strings, even after escaped quotes, don't count
+        assert_eq!(check_json_depth(r#"["\"[[[", "\\", "{{"]"#, 1), Ok(()));
+        assert!(check_json_depth(&"[".repeat(100_000), 32).is_err());
+    }
+}
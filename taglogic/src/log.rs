@@ -0,0 +1,1319 @@
+//! In-memory repersentation of a user's answered pings.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "expr")]
+use crate::bool::{CommentMatcher, EvalContext, Expr, Functions, LogStats};
+use crate::intern::TagInterner;
+
+mod audit;
+mod diff;
+mod edit;
+#[cfg(feature = "expr")]
+mod seq;
+mod weights;
+
+pub use audit::{AmendError, Amendment, DUPLICATE_REASON};
+pub use diff::{FieldChange, LogDiff, PatchError, PingDiff};
+pub use edit::{Edit, EditError, PingChange, Preview};
+#[cfg(feature = "expr")]
+pub use seq::SeqQuery;
+pub use weights::{Weight, Weights};
+
+/// A single answered ping. Mirrors the `Ping` interface used by the web frontend.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify), tsify(missing_as_null))]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ping {
+    /// Unix timestamp (in seconds) of when the ping was sent.
+    pub time: u64,
+    pub tags: Vec<String>,
+    /// The average ping gap (in seconds) at the time the ping was answered. Each ping is taken to
+    /// repersent this much time.
+    pub interval: u32,
+    pub comment: Option<String>,
+    /// Unix timestamp (in seconds) of when the ping was answered, if it was recorded.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub answered: Option<u64>,
+    /// Who answered the ping, in logs shared by a team. Expressions match it as the tag
+    /// `@author`, so `@alice & work` is Alice's work. A ping's own tags starting with `@` still
+    /// match as themselves, so `@alice` also matches pings tagged that way.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub author: Option<String>,
+    /// Tags that only get part of the ping's time, for pings split between things, like
+    /// `work:0.7 email:0.3`. Stats give each weighted tag its weight of the time (scaled down if
+    /// they add up to more than 1), and tags without a weight all of it.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    #[cfg_attr(feature = "wasm", tsify(optional, type = "Record<string, number>"))]
+    #[cfg_attr(feature = "graphql", graphql(skip))]
+    pub weights: BTreeMap<String, Weight>,
+}
+
+impl Ping {
+    pub fn new(time: u64, tags: Vec<String>, interval: u32) -> Self {
+        Self {
+            time,
+            tags,
+            interval,
+            comment: None,
+            answered: None,
+            author: None,
+            weights: BTreeMap::new(),
+        }
+    }
+
+    /// A ping answered with `entry`, tags separated by spaces, where tags can have weights like
+    /// `work:0.7 email:0.3`. Weights are decimals with a point, up to 1, so a tag like `level:3`
+    /// is just a tag.
+    ///
+    /// ```
+    /// use taglogic::log::Ping;
+    ///
+    /// let ping = Ping::from_entry(0, "work:0.7 email:0.3 home", 2700);
+    /// assert_eq!(ping.tags, ["work", "email", "home"]);
+    /// assert_eq!(ping.weights["work"].get(), 0.7);
+    /// assert!(!ping.weights.contains_key("home"));
+    /// assert_eq!(Ping::from_entry(0, "level:3", 2700).tags, ["level:3"]);
+    /// ```
+    pub fn from_entry(time: u64, entry: &str, interval: u32) -> Self {
+        let mut ping = Self::new(time, Vec::new(), interval);
+        for tag in entry.split_whitespace() {
+            match weights::split_weight(tag) {
+                Some((tag, weight)) => {
+                    ping.tags.push(tag.to_string());
+                    ping.weights.insert(tag.to_string(), weight);
+                }
+                None => ping.tags.push(tag.to_string()),
+            }
+        }
+        ping
+    }
+
+    /// Returns if the ping's tags (and `@author`) match an expression, with any
+    /// [comment searches](Expr::matches_ping) searching its comment.
+    #[cfg(feature = "expr")]
+    pub fn matches(&self, expr: &Expr) -> bool {
+        let author = self.author.as_deref().map(author_tag);
+        let tags = match_tags(self.tags.iter().map(String::as_str), author.as_deref());
+        expr.matches_ping(&tags, self.comment.as_deref())
+    }
+
+    /// Like [`Ping::matches`], with calls in the expression made to `functions`.
+    #[cfg(feature = "expr")]
+    pub fn matches_with(&self, expr: &Expr, functions: &Functions) -> bool {
+        let author = self.author.as_deref().map(author_tag);
+        let tags = match_tags(self.tags.iter().map(String::as_str), author.as_deref());
+        let ping = EvalContext::new(&tags)
+            .with_timestamp(self.time)
+            .with_comment(self.comment.as_deref());
+        expr.matches_with(&ping, functions)
+    }
+
+    /// The fraction of the ping's time that matches an expression, which is 0 or 1 unless some
+    /// tags are [weighted](Self::weights).
+    #[cfg(feature = "expr")]
+    pub fn share(&self, expr: &Expr) -> f64 {
+        let author = self.author.as_deref().map(author_tag);
+        let tags = match_tags(self.tags.iter().map(String::as_str), author.as_deref());
+        let weights: Vec<(&str, Weight)> = (self.weights.iter())
+            .filter(|(tag, _)| self.tags.contains(tag))
+            .map(|(tag, &weight)| (tag.as_str(), weight))
+            .collect();
+        let expr = CommentMatcher::new(expr);
+        let comment = self.comment.as_deref();
+        weights::share(&|tags| expr.matches(tags, comment), &tags, &weights)
+    }
+
+    /// The parts of the ping's time with different tags, from its [weighted tags](Self::weights),
+    /// as each part's tags and its fraction of the time. Without weighted tags, it's one part.
+    #[cfg(feature = "expr")]
+    pub(crate) fn parts(&self) -> Vec<(Vec<&str>, f64)> {
+        let tags: Vec<&str> = self.tags.iter().map(String::as_str).collect();
+        let weights: Vec<(&str, Weight)> = (self.weights.iter())
+            .filter(|(tag, _)| self.tags.contains(tag))
+            .map(|(tag, &weight)| (tag.as_str(), weight))
+            .collect();
+        weights::parts(&tags, &weights)
+    }
+}
+
+/// The tags a ping is matched with: its own `tags`, and its author's tag, if it has one.
+#[cfg(feature = "expr")]
+fn match_tags<'t>(
+    tags: impl IntoIterator<Item = &'t str>,
+    author_tag: Option<&'t str>,
+) -> Vec<&'t str> {
+    let mut tags: Vec<&str> = tags.into_iter().collect();
+    tags.extend(author_tag);
+    tags
+}
+
+/// The tag an author is matched as.
+fn author_tag(author: &str) -> String {
+    format!("@{}", author)
+}
+
+/// A ping's weighted tags, as interned tag ids sorted by tag.
+type TagWeights = Box<[(u32, Weight)]>;
+
+/// A collection of pings, always kept sorted from oldest to newest.
+///
+/// Pings are stored as columns (one `Vec` per field) rather than as a `Vec<Ping>`, with tags
+/// interned, so a log takes a few big allocations instead of several per ping, and scanning one
+/// field doesn't load the others. Pings are read through [`PingRef`] views.
+#[derive(Debug)]
+pub struct PingLog {
+    times: Vec<u64>,
+    intervals: Vec<u32>,
+    answered: Vec<Option<u64>>,
+    comments: Vec<Option<Box<str>>>,
+    /// The interned `@author` tag of each ping's author.
+    authors: Vec<Option<u32>>,
+    /// Each ping's weighted tags, sorted by tag, or None if it doesn't have any.
+    weights: Vec<Option<TagWeights>>,
+    /// Ping `i`'s tags are `tag_ids[tag_offsets[i]..tag_offsets[i + 1]]`, in their original
+    /// order.
+    tag_offsets: Vec<u32>,
+    tag_ids: Vec<u32>,
+    interner: TagInterner,
+    generation: u64,
+    /// Changes made by [`PingLog::amend`] and [`PingLog::collapse_duplicates`], oldest first.
+    audit: Vec<Amendment>,
+}
+
+/// A generation no log has had before.
+fn next_generation() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+impl PingLog {
+    pub fn new() -> Self {
+        Self {
+            times: Vec::new(),
+            intervals: Vec::new(),
+            answered: Vec::new(),
+            comments: Vec::new(),
+            authors: Vec::new(),
+            weights: Vec::new(),
+            tag_offsets: vec![0],
+            tag_ids: Vec::new(),
+            interner: TagInterner::new(),
+            generation: next_generation(),
+            audit: Vec::new(),
+        }
+    }
+
+    pub fn from_pings(mut pings: Vec<Ping>) -> Self {
+        pings.sort_by_key(|ping| ping.time);
+        let mut log = Self::new();
+        for ping in pings {
+            log.insert(log.len(), ping);
+        }
+        log
+    }
+
+    /// Adds a ping to the log, keeping it sorted. Appending a ping newer than every other ping
+    /// (the common case) doesn't need to move any existing pings.
+    pub fn push(&mut self, ping: Ping) {
+        let index = self.times.partition_point(|&time| time <= ping.time);
+        if index < self.len() {
+            self.generation = next_generation();
+        }
+        self.insert(index, ping);
+    }
+
+    fn insert(&mut self, index: usize, ping: Ping) {
+        let weights: Vec<(u32, Weight)> = (ping.weights.iter())
+            .filter(|(tag, _)| ping.tags.contains(tag))
+            .map(|(tag, &weight)| (self.interner.intern(tag), weight))
+            .collect();
+        self.weights.insert(
+            index,
+            Some(weights.into_boxed_slice()).filter(|w| !w.is_empty()),
+        );
+        self.times.insert(index, ping.time);
+        self.intervals.insert(index, ping.interval);
+        self.answered.insert(index, ping.answered);
+        self.comments
+            .insert(index, ping.comment.map(String::into_boxed_str));
+        let author =
+            (ping.author.as_deref()).map(|author| self.interner.intern(&author_tag(author)));
+        self.authors.insert(index, author);
+        let start = self.tag_offsets[index] as usize;
+        let interner = &mut self.interner;
+        let ids: Vec<u32> = ping.tags.iter().map(|tag| interner.intern(tag)).collect();
+        let added = ids.len() as u32;
+        self.tag_ids.splice(start..start, ids);
+        for offset in &mut self.tag_offsets[index + 1..] {
+            *offset += added;
+        }
+        self.tag_offsets.insert(index + 1, start as u32 + added);
+    }
+
+    /// Identifies the log's pings, for caches like [`QueryCache`](crate::cache::QueryCache).
+    /// Appending pings keeps the generation, since the pings already in the log are unchanged.
+    /// Any other edit gives the log a generation no other log (including its clones) has had.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// All pings, oldest to newest.
+    pub fn pings(&self) -> PingSlice<'_> {
+        PingSlice {
+            log: self,
+            indices: 0..self.len(),
+        }
+    }
+
+    /// Pings sent in the range `start..end`.
+    pub fn range(&self, start: u64, end: u64) -> PingSlice<'_> {
+        let from = self.times.partition_point(|&time| time < start);
+        let to = self.times.partition_point(|&time| time < end);
+        PingSlice {
+            log: self,
+            indices: from..to.max(from),
+        }
+    }
+
+    /// The ping at `index`, counting from the oldest.
+    pub fn get(&self, index: usize) -> Option<PingRef<'_>> {
+        if index >= self.len() {
+            return None;
+        }
+        Some(PingRef {
+            time: self.times[index],
+            tags: Tags {
+                ids: self.tag_ids(index),
+                interner: &self.interner,
+            },
+            interval: self.intervals[index],
+            comment: self.comments[index].as_deref(),
+            answered: self.answered[index],
+            author: self.author(index),
+            weights: Weights {
+                entries: self.weights[index].as_deref().unwrap_or_default(),
+                interner: &self.interner,
+            },
+        })
+    }
+
+    /// The author of the ping at `index`, without the `@`.
+    fn author(&self, index: usize) -> Option<&str> {
+        let id = self.authors[index]?;
+        Some(&self.interner.name_unchecked(id)[1..])
+    }
+
+    /// The id of the interned `@author` tag of the ping at `index`, if it has an author.
+    pub(crate) fn author_id(&self, index: usize) -> Option<u32> {
+        self.authors[index]
+    }
+
+    /// Every author in the log, in the order they first answered a ping.
+    pub fn authors(&self) -> Vec<&str> {
+        let mut authors: Vec<u32> = Vec::new();
+        for &id in self.authors.iter().flatten() {
+            if !authors.contains(&id) {
+                authors.push(id);
+            }
+        }
+        let name = |id| &self.interner.name_unchecked(id)[1..];
+        authors.into_iter().map(name).collect()
+    }
+
+    /// Times of every ping, oldest to newest.
+    pub fn times(&self) -> &[u64] {
+        &self.times
+    }
+
+    /// The interner the log's tags are stored with.
+    pub fn interner(&self) -> &TagInterner {
+        &self.interner
+    }
+
+    /// The fraction of pings with each tag, for [`Expr::reorder`]. It's counted when this is
+    /// called, so later changes to the log aren't seen.
+    #[cfg(feature = "expr")]
+    pub fn tag_frequency(&self) -> impl Fn(&str) -> f64 + '_ {
+        let stats = self.stats();
+        move |tag| stats.frequency(tag)
+    }
+
+    /// A summary of the log, counted in one pass: how many pings have each tag and each author,
+    /// the first and last ping times, and the time the pings stand for.
+    #[cfg(feature = "expr")]
+    pub fn stats(&self) -> LogStats {
+        let mut counts = vec![0u64; self.interner.len()];
+        // authors are interned as their `@author` tags, but counted apart from tags
+        let mut author_counts = vec![0u64; self.interner.len()];
+        for index in 0..self.len() {
+            let ids = self.tag_ids(index);
+            for (i, &id) in ids.iter().enumerate() {
+                if !ids[..i].contains(&id) {
+                    counts[id as usize] += 1;
+                }
+            }
+            if let Some(author) = self.authors[index] {
+                author_counts[author as usize] += 1;
+            }
+        }
+        let mut stats = LogStats::new(self.len() as u64);
+        stats.span = self
+            .times
+            .first()
+            .zip(self.times.last())
+            .map(|(&first, &last)| (first, last));
+        stats.interval_secs = self
+            .intervals
+            .iter()
+            .map(|&interval| u64::from(interval))
+            .sum();
+        for ((tag, count), author_count) in self.interner.names().zip(counts).zip(author_counts) {
+            if count > 0 {
+                stats.set_tag_pings(tag, count);
+            }
+            if author_count > 0 {
+                stats.set_author_pings(&tag[1..], author_count);
+            }
+        }
+        stats
+    }
+
+    fn tag_ids(&self, index: usize) -> &[u32] {
+        &self.tag_ids[self.tag_offsets[index] as usize..self.tag_offsets[index + 1] as usize]
+    }
+
+    /// Whether each ping matches an expression, oldest to newest. Much faster than calling
+    /// [`PingRef::matches`] on every ping, since tags are matched by their interned ids.
+    #[cfg(feature = "expr")]
+    pub fn matches_many(&self, expr: &Expr) -> Vec<bool> {
+        matches_each(&self.pings(), expr)
+    }
+
+    /// Which pings have each tag, as bitsets for [`TagBitsets::matches`].
+    pub fn tag_bitsets(&self) -> TagBitsets<'_> {
+        let words = self.len().div_ceil(32);
+        let mut bits = vec![0; self.interner.len() * words];
+        for index in 0..self.len() {
+            for &id in self.tag_ids(index).iter().chain(&self.authors[index]) {
+                bits[id as usize * words + index / 32] |= 1 << (index % 32);
+            }
+        }
+        TagBitsets {
+            log: self,
+            pings: self.len(),
+            bits,
+        }
+    }
+
+    /// Bytes allocated for the log, by what they're for.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let comments = self
+            .comments
+            .iter()
+            .flatten()
+            .map(|comment| comment.len())
+            .sum::<usize>();
+        MemoryUsage {
+            times: self.times.capacity() * 8,
+            intervals: self.intervals.capacity() * 4,
+            answered: self.answered.capacity() * std::mem::size_of::<Option<u64>>(),
+            comments: self.comments.capacity() * std::mem::size_of::<Option<Box<str>>>() + comments,
+            tags: (self.tag_offsets.capacity() + self.tag_ids.capacity()) * 4
+                + self.authors.capacity() * std::mem::size_of::<Option<u32>>()
+                + self.weights.capacity() * std::mem::size_of::<Option<TagWeights>>()
+                + (self.weights.iter().flatten())
+                    .map(|weights| std::mem::size_of_val(&**weights))
+                    .sum::<usize>(),
+            interner: self.interner.memory_usage(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+}
+
+/// Bytes allocated by a [`PingLog`], from [`PingLog::memory_usage`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub times: usize,
+    pub intervals: usize,
+    pub answered: usize,
+    pub comments: usize,
+    /// Each ping's tag ids, author and weights.
+    pub tags: usize,
+    /// The text of each distinct tag, and the table for finding their ids.
+    pub interner: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.times + self.intervals + self.answered + self.comments + self.tags + self.interner
+    }
+}
+
+impl Default for PingLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for PingLog {
+    /// Clones the pings. The clone gets a new generation, since it can be edited separately.
+    fn clone(&self) -> Self {
+        Self {
+            times: self.times.clone(),
+            intervals: self.intervals.clone(),
+            answered: self.answered.clone(),
+            comments: self.comments.clone(),
+            authors: self.authors.clone(),
+            weights: self.weights.clone(),
+            tag_offsets: self.tag_offsets.clone(),
+            tag_ids: self.tag_ids.clone(),
+            interner: self.interner.clone(),
+            generation: next_generation(),
+            audit: self.audit.clone(),
+        }
+    }
+}
+
+/// Logs are equal if they have the same pings, no matter how their tags were interned or their
+/// generations.
+impl PartialEq for PingLog {
+    fn eq(&self, other: &Self) -> bool {
+        self.pings() == other.pings()
+    }
+}
+
+impl Eq for PingLog {}
+
+/// A view of a ping in a [`PingLog`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PingRef<'a> {
+    /// Unix timestamp (in seconds) of when the ping was sent.
+    pub time: u64,
+    pub tags: Tags<'a>,
+    /// The average ping gap (in seconds) at the time the ping was answered.
+    pub interval: u32,
+    pub comment: Option<&'a str>,
+    /// Unix timestamp (in seconds) of when the ping was answered, if it was recorded.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub answered: Option<u64>,
+    /// Who answered the ping, if the log is shared.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub author: Option<&'a str>,
+    /// Tags that only get part of the ping's time, like in [`Ping::weights`].
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Weights::is_empty"))]
+    pub weights: Weights<'a>,
+}
+
+impl<'a> PingRef<'a> {
+    /// Copies the ping out of the log.
+    pub fn to_ping(&self) -> Ping {
+        Ping {
+            time: self.time,
+            tags: self.tags.to_vec(),
+            interval: self.interval,
+            comment: self.comment.map(String::from),
+            answered: self.answered,
+            author: self.author.map(String::from),
+            weights: (self.weights.iter())
+                .map(|(tag, weight)| (tag.to_string(), weight))
+                .collect(),
+        }
+    }
+
+    /// Like [`Ping::matches`].
+    #[cfg(feature = "expr")]
+    pub fn matches(&self, expr: &Expr) -> bool {
+        let author = self.author.map(author_tag);
+        let tags = match_tags(self.tags.iter(), author.as_deref());
+        expr.matches_ping(&tags, self.comment)
+    }
+
+    /// Like [`Ping::matches_with`].
+    #[cfg(feature = "expr")]
+    pub fn matches_with(&self, expr: &Expr, functions: &Functions) -> bool {
+        let author = self.author.map(author_tag);
+        let tags = match_tags(self.tags.iter(), author.as_deref());
+        let ping = EvalContext::new(&tags)
+            .with_timestamp(self.time)
+            .with_comment(self.comment);
+        expr.matches_with(&ping, functions)
+    }
+
+    /// Like [`Ping::share`].
+    #[cfg(feature = "expr")]
+    pub fn share(&self, expr: &Expr) -> f64 {
+        let author = self.author.map(author_tag);
+        let tags = match_tags(self.tags.iter(), author.as_deref());
+        let weights: Vec<(&str, Weight)> = self.weights.iter().collect();
+        let expr = CommentMatcher::new(expr);
+        weights::share(&|tags| expr.matches(tags, self.comment), &tags, &weights)
+    }
+
+    /// Like [`Ping::parts`]. Match the parts with [`part_matches`](Self::part_matches).
+    #[cfg(feature = "expr")]
+    pub(crate) fn parts(&self) -> Vec<(Vec<&'a str>, f64)> {
+        let tags: Vec<&str> = self.tags.iter().collect();
+        let weights: Vec<(&str, Weight)> = self.weights.iter().collect();
+        weights::parts(&tags, &weights)
+    }
+
+    /// Whether `expr` matches the part of the ping with `tags`, from [`parts`](Self::parts),
+    /// along with the ping's author and comment.
+    #[cfg(feature = "expr")]
+    pub(crate) fn part_matches(&self, expr: &CommentMatcher<'_>, tags: &[&str]) -> bool {
+        let author = self.author.map(author_tag);
+        let tags = match_tags(tags.iter().copied(), author.as_deref());
+        expr.matches(&tags, self.comment)
+    }
+}
+
+impl PartialEq<Ping> for PingRef<'_> {
+    fn eq(&self, other: &Ping) -> bool {
+        self.time == other.time
+            && self.tags.iter().eq(other.tags.iter().map(String::as_str))
+            && self.interval == other.interval
+            && self.comment == other.comment.as_deref()
+            && self.answered == other.answered
+            && self.author == other.author.as_deref()
+            && (self.weights.iter()).eq((other.weights.iter())
+                .filter(|(tag, _)| other.tags.contains(tag))
+                .map(|(tag, &weight)| (tag.as_str(), weight)))
+    }
+}
+
+/// The tags of a [`PingRef`], in the order they were added.
+#[derive(Copy, Clone)]
+pub struct Tags<'a> {
+    ids: &'a [u32],
+    interner: &'a TagInterner,
+}
+
+impl<'a> Tags<'a> {
+    pub fn iter(&self) -> TagIter<'a> {
+        TagIter {
+            ids: self.ids.iter(),
+            interner: self.interner,
+        }
+    }
+
+    /// Whether one of the tags is `tag`. Doesn't compare any strings.
+    pub fn contains(&self, tag: &str) -> bool {
+        self.interner
+            .get(tag)
+            .is_some_and(|id| self.ids.contains(&id))
+    }
+
+    /// The tags' ids in the log's [interner](PingLog::interner).
+    pub fn ids(&self) -> &'a [u32] {
+        self.ids
+    }
+
+    pub fn to_vec(&self) -> Vec<String> {
+        self.iter().map(String::from).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for Tags<'a> {
+    type Item = &'a str;
+    type IntoIter = TagIter<'a>;
+
+    fn into_iter(self) -> TagIter<'a> {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &Tags<'a> {
+    type Item = &'a str;
+    type IntoIter = TagIter<'a>;
+
+    fn into_iter(self) -> TagIter<'a> {
+        self.iter()
+    }
+}
+
+impl PartialEq for Tags<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for Tags<'_> {}
+
+impl fmt::Debug for Tags<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Tags<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+/// An iterator over [`Tags`].
+#[derive(Debug, Clone)]
+pub struct TagIter<'a> {
+    ids: std::slice::Iter<'a, u32>,
+    interner: &'a TagInterner,
+}
+
+impl<'a> Iterator for TagIter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.ids.next().map(|&id| self.interner.name_unchecked(id))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for TagIter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.ids
+            .next_back()
+            .map(|&id| self.interner.name_unchecked(id))
+    }
+}
+
+impl ExactSizeIterator for TagIter<'_> {}
+
+/// A run of consecutive pings in a [`PingLog`], like a `&[Ping]`.
+#[derive(Debug, Clone)]
+pub struct PingSlice<'a> {
+    log: &'a PingLog,
+    indices: Range<usize>,
+}
+
+impl<'a> PingSlice<'a> {
+    /// Whether each ping matches `expr`, with calls made to `functions`. Each ping is matched in
+    /// an [`EvalContext`] with the time zone and facts of `context`, and the ping before it
+    /// (even if that's before the slice).
+    #[cfg(feature = "expr")]
+    pub fn matches_with(
+        &self,
+        expr: &Expr,
+        functions: &Functions,
+        context: &EvalContext<'_>,
+    ) -> Vec<bool> {
+        fn in_context<'c>(
+            ping: &PingRef<'c>,
+            tags: &'c [&'c str],
+            context: &EvalContext<'c>,
+        ) -> EvalContext<'c> {
+            EvalContext::new(tags)
+                .with_timestamp(ping.time)
+                .with_comment(ping.comment)
+                .with_tz(context.tz)
+                .with_extra(context.extra)
+        }
+        (self.indices.clone())
+            .filter_map(|index| {
+                let ping = self.log.get(index)?;
+                let prev = index.checked_sub(1).and_then(|prev| self.log.get(prev));
+                let author = ping.author.map(author_tag);
+                let tags = match_tags(ping.tags.iter(), author.as_deref());
+                let prev_author = prev.as_ref().and_then(|prev| prev.author.map(author_tag));
+                let prev_tags = (prev.as_ref())
+                    .map(|prev| match_tags(prev.tags.iter(), prev_author.as_deref()));
+                let prev = (prev.as_ref().zip(prev_tags.as_deref()))
+                    .map(|(prev, tags)| in_context(prev, tags, context));
+                let mut ping = in_context(&ping, &tags, context);
+                if let Some(prev) = &prev {
+                    ping = ping.with_prev_ping(prev);
+                }
+                Some(expr.matches_with(&ping, functions))
+            })
+            .collect()
+    }
+
+    pub fn iter(&self) -> Iter<'a> {
+        Iter {
+            log: self.log,
+            indices: self.indices.clone(),
+        }
+    }
+
+    /// The ping at `index`, counting from the start of the slice.
+    pub fn get(&self, index: usize) -> Option<PingRef<'a>> {
+        if index < self.len() {
+            self.log.get(self.indices.start + index)
+        } else {
+            None
+        }
+    }
+
+    pub fn first(&self) -> Option<PingRef<'a>> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<PingRef<'a>> {
+        self.len().checked_sub(1).and_then(|index| self.get(index))
+    }
+
+    /// Divides the slice in two at `mid`, like [`slice::split_at`].
+    ///
+    /// ## Panics
+    /// Panics if `mid > len`.
+    pub fn split_at(&self, mid: usize) -> (PingSlice<'a>, PingSlice<'a>) {
+        assert!(mid <= self.len(), "mid is out of bounds");
+        let mid = self.indices.start + mid;
+        (
+            PingSlice {
+                log: self.log,
+                indices: self.indices.start..mid,
+            },
+            PingSlice {
+                log: self.log,
+                indices: mid..self.indices.end,
+            },
+        )
+    }
+
+    /// Times of the pings, oldest to newest.
+    pub fn times(&self) -> &'a [u64] {
+        &self.log.times[self.indices.clone()]
+    }
+
+    /// Copies the pings out of the log.
+    pub fn to_vec(&self) -> Vec<Ping> {
+        self.iter().map(|ping| ping.to_ping()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for PingSlice<'a> {
+    type Item = PingRef<'a>;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &PingSlice<'a> {
+    type Item = PingRef<'a>;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl PartialEq for PingSlice<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for PingSlice<'_> {}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for PingSlice<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+/// An iterator over the pings in a [`PingSlice`].
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    log: &'a PingLog,
+    indices: Range<usize>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = PingRef<'a>;
+
+    fn next(&mut self) -> Option<PingRef<'a>> {
+        self.indices.next().and_then(|index| self.log.get(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.indices.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.indices
+            .next_back()
+            .and_then(|index| self.log.get(index))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+/// A bitset for each tag of a log, where bit `i % 32` of word `i / 32` is set if ping `i` has the
+/// tag. Matching an expression on these combines whole words of pings at once, so it's much
+/// faster than [`PingLog::matches_many`] when matching several expressions against the same log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagBitsets<'a> {
+    log: &'a PingLog,
+    pings: usize,
+    /// The bitset of tag id `t` is the `t`th run of `pings.div_ceil(32)` words.
+    bits: Vec<u32>,
+}
+
+impl TagBitsets<'_> {
+    /// The bitset of pings with `tag`, or `None` if no ping has it.
+    pub fn tag(&self, tag: &str) -> Option<&[u32]> {
+        let words = self.pings.div_ceil(32);
+        let id = self.log.interner.get(tag)? as usize;
+        Some(&self.bits[id * words..(id + 1) * words])
+    }
+
+    /// A bitset of the pings that match `expr`. Comments aren't in the bitsets, so expressions
+    /// that [search them](Expr::matches_ping) are matched a ping at a time instead.
+    #[cfg(feature = "expr")]
+    pub fn matches(&self, expr: &Expr) -> Vec<u32> {
+        if !expr.searches_comments() {
+            return expr
+                .compile()
+                .matches_bitsets(self.pings, &|tag| self.tag(tag));
+        }
+        let mut bits = vec![0; self.pings.div_ceil(32)];
+        for (index, matched) in self.log.matches_many(expr).into_iter().enumerate() {
+            bits[index / 32] |= u32::from(matched) << (index % 32);
+        }
+        bits
+    }
+
+    /// Number of pings.
+    pub fn len(&self) -> usize {
+        self.pings
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pings == 0
+    }
+}
+
+/// Whether each ping matches `expr`, matching tags by their ids. With the `parallel` feature, the
+/// pings are split between every core.
+#[cfg(feature = "expr")]
+pub(crate) fn matches_each(pings: &PingSlice<'_>, expr: &Expr) -> Vec<bool> {
+    let log = pings.log;
+    // comments aren't interned, so searching them needs the tags as text
+    let searcher = CommentMatcher::new(expr);
+    if searcher.searches_comments() {
+        return (pings.iter())
+            .map(|ping| {
+                let author = ping.author.map(author_tag);
+                searcher.matches(
+                    &match_tags(ping.tags.iter(), author.as_deref()),
+                    ping.comment,
+                )
+            })
+            .collect();
+    }
+    let expr = log.interner.compile(expr);
+    let matches = |index| match log.authors[index] {
+        Some(author) => expr.matches_id_iter(log.tag_ids(index).iter().copied().chain([author])),
+        None => expr.matches_ids(log.tag_ids(index)),
+    };
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        pings.indices.clone().into_par_iter().map(matches).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    pings.indices.clone().map(matches).collect()
+}
+
+/// The fraction of each ping's time that matches `expr`, like [`matches_each`] but with
+/// [weighted tags](Ping::weights) splitting pings.
+#[cfg(feature = "expr")]
+pub(crate) fn match_shares(pings: &PingSlice<'_>, expr: &Expr) -> Vec<f64> {
+    let matched = matches_each(pings, expr);
+    (pings.iter().zip(matched))
+        .map(|(ping, matched)| match (ping.weights.is_empty(), matched) {
+            (true, true) => 1.0,
+            (true, false) => 0.0,
+            (false, _) => ping.share(expr),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ping(time: u64, tags: &str) -> Ping {
+        Ping::new(time, tags.split(' ').map(String::from).collect(), 2700)
+    }
+
+    #[cfg(feature = "expr")]
+    #[test]
+    fn searches_comments() {
+        let commented = Ping {
+            comment: Some("Standup ran long".to_string()),
+            ..ping(10, "work")
+        };
+        let log = PingLog::from_pings(vec![commented.clone(), ping(20, "work")]);
+        let expr = Expr::parse(r#"work & comment:"standup""#).unwrap();
+        assert_eq!(matches_each(&log.pings(), &expr), [true, false]);
+        assert!(commented.matches(&expr));
+        assert!(log.get(0).unwrap().matches(&expr));
+    }
+
+    #[test]
+    fn keeps_weights() {
+        let ping = Ping::from_entry(10, "work:0.7 email:0.3 home", 2700);
+        let mut log = PingLog::from_pings(vec![ping.clone(), self::ping(20, "a")]);
+        let stored = log.get(0).unwrap();
+        assert_eq!(stored, ping);
+        assert_eq!(stored.weights.get("work").map(Weight::get), Some(0.7));
+        assert_eq!(stored.weights.get("home"), None);
+        assert_eq!(stored.to_ping(), ping);
+        assert!(log.get(1).unwrap().weights.is_empty());
+
+        log.amend(10, vec!["work".to_string()], "no email", 30)
+            .unwrap();
+        let amended = log.get(0).unwrap();
+        assert_eq!(amended.weights.len(), 1);
+        assert!(log.clone().get(0).unwrap().weights.get("email").is_none());
+    }
+
+    #[test]
+    fn stays_sorted() {
+        let mut log = PingLog::from_pings(vec![ping(30, "a"), ping(10, "b")]);
+        log.push(ping(20, "c"));
+        log.push(ping(40, "d"));
+        let times: Vec<u64> = log.pings().iter().map(|ping| ping.time).collect();
+        assert_eq!(times, vec![10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn columns() {
+        let mut first = ping(10, "b a b");
+        first.comment = Some("hi".to_string());
+        first.answered = Some(12);
+        let pings = vec![ping(30, "c"), first.clone(), ping(20, "a")];
+        let mut log = PingLog::from_pings(pings);
+        log.push(ping(15, "d a"));
+
+        assert_eq!(log.times(), &[10, 15, 20, 30]);
+        assert_eq!(log.get(0).unwrap(), first);
+        assert_eq!(log.get(0).unwrap().to_ping(), first);
+        assert_eq!(log.get(1).unwrap().tags.to_vec(), vec!["d", "a"]);
+        assert!(log.get(3).unwrap().tags.contains("c"));
+        assert!(!log.get(3).unwrap().tags.contains("a"));
+        assert!(log.get(4).is_none());
+        assert_eq!(
+            log.interner().names().collect::<Vec<_>>(),
+            ["b", "a", "c", "d"]
+        );
+
+        let pings = log.pings();
+        assert_eq!(pings.last().unwrap().time, 30);
+        assert_eq!(pings.iter().next_back(), pings.last());
+        assert_eq!(pings.to_vec()[2], ping(20, "a"));
+        assert_eq!(PingLog::from_pings(pings.to_vec()), log);
+    }
+
+    #[test]
+    fn generations() {
+        let mut log = PingLog::from_pings(vec![ping(10, "a"), ping(20, "b")]);
+        let generation = log.generation();
+        log.push(ping(30, "c"));
+        log.push(ping(30, "d"));
+        assert_eq!(log.generation(), generation);
+        log.push(ping(25, "e"));
+        assert_ne!(log.generation(), generation);
+
+        let clone = log.clone();
+        assert_eq!(clone, log);
+        assert_ne!(clone.generation(), log.generation());
+        assert_ne!(PingLog::new().generation(), PingLog::new().generation());
+    }
+
+    #[test]
+    fn range_is_half_open() {
+        let log = PingLog::from_pings(vec![ping(10, "a"), ping(20, "b"), ping(30, "c")]);
+        assert_eq!(log.range(10, 30), log.pings().split_at(2).0);
+        assert_eq!(log.range(11, 31), log.pings().split_at(1).1);
+        assert!(log.range(31, 100).is_empty());
+        assert!(log.range(30, 10).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "expr")]
+    fn ping_matches() {
+        let expr = Expr::from_string("a & !b").unwrap();
+        assert!(ping(10, "a c").matches(&expr));
+        assert!(!ping(10, "a b").matches(&expr));
+    }
+
+    #[test]
+    #[cfg(feature = "expr")]
+    fn calls_functions() {
+        let mut functions = Functions::new();
+        functions.register("after", |arg: &str, ping: &EvalContext<'_>| {
+            arg.parse()
+                .is_ok_and(|after: u64| ping.timestamp > Some(after))
+        });
+        let log = PingLog::from_pings(vec![ping(10, "work"), ping(20, "work")]);
+        let expr = Expr::parse("work & after(15)").unwrap();
+        assert!(!log.get(0).unwrap().matches_with(&expr, &functions));
+        assert!(log.get(1).unwrap().matches_with(&expr, &functions));
+        assert!(ping(20, "work").matches_with(&expr, &functions));
+    }
+
+    #[test]
+    #[cfg(feature = "expr")]
+    fn matches_many() {
+        let log = PingLog::from_pings(vec![ping(10, "a c"), ping(20, "a b"), ping(30, "c")]);
+        let expr = Expr::from_string("a & !b").unwrap();
+        assert_eq!(log.matches_many(&expr), vec![true, false, false]);
+        assert!(log
+            .pings()
+            .iter()
+            .all(|ping| !ping.matches(&expr) || ping.time == 10));
+    }
+
+    #[test]
+    #[cfg(feature = "expr")]
+    fn evaluates_in_context() {
+        let mut functions = Functions::new();
+        functions.register("fact", |arg: &str, ping: &EvalContext<'_>| {
+            ping.fact(arg).is_some()
+        });
+        functions.register("after_comment", |_: &str, ping: &EvalContext<'_>| {
+            ping.prev_ping.is_some_and(|prev| prev.comment.is_some())
+        });
+        let commented = Ping {
+            comment: Some("Standup ran long".to_string()),
+            ..ping(10, "work")
+        };
+        let log = PingLog::from_pings(vec![commented, ping(20, "work")]);
+        let facts = BTreeMap::from([("raining".to_string(), String::new())]);
+        let context = EvalContext::new(&[]).with_extra(&facts);
+        let expr = Expr::parse("after_comment() & fact(raining)").unwrap();
+        let pings = log.range(15, 30);
+        assert_eq!(pings.matches_with(&expr, &functions, &context), [true]);
+        let context = EvalContext::new(&[]);
+        assert_eq!(pings.matches_with(&expr, &functions, &context), [false]);
+        let expr = Expr::parse("after_comment()").unwrap();
+        assert_eq!(
+            log.range(0, 15).matches_with(&expr, &functions, &context),
+            [false]
+        );
+    }
+
+    #[test]
+    fn memory_usage() {
+        let log = PingLog::from_pings(vec![ping(10, "a b"), ping(20, "a c")]);
+        let usage = log.memory_usage();
+        assert!(usage.times >= 16);
+        assert!(usage.tags >= (3 + 4) * 4);
+        assert!(usage.interner >= 3);
+        assert_eq!(
+            usage.total(),
+            usage.times
+                + usage.intervals
+                + usage.answered
+                + usage.comments
+                + usage.tags
+                + usage.interner
+        );
+        assert!(PingLog::new().memory_usage().total() < usage.total());
+    }
+
+    #[test]
+    #[cfg(feature = "expr")]
+    fn tag_frequency() {
+        let log = PingLog::from_pings(vec![ping(10, "a a c"), ping(20, "a b"), ping(30, "c")]);
+        let frequency = log.tag_frequency();
+        assert_eq!(frequency("a"), 2.0 / 3.0);
+        assert_eq!(frequency("b"), 1.0 / 3.0);
+        assert_eq!(frequency("nope"), 0.0);
+        assert_eq!(PingLog::new().tag_frequency()("a"), 0.0);
+
+        let mut authored = ping(40, "a");
+        authored.author = Some("bo".to_string());
+        let log = PingLog::from_pings(log.pings().to_vec().into_iter().chain([authored]).collect());
+        let stats = log.stats();
+        assert_eq!(stats.pings, 4);
+        assert_eq!(
+            (
+                stats.tag_pings("a"),
+                stats.tag_pings("c"),
+                stats.tag_pings("@bo"),
+                stats.author_pings("bo")
+            ),
+            (3, 2, 0, 1)
+        );
+        assert_eq!(stats.tags().collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert_eq!(stats.authors().collect::<Vec<_>>(), ["bo"]);
+        assert_eq!(stats.frequency("@bo"), 0.25);
+        assert_eq!(stats.span, Some((10, 40)));
+        assert_eq!(stats.density(), Some(4.0 * 86400.0 / 30.0));
+
+        // a literal `@bo` tag is a tag, not one of Bo's pings
+        let log = PingLog::from_pings(
+            log.pings()
+                .to_vec()
+                .into_iter()
+                .chain([ping(50, "@bo")])
+                .collect(),
+        );
+        let stats = log.stats();
+        assert_eq!((stats.tag_pings("@bo"), stats.author_pings("bo")), (1, 1));
+        assert_eq!(stats.tags().collect::<Vec<_>>(), ["@bo", "a", "b", "c"]);
+    }
+
+    #[test]
+    #[cfg(feature = "expr")]
+    fn bitsets_match_like_matches_many() {
+        // more than two words of pings, so the last word is partly used
+        let names = ["a", "b", "c", "a b", "b c", "a c d"];
+        let log = PingLog::from_pings(
+            (0..70)
+                .map(|i| ping(i * 10, names[(i * i % 7 % 6) as usize]))
+                .collect(),
+        );
+        let bitsets = log.tag_bitsets();
+        assert_eq!(bitsets.len(), 70);
+        assert_eq!(bitsets.tag("nope"), None);
+        for expr in [
+            "",
+            "a",
+            "!a",
+            "nope",
+            "!nope",
+            "a & !b | c",
+            "(a | d) & !(b & c)",
+        ] {
+            let expr = Expr::from_string(expr).unwrap();
+            let bits = bitsets.matches(&expr);
+            assert_eq!(bits.len(), 3);
+            let matches: Vec<bool> = (0..80).map(|i| bits[i / 32] >> (i % 32) & 1 == 1).collect();
+            assert_eq!(matches[..70], log.matches_many(&expr)[..], "{}", expr);
+            assert!(matches[70..].iter().all(|&matched| !matched), "{}", expr);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "expr")]
+    fn comment_searches_match_like_reference() {
+        use crate::binlog::{write_binary_log, BinaryLog};
+
+        let comments = [None, Some("Standup ran long"), Some("lunch"), Some("")];
+        let log = PingLog::from_pings(
+            (0..40)
+                .map(|i| Ping {
+                    comment: comments[i % 4].map(String::from),
+                    ..ping(i as u64 * 10, ["a", "b", "a b"][i % 3])
+                })
+                .collect(),
+        );
+        let binary = BinaryLog::new(write_binary_log(&log)).unwrap();
+        for expr in [
+            r#"comment:"standup""#,
+            r#"a & !comment:"LUNCH""#,
+            r#"comment:"" | b"#,
+            r#"!(comment:"ran" | comment:"lunch") & a"#,
+        ] {
+            let expr = Expr::parse(expr).unwrap();
+            let expected: Vec<bool> = (log.pings().iter())
+                .map(|ping| {
+                    expr.matches_slow_ping(&ping.tags.iter().collect::<Vec<_>>(), ping.comment)
+                })
+                .collect();
+            assert_eq!(log.matches_many(&expr), expected, "{}", expr);
+            let bits = log.tag_bitsets().matches(&expr);
+            let from_bits: Vec<bool> = (0..40).map(|i| bits[i / 32] >> (i % 32) & 1 == 1).collect();
+            assert_eq!(from_bits, expected, "{}", expr);
+            assert_eq!(
+                binary.matches_each(0..40, &expr).unwrap(),
+                expected,
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[cfg(feature = "arbitrary")]
+    mod properties {
+        use super::*;
+        use crate::binlog::{write_binary_log, BinaryLog};
+        use crate::bool::ARBITRARY_TAGS;
+        use arbitrary::{Arbitrary, Unstructured};
+        use proptest::prelude::*;
+
+        proptest! {
+            /// Every way of matching a whole log gives what the reference evaluator does.
+            #[test]
+            fn matches_like_reference(bytes in any::<Vec<u8>>(), sets in any::<Vec<u8>>()) {
+                let expr = Expr::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+                // each byte picks which of the 8 tags a ping has
+                let pings = sets.iter().enumerate().map(|(i, &set)| {
+                    let tags = ARBITRARY_TAGS
+                        .iter()
+                        .enumerate()
+                        .filter(|&(bit, _)| set >> bit & 1 == 1)
+                        .map(|(_, tag)| tag.to_string())
+                        .collect();
+                    Ping::new(i as u64, tags, 2700)
+                });
+                let log = PingLog::from_pings(pings.collect());
+                let expected: Vec<bool> = log
+                    .pings()
+                    .iter()
+                    .map(|ping| expr.matches_slow(&ping.tags.iter().collect::<Vec<_>>()))
+                    .collect();
+
+                prop_assert_eq!(&log.matches_many(&expr), &expected, "{}", expr);
+                let bits = log.tag_bitsets().matches(&expr);
+                let from_bits: Vec<bool> =
+                    (0..log.len()).map(|i| bits[i / 32] >> (i % 32) & 1 == 1).collect();
+                prop_assert_eq!(&from_bits, &expected);
+                let binary = BinaryLog::new(write_binary_log(&log)).unwrap();
+                prop_assert_eq!(&binary.matches_each(0..log.len(), &expr).unwrap(), &expected);
+            }
+        }
+    }
+}
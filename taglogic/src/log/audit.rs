@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use super::edit::Edit;
+use super::{next_generation, PingLog, Weight};
+
+/// A change to a ping's tags, kept in the log's [audit trail](PingLog::audit) so it's clear
+/// what was edited and when.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Amendment {
+    /// Unix timestamp (in seconds) of when the amended ping was sent.
+    pub time: u64,
+    /// Unix timestamp (in seconds) of when the ping was amended.
+    pub at: u64,
+    pub old_tags: Vec<String>,
+    pub new_tags: Vec<String>,
+    /// Why the tags were changed, in the user's words.
+    pub reason: String,
+}
+
+/// An error amending a [`PingLog`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum AmendError {
+    #[error("no ping was sent at {0}")]
+    NoPing(u64),
+}
+
+/// The reason recorded for answers dropped by [`PingLog::collapse_duplicates`].
+pub const DUPLICATE_REASON: &str = "duplicate answer";
+
+impl PingLog {
+    /// Changes the tags of the ping sent at `time` to `new_tags`, as if it was answered again
+    /// at `at`, and adds the change to the [audit trail](Self::audit) instead of losing the old
+    /// tags. If more than one ping was sent at `time`, the last one is amended. Amending a ping
+    /// to the tags it already has changes nothing. Weights stay on the tags the ping still has.
+    pub fn amend(
+        &mut self,
+        time: u64,
+        new_tags: Vec<String>,
+        reason: &str,
+        at: u64,
+    ) -> Result<(), AmendError> {
+        let index = self.last_at(time).ok_or(AmendError::NoPing(time))?;
+        let mut weights = self.weights_at(index);
+        weights.retain(|tag, _| new_tags.contains(tag));
+        self.retag(index, new_tags, weights, reason, at);
+        Ok(())
+    }
+
+    /// The [weights](super::Ping::weights) of the ping at `index`.
+    pub(super) fn weights_at(&self, index: usize) -> BTreeMap<String, Weight> {
+        (self.get(index).into_iter())
+            .flat_map(|ping| ping.weights.iter())
+            .map(|(tag, weight)| (tag.to_string(), weight))
+            .collect()
+    }
+
+    /// Index of the last ping sent at `time`.
+    pub(super) fn last_at(&self, time: u64) -> Option<usize> {
+        let index = self.times.partition_point(|&t| t <= time).checked_sub(1)?;
+        Some(index).filter(|&index| self.times[index] == time)
+    }
+
+    /// Changes the tags and weights of the ping at `index`, like [`PingLog::amend`]. Weights of
+    /// tags not in `new_tags` are ignored.
+    pub(super) fn retag(
+        &mut self,
+        index: usize,
+        new_tags: Vec<String>,
+        new_weights: BTreeMap<String, Weight>,
+        reason: &str,
+        at: u64,
+    ) {
+        let old_tags = self.get(index).map(|ping| ping.tags.to_vec());
+        let old_tags = old_tags.unwrap_or_default();
+        if old_tags == new_tags && self.weights_at(index) == new_weights {
+            return;
+        }
+        let time = self.times[index];
+
+        let interner = &mut self.interner;
+        let ids: Vec<u32> = new_tags.iter().map(|tag| interner.intern(tag)).collect();
+        let weights: Vec<(u32, Weight)> = (new_weights.iter())
+            .filter(|(tag, _)| new_tags.contains(tag))
+            .map(|(tag, &weight)| (interner.intern(tag), weight))
+            .collect();
+        self.weights[index] = Some(weights.into_boxed_slice()).filter(|w| !w.is_empty());
+        let (start, end) = (self.tag_offsets[index], self.tag_offsets[index + 1]);
+        let added = ids.len() as u32;
+        self.tag_ids.splice(start as usize..end as usize, ids);
+        for offset in &mut self.tag_offsets[index + 1..] {
+            *offset = *offset - (end - start) + added;
+        }
+        self.answered[index] = Some(at);
+        self.generation = next_generation();
+        self.audit.push(Amendment {
+            time,
+            at,
+            old_tags,
+            new_tags,
+            reason: reason.to_string(),
+        });
+    }
+
+    /// Collapses pings sent at the same time, which happens when a ping is answered on two
+    /// devices, into the one answered last (or added last, if they were answered at the same
+    /// time). Each answer that's dropped goes in the [audit trail](Self::audit) as an amendment
+    /// made `at` to the kept tags, with [`DUPLICATE_REASON`]. Returns how many were dropped.
+    pub fn collapse_duplicates(&mut self, at: u64) -> usize {
+        let dedupe = Edit::Dedupe.apply(self, DUPLICATE_REASON, at);
+        dedupe.map_or(0, |preview| preview.changes().len())
+    }
+
+    /// Which of the duplicate pings at `indices` [`PingLog::collapse_duplicates`] keeps: the last
+    /// of the latest answers.
+    pub(super) fn kept_duplicate(&self, indices: Range<usize>) -> usize {
+        let start = indices.start;
+        indices.max_by_key(|&i| self.answered[i]).unwrap_or(start)
+    }
+
+    /// Removes the ping at `index`, returning its tags.
+    pub(super) fn remove(&mut self, index: usize) -> Vec<String> {
+        let tags = self.get(index).map(|ping| ping.tags.to_vec());
+        self.times.remove(index);
+        self.intervals.remove(index);
+        self.answered.remove(index);
+        self.comments.remove(index);
+        self.authors.remove(index);
+        self.weights.remove(index);
+        let (start, end) = (self.tag_offsets[index], self.tag_offsets[index + 1]);
+        self.tag_ids.drain(start as usize..end as usize);
+        self.tag_offsets.remove(index + 1);
+        for offset in &mut self.tag_offsets[index + 1..] {
+            *offset -= end - start;
+        }
+        tags.unwrap_or_default()
+    }
+
+    /// Every amendment made to the log, in the order they were made.
+    pub fn audit(&self) -> &[Amendment] {
+        &self.audit
+    }
+
+    /// The amendments to the ping sent at `time`, in the order they were made.
+    pub fn history(&self, time: u64) -> impl Iterator<Item = &Amendment> {
+        self.audit
+            .iter()
+            .filter(move |amendment| amendment.time == time)
+    }
+
+    /// The amendments made in the range `at`, like the ones made after a goal's deadline.
+    pub fn amended_between(&self, at: Range<u64>) -> impl Iterator<Item = &Amendment> {
+        (self.audit.iter()).filter(move |amendment| at.contains(&amendment.at))
+    }
+
+    /// The tags the ping sent at `time` had at the moment `at`, before any amendments made
+    /// after it, or None if there's no such ping.
+    pub fn tags_as_of(&self, time: u64, at: u64) -> Option<Vec<String>> {
+        let later = self.history(time).find(|amendment| amendment.at > at);
+        if let Some(amendment) = later {
+            return Some(amendment.old_tags.clone());
+        }
+        let index = self.times.partition_point(|&t| t <= time).checked_sub(1)?;
+        let ping = self.get(index).filter(|ping| ping.time == time)?;
+        Some(ping.tags.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+
+    fn tags(tags: &str) -> Vec<String> {
+        tags.split_whitespace().map(String::from).collect()
+    }
+
+    fn ping(time: u64, names: &str) -> Ping {
+        Ping::new(time, tags(names), 2700)
+    }
+
+    #[test]
+    fn amends_with_history() {
+        let mut log = PingLog::from_pings(vec![ping(10, "a"), ping(20, "b c"), ping(30, "d")]);
+        let generation = log.generation();
+        log.amend(20, tags("work"), "forgot I was working", 100)
+            .unwrap();
+        log.amend(20, tags("work email"), "was emailing too", 200)
+            .unwrap();
+        log.amend(10, tags("a"), "no change", 300).unwrap();
+        assert_ne!(log.generation(), generation);
+        assert_eq!(
+            log.amend(15, tags("x"), "", 300),
+            Err(AmendError::NoPing(15))
+        );
+
+        // the other pings' tags didn't move
+        let all: Vec<Vec<String>> = log.pings().iter().map(|ping| ping.tags.to_vec()).collect();
+        assert_eq!(all, [tags("a"), tags("work email"), tags("d")]);
+        assert_eq!(log.get(1).unwrap().answered, Some(200));
+
+        assert_eq!(log.audit().len(), 2);
+        assert_eq!(log.history(20).count(), 2);
+        assert_eq!(log.history(10).count(), 0);
+        let amended: Vec<&str> = (log.amended_between(150..250))
+            .map(|amendment| amendment.reason.as_str())
+            .collect();
+        assert_eq!(amended, ["was emailing too"]);
+
+        assert_eq!(log.tags_as_of(20, 50), Some(tags("b c")));
+        assert_eq!(log.tags_as_of(20, 100), Some(tags("work")));
+        assert_eq!(log.tags_as_of(20, 500), Some(tags("work email")));
+        assert_eq!(log.tags_as_of(30, 50), Some(tags("d")));
+        assert_eq!(log.tags_as_of(25, 50), None);
+    }
+
+    #[test]
+    fn collapses_duplicates() {
+        let mut phone = ping(20, "phone");
+        phone.answered = Some(50);
+        let mut laptop = ping(20, "laptop");
+        laptop.answered = Some(40);
+        let mut log = PingLog::from_pings(vec![
+            ping(10, "a"),
+            ping(10, "b"),
+            phone,
+            laptop,
+            ping(30, "c"),
+        ]);
+        assert_eq!(log.collapse_duplicates(100), 2);
+        let all: Vec<(u64, Vec<String>)> = (log.pings().iter())
+            .map(|ping| (ping.time, ping.tags.to_vec()))
+            .collect();
+        assert_eq!(all, [(10, tags("b")), (20, tags("phone")), (30, tags("c"))]);
+        assert_eq!(
+            log.audit()[1],
+            Amendment {
+                time: 20,
+                at: 100,
+                old_tags: tags("laptop"),
+                new_tags: tags("phone"),
+                reason: DUPLICATE_REASON.to_string(),
+            }
+        );
+        assert_eq!(log.collapse_duplicates(200), 0);
+        assert_eq!(log.audit().len(), 2);
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::BTreeMap;
+
+use super::{next_generation, Ping, PingLog, Weight};
+
+/// The differences between two versions of a log, from [`PingLog::diff`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogDiff {
+    /// Pings only in the new log.
+    pub added: Vec<Ping>,
+    /// Pings only in the old log.
+    pub removed: Vec<Ping>,
+    /// Pings in both, with some fields changed.
+    pub modified: Vec<PingDiff>,
+}
+
+/// How a ping sent at `time` changed.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PingDiff {
+    pub time: u64,
+    pub changes: Vec<FieldChange>,
+}
+
+/// A field of a [`Ping`] that changed, with its old and new values.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "field", rename_all = "lowercase")
+)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FieldChange {
+    Tags {
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    Interval {
+        old: u32,
+        new: u32,
+    },
+    Comment {
+        old: Option<String>,
+        new: Option<String>,
+    },
+    Answered {
+        old: Option<u64>,
+        new: Option<u64>,
+    },
+    Author {
+        old: Option<String>,
+        new: Option<String>,
+    },
+    Weights {
+        old: BTreeMap<String, Weight>,
+        new: BTreeMap<String, Weight>,
+    },
+}
+
+/// An error from [`PingLog::patch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum PatchError {
+    /// The log doesn't have a ping sent at `time` like the one the diff removes or changes, so
+    /// it's not the log the diff was made from.
+    #[error("no ping at {0} matches the diff")]
+    Conflict(u64),
+}
+
+impl PingDiff {
+    /// The fields that differ between `old` and `new`, or None if none do.
+    fn between(old: &Ping, new: &Ping) -> Option<Self> {
+        let mut changes = Vec::new();
+        macro_rules! compare {
+            ($field:ident, $variant:ident) => {
+                if old.$field != new.$field {
+                    changes.push(FieldChange::$variant {
+                        old: old.$field.clone(),
+                        new: new.$field.clone(),
+                    });
+                }
+            };
+        }
+        compare!(tags, Tags);
+        compare!(interval, Interval);
+        compare!(comment, Comment);
+        compare!(answered, Answered);
+        compare!(author, Author);
+        compare!(weights, Weights);
+        (!changes.is_empty()).then_some(Self {
+            time: new.time,
+            changes,
+        })
+    }
+
+    /// Whether `ping` has the old values of every changed field.
+    fn applies_to(&self, ping: &Ping) -> bool {
+        ping.time == self.time
+            && self.changes.iter().all(|change| match change {
+                FieldChange::Tags { old, .. } => ping.tags == *old,
+                FieldChange::Interval { old, .. } => ping.interval == *old,
+                FieldChange::Comment { old, .. } => ping.comment == *old,
+                FieldChange::Answered { old, .. } => ping.answered == *old,
+                FieldChange::Author { old, .. } => ping.author == *old,
+                FieldChange::Weights { old, .. } => ping.weights == *old,
+            })
+    }
+
+    fn apply(&self, ping: &mut Ping) {
+        for change in &self.changes {
+            match change {
+                FieldChange::Tags { new, .. } => ping.tags = new.clone(),
+                FieldChange::Interval { new, .. } => ping.interval = *new,
+                FieldChange::Comment { new, .. } => ping.comment = new.clone(),
+                FieldChange::Answered { new, .. } => ping.answered = *new,
+                FieldChange::Author { new, .. } => ping.author = new.clone(),
+                FieldChange::Weights { new, .. } => ping.weights = new.clone(),
+            }
+        }
+    }
+}
+
+impl LogDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+impl PingLog {
+    /// What changed from this log to `other`, like for a report of what changed since a backup.
+    /// Pings are matched up by when they were sent. If more than one was sent at the same time,
+    /// the ones that are the same in both are matched first, and then the rest in order.
+    ///
+    /// ```
+    /// use taglogic::log::{FieldChange, Ping, PingLog};
+    ///
+    /// let ping = |time, tag: &str| Ping::new(time, vec![tag.to_string()], 2700);
+    /// let old = PingLog::from_pings(vec![ping(10, "a"), ping(20, "b")]);
+    /// let new = PingLog::from_pings(vec![ping(10, "c"), ping(30, "d")]);
+    /// let diff = old.diff(&new);
+    /// assert_eq!(diff.added, [ping(30, "d")]);
+    /// assert_eq!(diff.removed, [ping(20, "b")]);
+    /// assert_eq!(
+    ///     diff.modified[0].changes,
+    ///     [FieldChange::Tags { old: vec!["a".into()], new: vec!["c".into()] }]
+    /// );
+    ///
+    /// let mut patched = old.clone();
+    /// patched.patch(&diff).unwrap();
+    /// assert!(patched.diff(&new).is_empty());
+    /// ```
+    pub fn diff(&self, other: &PingLog) -> LogDiff {
+        let (old, new) = (self.pings().to_vec(), other.pings().to_vec());
+        let mut diff = LogDiff::default();
+        let (mut i, mut j) = (0, 0);
+        // both are sorted, so they're compared a time at a time
+        while i < old.len() || j < new.len() {
+            let time = match (old.get(i), new.get(j)) {
+                (Some(a), Some(b)) => a.time.min(b.time),
+                (Some(ping), None) | (None, Some(ping)) => ping.time,
+                (None, None) => break,
+            };
+            let old_end = i + old[i..].partition_point(|ping| ping.time == time);
+            let new_end = j + new[j..].partition_point(|ping| ping.time == time);
+            let mut olds: Vec<&Ping> = old[i..old_end].iter().collect();
+            let mut news: Vec<&Ping> = new[j..new_end].iter().collect();
+            olds.retain(|ping| match news.iter().position(|other| other == ping) {
+                Some(same) => {
+                    news.remove(same);
+                    false
+                }
+                None => true,
+            });
+            for (old, new) in olds.iter().zip(&news) {
+                diff.modified.extend(PingDiff::between(old, new));
+            }
+            let paired = olds.len().min(news.len());
+            diff.removed
+                .extend(olds[paired..].iter().map(|&ping| ping.clone()));
+            diff.added
+                .extend(news[paired..].iter().map(|&ping| ping.clone()));
+            i = old_end;
+            j = new_end;
+        }
+        diff
+    }
+
+    /// Makes the changes in `diff`, so a log that [`diff`](Self::diff) was given the old version
+    /// of becomes the new one. Fails without changing anything if a ping that's removed or
+    /// modified isn't in the log like it was in the old version.
+    pub fn patch(&mut self, diff: &LogDiff) -> Result<(), PatchError> {
+        let mut pings = self.pings().to_vec();
+        for removed in &diff.removed {
+            let index = (pings.iter())
+                .rposition(|ping| ping == removed)
+                .ok_or(PatchError::Conflict(removed.time))?;
+            pings.remove(index);
+        }
+        for modified in &diff.modified {
+            let ping = (pings.iter_mut())
+                .rfind(|ping| modified.applies_to(ping))
+                .ok_or(PatchError::Conflict(modified.time))?;
+            modified.apply(ping);
+        }
+        let changed = !diff.removed.is_empty() || !diff.modified.is_empty();
+        if changed {
+            let audit = std::mem::take(&mut self.audit);
+            *self = PingLog::from_pings(pings);
+            self.audit = audit;
+            self.generation = next_generation();
+        }
+        for added in &diff.added {
+            self.push(added.clone());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ping(time: u64, tags: &str) -> Ping {
+        Ping::new(time, tags.split(' ').map(String::from).collect(), 2700)
+    }
+
+    #[test]
+    fn diffs_fields() {
+        let mut answered = ping(10, "a");
+        answered.answered = Some(15);
+        answered.comment = Some("late".to_string());
+        let old = PingLog::from_pings(vec![ping(10, "a"), ping(20, "x"), ping(20, "y")]);
+        let new = PingLog::from_pings(vec![answered, ping(20, "y"), ping(20, "z")]);
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.modified,
+            [
+                PingDiff {
+                    time: 10,
+                    changes: vec![
+                        FieldChange::Comment {
+                            old: None,
+                            new: Some("late".to_string())
+                        },
+                        FieldChange::Answered {
+                            old: None,
+                            new: Some(15)
+                        },
+                    ],
+                },
+                PingDiff {
+                    time: 20,
+                    changes: vec![FieldChange::Tags {
+                        old: vec!["x".to_string()],
+                        new: vec!["z".to_string()]
+                    }],
+                },
+            ]
+        );
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert!(old.diff(&old).is_empty());
+
+        let mut patched = old.clone();
+        patched.patch(&diff).unwrap();
+        assert!(patched.diff(&new).is_empty());
+        // the diff doesn't apply to the new log, since it's already been made
+        let mut again = new.clone();
+        assert_eq!(again.patch(&diff), Err(PatchError::Conflict(10)));
+        assert!(again.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn patches_added_and_removed() {
+        let old = PingLog::from_pings(vec![ping(10, "a"), ping(20, "b")]);
+        let new = PingLog::from_pings(vec![ping(5, "z"), ping(20, "b"), ping(20, "b")]);
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, [ping(5, "z"), ping(20, "b")]);
+        assert_eq!(diff.removed, [ping(10, "a")]);
+        let mut patched = old.clone();
+        patched.patch(&diff).unwrap();
+        assert!(patched.diff(&new).is_empty());
+        assert_eq!(new.diff(&old).removed, diff.added);
+    }
+}
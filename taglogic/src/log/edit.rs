@@ -0,0 +1,387 @@
+use std::collections::BTreeMap;
+
+#[cfg(feature = "expr")]
+use crate::bool::Expr;
+
+use super::audit::Amendment;
+use super::{next_generation, PingLog, Weight};
+
+/// A change to many pings' tags at once, which can be [previewed](Edit::preview) before it's
+/// made.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Edit {
+    /// Sets the tags of the ping sent at `time`, like [`PingLog::amend`].
+    Retag { time: u64, tags: Vec<String> },
+    /// Renames a tag on every ping that has it, keeping its weight.
+    Rename { from: String, to: String },
+    /// Replaces every tag in `from` with `into`, so they count as one tag. Its weight is the
+    /// weights of the tags it replaced added up, unless one of them wasn't weighted.
+    Merge { from: Vec<String>, into: String },
+    /// Drops duplicate answers to the same ping, like [`PingLog::collapse_duplicates`].
+    Dedupe,
+    /// Adds `tag` to every ping that matches `expr` and doesn't have it yet.
+    #[cfg(feature = "expr")]
+    AutoTag { expr: Expr, tag: String },
+}
+
+/// An error previewing or applying an [`Edit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum EditError {
+    #[error("no ping was sent at {0}")]
+    NoPing(u64),
+    /// The log was changed after the preview was made, so applying it might not do what it
+    /// showed.
+    #[error("the log changed since the preview was made")]
+    Stale,
+}
+
+/// How one ping would change.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PingChange {
+    /// Index of the ping in the log.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    index: usize,
+    /// Unix timestamp (in seconds) of when the ping was sent.
+    pub time: u64,
+    pub old_tags: Vec<String>,
+    /// The ping's new tags, or for a removed duplicate, the tags of the answer that's kept.
+    pub new_tags: Vec<String>,
+    /// The [weights](crate::log::Ping::weights) of the ping's tags, before and after the change.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "BTreeMap::is_empty"))]
+    pub old_weights: BTreeMap<String, Weight>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "BTreeMap::is_empty"))]
+    pub new_weights: BTreeMap<String, Weight>,
+    /// Whether the ping is removed, instead of retagged.
+    pub removed: bool,
+}
+
+/// Exactly which pings an [`Edit`] would change, and how, for confirming it before it's made
+/// with [`Preview::apply`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preview {
+    /// The generation of the log the preview is of.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    generation: u64,
+    changes: Vec<PingChange>,
+}
+
+impl Edit {
+    /// The changes the edit would make to `log`, without making them.
+    pub fn preview(&self, log: &PingLog) -> Result<Preview, EditError> {
+        let tags = |index| log.get(index).map(|ping| ping.tags.to_vec());
+        let mut changes = Vec::new();
+        let mut retag = |index: usize, (new_tags, new_weights): (Vec<String>, _)| {
+            let old_tags = tags(index).unwrap_or_default();
+            let old_weights = log.weights_at(index);
+            if old_tags != new_tags || old_weights != new_weights {
+                changes.push(PingChange {
+                    index,
+                    time: log.times[index],
+                    old_tags,
+                    new_tags,
+                    old_weights,
+                    new_weights,
+                    removed: false,
+                });
+            }
+        };
+        match self {
+            Self::Retag { time, tags } => {
+                let index = log.last_at(*time).ok_or(EditError::NoPing(*time))?;
+                let mut weights = log.weights_at(index);
+                weights.retain(|tag, _| tags.contains(tag));
+                retag(index, (tags.clone(), weights));
+            }
+            Self::Rename { from, to } => {
+                for index in 0..log.len() {
+                    retag(index, replaced(log, index, &[from.as_str()], to));
+                }
+            }
+            Self::Merge { from, into } => {
+                let from: Vec<&str> = from.iter().map(String::as_str).collect();
+                for index in 0..log.len() {
+                    retag(index, replaced(log, index, &from, into));
+                }
+            }
+            Self::Dedupe => {
+                let mut index = 0;
+                while index < log.len() {
+                    let time = log.times[index];
+                    let end = index + log.times[index..].partition_point(|&t| t == time);
+                    let kept = log.kept_duplicate(index..end);
+                    // dropped from last to first, like they're removed
+                    for dropped in (index..end).rev().filter(|&i| i != kept) {
+                        changes.push(PingChange {
+                            index: dropped,
+                            time,
+                            old_tags: tags(dropped).unwrap_or_default(),
+                            new_tags: tags(kept).unwrap_or_default(),
+                            old_weights: log.weights_at(dropped),
+                            new_weights: log.weights_at(kept),
+                            removed: true,
+                        });
+                    }
+                    index = end;
+                }
+            }
+            #[cfg(feature = "expr")]
+            Self::AutoTag { expr, tag } => {
+                let matches = super::matches_each(&log.pings(), expr);
+                for (index, matched) in matches.into_iter().enumerate() {
+                    let mut tags = tags(index).unwrap_or_default();
+                    if matched && !tags.contains(tag) {
+                        tags.push(tag.clone());
+                        retag(index, (tags, log.weights_at(index)));
+                    }
+                }
+            }
+        }
+        Ok(Preview {
+            generation: log.generation,
+            changes,
+        })
+    }
+
+    /// Makes the edit, giving what it changed, like applying its [preview](Self::preview).
+    pub fn apply(&self, log: &mut PingLog, reason: &str, at: u64) -> Result<Preview, EditError> {
+        let preview = self.preview(log)?;
+        preview.apply(log, reason, at)?;
+        Ok(preview)
+    }
+}
+
+/// The tags and weights of the ping at `index` with every tag in `from` replaced with `to`,
+/// keeping only the first of any that end up the same. `to` gets the weights of the tags it
+/// replaced (and its own, if the ping already had it) added up, or no weight if any of them didn't
+/// have one, since an unweighted tag already gets all of the ping's time.
+fn replaced(
+    log: &PingLog,
+    index: usize,
+    from: &[&str],
+    to: &str,
+) -> (Vec<String>, BTreeMap<String, Weight>) {
+    let tags = log.get(index).map(|ping| ping.tags.to_vec());
+    let mut weights = log.weights_at(index);
+    let mut out: Vec<String> = Vec::new();
+    let mut replaced = Vec::new();
+    for tag in tags.unwrap_or_default() {
+        let tag = if from.contains(&tag.as_str()) || tag == to {
+            replaced.push(weights.remove(&tag));
+            to.to_string()
+        } else {
+            tag
+        };
+        if !out.contains(&tag) {
+            out.push(tag);
+        }
+    }
+    let total: Option<f64> = replaced.into_iter().map(|w| w.map(Weight::get)).sum();
+    if let Some(weight) = total.and_then(|total| Weight::new(total.min(1.0))) {
+        weights.insert(to.to_string(), weight);
+    }
+    (out, weights)
+}
+
+impl Preview {
+    /// Each ping that would change, in the order they're in the log (except that duplicates of
+    /// the same ping are last to first).
+    pub fn changes(&self) -> &[PingChange] {
+        &self.changes
+    }
+
+    /// Whether the edit wouldn't change anything.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Makes exactly the changes in the preview, adding each to the log's
+    /// [audit trail](PingLog::audit) with `reason`, as if they were made at `at`. Fails if the log
+    /// isn't the one the preview was made of, or was edited since (appending pings is fine, since
+    /// it doesn't change the pings the preview is of).
+    pub fn apply(&self, log: &mut PingLog, reason: &str, at: u64) -> Result<(), EditError> {
+        if log.generation != self.generation {
+            return Err(EditError::Stale);
+        }
+        for change in self.changes.iter().filter(|change| !change.removed) {
+            let (tags, weights) = (change.new_tags.clone(), change.new_weights.clone());
+            log.retag(change.index, tags, weights, reason, at);
+        }
+        let mut removed: Vec<&PingChange> = (self.changes.iter())
+            .filter(|change| change.removed)
+            .collect();
+        // removing from the end keeps the earlier indices the same
+        removed.sort_by_key(|change| std::cmp::Reverse(change.index));
+        for change in &removed {
+            log.remove(change.index);
+        }
+        // but they're recorded in the order they're previewed
+        for change in self.changes.iter().filter(|change| change.removed) {
+            log.audit.push(Amendment {
+                time: change.time,
+                at,
+                old_tags: change.old_tags.clone(),
+                new_tags: change.new_tags.clone(),
+                reason: reason.to_string(),
+            });
+        }
+        if !removed.is_empty() {
+            log.generation = next_generation();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+
+    fn tags(tags: &str) -> Vec<String> {
+        tags.split_whitespace().map(String::from).collect()
+    }
+
+    fn log() -> PingLog {
+        PingLog::from_pings(vec![
+            Ping::new(10, tags("mail work"), 2700),
+            Ping::new(20, tags("email"), 2700),
+            Ping::new(30, tags("e-mail mail"), 2700),
+            Ping::new(30, tags("sleep"), 2700),
+        ])
+    }
+
+    fn changed(preview: &Preview) -> Vec<(u64, Vec<String>, bool)> {
+        (preview.changes().iter())
+            .map(|change| (change.time, change.new_tags.clone(), change.removed))
+            .collect()
+    }
+
+    #[test]
+    fn previews_edits() {
+        let log = log();
+        let merge = Edit::Merge {
+            from: tags("mail e-mail"),
+            into: "email".to_string(),
+        };
+        assert_eq!(
+            changed(&merge.preview(&log).unwrap()),
+            [(10, tags("email work"), false), (30, tags("email"), false)]
+        );
+        let rename = Edit::Rename {
+            from: "work".to_string(),
+            to: "job".to_string(),
+        };
+        assert_eq!(
+            changed(&rename.preview(&log).unwrap()),
+            [(10, tags("mail job"), false)]
+        );
+        assert_eq!(
+            changed(&Edit::Dedupe.preview(&log).unwrap()),
+            [(30, tags("sleep"), true)]
+        );
+        let retag = Edit::Retag {
+            time: 25,
+            tags: tags("a"),
+        };
+        assert_eq!(retag.preview(&log), Err(EditError::NoPing(25)));
+        #[cfg(feature = "expr")]
+        {
+            let auto = Edit::AutoTag {
+                expr: Expr::parse("email | mail").unwrap(),
+                tag: "comms".to_string(),
+            };
+            let preview = auto.preview(&log).unwrap();
+            assert_eq!(preview.changes().len(), 3);
+            assert_eq!(preview.changes()[0].new_tags, tags("mail work comms"));
+        }
+        // previewing changes nothing
+        assert_eq!(log.get(0).unwrap().tags.to_vec(), tags("mail work"));
+    }
+
+    #[test]
+    fn applies_previews() {
+        let mut log = log();
+        let merge = Edit::Merge {
+            from: tags("mail e-mail"),
+            into: "email".to_string(),
+        };
+        let preview = merge.preview(&log).unwrap();
+        log.push(Ping::new(40, tags("mail"), 2700));
+        preview.apply(&mut log, "merge", 100).unwrap();
+        let all: Vec<Vec<String>> = log.pings().iter().map(|p| p.tags.to_vec()).collect();
+        // only the previewed pings change
+        assert_eq!(
+            all,
+            [
+                tags("email work"),
+                tags("email"),
+                tags("email"),
+                tags("sleep"),
+                tags("mail")
+            ]
+        );
+        assert_eq!(log.audit().len(), 2);
+        assert_eq!(log.audit()[0].reason, "merge");
+
+        // once it's applied, the log is newer than the preview
+        assert_eq!(preview.apply(&mut log, "merge", 100), Err(EditError::Stale));
+        let dedupe = Edit::Dedupe.apply(&mut log, "dupe", 200).unwrap();
+        assert_eq!(dedupe.changes().len(), 1);
+        assert_eq!(log.len(), 4);
+    }
+
+    #[test]
+    fn keeps_weights() {
+        let mut log = PingLog::from_pings(vec![
+            Ping::from_entry(10, "mail:0.25 work:0.25 other", 2700),
+            Ping::from_entry(20, "mail:0.3 e-mail:0.2", 2700),
+            Ping::from_entry(30, "mail:0.3 e-mail", 2700),
+        ]);
+        let weights = |entry: &str| Ping::from_entry(0, entry, 2700).weights;
+        let rename = Edit::Rename {
+            from: "mail".to_string(),
+            to: "email".to_string(),
+        };
+        let preview = rename.preview(&log).unwrap();
+        assert_eq!(
+            preview.changes()[0].old_weights,
+            weights("mail:0.25 work:0.25")
+        );
+        assert_eq!(
+            preview.changes()[0].new_weights,
+            weights("email:0.25 work:0.25")
+        );
+        let merge = Edit::Merge {
+            from: tags("mail e-mail"),
+            into: "email".to_string(),
+        };
+        let preview = merge.preview(&log).unwrap();
+        let new_weights: Vec<_> = (preview.changes().iter())
+            .map(|change| change.new_weights.clone())
+            .collect();
+        // e-mail already had all of the last ping's time, so email does too
+        assert_eq!(
+            new_weights,
+            [
+                weights("email:0.25 work:0.25"),
+                weights("email:0.5"),
+                weights("")
+            ]
+        );
+
+        #[cfg(feature = "expr")]
+        let shares = |log: &PingLog, expr: &str| -> Vec<f64> {
+            let expr = Expr::parse(expr).unwrap();
+            log.pings().iter().map(|ping| ping.share(&expr)).collect()
+        };
+        #[cfg(feature = "expr")]
+        let before = shares(&log, "mail | e-mail");
+        preview.apply(&mut log, "merge", 100).unwrap();
+        #[cfg(feature = "expr")]
+        assert_eq!(shares(&log, "email"), before);
+        assert_eq!(log.get(1).unwrap().to_ping().weights, weights("email:0.5"));
+    }
+}
@@ -0,0 +1,139 @@
+use std::ops::Range;
+
+use super::{matches_each, PingLog};
+use crate::bool::Expr;
+
+/// A pattern across consecutive pings, for habits that can't be seen one ping at a time, like
+/// `work` pings not followed by a `break` within 4 pings. A ping matches if it matches `first`,
+/// and one of the `within` pings after it matches `then` (or none do, if `negated`).
+///
+/// ```
+/// use taglogic::bool::Expr;
+/// use taglogic::log::{Ping, PingLog, SeqQuery};
+///
+/// let tags = ["work", "work", "break", "work", "work", "work"];
+/// let pings = (tags.iter().enumerate())
+///     .map(|(i, tag)| Ping::new(i as u64 * 2700, vec![tag.to_string()], 2700))
+///     .collect();
+/// let log = PingLog::from_pings(pings);
+/// let query = SeqQuery::not_followed_by(Expr::parse("work").unwrap(), Expr::parse("break").unwrap(), 2);
+/// // the last two pings don't have 2 pings after them yet
+/// assert_eq!(query.matches_each(&log), [false, false, false, true, false, false]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeqQuery {
+    pub first: Expr,
+    pub then: Expr,
+    /// How many pings after a ping matching `first` to look at.
+    pub within: usize,
+    pub negated: bool,
+}
+
+impl SeqQuery {
+    /// Pings matching `first` with a ping matching `then` in the `within` pings after them.
+    pub fn followed_by(first: Expr, then: Expr, within: usize) -> Self {
+        Self {
+            first,
+            then,
+            within,
+            negated: false,
+        }
+    }
+
+    /// Pings matching `first` without a ping matching `then` in the `within` pings after them.
+    pub fn not_followed_by(first: Expr, then: Expr, within: usize) -> Self {
+        Self {
+            negated: true,
+            ..Self::followed_by(first, then, within)
+        }
+    }
+
+    /// Whether each ping in the log matches, oldest to newest. Pings with fewer than `within`
+    /// pings after them and no match for `then` yet aren't known to be followed or not, so they
+    /// don't match either way.
+    pub fn matches_each(&self, log: &PingLog) -> Vec<bool> {
+        let pings = log.pings();
+        let first = matches_each(&pings, &self.first);
+        let then = matches_each(&pings, &self.then);
+        // the index of the next ping matching `then` after each one
+        let mut next = vec![None; then.len()];
+        for index in (1..then.len()).rev() {
+            next[index - 1] = if then[index] {
+                Some(index)
+            } else {
+                next[index]
+            };
+        }
+        (first.iter().zip(next).enumerate())
+            .map(|(index, (&first, next))| {
+                let followed = next.is_some_and(|next| next - index <= self.within);
+                let decided = followed || index + self.within < log.len();
+                first && decided && followed != self.negated
+            })
+            .collect()
+    }
+
+    /// The times of the matching pings in `range`.
+    pub fn matching_times(&self, log: &PingLog, range: Range<u64>) -> Vec<u64> {
+        (log.times().iter().zip(self.matches_each(log)))
+            .filter(|(time, matched)| *matched && range.contains(*time))
+            .map(|(&time, _)| time)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+
+    fn log(tags: &[&str]) -> PingLog {
+        let pings = (tags.iter().enumerate())
+            .map(|(i, tags)| {
+                let tags = tags.split_whitespace().map(String::from).collect();
+                Ping::new(i as u64 * 100, tags, 2700)
+            })
+            .collect();
+        PingLog::from_pings(pings)
+    }
+
+    fn query(first: &str, then: &str, within: usize) -> SeqQuery {
+        let (first, then) = (Expr::parse(first).unwrap(), Expr::parse(then).unwrap());
+        SeqQuery::followed_by(first, then, within)
+    }
+
+    #[test]
+    fn looks_ahead() {
+        let log = log(&["work", "work", "work break", "work", "", "break"]);
+        let followed = query("work", "break", 1);
+        assert_eq!(
+            followed.matches_each(&log),
+            [false, true, false, false, false, false]
+        );
+        let not_followed = SeqQuery {
+            negated: true,
+            ..followed
+        };
+        // the ping with a break doesn't count as followed by it
+        assert_eq!(
+            not_followed.matches_each(&log),
+            [true, false, true, true, false, false]
+        );
+        assert_eq!(not_followed.matching_times(&log, 100..400), [200, 300]);
+
+        let followed = query("work", "break", 3);
+        assert_eq!(
+            followed.matches_each(&log),
+            [true, true, true, true, false, false]
+        );
+        // nothing's decided without enough pings after it
+        let not_followed = query("work", "break", 10);
+        let not_followed = SeqQuery {
+            negated: true,
+            ..not_followed
+        };
+        assert_eq!(not_followed.matches_each(&log), [false; 6]);
+        assert!(query("work", "", 0).matches_each(&log).iter().all(|&m| !m));
+        assert!(query("a", "b", 2).matches_each(&PingLog::new()).is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt;
+
+use crate::intern::TagInterner;
+
+/// How much of a ping's time goes to a tag, like the 0.7 in `work:0.7`, to the nearest
+/// thousandth.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Weight(u16);
+
+impl Weight {
+    /// A weight, or None if it isn't more than 0 and at most 1, since no tag gets more than all of
+    /// a ping's time.
+    pub fn new(weight: f64) -> Option<Self> {
+        let thousandths = (weight * 1000.0).round();
+        if (1.0..=1000.0).contains(&thousandths) {
+            Some(Self(thousandths as u16))
+        } else {
+            None
+        }
+    }
+
+    pub fn get(self) -> f64 {
+        f64::from(self.0) / 1000.0
+    }
+}
+
+impl fmt::Display for Weight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Weight {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.get())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Weight {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let weight = f64::deserialize(deserializer)?;
+        Self::new(weight)
+            .ok_or_else(|| serde::de::Error::custom("weights must be more than 0 and at most 1"))
+    }
+}
+
+/// Splits a tag with a weight, like `work:0.7`, into the tag and weight. Weights are written as
+/// decimals with a point, like `0.7`, `.25` or `1.0`, so tags like `level:3` or `proj:ttw` that
+/// end in something else keep it, and are None.
+pub(crate) fn split_weight(tag: &str) -> Option<(&str, Weight)> {
+    let (tag, weight) = tag.rsplit_once(':')?;
+    let (whole, fraction) = weight.split_once('.')?;
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if !matches!(whole, "" | "0" | "1") || fraction.is_empty() || !digits(fraction) {
+        return None;
+    }
+    let weight = Weight::new(weight.parse().ok()?)?;
+    Some((tag, weight)).filter(|(tag, _)| !tag.is_empty())
+}
+
+/// The parts of a ping's time with different tags, as the tags of each part and its fraction of
+/// the time. Each weighted tag gets its weight of the ping's time, along with the ping's unweighted
+/// tags, and whatever is left over only has the unweighted tags. If the weights add up to more
+/// than 1, they're scaled down to add up to 1. Without weights, there's one part with every tag.
+#[cfg(feature = "expr")]
+pub(crate) fn parts<'t>(
+    tags: &[&'t str],
+    weights: &[(&'t str, Weight)],
+) -> Vec<(Vec<&'t str>, f64)> {
+    if weights.is_empty() {
+        return vec![(tags.to_vec(), 1.0)];
+    }
+    let total: f64 = weights.iter().map(|(_, weight)| weight.get()).sum();
+    let whole: Vec<&str> = (tags.iter().copied())
+        .filter(|tag| weights.iter().all(|(weighted, _)| weighted != tag))
+        .collect();
+    let mut parts = Vec::with_capacity(weights.len() + 1);
+    for &(tag, weight) in weights {
+        let mut part = whole.clone();
+        part.push(tag);
+        parts.push((part, weight.get() / total.max(1.0)));
+    }
+    if total < 1.0 {
+        parts.push((whole, 1.0 - total));
+    }
+    parts
+}
+
+/// The fraction of a ping's time where `matches` is true of the tags of its [`parts`].
+#[cfg(feature = "expr")]
+pub(crate) fn share(
+    matches: &dyn Fn(&[&str]) -> bool,
+    tags: &[&str],
+    weights: &[(&str, Weight)],
+) -> f64 {
+    (parts(tags, weights).iter())
+        .filter(|(tags, _)| matches(tags))
+        .map(|(_, fraction)| fraction)
+        .sum()
+}
+
+/// The weighted tags of a [`PingRef`](super::PingRef), sorted by tag.
+#[derive(Copy, Clone)]
+pub struct Weights<'a> {
+    pub(super) entries: &'a [(u32, Weight)],
+    pub(super) interner: &'a TagInterner,
+}
+
+impl<'a> Weights<'a> {
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, Weight)> + 'a {
+        let interner = self.interner;
+        (self.entries.iter()).map(move |&(id, weight)| (interner.name_unchecked(id), weight))
+    }
+
+    /// The weight of `tag`, if it has one.
+    pub fn get(&self, tag: &str) -> Option<Weight> {
+        let id = self.interner.get(tag)?;
+        let entry = self.entries.iter().find(|&&(other, _)| other == id);
+        entry.map(|&(_, weight)| weight)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl PartialEq for Weights<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for Weights<'_> {}
+
+impl fmt::Debug for Weights<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Weights<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "expr")]
+    use crate::bool::Expr;
+
+    #[test]
+    fn parses_weights() {
+        assert_eq!(split_weight("work:0.7"), Some(("work", Weight(700))));
+        assert_eq!(split_weight("a:b:.25"), Some(("a:b", Weight(250))));
+        assert_eq!(split_weight("a:1.0"), Some(("a", Weight(1000))));
+        assert_eq!(Weight(333).to_string(), "0.333");
+        for tag in [
+            "work", "proj:ttw", ":0.5", "a:0", "a:0.0", "a:-1", "a:NaN", "a:inf", "level:3", "v:1",
+            "a:1.5", "a:2.0", "a:0.", "a:0.5e1", "a:+0.5", "a:00.5",
+        ] {
+            assert_eq!(split_weight(tag), None, "{}", tag);
+        }
+    }
+
+    #[cfg(feature = "expr")]
+    #[test]
+    fn shares_time() {
+        let share = |expr: &str, tags: &[&str], weights: &[(&str, f64)]| {
+            let weights: Vec<(&str, Weight)> = (weights.iter())
+                .map(|&(tag, weight)| (tag, Weight::new(weight).unwrap()))
+                .collect();
+            let expr = Expr::parse(expr).unwrap();
+            let share = self::share(&|tags| expr.matches(tags), tags, &weights);
+            (share * 1000.0).round() / 1000.0
+        };
+        let tags = ["work", "email", "home"];
+        let weights = [("work", 0.7), ("email", 0.3)];
+        assert_eq!(share("work", &tags, &weights), 0.7);
+        assert_eq!(share("work | email", &tags, &weights), 1.0);
+        assert_eq!(share("work & email", &tags, &weights), 0.0);
+        assert_eq!(share("home", &tags, &weights), 1.0);
+        assert_eq!(share("home & !email", &tags, &weights), 0.7);
+        // the rest of the time only has the unweighted tags
+        assert_eq!(share("home", &tags, &[("work", 0.5)]), 1.0);
+        assert_eq!(share("!work", &tags, &[("work", 0.5)]), 0.5);
+        // weights adding up to more than 1 are relative
+        assert_eq!(share("work", &tags, &[("work", 1.0), ("email", 1.0)]), 0.5);
+        assert_eq!(share("work", &tags, &[]), 1.0);
+    }
+}
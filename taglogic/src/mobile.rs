@@ -0,0 +1,158 @@
+//! Bindings for the Android and iOS apps, generated with UniFFI. Build the library with
+//! `cargo rustc --lib --crate-type cdylib --features uniffi`, then generate the Kotlin or Swift
+//! sources with
+//! `cargo run --features uniffi --bin uniffi-bindgen generate --library <lib> --language kotlin`.
+
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use crate::bool::{Expr, ParseError};
+use crate::{tt, PingIntervalData};
+
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Error)]
+pub enum TaglogicError {
+    /// An expression couldn't be parsed. The span is in Unicode code points.
+    Parse {
+        message: String,
+        start: u32,
+        end: u32,
+    },
+    /// An argument was out of range.
+    InvalidInput { message: String },
+}
+
+impl TaglogicError {
+    fn parse(expr: &str, err: ParseError) -> Self {
+        let span = err.offsets(expr).chars;
+        let offset = |chars: usize| u32::try_from(chars).unwrap_or(u32::MAX);
+        Self::Parse {
+            message: err.kind.to_string(),
+            start: offset(span.start),
+            end: offset(span.end),
+        }
+    }
+}
+
+impl std::fmt::Display for TaglogicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Parse { message, .. } | Self::InvalidInput { message } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for TaglogicError {}
+
+/// A parsed tag expression, like `work & !email`.
+#[derive(Debug, uniffi::Object)]
+pub struct TagExpr(Expr);
+
+#[uniffi::export]
+impl TagExpr {
+    #[uniffi::constructor]
+    pub fn new(expr: String) -> Result<Arc<Self>, TaglogicError> {
+        Expr::parse(&expr)
+            .map(|parsed| Arc::new(Self(parsed)))
+            .map_err(|err| TaglogicError::parse(&expr, err))
+    }
+
+    pub fn matches(&self, tags: Vec<String>) -> bool {
+        let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+        self.0.matches(&tags)
+    }
+
+    /// The tags used in the expression, sorted and without duplicates.
+    pub fn tags(&self) -> Vec<String> {
+        self.0.tags().into_iter().map(String::from).collect()
+    }
+
+    /// The expression in its canonical form.
+    pub fn format(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+/// A ping schedule.
+#[derive(Debug, uniffi::Object)]
+pub struct PingSchedule(PingIntervalData);
+
+#[uniffi::export]
+impl PingSchedule {
+    /// `tagtime` picks the original TagTime algorithm instead of the newer hash-based one.
+    #[uniffi::constructor]
+    pub fn new(seed: u32, avg_interval: u32, tagtime: bool) -> Result<Arc<Self>, TaglogicError> {
+        if avg_interval == 0 {
+            return Err(TaglogicError::InvalidInput {
+                message: "avg_interval must be positive".to_string(),
+            });
+        }
+        if tagtime && tt::State::try_from_seed(seed).is_none() {
+            return Err(TaglogicError::InvalidInput {
+                message: "TagTime seeds must be between 1 and 2147483646".to_string(),
+            });
+        }
+        Ok(Arc::new(Self(crate::new_ping_interval_data(
+            seed,
+            avg_interval,
+            tagtime,
+        ))))
+    }
+
+    /// The universal TagTime schedule, which most TagTime clients use.
+    #[uniffi::constructor]
+    pub fn universal() -> Arc<Self> {
+        Arc::new(Self(tt::UNIV_SCHED))
+    }
+
+    pub fn should_ping(&self, time: u64) -> bool {
+        crate::should_ping_at_time(time, &self.0)
+    }
+
+    pub fn next_ping_after(&self, time: u64) -> Option<u64> {
+        crate::next_ping_after(time, &self.0)
+    }
+
+    pub fn last_ping_before(&self, time: u64) -> Option<u64> {
+        crate::last_ping(time, &self.0)
+    }
+
+    /// All pings from `start` up to `end`, inclusive.
+    pub fn pings_between(&self, start: u64, end: u64) -> Vec<u64> {
+        if start < end {
+            crate::pings_between(start, end, &self.0)
+        } else {
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_errors_use_code_points() {
+        let err = TagExpr::new("é & )".to_string()).unwrap_err();
+        assert!(matches!(
+            err,
+            TaglogicError::Parse {
+                start: 4,
+                end: 5,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn schedule_matches_library() {
+        let schedule = PingSchedule::universal();
+        let next = schedule.next_ping_after(1600000000).unwrap();
+        assert_eq!(
+            crate::next_ping_after(1600000000, &tt::UNIV_SCHED),
+            Some(next)
+        );
+        assert!(schedule.should_ping(next));
+        assert_eq!(schedule.last_ping_before(next + 1), Some(next));
+        assert!(PingSchedule::new(1, 0, false).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+//! Queries written in plain words, like `work but not meetings last week`, for people who'd rather
+//! not learn the expression syntax.
+//!
+//! Tags joined by `and` or `or` are kept, and everything after `but not`, `without`, `except` or
+//! `not` is left out. A time phrase (`today`, `yesterday`, `this week`, `last month`,
+//! `past 3 days`, `since 2024-03-01`) sets the range, and little words like `time spent on` are
+//! skipped. The result isn't always what was meant, so show [`NaturalQuery::canonical`] to the
+//! user to confirm before using it.
+
+use chrono::{Months, NaiveDate};
+use std::ops::Range;
+
+use crate::bool::{tag, BuildError, Expr, ExprBuilder};
+use crate::stats::{bucket_bounds, day_start, local_date, Bucket, Calendar};
+
+/// Words that are skipped, so they can be written around tags and times.
+const SKIPPED: [&str; 20] = [
+    "a", "all", "did", "doing", "during", "for", "hours", "how", "i", "in", "many", "me", "much",
+    "of", "on", "over", "show", "spent", "the", "time",
+];
+
+/// Words after which tags are left out.
+const EXCLUDING: [&str; 6] = ["not", "without", "except", "excluding", "minus", "no"];
+
+/// A query from [`parse_natural`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NaturalQuery {
+    pub expr: Expr,
+    /// The expression in the usual syntax, like `work & !meeting`, to show the user.
+    pub canonical: String,
+    /// Times of the pings the query is about, or None for all of them.
+    pub range: Option<Range<u64>>,
+}
+
+/// Why [`parse_natural`] couldn't understand a query.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum NaturalError {
+    /// There were no tags or times, like in `the`. Say `everything` to match every ping.
+    #[error("no tags or times in the query")]
+    Empty,
+    /// A word like `and` or `not` with no tag after it.
+    #[error("`{0}` needs a tag after it")]
+    Dangling(String),
+    /// More than one time phrase, like `today last week`.
+    #[error("`{0}` is a second time range")]
+    SecondRange(String),
+    /// A time that's out of range, like `past 99999999999 days`.
+    #[error("`{0}` is out of range")]
+    InvalidTime(String),
+    #[error(transparent)]
+    Build(#[from] BuildError),
+}
+
+/// Parses a query written in plain words, with times relative to `now` and days starting at
+/// midnight in `tz`. A word that isn't one of `tags` but is with an `s` taken off, like
+/// `meetings` for `meeting`, is taken to be that tag.
+///
+/// ```
+/// use chrono::Utc;
+/// use taglogic::natural::parse_natural;
+///
+/// // Wednesday 2024-03-13
+/// let query = parse_natural("work but not meetings last week", 1710288000, &Utc, &["meeting"]);
+/// let query = query.unwrap();
+/// assert_eq!(query.canonical, "work & !meeting");
+/// // Monday 2024-03-04 to Monday 2024-03-11
+/// assert_eq!(query.range, Some(1709510400..1710115200));
+/// ```
+pub fn parse_natural<Tz: Calendar, T: AsRef<str>>(
+    text: &str,
+    now: u64,
+    tz: &Tz,
+    tags: &[T],
+) -> Result<NaturalQuery, NaturalError> {
+    let words: Vec<&str> = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|word| word.trim_end_matches(['?', '.', '!']))
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let mut range = None;
+    let mut everything = false;
+    // tags to keep, as alternatives that are each all of some tags, like `&` binding tighter than
+    // `|` in expressions
+    let mut kept: Vec<Vec<ExprBuilder>> = Vec::new();
+    let mut excluded = Vec::new();
+    let mut excluding = false;
+    // whether the next tag is joined to the ones before it with `or`
+    let mut or = false;
+    let mut dangling: Option<&str> = None;
+    let mut index = 0;
+    while let Some(&word) = words.get(index) {
+        let lower = word.to_lowercase();
+        if let Some((time, len)) = time_phrase(&words[index..], now, tz) {
+            let phrase = words[index..index + len].join(" ");
+            let time = time.ok_or_else(|| NaturalError::InvalidTime(phrase.clone()))?;
+            if range.replace(time).is_some() {
+                return Err(NaturalError::SecondRange(phrase));
+            }
+            index += len;
+            continue;
+        }
+        index += 1;
+        match lower.as_str() {
+            "and" | "&" | "but" | "with" | "plus" => or = false,
+            "or" | "|" => or = true,
+            "everything" | "anything" => {
+                everything = true;
+                continue;
+            }
+            _ if EXCLUDING.contains(&lower.as_str()) => excluding = true,
+            _ if SKIPPED.contains(&lower.as_str()) => continue,
+            _ => {
+                let term = tag(resolve(word, tags));
+                match kept.last_mut() {
+                    _ if excluding => excluded.push(term),
+                    Some(all) if !or => all.push(term),
+                    _ => kept.push(vec![term]),
+                }
+                (or, dangling) = (false, None);
+                continue;
+            }
+        }
+        if kept.is_empty() && excluded.is_empty() && !excluding {
+            return Err(NaturalError::Dangling(word.to_string()));
+        }
+        dangling = Some(word);
+    }
+    if let Some(word) = dangling {
+        return Err(NaturalError::Dangling(word.to_string()));
+    }
+
+    let mut excluded = chain(excluded, ExprBuilder::or).map(|excluded| !excluded);
+    if let [all] = kept.as_mut_slice() {
+        // `a & b & !c` rather than `(a & b) & !c`
+        all.extend(excluded.take());
+    }
+    let kept = kept
+        .into_iter()
+        .filter_map(|all| chain(all, ExprBuilder::and));
+    let builder = match (chain(kept.collect(), ExprBuilder::or), excluded) {
+        (Some(kept), Some(excluded)) => Some(kept.and(excluded)),
+        (kept, excluded) => kept.or(excluded),
+    };
+    let expr = match builder {
+        Some(builder) => builder.build()?,
+        None if everything || range.is_some() => Expr::EMPTY,
+        None => return Err(NaturalError::Empty),
+    };
+    Ok(NaturalQuery {
+        canonical: expr.to_string(),
+        expr,
+        range,
+    })
+}
+
+/// `terms` joined with `op`, grouped from the right like parsing groups them.
+fn chain(
+    terms: Vec<ExprBuilder>,
+    op: fn(ExprBuilder, ExprBuilder) -> ExprBuilder,
+) -> Option<ExprBuilder> {
+    terms
+        .into_iter()
+        .rev()
+        .reduce(|right, left| op(left, right))
+}
+
+/// The tag `word` means: itself, or itself without a plural `s` if only that is one of `tags`.
+fn resolve<'a, T: AsRef<str>>(word: &'a str, tags: &[T]) -> &'a str {
+    let is_tag = |word: &str| tags.iter().any(|tag| tag.as_ref() == word);
+    match word.strip_suffix('s') {
+        Some(singular) if !is_tag(word) && is_tag(singular) => singular,
+        _ => word,
+    }
+}
+
+/// The range of the time phrase at the start of `words` and how many words it is, or None if it
+/// doesn't start with one. The range is None if the phrase is out of range.
+fn time_phrase<Tz: Calendar>(
+    words: &[&str],
+    now: u64,
+    tz: &Tz,
+) -> Option<(Option<Range<u64>>, usize)> {
+    let lower: Vec<String> = words
+        .iter()
+        .take(3)
+        .map(|word| word.to_lowercase())
+        .collect();
+    let lower: Vec<&str> = lower.iter().map(String::as_str).collect();
+    let bucket = |word: &str| match word.trim_end_matches('s') {
+        "day" => Some(Bucket::Day),
+        "week" => Some(Bucket::Week),
+        "month" => Some(Bucket::Month),
+        _ => None,
+    };
+    // the bucket containing `time`
+    let current = |bucket, time| bucket_bounds(bucket, time, tz).map(|(_, start, end)| start..end);
+    // the bucket before the one containing `now`
+    let previous = |bucket| current(bucket, current(bucket, now)?.start.checked_sub(1)?);
+    match lower.as_slice() {
+        ["today", ..] => Some((current(Bucket::Day, now), 1)),
+        ["yesterday", ..] => Some((previous(Bucket::Day), 1)),
+        ["this", unit, ..] => Some((current(bucket(unit)?, now), 2)),
+        ["last" | "previous", number, unit, ..] if number.parse::<u32>().is_ok() => {
+            Some((past(number.parse().ok()?, bucket(unit)?, now, tz), 3))
+        }
+        ["past", number, unit, ..] => Some((past(number.parse().ok()?, bucket(unit)?, now, tz), 3)),
+        ["last" | "previous", unit, ..] => Some((previous(bucket(unit)?), 2)),
+        ["since", date, ..] => {
+            let date: NaiveDate = date.parse().ok()?;
+            Some((day_start(date, tz).map(|start| start..now), 2))
+        }
+        _ => None,
+    }
+}
+
+/// The `count` days, weeks or months up to `now`. Months are calendar months, starting at
+/// midnight on the same day of the month.
+fn past<Tz: Calendar>(count: u32, bucket: Bucket, now: u64, tz: &Tz) -> Option<Range<u64>> {
+    let secs = match bucket {
+        Bucket::Day => 86400,
+        Bucket::Week => 7 * 86400,
+        Bucket::Month => {
+            let date = local_date(now, tz)?.checked_sub_months(Months::new(count))?;
+            return Some(day_start(date, tz)?..now);
+        }
+    };
+    Some(now.checked_sub(u64::from(count) * secs)?..now)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+
+    const TAGS: [&str; 4] = ["work", "meeting", "email", "sleep"];
+    /// Wednesday 2024-03-13, 00:00 UTC.
+    const NOW: u64 = 1710288000;
+    const DAY: u64 = 86400;
+
+    fn canonical(text: &str) -> String {
+        match parse_natural(text, NOW, &Utc, &TAGS) {
+            Ok(query) => query.canonical,
+            Err(err) => err.to_string(),
+        }
+    }
+
+    fn range(text: &str) -> Option<Range<u64>> {
+        parse_natural(text, NOW, &Utc, &TAGS).unwrap().range
+    }
+
+    #[test]
+    fn lowers_words_to_expressions() {
+        assert_eq!(canonical("work but not meetings"), "work & !meeting");
+        assert_eq!(canonical("time spent on work or email"), "work | email");
+        assert_eq!(
+            canonical("work and email, without meetings or sleep"),
+            "work & email & !(meeting | sleep)"
+        );
+        assert_eq!(canonical("Work except Meetings?"), "Work & !Meetings");
+        assert_eq!(canonical("everything except sleep"), "!sleep");
+        assert_eq!(canonical("work email"), "work & email");
+        assert_eq!(canonical("work or sleep and email"), "work | sleep & email");
+        assert_eq!(
+            canonical("work or email but not meetings"),
+            "(work | email) & !meeting"
+        );
+        // "emails" isn't a tag, but "email" is; "news" is left alone since "new" isn't one
+        assert_eq!(canonical("emails or news"), "email | news");
+        assert_eq!(canonical("everything"), "");
+        assert_eq!(canonical("last week"), "");
+    }
+
+    #[test]
+    fn reports_what_it_cant_understand() {
+        assert_eq!(canonical("the"), "no tags or times in the query");
+        assert_eq!(canonical("work and"), "`and` needs a tag after it");
+        assert_eq!(canonical("or work"), "`or` needs a tag after it");
+        assert_eq!(canonical("work but not"), "`not` needs a tag after it");
+        assert_eq!(
+            canonical("work today yesterday"),
+            "`yesterday` is a second time range"
+        );
+        assert_eq!(
+            canonical("work past 99999999 weeks"),
+            "`past 99999999 weeks` is out of range"
+        );
+        assert_eq!(canonical("work)"), "`work)` isn't a valid tag");
+    }
+
+    #[test]
+    fn finds_time_ranges() {
+        assert_eq!(range("work"), None);
+        assert_eq!(range("work today"), Some(NOW..NOW + DAY));
+        assert_eq!(range("yesterday"), Some(NOW - DAY..NOW));
+        // weeks start on Monday, the 11th
+        assert_eq!(range("work this week"), Some(NOW - 2 * DAY..NOW + 5 * DAY));
+        assert_eq!(
+            range("in the last week"),
+            Some(NOW - 9 * DAY..NOW - 2 * DAY)
+        );
+        assert_eq!(range("over the past 3 days"), Some(NOW - 3 * DAY..NOW));
+        assert_eq!(range("last 2 weeks"), Some(NOW - 14 * DAY..NOW));
+        // February 2024 has 29 days
+        assert_eq!(range("last month"), Some(NOW - 41 * DAY..NOW - 12 * DAY));
+        assert_eq!(range("past 1 month"), Some(NOW - 29 * DAY..NOW));
+        assert_eq!(range("since 2024-03-01"), Some(NOW - 12 * DAY..NOW));
+
+        // days start at midnight in the time zone given
+        let tz = FixedOffset::east_opt(3600).unwrap();
+        let query = parse_natural("today", NOW, &tz, &TAGS).unwrap();
+        assert_eq!(query.range, Some(NOW - 3600..NOW + DAY - 3600));
+    }
+}
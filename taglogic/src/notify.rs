@@ -0,0 +1,156 @@
+//! Working out which notifications to show for upcoming pings, so the service worker only has to
+//! schedule them.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "wasm")]
+use serde::{Deserialize, Serialize};
+
+use crate::{next_ping_after, PingIntervalData};
+
+mod text;
+
+pub use text::{
+    DateOrder, LastPing, Locale, NotificationTemplate, NotificationTemplateError, TextOptions,
+};
+
+/// Notifications are only worked out this far ahead, in seconds.
+pub const MAX_LOOKAHEAD: u64 = 86400;
+
+/// The `tag` notifications are shown with, so a new ping's notification replaces the last one.
+pub const NOTIFICATION_TAG: &str = "retag-ping";
+
+/// When the user wants to be notified.
+#[cfg_attr(feature = "wasm", derive(Deserialize, tsify::Tsify))]
+#[cfg_attr(feature = "wasm", serde(rename_all = "camelCase"))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NotificationOptions {
+    /// Minutes ahead of UTC of the user's time zone, for quiet hours and the times in titles.
+    pub utc_offset_mins: i32,
+    /// Minutes after local midnight that quiet hours start. Pings from then until
+    /// `quiet_end_mins` (which can be on the next day) aren't notified. Quiet hours are off if
+    /// they start and end at the same time.
+    pub quiet_start_mins: u32,
+    pub quiet_end_mins: u32,
+    /// How far ahead to look, in seconds. Anything over [`MAX_LOOKAHEAD`] is treated as that.
+    pub lookahead_secs: u64,
+}
+
+/// A notification to show for a ping.
+#[cfg_attr(feature = "wasm", derive(Serialize, tsify::Tsify))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    /// Unix timestamp (in seconds) of the ping, which is when to show the notification.
+    pub time: u64,
+    /// Like `Ping! 14:05:09`, with the ping's local time. Shells can render their own with a
+    /// [`NotificationTemplate`].
+    pub title: String,
+    /// Always [`NOTIFICATION_TAG`].
+    pub tag: &'static str,
+}
+
+/// Notifications for the pings after `now`, up to the lookahead, skipping pings in quiet hours.
+pub fn upcoming_notifications(
+    schedule: &PingIntervalData,
+    now: u64,
+    options: &NotificationOptions,
+) -> Vec<Notification> {
+    let end = now.saturating_add(options.lookahead_secs.min(MAX_LOOKAHEAD));
+    let mut notifications = Vec::new();
+    let mut time = now;
+    while let Some(next) = next_ping_after(time, schedule) {
+        if next > end {
+            break;
+        }
+        time = next;
+        let local = local_secs_of_day(time, options.utc_offset_mins);
+        if in_quiet_hours(local / 60, options) {
+            continue;
+        }
+        notifications.push(Notification {
+            time,
+            title: format!(
+                "Ping! {:02}:{:02}:{:02}",
+                local / 3600,
+                local / 60 % 60,
+                local % 60
+            ),
+            tag: NOTIFICATION_TAG,
+        });
+    }
+    notifications
+}
+
+fn local_secs_of_day(time: u64, utc_offset_mins: i32) -> u32 {
+    (i128::from(time) + i128::from(utc_offset_mins) * 60).rem_euclid(86400) as u32
+}
+
+fn in_quiet_hours(mins: u32, options: &NotificationOptions) -> bool {
+    let start = options.quiet_start_mins % 1440;
+    let end = options.quiet_end_mins % 1440;
+    if start <= end {
+        start <= mins && mins < end
+    } else {
+        // quiet hours go over midnight
+        mins >= start || mins < end
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tt::UNIV_SCHED;
+
+    // 2018-08-08 18:52:21 UTC, with pings at 20:09:40 and 20:25:40
+    const NOW: u64 = 1533754341;
+
+    fn options(quiet_start_mins: u32, quiet_end_mins: u32) -> NotificationOptions {
+        NotificationOptions {
+            utc_offset_mins: 0,
+            quiet_start_mins,
+            quiet_end_mins,
+            lookahead_secs: 2 * 3600,
+        }
+    }
+
+    #[test]
+    fn notifies_upcoming_pings() {
+        let notifications = upcoming_notifications(&UNIV_SCHED, NOW, &options(0, 0));
+        assert_eq!(notifications[0].time, 1533758980);
+        assert_eq!(notifications[0].title, "Ping! 20:09:40");
+        assert_eq!(notifications[1].title, "Ping! 20:25:40");
+        assert!(notifications
+            .iter()
+            .all(|notification| notification.time <= NOW + 2 * 3600));
+
+        let est = NotificationOptions {
+            utc_offset_mins: -240,
+            ..options(0, 0)
+        };
+        assert_eq!(
+            upcoming_notifications(&UNIV_SCHED, NOW, &est)[0].title,
+            "Ping! 16:09:40"
+        );
+    }
+
+    #[test]
+    fn skips_quiet_hours() {
+        // 20:00 to 20:15
+        let notifications = upcoming_notifications(&UNIV_SCHED, NOW, &options(1200, 1215));
+        assert_eq!(notifications[0].title, "Ping! 20:25:40");
+        // 20:00 until 8:00 the next day
+        assert!(upcoming_notifications(&UNIV_SCHED, NOW, &options(1200, 480)).is_empty());
+    }
+
+    #[test]
+    fn lookahead_is_capped() {
+        let options = NotificationOptions {
+            lookahead_secs: u64::MAX,
+            ..options(0, 0)
+        };
+        let notifications = upcoming_notifications(&UNIV_SCHED, NOW, &options);
+        assert!(notifications.last().unwrap().time <= NOW + MAX_LOOKAHEAD);
+        assert!(notifications.len() > 10);
+    }
+}
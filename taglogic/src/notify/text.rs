@@ -0,0 +1,387 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+
+use super::local_secs_of_day;
+use crate::unicode;
+
+/// The text of a notification, with placeholders for the ping, like
+/// `PING! It's {local_time}. Last: {last_tags}`:
+///
+/// - `{local_time}`: the ping's local time, like `14:05:09` or `2:05:09 PM`
+/// - `{local_date}`: the ping's local date, like `2024-03-13` or `3/13/2024`
+/// - `{last_tags}`: the tags of the last ping, separated by spaces
+/// - `{since_last}`: how long ago the last ping was, like `45m` or `2h5m`
+///
+/// `{{` and `}}` are a literal `{` and `}`. The last ping's placeholders are empty if there
+/// wasn't one.
+///
+/// ```
+/// use taglogic::notify::{LastPing, Locale, NotificationTemplate, TextOptions};
+///
+/// let template = NotificationTemplate::parse("PING! It's {local_time}. Last: {last_tags}").unwrap();
+/// let last = LastPing { time: 1533756000, tags: &["work", "email"] };
+/// let options = TextOptions { locale: Locale::from_tag("en-US"), ..TextOptions::default() };
+/// assert_eq!(
+///     template.render(1533758980, Some(last), &options),
+///     "PING! It's 8:09:40 PM. Last: work email"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotificationTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    LocalTime,
+    LocalDate,
+    LastTags,
+    SinceLast,
+}
+
+/// An error parsing a [`NotificationTemplate`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum NotificationTemplateError {
+    #[error("unknown placeholder `{{{0}}}`")]
+    UnknownPlaceholder(String),
+    #[error("unclosed `{{`")]
+    Unclosed,
+    #[error("unopened `}}`, write `}}}}` for a literal one")]
+    Unopened,
+}
+
+/// The ping before the one being notified, for the `{last_...}` placeholders.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LastPing<'a> {
+    /// Unix timestamp (in seconds) of when the ping was sent.
+    pub time: u64,
+    pub tags: &'a [&'a str],
+}
+
+/// How to write times and dates.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Locale {
+    /// Whether times are like `2:05:09 PM` instead of `14:05:09`.
+    pub hour12: bool,
+    pub date_order: DateOrder,
+    /// What goes between the parts of dates.
+    pub date_separator: char,
+}
+
+/// The order of the parts of a date.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DateOrder {
+    YearMonthDay,
+    DayMonthYear,
+    MonthDayYear,
+}
+
+impl Locale {
+    /// 24-hour times and dates like `2024-03-13`.
+    pub const ISO: Self = Self {
+        hour12: false,
+        date_order: DateOrder::YearMonthDay,
+        date_separator: '-',
+    };
+
+    /// The usual way to write times and dates for a language tag like `en-US` or `de`, from the
+    /// platform. Languages this doesn't know get [`Locale::ISO`].
+    pub fn from_tag(tag: &str) -> Self {
+        let mut parts = tag.split(['-', '_']);
+        let language = parts.next().unwrap_or_default().to_ascii_lowercase();
+        let region = parts
+            .find(|part| part.len() == 2)
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+        let (hour12, date_order, date_separator) = match (language.as_str(), region.as_str()) {
+            ("en", "US" | "PH") | ("en", "") => (true, DateOrder::MonthDayYear, '/'),
+            ("en", "GB" | "IE") => (false, DateOrder::DayMonthYear, '/'),
+            ("en", _) => (true, DateOrder::DayMonthYear, '/'),
+            ("de" | "ru" | "pl" | "cs" | "fi" | "nb" | "da" | "tr", _) => {
+                (false, DateOrder::DayMonthYear, '.')
+            }
+            ("fr" | "es" | "it" | "pt" | "el", _) => (false, DateOrder::DayMonthYear, '/'),
+            ("nl", _) => (false, DateOrder::DayMonthYear, '-'),
+            ("ja" | "zh", _) => (false, DateOrder::YearMonthDay, '/'),
+            _ => return Self::ISO,
+        };
+        Self {
+            hour12,
+            date_order,
+            date_separator,
+        }
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::ISO
+    }
+}
+
+/// How to render a [`NotificationTemplate`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct TextOptions {
+    /// Minutes ahead of UTC of the user's time zone.
+    pub utc_offset_mins: i32,
+    pub locale: Locale,
+    /// The most characters the text can have, counting what shows as one character as one (see
+    /// [`unicode::graphemes`]). Longer text is cut off with `…`.
+    pub max_len: Option<usize>,
+}
+
+impl NotificationTemplate {
+    /// Parses a template.
+    pub fn parse(s: &str) -> Result<Self, NotificationTemplateError> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut rest = s;
+        while let Some(i) = rest.find(['{', '}']) {
+            text.push_str(&rest[..i]);
+            let (brace, after) = rest[i..].split_at(1);
+            if after.starts_with(brace) {
+                text.push_str(brace);
+                rest = &after[1..];
+                continue;
+            }
+            if brace == "}" {
+                return Err(NotificationTemplateError::Unopened);
+            }
+            let end = after.find('}').ok_or(NotificationTemplateError::Unclosed)?;
+            let part = match &after[..end] {
+                "local_time" => Part::LocalTime,
+                "local_date" => Part::LocalDate,
+                "last_tags" => Part::LastTags,
+                "since_last" => Part::SinceLast,
+                name => {
+                    let name = name.to_string();
+                    return Err(NotificationTemplateError::UnknownPlaceholder(name));
+                }
+            };
+            if !text.is_empty() {
+                parts.push(Part::Text(core::mem::take(&mut text)));
+            }
+            parts.push(part);
+            rest = &after[end + 1..];
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self { parts })
+    }
+
+    /// The title of a notification for the ping at `time`, after `last`.
+    pub fn render(&self, time: u64, last: Option<LastPing>, options: &TextOptions) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::LocalTime => write_time(&mut out, time, options),
+                Part::LocalDate => write_date(&mut out, time, options),
+                Part::LastTags => {
+                    out.push_str(&last.map(|last| last.tags.join(" ")).unwrap_or_default())
+                }
+                Part::SinceLast => {
+                    if let Some(last) = last {
+                        let mins = time.saturating_sub(last.time) / 60;
+                        let _ = match (mins / 60, mins % 60) {
+                            (0, mins) => write!(out, "{}m", mins),
+                            (hours, 0) => write!(out, "{}h", hours),
+                            (hours, mins) => write!(out, "{}h{}m", hours, mins),
+                        };
+                    }
+                }
+            }
+        }
+        match options.max_len {
+            Some(max_len) => truncate(&out, max_len),
+            None => out,
+        }
+    }
+}
+
+fn write_time(out: &mut String, time: u64, options: &TextOptions) {
+    let local = local_secs_of_day(time, options.utc_offset_mins);
+    let (hours, mins, secs) = (local / 3600, local / 60 % 60, local % 60);
+    let _ = if options.locale.hour12 {
+        let am_pm = if hours < 12 { "AM" } else { "PM" };
+        let hours = (hours + 11) % 12 + 1;
+        write!(out, "{}:{:02}:{:02} {}", hours, mins, secs, am_pm)
+    } else {
+        write!(out, "{:02}:{:02}:{:02}", hours, mins, secs)
+    };
+}
+
+fn write_date(out: &mut String, time: u64, options: &TextOptions) {
+    let local = i128::from(time) + i128::from(options.utc_offset_mins) * 60;
+    let (year, month, day) = civil_from_days(local.div_euclid(86400));
+    let sep = options.locale.date_separator;
+    let _ = match options.locale.date_order {
+        DateOrder::YearMonthDay => write!(out, "{}{}{:02}{}{:02}", year, sep, month, sep, day),
+        DateOrder::DayMonthYear => write!(out, "{:02}{}{:02}{}{}", day, sep, month, sep, year),
+        DateOrder::MonthDayYear => write!(out, "{}{}{}{}{}", month, sep, day, sep, year),
+    };
+}
+
+/// The year, month and day of the date `days` days after 1970-01-01, from
+/// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: i128) -> (i128, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i128::from(month <= 2);
+    (year, month, day)
+}
+
+/// `text` cut to `max_len` graphemes, ending with `…` if it was cut. Graphemes aren't split, so
+/// accents and joined emoji stay whole.
+fn truncate(text: &str, max_len: usize) -> String {
+    let mut graphemes = unicode::graphemes(text);
+    let kept: String = graphemes.by_ref().take(max_len).collect();
+    if graphemes.next().is_none() {
+        return kept;
+    }
+    let mut kept: Vec<&str> = unicode::graphemes(&kept).collect();
+    kept.truncate(max_len.saturating_sub(1));
+    if max_len == 0 {
+        return String::new();
+    }
+    format!("{}…", kept.concat().trim_end())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 2018-08-08 20:09:40 UTC, a Wednesday
+    const TIME: u64 = 1533758980;
+
+    fn render(template: &str, last: Option<LastPing>, options: &TextOptions) -> String {
+        let template = NotificationTemplate::parse(template).unwrap();
+        template.render(TIME, last, options)
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        let last = LastPing {
+            time: TIME - 2 * 3600 - 5 * 60,
+            tags: &["work", "email"],
+        };
+        let options = TextOptions::default();
+        assert_eq!(
+            render(
+                "PING! It's {local_time}. Last: {last_tags}",
+                Some(last),
+                &options
+            ),
+            "PING! It's 20:09:40. Last: work email"
+        );
+        assert_eq!(
+            render(
+                "{local_date} ({since_last} since {{last}})",
+                Some(last),
+                &options
+            ),
+            "2018-08-08 (2h5m since {last})"
+        );
+        assert_eq!(
+            render("Last: {last_tags}{since_last}", None, &options),
+            "Last: "
+        );
+        assert_eq!(render("", None, &options), "");
+    }
+
+    #[test]
+    fn formats_for_locales() {
+        let options = |tag, utc_offset_mins| TextOptions {
+            utc_offset_mins,
+            locale: Locale::from_tag(tag),
+            max_len: None,
+        };
+        let time_and_date = "{local_time} {local_date}";
+        assert_eq!(
+            render(time_and_date, None, &options("en-US", 0)),
+            "8:09:40 PM 8/8/2018"
+        );
+        assert_eq!(
+            render(time_and_date, None, &options("en_GB", 0)),
+            "20:09:40 08/08/2018"
+        );
+        assert_eq!(
+            render(time_and_date, None, &options("de-DE", 0)),
+            "20:09:40 08.08.2018"
+        );
+        assert_eq!(
+            render(time_and_date, None, &options("ja", 0)),
+            "20:09:40 2018/08/08"
+        );
+        assert_eq!(
+            render(time_and_date, None, &options("xx", 0)),
+            "20:09:40 2018-08-08"
+        );
+        // the local date can be the next day
+        assert_eq!(
+            render(time_and_date, None, &options("en-US", 240)),
+            "12:09:40 AM 8/9/2018"
+        );
+        assert_eq!(
+            render(time_and_date, None, &options("en-US", -720)),
+            "8:09:40 AM 8/8/2018"
+        );
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+    }
+
+    #[test]
+    fn truncates_safely() {
+        let options = |max_len| TextOptions {
+            max_len: Some(max_len),
+            ..TextOptions::default()
+        };
+        let tags = LastPing {
+            time: TIME,
+            tags: &["café\u{301}", "👩\u{200D}💻", "x"],
+        };
+        assert_eq!(
+            render("{last_tags}", Some(tags), &options(20)),
+            "café\u{301} 👩\u{200D}💻 x"
+        );
+        assert_eq!(
+            render("{last_tags}", Some(tags), &options(8)),
+            "café\u{301} 👩\u{200D}💻 x"
+        );
+        assert_eq!(
+            render("{last_tags}", Some(tags), &options(7)),
+            "café\u{301} 👩\u{200D}💻…"
+        );
+        // no space before the ellipsis
+        assert_eq!(
+            render("{last_tags}", Some(tags), &options(6)),
+            "café\u{301}…"
+        );
+        assert_eq!(render("{last_tags}", Some(tags), &options(4)), "caf…");
+        assert_eq!(render("{last_tags}", Some(tags), &options(0)), "");
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        let error = |template| {
+            NotificationTemplate::parse(template)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(error("{nope}"), "unknown placeholder `{nope}`");
+        assert_eq!(error("{local_time"), "unclosed `{`");
+        assert_eq!(error("a } b"), "unopened `}`, write `}}` for a literal one");
+    }
+}
@@ -0,0 +1,269 @@
+//! Nudges from rules over the last few pings, like "fewer than 2 of the last 10 pings in work
+//! hours were `work`", so tracking gives feedback as pings are answered instead of only in
+//! reports later.
+//!
+//! A [`NudgeEngine`] is checked after each answer. Each rule looks at the last few pings in its
+//! hours and days, and nudges if too few (or too many) of them match its expression. The engine
+//! remembers when each rule last nudged, so a rule that stays broken doesn't nudge on every ping.
+
+use chrono::{Datelike, TimeZone, Timelike, Weekday};
+use std::convert::TryFrom;
+use std::ops::Range;
+
+use crate::bool::Expr;
+use crate::log::{PingLog, PingRef};
+
+/// When a [`Rule`] nudges, from how many of its pings match.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Threshold {
+    FewerThan(usize),
+    MoreThan(usize),
+}
+
+impl Threshold {
+    fn crossed(self, matching: usize) -> bool {
+        match self {
+            Self::FewerThan(count) => matching < count,
+            Self::MoreThan(count) => matching > count,
+        }
+    }
+}
+
+/// A rule for a [`NudgeEngine`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    pub expr: Expr,
+    /// How many of the latest pings are looked at. A rule doesn't nudge until there are this many.
+    pub window: usize,
+    pub threshold: Threshold,
+    /// The local hours the rule is checked in, like `9..17`. Pings outside of them aren't
+    /// counted, and don't make the rule nudge.
+    pub hours: Option<Range<u32>>,
+    /// The days the rule is checked on, or every day if it's empty.
+    pub days: Vec<Weekday>,
+    /// How long (in seconds) after nudging before the rule can nudge again.
+    pub cooldown: u64,
+}
+
+impl Rule {
+    /// A rule checked at any time, without a cooldown.
+    pub fn new(name: &str, expr: Expr, window: usize, threshold: Threshold) -> Self {
+        Self {
+            name: name.to_string(),
+            expr,
+            window,
+            threshold,
+            hours: None,
+            days: Vec::new(),
+            cooldown: 0,
+        }
+    }
+
+    /// If the rule is checked at `time`.
+    fn active<Tz: TimeZone>(&self, time: u64, tz: &Tz) -> bool {
+        let local = match i64::try_from(time)
+            .ok()
+            .and_then(|secs| tz.timestamp_opt(secs, 0).single())
+        {
+            Some(local) => local,
+            None => return false,
+        };
+        let in_hours = (self.hours.as_ref()).is_none_or(|hours| hours.contains(&local.hour()));
+        in_hours && (self.days.is_empty() || self.days.contains(&local.weekday()))
+    }
+}
+
+/// A nudge from a [`Rule`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nudge {
+    /// The rule's name.
+    pub rule: String,
+    /// The time of the ping that made the rule nudge.
+    pub time: u64,
+    /// How many of the rule's window of pings matched.
+    pub matching: usize,
+    pub window: usize,
+}
+
+/// Checks [`Rule`]s against a log as it grows.
+///
+/// ```
+/// use chrono::Utc;
+/// use taglogic::bool::Expr;
+/// use taglogic::log::{Ping, PingLog};
+/// use taglogic::nudge::{NudgeEngine, Rule, Threshold};
+///
+/// let rule = Rule::new("focus", Expr::parse("work").unwrap(), 3, Threshold::FewerThan(2));
+/// let mut engine = NudgeEngine::new(vec![rule]);
+/// let mut log = PingLog::new();
+/// for (time, tag) in [(0, "work"), (2700, "games"), (5400, "games")] {
+///     log.push(Ping::new(time, vec![tag.to_string()], 2700));
+/// }
+/// let nudges = engine.check(&log, &Utc);
+/// assert_eq!((nudges[0].time, nudges[0].matching), (5400, 1));
+/// // it's already nudged for that ping
+/// assert!(engine.check(&log, &Utc).is_empty());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct NudgeEngine {
+    rules: Vec<Rule>,
+    last_nudged: Vec<Option<u64>>,
+}
+
+impl NudgeEngine {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        let last_nudged = vec![None; rules.len()];
+        Self { rules, last_nudged }
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// The time of the ping the rule named `name` last nudged for.
+    pub fn last_nudged(&self, name: &str) -> Option<u64> {
+        let index = self.rules.iter().position(|rule| rule.name == name)?;
+        self.last_nudged[index]
+    }
+
+    /// Sets when the rule named `name` last nudged, like when restoring the engine after a
+    /// restart.
+    pub fn set_last_nudged(&mut self, name: &str, time: u64) {
+        if let Some(index) = self.rules.iter().position(|rule| rule.name == name) {
+            self.last_nudged[index] = Some(time);
+        }
+    }
+
+    /// Checks every rule at the latest ping in `log`, with hours and days in `tz`, returning the
+    /// nudges in the order of the rules. A rule nudges at most once for each ping.
+    pub fn check<Tz: TimeZone>(&mut self, log: &PingLog, tz: &Tz) -> Vec<Nudge> {
+        let pings = log.pings();
+        let latest = match pings.last() {
+            Some(latest) => latest.time,
+            None => return Vec::new(),
+        };
+        let mut nudges = Vec::new();
+        for (rule, last_nudged) in self.rules.iter().zip(&mut self.last_nudged) {
+            let cooling =
+                last_nudged.is_some_and(|last| latest <= last || latest - last < rule.cooldown);
+            if cooling || rule.window == 0 || !rule.active(latest, tz) {
+                continue;
+            }
+            let window: Vec<PingRef> = (0..pings.len())
+                .rev()
+                .filter_map(|index| pings.get(index))
+                .filter(|ping| rule.active(ping.time, tz))
+                .take(rule.window)
+                .collect();
+            if window.len() < rule.window {
+                continue;
+            }
+            let matching = window
+                .iter()
+                .filter(|ping| ping.matches(&rule.expr))
+                .count();
+            if rule.threshold.crossed(matching) {
+                *last_nudged = Some(latest);
+                nudges.push(Nudge {
+                    rule: rule.name.clone(),
+                    time: latest,
+                    matching,
+                    window: rule.window,
+                });
+            }
+        }
+        nudges
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use chrono::{FixedOffset, Utc};
+
+    const HOUR: u64 = 3600;
+    /// A Monday at midnight UTC.
+    const MONDAY: u64 = 1609718400;
+
+    fn log(pings: &[(u64, &str)]) -> PingLog {
+        let pings = (pings.iter())
+            .map(|&(time, tag)| Ping::new(time, vec![tag.to_string()], 2700))
+            .collect();
+        PingLog::from_pings(pings)
+    }
+
+    fn work_hours(threshold: Threshold) -> Rule {
+        Rule {
+            hours: Some(9..17),
+            days: vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+            ],
+            ..Rule::new("focus", Expr::parse("work").unwrap(), 2, threshold)
+        }
+    }
+
+    #[test]
+    fn counts_pings_in_hours() {
+        let mut engine = NudgeEngine::new(vec![work_hours(Threshold::FewerThan(1))]);
+        // the games ping at night isn't counted
+        let pings = [(MONDAY + 9 * HOUR, "games"), (MONDAY + 20 * HOUR, "games")];
+        assert!(engine.check(&log(&pings), &Utc).is_empty());
+        let pings = [
+            (MONDAY + 9 * HOUR, "games"),
+            (MONDAY + 20 * HOUR, "games"),
+            (MONDAY + 34 * HOUR, "games"),
+        ];
+        let nudges = engine.check(&log(&pings), &Utc);
+        assert_eq!(
+            nudges,
+            [Nudge {
+                rule: "focus".into(),
+                time: MONDAY + 34 * HOUR,
+                matching: 0,
+                window: 2,
+            }]
+        );
+        assert_eq!(engine.last_nudged("focus"), Some(MONDAY + 34 * HOUR));
+        // 10:00 in UTC is 2:00 in UTC-8, outside work hours
+        let mut engine = NudgeEngine::new(vec![work_hours(Threshold::FewerThan(1))]);
+        let pacific = FixedOffset::west_opt(8 * 3600).unwrap();
+        assert!(engine.check(&log(&pings), &pacific).is_empty());
+        // saturday
+        let weekend = [(MONDAY + 5 * 24 * HOUR + 10 * HOUR, "games")];
+        let mut engine = NudgeEngine::new(vec![work_hours(Threshold::FewerThan(1))]);
+        let mut pings = pings.to_vec();
+        pings.extend(weekend);
+        assert!(engine.check(&log(&pings), &Utc).is_empty());
+    }
+
+    #[test]
+    fn cools_down() {
+        let rule = Rule {
+            cooldown: 2 * HOUR,
+            ..Rule::new(
+                "games",
+                Expr::parse("games").unwrap(),
+                2,
+                Threshold::MoreThan(1),
+            )
+        };
+        let mut engine = NudgeEngine::new(vec![rule]);
+        let mut pings = vec![(0, "games"), (HOUR, "games")];
+        assert_eq!(engine.check(&log(&pings), &Utc).len(), 1);
+        pings.push((2 * HOUR, "games"));
+        assert!(engine.check(&log(&pings), &Utc).is_empty());
+        pings.push((3 * HOUR, "games"));
+        assert_eq!(engine.check(&log(&pings), &Utc)[0].time, 3 * HOUR);
+
+        let mut engine = NudgeEngine::new(engine.rules().to_vec());
+        engine.set_last_nudged("games", 3 * HOUR);
+        assert!(engine.check(&log(&pings), &Utc).is_empty());
+        assert!(engine.check(&PingLog::new(), &Utc).is_empty());
+    }
+}
@@ -0,0 +1,509 @@
+//! The ping schedule: when pings happen, for both the original TagTime algorithm and the newer
+//! hash-based one.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::hash::{self, Rate};
+use crate::tt;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PingAlg {
+    FnvTime,
+    TagTime,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PingIntervalData {
+    pub seed: u32,
+    pub avg_interval: u32,
+    pub alg: PingAlg,
+}
+
+/// Used to create interval data from JS. `seed` is passed in as a u32, but gets converted to
+/// a u64 in the returned struct. Not an associated function since I couldn't get that to work
+/// with wasm-bindgen.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn new_ping_interval_data(seed: u32, avg_interval: u32, old_alg: bool) -> PingIntervalData {
+    PingIntervalData {
+        seed,
+        avg_interval,
+        alg: if old_alg {
+            PingAlg::TagTime
+        } else {
+            PingAlg::FnvTime
+        },
+    }
+}
+
+/// Returns if a ping should occur at a given timestamp.
+pub fn should_ping_at_time(time: u64, interval_data: &PingIntervalData) -> bool {
+    match interval_data.alg {
+        PingAlg::FnvTime => fnv_ping(time, interval_data, Rate::new(interval_data.avg_interval)),
+        PingAlg::TagTime => {
+            let (mut state, mut pung) = match tt::State::from_seed_before(interval_data, time) {
+                Some(start) => start,
+                None => return false,
+            };
+            loop {
+                state.next_state();
+                let gap = state.gap(interval_data.avg_interval);
+                pung = match pung.checked_add(u64::from(gap)) {
+                    Some(pung) => pung,
+                    None => return false,
+                };
+                if pung > time {
+                    return false;
+                } else if pung == time {
+                    return true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn should_ping_at_time_u32(time: u32, interval_data: &PingIntervalData) -> bool {
+    should_ping_at_time(time as u64, interval_data)
+}
+
+/// Returns the next ping **after** a given timestamp. Returns None if there will never be another ping
+/// with a time repersentable as a u64. (although this should only occur if the average interval is *really* high).
+pub fn next_ping_after(mut t: u64, interval_data: &PingIntervalData) -> Option<u64> {
+    if interval_data.alg == PingAlg::TagTime {
+        // this can be optimized better than repeated calling of should_ping_at_time
+        let start = tt::State::from_seed_before(interval_data, t)?;
+        return tagtime_next_after(start, t, interval_data.avg_interval);
+    };
+    // NonZeroU64 isn't supported by wasm_bindgen, so we use a normal u64 (although zero will never be returned)
+    let rate = Rate::new(interval_data.avg_interval);
+    loop {
+        // if we fail to add one to the initial time, then the former time must have been the max
+        // u64 value, and therefore the next ping would be out of bounds (or never), so we return
+        // None in that case
+        t = t.checked_add(1)?;
+        if fnv_ping(t, interval_data, rate) {
+            return Some(t);
+        };
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn next_ping_after_u32(t: u32, interval_data: &PingIntervalData) -> Option<u32> {
+    // None after 2106, rather than wrapping
+    next_ping_after(t.into(), interval_data).and_then(|n| u32::try_from(n).ok())
+}
+
+/// Returns the next ping **before** a given timestamp. Returns None if there never was another earlier ping
+/// with a time repersentable as a u64. (although this should only occur if the average interval is *really* high).
+pub fn last_ping(mut t: u64, interval_data: &PingIntervalData) -> Option<u64> {
+    if interval_data.alg == PingAlg::TagTime {
+        // this can be optimized better than repeated calling of should_ping_at_time
+        let start = tt::State::from_seed_before(interval_data, t)?;
+        return tagtime_last_before(start, t, interval_data.avg_interval);
+    };
+
+    let rate = Rate::new(interval_data.avg_interval);
+    loop {
+        // if we fail to add one to the initial time, then the former time must have been the max
+        // u64 value, and therefore the next ping would be out of bounds (or never), so we return
+        // None in that case
+        t = t.checked_sub(1)?;
+        if t == 0 {
+            return None;
+        };
+        if fnv_ping(t, interval_data, rate) {
+            return Some(t);
+        };
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn last_ping_u32(t: u32, interval_data: &PingIntervalData) -> Option<u32> {
+    last_ping(t.into(), interval_data).and_then(|n| u32::try_from(n).ok())
+}
+
+/// Returns all pings between two specified times.
+///
+/// ## Panics
+/// Panics if t1 isn't less than t2. [`checked_pings_between`] returns None instead.
+pub fn pings_between(t1: u64, t2: u64, interval_data: &PingIntervalData) -> Vec<u64> {
+    assert!(
+        t1 < t2,
+        "t1 must be less than t2, since t1 and t2 specify a range."
+    );
+    if interval_data.alg == PingAlg::TagTime {
+        // this can be optimized better than repeated calling of should_ping_at_time
+        return match tt::State::from_seed_before(interval_data, t1) {
+            Some(start) => tagtime_pings_between(start, t1, t2, interval_data.avg_interval),
+            None => vec![],
+        };
+    };
+    let mut pings = Vec::with_capacity(1);
+    let rate = Rate::new(interval_data.avg_interval);
+    for t in t1..=t2 {
+        if fnv_ping(t, interval_data, rate) {
+            pings.push(t);
+        };
+    }
+    pings.shrink_to_fit();
+    pings
+}
+
+/// Like [`pings_between`], but returns None if t1 isn't less than t2.
+pub fn checked_pings_between(
+    t1: u64,
+    t2: u64,
+    interval_data: &PingIntervalData,
+) -> Option<Vec<u64>> {
+    if t1 < t2 {
+        Some(pings_between(t1, t2, interval_data))
+    } else {
+        None
+    }
+}
+
+/// If an FnvTime schedule pings at `time`, where `rate` is for the schedule's average interval.
+fn fnv_ping(time: u64, interval_data: &PingIntervalData, rate: Rate) -> bool {
+    rate.contains(hash::time_hash(time, u64::from(interval_data.seed)))
+}
+
+// The TagTime schedule is walked one ping at a time from a known state, and the ping it was at,
+// which must be before the times asked about. That's usually from `tt::State::from_seed_before`,
+// or a checkpoint in a `ScheduleCache`.
+
+/// The first TagTime ping after `t`, or None if it's past the end of time.
+pub(crate) fn tagtime_next_after(
+    start: (tt::State, u64),
+    t: u64,
+    avg_interval: u32,
+) -> Option<u64> {
+    let (mut state, mut pung) = start;
+    loop {
+        state.next_state();
+        let gap = state.gap(avg_interval);
+        pung = pung.checked_add(u64::from(gap))?;
+        if pung > t {
+            return Some(pung);
+        };
+    }
+}
+
+/// The last TagTime ping before `t`, or None if `t` is before the schedule starts.
+pub(crate) fn tagtime_last_before(
+    start: (tt::State, u64),
+    t: u64,
+    avg_interval: u32,
+) -> Option<u64> {
+    let (mut state, mut pung) = start;
+    // lookup table always has times/states at whole ping intervals
+    // so there's no way we can get a ping at or after t, unless t is before the first ping
+    if pung >= t {
+        return None;
+    }
+    loop {
+        state.next_state();
+        let gap = state.gap(avg_interval);
+        match pung.checked_add(u64::from(gap)) {
+            Some(next) if next < t => pung = next,
+            _ => return Some(pung),
+        }
+    }
+}
+
+/// The TagTime pings in `t1..=t2`.
+pub(crate) fn tagtime_pings_between(
+    start: (tt::State, u64),
+    t1: u64,
+    t2: u64,
+    avg_interval: u32,
+) -> Vec<u64> {
+    let (mut state, mut pung) = start;
+    let mut pings = Vec::with_capacity(1);
+    loop {
+        state.next_state();
+        let gap = state.gap(avg_interval);
+        pung = match pung.checked_add(u64::from(gap)) {
+            Some(pung) => pung,
+            None => return vec![],
+        };
+        if pung >= t1 {
+            if pung <= t2 {
+                pings.push(pung);
+                break;
+            } else {
+                return vec![];
+            };
+        };
+    }
+    loop {
+        state.next_state();
+        let gap = state.gap(avg_interval);
+        pung = match pung.checked_add(u64::from(gap)) {
+            Some(pung) => pung,
+            None => break,
+        };
+        if pung > t2 {
+            break;
+        } else if pung == t2 {
+            pings.push(pung);
+            break;
+        };
+        pings.push(pung);
+    }
+    pings.shrink_to_fit();
+    pings
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn pings_between_u32(t1: u32, t2: u32, interval_data: &PingIntervalData) -> Vec<u32> {
+    // the pings are before t2, so they all fit
+    checked_pings_between(t1.into(), t2.into(), interval_data)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|n| u32::try_from(n).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod fnv_alg {
+        use super::*;
+
+        #[test]
+        fn correct_tags_between() {
+            assert_eq!(
+                pings_between(5, 100, &new_ping_interval_data(1234, 28, false),),
+                vec![21, 50, 87]
+            )
+        }
+
+        #[test]
+        #[should_panic]
+        fn tags_between_panics_on_bad_range() {
+            pings_between(100, 5, &new_ping_interval_data(1234, 28, false));
+        }
+
+        #[test]
+        fn correct_next_ping() {
+            assert_eq!(
+                next_ping_after(10000000, &new_ping_interval_data(1234, 1000, false),),
+                Some(10001167)
+            );
+            assert_eq!(
+                next_ping_after(0, &new_ping_interval_data(543431, 60000, false),),
+                Some(40874)
+            );
+            assert_eq!(
+                next_ping_after(0, &new_ping_interval_data(0, 1, false),),
+                Some(1)
+            );
+        }
+
+        #[test]
+        fn next_ping_none_on_overflow() {
+            assert_eq!(
+                next_ping_after(u64::MAX, &new_ping_interval_data(54224, 1000, false),),
+                None
+            );
+            assert_eq!(
+                next_ping_after(
+                    u64::MAX - 1000000,
+                    &new_ping_interval_data(542432, u32::MAX, false),
+                ),
+                None
+            );
+        }
+
+        #[test]
+        fn u32_none_after_2106() {
+            let data = new_ping_interval_data(54224, 1000, false);
+            assert_eq!(next_ping_after_u32(u32::MAX - 1, &data), None);
+            let last = last_ping_u32(u32::MAX, &data).unwrap();
+            assert_eq!(next_ping_after_u32(last, &data), None);
+            assert!(pings_between_u32(last - 1, u32::MAX, &data).contains(&last));
+        }
+
+        #[test]
+        fn correct_last_ping() {
+            assert_eq!(
+                last_ping(10000000, &new_ping_interval_data(12352, 1000, false),),
+                Some(9999257)
+            );
+            assert_eq!(
+                last_ping(1000, &new_ping_interval_data(1234, 100, false),),
+                Some(944)
+            );
+        }
+
+        #[test]
+        fn correct_last_ping_none_on_underflow() {
+            assert_eq!(
+                last_ping(0, &new_ping_interval_data(1234, 100, false),),
+                None
+            );
+            assert_eq!(
+                last_ping(10000, &new_ping_interval_data(387112, 100000, false),),
+                None
+            );
+        }
+    }
+
+    /// Schedules found with the old floating point math, which every platform (including WASM and
+    /// ARM) has to give exactly.
+    #[test]
+    fn golden_vectors() {
+        assert_eq!(
+            pings_between(1_700_000_000, 1_700_040_000, &tt::UNIV_SCHED),
+            vec![
+                1700003368, 1700003660, 1700009822, 1700013932, 1700019178, 1700022140, 1700028458,
+                1700032126, 1700039277, 1700039342, 1700039595
+            ]
+        );
+        assert_eq!(
+            pings_between(
+                tt::UR_PING,
+                tt::UR_PING + 6000,
+                &new_ping_interval_data(1234, 600, true)
+            ),
+            vec![1184100177, 1184100865, 1184101390, 1184101406, 1184101906, 1184102885]
+        );
+        assert_eq!(
+            pings_between(
+                1_700_000_000,
+                1_700_020_000,
+                &new_ping_interval_data(1234, 2700, false)
+            ),
+            vec![
+                1700001640, 1700003250, 1700006350, 1700006540, 1700009882, 1700016536, 1700018585,
+                1700019330
+            ]
+        );
+        assert_eq!(
+            pings_between(
+                1_700_000_000,
+                1_700_000_060,
+                &new_ping_interval_data(99, 7, false)
+            ),
+            vec![
+                1700000003, 1700000010, 1700000017, 1700000020, 1700000023, 1700000040, 1700000041,
+                1700000048, 1700000051, 1700000057, 1700000060
+            ]
+        );
+    }
+
+    mod tagtime_alg {
+        use super::*;
+
+        // see https://tagtime.glitch.me/
+        #[test]
+        fn correct_should_ping() {
+            assert!(should_ping_at_time(1594907790, &tt::UNIV_SCHED));
+        }
+
+        #[test]
+        fn correct_next_ping_after() {
+            assert_eq!(
+                next_ping_after(1533754341, &tt::UNIV_SCHED),
+                Some(1533758980)
+            );
+            assert_eq!(
+                next_ping_after(1533754349, &tt::UNIV_SCHED),
+                Some(1533758980)
+            );
+        }
+
+        #[test]
+        fn correct_last_ping() {
+            assert_eq!(last_ping(1533758980, &tt::UNIV_SCHED), Some(1533754341));
+            assert_eq!(last_ping(1533758975, &tt::UNIV_SCHED), Some(1533754341));
+        }
+
+        #[test]
+        fn correct_tags_between() {
+            assert_eq!(
+                pings_between(1533748817, 1533759940, &tt::UNIV_SCHED),
+                vec![1533748817, 1533754341, 1533758980, 1533759940]
+            );
+            assert_eq!(
+                pings_between(1533748814, 1533759943, &tt::UNIV_SCHED),
+                vec![1533748817, 1533754341, 1533758980, 1533759940]
+            );
+            assert_eq!(
+                pings_between(1533748818, 1533759939, &tt::UNIV_SCHED),
+                vec![1533754341, 1533758980]
+            );
+            assert_eq!(
+                pings_between(1598481008, 1598481905, &tt::UNIV_SCHED),
+                Vec::<u64>::new(),
+            );
+            assert_eq!(
+                checked_pings_between(1533759940, 1533748817, &tt::UNIV_SCHED),
+                None
+            );
+        }
+
+        #[test]
+        fn whole_lookup_table() {
+            // the last item of the lookup table starts the schedule after 2029
+            let end = tt::UR_PING + 1643 * tt::LOOKUP_TABLE_INTERVAL;
+            for t in [end - 2 * tt::LOOKUP_TABLE_INTERVAL, end, end + 1000] {
+                let next = next_ping_after(t, &tt::UNIV_SCHED).unwrap();
+                assert_eq!(
+                    last_ping(next, &tt::UNIV_SCHED).map(|last| last <= t),
+                    Some(true)
+                );
+            }
+        }
+
+        #[test]
+        fn nothing_before_start() {
+            assert_eq!(last_ping(0, &tt::UNIV_SCHED), None);
+            assert_eq!(last_ping(tt::UR_PING, &tt::UNIV_SCHED), None);
+            assert_eq!(
+                last_ping(tt::UR_PING + 1, &tt::UNIV_SCHED),
+                Some(tt::UR_PING)
+            );
+            assert!(!should_ping_at_time(0, &tt::UNIV_SCHED));
+            assert_eq!(
+                next_ping_after(0, &tt::UNIV_SCHED),
+                next_ping_after(tt::UR_PING, &tt::UNIV_SCHED)
+            );
+        }
+
+        #[test]
+        fn end_of_time() {
+            let start = (tt::State::from_seed(1234), u64::MAX - 10);
+            assert_eq!(tagtime_next_after(start.clone(), u64::MAX - 5, 2700), None);
+            assert_eq!(
+                tagtime_last_before(start.clone(), u64::MAX, 2700),
+                Some(u64::MAX - 10)
+            );
+            assert_eq!(
+                tagtime_pings_between(start, u64::MAX - 5, u64::MAX, 2700),
+                Vec::<u64>::new()
+            );
+        }
+
+        #[test]
+        fn bad_seeds_never_ping() {
+            for seed in [0, tt::IM_U32, u32::MAX] {
+                let schedule = new_ping_interval_data(seed, 2700, true);
+                assert!(!should_ping_at_time(1533758980, &schedule));
+                assert_eq!(next_ping_after(1533758980, &schedule), None);
+                assert_eq!(last_ping(1533758980, &schedule), None);
+                assert_eq!(
+                    pings_between(1533748817, 1533759940, &schedule),
+                    Vec::<u64>::new()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Protobuf messages for sync payloads, defined in `proto/ttw.proto`.
+//!
+//! The messages are written by hand instead of generated, so building doesn't need `protoc`.
+//! Keep them in sync with the `.proto` file. Encoding and decoding is done with [`prost::Message`].
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use crate::log::{self, PingLog, PingRef};
+use crate::{PingAlg, PingIntervalData};
+
+pub use prost::Message;
+
+/// A single answered ping.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Ping {
+    #[prost(uint64, tag = "1")]
+    pub time: u64,
+    #[prost(string, repeated, tag = "2")]
+    pub tags: Vec<String>,
+    #[prost(uint32, tag = "3")]
+    pub interval: u32,
+    #[prost(string, optional, tag = "4")]
+    pub comment: Option<String>,
+    #[prost(uint64, optional, tag = "5")]
+    pub answered: Option<u64>,
+    #[prost(string, optional, tag = "6")]
+    pub author: Option<String>,
+    #[prost(btree_map = "string, double", tag = "7")]
+    pub weights: BTreeMap<String, f64>,
+}
+
+/// Changes that turn one version of a log into another.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct LogDelta {
+    /// Pings that were added or changed. Each replaces any ping with the same time.
+    #[prost(message, repeated, tag = "1")]
+    pub upserted: Vec<Ping>,
+    /// Times of pings that were removed.
+    #[prost(uint64, repeated, tag = "2")]
+    pub removed: Vec<u64>,
+}
+
+/// Which ping schedule a device is using, and how far through it it is.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SchedulerState {
+    #[prost(uint32, tag = "1")]
+    pub seed: u32,
+    #[prost(uint32, tag = "2")]
+    pub avg_interval: u32,
+    #[prost(enumeration = "scheduler_state::Algorithm", tag = "3")]
+    pub algorithm: i32,
+    /// Time of the last ping that was sent, so other devices don't notify about it again.
+    #[prost(uint64, tag = "4")]
+    pub last_ping: u64,
+}
+
+pub mod scheduler_state {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Algorithm {
+        FnvTime = 0,
+        TagTime = 1,
+    }
+}
+
+impl From<&log::Ping> for Ping {
+    fn from(ping: &log::Ping) -> Self {
+        Self {
+            time: ping.time,
+            tags: ping.tags.clone(),
+            interval: ping.interval,
+            comment: ping.comment.clone(),
+            answered: ping.answered,
+            author: ping.author.clone(),
+            weights: (ping.weights.iter())
+                .map(|(tag, weight)| (tag.clone(), weight.get()))
+                .collect(),
+        }
+    }
+}
+
+impl From<PingRef<'_>> for Ping {
+    fn from(ping: PingRef<'_>) -> Self {
+        Self {
+            time: ping.time,
+            tags: ping.tags.to_vec(),
+            interval: ping.interval,
+            comment: ping.comment.map(String::from),
+            answered: ping.answered,
+            author: ping.author.map(String::from),
+            weights: (ping.weights.iter())
+                .map(|(tag, weight)| (tag.to_string(), weight.get()))
+                .collect(),
+        }
+    }
+}
+
+impl From<Ping> for log::Ping {
+    fn from(ping: Ping) -> Self {
+        Self {
+            time: ping.time,
+            tags: ping.tags,
+            interval: ping.interval,
+            comment: ping.comment,
+            answered: ping.answered,
+            author: ping.author,
+            // weights that don't make sense are dropped, leaving the tag unweighted
+            weights: (ping.weights.into_iter())
+                .filter_map(|(tag, weight)| Some((tag, log::Weight::new(weight)?)))
+                .collect(),
+        }
+    }
+}
+
+impl LogDelta {
+    /// The changes from `old` to `new`. Pings are matched up by time, so if a log has more than
+    /// one ping at the same time, only the last is kept.
+    pub fn between(old: &PingLog, new: &PingLog) -> Self {
+        let old = by_time(old);
+        let new = by_time(new);
+        Self {
+            upserted: new
+                .iter()
+                .filter(|(time, ping)| old.get(time) != Some(ping))
+                .map(|(_, ping)| Ping::from(*ping))
+                .collect(),
+            removed: old
+                .keys()
+                .filter(|time| !new.contains_key(time))
+                .copied()
+                .collect(),
+        }
+    }
+
+    /// Applies the changes to `log`. Removals are applied before upserts.
+    pub fn apply(&self, log: &PingLog) -> PingLog {
+        let mut pings: BTreeMap<u64, log::Ping> = log
+            .pings()
+            .iter()
+            .map(|ping| (ping.time, ping.to_ping()))
+            .collect();
+        for time in &self.removed {
+            pings.remove(time);
+        }
+        for ping in &self.upserted {
+            pings.insert(ping.time, ping.clone().into());
+        }
+        PingLog::from_pings(pings.into_values().collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.upserted.is_empty() && self.removed.is_empty()
+    }
+}
+
+fn by_time(log: &PingLog) -> BTreeMap<u64, PingRef<'_>> {
+    log.pings().iter().map(|ping| (ping.time, ping)).collect()
+}
+
+impl SchedulerState {
+    pub fn new(schedule: &PingIntervalData, last_ping: u64) -> Self {
+        let algorithm = match schedule.alg {
+            PingAlg::FnvTime => scheduler_state::Algorithm::FnvTime,
+            PingAlg::TagTime => scheduler_state::Algorithm::TagTime,
+        };
+        Self {
+            seed: schedule.seed,
+            avg_interval: schedule.avg_interval,
+            algorithm: algorithm.into(),
+            last_ping,
+        }
+    }
+
+    /// The schedule, or None if it uses an algorithm this version doesn't know about.
+    pub fn schedule(&self) -> Option<PingIntervalData> {
+        let alg = match scheduler_state::Algorithm::try_from(self.algorithm).ok()? {
+            scheduler_state::Algorithm::FnvTime => PingAlg::FnvTime,
+            scheduler_state::Algorithm::TagTime => PingAlg::TagTime,
+        };
+        Some(PingIntervalData {
+            seed: self.seed,
+            avg_interval: self.avg_interval,
+            alg,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tt::UNIV_SCHED;
+
+    fn ping(time: u64, tag: &str) -> log::Ping {
+        log::Ping::new(time, vec![tag.into()], 2700)
+    }
+
+    #[test]
+    fn pings_round_trip() {
+        let mut original = ping(10, "a");
+        original.comment = Some("hi".into());
+        original.answered = Some(12);
+        let bytes = Ping::from(&original).encode_to_vec();
+        let decoded: log::Ping = Ping::decode(bytes.as_slice()).unwrap().into();
+        assert_eq!(decoded, original);
+
+        let bare = Ping::from(&ping(10, "a"));
+        assert_eq!(Ping::decode(bare.encode_to_vec().as_slice()).unwrap(), bare);
+    }
+
+    #[test]
+    fn deltas() {
+        let old = PingLog::from_pings(vec![ping(10, "a"), ping(20, "b"), ping(30, "c")]);
+        let new = PingLog::from_pings(vec![ping(10, "a"), ping(20, "changed"), ping(40, "d")]);
+        let delta = LogDelta::between(&old, &new);
+        assert_eq!(delta.removed, vec![30]);
+        assert_eq!(
+            delta
+                .upserted
+                .iter()
+                .map(|ping| ping.time)
+                .collect::<Vec<_>>(),
+            vec![20, 40]
+        );
+
+        let bytes = delta.encode_to_vec();
+        let decoded = LogDelta::decode(bytes.as_slice()).unwrap();
+        assert_eq!(decoded.apply(&old), new);
+        assert!(LogDelta::between(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn scheduler_state() {
+        let state = SchedulerState::new(&UNIV_SCHED, 1533758980);
+        let decoded = SchedulerState::decode(state.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded.schedule(), Some(UNIV_SCHED));
+        assert_eq!(decoded.last_ping, 1533758980);
+
+        let unknown = SchedulerState {
+            algorithm: 7,
+            ..state
+        };
+        assert_eq!(unknown.schedule(), None);
+    }
+}
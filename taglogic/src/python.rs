@@ -0,0 +1,289 @@
+//! Python bindings, for analyzing logs in pandas or Jupyter. Built with maturin (see
+//! `pyproject.toml`), as the `taglogic` module.
+//!
+//! Stats are returned as dicts (or lists of dicts), so they can be passed straight to
+//! `pandas.DataFrame`. Local days are given as a fixed offset from UTC in minutes.
+
+use chrono::{FixedOffset, NaiveDate};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::bool::Expr;
+use crate::log::{Ping, PingLog};
+use crate::stats::{self, Bucket, TimeEstimate};
+use crate::{tt, PingIntervalData};
+
+fn fixed_offset(utc_offset_mins: i32) -> PyResult<FixedOffset> {
+    utc_offset_mins
+        .checked_mul(60)
+        .and_then(FixedOffset::east_opt)
+        .ok_or_else(|| PyValueError::new_err("UTC offset must be less than a day"))
+}
+
+fn bucket(name: &str) -> PyResult<Bucket> {
+    match name {
+        "day" => Ok(Bucket::Day),
+        "week" => Ok(Bucket::Week),
+        "month" => Ok(Bucket::Month),
+        _ => Err(PyValueError::new_err(
+            "bucket must be \"day\", \"week\" or \"month\"",
+        )),
+    }
+}
+
+fn estimate_dict<'py>(py: Python<'py>, estimate: &TimeEstimate) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("pings", estimate.pings)?;
+    dict.set_item("hours", estimate.hours)?;
+    dict.set_item("low", estimate.low)?;
+    dict.set_item("high", estimate.high)?;
+    Ok(dict)
+}
+
+/// A tag expression, like `work & !email`.
+#[pyclass(name = "Expr", module = "taglogic", frozen)]
+pub struct PyExpr(Expr);
+
+#[pymethods]
+impl PyExpr {
+    /// Raises ValueError if the expression is invalid, with the message and the span of the
+    /// problem (as character offsets) in `args`.
+    #[new]
+    fn new(expr: &str) -> PyResult<Self> {
+        Expr::parse(expr).map(Self).map_err(|err| {
+            // Python strings are indexed by character, not byte
+            let span = err.offsets(expr).chars;
+            PyValueError::new_err((err.kind.to_string(), span.start, span.end))
+        })
+    }
+
+    fn matches(&self, tags: Vec<String>) -> bool {
+        let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+        self.0.matches(&tags)
+    }
+
+    /// The tags used in the expression, sorted and without duplicates.
+    fn tags(&self) -> Vec<String> {
+        self.0.tags().into_iter().map(String::from).collect()
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Expr({:?})", self.0.to_string())
+    }
+}
+
+/// A ping schedule.
+#[pyclass(name = "Schedule", module = "taglogic", frozen)]
+pub struct PySchedule(PingIntervalData);
+
+#[pymethods]
+impl PySchedule {
+    /// `tagtime` picks the original TagTime algorithm instead of the newer hash-based one.
+    #[new]
+    #[pyo3(signature = (seed, avg_interval, tagtime = false))]
+    fn new(seed: u32, avg_interval: u32, tagtime: bool) -> PyResult<Self> {
+        if avg_interval == 0 {
+            return Err(PyValueError::new_err("avg_interval must be positive"));
+        }
+        if tagtime && tt::State::try_from_seed(seed).is_none() {
+            return Err(PyValueError::new_err(
+                "TagTime seeds must be between 1 and 2147483646",
+            ));
+        }
+        Ok(Self(crate::new_ping_interval_data(
+            seed,
+            avg_interval,
+            tagtime,
+        )))
+    }
+
+    /// The universal TagTime schedule, which most TagTime clients use.
+    #[staticmethod]
+    fn universal() -> Self {
+        Self(tt::UNIV_SCHED)
+    }
+
+    fn should_ping(&self, time: u64) -> bool {
+        crate::should_ping_at_time(time, &self.0)
+    }
+
+    fn next_ping_after(&self, time: u64) -> Option<u64> {
+        crate::next_ping_after(time, &self.0)
+    }
+
+    fn last_ping_before(&self, time: u64) -> Option<u64> {
+        crate::last_ping(time, &self.0)
+    }
+
+    /// All pings from `start` up to `end`, inclusive.
+    fn pings_between(&self, start: u64, end: u64) -> Vec<u64> {
+        if start < end {
+            crate::pings_between(start, end, &self.0)
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// A log of answered pings.
+#[pyclass(name = "PingLog", module = "taglogic")]
+#[derive(Default)]
+pub struct PyPingLog(PingLog);
+
+#[pymethods]
+impl PyPingLog {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a ping, which repersents `interval` seconds of time.
+    #[pyo3(signature = (time, tags, interval, comment = None, answered = None))]
+    fn push(
+        &mut self,
+        time: u64,
+        tags: Vec<String>,
+        interval: u32,
+        comment: Option<String>,
+        answered: Option<u64>,
+    ) {
+        let mut ping = Ping::new(time, tags, interval);
+        ping.comment = comment;
+        ping.answered = answered;
+        self.0.push(ping);
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Estimated hours spent on `expr` from `start` up to (but not including) `end`, as a dict
+    /// with `pings`, `hours`, and the `low` and `high` ends of the 95% interval.
+    #[pyo3(signature = (expr, start = 0, end = u64::MAX))]
+    fn estimate<'py>(
+        &self,
+        py: Python<'py>,
+        expr: &PyExpr,
+        start: u64,
+        end: u64,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        estimate_dict(py, &stats::estimate(&self.0, &expr.0, start..end))
+    }
+
+    /// Estimated hours spent on `expr` on each local day from `first` to `last`, as a list of
+    /// `(date, hours)` tuples.
+    #[pyo3(signature = (expr, first, last, utc_offset_mins = 0))]
+    fn daily_hours(
+        &self,
+        expr: &PyExpr,
+        first: NaiveDate,
+        last: NaiveDate,
+        utc_offset_mins: i32,
+    ) -> PyResult<Vec<(NaiveDate, f64)>> {
+        let tz = fixed_offset(utc_offset_mins)?;
+        Ok(stats::daily_hours(&self.0, &expr.0, first..=last, &tz))
+    }
+
+    /// Estimated time spent on `expr` per `"day"`, `"week"` or `"month"`, as a list of dicts
+    /// like [`PingLog.estimate`], each with the `start` date of its bucket.
+    #[pyo3(signature = (expr, bucket, utc_offset_mins = 0))]
+    fn time_series<'py>(
+        &self,
+        py: Python<'py>,
+        expr: &PyExpr,
+        bucket: &str,
+        utc_offset_mins: i32,
+    ) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let tz = fixed_offset(utc_offset_mins)?;
+        let series = stats::time_series(&self.0, &expr.0, self::bucket(bucket)?, &tz);
+        series
+            .points
+            .iter()
+            .map(|point| {
+                let dict = estimate_dict(py, &point.estimate)?;
+                dict.set_item("start", point.start)?;
+                Ok(dict)
+            })
+            .collect()
+    }
+
+    /// Blocks of consecutive pings matching `expr`, as a list of dicts.
+    fn sessions<'py>(
+        &self,
+        py: Python<'py>,
+        expr: &PyExpr,
+        max_gap: u64,
+    ) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        stats::sessions(&self.0, &expr.0, max_gap)
+            .iter()
+            .map(|session| {
+                let dict = PyDict::new(py);
+                dict.set_item("start", session.start)?;
+                dict.set_item("end", session.end)?;
+                dict.set_item("pings", session.pings)?;
+                dict.set_item("estimated_secs", session.estimated_secs)?;
+                Ok(dict)
+            })
+            .collect()
+    }
+
+    /// Compares the time spent on `expr` in two ranges, each a `(start, end)` tuple.
+    fn compare<'py>(
+        &self,
+        py: Python<'py>,
+        expr: &PyExpr,
+        range_a: (u64, u64),
+        range_b: (u64, u64),
+    ) -> PyResult<Bound<'py, PyDict>> {
+        if range_a.0 >= range_a.1 || range_b.0 >= range_b.1 {
+            return Err(PyValueError::new_err("ranges must not be empty"));
+        }
+        let comparison =
+            stats::compare(&self.0, &expr.0, range_a.0..range_a.1, range_b.0..range_b.1);
+        let dict = PyDict::new(py);
+        dict.set_item("a", estimate_dict(py, &comparison.a)?)?;
+        dict.set_item("b", estimate_dict(py, &comparison.b)?)?;
+        dict.set_item("a_per_day", comparison.a_per_day)?;
+        dict.set_item("b_per_day", comparison.b_per_day)?;
+        dict.set_item("percent_change", comparison.percent_change)?;
+        dict.set_item("z_score", comparison.z_score)?;
+        dict.set_item("significant", comparison.significant)?;
+        Ok(dict)
+    }
+
+    /// When each tag was first and last used, as a list of dicts sorted by tag.
+    #[pyo3(signature = (utc_offset_mins = 0))]
+    fn tag_lifetimes<'py>(
+        &self,
+        py: Python<'py>,
+        utc_offset_mins: i32,
+    ) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let tz = fixed_offset(utc_offset_mins)?;
+        stats::tag_lifetimes(&self.0, &tz)
+            .into_iter()
+            .map(|lifetime| {
+                let dict = PyDict::new(py);
+                dict.set_item("tag", lifetime.tag)?;
+                dict.set_item("first_seen", lifetime.first_seen)?;
+                dict.set_item("last_seen", lifetime.last_seen)?;
+                dict.set_item("pings", lifetime.pings)?;
+                dict.set_item("peak_month", lifetime.peak_month)?;
+                dict.set_item("peak_month_pings", lifetime.peak_month_pings)?;
+                Ok(dict)
+            })
+            .collect()
+    }
+}
+
+#[pymodule]
+fn taglogic(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyExpr>()?;
+    m.add_class::<PySchedule>()?;
+    m.add_class::<PyPingLog>()?;
+    Ok(())
+}
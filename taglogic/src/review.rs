@@ -0,0 +1,263 @@
+//! Finding pings that are likely tagged wrong, for the user to look over a few at a time.
+//!
+//! A ping is worth a look if it has a tag that's hardly ever used (usually a typo), a tag the
+//! user marked as unsure with a `maybe-` prefix, or two tags their taxonomy says can't go
+//! together. Once a ping is reviewed it stays out of the queue until it's answered again.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::bool::Taxonomy;
+use crate::log::PingLog;
+
+/// The prefix of tags the user wasn't sure about, like `maybe-gym`.
+pub const UNSURE_PREFIX: &str = "maybe-";
+
+/// Why a ping might be tagged wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReviewReason {
+    /// Tags the taxonomy says can't go together.
+    Conflict([String; 2]),
+    /// A tag the user wasn't sure about, starting with [`UNSURE_PREFIX`].
+    Unsure(String),
+    /// A tag on so few pings in the log it might be a typo.
+    Rare { tag: String, pings: usize },
+}
+
+/// A ping to review, from [`review_queue`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReviewItem {
+    /// Unix timestamp (in seconds) of when the ping was sent.
+    pub time: u64,
+    /// Why the ping might be tagged wrong, most important first.
+    pub reasons: Vec<ReviewReason>,
+    /// How much the ping needs a look. Pings with a higher priority come first.
+    pub priority: f64,
+}
+
+/// Which pings to put in a [`review_queue`].
+#[derive(Debug, Clone, Copy)]
+pub struct ReviewOptions<'a> {
+    /// Relations between tags, for finding ones that can't go together.
+    pub taxonomy: Option<&'a Taxonomy>,
+    /// When each ping was last reviewed, by the time it was sent. Pings reviewed since they
+    /// were last answered are left out.
+    pub reviewed: Option<&'a HashMap<u64, u64>>,
+    /// Tags on at most this many pings are rare.
+    pub rare_pings: usize,
+    /// The most pings to return.
+    pub limit: usize,
+}
+
+impl Default for ReviewOptions<'_> {
+    /// 20 pings, about five minutes of reviewing, with tags on only one ping counting as rare.
+    fn default() -> Self {
+        Self {
+            taxonomy: None,
+            reviewed: None,
+            rare_pings: 1,
+            limit: 20,
+        }
+    }
+}
+
+impl ReviewReason {
+    fn priority(&self) -> f64 {
+        match self {
+            Self::Conflict(_) => 1.0,
+            Self::Unsure(_) => 0.75,
+            Self::Rare { pings, .. } => 0.5 / *pings as f64,
+        }
+    }
+}
+
+impl fmt::Display for ReviewReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Conflict([a, b]) => write!(f, "`{}` and `{}` can't go together", a, b),
+            Self::Unsure(tag) => write!(f, "`{}` might not be right", tag),
+            Self::Rare { tag, pings: 1 } => write!(f, "`{}` isn't on any other ping", tag),
+            Self::Rare { tag, pings } => write!(f, "`{}` is only on {} pings", tag, pings),
+        }
+    }
+}
+
+/// The pings in `log` most likely to be tagged wrong, most likely first and newest first after
+/// that. Pings without tags aren't included, since they're missing rather than wrong.
+///
+/// ```
+/// use taglogic::log::{Ping, PingLog};
+/// use taglogic::review::{review_queue, ReviewOptions};
+///
+/// let ping = |time, tags: &[&str]| {
+///     Ping::new(time, tags.iter().map(|tag| tag.to_string()).collect(), 2700)
+/// };
+/// let log = PingLog::from_pings(vec![
+///     ping(0, &["work"]),
+///     ping(1, &["wrok"]),
+///     ping(2, &["work", "maybe-email"]),
+///     ping(3, &["work", "maybe-email"]),
+/// ]);
+/// let queue = review_queue(&log, &ReviewOptions::default());
+/// assert_eq!(queue[0].time, 3);
+/// assert_eq!(queue[0].reasons[0].to_string(), "`maybe-email` might not be right");
+/// assert_eq!(queue[2].reasons[0].to_string(), "`wrok` isn't on any other ping");
+/// assert_eq!(queue.len(), 3);
+/// ```
+pub fn review_queue(log: &PingLog, options: &ReviewOptions) -> Vec<ReviewItem> {
+    let mut pings_with: HashMap<&str, usize> = HashMap::new();
+    for ping in &log.pings() {
+        let mut tags: Vec<&str> = ping.tags.iter().collect();
+        tags.sort_unstable();
+        tags.dedup();
+        for tag in tags {
+            *pings_with.entry(tag).or_default() += 1;
+        }
+    }
+
+    let mut queue = Vec::new();
+    for ping in &log.pings() {
+        let reviewed = options
+            .reviewed
+            .and_then(|reviewed| reviewed.get(&ping.time));
+        if ping.tags.is_empty() || reviewed.is_some_and(|&at| Some(at) >= ping.answered) {
+            continue;
+        }
+        let tags: Vec<&str> = ping.tags.iter().collect();
+        let mut reasons = Vec::new();
+        if let Some(taxonomy) = options.taxonomy {
+            for conflict in taxonomy.conflicts(&tags) {
+                reasons.push(ReviewReason::Conflict(conflict.tags.map(String::from)));
+            }
+        }
+        for (i, &tag) in tags.iter().enumerate() {
+            if tags[..i].contains(&tag) {
+                continue;
+            }
+            if tag.starts_with(UNSURE_PREFIX) {
+                reasons.push(ReviewReason::Unsure(tag.to_string()));
+            }
+            let pings = pings_with.get(tag).copied().unwrap_or_default();
+            if pings <= options.rare_pings {
+                let tag = tag.to_string();
+                reasons.push(ReviewReason::Rare { tag, pings });
+            }
+        }
+        if reasons.is_empty() {
+            continue;
+        }
+        reasons.sort_by(|a, b| b.priority().total_cmp(&a.priority()));
+        queue.push(ReviewItem {
+            time: ping.time,
+            priority: reasons.iter().map(ReviewReason::priority).sum(),
+            reasons,
+        });
+    }
+    queue.sort_by(|a, b| match b.priority.total_cmp(&a.priority) {
+        Ordering::Equal => b.time.cmp(&a.time),
+        ordering => ordering,
+    });
+    queue.truncate(options.limit);
+    queue
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+
+    fn ping(time: u64, tags: &str) -> Ping {
+        let tags = tags.split_whitespace().map(String::from).collect();
+        Ping::new(time, tags, 2700)
+    }
+
+    fn queue(log: &PingLog, options: &ReviewOptions) -> Vec<(u64, Vec<String>)> {
+        let queue = review_queue(log, options);
+        let reasons = |item: ReviewItem| item.reasons.iter().map(ToString::to_string).collect();
+        queue
+            .into_iter()
+            .map(|item| (item.time, reasons(item)))
+            .collect()
+    }
+
+    fn log() -> PingLog {
+        PingLog::from_pings(pings())
+    }
+
+    fn pings() -> Vec<Ping> {
+        vec![
+            ping(1, "work code"),
+            ping(2, "work code"),
+            ping(3, "home office"),
+            ping(4, "work maybe-code"),
+            ping(5, "wrok"),
+            ping(6, ""),
+            ping(7, "home work"),
+            ping(8, "home work"),
+        ]
+    }
+
+    #[test]
+    fn finds_likely_mistakes() {
+        let mut taxonomy = Taxonomy::new();
+        taxonomy.exclusive(&["home", "office"]);
+        let options = ReviewOptions {
+            taxonomy: Some(&taxonomy),
+            ..ReviewOptions::default()
+        };
+        assert_eq!(
+            queue(&log(), &options),
+            [
+                (
+                    3,
+                    vec![
+                        "`home` and `office` can't go together".to_string(),
+                        "`office` isn't on any other ping".to_string()
+                    ]
+                ),
+                (
+                    4,
+                    vec![
+                        "`maybe-code` might not be right".to_string(),
+                        "`maybe-code` isn't on any other ping".to_string()
+                    ]
+                ),
+                (5, vec!["`wrok` isn't on any other ping".to_string()]),
+            ]
+        );
+
+        // without the taxonomy, and with tags on two pings counting as rare
+        let options = ReviewOptions {
+            rare_pings: 2,
+            limit: 4,
+            ..ReviewOptions::default()
+        };
+        let queue = queue(&log(), &options);
+        let times: Vec<u64> = queue.iter().map(|(time, _)| *time).collect();
+        assert_eq!(times, [4, 5, 3, 2]);
+        assert_eq!(queue[3].1, ["`code` is only on 2 pings"]);
+    }
+
+    #[test]
+    fn leaves_out_reviewed_pings() {
+        let mut reviewed = HashMap::new();
+        reviewed.insert(4, 100);
+        reviewed.insert(5, 100);
+        let options = ReviewOptions {
+            reviewed: Some(&reviewed),
+            ..ReviewOptions::default()
+        };
+        let times = |log: &PingLog| -> Vec<u64> {
+            let queue = review_queue(log, &options);
+            queue.iter().map(|item| item.time).collect()
+        };
+        assert_eq!(times(&log()), [3]);
+
+        // retagged after the review, so it needs another look
+        let mut pings = pings();
+        pings[4] = ping(5, "work2");
+        pings[4].answered = Some(200);
+        assert_eq!(times(&PingLog::from_pings(pings)), [5, 3]);
+    }
+}
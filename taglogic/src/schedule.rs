@@ -0,0 +1,429 @@
+//! Checkpoints in a TagTime schedule, so finding pings years after the start of the schedule
+//! doesn't walk every ping since then.
+//!
+//! Each TagTime ping comes from the RNG state after the last one, so the only way to find a ping
+//! is to start from a known state. The universal schedule has a lookup table built in, but other
+//! schedules start from [`UR_PING`] every time. A [`ScheduleCache`] stores
+//! the state every few pings, which is small enough to save and load with the rest of the app's
+//! data.
+
+use alloc::vec::Vec;
+
+use crate::tt::{self, IM_U32, UR_PING};
+use crate::{PingAlg, PingIntervalData};
+
+mod verify;
+
+pub use verify::{verify_schedule, ScheduleMismatch, ScheduleReport};
+
+/// Version of the format written by [`ScheduleCache::to_bytes`].
+pub const SCHEDULE_CACHE_VERSION: u8 = 1;
+
+const MAGIC: &[u8; 4] = b"TTWS";
+
+/// Length of everything before the checkpoints: the magic, version, algorithm, seed, average
+/// interval, checkpoint spacing, and number of checkpoints.
+const HEADER_LEN: usize = 4 + 1 + 1 + 4 + 4 + 4 + 4;
+
+/// An error reading a cache written by [`ScheduleCache::to_bytes`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum ScheduleCacheError {
+    #[error("not a schedule cache")]
+    NotScheduleCache,
+    #[error("unsupported schedule cache version")]
+    UnsupportedVersion,
+    #[error("unknown ping algorithm")]
+    UnknownAlg,
+    #[error("checkpoints must be at least one ping apart")]
+    ZeroSpacing,
+    #[error("invalid seed")]
+    InvalidSeed,
+    #[error("invalid RNG state")]
+    InvalidState,
+    #[error("checkpoints must be in order")]
+    OutOfOrder,
+    #[error("checkpoint time out of range")]
+    TimeOutOfRange,
+    #[error("trailing bytes after checkpoints")]
+    TrailingBytes,
+    #[error("schedule cache ended early")]
+    Truncated,
+    #[error("invalid varint")]
+    InvalidVarint,
+}
+
+/// The RNG state and time of every `every`th ping of a schedule, which are binary searched to
+/// start from the last checkpoint before the times asked about.
+///
+/// Schedules using [`PingAlg::FnvTime`] don't need checkpoints, since each second is checked on
+/// its own, so the cache is always empty for them and its methods are the same as the plain
+/// functions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleCache {
+    schedule: PingIntervalData,
+    every: u32,
+    /// The state of the RNG after each checkpoint's ping, and the ping's time, oldest first.
+    /// Checkpoint `i` is ping `(i + 1) * every` after `UR_PING`.
+    checkpoints: Vec<(u32, u64)>,
+}
+
+impl ScheduleCache {
+    /// A cache with a checkpoint every `every` pings, up to `until`.
+    ///
+    /// ## Panics
+    /// Panics if `every` is 0.
+    pub fn new(schedule: PingIntervalData, every: u32, until: u64) -> Self {
+        assert!(every > 0, "checkpoints must be at least one ping apart");
+        let mut cache = Self {
+            schedule,
+            every,
+            checkpoints: Vec::new(),
+        };
+        cache.extend(until);
+        cache
+    }
+
+    /// Adds checkpoints up to `until`, for when the schedule has moved on since the cache was
+    /// made.
+    pub fn extend(&mut self, until: u64) {
+        if self.schedule.alg != PingAlg::TagTime {
+            return;
+        }
+        let (mut state, mut pung) = match self.start_before(u64::MAX) {
+            Some(start) => start,
+            None => return,
+        };
+        loop {
+            for _ in 0..self.every {
+                state.next_state();
+                pung = match pung.checked_add(u64::from(state.gap(self.schedule.avg_interval))) {
+                    Some(pung) => pung,
+                    None => return,
+                };
+            }
+            if pung > until {
+                return;
+            }
+            self.checkpoints.push((state.inner(), pung));
+        }
+    }
+
+    pub fn schedule(&self) -> &PingIntervalData {
+        &self.schedule
+    }
+
+    /// Number of pings between checkpoints.
+    pub fn every(&self) -> u32 {
+        self.every
+    }
+
+    /// Time of the last checkpoint, or `None` if there aren't any. Later times still work, but
+    /// walk the schedule from there.
+    pub fn last_checkpoint(&self) -> Option<u64> {
+        self.checkpoints.last().map(|&(_, time)| time)
+    }
+
+    /// Number of checkpoints.
+    pub fn len(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.checkpoints.is_empty()
+    }
+
+    /// The last checkpoint before `t`, or the start of the schedule if there isn't one. Returns
+    /// None if the schedule's seed isn't valid.
+    fn start_before(&self, t: u64) -> Option<(tt::State, u64)> {
+        let index = self.checkpoints.partition_point(|&(_, time)| time < t);
+        match index
+            .checked_sub(1)
+            .and_then(|index| self.checkpoints.get(index))
+        {
+            Some(&(state, time)) => Some((tt::State::try_from_seed(state)?, time)),
+            None => Some((tt::State::try_from_seed(self.schedule.seed)?, UR_PING)),
+        }
+    }
+
+    /// Like [`next_ping_after`](crate::next_ping_after).
+    pub fn next_ping_after(&self, t: u64) -> Option<u64> {
+        match self.schedule.alg {
+            PingAlg::TagTime => crate::ping::tagtime_next_after(
+                self.start_before(t)?,
+                t,
+                self.schedule.avg_interval,
+            ),
+            PingAlg::FnvTime => crate::next_ping_after(t, &self.schedule),
+        }
+    }
+
+    /// Like [`last_ping`](crate::last_ping).
+    pub fn last_ping(&self, t: u64) -> Option<u64> {
+        match self.schedule.alg {
+            PingAlg::TagTime => crate::ping::tagtime_last_before(
+                self.start_before(t)?,
+                t,
+                self.schedule.avg_interval,
+            ),
+            PingAlg::FnvTime => crate::last_ping(t, &self.schedule),
+        }
+    }
+
+    /// Like [`pings_between`](crate::pings_between).
+    ///
+    /// ## Panics
+    /// Panics if t1 isn't less than t2. [`checked_pings_between`](Self::checked_pings_between)
+    /// returns None instead.
+    pub fn pings_between(&self, t1: u64, t2: u64) -> Vec<u64> {
+        match self.schedule.alg {
+            PingAlg::TagTime => {
+                assert!(
+                    t1 < t2,
+                    "t1 must be less than t2, since t1 and t2 specify a range."
+                );
+                match self.start_before(t1) {
+                    Some(start) => crate::ping::tagtime_pings_between(
+                        start,
+                        t1,
+                        t2,
+                        self.schedule.avg_interval,
+                    ),
+                    None => Vec::new(),
+                }
+            }
+            PingAlg::FnvTime => crate::pings_between(t1, t2, &self.schedule),
+        }
+    }
+
+    /// Like [`checked_pings_between`](crate::checked_pings_between).
+    pub fn checked_pings_between(&self, t1: u64, t2: u64) -> Option<Vec<u64>> {
+        if t1 < t2 {
+            Some(self.pings_between(t1, t2))
+        } else {
+            None
+        }
+    }
+
+    /// Writes the cache compactly: after a short header, each checkpoint is its 4 byte state and
+    /// the seconds since the last checkpoint as a varint, which is usually 3 bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.checkpoints.len() * 7);
+        out.extend_from_slice(MAGIC);
+        out.push(SCHEDULE_CACHE_VERSION);
+        out.push(match self.schedule.alg {
+            PingAlg::FnvTime => 0,
+            PingAlg::TagTime => 1,
+        });
+        out.extend_from_slice(&self.schedule.seed.to_le_bytes());
+        out.extend_from_slice(&self.schedule.avg_interval.to_le_bytes());
+        out.extend_from_slice(&self.every.to_le_bytes());
+        out.extend_from_slice(&(self.checkpoints.len() as u32).to_le_bytes());
+        let mut last = UR_PING;
+        for &(state, time) in &self.checkpoints {
+            out.extend_from_slice(&state.to_le_bytes());
+            write_varint(&mut out, time - last);
+            last = time;
+        }
+        out
+    }
+
+    /// Reads a cache written by [`to_bytes`](Self::to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ScheduleCacheError> {
+        let (version, alg, mut rest) = match bytes.strip_prefix(MAGIC) {
+            Some([version, alg, rest @ ..]) if bytes.len() >= HEADER_LEN => (*version, *alg, rest),
+            _ => return Err(ScheduleCacheError::NotScheduleCache),
+        };
+        if version != SCHEDULE_CACHE_VERSION {
+            return Err(ScheduleCacheError::UnsupportedVersion);
+        }
+        let alg = match alg {
+            0 => PingAlg::FnvTime,
+            1 => PingAlg::TagTime,
+            _ => return Err(ScheduleCacheError::UnknownAlg),
+        };
+        let schedule = PingIntervalData {
+            seed: read_u32(&mut rest)?,
+            avg_interval: read_u32(&mut rest)?,
+            alg,
+        };
+        let every = read_u32(&mut rest)?;
+        let count = read_u32(&mut rest)? as usize;
+        if every == 0 {
+            return Err(ScheduleCacheError::ZeroSpacing);
+        }
+        if alg == PingAlg::TagTime && !(1..IM_U32).contains(&schedule.seed) {
+            return Err(ScheduleCacheError::InvalidSeed);
+        }
+
+        // every checkpoint takes at least 5 bytes
+        let mut checkpoints = Vec::with_capacity(count.min(rest.len() / 5));
+        let mut last = UR_PING;
+        for _ in 0..count {
+            let state = read_u32(&mut rest)?;
+            if !(1..IM_U32).contains(&state) {
+                return Err(ScheduleCacheError::InvalidState);
+            }
+            let delta = read_varint(&mut rest)?;
+            if delta == 0 {
+                return Err(ScheduleCacheError::OutOfOrder);
+            }
+            last = match last.checked_add(delta) {
+                Some(time) => time,
+                None => return Err(ScheduleCacheError::TimeOutOfRange),
+            };
+            checkpoints.push((state, last));
+        }
+        if !rest.is_empty() {
+            return Err(ScheduleCacheError::TrailingBytes);
+        }
+        Ok(Self {
+            schedule,
+            every,
+            checkpoints,
+        })
+    }
+}
+
+/// Writes `value` as a LEB128 varint, 7 bits per byte with the high bit set on all but the last.
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads a little-endian u32 off the front of `bytes`.
+fn read_u32(bytes: &mut &[u8]) -> Result<u32, ScheduleCacheError> {
+    match bytes.split_first_chunk::<4>() {
+        Some((word, rest)) => {
+            *bytes = rest;
+            Ok(u32::from_le_bytes(*word))
+        }
+        None => Err(ScheduleCacheError::Truncated),
+    }
+}
+
+/// Reads a varint written by [`write_varint`] off the front of `bytes`.
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, ScheduleCacheError> {
+    let mut value = 0u64;
+    for (i, &byte) in bytes.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            *bytes = bytes.get(i + 1..).unwrap_or_default();
+            return Ok(value);
+        }
+    }
+    Err(ScheduleCacheError::InvalidVarint)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::new_ping_interval_data;
+    use alloc::vec;
+
+    /// About a year after the start of the schedule.
+    const YEAR_IN: u64 = UR_PING + 365 * 86400;
+
+    fn custom() -> PingIntervalData {
+        new_ping_interval_data(1234, 2700, true)
+    }
+
+    #[test]
+    fn matches_uncached() {
+        for schedule in [custom(), tt::UNIV_SCHED] {
+            let cache = ScheduleCache::new(schedule, 64, YEAR_IN);
+            assert!(cache.len() > 100);
+            // ranges before, between, on and after checkpoints, and after the last one
+            let checkpoint = cache.checkpoints[10].1;
+            for &(t1, t2) in &[
+                (UR_PING + 1, UR_PING + 86400),
+                (checkpoint - 5000, checkpoint + 5000),
+                (checkpoint, checkpoint + 1),
+                (checkpoint - 1, checkpoint),
+                (YEAR_IN - 86400, YEAR_IN + 86400),
+                (YEAR_IN + 86400, YEAR_IN + 2 * 86400),
+            ] {
+                assert_eq!(
+                    cache.pings_between(t1, t2),
+                    crate::pings_between(t1, t2, &schedule),
+                    "{} {}",
+                    t1,
+                    t2
+                );
+                assert_eq!(
+                    cache.next_ping_after(t1),
+                    crate::next_ping_after(t1, &schedule)
+                );
+                assert_eq!(cache.last_ping(t2), crate::last_ping(t2, &schedule));
+            }
+        }
+    }
+
+    #[test]
+    fn checkpoints_are_every_nth_ping() {
+        let cache = ScheduleCache::new(custom(), 3, UR_PING + 86400);
+        let pings = crate::pings_between(UR_PING + 1, UR_PING + 86400, &custom());
+        let times: Vec<u64> = cache.checkpoints.iter().map(|&(_, time)| time).collect();
+        let every_third: Vec<u64> = pings.iter().skip(2).step_by(3).copied().collect();
+        assert_eq!(times, every_third);
+        assert_eq!(cache.last_checkpoint(), every_third.last().copied());
+    }
+
+    #[test]
+    fn extends() {
+        let mut cache = ScheduleCache::new(custom(), 50, UR_PING + 86400 * 30);
+        cache.extend(YEAR_IN);
+        assert_eq!(cache, ScheduleCache::new(custom(), 50, YEAR_IN));
+        // extending to an earlier time changes nothing
+        cache.extend(UR_PING);
+        assert_eq!(cache, ScheduleCache::new(custom(), 50, YEAR_IN));
+    }
+
+    #[test]
+    fn fnv_has_no_checkpoints() {
+        let schedule = new_ping_interval_data(1234, 28, false);
+        let cache = ScheduleCache::new(schedule, 10, YEAR_IN);
+        assert!(cache.is_empty());
+        assert_eq!(cache.pings_between(5, 100), vec![21, 50, 87]);
+        assert_eq!(ScheduleCache::from_bytes(&cache.to_bytes()), Ok(cache));
+    }
+
+    #[test]
+    fn round_trips_compactly() {
+        let cache = ScheduleCache::new(custom(), 16, YEAR_IN);
+        let bytes = cache.to_bytes();
+        assert!(
+            bytes.len() <= HEADER_LEN + cache.len() * 8,
+            "{}",
+            bytes.len()
+        );
+        assert_eq!(ScheduleCache::from_bytes(&bytes), Ok(cache));
+    }
+
+    #[test]
+    fn rejects_bad_bytes() {
+        let bytes = ScheduleCache::new(custom(), 16, UR_PING + 86400 * 30).to_bytes();
+        let error = |bytes: &[u8]| ScheduleCache::from_bytes(bytes).unwrap_err();
+        assert_eq!(error(b"TTWL"), ScheduleCacheError::NotScheduleCache);
+        let mut version = bytes.clone();
+        version[4] = 9;
+        assert_eq!(error(&version), ScheduleCacheError::UnsupportedVersion);
+        assert_eq!(
+            error(&bytes[..bytes.len() - 1]),
+            ScheduleCacheError::InvalidVarint
+        );
+        assert_eq!(
+            error(&bytes[..bytes.len() - 4]),
+            ScheduleCacheError::Truncated
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(error(&trailing), ScheduleCacheError::TrailingBytes);
+        let mut state = bytes;
+        state[HEADER_LEN..HEADER_LEN + 4].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(error(&state), ScheduleCacheError::InvalidState);
+    }
+}
@@ -0,0 +1,197 @@
+use alloc::vec::Vec;
+
+use super::ScheduleCache;
+
+/// A difference between the pings a client sent and the schedule, from [`verify_schedule`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScheduleMismatch {
+    /// A ping that isn't in the schedule, with the scheduled ping nearest to it (if there is one).
+    Unexpected { time: u64, nearest: Option<u64> },
+    /// A scheduled ping between the first and last pings sent that wasn't sent.
+    Missing { time: u64 },
+    /// A ping sent more than once.
+    Duplicate { time: u64 },
+}
+
+impl ScheduleMismatch {
+    /// The time of the ping that's wrong.
+    pub fn time(&self) -> u64 {
+        match *self {
+            Self::Unexpected { time, .. } | Self::Missing { time } | Self::Duplicate { time } => {
+                time
+            }
+        }
+    }
+}
+
+/// The result of [`verify_schedule`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScheduleReport {
+    /// How many different pings were checked.
+    pub checked: usize,
+    /// Every mismatch, sorted by time.
+    pub mismatches: Vec<ScheduleMismatch>,
+}
+
+impl ScheduleReport {
+    /// If every ping matched the schedule.
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    /// If there are unexpected pings and every one is the same number of seconds after the
+    /// nearest scheduled ping, that number. A constant offset points to a wrong clock or time
+    /// zone, and different offsets to a wrong seed or schedule.
+    pub fn offset(&self) -> Option<i64> {
+        let mut offsets = self
+            .mismatches
+            .iter()
+            .filter_map(|mismatch| match *mismatch {
+                ScheduleMismatch::Unexpected { time, nearest } => {
+                    Some(nearest.map(|nearest| time as i64 - nearest as i64))
+                }
+                _ => None,
+            });
+        let first = offsets.next()??;
+        offsets.all(|offset| offset == Some(first)).then_some(first)
+    }
+}
+
+/// Checks the times of pings a client sent against the schedule in `cache`, so a bug in a
+/// platform's notifications (or a wrong seed) shows up instead of quietly skewing stats. Pings
+/// don't need to be sorted. Only the span from the first ping sent to the last is checked, so
+/// pings missed before or after that aren't reported.
+///
+/// ```
+/// use taglogic::schedule::{verify_schedule, ScheduleCache, ScheduleMismatch};
+/// use taglogic::tt::{UNIV_SCHED, UR_PING};
+///
+/// let cache = ScheduleCache::new(UNIV_SCHED, 64, UR_PING);
+/// let mut pings = cache.pings_between(UR_PING + 1, UR_PING + 86400);
+/// assert!(verify_schedule(&pings, &cache).is_ok());
+///
+/// let dropped = pings.remove(3);
+/// let report = verify_schedule(&pings, &cache);
+/// assert_eq!(report.mismatches, [ScheduleMismatch::Missing { time: dropped }]);
+/// ```
+pub fn verify_schedule(observed: &[u64], cache: &ScheduleCache) -> ScheduleReport {
+    let mut observed = observed.to_vec();
+    observed.sort_unstable();
+    let mut mismatches = Vec::new();
+    let mut sent: Vec<u64> = Vec::with_capacity(observed.len());
+    for time in observed {
+        if sent.last() == Some(&time) {
+            mismatches.push(ScheduleMismatch::Duplicate { time });
+        } else {
+            sent.push(time);
+        }
+    }
+    let (first, last) = match (sent.first(), sent.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return ScheduleReport::default(),
+    };
+    let mut expected =
+        (cache.checked_pings_between(first, last.saturating_add(1))).unwrap_or_default();
+    expected.retain(|&time| time <= last);
+
+    let nearest = |time: u64| {
+        let before = cache.last_ping(time);
+        let after = cache.next_ping_after(time);
+        match (before, after) {
+            (Some(before), Some(after)) if after - time < time - before => Some(after),
+            (Some(before), _) => Some(before),
+            (None, after) => after,
+        }
+    };
+    let checked = sent.len();
+    let (mut sent, mut expected) = (sent.iter().peekable(), expected.iter().peekable());
+    loop {
+        match (sent.peek(), expected.peek()) {
+            (Some(&&time), Some(&&scheduled)) if time == scheduled => {
+                sent.next();
+                expected.next();
+            }
+            (Some(&&time), Some(&&scheduled)) if scheduled < time => {
+                mismatches.push(ScheduleMismatch::Missing { time: scheduled });
+                expected.next();
+            }
+            (Some(&&time), _) => {
+                let nearest = nearest(time);
+                mismatches.push(ScheduleMismatch::Unexpected { time, nearest });
+                sent.next();
+            }
+            (None, Some(&&scheduled)) => {
+                mismatches.push(ScheduleMismatch::Missing { time: scheduled });
+                expected.next();
+            }
+            (None, None) => break,
+        }
+    }
+    mismatches.sort_by_key(ScheduleMismatch::time);
+    ScheduleReport {
+        checked,
+        mismatches,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::new_ping_interval_data;
+    use crate::tt::{UNIV_SCHED, UR_PING};
+
+    #[test]
+    fn reports_mismatches() {
+        let cache = ScheduleCache::new(UNIV_SCHED, 64, UR_PING + 86400);
+        let pings = cache.pings_between(UR_PING + 1, UR_PING + 86400);
+        assert!(pings.len() > 10);
+        assert_eq!(verify_schedule(&[], &cache), ScheduleReport::default());
+        let report = verify_schedule(&pings[..1], &cache);
+        assert_eq!((report.checked, report.is_ok()), (1, true));
+
+        let mut observed = pings.clone();
+        observed.reverse();
+        observed.push(pings[2]);
+        observed.retain(|&time| time != pings[4]);
+        observed.push(pings[6] + 30);
+        let report = verify_schedule(&observed, &cache);
+        assert_eq!(report.checked, pings.len());
+        assert_eq!(
+            report.mismatches,
+            [
+                ScheduleMismatch::Duplicate { time: pings[2] },
+                ScheduleMismatch::Missing { time: pings[4] },
+                ScheduleMismatch::Unexpected {
+                    time: pings[6] + 30,
+                    nearest: Some(pings[6]),
+                },
+            ]
+        );
+        assert_eq!(report.offset(), Some(30));
+    }
+
+    #[test]
+    fn finds_offsets() {
+        let cache = ScheduleCache::new(UNIV_SCHED, 64, UR_PING);
+        let pings = cache.pings_between(UR_PING + 1, UR_PING + 86400);
+        // a client an hour off
+        let shifted: Vec<u64> = pings.iter().map(|time| time + 3600).collect();
+        let report = verify_schedule(&shifted, &cache);
+        assert!(!report.is_ok());
+        let unexpected = (report.mismatches.iter())
+            .filter(|mismatch| matches!(mismatch, ScheduleMismatch::Unexpected { .. }))
+            .count();
+        assert_eq!(unexpected, shifted.len());
+        assert_eq!(report.offset(), None);
+
+        // a client with the wrong seed
+        let other = ScheduleCache::new(new_ping_interval_data(1234, 2700, true), 64, UR_PING);
+        let report = verify_schedule(&other.pings_between(UR_PING + 1, UR_PING + 86400), &cache);
+        assert!(!report.is_ok());
+        assert_eq!(report.offset(), None);
+        assert_eq!(
+            verify_schedule(&[pings[0] + 5; 2], &cache).offset(),
+            Some(5)
+        );
+    }
+}
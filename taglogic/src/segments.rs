@@ -0,0 +1,394 @@
+//! Logs split into monthly segments, which are only loaded when a query needs them, so a decade
+//! of pings doesn't all have to be in memory (like in the frontend's WASM memory) at once.
+//!
+//! Segments come from a loader given to [`SegmentedLog::load`], which can be anything async, like
+//! reading IndexedDB, a file, or an HTTP endpoint. At most a set number of segments are kept, and
+//! the least recently used ones are dropped to make room, to be loaded again if they're needed.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::future::Future;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+#[cfg(feature = "expr")]
+use crate::bool::Expr;
+use crate::log::{Ping, PingLog, PingSlice};
+
+/// A calendar month in UTC, which segments are split by. Written like `2024-03`, which makes a
+/// good key for storing segments.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Month {
+    pub year: i32,
+    /// From 1 to 12.
+    pub month: u32,
+}
+
+impl Month {
+    /// The month that `time` is in.
+    pub fn containing(time: u64) -> Self {
+        // from Howard Hinnant's `civil_from_days`
+        let days = (time / 86400) as i64 + 719_468;
+        let era = days / 146_097;
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        // counting from March
+        let month = (5 * day_of_year + 2) / 153;
+        let month = if month < 10 { month + 3 } else { month - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Self {
+            // times after the year 2147483647 are all in its last month
+            year: i32::try_from(year).unwrap_or(i32::MAX),
+            month: if year > i64::from(i32::MAX) {
+                12
+            } else {
+                month as u32
+            },
+        }
+    }
+
+    /// The first second of the month, or 0 for months before 1970.
+    pub fn start(self) -> u64 {
+        // from Howard Hinnant's `days_from_civil`
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = i64::from(self.month);
+        let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+        (days.max(0) as u64) * 86400
+    }
+
+    /// The month after this one, or this one if it's the last month an `i32` year can hold.
+    pub fn next(self) -> Self {
+        if self.month == 12 && self.year == i32::MAX {
+            self
+        } else if self.month == 12 {
+            Self {
+                year: self.year + 1,
+                month: 1,
+            }
+        } else {
+            Self {
+                year: self.year,
+                month: self.month + 1,
+            }
+        }
+    }
+
+    /// The months with any time in `start..end`, oldest first.
+    pub fn covering(start: u64, end: u64) -> Vec<Month> {
+        let mut months = Vec::new();
+        if start >= end {
+            return months;
+        }
+        let mut month = Self::containing(start);
+        while month.start() < end {
+            months.push(month);
+            if month.next() == month {
+                break;
+            }
+            month = month.next();
+        }
+        months
+    }
+}
+
+impl fmt::Display for Month {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month)
+    }
+}
+
+/// An error parsing a [`Month`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum MonthError {
+    #[error("months are written like 2024-03")]
+    NoDash,
+    #[error("invalid year")]
+    Year(#[source] ParseIntError),
+    #[error("invalid month")]
+    Month(#[source] Option<ParseIntError>),
+}
+
+impl FromStr for Month {
+    type Err = MonthError;
+
+    /// Parses a month written like `2024-03`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (year, month) = s.split_once('-').ok_or(MonthError::NoDash)?;
+        let year = year.parse().map_err(MonthError::Year)?;
+        let month = month.parse().map_err(|err| MonthError::Month(Some(err)))?;
+        if !(1..=12).contains(&month) {
+            return Err(MonthError::Month(None));
+        }
+        Ok(Self { year, month })
+    }
+}
+
+/// A log split into monthly segments, where only some are loaded at a time.
+///
+/// Queries first [`load`](Self::load) the months they need, and then read them with
+/// [`range`](Self::range) or [`matches`](Self::matches). Loading never drops segments that the
+/// same call needs, so a query over more months than the capacity still works, and the extra
+/// segments are dropped by the next load.
+#[derive(Debug)]
+pub struct SegmentedLog {
+    capacity: usize,
+    segments: BTreeMap<Month, Segment>,
+    /// Counts up with every load, to find the least recently used segment.
+    clock: u64,
+}
+
+#[derive(Debug)]
+struct Segment {
+    log: PingLog,
+    last_used: u64,
+}
+
+impl SegmentedLog {
+    /// A log that keeps up to `capacity` segments loaded.
+    ///
+    /// ## Panics
+    /// Panics if `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be at least 1");
+        Self {
+            capacity,
+            segments: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// The months in `start..end` that aren't loaded, oldest first.
+    pub fn missing(&self, start: u64, end: u64) -> Vec<Month> {
+        Month::covering(start, end)
+            .into_iter()
+            .filter(|month| !self.segments.contains_key(month))
+            .collect()
+    }
+
+    /// Loads every month in `start..end` that isn't loaded yet, by calling `loader` with each
+    /// month, one at a time. Stops at the first error, keeping the months loaded before it.
+    pub async fn load<F, Fut, E>(&mut self, start: u64, end: u64, mut loader: F) -> Result<(), E>
+    where
+        F: FnMut(Month) -> Fut,
+        Fut: Future<Output = Result<Vec<Ping>, E>>,
+    {
+        self.clock += 1;
+        for month in Month::covering(start, end) {
+            match self.segments.get_mut(&month) {
+                Some(segment) => segment.last_used = self.clock,
+                None => {
+                    let pings = loader(month).await?;
+                    self.insert(month, pings);
+                }
+            }
+        }
+        self.evict();
+        Ok(())
+    }
+
+    /// Adds a loaded segment, replacing the month if it's already loaded, for loaders that can't
+    /// be called from Rust (like JS ones). Pings outside of the month are ignored.
+    pub fn insert(&mut self, month: Month, mut pings: Vec<Ping>) {
+        let range = month.start()..month.next().start();
+        pings.retain(|ping| range.contains(&ping.time));
+        self.segments.insert(
+            month,
+            Segment {
+                log: PingLog::from_pings(pings),
+                last_used: self.clock,
+            },
+        );
+        self.evict();
+    }
+
+    /// Drops least recently used segments until there are at most `capacity`, except ones used
+    /// since the last load started.
+    fn evict(&mut self) {
+        while self.segments.len() > self.capacity {
+            let oldest = self
+                .segments
+                .iter()
+                .filter(|(_, segment)| segment.last_used < self.clock)
+                .min_by_key(|(_, segment)| segment.last_used)
+                .map(|(&month, _)| month);
+            match oldest {
+                Some(month) => self.segments.remove(&month),
+                None => return,
+            };
+        }
+    }
+
+    /// Drops a month's segment, like after its pings are edited somewhere else.
+    pub fn unload(&mut self, month: Month) {
+        self.segments.remove(&month);
+    }
+
+    /// The pings in `start..end`, as a slice of each month's segment, or `None` if any of the
+    /// months aren't loaded.
+    pub fn range(&self, start: u64, end: u64) -> Option<Vec<PingSlice<'_>>> {
+        Month::covering(start, end)
+            .into_iter()
+            .map(|month| Some(self.segments.get(&month)?.log.range(start, end)))
+            .collect()
+    }
+
+    /// Whether each ping in `start..end` matches `expr`, oldest to newest, or `None` if any of
+    /// the months aren't loaded.
+    #[cfg(feature = "expr")]
+    pub fn matches(&self, expr: &Expr, start: u64, end: u64) -> Option<Vec<bool>> {
+        let mut matches = Vec::new();
+        for slice in self.range(start, end)? {
+            matches.extend(crate::log::matches_each(&slice, expr));
+        }
+        Some(matches)
+    }
+
+    /// The loaded months, oldest first.
+    pub fn loaded(&self) -> impl Iterator<Item = Month> + '_ {
+        self.segments.keys().copied()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn month(s: &str) -> Month {
+        s.parse().unwrap()
+    }
+
+    /// A ping at noon on the first of each month, and on the 15th, from 2023 to 2024.
+    fn pings(month: Month) -> Vec<Ping> {
+        [0, 14]
+            .iter()
+            .map(|day| {
+                let tag = if month.month.is_multiple_of(2) {
+                    "even"
+                } else {
+                    "odd"
+                };
+                Ping::new(
+                    month.start() + day * 86400 + 43200,
+                    vec![tag.to_string()],
+                    2700,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn months() {
+        assert_eq!(Month::containing(0), month("1970-01"));
+        assert_eq!(month("1970-01").start(), 0);
+        // 2024-03-01T00:00:00Z, after a leap day
+        assert_eq!(month("2024-03").start(), 1709251200);
+        assert_eq!(Month::containing(1709251199), month("2024-02"));
+        assert_eq!(Month::containing(1709251200), month("2024-03"));
+        assert_eq!(month("2023-12").next(), month("2024-01"));
+        assert_eq!(month("2024-01").to_string(), "2024-01");
+        assert_eq!("2024-13".parse::<Month>(), Err(MonthError::Month(None)));
+        assert_eq!("2024".parse::<Month>(), Err(MonthError::NoDash));
+        let err = "20x4-01".parse::<Month>().unwrap_err();
+        assert!(matches!(err, MonthError::Year(_)));
+        assert!(std::error::Error::source(&err).is_some());
+        for time in (0..2_000_000_000).step_by(86400 * 7 + 3601) {
+            let month = Month::containing(time);
+            assert!(
+                month.start() <= time && time < month.next().start(),
+                "{}",
+                time
+            );
+        }
+        assert_eq!(
+            Month::covering(month("2023-11").start(), month("2024-02").start()),
+            vec![month("2023-11"), month("2023-12"), month("2024-01")]
+        );
+        assert!(Month::covering(10, 10).is_empty());
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "expr")]
+    async fn loads_months_as_needed() {
+        let mut log = SegmentedLog::new(3);
+        let calls = std::cell::RefCell::new(Vec::new());
+        let loader = |month: Month| {
+            calls.borrow_mut().push(month);
+            async move { Ok::<_, ()>(pings(month)) }
+        };
+        let (start, end) = (month("2023-11").start(), month("2024-01").next().start());
+        assert_eq!(log.range(start, end), None);
+        assert_eq!(log.missing(start, end).len(), 3);
+
+        log.load(start, end, loader).await.unwrap();
+        let slices = log.range(start, end).unwrap();
+        assert_eq!(slices.iter().map(PingSlice::len).sum::<usize>(), 6);
+        let expr = Expr::parse("odd").unwrap();
+        assert_eq!(
+            log.matches(&expr, start, end),
+            Some(vec![true, true, false, false, true, true])
+        );
+        // loading again doesn't call the loader
+        log.load(start, end, loader).await.unwrap();
+        assert_eq!(calls.borrow().len(), 3);
+
+        // a new month drops the least recently used one
+        log.load(start + 86400 * 40, start + 86400 * 41, loader)
+            .await
+            .unwrap();
+        log.load(month("2024-02").start(), month("2024-03").start(), loader)
+            .await
+            .unwrap();
+        assert_eq!(
+            log.loaded().collect::<Vec<_>>(),
+            vec![month("2023-12"), month("2024-01"), month("2024-02")]
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_months_a_load_needs() {
+        let mut log = SegmentedLog::new(1);
+        let loader = |month| async move { Ok::<_, ()>(pings(month)) };
+        let (start, end) = (month("2023-01").start(), month("2023-06").start());
+        log.load(start, end, loader).await.unwrap();
+        assert_eq!(log.range(start, end).unwrap().len(), 5);
+        log.load(start, start + 1, loader).await.unwrap();
+        assert_eq!(log.loaded().collect::<Vec<_>>(), vec![month("2023-01")]);
+    }
+
+    #[tokio::test]
+    async fn stops_at_errors() {
+        let mut log = SegmentedLog::new(12);
+        let loader = |month: Month| async move {
+            if month.month == 3 {
+                Err("offline")
+            } else {
+                Ok(pings(month))
+            }
+        };
+        let (start, end) = (month("2023-01").start(), month("2023-06").start());
+        assert_eq!(log.load(start, end, loader).await, Err("offline"));
+        assert_eq!(log.missing(start, end).len(), 3);
+
+        // pings outside of a month are ignored
+        log.insert(month("2023-03"), pings(month("2023-04")));
+        assert_eq!(log.range(start, end), None);
+        log.insert(month("2023-04"), pings(month("2023-04")));
+        log.insert(month("2023-05"), pings(month("2023-05")));
+        let slices = log.range(start, end).unwrap();
+        assert_eq!(slices.iter().map(PingSlice::len).sum::<usize>(), 8);
+        log.unload(month("2023-04"));
+        assert_eq!(log.missing(start, end), vec![month("2023-04")]);
+    }
+}
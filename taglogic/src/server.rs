@@ -0,0 +1,259 @@
+//! An HTTP API for self-hosted deployments, where the web UI talks to a local daemon instead of
+//! doing everything in WASM.
+//!
+//! Every request needs an `Authorization: Bearer <token>` header. The endpoints are:
+//!
+//! - `GET /v1/log?start=...&end=...`: the pings in a range, as `{"pings": [...]}`.
+//! - `POST /v1/log`: adds the pings in the body (a JSON array of pings), returning the new total
+//!   as `{"pings": n}`.
+//! - `POST /v1/query`: like `query/v1` in [`commands`](crate::commands), on the daemon's log.
+//! - `POST /v1/stats`: like `stats/v1` in [`commands`](crate::commands), on the daemon's log.
+//!
+//! Bodies are the `args` of the matching command without `pings`, and responses are the
+//! command's `result`. Errors are `{"kind": ..., "message": ...}` objects like the commands', with
+//! the status 400, or 401 with the kind `unauthorized` if the token is missing or wrong.
+
+use std::sync::{Arc, RwLock};
+
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::commands::{self, MetricArg, TimeRange};
+use crate::limits::Limits;
+use crate::log::{Ping, PingLog};
+
+#[derive(Debug, Clone)]
+struct AppState {
+    log: Arc<RwLock<PingLog>>,
+    token: Arc<str>,
+}
+
+/// The API's routes, serving `log` to clients with `token`. The log is shared so the daemon can
+/// save it (or load pings from elsewhere) while serving.
+///
+/// ## Panics
+/// Panics if `token` is empty.
+pub fn router(log: Arc<RwLock<PingLog>>, token: &str) -> Router {
+    assert!(!token.is_empty(), "token must not be empty");
+    let state = AppState {
+        log,
+        token: token.into(),
+    };
+    Router::new()
+        .route("/v1/log", get(get_log).post(add_pings))
+        .route("/v1/query", post(query))
+        .route("/v1/stats", post(stats))
+        .route_layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryBody {
+    expr: String,
+    #[serde(flatten)]
+    range: TimeRange,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StatsBody {
+    expr: String,
+    #[serde(flatten)]
+    range: TimeRange,
+    #[serde(default)]
+    utc_offset_mins: i32,
+    metrics: Vec<MetricArg>,
+}
+
+async fn authorize(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match token {
+        Some(token) if same_token(token, &state.token) => next.run(request).await,
+        _ => (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "kind": "unauthorized", "message": "missing or wrong token" })),
+        )
+            .into_response(),
+    }
+}
+
+/// Compares tokens in the same time no matter how much of them match, so the token can't be
+/// guessed one character at a time.
+fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn get_log(
+    State(state): State<AppState>,
+    range: Result<Query<TimeRange>, QueryRejection>,
+) -> Response {
+    let Query(range) = match range {
+        Ok(range) => range,
+        Err(err) => return invalid_request(err.body_text()),
+    };
+    let log = state.log.read().unwrap_or_else(|err| err.into_inner());
+    respond(Ok(json!({ "pings": log.range(range.start, range.end) })))
+}
+
+async fn add_pings(
+    State(state): State<AppState>,
+    pings: Result<Json<Vec<Ping>>, JsonRejection>,
+) -> Response {
+    let Json(pings) = match pings {
+        Ok(pings) => pings,
+        Err(err) => return invalid_request(err.body_text()),
+    };
+    let mut log = state.log.write().unwrap_or_else(|err| err.into_inner());
+    for ping in pings {
+        log.push(ping);
+    }
+    respond(Ok(json!({ "pings": log.len() })))
+}
+
+async fn query(
+    State(state): State<AppState>,
+    body: Result<Json<QueryBody>, JsonRejection>,
+) -> Response {
+    let Json(body) = match body {
+        Ok(body) => body,
+        Err(err) => return invalid_request(err.body_text()),
+    };
+    let log = state.log.read().unwrap_or_else(|err| err.into_inner());
+    respond(commands::query(
+        &log,
+        &body.expr,
+        &body.range,
+        &Limits::DEFAULT,
+    ))
+}
+
+async fn stats(
+    State(state): State<AppState>,
+    body: Result<Json<StatsBody>, JsonRejection>,
+) -> Response {
+    let Json(body) = match body {
+        Ok(body) => body,
+        Err(err) => return invalid_request(err.body_text()),
+    };
+    let log = state.log.read().unwrap_or_else(|err| err.into_inner());
+    respond(commands::report(
+        &log,
+        &body.expr,
+        &body.range,
+        body.utc_offset_mins,
+        &body.metrics,
+        &Limits::DEFAULT,
+    ))
+}
+
+fn respond(result: Result<Value, Value>) -> Response {
+    match result {
+        Ok(result) => Json(result).into_response(),
+        Err(error) => (StatusCode::BAD_REQUEST, Json(error)).into_response(),
+    }
+}
+
+fn invalid_request(message: String) -> Response {
+    respond(Err(json!({ "kind": "invalidRequest", "message": message })))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "secret";
+
+    fn app() -> Router {
+        let log = PingLog::from_pings(vec![
+            Ping::new(10, vec!["a".into()], 3600),
+            Ping::new(20, vec!["b".into()], 3600),
+        ]);
+        router(Arc::new(RwLock::new(log)), TOKEN)
+    }
+
+    async fn call(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        token: &str,
+        body: Value,
+    ) -> (StatusCode, Value) {
+        let request = axum::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn needs_token() {
+        let app = app();
+        let (status, body) = call(&app, "GET", "/v1/log", "wrong", Value::Null).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["kind"], json!("unauthorized"));
+        assert!(same_token(TOKEN, "secret"));
+        assert!(!same_token(TOKEN, "secrets"));
+    }
+
+    #[tokio::test]
+    async fn reads_and_adds_pings() {
+        let app = app();
+        let (status, body) = call(&app, "GET", "/v1/log?start=15", TOKEN, Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["pings"][0]["time"], json!(20));
+        assert_eq!(body["pings"].as_array().unwrap().len(), 1);
+
+        let pings = json!([{"time": 30, "tags": ["a"], "interval": 3600, "comment": null}]);
+        let (status, body) = call(&app, "POST", "/v1/log", TOKEN, pings).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({"pings": 3}));
+
+        let (status, body) = call(&app, "POST", "/v1/log", TOKEN, json!({"time": 1})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], json!("invalidRequest"));
+    }
+
+    #[tokio::test]
+    async fn queries_and_stats() {
+        let app = app();
+        let (_, body) = call(&app, "POST", "/v1/query", TOKEN, json!({"expr": "a"})).await;
+        assert_eq!(body, json!({"matches": [10]}));
+
+        let (status, body) = call(&app, "POST", "/v1/query", TOKEN, json!({"expr": "a &"})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], json!("parse"));
+
+        let (_, body) = call(
+            &app,
+            "POST",
+            "/v1/stats",
+            TOKEN,
+            json!({"expr": "a | b", "metrics": ["total"]}),
+        )
+        .await;
+        assert_eq!(body["total"]["hours"], json!(2.0));
+    }
+}
@@ -0,0 +1,314 @@
+//! The user's settings, in one place, so the schedule, notifications, stats and exports all agree
+//! on the time zone, the ping gap and the rest instead of each taking them separately.
+
+use chrono::{FixedOffset, NaiveTime, Weekday};
+use std::borrow::Cow;
+
+use crate::bool::{Expr, ParseError};
+use crate::log::{Ping, PingLog};
+use crate::notify::NotificationOptions;
+use crate::stats::CustomCalendar;
+use crate::{tt, PingIntervalData};
+
+/// Settings that are out of range, from [`Settings::validate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum SettingsError {
+    #[error("UTC offset must be less than a day")]
+    UtcOffset,
+    #[error("the average gap between pings can't be zero")]
+    Gap,
+    #[error("days must start within a day")]
+    DayStart,
+    #[error("quiet hours must be within a day")]
+    QuietHours,
+}
+
+/// A user's settings. Missing fields are deserialized as their defaults.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, rename_all = "camelCase")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    /// Minutes ahead of UTC of the user's time zone, for days, weeks and months in stats and
+    /// the local times of pings.
+    pub utc_offset_mins: i32,
+    /// Minutes after local midnight that days start, so night owls' evenings can go past
+    /// midnight.
+    pub day_start_mins: u32,
+    /// The day weeks start on, written like `Mon`.
+    #[cfg_attr(feature = "serde", serde(with = "weekday"))]
+    pub week_start: Weekday,
+    /// The average gap between pings, in seconds.
+    pub avg_interval: u32,
+    /// Minutes after local midnight of the start and end of quiet hours, when pings aren't
+    /// notified. See [`NotificationOptions::quiet_start_mins`].
+    pub quiet_start_mins: u32,
+    pub quiet_end_mins: u32,
+    /// Whether `Work` and `work` are different tags.
+    pub case_sensitive: bool,
+}
+
+impl Default for Settings {
+    /// UTC, weeks starting on Monday, the universal schedule's 45 minute gap, no quiet hours, and
+    /// case-sensitive tags.
+    fn default() -> Self {
+        Self {
+            utc_offset_mins: 0,
+            day_start_mins: 0,
+            week_start: Weekday::Mon,
+            avg_interval: tt::UNIV_SCHED.avg_interval,
+            quiet_start_mins: 0,
+            quiet_end_mins: 0,
+            case_sensitive: true,
+        }
+    }
+}
+
+impl Settings {
+    /// Checks every setting is in range, as when loading saved settings.
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        self.calendar()?;
+        if self.avg_interval == 0 {
+            return Err(SettingsError::Gap);
+        }
+        if self.quiet_start_mins >= 1440 || self.quiet_end_mins >= 1440 {
+            return Err(SettingsError::QuietHours);
+        }
+        Ok(())
+    }
+
+    /// The user's time zone, for stats and exports that take one.
+    pub fn tz(&self) -> Result<FixedOffset, SettingsError> {
+        (self.utc_offset_mins.checked_mul(60))
+            .and_then(FixedOffset::east_opt)
+            .ok_or(SettingsError::UtcOffset)
+    }
+
+    /// The user's days and weeks, for stats and exports that group pings by them.
+    pub fn calendar(&self) -> Result<CustomCalendar<FixedOffset>, SettingsError> {
+        let secs = self
+            .day_start_mins
+            .checked_mul(60)
+            .ok_or(SettingsError::DayStart)?;
+        let day_start = NaiveTime::from_num_seconds_from_midnight_opt(secs, 0)
+            .ok_or(SettingsError::DayStart)?;
+        Ok(CustomCalendar {
+            tz: self.tz()?,
+            day_start,
+            week_start: self.week_start,
+        })
+    }
+
+    /// The universal schedule with the user's gap.
+    pub fn schedule(&self) -> PingIntervalData {
+        PingIntervalData {
+            avg_interval: self.avg_interval,
+            ..tt::UNIV_SCHED
+        }
+    }
+
+    /// Options for [`upcoming_notifications`](crate::notify::upcoming_notifications), looking
+    /// `lookahead_secs` ahead.
+    pub fn notification_options(&self, lookahead_secs: u64) -> NotificationOptions {
+        NotificationOptions {
+            utc_offset_mins: self.utc_offset_mins,
+            quiet_start_mins: self.quiet_start_mins,
+            quiet_end_mins: self.quiet_end_mins,
+            lookahead_secs,
+        }
+    }
+
+    /// A tag as it's compared, which is lowercase if tags aren't case-sensitive.
+    pub fn tag<'a>(&self, tag: &'a str) -> Cow<'a, str> {
+        if self.case_sensitive {
+            Cow::Borrowed(tag)
+        } else {
+            Cow::Owned(tag.to_lowercase())
+        }
+    }
+
+    /// The expression with its tags as they're compared. Only fails if lowercasing makes the
+    /// expression too long.
+    pub fn expr<'a>(&self, expr: &'a Expr) -> Result<Cow<'a, Expr>, ParseError> {
+        if self.case_sensitive {
+            return Ok(Cow::Borrowed(expr));
+        }
+        // operators are written the same in any case, so this only changes the tags
+        Ok(Cow::Owned(Expr::parse(&expr.to_string().to_lowercase())?))
+    }
+
+    /// The log with its tags as they're compared, so stats and exports of it treat tags the
+    /// same way as the user. Tags that are the same once lowercased are merged.
+    pub fn log<'a>(&self, log: &'a PingLog) -> Cow<'a, PingLog> {
+        if self.case_sensitive {
+            return Cow::Borrowed(log);
+        }
+        let pings = log.pings().iter().map(|ping| {
+            let mut tags: Vec<String> = ping.tags.iter().map(str::to_lowercase).collect();
+            let mut seen = Vec::with_capacity(tags.len());
+            tags.retain(|tag| {
+                let new = !seen.contains(tag);
+                seen.push(tag.clone());
+                new
+            });
+            Ping {
+                tags,
+                ..ping.to_ping()
+            }
+        });
+        Cow::Owned(PingLog::from_pings(pings.collect()))
+    }
+}
+
+/// Weekdays as their short English names, like `Mon`.
+#[cfg(feature = "serde")]
+mod weekday {
+    use chrono::Weekday;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(weekday: &Weekday, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(weekday)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Weekday, D::Error> {
+        let name = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        name.parse()
+            .map_err(|_| D::Error::custom("expected a day like `Mon`"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builds_options_for_each_module() {
+        let settings = Settings {
+            utc_offset_mins: -300,
+            avg_interval: 1800,
+            quiet_start_mins: 23 * 60,
+            quiet_end_mins: 7 * 60,
+            ..Settings::default()
+        };
+        assert_eq!(settings.validate(), Ok(()));
+        assert_eq!(settings.tz(), Ok(FixedOffset::west_opt(5 * 3600).unwrap()));
+        let calendar = Settings {
+            day_start_mins: 4 * 60,
+            week_start: Weekday::Sun,
+            ..settings
+        }
+        .calendar()
+        .unwrap();
+        assert_eq!(calendar.tz, settings.tz().unwrap());
+        assert_eq!(
+            calendar.day_start,
+            NaiveTime::from_hms_opt(4, 0, 0).unwrap()
+        );
+        assert_eq!(calendar.week_start, Weekday::Sun);
+        assert_eq!(settings.schedule().avg_interval, 1800);
+        assert_eq!(settings.schedule().seed, tt::UNIV_SCHED.seed);
+        let options = settings.notification_options(3600);
+        assert_eq!(
+            (
+                options.utc_offset_mins,
+                options.quiet_start_mins,
+                options.quiet_end_mins
+            ),
+            (-300, 23 * 60, 7 * 60)
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range_settings() {
+        let check = |settings: Settings| settings.validate();
+        let utc_offset_mins = 24 * 60;
+        assert_eq!(
+            check(Settings {
+                utc_offset_mins,
+                ..Settings::default()
+            }),
+            Err(SettingsError::UtcOffset)
+        );
+        assert_eq!(
+            check(Settings {
+                utc_offset_mins: i32::MIN,
+                ..Settings::default()
+            }),
+            Err(SettingsError::UtcOffset)
+        );
+        assert_eq!(
+            check(Settings {
+                day_start_mins: 1440,
+                ..Settings::default()
+            }),
+            Err(SettingsError::DayStart)
+        );
+        assert_eq!(
+            check(Settings {
+                avg_interval: 0,
+                ..Settings::default()
+            }),
+            Err(SettingsError::Gap)
+        );
+        assert_eq!(
+            check(Settings {
+                quiet_end_mins: 1440,
+                ..Settings::default()
+            }),
+            Err(SettingsError::QuietHours)
+        );
+    }
+
+    #[test]
+    fn folds_case() {
+        let insensitive = Settings {
+            case_sensitive: false,
+            ..Settings::default()
+        };
+        assert_eq!(Settings::default().tag("Work"), "Work");
+        assert_eq!(insensitive.tag("Work"), "work");
+
+        let expr = Expr::parse("Work AND !Meeting").unwrap();
+        assert_eq!(
+            Settings::default().expr(&expr).unwrap().to_string(),
+            "Work & !Meeting"
+        );
+        assert_eq!(
+            insensitive.expr(&expr).unwrap().to_string(),
+            "work & !meeting"
+        );
+
+        let log = PingLog::from_pings(vec![Ping::new(0, vec!["Work".into(), "work".into()], 60)]);
+        let folded = insensitive.log(&log);
+        assert_eq!(folded.pings().first().unwrap().tags.to_vec(), ["work"]);
+        assert!(matches!(Settings::default().log(&log), Cow::Borrowed(_)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes() {
+        let settings = Settings {
+            week_start: Weekday::Sun,
+            case_sensitive: false,
+            ..Settings::default()
+        };
+        let json = serde_json::to_value(settings).unwrap();
+        assert_eq!(json["weekStart"], "Sun");
+        assert_eq!(json["caseSensitive"], false);
+        assert_eq!(serde_json::from_value::<Settings>(json).unwrap(), settings);
+
+        // missing fields are the defaults
+        let partial: Settings = serde_json::from_str(r#"{"weekStart": "saturday"}"#).unwrap();
+        assert_eq!(
+            partial,
+            Settings {
+                week_start: Weekday::Sat,
+                ..Settings::default()
+            }
+        );
+        assert!(serde_json::from_str::<Settings>(r#"{"weekStart": "someday"}"#).is_err());
+    }
+}
@@ -0,0 +1,464 @@
+//! Estimates of how much time was spent on things, computed from pings.
+//!
+//! Every ping repersents its interval worth of time, so the estimated time for an expression is
+//! the sum of the intervals of the pings matching it. Since pings are a Poisson process, the number
+//! of matching pings is Poisson-distributed, which is what the confidence intervals are based on.
+
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveTime, TimeDelta, TimeZone, Weekday};
+use std::convert::TryFrom;
+use std::ops::Range;
+
+use crate::bool::Expr;
+use crate::log::{match_shares, PingLog};
+
+mod aggregates;
+mod anomalies;
+mod authors;
+mod bayes;
+mod bootstrap;
+mod breakdown;
+mod compare;
+mod correlation;
+mod daily;
+mod diversity;
+mod groups;
+mod lifetimes;
+mod pareto;
+mod project;
+mod report;
+mod response;
+mod sample;
+mod sessions;
+mod special;
+mod streaks;
+mod trend;
+
+pub use aggregates::{Aggregates, Counts, AGGREGATES_VERSION};
+pub use anomalies::{anomalies, Anomaly, AnomalyReason, OutlierMethod};
+pub use authors::{estimate_by_author, AuthorEstimates};
+pub use bayes::{bayes_fraction, BetaPrior, FractionPosterior};
+pub use bootstrap::{bootstrap, BootstrapInterval};
+pub use breakdown::{breakdown, Breakdown, Children, Share};
+pub use compare::{compare, Comparison};
+pub use correlation::{correlation, Coefficient, Correlation};
+pub use daily::daily_hours;
+#[cfg(feature = "http")]
+pub(crate) use daily::daily_tallies;
+pub use diversity::{diversity_series, tag_entropy, Diversity};
+pub use groups::{group_by, GroupTime, Groups, Overlap};
+pub use lifetimes::{tag_lifetimes, TagLifetime};
+pub use pareto::{pareto, Pareto};
+pub use project::{project, Projection, ProjectionModel};
+#[cfg(feature = "webhooks")]
+pub(crate) use report::bucket_name;
+pub use report::{report, Metric, ReportSpec, REPORT_VERSION};
+pub use response::{answer_delays, response_rates, AnswerDelays, ResponseRate};
+pub use sample::sample_pings;
+pub use sessions::{sessions, Session};
+pub use streaks::{streaks, Streak, Streaks};
+pub use trend::{time_series, TimeSeries, TimeSeriesPoint};
+
+/// z-score for a two-sided 95% confidence interval.
+const Z_95: f64 = 1.959_963_984_540_054;
+
+/// An estimate of the number of hours spent on something, with a 95% confidence interval.
+#[cfg_attr(feature = "wasm", derive(serde::Serialize, tsify::Tsify))]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TimeEstimate {
+    /// Number of matching pings the estimate is based on.
+    pub pings: u32,
+    pub hours: f64,
+    /// Lower bound of the confidence interval, in hours.
+    pub low: f64,
+    /// Upper bound of the confidence interval, in hours.
+    pub high: f64,
+}
+
+impl TimeEstimate {
+    pub const ZERO: Self = Self {
+        pings: 0,
+        hours: 0.0,
+        low: 0.0,
+        high: 0.0,
+    };
+}
+
+/// Estimated hours spent on `expr` during `range`.
+pub fn estimate(log: &PingLog, expr: &Expr, range: Range<u64>) -> TimeEstimate {
+    let pings = log.range(range.start, range.end);
+    let mut tally = Tally::default();
+    for (ping, share) in pings.iter().zip(match_shares(&pings, expr)) {
+        tally.add_share(ping.interval, share);
+    }
+    tally.estimate()
+}
+
+/// Accumulates pings into a [`TimeEstimate`].
+#[derive(Debug, Copy, Clone, Default)]
+pub(crate) struct Tally {
+    matching: u32,
+    matching_secs: u64,
+    /// Sum of the squares of the matching intervals, used to find the variance. Kept as an
+    /// integer so it doesn't depend on the order pings are added in.
+    matching_sq_secs: u128,
+    total: u32,
+    total_secs: u64,
+}
+
+impl Tally {
+    /// Adds a ping that repersents `interval` seconds, where `share` of its time matched: 1 or 0
+    /// unless it has [weighted tags](crate::log::Ping::weights). It counts as matching if any of
+    /// it did.
+    pub fn add_share(&mut self, interval: u32, share: f64) {
+        self.total += 1;
+        self.total_secs += u64::from(interval);
+        let secs = share_secs(interval, share);
+        if secs > 0 {
+            self.matching += 1;
+            self.matching_secs += secs;
+            self.matching_sq_secs += u128::from(secs) * u128::from(secs);
+        }
+    }
+
+    pub fn merge(&mut self, other: &Tally) {
+        self.matching += other.matching;
+        self.matching_secs += other.matching_secs;
+        self.matching_sq_secs += other.matching_sq_secs;
+        self.total += other.total;
+        self.total_secs += other.total_secs;
+    }
+
+    /// Number of matching pings.
+    pub fn matching(&self) -> u32 {
+        self.matching
+    }
+
+    /// Variance of the estimated hours. Each matching ping contributes the square of the time it
+    /// repersents, like a compound Poisson process.
+    pub fn variance(&self) -> f64 {
+        self.matching_sq_secs as f64 / (3600.0 * 3600.0)
+    }
+
+    pub fn estimate(&self) -> TimeEstimate {
+        // average interval of the pings that would have matched, used to turn bounds on the ping
+        // count into bounds on hours. if nothing matched, all of the pings are our best guess.
+        let avg_interval = if self.matching > 0 {
+            self.matching_secs as f64 / self.matching as f64
+        } else if self.total > 0 {
+            self.total_secs as f64 / self.total as f64
+        } else {
+            return TimeEstimate::ZERO;
+        };
+        // score interval for a Poisson count, which unlike the normal approximation still gives
+        // a useful upper bound when there are few (or zero) matching pings
+        let n = self.matching as f64;
+        let center = n + Z_95 * Z_95 / 2.0;
+        let spread = Z_95 * (n + Z_95 * Z_95 / 4.0).sqrt();
+        TimeEstimate {
+            pings: self.matching,
+            hours: self.matching_secs as f64 / 3600.0,
+            low: (center - spread).max(0.0) * avg_interval / 3600.0,
+            high: (center + spread) * avg_interval / 3600.0,
+        }
+    }
+}
+
+/// The seconds of a ping repersenting `interval` seconds that `share` of its time is, rounded.
+pub(crate) fn share_secs(interval: u32, share: f64) -> u64 {
+    (f64::from(interval) * share.clamp(0.0, 1.0)).round() as u64
+}
+
+/// Size of the buckets time is grouped into.
+#[cfg_attr(
+    feature = "wasm",
+    derive(serde::Serialize, serde::Deserialize, tsify::Tsify),
+    serde(rename_all = "lowercase")
+)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bucket {
+    Day,
+    /// Weeks start on the calendar's [`week_start`](Calendar::week_start), which is Monday
+    /// unless it's a [`CustomCalendar`].
+    Week,
+    Month,
+}
+
+impl Bucket {
+    /// The first day of the bucket containing a date, with weeks starting on Monday.
+    pub fn start_of(self, date: NaiveDate) -> NaiveDate {
+        self.start_with(date, Weekday::Mon)
+    }
+
+    /// The first day of the bucket containing a date, with weeks starting on `week_start`.
+    pub fn start_with(self, date: NaiveDate, week_start: Weekday) -> NaiveDate {
+        let days_in = match self {
+            Self::Day => 0,
+            Self::Week => date.weekday().days_since(week_start),
+            Self::Month => date.day0(),
+        };
+        // only the first week chrono can repersent starts before its first day
+        date.checked_sub_days(Days::new(u64::from(days_in)))
+            .unwrap_or(NaiveDate::MIN)
+    }
+
+    /// The first day of the bucket after the one starting at `start`, or None if that's after the
+    /// last day chrono can repersent.
+    pub fn next(self, start: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Self::Day => start.checked_add_days(Days::new(1)),
+            Self::Week => start.checked_add_days(Days::new(7)),
+            Self::Month => start.checked_add_months(Months::new(1)),
+        }
+    }
+}
+
+/// How time is split into days and weeks, for everything that groups pings by them. Every
+/// [`TimeZone`] is one, with days starting at local midnight and weeks starting on Monday, and
+/// [`CustomCalendar`] changes both.
+pub trait Calendar {
+    /// The day a timestamp is counted in. Returns None if the timestamp can't be repersented.
+    fn local_date(&self, time: u64) -> Option<NaiveDate>;
+
+    /// Timestamp of the start of a day.
+    fn day_start(&self, date: NaiveDate) -> Option<u64>;
+
+    /// The day weeks start on.
+    fn week_start(&self) -> Weekday {
+        Weekday::Mon
+    }
+}
+
+impl<Tz: TimeZone> Calendar for Tz {
+    fn local_date(&self, time: u64) -> Option<NaiveDate> {
+        let secs = i64::try_from(time).ok()?;
+        Some(self.timestamp_opt(secs, 0).single()?.date_naive())
+    }
+
+    fn day_start(&self, date: NaiveDate) -> Option<u64> {
+        local_timestamp(date, NaiveTime::MIN, self)
+    }
+}
+
+/// A time zone with days that start at some other time than midnight, like 4 a.m. for night
+/// owls whose evenings go past midnight, and weeks that can start on any day.
+///
+/// ```
+/// use chrono::{NaiveTime, Utc, Weekday};
+/// use taglogic::stats::{Calendar, CustomCalendar};
+///
+/// let calendar = CustomCalendar {
+///     day_start: NaiveTime::from_hms_opt(4, 0, 0).unwrap(),
+///     week_start: Weekday::Sun,
+///     ..CustomCalendar::new(Utc)
+/// };
+/// // 2 a.m. on January 2nd, 1970 is still part of January 1st
+/// let date = calendar.local_date(86400 + 2 * 3600).unwrap();
+/// assert_eq!(date.to_string(), "1970-01-01");
+/// assert_eq!(calendar.day_start(date), Some(4 * 3600));
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CustomCalendar<Tz> {
+    pub tz: Tz,
+    /// The local time days start at. Times before it count as the day before.
+    pub day_start: NaiveTime,
+    pub week_start: Weekday,
+}
+
+impl<Tz: TimeZone> CustomCalendar<Tz> {
+    /// Days starting at midnight and weeks starting on Monday, like `tz` on its own.
+    pub fn new(tz: Tz) -> Self {
+        Self {
+            tz,
+            day_start: NaiveTime::MIN,
+            week_start: Weekday::Mon,
+        }
+    }
+}
+
+impl<Tz: TimeZone> Calendar for CustomCalendar<Tz> {
+    fn local_date(&self, time: u64) -> Option<NaiveDate> {
+        let secs = i64::try_from(time).ok()?;
+        let local = self.tz.timestamp_opt(secs, 0).single()?.naive_local();
+        let since_midnight = self.day_start.signed_duration_since(NaiveTime::MIN);
+        Some(local.checked_sub_signed(since_midnight)?.date())
+    }
+
+    fn day_start(&self, date: NaiveDate) -> Option<u64> {
+        local_timestamp(date, self.day_start, &self.tz)
+    }
+
+    fn week_start(&self) -> Weekday {
+        self.week_start
+    }
+}
+
+/// The local date a timestamp is counted in. Returns None if the timestamp can't be repersented.
+pub(crate) fn local_date<C: Calendar + ?Sized>(time: u64, calendar: &C) -> Option<NaiveDate> {
+    calendar.local_date(time)
+}
+
+/// The first day, start time, and end time of the bucket containing `time`. The end time is the
+/// start of the next bucket.
+pub(crate) fn bucket_bounds<C: Calendar + ?Sized>(
+    bucket: Bucket,
+    time: u64,
+    calendar: &C,
+) -> Option<(NaiveDate, u64, u64)> {
+    let start_date = bucket.start_with(calendar.local_date(time)?, calendar.week_start());
+    let start = calendar.day_start(start_date)?;
+    let end = calendar.day_start(bucket.next(start_date)?)?;
+    Some((start_date, start, end))
+}
+
+/// Timestamp of the start of a day.
+pub(crate) fn day_start<C: Calendar + ?Sized>(date: NaiveDate, calendar: &C) -> Option<u64> {
+    calendar.day_start(date)
+}
+
+/// Timestamp of a local date and time. If the time was skipped by a DST transition, the first
+/// moment after it that did exist is used instead.
+fn local_timestamp<Tz: TimeZone>(date: NaiveDate, time: NaiveTime, tz: &Tz) -> Option<u64> {
+    let start = date.and_time(time);
+    // transitions are never more than a few hours long, and happen on whole minutes
+    let datetime = (0..=4 * 60)
+        .filter_map(|mins| start.checked_add_signed(TimeDelta::minutes(mins)))
+        .find_map(|time| tz.from_local_datetime(&time).earliest())?;
+    u64::try_from(datetime.timestamp()).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+
+    #[test]
+    fn estimate_of_nothing_is_zero() {
+        assert_eq!(Tally::default().estimate(), TimeEstimate::ZERO);
+    }
+
+    #[test]
+    fn estimate_bounds_contain_hours() {
+        let mut tally = Tally::default();
+        for i in 0..20 {
+            let ping = Ping::new(i, vec![], 3600);
+            tally.add_share(ping.interval, if i % 2 == 0 { 1.0 } else { 0.0 });
+        }
+        let estimate = tally.estimate();
+        assert_eq!(estimate.pings, 10);
+        assert_eq!(estimate.hours, 10.0);
+        assert!(estimate.low > 4.0 && estimate.low < 10.0);
+        assert!(estimate.high > 10.0 && estimate.high < 19.0);
+    }
+
+    #[test]
+    fn no_matches_still_has_upper_bound() {
+        let mut tally = Tally::default();
+        tally.add_share(1800, 0.0);
+        let estimate = tally.estimate();
+        assert_eq!(estimate.hours, 0.0);
+        assert_eq!(estimate.low, 0.0);
+        assert!(estimate.high > 0.0);
+    }
+
+    #[test]
+    fn estimate_in_range() {
+        let log = PingLog::from_pings(vec![
+            Ping::new(0, vec!["a".to_string()], 3600),
+            Ping::new(10, vec!["a".to_string()], 1800),
+            Ping::new(20, vec!["b".to_string()], 3600),
+        ]);
+        let expr = Expr::from_string("a").unwrap();
+        assert_eq!(estimate(&log, &expr, 0..100).hours, 1.5);
+        assert_eq!(estimate(&log, &expr, 5..100).hours, 0.5);
+    }
+
+    #[test]
+    fn weights_split_time() {
+        let log = PingLog::from_pings(vec![
+            Ping::from_entry(0, "work:0.75 email:0.25", 3600),
+            Ping::from_entry(10, "work", 3600),
+        ]);
+        let hours = |expr| estimate(&log, &Expr::from_string(expr).unwrap(), 0..100).hours;
+        assert_eq!(hours("work"), 1.75);
+        assert_eq!(hours("email"), 0.25);
+        assert_eq!(hours("work | email"), 2.0);
+        assert_eq!(hours("work & email"), 0.0);
+    }
+
+    #[test]
+    fn midnight_round_trips() {
+        let tz = chrono::FixedOffset::west_opt(5 * 3600).unwrap();
+        let date = NaiveDate::from_ymd_opt(2021, 3, 14).unwrap();
+        let midnight = day_start(date, &tz).unwrap();
+        assert_eq!(midnight, 1615698000);
+        assert_eq!(local_date(midnight, &tz), Some(date));
+        assert_eq!(local_date(midnight - 1, &tz), date.pred_opt());
+    }
+
+    #[test]
+    fn bucket_starts() {
+        let date = NaiveDate::from_ymd_opt(2021, 12, 30).unwrap(); // a Thursday
+        assert_eq!(Bucket::Day.start_of(date), date);
+        assert_eq!(
+            Bucket::Week.start_of(date),
+            NaiveDate::from_ymd_opt(2021, 12, 27).unwrap()
+        );
+        assert_eq!(
+            Bucket::Month.start_of(date),
+            NaiveDate::from_ymd_opt(2021, 12, 1).unwrap()
+        );
+        assert_eq!(
+            Bucket::Month.next(Bucket::Month.start_of(date)),
+            NaiveDate::from_ymd_opt(2022, 1, 1)
+        );
+        assert_eq!(Bucket::Week.start_of(NaiveDate::MIN), NaiveDate::MIN);
+        assert_eq!(Bucket::Day.next(NaiveDate::MAX), None);
+        assert_eq!(
+            Bucket::Week.start_with(date, Weekday::Sun),
+            NaiveDate::from_ymd_opt(2021, 12, 26).unwrap()
+        );
+        assert_eq!(Bucket::Week.start_with(date, Weekday::Thu), date);
+    }
+
+    #[test]
+    fn custom_day_starts_over_dst() {
+        use chrono_tz::America::New_York;
+
+        let date = |month, day| NaiveDate::from_ymd_opt(2021, month, day).unwrap();
+        let calendar = |hour, min| CustomCalendar {
+            day_start: NaiveTime::from_hms_opt(hour, min, 0).unwrap(),
+            ..CustomCalendar::new(New_York)
+        };
+        let night_owl = calendar(4, 0);
+        // clocks go forward at 2 a.m. on March 14th, so the day before is an hour short
+        assert_eq!(night_owl.day_start(date(3, 14)), Some(1615708800));
+        let (day, start, end) = bucket_bounds(Bucket::Day, 1615708800 - 1, &night_owl).unwrap();
+        assert_eq!((day, end - start), (date(3, 13), 23 * 3600));
+        // 3 a.m. is still the day before
+        assert_eq!(night_owl.local_date(1615791600), Some(date(3, 14)));
+        assert_eq!(night_owl.local_date(1615791600 + 3600), Some(date(3, 15)));
+        // and back at 2 a.m. on November 7th, so that day is an hour long
+        assert_eq!(night_owl.day_start(date(11, 7)), Some(1636275600));
+        let (day, start, end) = bucket_bounds(Bucket::Day, 1636275600, &night_owl).unwrap();
+        assert_eq!((day, end - start), (date(11, 7), 24 * 3600));
+        let (_, start, end) = bucket_bounds(Bucket::Day, 1636275600 - 1, &night_owl).unwrap();
+        assert_eq!(end - start, 25 * 3600);
+
+        // a day start that was skipped is moved to when the clocks went forward
+        assert_eq!(calendar(2, 30).day_start(date(3, 14)), Some(1615705200));
+        assert_eq!(
+            calendar(2, 30).local_date(1615705200 - 1),
+            Some(date(3, 13))
+        );
+        // and the same as the time zone at midnight
+        let midnight = calendar(0, 0);
+        for time in [1615705200, 1636275600, 1636243200 - 1] {
+            assert_eq!(midnight.local_date(time), New_York.local_date(time));
+        }
+        assert_eq!(
+            midnight.day_start(date(3, 14)),
+            New_York.day_start(date(3, 14))
+        );
+    }
+}
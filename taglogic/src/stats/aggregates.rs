@@ -0,0 +1,516 @@
+use chrono::{FixedOffset, NaiveDate};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::ops::Range;
+
+use super::{local_date, share_secs, Tally, TimeEstimate};
+use crate::bool::Expr;
+use crate::log::{match_shares, Ping, PingLog};
+
+/// Version of the JSON produced by [`Aggregates::to_json`]. Bump this when changing its shape in a
+/// way that isn't backwards compatible.
+pub const AGGREGATES_VERSION: u32 = 1;
+
+/// Pings counted on one day.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub pings: u32,
+    /// Sum of the intervals of the pings, in seconds.
+    pub secs: u64,
+    /// Sum of the squares of the intervals, used to find the variance.
+    pub sq_secs: u64,
+}
+
+impl Counts {
+    /// Counts a ping with `secs` seconds of counted time.
+    fn add(&mut self, secs: u64) {
+        self.pings += 1;
+        self.secs += secs;
+        self.sq_secs += secs.pow(2);
+    }
+
+    fn remove(&mut self, secs: u64) {
+        self.pings = self.pings.saturating_sub(1);
+        self.secs = self.secs.saturating_sub(secs);
+        self.sq_secs = self.sq_secs.saturating_sub(secs.pow(2));
+    }
+
+    fn merge(&mut self, other: &Counts) {
+        self.pings += other.pings;
+        self.secs += other.secs;
+        self.sq_secs += other.sq_secs;
+    }
+}
+
+type Days = BTreeMap<NaiveDate, Counts>;
+
+/// Daily counts of all pings, of each tag, and of the pings matching some expressions, kept up to
+/// date as pings are added, removed or edited instead of being recomputed from the whole log.
+/// Each update only touches the days and tags of the pings involved. Pings split by weighted tags
+/// count for the part of their time that has each tag or matches each expression, like in
+/// [`estimate`](super::estimate).
+///
+/// Days start at midnight with a fixed UTC offset, so aggregates can be saved with
+/// [`to_json`](Self::to_json) and loaded when the dashboard opens.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregates {
+    tz: FixedOffset,
+    total: Days,
+    tags: HashMap<String, Days>,
+    exprs: Vec<(Expr, Days)>,
+}
+
+impl Aggregates {
+    /// Empty aggregates with days in the time zone `tz`, counting matches of each of `exprs`.
+    pub fn new(tz: FixedOffset, exprs: Vec<Expr>) -> Self {
+        Self {
+            tz,
+            total: Days::new(),
+            tags: HashMap::new(),
+            exprs: exprs.into_iter().map(|expr| (expr, Days::new())).collect(),
+        }
+    }
+
+    /// Aggregates of every ping in `log`.
+    pub fn from_log(log: &PingLog, tz: FixedOffset, exprs: Vec<Expr>) -> Self {
+        let mut aggregates = Self::new(tz, exprs);
+        let pings = log.pings();
+        let shares: Vec<Vec<f64>> = aggregates
+            .exprs
+            .iter()
+            .map(|(expr, _)| match_shares(&pings, expr))
+            .collect();
+        let mut matched = vec![0.0; shares.len()];
+        for (i, ping) in pings.iter().enumerate() {
+            for (matched, shares) in matched.iter_mut().zip(&shares) {
+                *matched = shares[i];
+            }
+            let tags = tag_shares(&ping.parts());
+            aggregates.update(ping.time, &tags, ping.interval, &matched, true);
+        }
+        aggregates
+    }
+
+    /// Counts a ping that was added to the log.
+    pub fn add(&mut self, ping: &Ping) {
+        self.update_ping(ping, true);
+    }
+
+    /// Stops counting a ping that was removed from the log. Removing a ping that wasn't added gives
+    /// meaningless counts.
+    pub fn remove(&mut self, ping: &Ping) {
+        self.update_ping(ping, false);
+    }
+
+    /// Counts the edit of ping `old` into `new`.
+    pub fn replace(&mut self, old: &Ping, new: &Ping) {
+        self.remove(old);
+        self.add(new);
+    }
+
+    fn update_ping(&mut self, ping: &Ping, add: bool) {
+        let tags = tag_shares(&ping.parts());
+        let matched: Vec<f64> = self
+            .exprs
+            .iter()
+            .map(|(expr, _)| ping.share(expr))
+            .collect();
+        self.update(ping.time, &tags, ping.interval, &matched, add);
+    }
+
+    /// Counts (or stops counting) a ping with each of `tags` and `matched` giving the share of its
+    /// time that has the tag or matches the expression.
+    fn update(
+        &mut self,
+        time: u64,
+        tags: &[(&str, f64)],
+        interval: u32,
+        matched: &[f64],
+        add: bool,
+    ) {
+        let date = match local_date(time, &self.tz) {
+            Some(date) => date,
+            None => return,
+        };
+        let count = |days: &mut Days, share: f64| {
+            // like a tally, a ping only counts if some of its time does
+            let secs = share_secs(interval, share);
+            if secs == 0 {
+                return;
+            }
+            if add {
+                days.entry(date).or_default().add(secs);
+            } else if let Some(counts) = days.get_mut(&date) {
+                counts.remove(secs);
+                if counts.pings == 0 {
+                    days.remove(&date);
+                }
+            }
+        };
+        count(&mut self.total, 1.0);
+        for &(tag, share) in tags {
+            if add {
+                count(self.tags.entry(tag.to_string()).or_default(), share);
+            } else if let Some(days) = self.tags.get_mut(tag) {
+                count(days, share);
+                if days.is_empty() {
+                    self.tags.remove(tag);
+                }
+            }
+        }
+        for ((_, days), &share) in self.exprs.iter_mut().zip(matched) {
+            count(days, share);
+        }
+    }
+
+    /// Time zone the days are in.
+    pub fn tz(&self) -> FixedOffset {
+        self.tz
+    }
+
+    /// The expressions whose matches are counted, in the order they were given.
+    pub fn exprs(&self) -> impl Iterator<Item = &Expr> {
+        self.exprs.iter().map(|(expr, _)| expr)
+    }
+
+    /// Counts of all pings on `date`.
+    pub fn total(&self, date: NaiveDate) -> Counts {
+        self.total.get(&date).copied().unwrap_or_default()
+    }
+
+    /// Counts of the pings tagged `tag` on `date`.
+    pub fn tag(&self, tag: &str, date: NaiveDate) -> Counts {
+        self.tags
+            .get(tag)
+            .and_then(|days| days.get(&date))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Every tag that's on at least one counted ping, in no particular order.
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.tags.keys().map(String::as_str)
+    }
+
+    /// Counts of the pings matching the `index`th expression on `date`.
+    ///
+    /// ## Panics
+    /// Panics if there are `index` or fewer expressions.
+    pub fn matches(&self, index: usize, date: NaiveDate) -> Counts {
+        self.exprs[index].1.get(&date).copied().unwrap_or_default()
+    }
+
+    /// Estimated hours spent on the `index`th expression during `dates`, like
+    /// [`estimate`](super::estimate).
+    ///
+    /// ## Panics
+    /// Panics if there are `index` or fewer expressions.
+    pub fn estimate(&self, index: usize, dates: Range<NaiveDate>) -> TimeEstimate {
+        self.estimate_days(&self.exprs[index].1, dates)
+    }
+
+    /// Estimated hours spent on pings tagged `tag` during `dates`.
+    pub fn tag_estimate(&self, tag: &str, dates: Range<NaiveDate>) -> TimeEstimate {
+        match self.tags.get(tag) {
+            Some(days) => self.estimate_days(days, dates),
+            None => TimeEstimate::ZERO,
+        }
+    }
+
+    fn estimate_days(&self, days: &Days, dates: Range<NaiveDate>) -> TimeEstimate {
+        if dates.start >= dates.end {
+            return TimeEstimate::ZERO;
+        }
+        let sum = |days: &Days| {
+            let mut sum = Counts::default();
+            for counts in days.range(dates.clone()).map(|(_, counts)| counts) {
+                sum.merge(counts);
+            }
+            sum
+        };
+        let (matching, total) = (sum(days), sum(&self.total));
+        Tally {
+            matching: matching.pings,
+            matching_secs: matching.secs,
+            matching_sq_secs: u128::from(matching.sq_secs),
+            total: total.pings,
+            total_secs: total.secs,
+        }
+        .estimate()
+    }
+
+    /// The aggregates as JSON, to be read back with [`from_json`](Self::from_json). Days are keyed
+    /// by date, with counts as `[pings, secs, sq_secs]`, and expressions are stored in their
+    /// canonical form.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "version": AGGREGATES_VERSION,
+            "utc_offset": self.tz.local_minus_utc(),
+            "total": days_json(&self.total),
+            "tags": self
+                .tags
+                .iter()
+                .map(|(tag, days)| (tag.clone(), days_json(days)))
+                .collect::<Map<_, _>>(),
+            "exprs": self
+                .exprs
+                .iter()
+                .map(|(expr, days)| json!({ "expr": expr.to_string(), "days": days_json(days) }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Reads aggregates written by [`to_json`](Self::to_json). Returns `None` if `value` isn't
+    /// aggregates of this [version](AGGREGATES_VERSION), so run older ones through
+    /// [`format::load`](crate::format::load) first.
+    pub fn from_json(value: &Value) -> Option<Self> {
+        if value["version"].as_u64()? != u64::from(AGGREGATES_VERSION) {
+            return None;
+        }
+        let offset = i32::try_from(value["utc_offset"].as_i64()?).ok()?;
+        let tags = value["tags"]
+            .as_object()?
+            .iter()
+            .map(|(tag, days)| Some((tag.clone(), days_from_json(days)?)))
+            .collect::<Option<_>>()?;
+        let exprs = value["exprs"]
+            .as_array()?
+            .iter()
+            .map(|entry| {
+                let expr = Expr::parse(entry["expr"].as_str()?).ok()?;
+                Some((expr, days_from_json(&entry["days"])?))
+            })
+            .collect::<Option<_>>()?;
+        Some(Self {
+            tz: FixedOffset::east_opt(offset)?,
+            total: days_from_json(&value["total"])?,
+            tags,
+            exprs,
+        })
+    }
+}
+
+/// Each tag of a ping once, with the fraction of its time that has the tag, from its parts.
+fn tag_shares<'t>(parts: &[(Vec<&'t str>, f64)]) -> Vec<(&'t str, f64)> {
+    let mut shares: Vec<(&str, f64)> = Vec::new();
+    for (tags, fraction) in parts {
+        for (i, &tag) in tags.iter().enumerate() {
+            if tags[..i].contains(&tag) {
+                continue;
+            }
+            match shares.iter_mut().find(|(other, _)| *other == tag) {
+                Some((_, share)) => *share += fraction,
+                None => shares.push((tag, *fraction)),
+            }
+        }
+    }
+    shares
+}
+
+fn days_json(days: &Days) -> Value {
+    days.iter()
+        .map(|(date, counts)| {
+            (
+                date.to_string(),
+                json!([counts.pings, counts.secs, counts.sq_secs]),
+            )
+        })
+        .collect::<Map<_, _>>()
+        .into()
+}
+
+fn days_from_json(value: &Value) -> Option<Days> {
+    value
+        .as_object()?
+        .iter()
+        .map(|(date, counts)| {
+            let counts = match counts.as_array()?.as_slice() {
+                [pings, secs, sq_secs] => Counts {
+                    pings: u32::try_from(pings.as_u64()?).ok()?,
+                    secs: secs.as_u64()?,
+                    sq_secs: sq_secs.as_u64()?,
+                },
+                _ => return None,
+            };
+            Some((date.parse().ok()?, counts))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stats::estimate;
+
+    fn ping(time: u64, tags: &str) -> Ping {
+        Ping::new(
+            time,
+            tags.split_whitespace().map(String::from).collect(),
+            2700,
+        )
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn exprs() -> Vec<Expr> {
+        vec![Expr::parse("a & !b").unwrap(), Expr::parse("b").unwrap()]
+    }
+
+    // 2024-01-01T00:00:00Z
+    const DAY_1: u64 = 1704067200;
+
+    fn log() -> PingLog {
+        PingLog::from_pings(vec![
+            ping(DAY_1 + 100, "a"),
+            ping(DAY_1 + 200, "a b b"),
+            ping(DAY_1 + 86400 + 100, "b"),
+            ping(DAY_1 + 86400 + 200, ""),
+        ])
+    }
+
+    fn utc() -> FixedOffset {
+        FixedOffset::east_opt(0).unwrap()
+    }
+
+    #[test]
+    fn counts_log() {
+        let aggregates = Aggregates::from_log(&log(), utc(), exprs());
+        assert_eq!(aggregates.total(date(1)).pings, 2);
+        assert_eq!(aggregates.total(date(2)).secs, 5400);
+        assert_eq!(aggregates.tag("b", date(1)).pings, 1);
+        assert_eq!(aggregates.tag("a", date(2)), Counts::default());
+        assert_eq!(aggregates.matches(0, date(1)).pings, 1);
+        assert_eq!(aggregates.matches(1, date(2)).pings, 1);
+
+        let mut tags: Vec<_> = aggregates.tags().collect();
+        tags.sort_unstable();
+        assert_eq!(tags, vec!["a", "b"]);
+        // the offset moves the second day's pings into the first
+        let west = FixedOffset::west_opt(3600).unwrap();
+        let aggregates = Aggregates::from_log(&log(), west, exprs());
+        assert_eq!(aggregates.total(date(1)).pings, 2);
+        assert_eq!(
+            aggregates
+                .total(NaiveDate::from_ymd_opt(2023, 12, 31).unwrap())
+                .pings,
+            2
+        );
+    }
+
+    #[test]
+    fn updates_match_recomputing() {
+        let mut log = log();
+        let mut aggregates = Aggregates::from_log(&log, utc(), exprs());
+        let new = ping(DAY_1 + 300, "a c");
+        log.push(new.clone());
+        aggregates.add(&new);
+        assert_eq!(aggregates, Aggregates::from_log(&log, utc(), exprs()));
+
+        let old = log.get(1).unwrap().to_ping();
+        let edited = ping(old.time, "c");
+        let mut pings = log.pings().to_vec();
+        pings[1] = edited.clone();
+        aggregates.replace(&old, &edited);
+        assert_eq!(
+            aggregates,
+            Aggregates::from_log(&PingLog::from_pings(pings.clone()), utc(), exprs())
+        );
+
+        let removed = pings.remove(3);
+        aggregates.remove(&removed);
+        assert_eq!(
+            aggregates,
+            Aggregates::from_log(&PingLog::from_pings(pings), utc(), exprs())
+        );
+    }
+
+    #[test]
+    fn updates_match_authors() {
+        let exprs = || vec![Expr::parse("@alice & a").unwrap()];
+        let mut log = log();
+        let mut aggregates = Aggregates::from_log(&log, utc(), exprs());
+        let mut new = ping(DAY_1 + 300, "a");
+        new.author = Some("alice".to_string());
+        log.push(new.clone());
+        aggregates.add(&new);
+        assert_eq!(aggregates.matches(0, date(1)).pings, 1);
+        assert_eq!(aggregates, Aggregates::from_log(&log, utc(), exprs()));
+    }
+
+    #[test]
+    fn updates_match_comment_searches() {
+        let exprs = || vec![Expr::parse(r#"a & comment:"late""#).unwrap()];
+        let mut log = log();
+        let mut aggregates = Aggregates::from_log(&log, utc(), exprs());
+        let mut new = ping(DAY_1 + 300, "a");
+        new.comment = Some("Late again".to_string());
+        log.push(new.clone());
+        aggregates.add(&new);
+        assert_eq!(aggregates.matches(0, date(1)).pings, 1);
+        assert_eq!(aggregates, Aggregates::from_log(&log, utc(), exprs()));
+    }
+
+    #[test]
+    fn estimates_like_log() {
+        let log = log();
+        let aggregates = Aggregates::from_log(&log, utc(), exprs());
+        for (index, expr) in exprs().iter().enumerate() {
+            assert_eq!(
+                aggregates.estimate(index, date(1)..date(3)),
+                estimate(&log, expr, DAY_1..DAY_1 + 2 * 86400)
+            );
+        }
+        assert_eq!(
+            aggregates.tag_estimate("a", date(1)..date(2)),
+            estimate(&log, &Expr::parse("a").unwrap(), DAY_1..DAY_1 + 86400)
+        );
+        assert_eq!(
+            aggregates.tag_estimate("z", date(1)..date(2)),
+            TimeEstimate::ZERO
+        );
+    }
+
+    #[test]
+    fn round_trips_json() {
+        let aggregates =
+            Aggregates::from_log(&log(), FixedOffset::east_opt(-3600).unwrap(), exprs());
+        let value = aggregates.to_json();
+        assert_eq!(value["version"], AGGREGATES_VERSION);
+        assert_eq!(
+            value["total"]["2024-01-01"],
+            json!([2, 5400, 2 * 2700 * 2700])
+        );
+        assert_eq!(Aggregates::from_json(&value), Some(aggregates));
+
+        let mut value = value;
+        value["version"] = json!(AGGREGATES_VERSION + 1);
+        assert_eq!(Aggregates::from_json(&value), None);
+        assert_eq!(Aggregates::from_json(&json!({})), None);
+    }
+
+    #[test]
+    fn weighted_tags_agree_with_estimate() {
+        let exprs = || vec![Expr::parse("a").unwrap(), Expr::parse("a | c").unwrap()];
+        let weighted = Ping::from_entry(DAY_1 + 300, "a:0.25 c:0.5 d", 2700);
+        let mut log = log();
+        let mut aggregates = Aggregates::from_log(&log, utc(), exprs());
+        log.push(weighted.clone());
+        aggregates.add(&weighted);
+        assert_eq!(aggregates, Aggregates::from_log(&log, utc(), exprs()));
+        for (index, expr) in exprs().iter().enumerate() {
+            assert_eq!(
+                aggregates.estimate(index, date(1)..date(2)),
+                estimate(&log, expr, DAY_1..DAY_1 + 86400)
+            );
+        }
+        for tag in ["a", "c", "d"] {
+            assert_eq!(
+                aggregates.tag_estimate(tag, date(1)..date(2)),
+                estimate(&log, &Expr::parse(tag).unwrap(), DAY_1..DAY_1 + 86400)
+            );
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use chrono::{Datelike, NaiveDate};
+use std::ops::RangeInclusive;
+
+use super::daily::daily_tallies;
+use super::Calendar;
+use crate::bool::Expr;
+use crate::log::PingLog;
+
+/// A weekday counts as usually active if at least this fraction of those days had matching pings.
+const ACTIVE_WEEKDAY_FRACTION: f64 = 0.75;
+
+/// How to decide if a day's ping count is unusual.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OutlierMethod {
+    /// More than this many standard deviations from the mean.
+    ZScore(f64),
+    /// More than this many interquartile ranges below the first quartile or above the third
+    /// quartile. 1.5 is the usual choice.
+    Iqr(f64),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AnomalyReason {
+    UnusuallyHigh,
+    UnusuallyLow,
+    /// There were no matching pings on a weekday that usually has some.
+    ZeroOnActiveWeekday,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Anomaly {
+    pub date: NaiveDate,
+    /// Number of matching pings on the day.
+    pub pings: u32,
+    /// The typical number of matching pings per day: the mean for [`OutlierMethod::ZScore`], and
+    /// the median for [`OutlierMethod::Iqr`].
+    pub baseline: f64,
+    pub reason: AnomalyReason,
+}
+
+/// Finds local days in `range` where the number of pings matching `expr` is unusual compared to
+/// the rest of the range, in the time zone `tz`.
+pub fn anomalies<Tz: Calendar>(
+    log: &PingLog,
+    expr: &Expr,
+    range: RangeInclusive<NaiveDate>,
+    tz: &Tz,
+    method: OutlierMethod,
+) -> Vec<Anomaly> {
+    let days: Vec<(NaiveDate, u32)> = daily_tallies(log, expr, range, tz)
+        .into_iter()
+        .map(|(date, tally)| (date, tally.matching()))
+        .collect();
+    if days.is_empty() {
+        return vec![];
+    }
+    let counts: Vec<f64> = days.iter().map(|(_, count)| f64::from(*count)).collect();
+    let (baseline, low, high) = match method {
+        OutlierMethod::ZScore(z) => {
+            let mean = counts.iter().sum::<f64>() / counts.len() as f64;
+            let variance =
+                counts.iter().map(|c| (c - mean).powi(2)).sum::<f64>() / counts.len() as f64;
+            let spread = z * variance.sqrt();
+            (mean, mean - spread, mean + spread)
+        }
+        OutlierMethod::Iqr(k) => {
+            let mut sorted = counts.clone();
+            sorted.sort_by(f64::total_cmp);
+            let (q1, median, q3) = (
+                quantile(&sorted, 0.25),
+                quantile(&sorted, 0.5),
+                quantile(&sorted, 0.75),
+            );
+            let iqr = q3 - q1;
+            (median, q1 - k * iqr, q3 + k * iqr)
+        }
+    };
+
+    // fraction of each weekday (starting at Monday) that had any matching pings
+    let mut weekday_days = [0u32; 7];
+    let mut weekday_active = [0u32; 7];
+    for (date, count) in &days {
+        let weekday = date.weekday().num_days_from_monday() as usize;
+        weekday_days[weekday] += 1;
+        if *count > 0 {
+            weekday_active[weekday] += 1;
+        }
+    }
+
+    days.iter()
+        .filter_map(|(date, count)| {
+            let value = f64::from(*count);
+            let weekday = date.weekday().num_days_from_monday() as usize;
+            let reason = if value > high {
+                AnomalyReason::UnusuallyHigh
+            } else if value < low {
+                AnomalyReason::UnusuallyLow
+            } else if *count == 0
+                && f64::from(weekday_active[weekday]) / f64::from(weekday_days[weekday])
+                    >= ACTIVE_WEEKDAY_FRACTION
+            {
+                AnomalyReason::ZeroOnActiveWeekday
+            } else {
+                return None;
+            };
+            Some(Anomaly {
+                date: *date,
+                pings: *count,
+                baseline,
+                reason,
+            })
+        })
+        .collect()
+}
+
+/// Quantile of sorted values, linearly interpolating between the closest ranks.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let (below, above) = (position.floor() as usize, position.ceil() as usize);
+    sorted[below] + (sorted[above] - sorted[below]) * (position - below as f64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use chrono::{Duration, Utc};
+
+    // 2021-01-04 00:00:00 UTC, a Monday
+    const MONDAY: u64 = 1609718400;
+
+    fn date(day: i64) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2021, 1, 4).unwrap() + Duration::days(day)
+    }
+
+    fn log(counts: &[u64]) -> PingLog {
+        let mut pings = vec![];
+        for (day, count) in counts.iter().enumerate() {
+            for i in 0..*count {
+                let time = MONDAY + day as u64 * 86400 + i * 60;
+                pings.push(Ping::new(time, vec!["a".to_string()], 2700));
+            }
+        }
+        PingLog::from_pings(pings)
+    }
+
+    #[test]
+    fn flags_high_days() {
+        let counts = [4, 5, 4, 5, 4, 5, 4, 5, 4, 30, 4, 5, 4, 5];
+        let expr = Expr::from_string("a").unwrap();
+        for method in &[OutlierMethod::ZScore(3.0), OutlierMethod::Iqr(1.5)] {
+            let found = anomalies(&log(&counts), &expr, date(0)..=date(13), &Utc, *method);
+            assert_eq!(found.len(), 1, "{:?}", method);
+            assert_eq!(found[0].date, date(9));
+            assert_eq!(found[0].pings, 30);
+            assert_eq!(found[0].reason, AnomalyReason::UnusuallyHigh);
+        }
+    }
+
+    #[test]
+    fn flags_missing_active_weekdays() {
+        // weekdays always active, weekends never, except for one Wednesday
+        let mut counts = vec![];
+        for week in 0..4 {
+            counts.extend_from_slice(&[3, 3, if week == 2 { 0 } else { 3 }, 3, 3, 0, 0]);
+        }
+        let expr = Expr::from_string("a").unwrap();
+        let found = anomalies(
+            &log(&counts),
+            &expr,
+            date(0)..=date(27),
+            &Utc,
+            OutlierMethod::ZScore(3.0),
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].date, date(16));
+        assert_eq!(found[0].reason, AnomalyReason::ZeroOnActiveWeekday);
+    }
+
+    #[test]
+    fn iqr_quantiles() {
+        assert_eq!(quantile(&[1.0, 2.0, 3.0, 4.0], 0.5), 2.5);
+        assert_eq!(quantile(&[1.0, 2.0, 3.0, 4.0, 5.0], 0.25), 2.0);
+        assert_eq!(quantile(&[7.0], 0.75), 7.0);
+    }
+}
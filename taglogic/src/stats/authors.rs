@@ -0,0 +1,178 @@
+use std::ops::Range;
+
+use super::{Tally, TimeEstimate};
+use crate::bool::Expr;
+use crate::log::{match_shares, PingLog};
+
+/// Time spent on something by each person sharing a log, and by all of them together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthorEstimates {
+    /// Each author and their estimate, in the order they first answered a ping in the range.
+    /// Pings without an author are under `None`.
+    pub authors: Vec<(Option<String>, TimeEstimate)>,
+    /// Every author's pings together, so the hours are the sum of everyone's.
+    pub combined: TimeEstimate,
+}
+
+/// Estimated hours spent on `expr` during `range`, by each author of the log and combined.
+///
+/// ```
+/// use taglogic::bool::Expr;
+/// use taglogic::log::{Ping, PingLog};
+/// use taglogic::stats::estimate_by_author;
+///
+/// let ping = |time, tag: &str, author: &str| Ping {
+///     author: Some(author.to_string()),
+///     ..Ping::new(time, vec![tag.to_string()], 3600)
+/// };
+/// let log = PingLog::from_pings(vec![
+///     ping(0, "work", "alice"),
+///     ping(0, "work", "bob"),
+///     ping(3600, "work", "alice"),
+/// ]);
+/// let work = Expr::parse("work").unwrap();
+/// let estimates = estimate_by_author(&log, &work, 0..7200);
+/// assert_eq!(estimates.authors[0].0.as_deref(), Some("alice"));
+/// assert_eq!(estimates.authors[0].1.hours, 2.0);
+/// assert_eq!(estimates.combined.hours, 3.0);
+/// ```
+pub fn estimate_by_author(log: &PingLog, expr: &Expr, range: Range<u64>) -> AuthorEstimates {
+    let pings = log.range(range.start, range.end);
+    let mut tallies: Vec<(Option<&str>, Tally)> = Vec::new();
+    let mut combined = Tally::default();
+    for (ping, share) in pings.iter().zip(match_shares(&pings, expr)) {
+        combined.add_share(ping.interval, share);
+        let index = match tallies
+            .iter()
+            .position(|(author, _)| *author == ping.author)
+        {
+            Some(index) => index,
+            None => {
+                tallies.push((ping.author, Tally::default()));
+                tallies.len() - 1
+            }
+        };
+        tallies[index].1.add_share(ping.interval, share);
+    }
+    AuthorEstimates {
+        authors: (tallies.into_iter())
+            .map(|(author, tally)| (author.map(String::from), tally.estimate()))
+            .collect(),
+        combined: combined.estimate(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::binlog::{write_binary_log, BinaryLog};
+    use crate::log::Ping;
+    use crate::stats::estimate;
+
+    fn ping(time: u64, tags: &str, author: Option<&str>) -> Ping {
+        Ping {
+            author: author.map(String::from),
+            ..Ping::new(time, tags.split(' ').map(String::from).collect(), 1800)
+        }
+    }
+
+    fn log() -> PingLog {
+        PingLog::from_pings(vec![
+            ping(0, "work", Some("alice")),
+            ping(0, "play", Some("bob")),
+            ping(1800, "work email", Some("bob")),
+            ping(1800, "work", None),
+            ping(3600, "play", Some("alice")),
+        ])
+    }
+
+    #[test]
+    fn splits_by_author() {
+        let work = Expr::parse("work").unwrap();
+        let estimates = estimate_by_author(&log(), &work, 0..7200);
+        let hours: Vec<(Option<&str>, f64)> = (estimates.authors.iter())
+            .map(|(author, estimate)| (author.as_deref(), estimate.hours))
+            .collect();
+        assert_eq!(
+            hours,
+            [(Some("alice"), 0.5), (Some("bob"), 0.5), (None, 0.5)]
+        );
+        assert_eq!(estimates.combined.hours, 1.5);
+        assert_eq!(estimates.combined.pings, 3);
+
+        let empty = estimate_by_author(&log(), &work, 100..200);
+        assert!(empty.authors.is_empty());
+        assert_eq!(empty.combined, TimeEstimate::ZERO);
+    }
+
+    /// Which pings of `log` match `expr`, checking that every way of matching agrees.
+    fn matches(log: &PingLog, expr: &str) -> Vec<bool> {
+        let expr = Expr::parse(expr).unwrap();
+        let many = log.matches_many(&expr);
+        let one_by_one: Vec<bool> = log.pings().iter().map(|p| p.matches(&expr)).collect();
+        assert_eq!(many, one_by_one);
+        let owned: Vec<bool> = (log.pings().iter())
+            .map(|p| p.to_ping().matches(&expr))
+            .collect();
+        assert_eq!(many, owned);
+        let bitsets = log.tag_bitsets().matches(&expr)[0];
+        let from_bitsets: Vec<bool> = (0..log.len()).map(|i| bitsets & 1 << i != 0).collect();
+        assert_eq!(many, from_bitsets);
+        let binary = BinaryLog::new(write_binary_log(log)).unwrap();
+        assert_eq!(many, binary.matches_each(0..log.len(), &expr).unwrap());
+        many
+    }
+
+    #[test]
+    fn filters_by_author() {
+        let log = log();
+        let matching = |expr: &str| matches(&log, expr);
+        assert_eq!(
+            matching("@alice & work"),
+            [true, false, false, false, false]
+        );
+        assert_eq!(matching("@bob"), [false, true, true, false, false]);
+        assert_eq!(matching("work & !@bob"), [true, false, false, true, false]);
+        assert_eq!(log.authors(), ["alice", "bob"]);
+        assert!(log.pings().iter().all(|ping| !ping.tags.contains("@alice")));
+        assert!(ping(0, "work", Some("alice")).matches(&Expr::parse("@alice").unwrap()));
+    }
+
+    #[test]
+    fn matches_existing_at_tags() {
+        let log = PingLog::from_pings(vec![
+            ping(0, "work @home", Some("bob")),
+            ping(1800, "@home", None),
+            ping(3600, "work", Some("alice")),
+        ]);
+        // tags starting with `@` from before logs had authors still match as themselves
+        assert_eq!(matches(&log, "@home"), [true, true, false]);
+        assert_eq!(matches(&log, "work & !@home"), [false, false, true]);
+        let home = Expr::parse("@home").unwrap();
+        let by_tags: Vec<bool> = (log.pings().iter())
+            .map(|ping| home.matches(&ping.tags.iter().collect::<Vec<_>>()))
+            .collect();
+        assert_eq!(by_tags, [true, true, false]);
+        assert_eq!(matches(&log, "@alice"), [false, false, true]);
+        assert_eq!(matches(&log, "@bob & @home"), [true, false, false]);
+    }
+
+    #[test]
+    fn weighted_tags_agree_with_estimate() {
+        let ping = |time, entry, author: &str| Ping {
+            author: Some(author.to_string()),
+            ..Ping::from_entry(time, entry, 3600)
+        };
+        let log = PingLog::from_pings(vec![
+            ping(0, "work:0.5 email:0.5", "alice"),
+            ping(10, "work:0.25", "bob"),
+        ]);
+        let work = Expr::parse("work").unwrap();
+        let estimates = estimate_by_author(&log, &work, 0..100);
+        assert_eq!(estimates.combined, estimate(&log, &work, 0..100));
+        let hours: Vec<f64> = (estimates.authors.iter())
+            .map(|(_, estimate)| estimate.hours)
+            .collect();
+        assert_eq!(hours, [0.5, 0.25]);
+    }
+}
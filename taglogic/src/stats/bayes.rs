@@ -0,0 +1,136 @@
+use std::ops::Range;
+
+use super::special::beta_quantile;
+use crate::bool::Expr;
+use crate::log::{match_shares, PingLog};
+
+/// Prior belief about the fraction of time spent on something, as the parameters of a beta
+/// distribution.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BetaPrior {
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl BetaPrior {
+    /// Every fraction is equally likely.
+    pub const UNIFORM: Self = Self {
+        alpha: 1.0,
+        beta: 1.0,
+    };
+    /// The Jeffreys prior, which has less influence on the result than the uniform prior.
+    pub const JEFFREYS: Self = Self {
+        alpha: 0.5,
+        beta: 0.5,
+    };
+}
+
+/// Posterior distribution of the fraction of time spent on something.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FractionPosterior {
+    pub alpha: f64,
+    pub beta: f64,
+    pub mean: f64,
+    /// Lower bound of the equal-tailed credible interval.
+    pub low: f64,
+    /// Upper bound of the equal-tailed credible interval.
+    pub high: f64,
+}
+
+/// Estimates the fraction of time in `range` that was spent on `expr`, by updating `prior` with
+/// the number of matching and non-matching pings, where pings split by weighted tags count for
+/// the part that matched. Returns the posterior mean and a credible
+/// interval containing `credibility` (such as 0.95) of the posterior probability. Unlike the
+/// confidence intervals from the other stats, this stays sensible when there are very few pings.
+///
+/// ## Panics
+/// Panics if `credibility` isn't between 0 and 1, or if the prior's parameters aren't positive.
+pub fn bayes_fraction(
+    log: &PingLog,
+    expr: &Expr,
+    range: Range<u64>,
+    prior: BetaPrior,
+    credibility: f64,
+) -> FractionPosterior {
+    assert!(
+        credibility > 0.0 && credibility < 1.0,
+        "credibility must be between 0 and 1"
+    );
+    assert!(
+        prior.alpha > 0.0 && prior.beta > 0.0,
+        "prior parameters must be positive"
+    );
+    let pings = log.range(range.start, range.end);
+    let matching: f64 = match_shares(&pings, expr).iter().sum();
+    let alpha = prior.alpha + matching;
+    let beta = prior.beta + (pings.len() as f64 - matching);
+    let tail = (1.0 - credibility) / 2.0;
+    FractionPosterior {
+        alpha,
+        beta,
+        mean: alpha / (alpha + beta),
+        low: beta_quantile(tail, alpha, beta),
+        high: beta_quantile(1.0 - tail, alpha, beta),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use crate::stats::estimate;
+
+    fn log(matching: u64, other: u64) -> PingLog {
+        PingLog::from_pings(
+            (0..(matching + other))
+                .map(|i| {
+                    let tag = if i < matching { "a" } else { "b" };
+                    Ping::new(i, vec![tag.to_string()], 2700)
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn no_pings_gives_prior() {
+        let expr = Expr::from_string("a").unwrap();
+        let posterior = bayes_fraction(&log(0, 0), &expr, 0..100, BetaPrior::UNIFORM, 0.95);
+        assert_eq!(posterior.mean, 0.5);
+        assert!((posterior.low - 0.025).abs() < 1e-9);
+        assert!((posterior.high - 0.975).abs() < 1e-9);
+    }
+
+    #[test]
+    fn updates_with_pings() {
+        let expr = Expr::from_string("a").unwrap();
+        let posterior = bayes_fraction(&log(3, 1), &expr, 0..100, BetaPrior::UNIFORM, 0.95);
+        assert_eq!(posterior.alpha, 4.0);
+        assert_eq!(posterior.beta, 2.0);
+        assert!((posterior.mean - 2.0 / 3.0).abs() < 1e-12);
+        assert!(posterior.low > 0.2 && posterior.low < posterior.mean);
+        assert!(posterior.high < 1.0 && posterior.high > posterior.mean);
+    }
+
+    #[test]
+    fn more_pings_narrow_interval() {
+        let expr = Expr::from_string("a").unwrap();
+        let few = bayes_fraction(&log(2, 2), &expr, 0..1000, BetaPrior::JEFFREYS, 0.9);
+        let many = bayes_fraction(&log(200, 200), &expr, 0..1000, BetaPrior::JEFFREYS, 0.9);
+        assert!(many.high - many.low < few.high - few.low);
+        assert!((many.mean - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn weighted_tags_agree_with_estimate() {
+        let log = PingLog::from_pings(
+            (0..4)
+                .map(|i| Ping::from_entry(i, "a:0.25 b:0.75", 3600))
+                .collect(),
+        );
+        let expr = Expr::from_string("a").unwrap();
+        let posterior = bayes_fraction(&log, &expr, 0..100, BetaPrior::UNIFORM, 0.95);
+        // with hour-long pings, the matching pings are the hours estimated
+        assert_eq!(posterior.alpha - 1.0, estimate(&log, &expr, 0..100).hours);
+        assert_eq!(posterior.beta, 4.0);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::tt;
+
+/// A confidence interval found by resampling.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BootstrapInterval {
+    /// The estimator's value on the original sample.
+    pub estimate: f64,
+    /// Standard deviation of the estimator across resamples.
+    pub std_error: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+/// Finds a confidence interval for `estimator` by running it on `iterations` resamples of `items`
+/// (drawn with replacement), and taking percentiles of the results. Resampling uses the same RNG
+/// as the TagTime schedule, seeded with `seed`, so results are the same on every platform.
+///
+/// ## Panics
+/// Panics if `iterations` is zero, or if `confidence` isn't between 0 and 1.
+pub fn bootstrap<T, F>(
+    items: &[T],
+    estimator: F,
+    iterations: u32,
+    confidence: f64,
+    seed: u64,
+) -> BootstrapInterval
+where
+    F: Fn(&[&T]) -> f64,
+{
+    assert!(iterations > 0, "need at least one iteration");
+    assert!(
+        confidence > 0.0 && confidence < 1.0,
+        "confidence must be between 0 and 1"
+    );
+    let original: Vec<&T> = items.iter().collect();
+    let estimate = estimator(&original);
+
+    // the RNG state must be in 1..2^31-1
+    let modulus = u64::from(tt::IM_U32 - 1);
+    let mut rng = tt::State::from_seed((seed % modulus) as u32 + 1);
+    let mut resample: Vec<&T> = Vec::with_capacity(items.len());
+    let mut results: Vec<f64> = Vec::with_capacity(iterations as usize);
+    for _ in 0..iterations {
+        resample.clear();
+        for _ in 0..items.len() {
+            rng.next_state();
+            // inner() is never zero, and less than IM
+            let index = (u64::from(rng.inner() - 1) * items.len() as u64) / modulus;
+            resample.push(&items[index as usize]);
+        }
+        results.push(estimator(&resample));
+    }
+    results.sort_by(f64::total_cmp);
+
+    let mean = results.iter().sum::<f64>() / results.len() as f64;
+    let variance = results.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / results.len() as f64;
+    let tail = (1.0 - confidence) / 2.0;
+    let percentile = |q: f64| {
+        let index = (q * (results.len() - 1) as f64).round() as usize;
+        results[index]
+    };
+    BootstrapInterval {
+        estimate,
+        std_error: variance.sqrt(),
+        low: percentile(tail),
+        high: percentile(1.0 - tail),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+
+    fn mean(values: &[&f64]) -> f64 {
+        values.iter().copied().sum::<f64>() / values.len() as f64
+    }
+
+    #[test]
+    fn deterministic() {
+        let values: Vec<f64> = (0..50).map(|i| (i * 7 % 13) as f64).collect();
+        let a = bootstrap(&values, mean, 200, 0.95, 1234);
+        let b = bootstrap(&values, mean, 200, 0.95, 1234);
+        let c = bootstrap(&values, mean, 200, 0.95, 4321);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn interval_contains_estimate() {
+        let values: Vec<f64> = (0..100).map(|i| (i % 10) as f64).collect();
+        let result = bootstrap(&values, mean, 1000, 0.95, 0);
+        assert_eq!(result.estimate, 4.5);
+        assert!(result.low < 4.5 && result.high > 4.5);
+        // the standard error of the mean is about 2.87 / sqrt(100)
+        assert!((result.std_error - 0.287).abs() < 0.05);
+    }
+
+    #[test]
+    fn constant_sample() {
+        let values = vec![3.0; 20];
+        let result = bootstrap(&values, mean, 10, 0.9, 99);
+        assert_eq!((result.low, result.high, result.std_error), (3.0, 3.0, 0.0));
+    }
+
+    #[test]
+    fn works_with_pings() {
+        let pings: Vec<Ping> = (0..40)
+            .map(|i| Ping::new(i, vec![if i % 4 == 0 { "a" } else { "b" }.into()], 3600))
+            .collect();
+        let fraction = |pings: &[&Ping]| {
+            pings.iter().filter(|ping| ping.tags[0] == "a").count() as f64 / pings.len() as f64
+        };
+        let result = bootstrap(&pings, fraction, 500, 0.95, 7);
+        assert_eq!(result.estimate, 0.25);
+        assert!(result.low < 0.25 && result.high > 0.25);
+    }
+}
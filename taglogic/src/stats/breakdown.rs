@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::bool::{CommentMatcher, Expr};
+use crate::log::PingLog;
+
+/// What to break a filter's time down by.
+#[derive(Debug, Clone, Copy)]
+pub enum Children<'a> {
+    /// Named expressions. A ping matching more than one of them counts towards the first one.
+    Exprs(&'a [(String, Expr)]),
+    /// Every tag that isn't used in the parent filter. Each ping's time is split evenly between
+    /// its tags.
+    Tags,
+}
+
+/// Part of the time spent on a parent filter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Share {
+    pub name: String,
+    pub hours: f64,
+    /// Fraction of the parent filter's time, between 0 and 1.
+    pub fraction: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakdown {
+    /// Hours spent on the parent filter.
+    pub total_hours: f64,
+    /// Shares of each child, from largest to smallest.
+    pub shares: Vec<Share>,
+    /// Time that didn't go to any of the children in `shares`. This includes uncategorized time
+    /// and time from children that didn't make the cut when limiting the number of children.
+    pub other: Share,
+}
+
+/// Breaks down the time in `range` spent on `parent` by `children`. If `top_n` is given, only
+/// that many of the largest children are listed, and the rest is added to the other share. Pings
+/// split by weighted tags are broken down a part at a time, so the parent gets the time
+/// [`estimate`](super::estimate) would give it.
+pub fn breakdown(
+    log: &PingLog,
+    parent: &Expr,
+    children: Children,
+    range: Range<u64>,
+    top_n: Option<usize>,
+) -> Breakdown {
+    let parent_tags = parent.tags();
+    let parent = CommentMatcher::new(parent);
+    let child_exprs: Vec<(&str, CommentMatcher<'_>)> = match children {
+        Children::Exprs(exprs) => (exprs.iter())
+            .map(|(name, expr)| (name.as_str(), CommentMatcher::new(expr)))
+            .collect(),
+        Children::Tags => Vec::new(),
+    };
+    let mut secs: HashMap<&str, f64> = HashMap::new();
+    let mut total_secs = 0.0;
+    let mut other_secs = 0.0;
+    for ping in log.range(range.start, range.end) {
+        for (tags, fraction) in ping.parts() {
+            if !ping.part_matches(&parent, &tags) {
+                continue;
+            }
+            let interval = f64::from(ping.interval) * fraction;
+            total_secs += interval;
+            match children {
+                Children::Exprs(_) => {
+                    match (child_exprs.iter()).find(|(_, expr)| ping.part_matches(expr, &tags)) {
+                        Some((name, _)) => *secs.entry(name).or_default() += interval,
+                        None => other_secs += interval,
+                    }
+                }
+                Children::Tags => {
+                    let tags: Vec<&str> = (tags.into_iter())
+                        .filter(|tag| !parent_tags.contains(tag))
+                        .collect();
+                    if tags.is_empty() {
+                        other_secs += interval;
+                    }
+                    for tag in &tags {
+                        *secs.entry(tag).or_default() += interval / tags.len() as f64;
+                    }
+                }
+            }
+        }
+    }
+
+    let share = |name: &str, secs: f64| Share {
+        name: name.to_string(),
+        hours: secs / 3600.0,
+        fraction: if total_secs > 0.0 {
+            secs / total_secs
+        } else {
+            0.0
+        },
+    };
+    let mut sorted: Vec<(&str, f64)> = secs.into_iter().collect();
+    // biggest first, ties broken by name so the order is stable
+    sorted.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+    if let Some(n) = top_n {
+        other_secs += sorted.iter().skip(n).map(|(_, secs)| secs).sum::<f64>();
+        sorted.truncate(n);
+    }
+    Breakdown {
+        total_hours: total_secs / 3600.0,
+        shares: sorted
+            .into_iter()
+            .map(|(name, secs)| share(name, secs))
+            .collect(),
+        other: share("other", other_secs),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use crate::stats::estimate;
+
+    fn log() -> PingLog {
+        let pings = [
+            "work code",
+            "work code",
+            "work code rust",
+            "work email",
+            "work",
+            "play",
+        ];
+        PingLog::from_pings(
+            pings
+                .iter()
+                .enumerate()
+                .map(|(i, tags)| {
+                    Ping::new(i as u64, tags.split(' ').map(String::from).collect(), 3600)
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn by_exprs() {
+        let parent = Expr::from_string("work").unwrap();
+        let children = vec![
+            ("coding".to_string(), Expr::from_string("code").unwrap()),
+            ("rust".to_string(), Expr::from_string("rust").unwrap()),
+        ];
+        let result = breakdown(&log(), &parent, Children::Exprs(&children), 0..100, None);
+        assert_eq!(result.total_hours, 5.0);
+        assert_eq!(
+            result.shares,
+            vec![
+                Share {
+                    name: "coding".to_string(),
+                    hours: 3.0,
+                    fraction: 0.6
+                },
+                // rust pings were already counted as coding
+            ]
+        );
+        assert_eq!(result.other.hours, 2.0);
+    }
+
+    #[test]
+    fn by_tags() {
+        let parent = Expr::from_string("work").unwrap();
+        let result = breakdown(&log(), &parent, Children::Tags, 0..100, None);
+        let shares: Vec<(&str, f64)> = result
+            .shares
+            .iter()
+            .map(|share| (share.name.as_str(), share.hours))
+            .collect();
+        assert_eq!(shares, vec![("code", 2.5), ("email", 1.0), ("rust", 0.5)]);
+        assert_eq!(result.other.hours, 1.0);
+        let fractions: f64 = result.shares.iter().map(|share| share.fraction).sum();
+        assert!((fractions + result.other.fraction - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn top_n_goes_to_other() {
+        let parent = Expr::from_string("work").unwrap();
+        let result = breakdown(&log(), &parent, Children::Tags, 0..100, Some(1));
+        assert_eq!(result.shares.len(), 1);
+        assert_eq!(result.other.hours, 2.5);
+    }
+
+    #[test]
+    fn nothing_matches() {
+        let parent = Expr::from_string("sleep").unwrap();
+        let result = breakdown(&log(), &parent, Children::Tags, 0..100, None);
+        assert_eq!(result.total_hours, 0.0);
+        assert!(result.shares.is_empty());
+        assert_eq!(result.other.fraction, 0.0);
+    }
+
+    #[test]
+    fn weighted_tags_agree_with_estimate() {
+        let log = PingLog::from_pings(vec![
+            Ping::from_entry(0, "work code:0.25 email:0.75", 3600),
+            Ping::from_entry(1, "work", 3600),
+        ]);
+        let hours = |expr| estimate(&log, &Expr::from_string(expr).unwrap(), 0..100).hours;
+        let parent = Expr::from_string("work").unwrap();
+        let children = vec![("coding".to_string(), Expr::from_string("code").unwrap())];
+        let result = breakdown(&log, &parent, Children::Exprs(&children), 0..100, None);
+        assert_eq!(result.total_hours, hours("work"));
+        assert_eq!(result.shares[0].hours, hours("work & code"));
+        let result = breakdown(&log, &parent, Children::Tags, 0..100, None);
+        let shares: Vec<(&str, f64)> = (result.shares.iter())
+            .map(|share| (share.name.as_str(), share.hours))
+            .collect();
+        assert_eq!(shares, vec![("email", 0.75), ("code", 0.25)]);
+        assert_eq!(result.other.hours, 1.0);
+    }
+}
@@ -0,0 +1,146 @@
+use std::ops::Range;
+
+use super::{Tally, TimeEstimate, Z_95};
+use crate::bool::Expr;
+use crate::log::{match_shares, PingLog};
+
+const DAY_SECS: f64 = 86400.0;
+
+/// Estimated time spent on something in two time ranges, and how they differ.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Comparison {
+    pub a: TimeEstimate,
+    pub b: TimeEstimate,
+    /// Hours per day in range a.
+    pub a_per_day: f64,
+    /// Hours per day in range b.
+    pub b_per_day: f64,
+    /// Percent change in hours per day from range a to range b, or None if no time was spent in
+    /// range a.
+    pub percent_change: Option<f64>,
+    /// z-score of the difference in hours per day between b and a.
+    pub z_score: f64,
+    /// If the difference is statistically significant at the 95% level.
+    pub significant: bool,
+}
+
+/// Compares the time spent on `expr` during two time ranges. Ranges don't need to be the same
+/// length, since the comparison is done on hours per day. The difference is tested with a z-test,
+/// using the variance of each estimate from the number of matching pings.
+///
+/// ## Panics
+/// Panics if either range is empty.
+pub fn compare(log: &PingLog, expr: &Expr, range_a: Range<u64>, range_b: Range<u64>) -> Comparison {
+    assert!(
+        range_a.start < range_a.end && range_b.start < range_b.end,
+        "ranges must not be empty"
+    );
+    let tally = |range: &Range<u64>| {
+        let pings = log.range(range.start, range.end);
+        let mut tally = Tally::default();
+        for (ping, share) in pings.iter().zip(match_shares(&pings, expr)) {
+            tally.add_share(ping.interval, share);
+        }
+        tally
+    };
+    let (tally_a, tally_b) = (tally(&range_a), tally(&range_b));
+    let days_a = (range_a.end - range_a.start) as f64 / DAY_SECS;
+    let days_b = (range_b.end - range_b.start) as f64 / DAY_SECS;
+    let (a, b) = (tally_a.estimate(), tally_b.estimate());
+    let (a_per_day, b_per_day) = (a.hours / days_a, b.hours / days_b);
+
+    let variance = tally_a.variance() / days_a.powi(2) + tally_b.variance() / days_b.powi(2);
+    let z_score = if variance > 0.0 {
+        (b_per_day - a_per_day) / variance.sqrt()
+    } else {
+        0.0
+    };
+    Comparison {
+        a,
+        b,
+        a_per_day,
+        b_per_day,
+        percent_change: if a_per_day > 0.0 {
+            Some((b_per_day - a_per_day) / a_per_day * 100.0)
+        } else {
+            None
+        },
+        z_score,
+        significant: z_score.abs() > Z_95,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use crate::stats::estimate;
+
+    const DAY: u64 = 86400;
+
+    /// A log where day `i` has `counts[i]` pings tagged "a", and one tagged "b".
+    fn log(counts: &[u64]) -> PingLog {
+        let mut pings = vec![];
+        for (day, count) in counts.iter().enumerate() {
+            let day = day as u64;
+            for i in 0..*count {
+                pings.push(Ping::new(day * DAY + i, vec!["a".to_string()], 1800));
+            }
+            pings.push(Ping::new(day * DAY + 1000, vec!["b".to_string()], 1800));
+        }
+        PingLog::from_pings(pings)
+    }
+
+    #[test]
+    fn big_difference_is_significant() {
+        let log = log(&[2, 2, 2, 2, 20, 20, 20, 20]);
+        let expr = Expr::from_string("a").unwrap();
+        let result = compare(&log, &expr, 0..(4 * DAY), (4 * DAY)..(8 * DAY));
+        assert_eq!(result.a.hours, 4.0);
+        assert_eq!(result.b.hours, 40.0);
+        assert_eq!(result.a_per_day, 1.0);
+        assert_eq!(result.percent_change, Some(900.0));
+        assert!(result.z_score > 0.0);
+        assert!(result.significant);
+    }
+
+    #[test]
+    fn small_difference_is_not_significant() {
+        let log = log(&[3, 2, 2, 3]);
+        let expr = Expr::from_string("a").unwrap();
+        let result = compare(&log, &expr, 0..(2 * DAY), (2 * DAY)..(4 * DAY));
+        assert_eq!(result.percent_change, Some(0.0));
+        assert!(!result.significant);
+    }
+
+    #[test]
+    fn different_length_ranges() {
+        let log = log(&[2, 2, 2]);
+        let expr = Expr::from_string("a").unwrap();
+        let result = compare(&log, &expr, 0..DAY, DAY..(3 * DAY));
+        assert_eq!(result.b.hours, 2.0 * result.a.hours);
+        assert_eq!(result.percent_change, Some(0.0));
+    }
+
+    #[test]
+    fn nothing_in_first_range() {
+        let log = log(&[0, 2]);
+        let expr = Expr::from_string("a").unwrap();
+        let result = compare(&log, &expr, 0..DAY, DAY..(2 * DAY));
+        assert_eq!(result.percent_change, None);
+        assert!(result.z_score > 0.0);
+    }
+
+    #[test]
+    fn weighted_tags_agree_with_estimate() {
+        let log = PingLog::from_pings(vec![
+            Ping::from_entry(0, "a:0.75 b:0.25", 1800),
+            Ping::from_entry(DAY, "a:0.5", 1800),
+        ]);
+        let expr = Expr::from_string("a").unwrap();
+        let result = compare(&log, &expr, 0..DAY, DAY..(2 * DAY));
+        assert_eq!(result.a, estimate(&log, &expr, 0..DAY));
+        assert_eq!(result.b, estimate(&log, &expr, DAY..(2 * DAY)));
+        assert_eq!(result.b.hours, 0.25);
+    }
+}
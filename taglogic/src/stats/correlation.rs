@@ -0,0 +1,227 @@
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+
+use super::special::reg_inc_beta;
+use super::trend::series_from_tallies;
+use super::{local_date, Bucket, Calendar, Tally};
+use crate::bool::Expr;
+use crate::log::{match_shares, PingLog};
+
+/// A correlation coefficient with its two-sided p-value.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Coefficient {
+    /// Between -1 and 1.
+    pub r: f64,
+    /// Probability of a correlation at least this strong if the two were actually unrelated.
+    pub p_value: f64,
+}
+
+/// How the time spent on two expressions moves together.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Correlation {
+    /// Number of buckets compared.
+    pub samples: usize,
+    /// Linear correlation of the estimated hours.
+    pub pearson: Coefficient,
+    /// Correlation of the ranks of the estimated hours, which doesn't assume the relationship is
+    /// linear and isn't thrown off by a few extreme buckets.
+    pub spearman: Coefficient,
+}
+
+/// Correlates the estimated hours spent on `expr_a` and `expr_b` per bucket, in the time zone
+/// `tz`, over every bucket from the first to the last ping in the log. Returns None if there are
+/// less than three buckets, or if either expression's hours are the same in every bucket.
+pub fn correlation<Tz: Calendar>(
+    log: &PingLog,
+    expr_a: &Expr,
+    expr_b: &Expr,
+    bucket: Bucket,
+    tz: &Tz,
+) -> Option<Correlation> {
+    let mut tallies_a: BTreeMap<NaiveDate, Tally> = BTreeMap::new();
+    let mut tallies_b: BTreeMap<NaiveDate, Tally> = BTreeMap::new();
+    let pings = log.pings();
+    let shares = match_shares(&pings, expr_a)
+        .into_iter()
+        .zip(match_shares(&pings, expr_b));
+    for (ping, (share_a, share_b)) in pings.iter().zip(shares) {
+        if let Some(date) = local_date(ping.time, tz) {
+            let start = bucket.start_with(date, tz.week_start());
+            tallies_a
+                .entry(start)
+                .or_default()
+                .add_share(ping.interval, share_a);
+            tallies_b
+                .entry(start)
+                .or_default()
+                .add_share(ping.interval, share_b);
+        }
+    }
+    let hours = |tallies| -> Vec<f64> {
+        series_from_tallies(bucket, tz.week_start(), tallies)
+            .points
+            .iter()
+            .map(|point| point.estimate.hours)
+            .collect()
+    };
+    let (a, b) = (hours(&tallies_a), hours(&tallies_b));
+    if a.len() < 3 {
+        return None;
+    }
+    Some(Correlation {
+        samples: a.len(),
+        pearson: coefficient(pearson(&a, &b)?, a.len()),
+        spearman: coefficient(pearson(&ranks(&a), &ranks(&b))?, a.len()),
+    })
+}
+
+fn pearson(a: &[f64], b: &[f64]) -> Option<f64> {
+    let n = a.len() as f64;
+    let mean_a = a.iter().sum::<f64>() / n;
+    let mean_b = b.iter().sum::<f64>() / n;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+    if var_a == 0.0 || var_b == 0.0 {
+        return None;
+    }
+    // rounding can push this slightly past 1
+    Some((cov / (var_a * var_b).sqrt()).clamp(-1.0, 1.0))
+}
+
+/// Ranks of the values starting at 1, with tied values getting the average of their ranks.
+fn ranks(values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|i, j| values[*i].total_cmp(&values[*j]));
+    let mut ranks = vec![0.0; values.len()];
+    let mut i = 0;
+    while i < order.len() {
+        let mut j = i;
+        while j + 1 < order.len() && values[order[j + 1]] == values[order[i]] {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        for k in &order[i..=j] {
+            ranks[*k] = rank;
+        }
+        i = j + 1;
+    }
+    ranks
+}
+
+/// Finds the p-value of `r` with a t-test with `n - 2` degrees of freedom.
+fn coefficient(r: f64, n: usize) -> Coefficient {
+    let df = (n - 2) as f64;
+    let t_sq = r * r * df / (1.0 - r * r);
+    Coefficient {
+        r,
+        // the two-sided tail of Student's t distribution
+        p_value: reg_inc_beta(df / (df + t_sq), df / 2.0, 0.5),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use crate::stats::estimate;
+    use chrono::Utc;
+
+    const DAY: u64 = 86400;
+    // 2021-01-04 00:00:00 UTC
+    const START: u64 = 1609718400;
+
+    /// A log where day `i` has `a[i]` pings of "a" and `b[i]` pings of "b".
+    fn log(a: &[u64], b: &[u64]) -> PingLog {
+        let mut pings = vec![];
+        for (day, (a, b)) in a.iter().zip(b).enumerate() {
+            let time = START + day as u64 * DAY;
+            // make sure every day has a ping
+            pings.push(Ping::new(time, vec!["other".to_string()], 3600));
+            for i in 0..*a {
+                pings.push(Ping::new(time + 1 + i, vec!["a".to_string()], 3600));
+            }
+            for i in 0..*b {
+                pings.push(Ping::new(time + 1000 + i, vec!["b".to_string()], 3600));
+            }
+        }
+        PingLog::from_pings(pings)
+    }
+
+    fn run(log: &PingLog) -> Option<Correlation> {
+        let a = Expr::from_string("a").unwrap();
+        let b = Expr::from_string("b").unwrap();
+        correlation(log, &a, &b, Bucket::Day, &Utc)
+    }
+
+    #[test]
+    fn perfect_correlation() {
+        let result = run(&log(&[1, 2, 3, 4, 5], &[2, 4, 6, 8, 10])).unwrap();
+        assert_eq!(result.samples, 5);
+        assert!((result.pearson.r - 1.0).abs() < 1e-12);
+        assert!(result.pearson.p_value < 1e-6);
+        assert!((result.spearman.r - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn spearman_ignores_nonlinearity() {
+        let result = run(&log(&[1, 2, 3, 4, 5], &[1, 2, 4, 8, 16])).unwrap();
+        assert!(result.pearson.r < 0.99);
+        assert!((result.spearman.r - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn known_p_value() {
+        // r = 0.8 with 10 samples has a p-value of about 0.0055
+        let p = coefficient(0.8, 10).p_value;
+        assert!((p - 0.005_47).abs() < 1e-4, "{}", p);
+    }
+
+    #[test]
+    fn anticorrelated_and_weak() {
+        let result = run(&log(&[5, 4, 3, 2, 1], &[1, 2, 3, 4, 5])).unwrap();
+        assert!((result.pearson.r + 1.0).abs() < 1e-12);
+
+        let result = run(&log(&[1, 3, 2, 3, 1, 2], &[2, 2, 1, 3, 3, 1])).unwrap();
+        assert!(result.pearson.p_value > 0.5);
+    }
+
+    #[test]
+    fn ties_get_average_rank() {
+        assert_eq!(ranks(&[3.0, 1.0, 3.0, 2.0]), vec![3.5, 1.0, 3.5, 2.0]);
+    }
+
+    #[test]
+    fn needs_variation() {
+        assert_eq!(run(&log(&[1, 1, 1], &[1, 2, 3])), None);
+        assert_eq!(run(&log(&[1, 2], &[1, 2])), None);
+    }
+
+    #[test]
+    fn weighted_tags_agree_with_estimate() {
+        let mut pings = vec![];
+        for (day, entry) in ["a:0.25 b:0.75", "a:0.5 b:0.5", "a:0.75 b:0.25"]
+            .iter()
+            .enumerate()
+        {
+            pings.push(Ping::from_entry(START + day as u64 * DAY, entry, 3600));
+        }
+        let log = PingLog::from_pings(pings);
+        let hours = |expr, day| {
+            let start = START + day * DAY;
+            estimate(
+                &log,
+                &Expr::from_string(expr).unwrap(),
+                start..(start + DAY),
+            )
+            .hours
+        };
+        assert_eq!((hours("a", 0), hours("b", 0)), (0.25, 0.75));
+        // every day's a and b add up to the same hour, so they go opposite ways
+        let result = run(&log).unwrap();
+        assert!((result.pearson.r + 1.0).abs() < 1e-12);
+    }
+}
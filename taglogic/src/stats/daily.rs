@@ -0,0 +1,133 @@
+use chrono::{Duration, NaiveDate};
+use std::ops::RangeInclusive;
+
+use super::{day_start, local_date, Calendar, Tally};
+use crate::bool::Expr;
+use crate::log::{match_shares, PingLog};
+
+/// Estimated hours spent on `expr` for every local day in `range` (including days with no
+/// pings), in the time zone `tz`. Days are grouped by their local date, so days that are shorter
+/// or longer because of DST transitions get exactly the pings sent during them.
+pub fn daily_hours<Tz: Calendar>(
+    log: &PingLog,
+    expr: &Expr,
+    range: RangeInclusive<NaiveDate>,
+    tz: &Tz,
+) -> Vec<(NaiveDate, f64)> {
+    daily_tallies(log, expr, range, tz)
+        .into_iter()
+        .map(|(date, tally)| (date, tally.estimate().hours))
+        .collect()
+}
+
+/// Tallies of pings for every local day in `range`, like [`daily_hours`].
+pub(crate) fn daily_tallies<Tz: Calendar>(
+    log: &PingLog,
+    expr: &Expr,
+    range: RangeInclusive<NaiveDate>,
+    tz: &Tz,
+) -> Vec<(NaiveDate, Tally)> {
+    let (first, last) = (*range.start(), *range.end());
+    if first > last {
+        return vec![];
+    }
+    let days = (last - first).num_days() + 1;
+    let mut tallies = vec![Tally::default(); days as usize];
+    let start = day_start(first, tz).unwrap_or(0);
+    let end = last
+        .succ_opt()
+        .and_then(|day| day_start(day, tz))
+        .unwrap_or(u64::MAX);
+    let pings = log.range(start, end);
+    for (ping, share) in pings.iter().zip(match_shares(&pings, expr)) {
+        let date = match local_date(ping.time, tz) {
+            Some(date) if range.contains(&date) => date,
+            _ => continue,
+        };
+        tallies[(date - first).num_days() as usize].add_share(ping.interval, share);
+    }
+    tallies
+        .into_iter()
+        .enumerate()
+        .map(|(i, tally)| (first + Duration::days(i as i64), tally))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use chrono_tz::America::New_York;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    /// A log with an hour-long ping at the start of every UTC hour from `start` to `end`.
+    fn hourly(start: u64, end: u64) -> PingLog {
+        PingLog::from_pings(
+            (start..end)
+                .step_by(3600)
+                .map(|time| Ping::new(time, vec!["a".to_string()], 3600))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn dst_days() {
+        // 2021-03-13 00:00 to 2021-03-16 00:00 in New York
+        let log = hourly(1615611600, 1615867200);
+        let expr = Expr::from_string("a").unwrap();
+        let hours = daily_hours(
+            &log,
+            &expr,
+            date(2021, 3, 13)..=date(2021, 3, 15),
+            &New_York,
+        );
+        assert_eq!(
+            hours,
+            vec![
+                (date(2021, 3, 13), 24.0),
+                (date(2021, 3, 14), 23.0),
+                (date(2021, 3, 15), 24.0),
+            ]
+        );
+
+        // 2021-11-07 00:00 to 2021-11-08 00:00 in New York
+        let log = hourly(1636257600, 1636347600);
+        let hours = daily_hours(
+            &log,
+            &expr,
+            date(2021, 11, 7)..=date(2021, 11, 7),
+            &New_York,
+        );
+        assert_eq!(hours, vec![(date(2021, 11, 7), 25.0)]);
+    }
+
+    #[test]
+    fn includes_empty_days() {
+        let log = hourly(1615611600, 1615611600 + 3600);
+        let expr = Expr::from_string("a").unwrap();
+        let hours = daily_hours(
+            &log,
+            &expr,
+            date(2021, 3, 12)..=date(2021, 3, 14),
+            &New_York,
+        );
+        assert_eq!(
+            hours,
+            vec![
+                (date(2021, 3, 12), 0.0),
+                (date(2021, 3, 13), 1.0),
+                (date(2021, 3, 14), 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn backwards_range() {
+        let expr = Expr::from_string("a").unwrap();
+        let range = date(2021, 3, 14)..=date(2021, 3, 12);
+        assert!(daily_hours(&PingLog::new(), &expr, range, &New_York).is_empty());
+    }
+}
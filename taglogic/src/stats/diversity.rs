@@ -0,0 +1,149 @@
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use super::{local_date, Bucket, Calendar};
+use crate::log::{PingLog, PingRef};
+
+/// How spread out time is across tags. Each ping's time is split evenly between its tags.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Diversity {
+    /// Number of distinct tags.
+    pub tags: usize,
+    /// Shannon entropy of the distribution of time across tags, in bits.
+    pub entropy: f64,
+    /// Entropy divided by the largest possible entropy with this many tags, between 0 (all time
+    /// on one tag) and 1 (time spread perfectly evenly). Zero if there are less than two tags.
+    pub evenness: f64,
+    /// The number of equally-used tags that would have the same entropy.
+    pub effective_tags: f64,
+}
+
+impl Diversity {
+    pub(super) fn from_pings<'a>(pings: impl Iterator<Item = PingRef<'a>>) -> Self {
+        let mut secs: HashMap<&str, f64> = HashMap::new();
+        for ping in pings {
+            for tag in &ping.tags {
+                *secs.entry(tag).or_default() += f64::from(ping.interval) / ping.tags.len() as f64;
+            }
+        }
+        let total: f64 = secs.values().sum();
+        let entropy = if total > 0.0 {
+            -secs
+                .values()
+                .map(|secs| secs / total)
+                .filter(|p| *p > 0.0)
+                .map(|p| p * p.log2())
+                .sum::<f64>()
+        } else {
+            0.0
+        };
+        Self {
+            tags: secs.len(),
+            // -0.0 looks weird, so make sure that doesn't come out
+            entropy: entropy.max(0.0),
+            evenness: if secs.len() > 1 {
+                entropy / (secs.len() as f64).log2()
+            } else {
+                0.0
+            },
+            effective_tags: entropy.exp2(),
+        }
+    }
+}
+
+/// Diversity of the tags of all pings in `range`.
+pub fn tag_entropy(log: &PingLog, range: Range<u64>) -> Diversity {
+    Diversity::from_pings(log.range(range.start, range.end).iter())
+}
+
+/// Diversity of tags in each bucket, in the time zone `tz`. Buckets without pings are left out.
+pub fn diversity_series<Tz: Calendar>(
+    log: &PingLog,
+    bucket: Bucket,
+    tz: &Tz,
+) -> Vec<(NaiveDate, Diversity)> {
+    let mut buckets: BTreeMap<NaiveDate, Vec<PingRef>> = BTreeMap::new();
+    for ping in log.pings() {
+        if let Some(date) = local_date(ping.time, tz) {
+            buckets
+                .entry(bucket.start_with(date, tz.week_start()))
+                .or_default()
+                .push(ping);
+        }
+    }
+    buckets
+        .into_iter()
+        .map(|(start, pings)| (start, Diversity::from_pings(pings.into_iter())))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use chrono::Utc;
+
+    fn log(pings: &[&str]) -> PingLog {
+        PingLog::from_pings(
+            pings
+                .iter()
+                .enumerate()
+                .map(|(i, tags)| {
+                    let time = 1609718400 + i as u64 * 86400;
+                    Ping::new(time, tags.split(' ').map(String::from).collect(), 2700)
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn single_tag_has_no_entropy() {
+        let diversity = tag_entropy(&log(&["a", "a", "a"]), 0..u64::MAX);
+        assert_eq!(diversity.tags, 1);
+        assert_eq!(diversity.entropy, 0.0);
+        assert_eq!(diversity.evenness, 0.0);
+        assert_eq!(diversity.effective_tags, 1.0);
+    }
+
+    #[test]
+    fn even_split() {
+        let diversity = tag_entropy(&log(&["a b", "c", "d"]), 0..u64::MAX);
+        // a and b each get half as much time as c and d
+        assert_eq!(diversity.tags, 4);
+        let expected = -(2.0 * (1.0 / 6.0) * (1.0f64 / 6.0).log2()
+            + 2.0 * (1.0 / 3.0) * (1.0f64 / 3.0).log2());
+        assert!((diversity.entropy - expected).abs() < 1e-12);
+
+        let diversity = tag_entropy(&log(&["a", "b", "c", "d"]), 0..u64::MAX);
+        assert_eq!(diversity.entropy, 2.0);
+        assert_eq!(diversity.evenness, 1.0);
+        assert_eq!(diversity.effective_tags, 4.0);
+    }
+
+    #[test]
+    fn empty_range() {
+        let diversity = tag_entropy(&log(&["a", "b"]), 0..10);
+        assert_eq!(diversity.tags, 0);
+        assert_eq!(diversity.entropy, 0.0);
+    }
+
+    #[test]
+    fn weekly_series() {
+        // first week has 7 days of "a", second has 7 different tags
+        let tags: Vec<String> = (0..14)
+            .map(|i| {
+                if i < 7 {
+                    "a".to_string()
+                } else {
+                    i.to_string()
+                }
+            })
+            .collect();
+        let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+        let series = diversity_series(&log(&tags), Bucket::Week, &Utc);
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].1.entropy, 0.0);
+        assert!((series[1].1.effective_tags - 7.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,202 @@
+use std::ops::Range;
+
+use crate::bool::{CommentMatcher, Expr};
+use crate::log::{matches_each, PingLog};
+
+/// What to do with a ping that matches more than one group, for [`group_by`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlap {
+    /// The ping counts fully towards every group it matches, so groups can add up to more than
+    /// the time spent.
+    CountBoth,
+    /// The ping counts towards the first group it matches.
+    FirstWins,
+    /// The ping's time is split evenly between the groups it matches.
+    Proportional,
+}
+
+/// The time in one of the groups of [`group_by`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupTime {
+    pub name: String,
+    /// Pings counted towards the group. With [`Overlap::Proportional`] this counts the part of
+    /// each ping that went to the group.
+    pub pings: f64,
+    pub hours: f64,
+}
+
+/// Time spent on each of a set of groups, from [`group_by`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Groups {
+    /// Each group, in the order they were given.
+    pub groups: Vec<GroupTime>,
+    /// Hours of pings matching more than one group, counted once, so charts can say how much was
+    /// double-counted or split.
+    pub overlap_hours: f64,
+    /// Hours of pings matching none of the groups.
+    pub ungrouped_hours: f64,
+}
+
+/// Hours in `range` spent on each of the named `groups`, with pings matching more than one of them
+/// counted as `overlap` says. Pings split by weighted tags are grouped a part at a time, so each
+/// group gets the time [`estimate`](super::estimate) would give it with [`Overlap::CountBoth`].
+///
+/// ```
+/// use taglogic::bool::Expr;
+/// use taglogic::log::{Ping, PingLog};
+/// use taglogic::stats::{group_by, Overlap};
+///
+/// let ping = |time, tags: &str| {
+///     Ping::new(time, tags.split(' ').map(String::from).collect(), 3600)
+/// };
+/// let log = PingLog::from_pings(vec![ping(0, "work"), ping(1, "work call"), ping(2, "call")]);
+/// let groups = [
+///     ("work".to_string(), Expr::parse("work").unwrap()),
+///     ("calls".to_string(), Expr::parse("call").unwrap()),
+/// ];
+/// let hours = |overlap| -> Vec<f64> {
+///     let grouped = group_by(&log, &groups, 0..10, overlap);
+///     grouped.groups.iter().map(|group| group.hours).collect()
+/// };
+/// assert_eq!(hours(Overlap::CountBoth), [2.0, 2.0]);
+/// assert_eq!(hours(Overlap::FirstWins), [2.0, 1.0]);
+/// assert_eq!(hours(Overlap::Proportional), [1.5, 1.5]);
+/// ```
+pub fn group_by(
+    log: &PingLog,
+    groups: &[(String, Expr)],
+    range: Range<u64>,
+    overlap: Overlap,
+) -> Groups {
+    let pings = log.range(range.start, range.end);
+    let matches: Vec<Vec<bool>> = (groups.iter())
+        .map(|(_, expr)| matches_each(&pings, expr))
+        .collect();
+    let matchers: Vec<CommentMatcher<'_>> = (groups.iter())
+        .map(|(_, expr)| CommentMatcher::new(expr))
+        .collect();
+    let mut times: Vec<GroupTime> = (groups.iter())
+        .map(|(name, _)| GroupTime {
+            name: name.clone(),
+            pings: 0.0,
+            hours: 0.0,
+        })
+        .collect();
+    let mut overlap_hours = 0.0;
+    let mut ungrouped_hours = 0.0;
+    for (index, ping) in pings.iter().enumerate() {
+        let parts: Vec<(Vec<usize>, f64)> = if ping.weights.is_empty() {
+            let matched = (0..groups.len()).filter(|&group| matches[group][index]);
+            vec![(matched.collect(), 1.0)]
+        } else {
+            (ping.parts().iter())
+                .map(|(tags, fraction)| {
+                    let matched = (0..groups.len())
+                        .filter(|&group| ping.part_matches(&matchers[group], tags));
+                    (matched.collect(), *fraction)
+                })
+                .collect()
+        };
+        for (matched, fraction) in parts {
+            let hours = f64::from(ping.interval) / 3600.0 * fraction;
+            match matched.len() {
+                0 => ungrouped_hours += hours,
+                1 => {}
+                _ => overlap_hours += hours,
+            }
+            let (counted, share) = match overlap {
+                Overlap::CountBoth => (&matched[..], 1.0),
+                Overlap::FirstWins => (&matched[..matched.len().min(1)], 1.0),
+                Overlap::Proportional => (&matched[..], 1.0 / matched.len().max(1) as f64),
+            };
+            for &group in counted {
+                times[group].pings += share * fraction;
+                times[group].hours += hours * share;
+            }
+        }
+    }
+    Groups {
+        groups: times,
+        overlap_hours,
+        ungrouped_hours,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use crate::stats::estimate;
+
+    fn ping(time: u64, tags: &str) -> Ping {
+        Ping::new(time, tags.split(' ').map(String::from).collect(), 1800)
+    }
+
+    fn groups() -> Vec<(String, Expr)> {
+        ["code", "meeting", "work"]
+            .iter()
+            .map(|&name| (name.to_string(), Expr::parse(name).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn counts_overlaps_by_policy() {
+        let log = PingLog::from_pings(vec![
+            ping(0, "work code"),
+            ping(1, "work meeting code"),
+            ping(2, "meeting"),
+            ping(3, "sleep"),
+            ping(4, "work"),
+        ]);
+        let hours = |overlap| -> Vec<(f64, f64)> {
+            let grouped = group_by(&log, &groups(), 0..10, overlap);
+            assert_eq!(grouped.overlap_hours, 1.0);
+            assert_eq!(grouped.ungrouped_hours, 0.5);
+            (grouped.groups.iter())
+                .map(|group| (group.pings, group.hours))
+                .collect()
+        };
+        assert_eq!(
+            hours(Overlap::CountBoth),
+            [(2.0, 1.0), (2.0, 1.0), (3.0, 1.5)]
+        );
+        assert_eq!(
+            hours(Overlap::FirstWins),
+            [(2.0, 1.0), (1.0, 0.5), (1.0, 0.5)]
+        );
+        let proportional = hours(Overlap::Proportional);
+        let total: f64 = proportional.iter().map(|&(_, hours)| hours).sum();
+        // every grouped ping is counted exactly once
+        assert!((total - 2.0).abs() < 1e-9);
+        assert!((proportional[0].0 - (0.5 + 1.0 / 3.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn empty_groups() {
+        let log = PingLog::from_pings(vec![ping(0, "work")]);
+        let grouped = group_by(&log, &[], 0..10, Overlap::FirstWins);
+        assert!(grouped.groups.is_empty());
+        assert_eq!(grouped.ungrouped_hours, 0.5);
+        let grouped = group_by(&log, &groups(), 10..20, Overlap::CountBoth);
+        assert_eq!(grouped.groups[2].hours, 0.0);
+    }
+
+    #[test]
+    fn weighted_tags_agree_with_estimate() {
+        let log = PingLog::from_pings(vec![
+            Ping::from_entry(0, "work:0.75 code:0.25", 3600),
+            Ping::from_entry(1, "code", 3600),
+        ]);
+        let groups: Vec<(String, Expr)> = ["work", "code"]
+            .iter()
+            .map(|&name| (name.to_string(), Expr::parse(name).unwrap()))
+            .collect();
+        let grouped = group_by(&log, &groups, 0..10, Overlap::CountBoth);
+        for ((_, expr), group) in groups.iter().zip(&grouped.groups) {
+            assert_eq!(group.hours, estimate(&log, expr, 0..10).hours);
+        }
+        assert_eq!(grouped.groups[0].pings, 0.75);
+        // the weighted tags split the first ping, so none of it is in both groups
+        assert_eq!(grouped.overlap_hours, 0.0);
+    }
+}
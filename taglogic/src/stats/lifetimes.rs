@@ -0,0 +1,149 @@
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, HashMap};
+
+use super::{local_date, Bucket, Calendar};
+use crate::log::PingLog;
+
+/// When a tag was in use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagLifetime {
+    pub tag: String,
+    /// Time of the first ping with the tag.
+    pub first_seen: u64,
+    /// Time of the last ping with the tag.
+    pub last_seen: u64,
+    pub pings: u32,
+    /// First day of the month with the most pings with the tag. If several months tie, the
+    /// earliest one is used.
+    pub peak_month: NaiveDate,
+    /// Number of pings with the tag during the peak month.
+    pub peak_month_pings: u32,
+}
+
+impl TagLifetime {
+    /// Seconds between the first and last pings with the tag.
+    pub fn lifetime_secs(&self) -> u64 {
+        self.last_seen - self.first_seen
+    }
+}
+
+/// Summarizes when each tag in the log was used, with months in the time zone `tz`. The result is
+/// sorted by tag. Pings whose time can't be repersented in `tz` are left out.
+pub fn tag_lifetimes<Tz: Calendar>(log: &PingLog, tz: &Tz) -> Vec<TagLifetime> {
+    struct Running {
+        first_seen: u64,
+        last_seen: u64,
+        pings: u32,
+        months: BTreeMap<NaiveDate, u32>,
+    }
+
+    let mut tags: HashMap<&str, Running> = HashMap::new();
+    for ping in log.pings() {
+        let month = match local_date(ping.time, tz) {
+            Some(date) => Bucket::Month.start_of(date),
+            None => continue,
+        };
+        for tag in &ping.tags {
+            // pings are sorted, so the first ping seen is the earliest
+            let running = tags.entry(tag).or_insert_with(|| Running {
+                first_seen: ping.time,
+                last_seen: ping.time,
+                pings: 0,
+                months: BTreeMap::new(),
+            });
+            running.last_seen = ping.time;
+            running.pings += 1;
+            *running.months.entry(month).or_default() += 1;
+        }
+    }
+
+    let mut lifetimes: Vec<TagLifetime> = tags
+        .into_iter()
+        .map(|(tag, running)| {
+            let (peak_month, peak_month_pings) =
+                running
+                    .months
+                    .into_iter()
+                    .fold((NaiveDate::MIN, 0), |peak, (month, pings)| {
+                        if pings > peak.1 {
+                            (month, pings)
+                        } else {
+                            peak
+                        }
+                    });
+            TagLifetime {
+                tag: tag.to_string(),
+                first_seen: running.first_seen,
+                last_seen: running.last_seen,
+                pings: running.pings,
+                peak_month,
+                peak_month_pings,
+            }
+        })
+        .collect();
+    lifetimes.sort_by(|a, b| a.tag.cmp(&b.tag));
+    lifetimes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use chrono::Utc;
+
+    // 2021-01-01 00:00:00 UTC
+    const JAN: u64 = 1609459200;
+    // 2021-02-01 00:00:00 UTC
+    const FEB: u64 = 1612137600;
+
+    fn ping(time: u64, tags: &str) -> Ping {
+        Ping::new(time, tags.split(' ').map(String::from).collect(), 2700)
+    }
+
+    #[test]
+    fn summarizes_each_tag() {
+        let log = PingLog::from_pings(vec![
+            ping(JAN, "work"),
+            ping(JAN + 100, "work email"),
+            ping(FEB, "email"),
+            ping(FEB + 100, "email"),
+            ping(FEB + 200, "work"),
+        ]);
+        let date = |m| NaiveDate::from_ymd_opt(2021, m, 1).unwrap();
+        assert_eq!(
+            tag_lifetimes(&log, &Utc),
+            vec![
+                TagLifetime {
+                    tag: "email".to_string(),
+                    first_seen: JAN + 100,
+                    last_seen: FEB + 100,
+                    pings: 3,
+                    peak_month: date(2),
+                    peak_month_pings: 2,
+                },
+                TagLifetime {
+                    tag: "work".to_string(),
+                    first_seen: JAN,
+                    last_seen: FEB + 200,
+                    pings: 3,
+                    peak_month: date(1),
+                    peak_month_pings: 2,
+                },
+            ]
+        );
+        assert_eq!(
+            tag_lifetimes(&log, &Utc)[1].lifetime_secs(),
+            FEB + 200 - JAN
+        );
+    }
+
+    #[test]
+    fn ties_use_earliest_month() {
+        let log = PingLog::from_pings(vec![ping(FEB, "a"), ping(JAN, "a")]);
+        let lifetimes = tag_lifetimes(&log, &Utc);
+        assert_eq!(
+            lifetimes[0].peak_month,
+            NaiveDate::from_ymd_opt(2021, 1, 1).unwrap()
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use std::ops::Range;
+
+use super::breakdown::{breakdown, Children, Share};
+use crate::bool::Expr;
+use crate::log::PingLog;
+
+/// The fewest children that together cover a target fraction of a parent filter's time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pareto {
+    /// Hours spent on the parent filter.
+    pub total_hours: f64,
+    /// The covering children, from largest to smallest.
+    pub shares: Vec<Share>,
+    /// Fraction of the parent filter's time covered by `shares`. This is at least the target,
+    /// unless all the children together don't reach it.
+    pub covered: f64,
+    /// How many children had any time at all.
+    pub ranked: usize,
+}
+
+/// Ranks `children` by the time they took up of `parent` in `range`, and finds the shortest
+/// prefix covering at least `target` (e.g. 0.8) of the parent's time.
+///
+/// ## Panics
+/// Panics if `target` isn't between 0 and 1.
+pub fn pareto(
+    log: &PingLog,
+    parent: &Expr,
+    children: Children,
+    range: Range<u64>,
+    target: f64,
+) -> Pareto {
+    assert!(
+        (0.0..=1.0).contains(&target),
+        "target must be between 0 and 1"
+    );
+    let breakdown = breakdown(log, parent, children, range, None);
+    let ranked = breakdown.shares.len();
+    let mut covered = 0.0;
+    let mut shares = vec![];
+    for share in breakdown.shares {
+        // a little slack, so rounding doesn't add an extra child when the target is hit exactly
+        if covered >= target - 1e-12 {
+            break;
+        }
+        covered += share.fraction;
+        shares.push(share);
+    }
+    Pareto {
+        total_hours: breakdown.total_hours,
+        shares,
+        covered,
+        ranked,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+
+    fn log() -> PingLog {
+        // 10 hours: 5 of a, 3 of b, 1 of c, 1 uncategorized
+        let pings = ["a", "a", "a", "a", "a", "b", "b", "b", "c", ""];
+        PingLog::from_pings(
+            pings
+                .iter()
+                .enumerate()
+                .map(|(i, tags)| {
+                    let tags = tags.split(' ').filter(|t| !t.is_empty());
+                    Ping::new(i as u64, tags.map(String::from).collect(), 3600)
+                })
+                .collect(),
+        )
+    }
+
+    fn names(pareto: &Pareto) -> Vec<&str> {
+        pareto.shares.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn covers_target() {
+        let everything = Expr::from_string("!nothing").unwrap();
+        let result = pareto(&log(), &everything, Children::Tags, 0..100, 0.8);
+        assert_eq!(result.total_hours, 10.0);
+        assert_eq!(names(&result), vec!["a", "b"]);
+        assert!((result.covered - 0.8).abs() < 1e-12);
+        assert_eq!(result.ranked, 3);
+
+        let result = pareto(&log(), &everything, Children::Tags, 0..100, 0.81);
+        assert_eq!(names(&result), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn unreachable_target() {
+        let everything = Expr::from_string("!nothing").unwrap();
+        let result = pareto(&log(), &everything, Children::Tags, 0..100, 1.0);
+        assert_eq!(result.shares.len(), 3);
+        assert!((result.covered - 0.9).abs() < 1e-12);
+    }
+
+    #[test]
+    fn with_exprs() {
+        let everything = Expr::from_string("!nothing").unwrap();
+        let children = vec![
+            ("ab".to_string(), Expr::from_string("a | b").unwrap()),
+            ("c".to_string(), Expr::from_string("c").unwrap()),
+        ];
+        let result = pareto(&log(), &everything, Children::Exprs(&children), 0..100, 0.5);
+        assert_eq!(names(&result), vec!["ab"]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn bad_target() {
+        let everything = Expr::from_string("!nothing").unwrap();
+        pareto(&log(), &everything, Children::Tags, 0..100, 1.5);
+    }
+}
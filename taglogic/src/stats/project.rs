@@ -0,0 +1,242 @@
+use chrono::{Datelike, Duration};
+
+use super::daily::daily_tallies;
+use super::{bucket_bounds, day_start, local_date, Bucket, Calendar, Tally, Z_95};
+use crate::bool::Expr;
+use crate::log::{match_shares, PingLog};
+
+/// How many weeks before the current period are used to learn the typical time per weekday.
+const HISTORY_WEEKS: i64 = 8;
+
+/// How to predict the time spent during the rest of a period.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProjectionModel {
+    /// Assume the rest of the period continues at the pace so far.
+    NaivePace,
+    /// Assume each remaining day will be like the average of the same weekday in the previous
+    /// weeks, so a goal isn't projected to fail just because the weekend came first.
+    WeekdayWeighted,
+}
+
+/// Projected hours by the end of a period, with a 95% interval.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Projection {
+    pub hours: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Projection {
+    fn new(hours: f64, variance: f64) -> Self {
+        let spread = Z_95 * variance.sqrt();
+        Self {
+            hours,
+            low: (hours - spread).max(0.0),
+            high: hours + spread,
+        }
+    }
+}
+
+/// Projects how many hours will be spent on `expr` by the end of the period containing `now`, in
+/// the time zone `tz`. Returns None if the period can't be repersented, or if the model has
+/// nothing to go on (no time elapsed in the period for [`ProjectionModel::NaivePace`], no pings
+/// in the previous weeks for [`ProjectionModel::WeekdayWeighted`]).
+pub fn project<Tz: Calendar>(
+    log: &PingLog,
+    expr: &Expr,
+    period: Bucket,
+    now: u64,
+    tz: &Tz,
+    model: ProjectionModel,
+) -> Option<Projection> {
+    let (start_date, start, end) = bucket_bounds(period, now, tz)?;
+    let pings = log.range(start, now.saturating_add(1));
+    let mut done = Tally::default();
+    for (ping, share) in pings.iter().zip(match_shares(&pings, expr)) {
+        done.add_share(ping.interval, share);
+    }
+    let hours_done = done.estimate().hours;
+
+    match model {
+        ProjectionModel::NaivePace => {
+            let elapsed = now.checked_sub(start).filter(|secs| *secs > 0)? as f64;
+            let left = end.saturating_sub(now) as f64;
+            let scale = left / elapsed;
+            // uncertainty in the pace so far, plus the randomness of the pings still to come
+            let variance = done.variance() * (scale * scale + scale);
+            Some(Projection::new(hours_done * (1.0 + scale), variance))
+        }
+        ProjectionModel::WeekdayWeighted => {
+            let history_start = start_date - Duration::weeks(HISTORY_WEEKS);
+            let history_end = start_date.pred_opt()?;
+            if log.range(day_start(history_start, tz)?, start).is_empty() {
+                return None;
+            }
+            // sum and sum of squares of hours, for each weekday starting at Monday
+            let mut sums = [(0.0, 0.0, 0u32); 7];
+            for (date, tally) in daily_tallies(log, expr, history_start..=history_end, tz) {
+                let hours = tally.estimate().hours;
+                let sum = &mut sums[date.weekday().num_days_from_monday() as usize];
+                sum.0 += hours;
+                sum.1 += hours * hours;
+                sum.2 += 1;
+            }
+            let weekday_stats = |date: chrono::NaiveDate| {
+                let (sum, sum_sq, n) = sums[date.weekday().num_days_from_monday() as usize];
+                let n = f64::from(n);
+                let mean = sum / n;
+                (mean, (sum_sq / n - mean * mean).max(0.0))
+            };
+
+            let today = local_date(now, tz)?;
+            let tomorrow = today.succ_opt()?;
+            let today_start = day_start(today, tz)?;
+            let today_end = day_start(tomorrow, tz)?;
+            let today_left =
+                today_end.saturating_sub(now) as f64 / (today_end - today_start) as f64;
+            let (mean, var) = weekday_stats(today);
+            let mut hours = hours_done + today_left * mean;
+            let mut variance = done.variance() + today_left * today_left * var;
+            let mut date = tomorrow;
+            while day_start(date, tz)? < end {
+                let (mean, var) = weekday_stats(date);
+                hours += mean;
+                variance += var;
+                date = date.succ_opt()?;
+            }
+            Some(Projection::new(hours, variance))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use crate::stats::estimate;
+    use chrono::Utc;
+
+    const DAY: u64 = 86400;
+    // 2021-03-01 00:00:00 UTC, a Monday
+    const MONDAY: u64 = 1614556800;
+
+    /// Adds `hours` one-hour "work" pings starting at the given time.
+    fn work(pings: &mut Vec<Ping>, time: u64, hours: u64) {
+        for i in 0..hours {
+            pings.push(Ping::new(time + i * 60, vec!["work".to_string()], 3600));
+        }
+    }
+
+    #[test]
+    fn naive_pace() {
+        let mut pings = vec![];
+        work(&mut pings, MONDAY, 4);
+        work(&mut pings, MONDAY + DAY, 4);
+        let log = PingLog::from_pings(pings);
+        let expr = Expr::from_string("work").unwrap();
+        let now = MONDAY + 2 * DAY;
+        let projection = project(
+            &log,
+            &expr,
+            Bucket::Week,
+            now,
+            &Utc,
+            ProjectionModel::NaivePace,
+        )
+        .unwrap();
+        assert_eq!(projection.hours, 28.0);
+        assert!(projection.low < 28.0 && projection.high > 28.0);
+    }
+
+    #[test]
+    fn naive_pace_needs_elapsed_time() {
+        let log = PingLog::new();
+        let expr = Expr::from_string("work").unwrap();
+        let projection = project(
+            &log,
+            &expr,
+            Bucket::Week,
+            MONDAY,
+            &Utc,
+            ProjectionModel::NaivePace,
+        );
+        assert_eq!(projection, None);
+    }
+
+    #[test]
+    fn weekday_weighted() {
+        // 8 hours on weekdays, nothing on weekends, for the previous weeks
+        let mut pings = vec![];
+        for week in 1..=(HISTORY_WEEKS as u64) {
+            for day in 0..5 {
+                work(&mut pings, MONDAY - week * 7 * DAY + day * DAY, 8);
+            }
+        }
+        // this week: 8 hours on Monday
+        work(&mut pings, MONDAY, 8);
+        let log = PingLog::from_pings(pings);
+        let expr = Expr::from_string("work").unwrap();
+        let now = MONDAY + DAY;
+        let weighted = project(
+            &log,
+            &expr,
+            Bucket::Week,
+            now,
+            &Utc,
+            ProjectionModel::WeekdayWeighted,
+        )
+        .unwrap();
+        // Monday plus four more workdays
+        assert!((weighted.hours - 40.0).abs() < 1e-9);
+        // every week was the same, so the only uncertainty is from Monday's pings
+        assert!(weighted.high - weighted.low < 12.0);
+
+        let naive = project(
+            &log,
+            &expr,
+            Bucket::Week,
+            now,
+            &Utc,
+            ProjectionModel::NaivePace,
+        )
+        .unwrap();
+        assert_eq!(naive.hours, 56.0);
+    }
+
+    #[test]
+    fn weekday_weighted_needs_history() {
+        let mut pings = vec![];
+        work(&mut pings, MONDAY, 8);
+        let log = PingLog::from_pings(pings);
+        let expr = Expr::from_string("work").unwrap();
+        let projection = project(
+            &log,
+            &expr,
+            Bucket::Week,
+            MONDAY + DAY,
+            &Utc,
+            ProjectionModel::WeekdayWeighted,
+        );
+        assert_eq!(projection, None);
+    }
+
+    #[test]
+    fn weighted_tags_agree_with_estimate() {
+        let log = PingLog::from_pings(vec![Ping::from_entry(MONDAY, "work:0.5 play:0.5", 3600)]);
+        let expr = Expr::from_string("work").unwrap();
+        let now = MONDAY + DAY;
+        let projection = project(
+            &log,
+            &expr,
+            Bucket::Week,
+            now,
+            &Utc,
+            ProjectionModel::NaivePace,
+        )
+        .unwrap();
+        let done = estimate(&log, &expr, MONDAY..now).hours;
+        assert_eq!(done, 0.5);
+        // a day in, at the same pace for the other six
+        assert_eq!(projection.hours, done * 7.0);
+    }
+}
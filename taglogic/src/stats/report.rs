@@ -0,0 +1,261 @@
+use chrono::NaiveDate;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use super::diversity::Diversity;
+use super::response::AnswerDelays;
+use super::sessions::sessions_from;
+use super::trend::series_from_tallies;
+use super::{local_date, Bucket, Calendar, Tally, TimeEstimate};
+use crate::bool::{Expr, LogStats};
+use crate::log::{match_shares, PingLog};
+
+/// Version of the JSON document produced by [`report`]. Bump this when changing its shape in a
+/// way that isn't backwards compatible.
+pub const REPORT_VERSION: u32 = 1;
+
+/// A metric to include in a report.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Metric {
+    /// Total estimated time, under `"total"`.
+    Total,
+    /// Estimated time per bucket, under `"series"` and then the bucket name.
+    Series(Bucket),
+    /// Sessions of consecutive matching pings, under `"sessions"`.
+    Sessions { max_gap: u64 },
+    /// Distribution of answer delays, under `"answer_delays"`.
+    AnswerDelays,
+    /// Diversity of all tags (not just matching ones), under `"diversity"`.
+    Diversity,
+    /// A [`LogStats`] summary of all pings (not just matching ones), under `"summary"`.
+    Summary,
+}
+
+/// What to compute in a report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportSpec<Tz: Calendar> {
+    pub expr: Expr,
+    /// Only pings in this range are used.
+    pub range: Range<u64>,
+    /// Time zone used for bucketing.
+    pub tz: Tz,
+    pub metrics: Vec<Metric>,
+}
+
+/// Computes every metric in `spec` with one pass over the log, and returns them as a JSON object
+/// for the dashboard. The object always has `"version"`, `"range"` and `"pings"` keys, and one key
+/// for each kind of metric requested.
+pub fn report<Tz: Calendar>(log: &PingLog, spec: &ReportSpec<Tz>) -> Value {
+    let pings = log.range(spec.range.start, spec.range.end);
+    let mut total = Tally::default();
+    let mut daily: BTreeMap<NaiveDate, Tally> = BTreeMap::new();
+    let mut summary = spec
+        .metrics
+        .contains(&Metric::Summary)
+        .then(LogStats::default);
+    let shares = match_shares(&pings, &spec.expr);
+    for (ping, &share) in pings.iter().zip(&shares) {
+        total.add_share(ping.interval, share);
+        if let Some(summary) = &mut summary {
+            summary.add_ping(ping.time, ping.interval, ping.tags.iter(), ping.author);
+        }
+        if let Some(date) = local_date(ping.time, &spec.tz) {
+            daily
+                .entry(date)
+                .or_default()
+                .add_share(ping.interval, share);
+        }
+    }
+
+    let mut out = json!({
+        "version": REPORT_VERSION,
+        "range": { "start": spec.range.start, "end": spec.range.end },
+        "pings": pings.len(),
+    });
+    for metric in &spec.metrics {
+        match metric {
+            Metric::Total => out["total"] = estimate_json(&total.estimate()),
+            Metric::Series(bucket) => {
+                let series = series_from_tallies(*bucket, spec.tz.week_start(), &daily);
+                let points: Vec<Value> = series
+                    .points
+                    .iter()
+                    .map(|point| {
+                        let mut value = estimate_json(&point.estimate);
+                        value["start"] = json!(point.start.to_string());
+                        value
+                    })
+                    .collect();
+                out["series"][bucket_name(*bucket)] = Value::Array(points);
+            }
+            Metric::Sessions { max_gap } => {
+                let sessions = sessions_from(
+                    pings.iter().zip(shares.iter().map(|&share| share > 0.0)),
+                    *max_gap,
+                );
+                out["sessions"] = sessions
+                    .iter()
+                    .map(|session| {
+                        json!({
+                            "start": session.start,
+                            "end": session.end,
+                            "pings": session.pings,
+                            "estimated_secs": session.estimated_secs,
+                        })
+                    })
+                    .collect();
+            }
+            Metric::AnswerDelays => {
+                let delays = AnswerDelays::from_pings(pings.iter());
+                out["answer_delays"] = json!({
+                    "count": delays.delays().len(),
+                    "mean": delays.mean(),
+                    "median": delays.quantile(0.5),
+                    "p90": delays.quantile(0.9),
+                });
+            }
+            Metric::Diversity => {
+                let diversity = Diversity::from_pings(pings.iter());
+                out["diversity"] = json!({
+                    "tags": diversity.tags,
+                    "entropy": diversity.entropy,
+                    "evenness": diversity.evenness,
+                    "effective_tags": diversity.effective_tags,
+                });
+            }
+            Metric::Summary => {
+                if let Some(summary) = &summary {
+                    let tag_pings: BTreeMap<&str, u64> = (summary.tags())
+                        .map(|tag| (tag, summary.tag_pings(tag)))
+                        .collect();
+                    let author_pings: BTreeMap<&str, u64> = (summary.authors())
+                        .map(|author| (author, summary.author_pings(author)))
+                        .collect();
+                    out["summary"] = json!({
+                        "tags": tag_pings,
+                        "authors": author_pings,
+                        "first": summary.span.map(|(first, _)| first),
+                        "last": summary.span.map(|(_, last)| last),
+                        "pings_per_day": summary.density(),
+                        "answer_rate": summary.answer_rate(),
+                    });
+                }
+            }
+        }
+    }
+    out
+}
+
+fn estimate_json(estimate: &TimeEstimate) -> Value {
+    json!({
+        "pings": estimate.pings,
+        "hours": estimate.hours,
+        "low": estimate.low,
+        "high": estimate.high,
+    })
+}
+
+pub(crate) fn bucket_name(bucket: Bucket) -> &'static str {
+    match bucket {
+        Bucket::Day => "day",
+        Bucket::Week => "week",
+        Bucket::Month => "month",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use crate::stats::time_series;
+    use chrono::Utc;
+
+    // 2021-01-04 00:00:00 UTC, a Monday
+    const MONDAY: u64 = 1609718400;
+
+    fn log() -> PingLog {
+        let pings = ["work", "work", "play", "work email", "sleep"];
+        PingLog::from_pings(
+            pings
+                .iter()
+                .enumerate()
+                .map(|(i, tags)| {
+                    let time = MONDAY + i as u64 * 40000;
+                    let mut ping =
+                        Ping::new(time, tags.split(' ').map(String::from).collect(), 3600);
+                    ping.answered = Some(time + 60 * (i as u64 + 1));
+                    ping
+                })
+                .collect(),
+        )
+    }
+
+    fn spec(metrics: Vec<Metric>) -> ReportSpec<Utc> {
+        ReportSpec {
+            expr: Expr::from_string("work").unwrap(),
+            range: 0..u64::MAX,
+            tz: Utc,
+            metrics,
+        }
+    }
+
+    #[test]
+    fn always_has_header() {
+        let value = report(&log(), &spec(vec![]));
+        assert_eq!(
+            value,
+            json!({
+                "version": 1,
+                "range": { "start": 0, "end": u64::MAX },
+                "pings": 5,
+            })
+        );
+    }
+
+    #[test]
+    fn all_metrics() {
+        let value = report(
+            &log(),
+            &spec(vec![
+                Metric::Total,
+                Metric::Series(Bucket::Day),
+                Metric::Series(Bucket::Week),
+                Metric::Sessions { max_gap: 50000 },
+                Metric::AnswerDelays,
+                Metric::Diversity,
+                Metric::Summary,
+            ]),
+        );
+        assert_eq!(value["total"]["pings"], 3);
+        assert_eq!(value["total"]["hours"], 3.0);
+        assert_eq!(value["series"]["day"].as_array().unwrap().len(), 2);
+        assert_eq!(value["series"]["day"][0]["start"], "2021-01-04");
+        assert_eq!(value["series"]["week"][0]["hours"], 3.0);
+        assert_eq!(value["sessions"].as_array().unwrap().len(), 2);
+        assert_eq!(value["sessions"][0]["pings"], 2);
+        assert_eq!(value["answer_delays"]["count"], 5);
+        assert_eq!(value["answer_delays"]["median"], 180);
+        assert_eq!(value["diversity"]["tags"], 4);
+        assert_eq!(value["summary"]["tags"].as_object().unwrap().len(), 4);
+        assert_eq!(
+            value["summary"]["first"],
+            log().pings().first().unwrap().time
+        );
+    }
+
+    #[test]
+    fn series_matches_time_series() {
+        let log = log();
+        let value = report(&log, &spec(vec![Metric::Series(Bucket::Day)]));
+        let series = time_series(&log, &spec(vec![]).expr, Bucket::Day, &Utc);
+        for (point, json) in series
+            .points
+            .iter()
+            .zip(value["series"]["day"].as_array().unwrap())
+        {
+            assert_eq!(json["hours"], point.estimate.hours);
+            assert_eq!(json["high"], point.estimate.high);
+        }
+    }
+}
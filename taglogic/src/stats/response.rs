@@ -0,0 +1,197 @@
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+
+use super::{local_date, Bucket, Calendar};
+use crate::log::{PingLog, PingRef};
+use crate::{pings_between, should_ping_at_time, PingIntervalData};
+
+/// How many of the scheduled pings in a bucket were answered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ResponseRate {
+    /// The first day of the bucket.
+    pub start: NaiveDate,
+    pub scheduled: u32,
+    pub answered: u32,
+}
+
+impl ResponseRate {
+    /// Fraction of scheduled pings that were answered, or None if no pings were scheduled.
+    pub fn fraction(&self) -> Option<f64> {
+        if self.scheduled == 0 {
+            None
+        } else {
+            Some(f64::from(self.answered) / f64::from(self.scheduled))
+        }
+    }
+}
+
+/// Response rates for pings scheduled in `start..end`, per bucket, in the time zone `tz`. A ping
+/// counts as answered if the log has a ping at exactly the scheduled time, so pings in the log
+/// that aren't on the schedule are ignored. Buckets with no scheduled pings are left out.
+pub fn response_rates<Tz: Calendar>(
+    log: &PingLog,
+    schedule: &PingIntervalData,
+    start: u64,
+    end: u64,
+    bucket: Bucket,
+    tz: &Tz,
+) -> Vec<ResponseRate> {
+    if start >= end {
+        return vec![];
+    }
+    let logged = log.range(start, end).times();
+    let mut logged_index = 0;
+    let mut rates: BTreeMap<NaiveDate, ResponseRate> = BTreeMap::new();
+    // pings_between needs a range of at least two seconds
+    let scheduled = if end - start > 1 {
+        pings_between(start, end - 1, schedule)
+    } else if should_ping_at_time(start, schedule) {
+        vec![start]
+    } else {
+        vec![]
+    };
+    for time in scheduled {
+        let date = match local_date(time, tz) {
+            Some(date) => bucket.start_with(date, tz.week_start()),
+            None => continue,
+        };
+        // both lists are sorted, so walk through them together
+        while logged_index < logged.len() && logged[logged_index] < time {
+            logged_index += 1;
+        }
+        let answered = logged_index < logged.len() && logged[logged_index] == time;
+        let rate = rates.entry(date).or_insert(ResponseRate {
+            start: date,
+            scheduled: 0,
+            answered: 0,
+        });
+        rate.scheduled += 1;
+        if answered {
+            rate.answered += 1;
+        }
+    }
+    rates.into_values().collect()
+}
+
+/// Distribution of how long it took to answer pings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnswerDelays {
+    /// Delays in seconds, sorted from shortest to longest.
+    delays: Vec<u64>,
+}
+
+impl AnswerDelays {
+    pub(super) fn from_pings<'a>(pings: impl Iterator<Item = PingRef<'a>>) -> Self {
+        let mut delays: Vec<u64> = pings
+            .filter_map(|ping| Some(ping.answered?.saturating_sub(ping.time)))
+            .collect();
+        delays.sort_unstable();
+        Self { delays }
+    }
+
+    /// Delays in seconds, sorted from shortest to longest.
+    pub fn delays(&self) -> &[u64] {
+        &self.delays
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        if self.delays.is_empty() {
+            return None;
+        }
+        Some(self.delays.iter().sum::<u64>() as f64 / self.delays.len() as f64)
+    }
+
+    /// The delay that a fraction `q` of answers were at least as fast as, using the nearest-rank
+    /// method. `quantile(0.5)` is the median.
+    ///
+    /// ## Panics
+    /// Panics if `q` is not between 0 and 1.
+    pub fn quantile(&self, q: f64) -> Option<u64> {
+        assert!((0.0..=1.0).contains(&q), "quantile must be between 0 and 1");
+        if self.delays.is_empty() {
+            return None;
+        }
+        let rank = (q * self.delays.len() as f64).ceil() as usize;
+        Some(self.delays[rank.max(1) - 1])
+    }
+}
+
+/// Delays between pings in `start..end` being sent and being answered. Pings without a recorded
+/// answer time are skipped.
+pub fn answer_delays(log: &PingLog, start: u64, end: u64) -> AnswerDelays {
+    AnswerDelays::from_pings(log.range(start, end).iter())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use crate::tt::UNIV_SCHED;
+    use chrono::Utc;
+
+    // pings from the universal schedule, see the tagtime_alg tests
+    const PINGS: [u64; 4] = [1533748817, 1533754341, 1533758980, 1533759940];
+
+    fn ping(time: u64, answered: Option<u64>) -> Ping {
+        let mut ping = Ping::new(time, vec!["a".to_string()], 2700);
+        ping.answered = answered;
+        ping
+    }
+
+    #[test]
+    fn counts_answered_pings() {
+        let log = PingLog::from_pings(vec![
+            ping(PINGS[0], None),
+            ping(PINGS[2], None),
+            // not on the schedule
+            ping(PINGS[2] + 1, None),
+        ]);
+        let rates = response_rates(
+            &log,
+            &UNIV_SCHED,
+            PINGS[0],
+            PINGS[3] + 1,
+            Bucket::Week,
+            &Utc,
+        );
+        assert_eq!(
+            rates,
+            vec![ResponseRate {
+                start: NaiveDate::from_ymd_opt(2018, 8, 6).unwrap(),
+                scheduled: 4,
+                answered: 2,
+            }]
+        );
+        assert_eq!(rates[0].fraction(), Some(0.5));
+    }
+
+    #[test]
+    fn empty_range() {
+        let log = PingLog::new();
+        assert!(response_rates(&log, &UNIV_SCHED, 10, 10, Bucket::Week, &Utc).is_empty());
+        let rate = ResponseRate {
+            start: NaiveDate::from_ymd_opt(2018, 8, 6).unwrap(),
+            scheduled: 0,
+            answered: 0,
+        };
+        assert_eq!(rate.fraction(), None);
+    }
+
+    #[test]
+    fn delay_distribution() {
+        let log = PingLog::from_pings(vec![
+            ping(100, Some(110)),
+            ping(200, Some(260)),
+            ping(300, None),
+            ping(400, Some(430)),
+            ping(500, Some(900)),
+        ]);
+        let delays = answer_delays(&log, 0, 1000);
+        assert_eq!(delays.delays(), &[10, 30, 60, 400]);
+        assert_eq!(delays.mean(), Some(125.0));
+        assert_eq!(delays.quantile(0.5), Some(30));
+        assert_eq!(delays.quantile(0.0), Some(10));
+        assert_eq!(delays.quantile(1.0), Some(400));
+        assert_eq!(answer_delays(&log, 0, 50).quantile(0.5), None);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::bool::Expr;
+use crate::log::{PingLog, PingRef};
+
+/// `n` pings picked at random from the pings in `log` matching `filter`, oldest first, for
+/// checking a sample of them by hand, like auditing 20 random `work` pings. Every set of `n`
+/// matching pings is as likely as any other, and picking is seeded with `seed` and doesn't use
+/// floats, so the same seed picks the same pings on every platform. If
+/// fewer than `n` pings match, all of them are returned.
+///
+/// ```
+/// use taglogic::bool::Expr;
+/// use taglogic::log::{Ping, PingLog};
+/// use taglogic::stats::sample_pings;
+///
+/// let pings = (0..100).map(|time| {
+///     let tag = if time % 2 == 0 { "work" } else { "play" };
+///     Ping::new(time, vec![tag.to_string()], 2700)
+/// });
+/// let log = PingLog::from_pings(pings.collect());
+/// let work = Expr::parse("work").unwrap();
+/// let sample = sample_pings(&log, &work, 20, 1);
+/// assert_eq!(sample.len(), 20);
+/// assert!(sample.iter().all(|ping| ping.tags.contains("work")));
+/// assert_eq!(sample, sample_pings(&log, &work, 20, 1));
+/// ```
+pub fn sample_pings<'a>(log: &'a PingLog, filter: &Expr, n: usize, seed: u64) -> Vec<PingRef<'a>> {
+    let mut matching: Vec<usize> = (log.matches_many(filter).into_iter().enumerate())
+        .filter_map(|(index, matched)| matched.then_some(index))
+        .collect();
+    let n = n.min(matching.len());
+
+    // the start of a Fisher-Yates shuffle, which leaves a uniform sample in the first n places
+    let mut state = seed;
+    for i in 0..n {
+        let remaining = (matching.len() - i) as u128;
+        let random = u128::from(splitmix64(&mut state));
+        matching.swap(i, i + ((random * remaining) >> 64) as usize);
+    }
+    matching.truncate(n);
+    matching.sort_unstable();
+    matching
+        .into_iter()
+        .filter_map(|index| log.get(index))
+        .collect()
+}
+
+/// The next number from the SplitMix64 generator, which is small and mixes similar seeds well.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+
+    fn log() -> PingLog {
+        let pings = (0..200).map(|time| {
+            let tag = if time % 4 == 0 { "work" } else { "play" };
+            Ping::new(time, vec![tag.to_string()], 2700)
+        });
+        PingLog::from_pings(pings.collect())
+    }
+
+    fn sample(log: &PingLog, filter: &str, n: usize, seed: u64) -> Vec<u64> {
+        let filter = Expr::parse(filter).unwrap();
+        let sample = sample_pings(log, &filter, n, seed);
+        sample.iter().map(|ping| ping.time).collect()
+    }
+
+    #[test]
+    fn samples_matching_pings() {
+        let log = log();
+        let times = sample(&log, "work", 20, 42);
+        assert_eq!(times.len(), 20);
+        assert!(times.iter().all(|time| time % 4 == 0));
+        assert!(times.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(times, sample(&log, "work", 20, 42));
+        assert_ne!(times, sample(&log, "work", 20, 43));
+
+        // an empty filter matches everything
+        assert_eq!(sample(&log, "", 200, 0).len(), 200);
+        assert_eq!(sample(&log, "work", 1000, 0).len(), 50);
+        assert!(sample(&log, "nothing", 5, 0).is_empty());
+        assert!(sample(&log, "work", 0, 0).is_empty());
+    }
+
+    #[test]
+    fn picks_pings_evenly() {
+        let log = log();
+        let mut picked = [0u32; 50];
+        for seed in 0..2000 {
+            for time in sample(&log, "work", 5, seed) {
+                picked[time as usize / 4] += 1;
+            }
+        }
+        // each ping is picked a tenth of the time
+        assert!(picked.iter().all(|&count| (150..=250).contains(&count)));
+    }
+}
@@ -0,0 +1,154 @@
+use crate::bool::Expr;
+use crate::log::{match_shares, PingLog, PingRef};
+
+/// A block of time spent continuously on something, detected from consecutive matching pings.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Session {
+    /// Time of the first ping in the session.
+    pub start: u64,
+    /// Time of the last ping in the session.
+    pub end: u64,
+    pub pings: u32,
+    /// Estimated length of the session in seconds. This is the time between the first and last
+    /// pings, plus the average interval of the session's pings, since the session probably started
+    /// a bit before the first ping and ended a bit after the last one. A session with a single ping
+    /// is estimated to be one interval long.
+    pub estimated_secs: u64,
+}
+
+/// Groups pings matching `expr` into sessions. Matching pings are in the same session if they are
+/// less than `max_gap` seconds apart and there are no non-matching pings between them. A ping
+/// split by weighted tags matches if any part of it does, like in [`estimate`](super::estimate).
+pub fn sessions(log: &PingLog, expr: &Expr, max_gap: u64) -> Vec<Session> {
+    let pings = log.pings();
+    let shares = match_shares(&pings, expr);
+    sessions_from(
+        pings
+            .iter()
+            .zip(shares.into_iter().map(|share| share > 0.0)),
+        max_gap,
+    )
+}
+
+/// Groups pings into sessions, given each ping and whether it matched.
+pub(super) fn sessions_from<'a>(
+    pings: impl Iterator<Item = (PingRef<'a>, bool)>,
+    max_gap: u64,
+) -> Vec<Session> {
+    struct Running {
+        start: u64,
+        end: u64,
+        pings: u32,
+        interval_secs: u64,
+    }
+
+    impl Running {
+        fn finish(self) -> Session {
+            let avg_interval = self.interval_secs / u64::from(self.pings);
+            Session {
+                start: self.start,
+                end: self.end,
+                pings: self.pings,
+                estimated_secs: self.end - self.start + avg_interval,
+            }
+        }
+    }
+
+    let mut sessions = vec![];
+    let mut running: Option<Running> = None;
+    for (ping, matched) in pings {
+        if !matched {
+            sessions.extend(running.take().map(Running::finish));
+            continue;
+        }
+        match &mut running {
+            Some(session) if ping.time - session.end < max_gap => {
+                session.end = ping.time;
+                session.pings += 1;
+                session.interval_secs += u64::from(ping.interval);
+            }
+            _ => {
+                sessions.extend(running.take().map(Running::finish));
+                running = Some(Running {
+                    start: ping.time,
+                    end: ping.time,
+                    pings: 1,
+                    interval_secs: u64::from(ping.interval),
+                });
+            }
+        }
+    }
+    sessions.extend(running.map(Running::finish));
+    sessions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use crate::stats::estimate;
+
+    fn ping(time: u64, tag: &str) -> Ping {
+        Ping::new(time, vec![tag.to_string()], 600)
+    }
+
+    #[test]
+    fn splits_on_gaps_and_other_pings() {
+        let log = PingLog::from_pings(vec![
+            ping(0, "work"),
+            ping(500, "work"),
+            ping(1000, "work"),
+            // too far from the last one
+            ping(5000, "work"),
+            ping(5100, "lunch"),
+            ping(5200, "work"),
+            ping(5300, "work"),
+        ]);
+        let expr = Expr::from_string("work").unwrap();
+        assert_eq!(
+            sessions(&log, &expr, 3600),
+            vec![
+                Session {
+                    start: 0,
+                    end: 1000,
+                    pings: 3,
+                    estimated_secs: 1600,
+                },
+                Session {
+                    start: 5000,
+                    end: 5000,
+                    pings: 1,
+                    estimated_secs: 600,
+                },
+                Session {
+                    start: 5200,
+                    end: 5300,
+                    pings: 2,
+                    estimated_secs: 700,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn no_matches() {
+        let log = PingLog::from_pings(vec![ping(0, "a"), ping(10, "b")]);
+        let expr = Expr::from_string("c").unwrap();
+        assert!(sessions(&log, &expr, 3600).is_empty());
+    }
+
+    #[test]
+    fn weighted_tags_agree_with_estimate() {
+        let log = PingLog::from_pings(vec![
+            Ping::from_entry(0, "work:0.25 email:0.75", 600),
+            Ping::from_entry(500, "email", 600),
+        ]);
+        for (expr, pings) in [("work", 1), ("email", 2)] {
+            let expr = Expr::from_string(expr).unwrap();
+            let sessions = sessions(&log, &expr, 3600);
+            assert_eq!(sessions.len(), 1);
+            assert_eq!(sessions[0].pings, pings);
+            assert_eq!(sessions[0].pings, estimate(&log, &expr, 0..1000).pings);
+        }
+    }
+}
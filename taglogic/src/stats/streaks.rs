@@ -0,0 +1,172 @@
+use chrono::{Duration, NaiveDate};
+
+use super::{time_series, Bucket, Calendar, TimeEstimate};
+use crate::bool::Expr;
+use crate::log::PingLog;
+
+/// A run of consecutive days that all satisfied a condition.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Streak {
+    pub start: NaiveDate,
+    pub days: u32,
+}
+
+impl Streak {
+    /// The last day of the streak.
+    pub fn end(&self) -> NaiveDate {
+        self.start + Duration::days(i64::from(self.days) - 1)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Streaks {
+    /// The streak that is still going. A streak is still going if it includes today, or if it
+    /// ends yesterday, since today isn't over yet.
+    pub current: Option<Streak>,
+    /// The longest streak ever. If there are several, this is the earliest one.
+    pub longest: Option<Streak>,
+}
+
+/// Finds streaks of days where the estimated time spent on `expr` satisfies `predicate`, using
+/// local days in the time zone `tz`. Days between the first ping and `today` without any pings
+/// are given a zero estimate, so they can break a streak. For example, `|day| day.pings > 0` finds
+/// days with any matching pings, and `|day| day.hours >= 2.0` finds days with at least two hours.
+pub fn streaks<Tz, F>(
+    log: &PingLog,
+    expr: &Expr,
+    tz: &Tz,
+    today: NaiveDate,
+    predicate: F,
+) -> Streaks
+where
+    Tz: Calendar,
+    F: Fn(&TimeEstimate) -> bool,
+{
+    let series = time_series(log, expr, Bucket::Day, tz);
+    let days = series
+        .points
+        .iter()
+        .filter(|point| point.start <= today)
+        .map(|point| (point.start, predicate(&point.estimate)));
+    // pad the days after the end of the log with empty days
+    let padding_start = series
+        .points
+        .last()
+        .map(|point| point.start + Duration::days(1))
+        .unwrap_or(today);
+    let empty_day = predicate(&TimeEstimate::ZERO);
+    let padding = (0..(today - padding_start).num_days() + 1)
+        .map(|i| (padding_start + Duration::days(i), empty_day));
+
+    let mut longest: Option<Streak> = None;
+    let mut running: Option<Streak> = None;
+    for (date, satisfied) in days.chain(padding) {
+        if satisfied {
+            let streak = running.get_or_insert(Streak {
+                start: date,
+                days: 0,
+            });
+            streak.days += 1;
+            if streak.days > longest.map_or(0, |longest| longest.days) {
+                longest = Some(*streak);
+            }
+        } else if date != today {
+            running = None;
+        }
+    }
+
+    let yesterday = today - Duration::days(1);
+    Streaks {
+        current: running.filter(|streak| streak.end() >= yesterday),
+        longest,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use chrono::Utc;
+
+    const DAY: u64 = 86400;
+    // 2021-01-04 00:00:00 UTC
+    const START: u64 = 1609718400;
+
+    fn date(day: i64) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2021, 1, 4).unwrap() + Duration::days(day)
+    }
+
+    /// A log with one ping per day, tagged "a" on the given days and "b" otherwise.
+    fn log_with_days(days: u64, matching: &[u64]) -> PingLog {
+        PingLog::from_pings(
+            (0..days)
+                .map(|day| {
+                    let tag = if matching.contains(&day) { "a" } else { "b" };
+                    Ping::new(START + day * DAY, vec![tag.to_string()], 3600)
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn current_and_longest() {
+        let log = log_with_days(10, &[0, 1, 2, 3, 5, 6, 8, 9]);
+        let expr = Expr::from_string("a").unwrap();
+        let result = streaks(&log, &expr, &Utc, date(9), |day| day.pings > 0);
+        assert_eq!(
+            result.longest,
+            Some(Streak {
+                start: date(0),
+                days: 4
+            })
+        );
+        assert_eq!(
+            result.current,
+            Some(Streak {
+                start: date(8),
+                days: 2
+            })
+        );
+        assert_eq!(result.longest.unwrap().end(), date(3));
+    }
+
+    #[test]
+    fn today_in_progress_does_not_break_streak() {
+        let log = log_with_days(3, &[0, 1, 2]);
+        let expr = Expr::from_string("a").unwrap();
+        let result = streaks(&log, &expr, &Utc, date(3), |day| day.pings > 0);
+        assert_eq!(result.current.unwrap().days, 3);
+        let result = streaks(&log, &expr, &Utc, date(4), |day| day.pings > 0);
+        assert_eq!(result.current, None);
+        assert_eq!(result.longest.unwrap().days, 3);
+    }
+
+    #[test]
+    fn hour_threshold() {
+        let mut log = log_with_days(3, &[0, 1, 2]);
+        log.push(Ping::new(START + 10, vec!["a".to_string()], 3600));
+        let expr = Expr::from_string("a").unwrap();
+        let result = streaks(&log, &expr, &Utc, date(2), |day| day.hours >= 2.0);
+        assert_eq!(
+            result.longest,
+            Some(Streak {
+                start: date(0),
+                days: 1
+            })
+        );
+        assert_eq!(result.current, None);
+    }
+
+    #[test]
+    fn empty_log() {
+        let expr = Expr::from_string("a").unwrap();
+        let result = streaks(&PingLog::new(), &expr, &Utc, date(0), |day| day.pings > 0);
+        assert_eq!(
+            result,
+            Streaks {
+                current: None,
+                longest: None
+            }
+        );
+    }
+}
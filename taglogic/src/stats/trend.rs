@@ -0,0 +1,231 @@
+use chrono::{NaiveDate, Weekday};
+use std::collections::BTreeMap;
+
+use super::{local_date, Bucket, Calendar, Tally, TimeEstimate};
+use crate::bool::Expr;
+use crate::log::{match_shares, PingLog};
+
+/// Estimated time spent in one bucket of a [`TimeSeries`].
+#[cfg_attr(feature = "wasm", derive(serde::Serialize, tsify::Tsify))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TimeSeriesPoint {
+    /// The first day of the bucket.
+    #[cfg_attr(feature = "wasm", tsify(type = "string"))]
+    pub start: NaiveDate,
+    pub estimate: TimeEstimate,
+}
+
+#[cfg_attr(feature = "wasm", derive(serde::Serialize, tsify::Tsify))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeSeries {
+    pub bucket: Bucket,
+    /// One point per bucket, oldest first, with no gaps.
+    pub points: Vec<TimeSeriesPoint>,
+}
+
+impl TimeSeries {
+    /// Centered moving average of the estimated hours, with one item per point. Points too close
+    /// to either end of the series to have a full window are None.
+    ///
+    /// ## Panics
+    /// Panics if `window` isn't odd, since an even window can't be centered on a point.
+    pub fn moving_average(&self, window: usize) -> Vec<Option<f64>> {
+        assert!(window % 2 == 1, "window must be odd");
+        let half = window / 2;
+        (0..self.points.len())
+            .map(|i| {
+                if i < half || i + half >= self.points.len() {
+                    return None;
+                }
+                let sum: f64 = self.points[(i - half)..=(i + half)]
+                    .iter()
+                    .map(|point| point.estimate.hours)
+                    .sum();
+                Some(sum / window as f64)
+            })
+            .collect()
+    }
+}
+
+/// Estimated hours spent on pings matching `expr`, per bucket, in the time zone `tz`. The series
+/// covers every bucket from the first to the last ping in the log, including ones with no pings.
+pub fn time_series<Tz: Calendar>(
+    log: &PingLog,
+    expr: &Expr,
+    bucket: Bucket,
+    tz: &Tz,
+) -> TimeSeries {
+    let mut tallies: BTreeMap<NaiveDate, Tally> = BTreeMap::new();
+    let shares = match_shares(&log.pings(), expr);
+    for (ping, share) in log.pings().iter().zip(shares) {
+        if let Some(date) = local_date(ping.time, tz) {
+            tallies
+                .entry(bucket.start_with(date, tz.week_start()))
+                .or_default()
+                .add_share(ping.interval, share);
+        }
+    }
+    series_from_tallies(bucket, tz.week_start(), &tallies)
+}
+
+/// Turns tallies keyed by the first day of each bucket into a series without gaps. Tallies for
+/// days that aren't the start of a bucket are merged into the bucket containing them, with weeks
+/// starting on `week_start`.
+pub(crate) fn series_from_tallies(
+    bucket: Bucket,
+    week_start: Weekday,
+    tallies: &BTreeMap<NaiveDate, Tally>,
+) -> TimeSeries {
+    let mut merged: BTreeMap<NaiveDate, Tally> = BTreeMap::new();
+    for (date, tally) in tallies {
+        merged
+            .entry(bucket.start_with(*date, week_start))
+            .or_default()
+            .merge(tally);
+    }
+    let tallies = merged;
+    let mut points = Vec::with_capacity(tallies.len());
+    if let (Some(first), Some(last)) = (tallies.keys().next(), tallies.keys().next_back()) {
+        let mut start = *first;
+        while start <= *last {
+            let estimate = tallies
+                .get(&start)
+                .map_or(TimeEstimate::ZERO, Tally::estimate);
+            points.push(TimeSeriesPoint { start, estimate });
+            start = match bucket.next(start) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+    }
+    TimeSeries { bucket, points }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use chrono::{FixedOffset, Utc};
+
+    const DAY: u64 = 86400;
+    // 2021-01-04 00:00:00 UTC, a Monday
+    const MONDAY: u64 = 1609718400;
+
+    fn ping(time: u64, tags: &str) -> Ping {
+        Ping::new(time, tags.split(' ').map(String::from).collect(), 3600)
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn daily_series_has_no_gaps() {
+        let log = PingLog::from_pings(vec![
+            ping(MONDAY + 100, "work"),
+            ping(MONDAY + 200, "work"),
+            ping(MONDAY + 300, "play"),
+            ping(MONDAY + 3 * DAY, "work"),
+        ]);
+        let expr = Expr::from_string("work").unwrap();
+        let series = time_series(&log, &expr, Bucket::Day, &Utc);
+        let hours: Vec<f64> = series.points.iter().map(|p| p.estimate.hours).collect();
+        assert_eq!(hours, vec![2.0, 0.0, 0.0, 1.0]);
+        assert_eq!(series.points[0].start, date(2021, 1, 4));
+        assert_eq!(series.points[3].start, date(2021, 1, 7));
+    }
+
+    #[test]
+    fn weekly_and_monthly_buckets() {
+        let log = PingLog::from_pings(vec![
+            ping(MONDAY, "a"),
+            ping(MONDAY + 6 * DAY, "a"),
+            ping(MONDAY + 7 * DAY, "a"),
+            ping(MONDAY + 40 * DAY, "a"),
+        ]);
+        let expr = Expr::from_string("a").unwrap();
+
+        let weekly = time_series(&log, &expr, Bucket::Week, &Utc);
+        assert_eq!(weekly.points.len(), 6);
+        assert_eq!(weekly.points[0].estimate.hours, 2.0);
+        assert_eq!(weekly.points[1].estimate.hours, 1.0);
+
+        let monthly = time_series(&log, &expr, Bucket::Month, &Utc);
+        let starts: Vec<NaiveDate> = monthly.points.iter().map(|p| p.start).collect();
+        assert_eq!(starts, vec![date(2021, 1, 1), date(2021, 2, 1)]);
+        assert_eq!(monthly.points[0].estimate.hours, 3.0);
+    }
+
+    #[test]
+    fn uses_custom_calendars() {
+        use crate::stats::CustomCalendar;
+        use chrono::{NaiveTime, Weekday};
+
+        // a late night on Saturday, and Sunday morning
+        let saturday = MONDAY + 5 * DAY;
+        let log = PingLog::from_pings(vec![
+            ping(saturday + 23 * 3600, "a"),
+            ping(saturday + DAY + 3600, "a"),
+            ping(saturday + DAY + 10 * 3600, "a"),
+        ]);
+        let expr = Expr::from_string("a").unwrap();
+        let calendar = CustomCalendar {
+            day_start: NaiveTime::from_hms_opt(4, 0, 0).unwrap(),
+            week_start: Weekday::Sun,
+            ..CustomCalendar::new(Utc)
+        };
+        let daily = time_series(&log, &expr, Bucket::Day, &calendar);
+        let hours: Vec<f64> = daily.points.iter().map(|p| p.estimate.hours).collect();
+        assert_eq!(daily.points[0].start, date(2021, 1, 9));
+        assert_eq!(hours, vec![2.0, 1.0]);
+        let weekly = time_series(&log, &expr, Bucket::Week, &calendar);
+        let starts: Vec<NaiveDate> = weekly.points.iter().map(|p| p.start).collect();
+        assert_eq!(starts, vec![date(2021, 1, 3), date(2021, 1, 10)]);
+    }
+
+    #[test]
+    fn uses_local_dates() {
+        // 23:00 UTC on Monday is already Tuesday at UTC+2
+        let log = PingLog::from_pings(vec![ping(MONDAY + 23 * 3600, "a")]);
+        let expr = Expr::from_string("a").unwrap();
+        let tz = FixedOffset::east_opt(2 * 3600).unwrap();
+        let series = time_series(&log, &expr, Bucket::Day, &tz);
+        assert_eq!(series.points[0].start, date(2021, 1, 5));
+    }
+
+    #[test]
+    fn empty_log() {
+        let expr = Expr::from_string("a").unwrap();
+        let series = time_series(&PingLog::new(), &expr, Bucket::Day, &Utc);
+        assert!(series.points.is_empty());
+    }
+
+    #[test]
+    fn centered_moving_average() {
+        let log = PingLog::from_pings(
+            (0..5)
+                .flat_map(|day| (0..day).map(move |i| ping(MONDAY + day * DAY + i, "a")))
+                .chain(std::iter::once(ping(MONDAY, "b")))
+                .collect(),
+        );
+        let expr = Expr::from_string("a").unwrap();
+        let series = time_series(&log, &expr, Bucket::Day, &Utc);
+        assert_eq!(
+            series.moving_average(3),
+            vec![None, Some(1.0), Some(2.0), Some(3.0), None]
+        );
+        assert_eq!(series.moving_average(1)[0], Some(0.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn moving_average_needs_odd_window() {
+        time_series(
+            &PingLog::new(),
+            &Expr::from_string("a").unwrap(),
+            Bucket::Day,
+            &Utc,
+        )
+        .moving_average(2);
+    }
+}
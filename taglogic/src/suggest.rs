@@ -0,0 +1,279 @@
+//! Suggestions of tags for answering a ping, from the tags of earlier pings.
+//!
+//! Each tag seen before the ping starts with its chance of being on a ping, from how often it's
+//! used and how often it's been used lately. That's scaled by how much likelier it is at the same
+//! hour of the day, and after the tags of the previous ping, if that was recent enough to still be
+//! going on, as if those were independent.
+
+use chrono::{TimeZone, Timelike};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use crate::log::PingLog;
+
+/// Pings this many seconds old count half as much as new ones for recency.
+const HALF_LIFE: f64 = 7.0 * 86400.0;
+
+/// The longest gap (in seconds) between pings for the tags of one to suggest those of the next.
+const FOLLOW_GAP: u64 = 3 * 3600;
+
+/// How many pings the overall frequency of a tag counts as when scoring it for an hour or a
+/// previous tag, so a tag seen once at some hour isn't suggested every time at that hour.
+const PRIOR_PINGS: f64 = 5.0;
+
+/// How much recent use counts towards a tag's chance, against use over the whole log.
+const RECENCY: f64 = 0.75;
+
+/// A suggested tag, from [`suggest_tags`].
+#[cfg_attr(feature = "wasm", derive(serde::Serialize, tsify::Tsify))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoredTag {
+    pub tag: String,
+    /// Roughly the chance that the tag is right, between 0 and 1.
+    pub score: f64,
+}
+
+/// What's known about the ping being answered, for [`suggest_tags`].
+#[derive(Debug, Clone, Copy)]
+pub struct SuggestContext<'a, Tz> {
+    /// The time zone hours of the day are in.
+    pub tz: &'a Tz,
+    /// Tags already entered for the ping, which aren't suggested again.
+    pub entered: &'a [&'a str],
+    /// What's been typed of the next tag. Only tags starting with it are suggested.
+    pub prefix: &'a str,
+    /// The most tags to suggest.
+    pub limit: usize,
+}
+
+impl<'a, Tz: TimeZone> SuggestContext<'a, Tz> {
+    /// Up to 10 suggestions, with nothing entered or typed yet.
+    pub fn new(tz: &'a Tz) -> Self {
+        Self {
+            tz,
+            entered: &[],
+            prefix: "",
+            limit: 10,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    pings: u32,
+    /// Sum of the recency weights of the pings with the tag.
+    recent: f64,
+    /// Pings with the tag at the hour of the ping being answered.
+    at_hour: u32,
+    /// Pings with the tag right after one with each of the previous ping's tags.
+    following: Vec<u32>,
+}
+
+/// Suggests tags for the ping sent at `now`, best first, from the pings in `log` sent before it.
+///
+/// ```
+/// use chrono::Utc;
+/// use taglogic::log::{Ping, PingLog};
+/// use taglogic::suggest::{suggest_tags, SuggestContext};
+///
+/// let ping = |time, tag: &str| Ping::new(time, vec![tag.to_string()], 2700);
+/// let log = PingLog::from_pings(vec![ping(0, "sleep"), ping(3600, "work"), ping(5400, "work")]);
+/// let suggestions = suggest_tags(&log, 7200, &SuggestContext::new(&Utc));
+/// assert_eq!(suggestions[0].tag, "work");
+/// assert_eq!(suggestions.len(), 2);
+/// ```
+pub fn suggest_tags<Tz: TimeZone>(
+    log: &PingLog,
+    now: u64,
+    context: &SuggestContext<Tz>,
+) -> Vec<ScoredTag> {
+    let hour = |time| {
+        let secs = i64::try_from(time).ok()?;
+        Some(context.tz.timestamp_opt(secs, 0).single()?.hour())
+    };
+    let now_hour = hour(now);
+    let before = log.range(0, now);
+    let previous: Vec<&str> = match before.last() {
+        Some(ping) if now - ping.time <= FOLLOW_GAP => distinct(ping.tags.iter()),
+        _ => Vec::new(),
+    };
+
+    let mut tags: HashMap<&str, Counts> = HashMap::new();
+    let (mut total_recent, mut hour_pings) = (0.0, 0u32);
+    // pings right after one with each of the previous ping's tags
+    let mut following_pings = vec![0u32; previous.len()];
+    let mut last: Option<(u64, Vec<&str>)> = None;
+    for ping in &before {
+        let recent = 0.5f64.powf((now - ping.time) as f64 / HALF_LIFE);
+        let at_hour = now_hour.is_some() && hour(ping.time) == now_hour;
+        total_recent += recent;
+        hour_pings += u32::from(at_hour);
+        let ping_tags = distinct(ping.tags.iter());
+        // which of the previous ping's tags the ping before this one had
+        let followed: Vec<bool> = match &last {
+            Some((time, last_tags)) if ping.time - time <= FOLLOW_GAP => {
+                previous.iter().map(|tag| last_tags.contains(tag)).collect()
+            }
+            _ => vec![false; previous.len()],
+        };
+        for (pings, &followed) in following_pings.iter_mut().zip(&followed) {
+            *pings += u32::from(followed);
+        }
+        for &tag in &ping_tags {
+            let counts = tags.entry(tag).or_default();
+            counts.pings += 1;
+            counts.recent += recent;
+            counts.at_hour += u32::from(at_hour);
+            counts.following.resize(previous.len(), 0);
+            for (count, &followed) in counts.following.iter_mut().zip(&followed) {
+                *count += u32::from(followed);
+            }
+        }
+        last = Some((ping.time, ping_tags));
+    }
+
+    let total = before.len() as f64;
+    // a tag's chance in some subset of pings, pulled towards its overall chance `prior`
+    let smoothed = |count: u32, pings: u32, prior: f64| {
+        (f64::from(count) + PRIOR_PINGS * prior) / (f64::from(pings) + PRIOR_PINGS)
+    };
+    let mut suggestions: Vec<ScoredTag> = tags
+        .into_iter()
+        .filter(|(tag, _)| tag.starts_with(context.prefix) && !context.entered.contains(tag))
+        .map(|(tag, counts)| {
+            let frequency = f64::from(counts.pings) / total;
+            let chance = RECENCY * counts.recent / total_recent + (1.0 - RECENCY) * frequency;
+            let at_hour = smoothed(counts.at_hour, hour_pings, frequency) / frequency;
+            let follows = if previous.is_empty() {
+                1.0
+            } else {
+                let sum: f64 = (counts.following.iter().zip(&following_pings))
+                    .map(|(&count, &pings)| smoothed(count, pings, frequency))
+                    .sum();
+                sum / previous.len() as f64 / frequency
+            };
+            ScoredTag {
+                tag: tag.to_string(),
+                score: (chance * at_hour * follows).min(1.0),
+            }
+        })
+        .collect();
+    suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.tag.cmp(&b.tag)));
+    suggestions.truncate(context.limit);
+    suggestions
+}
+
+fn distinct<'a>(tags: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut tags: Vec<&str> = tags.collect();
+    tags.sort_unstable();
+    tags.dedup();
+    tags
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::Ping;
+    use chrono::{FixedOffset, Utc};
+
+    const HOUR: u64 = 3600;
+    const DAY: u64 = 86400;
+
+    fn ping(time: u64, tags: &str) -> Ping {
+        let tags = tags.split_whitespace().map(String::from).collect();
+        Ping::new(time, tags, 2700)
+    }
+
+    fn suggest(log: &PingLog, now: u64, context: &SuggestContext<Utc>) -> Vec<String> {
+        let suggestions = suggest_tags(log, now, context);
+        suggestions.into_iter().map(|scored| scored.tag).collect()
+    }
+
+    #[test]
+    fn suggests_tags_used_at_the_same_hour() {
+        // lunch at noon and work the rest of the day, for a week
+        let mut pings = Vec::new();
+        for day in 0..7 {
+            for hour in 9..17 {
+                let tags = if hour == 12 { "lunch" } else { "work" };
+                pings.push(ping(day * DAY + hour * HOUR, tags));
+            }
+        }
+        let log = PingLog::from_pings(pings);
+        let context = SuggestContext::new(&Utc);
+        // the previous ping was hours ago, so only the hour decides it
+        assert_eq!(
+            suggest(&log, 7 * DAY + 12 * HOUR, &context),
+            ["lunch", "work"]
+        );
+        assert_eq!(
+            suggest(&log, 7 * DAY + 10 * HOUR, &context),
+            ["work", "lunch"]
+        );
+
+        // hours are in the time zone given, so 11:45 is at noon half an hour ahead of UTC
+        let now = 7 * DAY + 11 * HOUR + 45 * 60;
+        assert_eq!(suggest(&log, now, &context)[0], "work");
+        let tz = FixedOffset::east_opt(1800).unwrap();
+        let suggestions = suggest_tags(&log, now, &SuggestContext::new(&tz));
+        assert_eq!(suggestions[0].tag, "lunch");
+        assert!(suggestions[0].score <= 1.0 && suggestions[1].score > 0.0);
+    }
+
+    #[test]
+    fn suggests_tags_that_follow_the_previous_ones() {
+        // coding is always followed by review, at different hours
+        let mut pings = Vec::new();
+        for day in 0..5 {
+            pings.push(ping(day * DAY + (9 + day) * HOUR, "code"));
+            pings.push(ping(day * DAY + (10 + day) * HOUR, "review"));
+            pings.push(ping(day * DAY + 20 * HOUR, "email"));
+            pings.push(ping(day * DAY + 21 * HOUR, "email"));
+        }
+        pings.push(ping(5 * DAY + 15 * HOUR, "code"));
+        let log = PingLog::from_pings(pings);
+        let context = SuggestContext::new(&Utc);
+        assert_eq!(suggest(&log, 5 * DAY + 16 * HOUR, &context)[0], "review");
+        // but not once the previous ping is too long ago
+        assert_eq!(suggest(&log, 5 * DAY + 23 * HOUR, &context)[0], "email");
+    }
+
+    #[test]
+    fn prefers_recent_tags() {
+        let mut pings = Vec::new();
+        for day in 0..30 {
+            let tags = if day < 20 { "old" } else { "new" };
+            pings.push(ping(day * DAY, tags));
+        }
+        let log = PingLog::from_pings(pings);
+        let context = SuggestContext::new(&Utc);
+        assert_eq!(
+            suggest(&log, 30 * DAY + 12 * HOUR, &context),
+            ["new", "old"]
+        );
+    }
+
+    #[test]
+    fn filters_by_context() {
+        let log = PingLog::from_pings(vec![
+            ping(0, "work meeting"),
+            ping(HOUR, "work email"),
+            ping(2 * HOUR, "meal"),
+            ping(10 * HOUR, "future"),
+        ]);
+        let context = SuggestContext {
+            entered: &["work"],
+            prefix: "me",
+            ..SuggestContext::new(&Utc)
+        };
+        let mut tags = suggest(&log, 3 * HOUR, &context);
+        tags.sort();
+        assert_eq!(tags, ["meal", "meeting"]);
+        let context = SuggestContext {
+            limit: 1,
+            ..SuggestContext::new(&Utc)
+        };
+        assert_eq!(suggest(&log, 3 * HOUR, &context).len(), 1);
+        assert!(suggest(&log, 0, &context).is_empty());
+    }
+}
@@ -0,0 +1,170 @@
+//! Synthetic logs for benchmarks and tests, generated from a seed so every run gets the same log.
+
+use crate::log::{Ping, PingLog};
+
+/// Tags that synthetic logs use first, so the most common tags look like real ones. Further tags
+/// are named `tag` and a number.
+const NAMES: [&str; 16] = [
+    "work", "email", "code", "sleep", "eat", "social", "read", "exercise", "commute", "meeting",
+    "music", "phone", "cook", "family", "errands", "tv",
+];
+
+/// How the tags of a synthetic log are picked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagModel {
+    /// Number of distinct tags.
+    pub tags: usize,
+    /// How much more popular common tags are than rare ones. Tag `n` (counting from 1) is picked
+    /// with weight `1 / n^skew`, so 0 picks every tag equally and 1 is like real logs, where a few
+    /// tags are on most pings.
+    pub skew: f64,
+    /// Average number of tags on each ping. Every ping has at least one.
+    pub tags_per_ping: f64,
+    /// Average seconds between pings, which is also every ping's interval.
+    pub avg_interval: u32,
+    /// Fraction of pings with a comment.
+    pub comments: f64,
+    /// Time of the first ping.
+    pub start: u64,
+}
+
+impl Default for TagModel {
+    /// A model like a few years of a real log: 200 tags, 2.5 per ping, pinged every 45 minutes.
+    fn default() -> Self {
+        Self {
+            tags: 200,
+            skew: 1.0,
+            tags_per_ping: 2.5,
+            avg_interval: 2700,
+            comments: 0.02,
+            start: 1_500_000_000,
+        }
+    }
+}
+
+/// Generates a log of `pings` pings. Gaps between pings are exponentially distributed, like the
+/// real schedule, and the same seed and model always give the same log.
+///
+/// ## Panics
+/// Panics if `model` has no tags, or if `model.tags_per_ping` is less than 1.
+pub fn generate_log(seed: u64, pings: usize, model: &TagModel) -> PingLog {
+    assert!(model.tags > 0, "the model needs tags");
+    assert!(
+        model.tags_per_ping >= 1.0,
+        "every ping has at least one tag"
+    );
+    let mut rng = SplitMix64(seed);
+    let names: Vec<String> = (0..model.tags)
+        .map(|n| match NAMES.get(n) {
+            Some(name) => name.to_string(),
+            None => format!("tag{}", n),
+        })
+        .collect();
+    let mut cumulative = Vec::with_capacity(model.tags);
+    let mut total = 0.0;
+    for n in 1..=model.tags {
+        total += (n as f64).powf(-model.skew);
+        cumulative.push(total);
+    }
+    // each tag after the first is added with this chance, so the count is geometric with the
+    // right mean
+    let another = 1.0 - 1.0 / model.tags_per_ping;
+
+    let mut time = model.start;
+    let mut log = Vec::with_capacity(pings);
+    for _ in 0..pings {
+        let gap = -(1.0 - rng.next_f64()).ln() * f64::from(model.avg_interval);
+        time += (gap as u64).max(1);
+
+        let mut tags: Vec<String> = Vec::new();
+        loop {
+            let pick = rng.next_f64() * total;
+            let index = cumulative.partition_point(|&weight| weight < pick);
+            let tag = &names[index.min(model.tags - 1)];
+            if tags.contains(tag) {
+                continue;
+            }
+            tags.push(tag.clone());
+            if tags.len() == model.tags || rng.next_f64() >= another {
+                break;
+            }
+        }
+
+        let mut ping = Ping::new(time, tags, model.avg_interval);
+        if rng.next_f64() < model.comments {
+            ping.comment = Some(format!("comment {}", log.len()));
+        }
+        log.push(ping);
+    }
+    PingLog::from_pings(log)
+}
+
+/// A small, fast generator that's good enough for synthetic data.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A float in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reproducible() {
+        let model = TagModel::default();
+        let log = generate_log(7, 1000, &model);
+        assert_eq!(log.len(), 1000);
+        assert_eq!(log, generate_log(7, 1000, &model));
+        assert_ne!(log, generate_log(8, 1000, &model));
+    }
+
+    #[test]
+    fn follows_model() {
+        let model = TagModel {
+            tags: 20,
+            ..TagModel::default()
+        };
+        let log = generate_log(1, 10_000, &model);
+        let pings = log.pings();
+        let tags: usize = pings.iter().map(|ping| ping.tags.len()).sum();
+        let per_ping = tags as f64 / pings.len() as f64;
+        assert!((per_ping - 2.5).abs() < 0.1, "{}", per_ping);
+
+        let span = pings.last().unwrap().time - model.start;
+        let gap = span as f64 / pings.len() as f64;
+        assert!((gap - 2700.0).abs() < 100.0, "{}", gap);
+
+        // with skew 1, the most common tag is on far more pings than the rarest
+        let frequency = log.tag_frequency();
+        assert!(frequency("work") > 5.0 * frequency("tag19"));
+        assert!(log.interner().len() <= 20);
+    }
+
+    #[test]
+    fn uniform_tags() {
+        let model = TagModel {
+            tags: 4,
+            skew: 0.0,
+            tags_per_ping: 1.0,
+            ..TagModel::default()
+        };
+        let log = generate_log(3, 4000, &model);
+        let frequency = log.tag_frequency();
+        for tag in &NAMES[..4] {
+            assert!((frequency(tag) - 0.25).abs() < 0.03, "{}", tag);
+        }
+        assert!(log.pings().iter().all(|ping| ping.tags.len() == 1));
+    }
+}
@@ -0,0 +1,274 @@
+//! Timestamps that can't silently wrap, truncate, or turn NaN into zero when they cross from JS,
+//! Python, or a database into the crate.
+//!
+//! Most of the crate takes times as `u64` seconds since the Unix epoch, but hosts have them as
+//! signed seconds, milliseconds, or JS numbers. [`PingTimestamp`] is where those are converted,
+//! with every conversion either exact or checked.
+
+use core::convert::TryFrom;
+use core::fmt;
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, TimeZone, Utc};
+
+/// Milliseconds either side of the epoch that a JS `Date` can be.
+const JS_MAX_MILLIS: f64 = 8.64e15;
+
+/// Seconds since the Unix epoch, which can be before it.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PingTimestamp(i64);
+
+/// A time that doesn't fit where it was being converted to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("timestamp out of range")]
+pub struct TimestampOutOfRange;
+
+impl PingTimestamp {
+    pub const UNIX_EPOCH: Self = Self(0);
+    pub const MIN: Self = Self(i64::MIN);
+    pub const MAX: Self = Self(i64::MAX);
+
+    pub const fn from_secs(secs: i64) -> Self {
+        Self(secs)
+    }
+
+    pub const fn secs(self) -> i64 {
+        self.0
+    }
+
+    /// Rounds down to the second, so a millisecond before the epoch is the second before it.
+    pub const fn from_millis(millis: i64) -> Self {
+        Self(millis.div_euclid(1000))
+    }
+
+    /// Returns None if the time in milliseconds doesn't fit in an i64.
+    pub const fn millis(self) -> Option<i64> {
+        self.0.checked_mul(1000)
+    }
+
+    /// A time from JS, like `Date.now()` or `date.getTime()`, rounded down to the second. Returns
+    /// None for NaN, infinities, and times outside of what a `Date` can be.
+    pub fn from_js_millis(millis: f64) -> Option<Self> {
+        if !(-JS_MAX_MILLIS..=JS_MAX_MILLIS).contains(&millis) {
+            return None;
+        }
+        // in range, so the cast is exact apart from dropping the fraction towards zero
+        let mut whole = millis as i64;
+        if whole as f64 > millis {
+            whole -= 1;
+        }
+        Some(Self::from_millis(whole))
+    }
+
+    /// The time as milliseconds for a JS `Date`. Times that a `Date` can't be are clamped to the
+    /// ends of its range.
+    pub fn to_js_millis(self) -> f64 {
+        // seconds in the range are below 2^53, so this is exact
+        let secs = self.0.clamp(-8_640_000_000_000, 8_640_000_000_000);
+        secs as f64 * 1000.0
+    }
+
+    /// Seconds as a float, like a time given to the WASM bindings, rounded down. Returns None for
+    /// NaN, infinities, and times that don't fit.
+    pub fn from_f64_secs(secs: f64) -> Option<Self> {
+        // i64::MAX rounds up to 2^63 as a float, so that has to be excluded
+        if !(secs >= i64::MIN as f64 && secs < i64::MAX as f64) {
+            return None;
+        }
+        let mut whole = secs as i64;
+        if (whole as f64) > secs {
+            whole -= 1;
+        }
+        Some(Self(whole))
+    }
+
+    pub fn checked_add_secs(self, secs: i64) -> Option<Self> {
+        self.0.checked_add(secs).map(Self)
+    }
+
+    pub fn checked_sub_secs(self, secs: i64) -> Option<Self> {
+        self.0.checked_sub(secs).map(Self)
+    }
+
+    /// Seconds from `earlier` to this time, or None if `earlier` is later.
+    pub fn checked_secs_since(self, earlier: Self) -> Option<u64> {
+        u64::try_from(i128::from(self.0) - i128::from(earlier.0)).ok()
+    }
+
+    /// The time as a `chrono` time in UTC, or None if it's past the years `chrono` supports.
+    #[cfg(feature = "chrono")]
+    pub fn to_datetime(self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(self.0, 0)
+    }
+}
+
+impl fmt::Display for PingTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<u32> for PingTimestamp {
+    fn from(secs: u32) -> Self {
+        Self(secs.into())
+    }
+}
+
+impl From<i64> for PingTimestamp {
+    fn from(secs: i64) -> Self {
+        Self(secs)
+    }
+}
+
+impl TryFrom<u64> for PingTimestamp {
+    type Error = TimestampOutOfRange;
+
+    fn try_from(secs: u64) -> Result<Self, Self::Error> {
+        i64::try_from(secs)
+            .map(Self)
+            .map_err(|_| TimestampOutOfRange)
+    }
+}
+
+/// For the parts of the crate that take `u64` times, which can't be before the epoch.
+impl TryFrom<PingTimestamp> for u64 {
+    type Error = TimestampOutOfRange;
+
+    fn try_from(time: PingTimestamp) -> Result<Self, Self::Error> {
+        u64::try_from(time.0).map_err(|_| TimestampOutOfRange)
+    }
+}
+
+/// For the `_u32` functions, which only work until 2106.
+impl TryFrom<PingTimestamp> for u32 {
+    type Error = TimestampOutOfRange;
+
+    fn try_from(time: PingTimestamp) -> Result<Self, Self::Error> {
+        u32::try_from(time.0).map_err(|_| TimestampOutOfRange)
+    }
+}
+
+/// Drops anything under a second.
+#[cfg(feature = "chrono")]
+impl<Tz: TimeZone> From<DateTime<Tz>> for PingTimestamp {
+    fn from(time: DateTime<Tz>) -> Self {
+        Self(time.timestamp())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts_millis() {
+        assert_eq!(PingTimestamp::from_millis(1999).secs(), 1);
+        assert_eq!(PingTimestamp::from_millis(-1).secs(), -1);
+        assert_eq!(PingTimestamp::from_secs(2).millis(), Some(2000));
+        assert_eq!(PingTimestamp::MAX.millis(), None);
+    }
+
+    #[test]
+    fn converts_js_millis() {
+        let js = PingTimestamp::from_js_millis;
+        assert_eq!(
+            js(1_700_000_000_999.0),
+            Some(PingTimestamp::from_secs(1_700_000_000))
+        );
+        assert_eq!(js(1500.5), Some(PingTimestamp::from_secs(1)));
+        assert_eq!(js(-0.5), Some(PingTimestamp::from_secs(-1)));
+        assert_eq!(js(-1000.5), Some(PingTimestamp::from_secs(-2)));
+        assert_eq!(
+            js(8.64e15),
+            Some(PingTimestamp::from_secs(8_640_000_000_000))
+        );
+        for bad in [f64::NAN, f64::INFINITY, -f64::INFINITY, 8.64e15 + 1000.0] {
+            assert_eq!(js(bad), None);
+        }
+        assert_eq!(
+            PingTimestamp::from_secs(1_700_000_000).to_js_millis(),
+            1.7e12
+        );
+        assert_eq!(PingTimestamp::MIN.to_js_millis(), -8.64e15);
+    }
+
+    #[test]
+    fn converts_f64_secs() {
+        let secs = PingTimestamp::from_f64_secs;
+        assert_eq!(secs(1.9), Some(PingTimestamp::from_secs(1)));
+        assert_eq!(secs(-1.5), Some(PingTimestamp::from_secs(-2)));
+        assert_eq!(secs(i64::MIN as f64), Some(PingTimestamp::MIN));
+        for bad in [f64::NAN, f64::INFINITY, i64::MAX as f64, 1e19] {
+            assert_eq!(secs(bad), None);
+        }
+    }
+
+    #[test]
+    fn checks_arithmetic() {
+        let time = PingTimestamp::from_secs(100);
+        assert_eq!(
+            time.checked_add_secs(5),
+            Some(PingTimestamp::from_secs(105))
+        );
+        assert_eq!(PingTimestamp::MAX.checked_add_secs(1), None);
+        assert_eq!(PingTimestamp::MIN.checked_sub_secs(1), None);
+        assert_eq!(
+            time.checked_secs_since(PingTimestamp::from_secs(-5)),
+            Some(105)
+        );
+        assert_eq!(time.checked_secs_since(PingTimestamp::from_secs(101)), None);
+        assert_eq!(
+            PingTimestamp::MAX.checked_secs_since(PingTimestamp::MIN),
+            Some(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn converts_integers() {
+        assert_eq!(
+            u64::try_from(PingTimestamp::from_secs(-1)),
+            Err(TimestampOutOfRange)
+        );
+        assert_eq!(u64::try_from(PingTimestamp::from_secs(7)), Ok(7));
+        assert_eq!(PingTimestamp::try_from(u64::MAX), Err(TimestampOutOfRange));
+        assert_eq!(
+            u32::try_from(PingTimestamp::from_secs(1 << 32)),
+            Err(TimestampOutOfRange)
+        );
+        assert_eq!(PingTimestamp::from(u32::MAX).secs(), i64::from(u32::MAX));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn converts_chrono() {
+        use chrono::FixedOffset;
+
+        let time = FixedOffset::east_opt(3600)
+            .unwrap()
+            .timestamp_opt(1_700_000_000, 500)
+            .unwrap();
+        let stamp = PingTimestamp::from(time);
+        assert_eq!(stamp.secs(), 1_700_000_000);
+        assert_eq!(
+            stamp.to_datetime(),
+            Utc.timestamp_opt(1_700_000_000, 0).single()
+        );
+        assert_eq!(PingTimestamp::MAX.to_datetime(), None);
+    }
+
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    #[test]
+    fn serializes_as_seconds() {
+        let time = PingTimestamp::from_secs(-5);
+        assert_eq!(serde_json::to_string(&time).unwrap(), "-5");
+        assert_eq!(
+            serde_json::from_str::<PingTimestamp>("12").unwrap().secs(),
+            12
+        );
+    }
+}
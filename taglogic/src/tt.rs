@@ -1,7 +1,7 @@
 //! Implementation of the original TagTime algorithm.
 // see https://forum.beeminder.com/t/official-reference-implementation-of-the-tagtime-universal-ping-schedule/4282
 
-use std::convert::TryInto;
+use core::convert::{TryFrom, TryInto};
 
 /// Effective start of time.
 pub const UR_PING: u64 = 1184097393;
@@ -12,9 +12,10 @@ pub const UNIV_SCHED: super::PingIntervalData = super::PingIntervalData {
 };
 const UNIV_SCHED_LOOKUP_TABLE: &[u8; 19704] = include_bytes!("tt/lookup_tables/univ.bin");
 pub const LOOKUP_TABLE_INTERVAL: u64 = 432000; // 5 days, regenerate lookup table when changing
-const IA: f64 = 16807.0;
-const IM_U32: u32 = 2147483647;
-const IM_F64: f64 = 2147483647.0;
+const IA: u64 = 16807;
+pub(crate) const IM_U32: u32 = 2147483647;
+/// ln(2) in 64.64 fixed point.
+const LN_2: u128 = 0xb172_17f7_d1cf_79ac;
 
 /// Repersents a state of the RNG, wraps a u32.
 /// Since the RNG state is a 31-bit non-zero integer, the leading bit is always zero.
@@ -22,56 +23,63 @@ const IM_F64: f64 = 2147483647.0;
 pub struct State(u32);
 
 impl State {
+    /// ## Panics
+    /// Panics if `seed` isn't between 1 and 2147483646.
     pub fn from_seed(seed: u32) -> Self {
         assert!(seed > 0);
         assert!(seed < IM_U32);
         Self(seed)
     }
 
-    pub fn from_seed_before(pint: &super::PingIntervalData, before: u64) -> (Self, u64) {
-        if *pint == UNIV_SCHED {
-            // try to find the state in the lookup table
-            // integer division rounds down, which is what we want here
-            let item_num = (before - UR_PING - 1) / LOOKUP_TABLE_INTERVAL;
-            let item_num_usize: usize = match item_num.try_into() {
-                Ok(x) => x,
-                Err(_) => return (Self::from_seed(pint.seed), UR_PING),
-            };
-            let index = item_num_usize * 12; // 12 bytes per item
-            if index >= UNIV_SCHED_LOOKUP_TABLE.len() {
-                (Self::from_seed(pint.seed), UR_PING)
-            } else {
-                // 4 bytes of data for state
-                let bytes = UNIV_SCHED_LOOKUP_TABLE.get(index..=(index + 12)).unwrap();
-                let state =
-                    Self::from_seed(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
-                let time = u64::from_le_bytes([
-                    bytes[4], bytes[5], bytes[6], bytes[7], bytes[8], bytes[9], bytes[10],
-                    bytes[11],
-                ]);
-                (state, time)
-            }
+    /// Like [`from_seed`](Self::from_seed), but returns None if `seed` isn't between 1 and
+    /// 2147483646.
+    pub fn try_from_seed(seed: u32) -> Option<Self> {
+        if (1..IM_U32).contains(&seed) {
+            Some(Self(seed))
         } else {
-            (Self::from_seed(pint.seed), UR_PING)
+            None
         }
     }
 
-    /// ran0 from Numerical Recipes. Has a period of around 2 billion
-    pub fn next_state(&mut self) {
-        let new_val = (IA * (self.0 as f64)) % IM_F64;
-        debug_assert!(new_val > 1.0);
-        debug_assert!(new_val < IM_F64);
-        self.0 = new_val as u32;
+    /// The latest known state of the schedule at or before `before`, and the time of its ping.
+    /// Returns None if the schedule's seed isn't valid.
+    pub fn from_seed_before(pint: &super::PingIntervalData, before: u64) -> Option<(Self, u64)> {
+        let start = (Self::try_from_seed(pint.seed)?, UR_PING);
+        if *pint != UNIV_SCHED {
+            return Some(start);
+        }
+        // try to find the state in the lookup table
+        // integer division rounds down, which is what we want here
+        let item_num = match before.checked_sub(UR_PING + 1) {
+            Some(since) => since / LOOKUP_TABLE_INTERVAL,
+            None => return Some(start),
+        };
+        // 12 bytes per item: 4 bytes of state, then 8 bytes of time
+        let bytes = usize::try_from(item_num)
+            .ok()
+            .and_then(|item_num| item_num.checked_mul(12))
+            .and_then(|index| UNIV_SCHED_LOOKUP_TABLE.get(index..index.checked_add(12)?));
+        let bytes = match bytes {
+            Some(bytes) => bytes,
+            None => return Some(start),
+        };
+        let (state, time) = bytes.split_at(4);
+        let state = Self::try_from_seed(u32::from_le_bytes(state.try_into().ok()?))?;
+        Some((state, u64::from_le_bytes(time.try_into().ok()?)))
     }
 
-    /// Returns a random number drawn from an exponential distribution with the given mean and state.
-    fn exp_rand(&self, m: u32) -> f64 {
-        -(m as f64) * ((self.0 as f64) / IM_F64).ln()
+    /// ran0 from Numerical Recipes. Has a period of around 2 billion
+    pub fn next_state(&mut self) {
+        // the product is below 2^46, so this is what the reference's floats compute too
+        self.0 = (IA * u64::from(self.0) % u64::from(IM_U32)) as u32;
     }
 
-    /// Returns the integer number of seconds until the next ping.
+    /// Returns the integer number of seconds until the next ping, drawn from an exponential
+    /// distribution with a mean of `avg_interval`. This is `-avg_interval * ln(state / IM)`
+    /// rounded, like the reference implementation, but in fixed point so every platform agrees.
     pub fn gap(&self, avg_interval: u32) -> u32 {
-        (self.exp_rand(avg_interval).round() as u32).max(1)
+        let secs = (u128::from(avg_interval) * ln_im_over(self.0) + (1 << 63)) >> 64;
+        u32::try_from(secs).unwrap_or(u32::MAX).max(1)
     }
 
     /// Gets the inner RNG value.
@@ -80,14 +88,40 @@ impl State {
     }
 }
 
+/// `ln(IM / x)` in 64.64 fixed point, for `x` from 1 to `IM - 1`, accurate to about 2^-58.
+fn ln_im_over(x: u32) -> u128 {
+    let (x, im) = (u64::from(x.max(1)), u64::from(IM_U32));
+    // IM / x = 2^k * IM / d, where IM / d is from 1 to 2
+    let k = (im / x).ilog2();
+    let d = x << k;
+    // ln(IM / d) = 2 atanh(z), where z is at most 1/3 so the series converges quickly
+    let z = (u128::from(im - d) << 64) / u128::from(im + d);
+    let z2 = (z * z) >> 64;
+    let (mut term, mut n, mut atanh) = (z, 1, 0);
+    while term > 0 {
+        atanh += term / n;
+        term = (term * z2) >> 64;
+        n += 2;
+    }
+    u128::from(k) * LN_2 + 2 * atanh
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+
+    const IM_F64: f64 = 2147483647.0;
+
+    /// The reference implementation's exponential random number, in floats.
+    fn float_exp_rand(state: &State, m: u32) -> f64 {
+        -(m as f64) * ((state.0 as f64) / IM_F64).ln()
+    }
+
     #[test]
     fn exp_rand() {
         // valid value determined by using JS refrence implementation functions
         let state = State::from_seed(UNIV_SCHED.seed);
-        assert_eq!(state.exp_rand(2700), 14193.149888904356);
+        assert_eq!(float_exp_rand(&state, 2700), 14193.149888904356);
     }
 
     #[test]
@@ -113,7 +147,7 @@ mod test {
             state.next_state();
             // using f32 quells issues with slightly different decimal stringification
             assert_eq!(
-                state.exp_rand(2700) as f32,
+                float_exp_rand(&state, 2700) as f32,
                 expected_val,
                 "diverged on {}th call",
                 index + 1
@@ -149,6 +183,62 @@ mod test {
         }
     }
 
+    #[test]
+    fn gaps_match_floats() {
+        // the reference implementation's floats give the same gaps
+        let float_gap = |state: &State, m: u32| (float_exp_rand(state, m).round() as u32).max(1);
+        let mut state = State(11193462);
+        for index in 0..300_000 {
+            state.next_state();
+            for &m in &[1, 60, 2700, 86_400] {
+                assert_eq!(state.gap(m), float_gap(&state, m), "{}th call", index + 1);
+            }
+        }
+        for x in (1..2000).chain(IM_U32 - 2000..IM_U32) {
+            let state = State(x);
+            for &m in &[2700, u32::MAX] {
+                let (gap, float) = (state.gap(m), float_gap(&state, m));
+                assert!(gap.abs_diff(float) <= 1, "{} {}", x, m);
+            }
+        }
+    }
+
+    #[test]
+    fn lookup_table_matches_gaps() {
+        // the table was made with floats, so this checks that fixed point gives the same
+        // schedule for its first 1644 checkpoints (about 260,000 pings)
+        let mut state = State::from_seed(UNIV_SCHED.seed);
+        let mut pung = UR_PING;
+        for (index, item) in UNIV_SCHED_LOOKUP_TABLE.chunks_exact(12).enumerate() {
+            let time = UR_PING + index as u64 * LOOKUP_TABLE_INTERVAL;
+            loop {
+                let mut next = state.clone();
+                next.next_state();
+                let next_pung = pung + u64::from(next.gap(UNIV_SCHED.avg_interval));
+                if next_pung >= time {
+                    break;
+                }
+                state = next;
+                pung = next_pung;
+            }
+            assert_eq!(item[..4], state.0.to_le_bytes(), "{}th item", index);
+            assert_eq!(item[4..], pung.to_le_bytes(), "{}th item", index);
+        }
+    }
+
+    #[test]
+    fn fixed_point_ln() {
+        // golden values, computed with 60 digits of precision
+        let close = |x: u32, expected: u128| ln_im_over(x).abs_diff(expected) < 64;
+        assert!(close(IM_U32 - 1, 8_589_934_598));
+        assert!(close(1, 396_375_567_992_692_390_859));
+        assert!(close(1_000_000_000, 14_098_786_727_093_569_682));
+        assert_eq!(State(1).gap(2700), 58_016);
+        assert_eq!(State(IM_U32 - 1).gap(2700), 1);
+        assert_eq!(State(1).gap(u32::MAX), u32::MAX);
+        assert_eq!(State(1_000_000_000).gap(2700), 2_064);
+    }
+
     #[test]
     fn next_state_matches_100k() {
         let mut state = State(1);
@@ -200,7 +290,24 @@ mod test {
         let year2100 = 4102376400000;
         assert_eq!(
             State::from_seed_before(&UNIV_SCHED, year2100),
-            (State::from_seed(UNIV_SCHED.seed), UR_PING)
+            Some((State::from_seed(UNIV_SCHED.seed), UR_PING))
         );
     }
+
+    #[test]
+    fn starts_before_any_time() {
+        // the last item of the lookup table
+        let last = UR_PING + (UNIV_SCHED_LOOKUP_TABLE.len() as u64 / 12) * LOOKUP_TABLE_INTERVAL;
+        let (_, time) = State::from_seed_before(&UNIV_SCHED, last).unwrap();
+        assert!(time < last && time > last - 2 * LOOKUP_TABLE_INTERVAL);
+        for before in [0, UR_PING, u64::MAX] {
+            assert!(State::from_seed_before(&UNIV_SCHED, before).is_some());
+        }
+        let bad_seed = super::super::PingIntervalData {
+            seed: 0,
+            ..UNIV_SCHED
+        };
+        assert_eq!(State::from_seed_before(&bad_seed, UR_PING + 1), None);
+        assert_eq!(State::try_from_seed(IM_U32), None);
+    }
 }
@@ -0,0 +1,378 @@
+//! What tags look like, as opposed to the strings they are.
+//!
+//! A tag can be several characters that show as one, like `❤️` (a heart and a variation selector)
+//! or `👩‍💻` (two emoji joined with a zero-width joiner). [`graphemes`] splits text into what
+//! shows as one character, and the expression lexer uses [`extends_grapheme`] so it never splits
+//! one apart.
+//!
+//! Two different tags can also look the same, like `work` and `wоrk` with a Cyrillic `о`, or `café`
+//! with its `é` as one character or as an `e` and an accent. Matching treats those as different
+//! tags, so [`confusables`] finds them, to warn about.
+//!
+//! Both cover the characters tags are likely to have (Latin, Greek and Cyrillic letters, accents,
+//! and emoji), not all of Unicode.
+//!
+//! Spans in the crate are byte offsets, but JS indexes strings by UTF-16 code unit and Python by
+//! character, so [`offsets`] gives a span in all three.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+const ZWJ: char = '\u{200D}';
+
+/// Whether `c` is shown as part of the character before it, like an accent or an emoji skin tone.
+pub fn extends_grapheme(c: char) -> bool {
+    matches!(
+        u32::from(c),
+        0x0300..=0x036F // combining accents
+            | 0x0483..=0x0489
+            | 0x0591..=0x05BD
+            | 0x0610..=0x061A
+            | 0x064B..=0x065F
+            | 0x1AB0..=0x1AFF
+            | 0x1DC0..=0x1DFF
+            | 0x200C..=0x200D // zero-width non-joiner and joiner
+            | 0x20D0..=0x20FF // combining marks for symbols, like the keycap in 1️⃣
+            | 0xFE00..=0xFE0F // variation selectors
+            | 0xFE20..=0xFE2F
+            | 0x1F3FB..=0x1F3FF // skin tones
+            | 0xE0020..=0xE007F // tags, in subdivision flags like 🏴󠁧󠁢󠁳󠁣󠁴󠁿
+            | 0xE0100..=0xE01EF
+    )
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+/// Splits `text` into what shows as one character each: a character with any accents or other
+/// marks after it, emoji joined with zero-width joiners, and pairs of regional indicators (flags).
+pub fn graphemes(text: &str) -> impl Iterator<Item = &str> + '_ {
+    let mut rest = text;
+    core::iter::from_fn(move || {
+        let mut chars = rest.char_indices();
+        let (_, first) = chars.next()?;
+        let mut end = first.len_utf8();
+        let mut joined = false;
+        let mut flag = is_regional_indicator(first);
+        for (i, c) in chars {
+            if joined || extends_grapheme(c) {
+                joined = c == ZWJ;
+            } else if flag && is_regional_indicator(c) {
+                flag = false;
+            } else {
+                break;
+            }
+            end = i + c.len_utf8();
+        }
+        let (grapheme, after) = rest.split_at(end);
+        rest = after;
+        Some(grapheme)
+    })
+}
+
+/// A span of some text, as byte offsets and in the units other languages index strings by.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Offsets {
+    pub bytes: Range<usize>,
+    /// UTF-16 code units, for JS, Java, Kotlin and C# strings.
+    pub utf16: Range<usize>,
+    /// Unicode code points, for Python strings.
+    pub chars: Range<usize>,
+}
+
+/// `span`, a byte range of `text`, in every unit. Offsets past the end, or inside a character,
+/// are moved back to the start of the character.
+pub fn offsets(text: &str, span: Range<usize>) -> Offsets {
+    let start = floor_char_boundary(text, span.start);
+    let end = floor_char_boundary(text, span.end).max(start);
+    let (before, inside) = (&text[..start], &text[start..end]);
+    let utf16_start = before.encode_utf16().count();
+    let chars_start = before.chars().count();
+    Offsets {
+        bytes: start..end,
+        utf16: utf16_start..utf16_start + inside.encode_utf16().count(),
+        chars: chars_start..chars_start + inside.chars().count(),
+    }
+}
+
+/// The start of the character `offset` is in, or the end of `text` if it's past it.
+pub(crate) fn floor_char_boundary(text: &str, offset: usize) -> usize {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// Characters that look like ASCII letters, and the letters they look like.
+const LOOKALIKES: &[(char, char)] = &[
+    // Cyrillic
+    ('а', 'a'),
+    ('е', 'e'),
+    ('о', 'o'),
+    ('р', 'p'),
+    ('с', 'c'),
+    ('у', 'y'),
+    ('х', 'x'),
+    ('і', 'i'),
+    ('ј', 'j'),
+    ('ѕ', 's'),
+    ('ԁ', 'd'),
+    ('ԛ', 'q'),
+    ('ԝ', 'w'),
+    ('һ', 'h'),
+    ('ӏ', 'l'),
+    ('А', 'A'),
+    ('В', 'B'),
+    ('Е', 'E'),
+    ('К', 'K'),
+    ('М', 'M'),
+    ('Н', 'H'),
+    ('О', 'O'),
+    ('Р', 'P'),
+    ('С', 'C'),
+    ('Т', 'T'),
+    ('Х', 'X'),
+    ('І', 'l'),
+    ('Ј', 'J'),
+    ('Ѕ', 'S'),
+    ('Ү', 'Y'),
+    // Greek
+    ('α', 'a'),
+    ('ο', 'o'),
+    ('ν', 'v'),
+    ('ι', 'i'),
+    ('Α', 'A'),
+    ('Β', 'B'),
+    ('Ε', 'E'),
+    ('Ζ', 'Z'),
+    ('Η', 'H'),
+    ('Ι', 'l'),
+    ('Κ', 'K'),
+    ('Μ', 'M'),
+    ('Ν', 'N'),
+    ('Ο', 'O'),
+    ('Ρ', 'P'),
+    ('Τ', 'T'),
+    ('Υ', 'Y'),
+    ('Χ', 'X'),
+    // Latin and digits that look like each other
+    ('I', 'l'),
+    ('1', 'l'),
+    ('0', 'O'),
+];
+
+/// Accented Latin-1 letters, and the letter and combining accent they can also be written as.
+const DECOMPOSED: &[(char, char, char)] = &[
+    ('À', 'A', '\u{300}'),
+    ('Á', 'A', '\u{301}'),
+    ('Â', 'A', '\u{302}'),
+    ('Ã', 'A', '\u{303}'),
+    ('Ä', 'A', '\u{308}'),
+    ('Å', 'A', '\u{30A}'),
+    ('Ç', 'C', '\u{327}'),
+    ('È', 'E', '\u{300}'),
+    ('É', 'E', '\u{301}'),
+    ('Ê', 'E', '\u{302}'),
+    ('Ë', 'E', '\u{308}'),
+    ('Ì', 'I', '\u{300}'),
+    ('Í', 'I', '\u{301}'),
+    ('Î', 'I', '\u{302}'),
+    ('Ï', 'I', '\u{308}'),
+    ('Ñ', 'N', '\u{303}'),
+    ('Ò', 'O', '\u{300}'),
+    ('Ó', 'O', '\u{301}'),
+    ('Ô', 'O', '\u{302}'),
+    ('Õ', 'O', '\u{303}'),
+    ('Ö', 'O', '\u{308}'),
+    ('Ù', 'U', '\u{300}'),
+    ('Ú', 'U', '\u{301}'),
+    ('Û', 'U', '\u{302}'),
+    ('Ü', 'U', '\u{308}'),
+    ('Ý', 'Y', '\u{301}'),
+    ('à', 'a', '\u{300}'),
+    ('á', 'a', '\u{301}'),
+    ('â', 'a', '\u{302}'),
+    ('ã', 'a', '\u{303}'),
+    ('ä', 'a', '\u{308}'),
+    ('å', 'a', '\u{30A}'),
+    ('ç', 'c', '\u{327}'),
+    ('è', 'e', '\u{300}'),
+    ('é', 'e', '\u{301}'),
+    ('ê', 'e', '\u{302}'),
+    ('ë', 'e', '\u{308}'),
+    ('ì', 'i', '\u{300}'),
+    ('í', 'i', '\u{301}'),
+    ('î', 'i', '\u{302}'),
+    ('ï', 'i', '\u{308}'),
+    ('ñ', 'n', '\u{303}'),
+    ('ò', 'o', '\u{300}'),
+    ('ó', 'o', '\u{301}'),
+    ('ô', 'o', '\u{302}'),
+    ('õ', 'o', '\u{303}'),
+    ('ö', 'o', '\u{308}'),
+    ('ù', 'u', '\u{300}'),
+    ('ú', 'u', '\u{301}'),
+    ('û', 'u', '\u{302}'),
+    ('ü', 'u', '\u{308}'),
+    ('ý', 'y', '\u{301}'),
+    ('ÿ', 'y', '\u{308}'),
+];
+
+/// Characters that don't show at all, or only pick between text and emoji style.
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{AD}' | '\u{200B}' | '\u{2060}' | '\u{FEFF}' | '\u{FE0E}' | '\u{FE0F}'
+    )
+}
+
+/// What `tag` looks like: two tags with the same skeleton are hard to tell apart. Lookalike
+/// letters become the ASCII ones they look like, accented letters are written as a letter and an
+/// accent, fullwidth letters become ASCII, and invisible characters are dropped.
+pub fn skeleton(tag: &str) -> String {
+    let mut out = String::with_capacity(tag.len());
+    for c in tag.chars() {
+        if is_invisible(c) {
+            continue;
+        }
+        if let Some(&(_, letter, accent)) = DECOMPOSED.iter().find(|(from, ..)| *from == c) {
+            out.push(letter);
+            out.push(accent);
+        } else if let Some(&(_, to)) = LOOKALIKES.iter().find(|(from, _)| *from == c) {
+            out.push(to);
+        } else if ('\u{FF01}'..='\u{FF5E}').contains(&c) {
+            let ascii = char::from_u32(u32::from(c) - 0xFEE0).unwrap_or(c);
+            // a fullwidth letter could itself look like another one, like `Ｉ`
+            let to = LOOKALIKES.iter().find(|(from, _)| *from == ascii);
+            out.push(to.map_or(ascii, |&(_, to)| to));
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// A tag that looks like another one, but isn't the same string.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Confusable<'a> {
+    pub tag: &'a str,
+    pub looks_like: &'a str,
+}
+
+/// Tags in `tags` that look like another one of them, or like one of `known` (like the tags in
+/// a log). Each pair is only given once.
+pub fn confusables<'a>(tags: &[&'a str], known: &[&'a str]) -> Vec<Confusable<'a>> {
+    let mut by_skeleton: BTreeMap<String, Vec<&'a str>> = BTreeMap::new();
+    for &tag in tags.iter().chain(known) {
+        let same = by_skeleton.entry(skeleton(tag)).or_default();
+        if !same.contains(&tag) {
+            same.push(tag);
+        }
+    }
+    let mut found = Vec::new();
+    for (i, &tag) in tags.iter().enumerate() {
+        if tags[..i].contains(&tag) {
+            continue;
+        }
+        let same = by_skeleton
+            .get(&skeleton(tag))
+            .map_or(&[][..], Vec::as_slice);
+        for &other in same {
+            // pairs within `tags` are only given from the first of them
+            let earlier = tags[..i].contains(&other);
+            if other != tag && !earlier {
+                found.push(Confusable {
+                    tag,
+                    looks_like: other,
+                });
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_graphemes() {
+        let text = "e\u{301}a👩\u{200D}💻❤\u{FE0F}🇳🇿🇦1\u{FE0F}\u{20E3}";
+        assert_eq!(
+            graphemes(text).collect::<Vec<_>>(),
+            [
+                "e\u{301}",
+                "a",
+                "👩\u{200D}💻",
+                "❤\u{FE0F}",
+                "🇳🇿",
+                "🇦",
+                "1\u{FE0F}\u{20E3}"
+            ]
+        );
+        assert_eq!(graphemes("").count(), 0);
+        // a mark with nothing before it is shown on its own
+        assert_eq!(graphemes("\u{301}a").collect::<Vec<_>>(), ["\u{301}", "a"]);
+    }
+
+    #[test]
+    fn converts_offsets() {
+        let text = "a 😀 é";
+        assert_eq!(
+            offsets(text, 2..7),
+            Offsets {
+                bytes: 2..7,
+                utf16: 2..5,
+                chars: 2..4
+            }
+        );
+        // inside a character, and past the end
+        assert_eq!(offsets(text, 3..100).bytes, 2..9);
+        assert_eq!(offsets(text, 3..100).utf16, 2..6);
+        assert_eq!(offsets(text, 9..9).chars, 5..5);
+        // and backwards
+        let backwards = Range { start: 8, end: 2 };
+        assert_eq!(offsets(text, backwards).bytes, 7..7);
+    }
+
+    #[test]
+    fn skeletons() {
+        assert_eq!(skeleton("wоrk"), "work");
+        assert_eq!(skeleton("café"), skeleton("cafe\u{301}"));
+        assert_eq!(skeleton("❤\u{FE0F}"), "❤");
+        assert_eq!(skeleton("ｗｏｒｋ"), "work");
+        assert_eq!(skeleton("Ｉ1l"), "lll");
+        assert_ne!(skeleton("work"), skeleton("Work"));
+    }
+
+    #[test]
+    fn finds_confusables() {
+        let found = confusables(&["wоrk", "sleep", "wоrk"], &["work", "sleep", "ѕleep"]);
+        assert_eq!(
+            found,
+            [
+                Confusable {
+                    tag: "wоrk",
+                    looks_like: "work"
+                },
+                Confusable {
+                    tag: "sleep",
+                    looks_like: "ѕleep"
+                },
+            ]
+        );
+        let found = confusables(&["café", "cafe\u{301}"], &[]);
+        assert_eq!(
+            found,
+            [Confusable {
+                tag: "café",
+                looks_like: "cafe\u{301}"
+            }]
+        );
+        assert!(confusables(&["work", "play"], &["work"]).is_empty());
+    }
+}
@@ -0,0 +1,799 @@
+//! Bindings for the web frontend. Only the bindings for the enabled features are included.
+
+#[cfg(feature = "stats")]
+use core::convert::TryFrom;
+
+use wasm_bindgen::prelude::*;
+
+#[cfg(feature = "stats")]
+use chrono::FixedOffset;
+#[cfg(feature = "stats")]
+use serde::Deserialize;
+use serde::Serialize;
+#[cfg(any(feature = "expr", feature = "ping", feature = "stats"))]
+use tsify::Ts;
+use tsify::Tsify;
+
+#[cfg(feature = "expr")]
+use crate::bool::{CompiledExpr, Expr, ParseError};
+#[cfg(feature = "stats")]
+use crate::log::{Ping, PingLog};
+#[cfg(feature = "natural")]
+use crate::natural;
+#[cfg(feature = "ping")]
+use crate::notify::{self, Notification, NotificationOptions};
+#[cfg(feature = "stats")]
+use crate::segments::{Month, MonthError, SegmentedLog};
+#[cfg(feature = "stats")]
+use crate::stats::{self, Bucket, Tally, TimeEstimate, TimeSeries};
+#[cfg(feature = "stats")]
+use crate::suggest::{self, ScoredTag, SuggestContext};
+#[cfg(feature = "stats")]
+use crate::timestamp::PingTimestamp;
+#[cfg(feature = "expr")]
+use crate::unicode;
+#[cfg(feature = "ping")]
+use crate::{tt, PingIntervalData};
+
+/// An error thrown to JS. `kind` tells the different kinds of errors apart.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Tsify)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum TaglogicError {
+    /// An expression couldn't be parsed. Unlike [`ParseError`], the span is in UTF-16 code units,
+    /// so it can be used with JS string methods directly.
+    Parse {
+        message: String,
+        start: usize,
+        end: usize,
+    },
+    /// An argument was out of range.
+    InvalidInput { message: String },
+}
+
+#[cfg(feature = "expr")]
+impl TaglogicError {
+    fn parse(expr: &str, err: ParseError) -> Self {
+        let span = err.offsets(expr).utf16;
+        Self::Parse {
+            message: err.kind.to_string(),
+            start: span.start,
+            end: span.end,
+        }
+    }
+}
+
+impl From<tsify::Error> for TaglogicError {
+    fn from(err: tsify::Error) -> Self {
+        Self::InvalidInput {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<TaglogicError> for JsValue {
+    fn from(err: TaglogicError) -> Self {
+        match err.into_ts() {
+            Ok(ts) => ts.into(),
+            Err(err) => JsValue::from_str(&err.to_string()),
+        }
+    }
+}
+
+/// Pings passed in from JS. Extra fields (like `category` or `synced`) are ignored.
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+pub struct Pings(Vec<Ping>);
+
+/// Parses an expression, throwing a `TaglogicError` if it's invalid.
+#[cfg(feature = "expr")]
+#[wasm_bindgen]
+pub fn parse(expr: &str) -> Result<Expr, TaglogicError> {
+    Expr::parse(expr).map_err(|err| TaglogicError::parse(expr, err))
+}
+
+/// Returns if an expression matches the space-separated `tags`.
+#[cfg(feature = "expr")]
+#[wasm_bindgen]
+pub fn matches(expr: &Expr, tags: &str) -> bool {
+    expr.matches(&tags.split_whitespace().collect::<Vec<_>>())
+}
+
+/// Returns why an expression does or doesn't match the space-separated `tags`, like "matched
+/// because `work` present and `meeting` absent", for tooltips.
+#[cfg(feature = "expr")]
+#[wasm_bindgen]
+pub fn explain(expr: &Expr, tags: &str) -> String {
+    expr.explain(&tags.split_whitespace().collect::<Vec<_>>())
+        .to_string()
+}
+
+/// A part of an expression that decided whether it matched, for highlighting it in green if it
+/// matched or red if it didn't. Unlike `bool::Highlight`, the span is in UTF-16 code units.
+#[cfg(feature = "expr")]
+#[derive(Debug, Clone, Serialize, Tsify)]
+pub struct TermHighlight {
+    start: usize,
+    end: usize,
+    matched: bool,
+}
+
+/// Highlights returned to JS.
+#[cfg(feature = "expr")]
+#[derive(Debug, Clone, Serialize, Tsify)]
+pub struct Highlights(Vec<TermHighlight>);
+
+/// Returns the parts of an expression that decided whether it matches the space-separated
+/// `tags`, throwing a `TaglogicError` if it's invalid.
+#[cfg(feature = "expr")]
+#[wasm_bindgen]
+pub fn highlight(expr: &str, tags: &str) -> Result<Ts<Highlights>, TaglogicError> {
+    let parsed = Expr::parse(expr).map_err(|err| TaglogicError::parse(expr, err))?;
+    let tags: Vec<&str> = tags.split_whitespace().collect();
+    let highlights = parsed
+        .explain(&tags)
+        .highlights()
+        .into_iter()
+        .map(|highlight| {
+            let span = unicode::offsets(expr, highlight.span).utf16;
+            TermHighlight {
+                start: span.start,
+                end: span.end,
+                matched: highlight.matched,
+            }
+        })
+        .collect();
+    Ok(Highlights(highlights).into_ts()?)
+}
+
+/// Returns an array of the tags used in an expression, sorted and without duplicates.
+#[cfg(feature = "expr")]
+#[wasm_bindgen(unchecked_return_type = "string[]")]
+pub fn tags(expr: &Expr) -> Box<[JsValue]> {
+    expr.tags().into_iter().map(JsValue::from_str).collect()
+}
+
+/// Returns a `TaglogicError` if the expression is invalid, or null if it's valid.
+#[cfg(feature = "expr")]
+#[wasm_bindgen(unchecked_return_type = "TaglogicError | null")]
+pub fn validate(expr: &str) -> JsValue {
+    match Expr::parse(expr) {
+        Ok(_) => JsValue::NULL,
+        Err(err) => TaglogicError::parse(expr, err).into(),
+    }
+}
+
+/// Rewrites an expression in canonical form, throwing a `TaglogicError` if it's invalid.
+#[cfg(feature = "expr")]
+#[wasm_bindgen]
+pub fn format(expr: &str) -> Result<String, TaglogicError> {
+    Expr::parse(expr)
+        .map(|expr| expr.to_string())
+        .map_err(|err| TaglogicError::parse(expr, err))
+}
+
+/// Compiles an expression against a table of tags, for matching packed pings with `matchPacked`.
+/// Packed pings don't have comments, so expressions that search them are rejected.
+#[cfg(feature = "expr")]
+#[wasm_bindgen(js_name = compileExpr)]
+pub fn compile_expr(expr: &Expr, tags: Vec<String>) -> Result<CompiledExpr, TaglogicError> {
+    if expr.searches_comments() {
+        return Err(TaglogicError::InvalidInput {
+            message: "packed pings don't have comments to search".to_string(),
+        });
+    }
+    Ok(expr.compile_with_table(&tags))
+}
+
+/// Matches many pings at once, rather than crossing into WASM for each one. The tags of ping `i`
+/// are `tagIndices[tagOffsets[i]..tagOffsets[i + 1]]`, as indices into the table `expr` was
+/// compiled with, and pings with times outside of `start..end` never match.
+///
+/// Returns a bitset, where bit `i % 32` of word `i / 32` is set if ping `i` matched.
+#[cfg(feature = "expr")]
+#[wasm_bindgen(js_name = matchPacked)]
+pub fn match_packed(
+    expr: &CompiledExpr,
+    times: &[f64],
+    tag_offsets: &[u32],
+    tag_indices: &[u32],
+    start: f64,
+    end: f64,
+) -> Result<Vec<u32>, TaglogicError> {
+    check_offsets(times, tag_offsets)?;
+    let mut bits = vec![0; times.len().div_ceil(32)];
+    let packed = Packed {
+        times,
+        tag_offsets,
+        tag_indices,
+        range: start..end,
+    };
+    packed.match_into(expr, 0..times.len(), &mut bits)?;
+    Ok(bits)
+}
+
+/// Matches pings given as a bitset for each tag, rather than as lists of tags, which is faster
+/// when the same pings are matched against many expressions. Row `t` of `matrix` is the
+/// `ceil(pings / 32)` words of tag `t` in the table `expr` was compiled with, where bit `i % 32`
+/// of word `i / 32` is set if ping `i` has the tag.
+///
+/// Returns a bitset of the pings that matched, like `matchPacked`.
+#[cfg(feature = "expr")]
+#[wasm_bindgen(js_name = matchBitsets)]
+pub fn match_bitsets(
+    expr: &CompiledExpr,
+    pings: u32,
+    matrix: &[u32],
+) -> Result<Vec<u32>, TaglogicError> {
+    let words = (pings as usize).div_ceil(32);
+    if !matrix.len().is_multiple_of(words) {
+        return Err(TaglogicError::InvalidInput {
+            message: "matrix must be made of whole rows of ceil(pings / 32) words".to_string(),
+        });
+    }
+    Ok(expr.matches_bitset_rows(pings as usize, matrix))
+}
+
+/// Whether this build uses WASM SIMD instructions for `matchBitsets`. Browsers without SIMD
+/// can't load a build with them, so the frontend checks for support and picks a build:
+///
+/// ```js
+/// // a module with a function using v128, which only validates with SIMD support
+/// const simd = WebAssembly.validate(new Uint8Array([
+///   0, 97, 115, 109, 1, 0, 0, 0, 1, 5, 1, 96, 0, 1, 123, 3, 2, 1, 0, 10, 10, 1, 8, 0, 65, 0,
+///   253, 15, 253, 98, 11,
+/// ]));
+/// const taglogic = await import(simd ? "./taglogic-simd/taglogic.js" : "./taglogic/taglogic.js");
+/// ```
+///
+/// where the SIMD build is built with `RUSTFLAGS="-C target-feature=+simd128"`.
+#[cfg(feature = "expr")]
+#[wasm_bindgen(js_name = simdEnabled)]
+pub fn simd_enabled() -> bool {
+    crate::bits::WASM_SIMD
+}
+
+/// Like `matchPacked`, but matches the pings a chunk at a time, so the page can yield to the
+/// event loop between chunks instead of freezing on a big log:
+///
+/// ```js
+/// const matches = new PackedMatches(expr, times, tagOffsets, tagIndices, start, end);
+/// while (!matches.step(10000)) await new Promise((resolve) => setTimeout(resolve));
+/// const bits = matches.bits();
+/// ```
+#[cfg(feature = "expr")]
+#[wasm_bindgen]
+pub struct PackedMatches {
+    expr: CompiledExpr,
+    times: Vec<f64>,
+    tag_offsets: Vec<u32>,
+    tag_indices: Vec<u32>,
+    start: f64,
+    end: f64,
+    /// Index of the next ping to match.
+    next: usize,
+    bits: Vec<u32>,
+}
+
+#[cfg(feature = "expr")]
+#[wasm_bindgen]
+impl PackedMatches {
+    /// Takes the same arguments as `matchPacked`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        expr: &CompiledExpr,
+        times: Vec<f64>,
+        tag_offsets: Vec<u32>,
+        tag_indices: Vec<u32>,
+        start: f64,
+        end: f64,
+    ) -> Result<PackedMatches, TaglogicError> {
+        check_offsets(&times, &tag_offsets)?;
+        Ok(Self {
+            expr: expr.clone(),
+            bits: vec![0; times.len().div_ceil(32)],
+            times,
+            tag_offsets,
+            tag_indices,
+            start,
+            end,
+            next: 0,
+        })
+    }
+
+    /// Matches up to `count` more pings, and returns whether every ping has been matched.
+    pub fn step(&mut self, count: u32) -> Result<bool, TaglogicError> {
+        let end = self
+            .next
+            .saturating_add(count as usize)
+            .min(self.times.len());
+        let packed = Packed {
+            times: &self.times,
+            tag_offsets: &self.tag_offsets,
+            tag_indices: &self.tag_indices,
+            range: self.start..self.end,
+        };
+        packed.match_into(&self.expr, self.next..end, &mut self.bits)?;
+        self.next = end;
+        Ok(self.done())
+    }
+
+    /// Whether every ping has been matched.
+    #[wasm_bindgen(getter)]
+    pub fn done(&self) -> bool {
+        self.next == self.times.len()
+    }
+
+    /// Number of pings matched so far, for showing progress.
+    #[wasm_bindgen(getter)]
+    pub fn matched(&self) -> u32 {
+        self.next as u32
+    }
+
+    /// The bitset of matches like `matchPacked` returns. Pings that haven't been matched yet are
+    /// never set.
+    pub fn bits(&self) -> Vec<u32> {
+        self.bits.clone()
+    }
+}
+
+#[cfg(feature = "expr")]
+fn check_offsets(times: &[f64], tag_offsets: &[u32]) -> Result<(), TaglogicError> {
+    if tag_offsets.len() != times.len() + 1 {
+        return Err(TaglogicError::InvalidInput {
+            message: "tagOffsets must have one more item than times".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Pings packed into arrays, as given to `matchPacked`.
+#[cfg(feature = "expr")]
+struct Packed<'a> {
+    times: &'a [f64],
+    tag_offsets: &'a [u32],
+    tag_indices: &'a [u32],
+    range: core::ops::Range<f64>,
+}
+
+#[cfg(feature = "expr")]
+impl Packed<'_> {
+    /// Sets the bits of the pings in `pings` that match.
+    fn match_into(
+        &self,
+        expr: &CompiledExpr,
+        pings: core::ops::Range<usize>,
+        bits: &mut [u32],
+    ) -> Result<(), TaglogicError> {
+        for i in pings {
+            let tags = self
+                .tag_indices
+                .get(self.tag_offsets[i] as usize..self.tag_offsets[i + 1] as usize)
+                .ok_or_else(|| TaglogicError::InvalidInput {
+                    message: format!("tagOffsets[{}] is out of range", i + 1),
+                })?;
+            if self.range.contains(&self.times[i]) && expr.matches_ids(tags) {
+                bits[i / 32] |= 1 << (i % 32);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Estimated time spent on pings matching an expression.
+#[cfg(feature = "stats")]
+#[wasm_bindgen]
+pub fn estimate(pings: Ts<Pings>, expr: &Expr) -> Result<Ts<TimeEstimate>, TaglogicError> {
+    let mut tally = Tally::default();
+    for ping in &pings.to_rust()?.0 {
+        tally.add_share(ping.interval, ping.share(expr));
+    }
+    Ok(tally.estimate().into_ts()?)
+}
+
+/// Estimated time spent on pings matching an expression per bucket, with days starting at
+/// midnight `utc_offset_mins` minutes ahead of UTC.
+#[cfg(feature = "stats")]
+#[wasm_bindgen(js_name = timeSeries)]
+pub fn time_series(
+    pings: Ts<Pings>,
+    expr: &Expr,
+    bucket: Ts<Bucket>,
+    utc_offset_mins: i32,
+) -> Result<Ts<TimeSeries>, TaglogicError> {
+    let tz = utc_offset(utc_offset_mins)?;
+    let log = PingLog::from_pings(pings.to_rust()?.0);
+    Ok(stats::time_series(&log, expr, bucket.to_rust()?, &tz).into_ts()?)
+}
+
+/// `n` random pings matching an expression, oldest first, picked the same way for the same
+/// `seed`.
+#[cfg(feature = "stats")]
+#[wasm_bindgen(js_name = samplePings)]
+pub fn sample_pings(
+    pings: Ts<Pings>,
+    expr: &Expr,
+    n: u32,
+    seed: u32,
+) -> Result<Ts<Pings>, TaglogicError> {
+    let log = PingLog::from_pings(pings.to_rust()?.0);
+    let sample = stats::sample_pings(&log, expr, n as usize, u64::from(seed));
+    Ok(Pings(sample.iter().map(|ping| ping.to_ping()).collect()).into_ts()?)
+}
+
+/// A query written in plain words, from `parseNatural`.
+#[cfg(feature = "natural")]
+#[derive(Debug, Clone, Serialize, Tsify)]
+pub struct NaturalQuery {
+    /// The expression in the usual syntax, to show the user to confirm.
+    expr: String,
+    /// Unix times (in seconds) of the range the query is about, or null for all pings.
+    start: Option<f64>,
+    end: Option<f64>,
+}
+
+/// Parses a query like "work but not meetings last week", with days starting at midnight
+/// `utc_offset_mins` minutes ahead of UTC, and plurals of the space-separated `tags` taken to be
+/// those tags. Throws a `TaglogicError` if it can't be understood.
+#[cfg(feature = "natural")]
+#[wasm_bindgen(js_name = parseNatural)]
+pub fn parse_natural(
+    text: &str,
+    now: f64,
+    utc_offset_mins: i32,
+    tags: &str,
+) -> Result<Ts<NaturalQuery>, TaglogicError> {
+    let tz = utc_offset(utc_offset_mins)?;
+    let tags: Vec<&str> = tags.split_whitespace().collect();
+    let query = natural::parse_natural(text, unix_time(now)?, &tz, &tags).map_err(|err| {
+        TaglogicError::InvalidInput {
+            message: err.to_string(),
+        }
+    })?;
+    let range = query
+        .range
+        .map(|range| (range.start as f64, range.end as f64));
+    let query = NaturalQuery {
+        expr: query.canonical,
+        start: range.map(|range| range.0),
+        end: range.map(|range| range.1),
+    };
+    Ok(query.into_ts()?)
+}
+
+/// Suggested tags returned to JS.
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, Serialize, Tsify)]
+pub struct Suggestions(Vec<ScoredTag>);
+
+/// Up to `limit` suggested tags for the ping sent at `now`, best first, for the answer dialog.
+/// The space-separated `entered` tags aren't suggested again, only tags starting with `prefix`
+/// are suggested, and hours of the day are `utc_offset_mins` minutes ahead of UTC.
+#[cfg(feature = "stats")]
+#[wasm_bindgen(js_name = suggestTags)]
+pub fn suggest_tags(
+    pings: Ts<Pings>,
+    now: f64,
+    utc_offset_mins: i32,
+    entered: &str,
+    prefix: &str,
+    limit: u32,
+) -> Result<Ts<Suggestions>, TaglogicError> {
+    let tz = utc_offset(utc_offset_mins)?;
+    let log = PingLog::from_pings(pings.to_rust()?.0);
+    let entered: Vec<&str> = entered.split_whitespace().collect();
+    let context = SuggestContext {
+        entered: &entered,
+        prefix,
+        limit: limit as usize,
+        ..SuggestContext::new(&tz)
+    };
+    let suggestions = suggest::suggest_tags(&log, unix_time(now)?, &context);
+    Ok(Suggestions(suggestions).into_ts()?)
+}
+
+/// A log split into monthly segments, which JS loads only when a query needs them, so long logs
+/// don't have to fit in WASM memory at once. Months are written like `2024-03`:
+///
+/// ```js
+/// const log = new SegmentedLog(24);
+/// for (const month of log.missing(start, end)) {
+///   log.insert(month, await db.get("pings", month));
+/// }
+/// const estimate = log.estimate(expr, start, end);
+/// ```
+#[cfg(feature = "stats")]
+#[wasm_bindgen(js_name = SegmentedLog)]
+#[derive(Debug)]
+pub struct JsSegmentedLog(SegmentedLog);
+
+#[cfg(feature = "stats")]
+#[wasm_bindgen(js_class = SegmentedLog)]
+impl JsSegmentedLog {
+    /// Keeps up to `capacity` months loaded, dropping the least recently used ones.
+    #[wasm_bindgen(constructor)]
+    pub fn new(capacity: u32) -> Result<JsSegmentedLog, TaglogicError> {
+        if capacity == 0 {
+            return Err(TaglogicError::InvalidInput {
+                message: "capacity must be at least 1".to_string(),
+            });
+        }
+        Ok(Self(SegmentedLog::new(capacity as usize)))
+    }
+
+    /// The months in `start..end` that need to be inserted before querying it.
+    #[wasm_bindgen(unchecked_return_type = "string[]")]
+    pub fn missing(&self, start: f64, end: f64) -> Result<Box<[JsValue]>, TaglogicError> {
+        Ok(self
+            .0
+            .missing(unix_time(start)?, unix_time(end)?)
+            .into_iter()
+            .map(|month| JsValue::from_str(&month.to_string()))
+            .collect())
+    }
+
+    /// Adds a month's pings. Pings outside of the month are ignored.
+    pub fn insert(&mut self, month: &str, pings: Ts<Pings>) -> Result<(), TaglogicError> {
+        let month = parse_month(month)?;
+        self.0.insert(month, pings.to_rust()?.0);
+        Ok(())
+    }
+
+    /// Drops a month, like after its pings are edited.
+    pub fn unload(&mut self, month: &str) -> Result<(), TaglogicError> {
+        self.0.unload(parse_month(month)?);
+        Ok(())
+    }
+
+    /// Estimated time spent on pings in `start..end` matching an expression. Throws if any of the
+    /// months are missing.
+    pub fn estimate(
+        &self,
+        expr: &Expr,
+        start: f64,
+        end: f64,
+    ) -> Result<Ts<TimeEstimate>, TaglogicError> {
+        let (start, end) = (unix_time(start)?, unix_time(end)?);
+        let slices = self
+            .0
+            .range(start, end)
+            .ok_or_else(|| TaglogicError::InvalidInput {
+                message: "insert the missing months first".to_string(),
+            })?;
+        let mut tally = Tally::default();
+        for slice in &slices {
+            for ping in slice {
+                tally.add_share(ping.interval, ping.share(expr));
+            }
+        }
+        Ok(tally.estimate().into_ts()?)
+    }
+}
+
+/// Seconds from JS as a `u64` time, throwing instead of turning NaN or negative times into 0.
+#[cfg(feature = "stats")]
+fn unix_time(secs: f64) -> Result<u64, TaglogicError> {
+    PingTimestamp::from_f64_secs(secs)
+        .and_then(|time| u64::try_from(time).ok())
+        .ok_or_else(|| TaglogicError::InvalidInput {
+            message: format!("invalid time {}", secs),
+        })
+}
+
+#[cfg(feature = "stats")]
+fn utc_offset(mins: i32) -> Result<FixedOffset, TaglogicError> {
+    mins.checked_mul(60)
+        .and_then(FixedOffset::east_opt)
+        .ok_or_else(|| TaglogicError::InvalidInput {
+            message: "UTC offset must be less than a day".to_string(),
+        })
+}
+
+#[cfg(feature = "stats")]
+fn parse_month(month: &str) -> Result<Month, TaglogicError> {
+    month
+        .parse()
+        .map_err(|err: MonthError| TaglogicError::InvalidInput {
+            message: err.to_string(),
+        })
+}
+
+/// Notifications returned to JS.
+#[cfg(feature = "ping")]
+#[derive(Debug, Clone, Serialize, Tsify)]
+pub struct Notifications(Vec<Notification>);
+
+/// Notifications for the pings in the day or so after `now`, for the service worker to schedule.
+#[cfg(feature = "ping")]
+#[wasm_bindgen(js_name = upcomingNotifications)]
+pub fn upcoming_notifications(
+    schedule: &PingIntervalData,
+    now: u32,
+    options: Ts<NotificationOptions>,
+) -> Result<Ts<Notifications>, TaglogicError> {
+    let notifications = notify::upcoming_notifications(schedule, now.into(), &options.to_rust()?);
+    Ok(Notifications(notifications).into_ts()?)
+}
+
+/// A seeded replacement for `Math.random`, using the same generator as the original TagTime
+/// schedule, so the same seed gives the same numbers in JS and Rust.
+#[cfg(feature = "ping")]
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct SeededRandom(tt::State);
+
+#[cfg(feature = "ping")]
+#[wasm_bindgen]
+impl SeededRandom {
+    /// `seed` must be between 1 and 2147483646. A generator's `state` can be used as a seed to
+    /// carry on where it left off.
+    #[wasm_bindgen(constructor)]
+    pub fn new(seed: u32) -> Result<SeededRandom, TaglogicError> {
+        if seed == 0 || seed >= tt::IM_U32 {
+            return Err(TaglogicError::InvalidInput {
+                message: "seed must be between 1 and 2147483646".to_string(),
+            });
+        }
+        Ok(Self(tt::State::from_seed(seed)))
+    }
+
+    /// A number between 0 and 1. Unlike `Math.random`, it's never exactly 0.
+    pub fn random(&mut self) -> f64 {
+        self.0.next_state();
+        f64::from(self.0.inner()) / f64::from(tt::IM_U32)
+    }
+
+    /// An integer from 0 up to (but not including) `n`.
+    #[wasm_bindgen(js_name = nextInt)]
+    pub fn next_int(&mut self, n: u32) -> u32 {
+        (self.random() * f64::from(n)) as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn state(&self) -> u32 {
+        self.0.inner()
+    }
+}
+
+#[cfg(feature = "expr")]
+#[wasm_bindgen]
+pub fn new_expr(expr: &str) -> Result<Expr, JsValue> {
+    Expr::from_string(expr).map_err(|kind| JsValue::from_str(&kind.to_string()))
+}
+
+#[cfg(feature = "expr")]
+#[wasm_bindgen]
+pub fn expr_matches(expr: &Expr, tags: String) -> bool {
+    expr.matches(&tags.split(' ').collect::<Vec<_>>())
+}
+
+/// Handles a JSON request for the `commands` module, returning a JSON response.
+#[cfg(feature = "commands")]
+#[wasm_bindgen]
+pub fn dispatch(request: &str) -> String {
+    crate::commands::dispatch(request)
+}
+
+#[wasm_bindgen(start)]
+pub fn init() {
+    #[cfg(feature = "console-panic")]
+    {
+        console_error_panic_hook::set_once();
+    }
+}
+
+#[cfg(all(test, feature = "stats"))]
+mod test {
+    use super::*;
+    use crate::stats::TimeSeriesPoint;
+    use serde_json::json;
+
+    #[test]
+    fn spans_are_utf16() {
+        // "é" is two bytes in UTF-8 but one code unit in UTF-16
+        let err = Expr::parse("é & )").unwrap_err();
+        assert_eq!(err.span, 5..6);
+        assert_eq!(
+            serde_json::to_value(TaglogicError::parse("é & )", err)).unwrap(),
+            json!({
+                "kind": "parse",
+                "message": "unexpected closing bracket",
+                "start": 4,
+                "end": 5,
+            })
+        );
+    }
+
+    #[test]
+    fn packed_matches() {
+        let expr = Expr::parse("a & !b")
+            .unwrap()
+            .compile_with_table(&["a", "b"]);
+        let times: Vec<f64> = (0..40).map(f64::from).collect();
+        // ping 0 is a, 1 is a b, 2 has no tags, and the rest alternate between b and a
+        let mut offsets = vec![0, 1, 3, 3];
+        let mut indices = vec![0, 0, 1];
+        for i in 3..40 {
+            indices.push(i % 2);
+            offsets.push(indices.len() as u32);
+        }
+        // ping 38 is after the end
+        let bits = match_packed(&expr, &times, &offsets, &indices, 0.0, 38.0).unwrap();
+        assert_eq!(
+            bits,
+            vec![0b0101_0101_0101_0101_0101_0101_0101_0001, 0b0001_0101]
+        );
+        assert!(match_packed(&expr, &times, &offsets[1..], &indices, 0.0, 39.0).is_err());
+        offsets[40] = 100;
+        assert!(match_packed(&expr, &times, &offsets, &indices, 0.0, 39.0).is_err());
+    }
+
+    #[test]
+    fn packed_matches_in_chunks() {
+        let expr = Expr::parse("a").unwrap().compile_with_table(&["a", "b"]);
+        let times: Vec<f64> = (0..70).map(f64::from).collect();
+        let offsets: Vec<u32> = (0..=70).collect();
+        let indices: Vec<u32> = (0..70).map(|i| i % 2).collect();
+        let whole = match_packed(&expr, &times, &offsets, &indices, 0.0, 100.0).unwrap();
+
+        let mut chunked =
+            PackedMatches::new(&expr, times.clone(), offsets, indices, 0.0, 100.0).unwrap();
+        assert!(!chunked.step(32).unwrap());
+        assert_eq!(chunked.matched(), 32);
+        assert_eq!(chunked.bits()[1], 0);
+        assert!(!chunked.step(32).unwrap());
+        assert!(chunked.step(32).unwrap());
+        assert!(chunked.done());
+        assert!(chunked.step(32).unwrap());
+        assert_eq!(chunked.bits(), whole);
+        assert!(PackedMatches::new(&expr, times, vec![0], vec![], 0.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn seeded_random() {
+        let mut random = SeededRandom::new(1).unwrap();
+        assert_eq!(random.random(), 16807.0 / 2147483647.0);
+        assert_eq!(random.state(), 16807);
+        assert_eq!(random.next_int(1000), 131);
+        // resuming from the state continues the same sequence
+        let mut resumed = SeededRandom::new(random.state()).unwrap();
+        assert_eq!(resumed.random(), random.random());
+        assert!(SeededRandom::new(0).is_err());
+        assert!(SeededRandom::new(2147483647).is_err());
+    }
+
+    #[test]
+    fn pings_from_frontend() {
+        let pings: Pings = serde_json::from_value(json!([
+            {"time": 100, "tags": ["a"], "category": null, "interval": 2700, "comment": null},
+            {"time": 200, "tags": [], "interval": 2700, "synced": true, "answered": 250},
+        ]))
+        .unwrap();
+        let mut ping = Ping::new(200, vec![], 2700);
+        ping.answered = Some(250);
+        assert_eq!(
+            pings.0,
+            vec![Ping::new(100, vec!["a".to_string()], 2700), ping]
+        );
+    }
+
+    #[test]
+    fn typescript_declarations() {
+        // these end up in the .d.ts, so changing them will break the frontend
+        for field in &[
+            "time: number;",
+            "tags: string[];",
+            "interval: number;",
+            "comment: string | null;",
+            "answered?: number;",
+        ] {
+            assert!(Ping::DECL.contains(field), "{}", Ping::DECL);
+        }
+        assert!(TaglogicError::DECL.ends_with(
+            "export type TaglogicError = { kind: \"parse\"; message: string; start: number; end: number } | { kind: \"invalidInput\"; message: string };"
+        ), "{}", TaglogicError::DECL);
+        assert!(Bucket::DECL.ends_with("export type Bucket = \"day\" | \"week\" | \"month\";"));
+        assert!(TimeSeriesPoint::DECL.contains("start: string;"));
+        assert!(NotificationOptions::DECL.contains("quietStartMins: number;"));
+        assert!(Notification::DECL.contains("tag: string;"));
+    }
+}
@@ -0,0 +1,6 @@
+//! The WASM module for the web frontend and server, built with
+//! `wasm-pack build taglogic/wasm --out-name taglogic`. The bindings are in `taglogic::wasm`; this
+//! crate only builds them as a cdylib, so `taglogic` itself stays a plain library that `no_std`
+//! crates can use.
+
+pub use taglogic::wasm::*;
@@ -8,4 +8,4 @@ sed "s/APPNAME/$(node -e "console.log(require('../config.json')['app-name'] || '
 
 echo Compiling taglogic
 rm -rf dist pkg
-wasm-pack build ../taglogic/ --out-dir ../web/pkg
+wasm-pack build ../taglogic/wasm/ --out-dir ../../web/pkg --out-name taglogic